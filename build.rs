@@ -0,0 +1,29 @@
+//! Build script: records the commit the engine is built from.
+//!
+//! The short hash is passed to the crate as `RUSTCHESS_COMMIT` so the
+//! strength history can tie each calibration run to a commit. Builds
+//! outside a git checkout, or without git, leave it unset.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let git_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(".git");
+    if !git_dir.exists() {
+        return;
+    }
+    // HEAD moves on checkouts, the branch refs on commits
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=RUSTCHESS_COMMIT={}", commit.trim());
+    }
+}
@@ -0,0 +1,19 @@
+//! Positions shared by the targets that need a board to read moves in.
+
+use rustchess::Board;
+
+/// Positions covering promotions, en passant, castling rights, ambiguous
+/// piece moves and check.
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    "3k4/8/8/R6R/8/1N3N2/8/R3K2R w KQ - 0 1",
+    "4k3/8/8/8/1b6/8/3P4/4K3 w - - 0 1",
+];
+
+/// The position selected by a fuzzer-chosen byte.
+pub fn board(pick: u8) -> Board {
+    Board::from_fen(POSITIONS[pick as usize % POSITIONS.len()]).expect("fuzz positions are legal")
+}
@@ -0,0 +1,73 @@
+//! Feeds arbitrary text to the FEN parser. A FEN it accepts must read back
+//! to the same position once written out again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustchess::position::{CastlingRights, Color, PieceType};
+use rustchess::Board;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(board) = Board::from_fen(text) else {
+        return;
+    };
+    let fen = write_fen(&board);
+    let again = Board::from_fen(&fen).unwrap_or_else(|e| panic!("'{}' written as '{}': {}", text, fen, e));
+    assert_eq!(write_fen(&again), fen);
+    assert_eq!(again.legal_moves(), board.legal_moves());
+});
+
+/// Writes the six FEN fields of a board.
+fn write_fen(board: &Board) -> String {
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            let Some((color, piece_type)) = board.piece_at(rank * 8 + file) else {
+                empty += 1;
+                continue;
+            };
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+                empty = 0;
+            }
+            let symbol = match piece_type {
+                PieceType::Pawn => 'p',
+                PieceType::Knight => 'n',
+                PieceType::Bishop => 'b',
+                PieceType::Rook => 'r',
+                PieceType::Queen => 'q',
+                PieceType::King => 'k',
+            };
+            placement.push(if color == Color::White { symbol.to_ascii_uppercase() } else { symbol });
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+
+    let position = board.position();
+    let side = if board.side_to_move() == Color::White { "w" } else { "b" };
+    let castling: String = [
+        (CastlingRights::WHITEKINGSIDE, 'K'),
+        (CastlingRights::WHITEQUEENSIDE, 'Q'),
+        (CastlingRights::BLACKKINGSIDE, 'k'),
+        (CastlingRights::BLACKQUEENSIDE, 'q'),
+    ]
+    .iter()
+    .filter(|(right, _)| position.castling_rights.contains(*right))
+    .map(|&(_, symbol)| symbol)
+    .collect();
+    let castling = if castling.is_empty() { "-".to_string() } else { castling };
+    let en_passant = position.en_passant.map_or("-".to_string(), |square| {
+        let index = square.trailing_zeros();
+        format!("{}{}", (b'a' + (index % 8) as u8) as char, index / 8 + 1)
+    });
+    format!("{} {} {} {} {} {}", placement, side, castling, en_passant, position.halfmove_clock, position.fullmove_number)
+}
@@ -0,0 +1,36 @@
+//! Feeds arbitrary packed moves to `Move::from_u64` and arbitrary text to
+//! `Board::parse_move`. Decoding must keep every bit of the packed move
+//! that means something, and a move read from text must print back to text
+//! that reads as the same move.
+
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use rustchess::chess_move::{Move, PROMOTION_FLAG};
+
+/// Squares, the promotion flag and, when it is set, the promotion piece.
+const SQUARE_BITS: u64 = 0xFFF;
+const PROMOTION_BITS: u64 = 0x7000;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&pick, rest)) = data.split_first() else {
+        return;
+    };
+    let board = common::board(pick);
+
+    if let Some(bytes) = rest.get(..8) {
+        let packed = u64::from_le_bytes(bytes.try_into().unwrap());
+        let mv = Move::from_u64(packed, board.position());
+        let meaningful = if packed & PROMOTION_FLAG != 0 { SQUARE_BITS | PROMOTION_BITS } else { SQUARE_BITS };
+        assert_eq!(mv.to_u64(), packed & meaningful, "{:#x} decoded as {:?}", packed, mv);
+    }
+
+    if let Ok(text) = std::str::from_utf8(rest) {
+        if let Ok(mv) = board.parse_move(text) {
+            assert!(board.legal_moves().contains(&mv), "'{}' read as illegal {}", text, mv);
+            assert_eq!(board.parse_move(&mv.to_string()), Ok(mv), "'{}' printed as '{}'", text, mv);
+        }
+    }
+});
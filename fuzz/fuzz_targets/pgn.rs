@@ -0,0 +1,38 @@
+//! Feeds arbitrary text to the PGN importer. An imported game must replay
+//! from its start position, and its moves written as SAN must import again
+//! to the same game. Read as a move tree, its variations and comments must
+//! also survive being written out and read back.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustchess::pgn::{import, import_tree, to_san, ImportFormat};
+use rustchess::Board;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(tree) = import_tree(text) {
+        let pgn = format!("[FEN \"{}\"]\n\n{} *", tree.start_fen, tree.to_movetext(to_san));
+        let again = import_tree(&pgn).unwrap_or_else(|e| panic!("'{}' written as '{}': {}", text, pgn, e));
+        assert_eq!(again, tree);
+    }
+    let Ok(game) = import(text) else {
+        return;
+    };
+    let mut board = Board::from_fen(&game.start_fen).expect("imported start position is legal");
+    let mut movetext = String::new();
+    for &mv in &game.moves {
+        movetext.push_str(&to_san(&board, mv));
+        movetext.push(' ');
+        board.play(mv).expect("imported moves are legal");
+    }
+    if game.format == ImportFormat::Fen {
+        return;
+    }
+
+    let pgn = format!("[FEN \"{}\"]\n\n{}*", game.start_fen, movetext);
+    let again = import(&pgn).unwrap_or_else(|e| panic!("'{}' written as '{}': {}", text, pgn, e));
+    assert_eq!(again.moves, game.moves);
+});
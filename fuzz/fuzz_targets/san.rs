@@ -0,0 +1,25 @@
+//! Feeds arbitrary text to the SAN parser in one of a few positions. A move
+//! it accepts must be legal, and its SAN must read back to the same move.
+
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use rustchess::notation::{parse_san, to_san};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&pick, text)) = data.split_first() else {
+        return;
+    };
+    let Ok(text) = std::str::from_utf8(text) else {
+        return;
+    };
+    let board = common::board(pick);
+    let Ok(mv) = parse_san(&board, text) else {
+        return;
+    };
+    assert!(board.legal_moves().contains(&mv), "'{}' read as illegal {}", text, mv);
+    let san = to_san(&board, mv);
+    assert_eq!(parse_san(&board, &san), Ok(mv), "'{}' written as '{}'", text, san);
+});
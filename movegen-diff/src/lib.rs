@@ -0,0 +1,3 @@
+//! Differential tests of the engine's move generation against the
+//! `shakmaty` crate. The tests are in `tests/differential.rs`; run them with
+//! `cargo test --release` from this directory.
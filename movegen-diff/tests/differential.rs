@@ -0,0 +1,140 @@
+//! Compares the engine's legal moves and perft counts with `shakmaty`'s
+//! across random positions. Every discrepancy is reported with the FEN of
+//! the position, which reproduces it with `Board::from_fen`.
+//!
+//! Positions come from random games played by the reference from a few
+//! starting points, standard and Chess960. Chess960 games are compared with
+//! castling written as the king taking its own rook, as the engine writes it
+//! for them.
+
+use rand::prelude::*;
+use rustchess::perft::{Divide, Perft};
+use rustchess::Board;
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, EnPassantMode, Position};
+
+/// Starting points of the random games: the initial position, a crowded
+/// middlegame, promotions, en passant, a rook endgame and castling on both
+/// wings, then Chess960 positions with their kings and rooks on other files,
+/// given in X-FEN and Shredder-FEN.
+const STARTS: [(&str, CastlingMode); 12] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", CastlingMode::Standard),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", CastlingMode::Standard),
+    ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", CastlingMode::Standard),
+    ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", CastlingMode::Standard),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", CastlingMode::Standard),
+    ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", CastlingMode::Standard),
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", CastlingMode::Chess960),
+    ("rk2r3/8/8/8/8/8/8/RK2R3 w AEae - 0 1", CastlingMode::Chess960),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", CastlingMode::Chess960),
+    ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", CastlingMode::Chess960),
+    ("qrkbbrnn/pppppppp/8/8/8/8/PPPPPPPP/QRKBBRNN w KQkq - 0 1", CastlingMode::Chess960),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", CastlingMode::Chess960),
+];
+const GAMES: usize = 100;
+const MAX_PLIES: usize = 120;
+/// Perft is compared in every this many positions, to this depth.
+const PERFT_EVERY: usize = 25;
+const PERFT_DEPTH: u32 = 3;
+/// Discrepancies listed in the failure message; the rest are only counted.
+const MAX_REPORTED: usize = 20;
+
+/// The random games' seed, from `MOVEGEN_DIFF_SEED` if set.
+fn seed() -> u64 {
+    std::env::var("MOVEGEN_DIFF_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or(3731)
+}
+
+fn reference_moves(position: &Chess, mode: CastlingMode) -> Vec<String> {
+    let mut moves: Vec<String> = position.legal_moves().iter()
+        .map(|mv| mv.to_uci(mode).to_string())
+        .collect();
+    moves.sort();
+    moves
+}
+
+/// `shakmaty`'s perft count below every move, after playing `line`.
+fn reference_divide(position: &Chess, mode: CastlingMode, line: &[String], depth: i32) -> Result<Divide, String> {
+    let mut position = position.clone();
+    for text in line {
+        let mv = text.parse::<UciMove>().ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+            .ok_or_else(|| format!("shakmaty rejects {} after {:?}", text, line))?;
+        position.play_unchecked(mv);
+    }
+    Ok(position.legal_moves().iter()
+        .map(|mv| {
+            let mut child = position.clone();
+            child.play_unchecked(*mv);
+            (mv.to_uci(mode).to_string(), shakmaty::perft(&child, depth as u32 - 1))
+        })
+        .collect())
+}
+
+/// What the engine gets wrong in a position, if anything.
+///
+/// # Arguments
+///
+/// * `fen` - The position
+/// * `reference` - The same position in `shakmaty`
+/// * `mode` - Whether the game is Chess960, which changes how castling is
+///   written
+/// * `perft_depth` - Depth to compare perft counts at, if any
+fn compare(fen: &str, reference: &Chess, mode: CastlingMode, perft_depth: Option<u32>) -> Option<String> {
+    let mut board = match Board::from_fen(fen) {
+        Ok(board) => board,
+        Err(e) => return Some(format!("{}: rejected: {}", fen, e)),
+    };
+    board.set_chess960(mode == CastlingMode::Chess960);
+    let mut moves: Vec<String> = board.legal_moves().iter().map(|mv| mv.to_string()).collect();
+    moves.sort();
+    let expected = reference_moves(reference, mode);
+    if moves != expected {
+        let missing: Vec<&String> = expected.iter().filter(|mv| !moves.contains(mv)).collect();
+        let extra: Vec<&String> = moves.iter().filter(|mv| !expected.contains(mv)).collect();
+        return Some(format!("{}: missing {:?}, extra {:?}", fen, missing, extra));
+    }
+
+    // A differing count is traced to the line where the moves differ
+    let depth = perft_depth?;
+    let reference_divide = |line: &[String], depth| reference_divide(reference, mode, line, depth);
+    match Perft::new().find_divergence(&board, depth as i32, reference_divide, |_, _, _| {}) {
+        Ok(None) => None,
+        Ok(Some(divergence)) => Some(format!("{}: perft({}) differs after {:?}: missing {:?}, extra {:?}",
+            fen, depth, divergence.line, divergence.missing, divergence.extra)),
+        Err(e) => Some(format!("{}: perft({}): {}", fen, depth, e)),
+    }
+}
+
+#[test]
+fn test_move_generation_matches_the_reference() {
+    let seed = seed();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut discrepancies = Vec::new();
+    let mut positions = 0;
+    for game in 0..GAMES {
+        let (start, mode) = STARTS[game % STARTS.len()];
+        let mut position: Chess = start.parse::<Fen>().unwrap().into_position(mode).unwrap();
+        for _ in 0..rng.gen_range(1..=MAX_PLIES) {
+            positions += 1;
+            let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+            let perft_depth = (positions % PERFT_EVERY == 0).then_some(PERFT_DEPTH);
+            discrepancies.extend(compare(&fen, &position, mode, perft_depth));
+
+            let moves = position.legal_moves();
+            let Some(mv) = moves.choose(&mut rng) else {
+                break;
+            };
+            position.play_unchecked(*mv);
+        }
+    }
+
+    assert!(
+        discrepancies.is_empty(),
+        "{} of {} positions differ from shakmaty (seed {}):\n{}",
+        discrepancies.len(),
+        positions,
+        seed,
+        discrepancies.iter().take(MAX_REPORTED).cloned().collect::<Vec<_>>().join("\n"),
+    );
+}
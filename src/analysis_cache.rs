@@ -0,0 +1,228 @@
+//! Analysis cache: the deepest engine line known for each position.
+//!
+//! The GUI keeps one cache for all its tabs, keyed by Zobrist hash, so a
+//! position analyzed in one tab, or reached again by another move order,
+//! shows its line at once. A line only replaces one searched at least as
+//! deep, so a longer search refines what is known and a quick one never
+//! overwrites it. The GUI stores the cache between runs as text, one
+//! position per line: `<hash as hex> <depth> <score> <remote|local> <pv>`.
+
+use std::collections::HashMap;
+
+use crate::remote::{AnalysisLine, AnalysisSource};
+
+/// Most positions kept by default; past it the shallowest lines are dropped.
+pub const DEFAULT_MAX_POSITIONS: usize = 5000;
+
+/// Engine lines by position hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisCache {
+    lines: HashMap<u64, AnalysisLine>,
+    max_positions: usize,
+}
+
+impl Default for AnalysisCache {
+    fn default() -> Self {
+        AnalysisCache { lines: HashMap::new(), max_positions: DEFAULT_MAX_POSITIONS }
+    }
+}
+
+impl AnalysisCache {
+    pub fn new() -> Self {
+        AnalysisCache::default()
+    }
+
+    pub fn max_positions(&self) -> usize {
+        self.max_positions
+    }
+
+    /// Changes how many positions are kept, dropping the shallowest lines at
+    /// once if the cache holds more.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_positions` - The new limit, at least one
+    ///
+    /// # Returns
+    ///
+    /// * The number of lines dropped
+    pub fn set_max_positions(&mut self, max_positions: usize) -> usize {
+        self.max_positions = max_positions.max(1);
+        self.shrink_to(self.max_positions)
+    }
+
+    /// Drops the shallowest lines until at most `positions` are left and
+    /// frees their memory; the limit stays as it was.
+    ///
+    /// # Returns
+    ///
+    /// * The number of lines dropped
+    pub fn shrink_to(&mut self, positions: usize) -> usize {
+        let excess = self.lines.len().saturating_sub(positions);
+        if excess > 0 {
+            let mut by_depth: Vec<(i32, u64)> = self.lines.iter().map(|(&hash, line)| (line.depth, hash)).collect();
+            by_depth.sort_unstable();
+            for (_, hash) in by_depth.into_iter().take(excess) {
+                self.lines.remove(&hash);
+            }
+            self.lines.shrink_to_fit();
+        }
+        excess
+    }
+
+    /// Approximate bytes the cache takes in memory.
+    pub fn memory_bytes(&self) -> usize {
+        let slots = self.lines.capacity() * (std::mem::size_of::<(u64, AnalysisLine)>() + 1);
+        let text: usize = self.lines.values()
+            .map(|line| line.best_move.capacity() + line.pv.capacity() * std::mem::size_of::<String>()
+                + line.pv.iter().map(String::capacity).sum::<usize>())
+            .sum();
+        slots + text
+    }
+
+    /// The line known for a position.
+    pub fn get(&self, hash: u64) -> Option<&AnalysisLine> {
+        self.lines.get(&hash)
+    }
+
+    /// Keeps a line unless a deeper one is already known for the position.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The position's Zobrist hash
+    /// * `line` - The engine's line from it
+    ///
+    /// # Returns
+    ///
+    /// * Whether the line was kept
+    pub fn store(&mut self, hash: u64, line: &AnalysisLine) -> bool {
+        if self.lines.get(&hash).is_some_and(|known| known.depth > line.depth || known == line) {
+            return false;
+        }
+        if self.lines.len() >= self.max_positions && !self.lines.contains_key(&hash) {
+            let shallowest = self.lines.iter().min_by_key(|(_, known)| known.depth).map(|(&hash, _)| hash);
+            if let Some(shallowest) = shallowest {
+                self.lines.remove(&shallowest);
+            }
+        }
+        self.lines.insert(hash, line.clone());
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Reads a stored cache; unreadable lines are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut cache = AnalysisCache::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(hash), Some(depth), Some(score), Some(source)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let source = match source {
+                "remote" => AnalysisSource::Remote,
+                "local" => AnalysisSource::Local,
+                _ => continue,
+            };
+            let pv: Vec<String> = fields.map(str::to_string).collect();
+            if let (Ok(hash), Ok(depth), Ok(score), Some(best_move)) =
+                (u64::from_str_radix(hash, 16), depth.parse(), score.parse(), pv.first().cloned()) {
+                cache.store(hash, &AnalysisLine { source, best_move, score, pv, depth });
+            }
+        }
+        cache
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut hashes: Vec<&u64> = self.lines.keys().collect();
+        hashes.sort();
+        hashes.into_iter()
+            .map(|hash| {
+                let line = &self.lines[hash];
+                let source = match line.source {
+                    AnalysisSource::Remote => "remote",
+                    AnalysisSource::Local => "local",
+                };
+                format!("{:016x} {} {} {} {}\n", hash, line.depth, line.score, source, line.pv.join(" "))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(depth: i32, pv: &[&str]) -> AnalysisLine {
+        AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: pv[0].to_string(),
+            score: 10 * depth,
+            pv: pv.iter().map(|mv| mv.to_string()).collect(),
+            depth,
+        }
+    }
+
+    #[test]
+    fn test_deeper_lines_refine_the_cache() {
+        let mut cache = AnalysisCache::new();
+        assert!(cache.store(1, &line(4, &["e2e4", "e7e5"])));
+        assert!(!cache.store(1, &line(3, &["d2d4"])));
+        assert!(!cache.store(1, &line(4, &["e2e4", "e7e5"])));
+        assert!(cache.store(1, &line(6, &["g1f3"])));
+        assert_eq!(cache.get(1).unwrap().best_move, "g1f3");
+        assert_eq!(cache.get(2), None);
+
+        // A full cache drops its shallowest line for a new position
+        for hash in 2..DEFAULT_MAX_POSITIONS as u64 {
+            cache.store(hash, &line(8, &["e2e4"]));
+        }
+        assert_eq!(cache.len(), DEFAULT_MAX_POSITIONS - 1);
+        cache.store(0, &line(5, &["c2c4"]));
+        cache.store(DEFAULT_MAX_POSITIONS as u64, &line(7, &["c2c4"]));
+        assert_eq!(cache.len(), DEFAULT_MAX_POSITIONS);
+        assert_eq!(cache.get(0), None);
+        assert!(cache.get(1).is_some() && cache.get(DEFAULT_MAX_POSITIONS as u64).is_some());
+    }
+
+    #[test]
+    fn test_shrinking_keeps_the_deepest_lines() {
+        let mut cache = AnalysisCache::new();
+        for depth in 1..=10 {
+            cache.store(depth as u64, &line(depth, &["e2e4"]));
+        }
+        let before = cache.memory_bytes();
+        assert_eq!(cache.set_max_positions(4), 6);
+        assert_eq!(cache.len(), 4);
+        assert!((7..=10).all(|hash| cache.get(hash).is_some()));
+        assert!(cache.memory_bytes() < before);
+        assert_eq!(cache.set_max_positions(8), 0);
+        assert_eq!(cache.shrink_to(2), 2);
+        assert_eq!((cache.len(), cache.max_positions()), (2, 8));
+
+        // The new limit holds for later lines too
+        cache.set_max_positions(2);
+        cache.store(20, &line(12, &["d2d4"]));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(9), None);
+    }
+
+    #[test]
+    fn test_cache_storage() {
+        let mut cache = AnalysisCache::new();
+        cache.store(0xabcdef, &line(5, &["e2e4", "e7e5", "g1f3"]));
+        cache.store(u64::MAX, &AnalysisLine { source: AnalysisSource::Remote, ..line(12, &["d2d4"]) });
+        assert_eq!(AnalysisCache::parse(&cache.to_text()), cache);
+        assert!(AnalysisCache::parse("abcdef 5 10 local\nzz 1 1 local e2e4\n12 x 1 local e2e4").is_empty());
+    }
+}
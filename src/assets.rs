@@ -0,0 +1,188 @@
+//! Locating optional asset files.
+//!
+//! Every asset is optional. Without an opening book the explorer and book
+//! moves are off, without a search config or an options file the defaults
+//! are used, and the endgame tables are generated (which takes a few seconds) when no
+//! saved copy is found. Each asset is looked for in the same places, in
+//! order:
+//!
+//! 1. The file named by its environment variable, if it has one
+//! 2. The working directory
+//! 3. The data directory: `$XDG_DATA_HOME/rustchess`, or
+//!    `~/.local/share/rustchess` (`%APPDATA%\rustchess` on Windows)
+//! 4. The directory of the executable
+//! 5. For the generated tables, the temporary directory they are saved to
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::options;
+use crate::search_params::{CONFIG_ENV, DEFAULT_CONFIG_FILE};
+
+/// Environment variable naming the opening book file.
+pub const BOOK_ENV: &str = "RUSTCHESS_BOOK";
+/// Book file looked for when `BOOK_ENV` is not set.
+pub const DEFAULT_BOOK_FILE: &str = "book.txt";
+/// Name of the data directory under the platform's data location.
+const DATA_DIR: &str = "rustchess";
+
+/// An optional file the engine or a front end reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Asset {
+    OpeningBook,
+    SearchConfig,
+    /// Engine options, see `options`
+    EngineOptions,
+    /// Distance-to-mate tables for king and piece against king
+    Tablebases,
+    /// Win/draw bitbase for king and pawn against king
+    KpkBitbase,
+}
+
+impl Asset {
+    pub const ALL: [Asset; 5] = [Asset::OpeningBook, Asset::SearchConfig, Asset::EngineOptions, Asset::Tablebases, Asset::KpkBitbase];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Asset::OpeningBook => "Opening book",
+            Asset::SearchConfig => "Search config",
+            Asset::EngineOptions => "Engine options",
+            Asset::Tablebases => "Endgame tables",
+            Asset::KpkBitbase => "KPK bitbase",
+        }
+    }
+
+    /// The file name looked for in each directory.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Asset::OpeningBook => DEFAULT_BOOK_FILE,
+            Asset::SearchConfig => DEFAULT_CONFIG_FILE,
+            Asset::EngineOptions => options::DEFAULT_CONFIG_FILE,
+            Asset::Tablebases => crate::tablebase::CACHE_FILE,
+            Asset::KpkBitbase => crate::bitbase::CACHE_FILE,
+        }
+    }
+
+    /// The environment variable naming the file, if it has one.
+    pub fn env_var(self) -> Option<&'static str> {
+        match self {
+            Asset::OpeningBook => Some(BOOK_ENV),
+            Asset::SearchConfig => Some(CONFIG_ENV),
+            Asset::EngineOptions => Some(options::CONFIG_ENV),
+            Asset::Tablebases | Asset::KpkBitbase => None,
+        }
+    }
+
+    /// What runs differently while the asset is missing.
+    pub fn fallback(self) -> &'static str {
+        match self {
+            Asset::OpeningBook => "The opening explorer and book moves are off",
+            Asset::SearchConfig => "The default search parameters are used",
+            Asset::EngineOptions => "The default engine options are used",
+            Asset::Tablebases => "The tables are generated on first use, which takes a few seconds",
+            Asset::KpkBitbase => "The bitbase is generated on first use",
+        }
+    }
+
+    /// How to get the asset.
+    pub fn hint(self) -> String {
+        match self {
+            Asset::OpeningBook => format!(
+                "Play a game with `--book {}` to start one, or set {} to an existing book or Polyglot .bin book",
+                DEFAULT_BOOK_FILE, BOOK_ENV),
+            Asset::SearchConfig => format!(
+                "Run `tune` to write one, or set {} to a parameter file", CONFIG_ENV),
+            Asset::EngineOptions => format!(
+                "Save them from the GUI's engine options, or set {} to an options file", options::CONFIG_ENV),
+            Asset::Tablebases | Asset::KpkBitbase => format!(
+                "Saved to the temporary directory once generated; copy {} to the data directory to keep it", self.file_name()),
+        }
+    }
+
+    /// The places the asset is looked for, in order.
+    pub fn search_paths(self) -> Vec<PathBuf> {
+        self.search_paths_with(|name| std::env::var_os(name))
+    }
+
+    /// `search_paths` with the environment read through `var`.
+    fn search_paths_with(self, var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.env_var().and_then(&var).map(PathBuf::from).into_iter().collect();
+        paths.push(PathBuf::from(self.file_name()));
+        let data = var("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| var("APPDATA").map(PathBuf::from))
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local").join("share")));
+        if let Some(data) = data {
+            paths.push(data.join(DATA_DIR).join(self.file_name()));
+        }
+        if let Some(dir) = std::env::current_exe().ok().as_ref().and_then(|exe| exe.parent()) {
+            paths.push(dir.join(self.file_name()));
+        }
+        if matches!(self, Asset::Tablebases | Asset::KpkBitbase) {
+            paths.push(std::env::temp_dir().join(self.file_name()));
+        }
+        paths
+    }
+
+    /// The first of `search_paths` that exists.
+    pub fn locate(self) -> Option<PathBuf> {
+        resolve(self).path
+    }
+}
+
+/// Where an asset was found, if anywhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetStatus {
+    pub asset: Asset,
+    pub path: Option<PathBuf>,
+    /// The places looked in
+    pub searched: Vec<PathBuf>,
+}
+
+/// Looks for an asset in its search paths.
+pub fn resolve(asset: Asset) -> AssetStatus {
+    resolve_in(asset, asset.search_paths())
+}
+
+fn resolve_in(asset: Asset, searched: Vec<PathBuf>) -> AssetStatus {
+    let path = searched.iter().find(|path| path.is_file()).cloned();
+    AssetStatus { asset, path, searched }
+}
+
+/// Looks for every asset.
+pub fn resolve_all() -> Vec<AssetStatus> {
+    Asset::ALL.iter().map(|&asset| resolve(asset)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_assets_are_looked_for_in_order() {
+        let dir = std::env::temp_dir().join(format!("rustchess-assets-{}", std::process::id()));
+        fs::create_dir_all(dir.join(DATA_DIR)).unwrap();
+        let env = |name: &str| match name {
+            BOOK_ENV => Some(dir.join("missing.txt").into_os_string()),
+            "XDG_DATA_HOME" => Some(dir.clone().into_os_string()),
+            _ => None,
+        };
+
+        // The variable's file comes first but is skipped while missing
+        let paths = Asset::OpeningBook.search_paths_with(env);
+        assert_eq!(paths[0], dir.join("missing.txt"));
+        assert_eq!(paths[1], PathBuf::from(DEFAULT_BOOK_FILE));
+        assert_eq!(paths[2], dir.join(DATA_DIR).join(DEFAULT_BOOK_FILE));
+        assert_eq!(resolve_in(Asset::OpeningBook, paths.clone()).path, None);
+
+        fs::write(&paths[2], "").unwrap();
+        let status = resolve_in(Asset::OpeningBook, paths.clone());
+        assert_eq!(status.path.as_ref(), Some(&paths[2]));
+        assert_eq!(status.searched, paths);
+
+        // Generated tables are also found where they are saved
+        let paths = Asset::Tablebases.search_paths_with(|_| None);
+        assert_eq!(paths.last(), Some(&std::env::temp_dir().join(crate::tablebase::CACHE_FILE)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
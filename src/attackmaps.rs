@@ -0,0 +1,174 @@
+//! Squares each side attacks, by piece type.
+//!
+//! `AttackMaps` walks the piece list once and records what every piece
+//! attacks. From that single pass it answers the threat questions asked at a
+//! search node: which squares a side controls and with what, how often the
+//! ring around a king is hit, how mobile the pieces are, which checks are
+//! safe and which pieces are threatened. Build it with
+//! `Position::attack_maps`; it is not updated by moves.
+
+use crate::board_consts::king_zone;
+use crate::position::{Color, PieceType, Position};
+use crate::utils::*;
+use crate::Game;
+
+/// Attacks of both sides, indexed by `Color as usize` and
+/// `PieceType as usize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AttackMaps {
+    /// Squares attacked by each side's pieces of each type
+    by_type: [[Bitboard; 6]; 2],
+    /// Squares each side attacks with at least two pieces
+    twice: [Bitboard; 2],
+    /// Each side's pieces of each type
+    pieces: [[Bitboard; 6]; 2],
+    /// Attacks by each side on the squares around the other side's king,
+    /// counted once per attacking piece and square
+    king_ring_hits: [u32; 2],
+    /// Squares each side's knights, bishops, rooks and queens reach that
+    /// hold no own piece and no enemy pawn attack, summed over the pieces
+    mobility: [u32; 2],
+    /// Squares from which each side could check the enemy king with a piece
+    /// that already reaches them, and which the enemy does not attack
+    safe_checks: [Bitboard; 2],
+}
+
+impl AttackMaps {
+    /// Computes the attack maps of a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to examine
+    /// * `game` - The game holding the attack tables
+    pub fn new(position: &Position, game: &Game) -> Self {
+        let mut maps = AttackMaps::default();
+        let occupancy = position.white_occupancy | position.black_occupancy;
+
+        let mut attacks_of = alloc::vec::Vec::with_capacity(position.pieces.len());
+        for piece in &position.pieces {
+            let (side, kind) = (piece.color as usize, piece.piece_type as usize);
+            let attacks = piece.attacks(game, occupancy);
+            maps.pieces[side][kind] |= piece.position;
+            maps.twice[side] |= maps.attacks(piece.color) & attacks;
+            maps.by_type[side][kind] |= attacks;
+            attacks_of.push(attacks);
+        }
+
+        let own = [position.white_occupancy, position.black_occupancy];
+        for (piece, attacks) in position.pieces.iter().zip(attacks_of) {
+            let side = piece.color as usize;
+            let enemy = opposite(piece.color);
+            if let Some(king) = position.king_square(enemy) {
+                maps.king_ring_hits[side] += (attacks & king_zone(king)).count_ones();
+            }
+            if !matches!(piece.piece_type, PieceType::Pawn | PieceType::King) {
+                let area = !own[side] & !maps.by_type[enemy as usize][PieceType::Pawn as usize];
+                maps.mobility[side] += (attacks & area).count_ones();
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            let Some(king) = position.king_square(opposite(color)) else {
+                continue;
+            };
+            let side = color as usize;
+            let diagonal = game.rays.get_bishop_attacks(king, occupancy, color, 0);
+            let straight = game.rays.get_rook_attacks(king, occupancy);
+            let checks = (game.move_gen_tables.knight_attacks[king] & maps.by_type[side][PieceType::Knight as usize])
+                | (diagonal & maps.by_type[side][PieceType::Bishop as usize])
+                | (straight & maps.by_type[side][PieceType::Rook as usize])
+                | ((diagonal | straight) & maps.by_type[side][PieceType::Queen as usize]);
+            maps.safe_checks[side] = checks & !own[side] & !maps.attacks(opposite(color));
+        }
+
+        maps
+    }
+
+    /// All squares `color` attacks.
+    pub fn attacks(&self, color: Color) -> Bitboard {
+        self.by_type[color as usize].iter().fold(0, |all, attacks| all | attacks)
+    }
+
+    /// The squares `color`'s pieces of type `piece_type` attack.
+    pub fn attacks_by(&self, color: Color, piece_type: PieceType) -> Bitboard {
+        self.by_type[color as usize][piece_type as usize]
+    }
+
+    /// The squares `color` attacks with two or more pieces.
+    pub fn attacked_twice(&self, color: Color) -> Bitboard {
+        self.twice[color as usize]
+    }
+
+    /// How many attacks `color` has on the king and the squares around the
+    /// enemy king, counting each attacking piece once per square.
+    pub fn king_ring_attacks(&self, color: Color) -> u32 {
+        self.king_ring_hits[color as usize]
+    }
+
+    /// Squares `color`'s knights, bishops, rooks and queens can go to
+    /// without landing on an own piece or a square an enemy pawn guards,
+    /// summed over the pieces.
+    pub fn mobility(&self, color: Color) -> u32 {
+        self.mobility[color as usize]
+    }
+
+    /// Squares from which `color` could give check by moving a knight,
+    /// bishop, rook or queen there, where the enemy could not take it.
+    ///
+    /// Discovered checks and pawn checks are not included.
+    pub fn safe_checks(&self, color: Color) -> Bitboard {
+        self.safe_checks[color as usize]
+    }
+
+    /// `color`'s pieces the enemy threatens to win material with: pieces
+    /// attacked by a less valuable enemy piece, and pieces attacked but not
+    /// defended. Kings are never included.
+    pub fn threatened(&self, color: Color) -> Bitboard {
+        let (side, enemy) = (color as usize, opposite(color) as usize);
+        let own = &self.pieces[side];
+        let by_enemy = &self.by_type[enemy];
+        let minors = by_enemy[PieceType::Knight as usize] | by_enemy[PieceType::Bishop as usize];
+
+        let pieces = own[PieceType::Knight as usize] | own[PieceType::Bishop as usize]
+            | own[PieceType::Rook as usize] | own[PieceType::Queen as usize];
+        let by_pawns = by_enemy[PieceType::Pawn as usize] & pieces;
+        let by_minors = minors & (own[PieceType::Rook as usize] | own[PieceType::Queen as usize]);
+        let by_rooks = by_enemy[PieceType::Rook as usize] & own[PieceType::Queen as usize];
+        let hanging = self.attacks(opposite(color)) & !self.attacks(color)
+            & (pieces | own[PieceType::Pawn as usize]);
+
+        by_pawns | by_minors | by_rooks | hanging
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    if color == Color::White { Color::Black } else { Color::White }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_consts::RANK_3;
+
+    #[test]
+    fn test_attack_maps() {
+        let game = Game::new();
+        let start = game.position.attack_maps(&game);
+        assert_eq!(start.attacks_by(Color::White, PieceType::Pawn), RANK_3);
+        assert_eq!(start.attacks_by(Color::Black, PieceType::Knight), (1 << 40) | (1 << 42) | (1 << 45) | (1 << 47) | (1 << 51) | (1 << 52));
+        assert_eq!(start.mobility(Color::White), 4);
+        assert_eq!(start.king_ring_attacks(Color::White), 0);
+        assert_eq!(start.threatened(Color::White), 0);
+        assert_eq!(start.safe_checks(Color::White), 0);
+
+        // The knight on e5 is attacked by a pawn, the b2 pawn by the bishop
+        // and undefended; the rook can check safely from a8
+        let position = Position::read_FEN("4k3/8/3p4/4N3/8/2b5/1P6/R5K1 w - - 0 1", &game);
+        let maps = position.attack_maps(&game);
+        assert_eq!(maps.threatened(Color::White), (1 << 36) | (1 << 9));
+        assert_ne!(maps.attacked_twice(Color::White) & (1 << 5), 0);
+        assert_eq!(maps.safe_checks(Color::White), 1 << 56);
+        assert_eq!(maps.king_ring_attacks(Color::White), 2);
+        assert_eq!(maps.king_ring_attacks(Color::Black), 0);
+    }
+}
@@ -0,0 +1,127 @@
+//! Engine benchmark.
+//!
+//! `run` searches a fixed set of positions to a fixed depth, each with an
+//! empty hash table, and totals the nodes visited. The search is
+//! deterministic at a fixed depth, so the total is a signature of the
+//! engine: a change that alters it changes how the engine plays, and a
+//! change meant only to speed things up must leave it alone. The time taken
+//! gives the nodes per second, to catch slowdowns between commits.
+
+use std::time::{Duration, Instant};
+use crate::position::Position;
+use crate::search::Search;
+use crate::search_params::SearchParams;
+use crate::Game;
+
+/// Depth searched when none is given.
+pub const DEFAULT_BENCH_DEPTH: i32 = 4;
+
+/// Openings, middlegames and endgames, with and without tactics.
+pub const BENCH_POSITIONS: [&str; 20] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/8 b - - 3 54",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+    "8/3p4/p1bk3p/Pp6/1Kp1PpPp/2P2P1P/2P5/5B2 b - - 0 1",
+    "5k2/7R/4P2p/5K2/p1r2P1p/8/8/8 b - - 0 1",
+];
+
+/// The search of one benchmark position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchPosition {
+    pub fen: &'static str,
+    pub best_move: Option<u64>,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+/// The searches of all benchmark positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub depth: i32,
+    pub positions: Vec<BenchPosition>,
+}
+
+impl BenchReport {
+    /// Nodes visited over all positions; the engine's signature.
+    pub fn nodes(&self) -> u64 {
+        self.positions.iter().map(|position| position.nodes).sum()
+    }
+
+    /// Time taken over all positions.
+    pub fn elapsed(&self) -> Duration {
+        self.positions.iter().map(|position| position.elapsed).sum()
+    }
+
+    /// Nodes searched per second over all positions.
+    pub fn nodes_per_second(&self) -> u64 {
+        (self.nodes() as f64 / self.elapsed().as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+/// Searches every position of `BENCH_POSITIONS` to `depth`.
+///
+/// # Arguments
+///
+/// * `search` - The search to run, with its parameters; its hash table is
+///   cleared before every position and random root bonuses are turned off,
+///   so the node counts depend only on the engine
+/// * `depth` - The depth of every search, in plies
+///
+/// # Returns
+///
+/// * Each position's best move, nodes and time
+pub fn run(search: &mut Search, depth: i32) -> BenchReport {
+    let game = Game::new();
+    search.set_params(SearchParams { root_variety: 0, ..*search.params() });
+    search.set_max_depth(depth);
+    // Only the depth may end the searches
+    search.set_max_time(24 * 60 * 60);
+
+    let positions = BENCH_POSITIONS.iter().map(|&fen| {
+        let mut position = Position::read_FEN(fen, &game);
+        search.set_hash_size(search.hash_size());
+        let start = Instant::now();
+        let best_move = search.find_best_move(&mut position);
+        BenchPosition { fen, best_move, nodes: search.nodes_searched(), elapsed: start.elapsed() }
+    }).collect();
+    BenchReport { depth, positions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn test_bench_is_reproducible() {
+        for fen in BENCH_POSITIONS {
+            assert!(Board::from_fen(fen).is_ok(), "{}", fen);
+        }
+
+        let mut search = Search::new();
+        let report = run(&mut search, 2);
+        assert_eq!(report.positions.len(), BENCH_POSITIONS.len());
+        assert!(report.positions.iter().all(|position| position.best_move.is_some() && position.nodes > 0));
+        assert_eq!(report.nodes(), report.positions.iter().map(|position| position.nodes).sum::<u64>());
+
+        // The same engine searches the same nodes, whatever came before
+        search.set_max_depth(4);
+        search.find_best_move(&mut Position::new(&Game::new()));
+        assert_eq!(run(&mut search, 2).nodes(), report.nodes());
+    }
+}
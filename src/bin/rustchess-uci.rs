@@ -0,0 +1,16 @@
+//! UCI-only engine binary.
+//!
+//! Starts straight in UCI mode on stdin/stdout, with no subcommands to parse
+//! and no front end to set up, for tournament managers such as cutechess-cli
+//! or Arena. Built without the default features it holds no egui, line
+//! editing or HTTP code:
+//!
+//! ```bash
+//! cargo build --release --no-default-features --features uci --bin rustchess-uci
+//! ```
+
+use rustchess::uci::UCI;
+
+fn main() {
+    UCI::new().run();
+}
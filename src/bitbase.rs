@@ -0,0 +1,364 @@
+//! King and pawn versus king bitbase.
+//!
+//! Every KPvK position is classified as won or drawn for the side with the
+//! pawn by retrograde analysis: positions that are decided immediately
+//! (the pawn promotes safely, the pawn is lost, stalemate) are labelled
+//! first, and the remaining positions are resolved from their successors
+//! until nothing changes. Anything still unresolved at that point can never
+//! be forced to a win and is a draw.
+//!
+//! The table is built on first use and cached on disk, so later runs only
+//! pay for reading 24KB. Positions are normalized so the pawn belongs to
+//! White and stands on files a-d; the other cases are mirrored onto those.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::assets::Asset;
+use crate::position::{Position, Color, PieceType};
+use crate::utils::bit_scan;
+
+/// Side to move x pawn on files a-d and ranks 2-7 x white king x black king
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+const CACHE_MAGIC: &[u8; 8] = b"RCKPK001";
+pub(crate) const CACHE_FILE: &str = "rustchess_kpk.bin";
+
+/// Score for a won KPvK position. It stays below a queen so the search
+/// still prefers promoting once it can.
+const KPK_WIN_SCORE: i32 = 600;
+/// Extra score per rank the pawn has advanced, so won positions make progress
+const PAWN_RANK_BONUS: i32 = 10;
+
+// Classification states; successors are OR-ed together while resolving
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+static KPK: OnceLock<KpkBitbase> = OnceLock::new();
+
+/// Won/drawn table for every KPvK position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KpkBitbase {
+    bits: Vec<u64>,
+}
+
+impl KpkBitbase {
+    /// Builds the bitbase by retrograde analysis.
+    pub fn generate() -> Self {
+        let mut db: Vec<u8> = (0..MAX_INDEX).map(initial_result).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..MAX_INDEX {
+                if db[idx] == UNKNOWN {
+                    let result = classify(&db, idx);
+                    if result != UNKNOWN {
+                        db[idx] = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut bits = vec![0u64; MAX_INDEX / 64];
+        for (idx, &result) in db.iter().enumerate() {
+            if result == WIN {
+                bits[idx / 64] |= 1 << (idx % 64);
+            }
+        }
+        KpkBitbase { bits }
+    }
+
+    /// Bytes the bitbase takes.
+    pub fn memory_bytes(&self) -> usize {
+        self.bits.len() * std::mem::size_of::<u64>()
+    }
+
+    /// Reads the bitbase from `path`, generating and writing it there if the
+    /// file is missing or unreadable.
+    pub fn load_or_generate(path: &Path) -> Self {
+        if let Some(bitbase) = fs::read(path).ok().and_then(|bytes| Self::from_bytes(&bytes)) {
+            return bitbase;
+        }
+        let bitbase = Self::generate();
+        // The cache is only an optimization, so failing to write it is fine
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, bitbase.to_bytes()).is_ok() {
+            let _ = fs::rename(&tmp, path);
+        }
+        bitbase
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = CACHE_MAGIC.to_vec();
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let data = bytes.strip_prefix(CACHE_MAGIC.as_slice())?;
+        if data.len() != MAX_INDEX / 8 {
+            return None;
+        }
+        let bits = data.chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Some(KpkBitbase { bits })
+    }
+
+    /// Whether the side with the pawn wins with best play.
+    ///
+    /// # Arguments
+    ///
+    /// * `strong_king` - Square (0-63) of the king on the pawn's side
+    /// * `pawn` - Square of the pawn
+    /// * `weak_king` - Square of the lone king
+    /// * `strong_side` - Color of the pawn
+    /// * `side_to_move` - Color to move
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the position is won, `false` if it is drawn or illegal
+    pub fn probe(&self, strong_king: usize, pawn: usize, weak_king: usize,
+                 strong_side: Color, side_to_move: Color) -> bool {
+        let (mut strong_king, mut pawn, mut weak_king) = (strong_king, pawn, weak_king);
+        if strong_side == Color::Black {
+            strong_king ^= 56;
+            pawn ^= 56;
+            weak_king ^= 56;
+        }
+        if pawn % 8 > 3 {
+            strong_king ^= 7;
+            pawn ^= 7;
+            weak_king ^= 7;
+        }
+        if !(1..=6).contains(&(pawn / 8)) {
+            return false;
+        }
+
+        let idx = index(side_to_move == strong_side, strong_king, weak_king, pawn);
+        self.bits[idx / 64] & (1 << (idx % 64)) != 0
+    }
+}
+
+/// The shared bitbase, loaded or generated on first use.
+pub fn kpk() -> &'static KpkBitbase {
+    KPK.get_or_init(|| KpkBitbase::load_or_generate(&cache_path()))
+}
+
+/// The shared bitbase if it has been used yet, without loading it.
+pub fn loaded_kpk() -> Option<&'static KpkBitbase> {
+    KPK.get()
+}
+
+/// A saved copy of the bitbase found among the asset paths, or where a
+/// generated copy is saved.
+fn cache_path() -> PathBuf {
+    Asset::KpkBitbase.locate().unwrap_or_else(|| std::env::temp_dir().join(CACHE_FILE))
+}
+
+/// Scores a position from the bitbase if it is a KPvK ending.
+///
+/// # Arguments
+///
+/// * `position` - The position to score
+///
+/// # Returns
+///
+/// * The score in centipawns from White's perspective, or `None` if the
+///   position is not king and pawn versus king
+pub fn probe_position(position: &Position) -> Option<i32> {
+    let mut pieces = position.pieces.iter();
+    let (a, b, c) = (pieces.next()?, pieces.next()?, pieces.next()?);
+    if pieces.next().is_some() {
+        return None;
+    }
+
+    let pawn = [a, b, c].into_iter().find(|p| p.piece_type == PieceType::Pawn)?;
+    let king_of = |color: Color| [a, b, c].into_iter()
+        .find(|p| p.piece_type == PieceType::King && p.color == color);
+    let strong_king = king_of(pawn.color)?;
+    let weak_king = king_of(opposite(pawn.color))?;
+
+    let pawn_square = bit_scan(pawn.position);
+    let won = kpk().probe(
+        bit_scan(strong_king.position),
+        pawn_square,
+        bit_scan(weak_king.position),
+        pawn.color,
+        position.active_color,
+    );
+    if !won {
+        return Some(0);
+    }
+
+    let ranks_advanced = match pawn.color {
+        Color::White => pawn_square / 8,
+        Color::Black => 7 - pawn_square / 8,
+    } as i32;
+    let score = KPK_WIN_SCORE + ranks_advanced * PAWN_RANK_BONUS;
+    Some(if pawn.color == Color::White { score } else { -score })
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+/// Packs a normalized position into a table index.
+fn index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    let side = if white_to_move { 0 } else { 1 };
+    white_king | (black_king << 6) | (side << 12) | ((pawn % 8) << 13) | ((6 - pawn / 8) << 15)
+}
+
+fn decode(idx: usize) -> (bool, usize, usize, usize) {
+    let white_king = idx & 63;
+    let black_king = (idx >> 6) & 63;
+    let white_to_move = (idx >> 12) & 1 == 0;
+    let pawn = (6 - (idx >> 15)) * 8 + ((idx >> 13) & 3);
+    (white_to_move, white_king, black_king, pawn)
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+    files.max(ranks)
+}
+
+fn king_moves(square: usize) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |&to| distance(square, to) == 1)
+}
+
+fn pawn_attacks(pawn: usize, square: usize) -> bool {
+    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+}
+
+/// Labels the positions that are decided without looking at successors.
+fn initial_result(idx: usize) -> u8 {
+    let (white_to_move, white_king, black_king, pawn) = decode(idx);
+
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks(pawn, black_king)) {
+        return INVALID;
+    }
+
+    if white_to_move {
+        // The pawn promotes and the new queen can't be taken
+        let queening = pawn + 8;
+        if pawn / 8 == 6 && white_king != queening
+            && (distance(black_king, queening) > 1 || distance(white_king, queening) == 1) {
+            return WIN;
+        }
+    } else {
+        let safe = |square: usize| distance(white_king, square) > 1 && !pawn_attacks(pawn, square);
+        // Stalemate, or the pawn falls
+        if !king_moves(black_king).any(|square| square != pawn && safe(square))
+            || (distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1) {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+/// Resolves a position from its successors: the side to move picks its best.
+fn classify(db: &[u8], idx: usize) -> u8 {
+    let (white_to_move, white_king, black_king, pawn) = decode(idx);
+    let mut reachable = INVALID;
+
+    if white_to_move {
+        for square in king_moves(white_king) {
+            reachable |= db[index(false, square, black_king, pawn)];
+        }
+        // Promotion from the 7th rank is handled by `initial_result`
+        let push = pawn + 8;
+        if pawn / 8 < 6 {
+            reachable |= db[index(false, white_king, black_king, push)];
+        }
+        if pawn / 8 == 1 && push != white_king && push != black_king {
+            reachable |= db[index(false, white_king, black_king, pawn + 16)];
+        }
+    } else {
+        for square in king_moves(black_king) {
+            reachable |= db[index(true, white_king, square, pawn)];
+        }
+    }
+
+    let (good, bad) = if white_to_move { (WIN, DRAW) } else { (DRAW, WIN) };
+    if reachable & good != 0 {
+        good
+    } else if reachable & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    fn sq(name: &str) -> usize {
+        let bytes = name.as_bytes();
+        (bytes[1] - b'1') as usize * 8 + (bytes[0] - b'a') as usize
+    }
+
+    #[test]
+    fn test_known_positions() {
+        let kpk = kpk();
+        // King on the sixth in front of the pawn wins with either side to move
+        assert!(kpk.probe(sq("d6"), sq("d4"), sq("d8"), Color::White, Color::White));
+        assert!(kpk.probe(sq("d6"), sq("d4"), sq("d8"), Color::White, Color::Black));
+        // Defending king in front of a rook pawn always holds
+        assert!(!kpk.probe(sq("h1"), sq("a5"), sq("a8"), Color::White, Color::White));
+        // The pawn runs outside the square of the defending king
+        assert!(kpk.probe(sq("h1"), sq("a5"), sq("h8"), Color::White, Color::White));
+        // The undefended pawn is captured
+        assert!(!kpk.probe(sq("h1"), sq("e4"), sq("d5"), Color::White, Color::Black));
+        // Opposition: whichever side has to move gives way
+        assert!(kpk.probe(sq("e5"), sq("e4"), sq("e7"), Color::White, Color::Black));
+        assert!(!kpk.probe(sq("e5"), sq("e4"), sq("e7"), Color::White, Color::White));
+    }
+
+    #[test]
+    fn test_mirrored_positions_agree() {
+        let kpk = kpk();
+        let cases = [("d6", "d4", "d8"), ("h1", "a5", "h8"), ("e6", "e5", "e8"), ("b6", "b5", "b8")];
+        for (strong, pawn, weak) in cases {
+            for to_move in [Color::White, Color::Black] {
+                let expected = kpk.probe(sq(strong), sq(pawn), sq(weak), Color::White, to_move);
+                let black_to_move = if to_move == Color::White { Color::Black } else { Color::White };
+                assert_eq!(kpk.probe(sq(strong) ^ 7, sq(pawn) ^ 7, sq(weak) ^ 7, Color::White, to_move), expected);
+                assert_eq!(kpk.probe(sq(strong) ^ 56, sq(pawn) ^ 56, sq(weak) ^ 56, Color::Black, black_to_move), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let bitbase = kpk();
+        assert_eq!(KpkBitbase::from_bytes(&bitbase.to_bytes()).as_ref(), Some(bitbase));
+        assert!(KpkBitbase::from_bytes(b"RCKPK001 truncated").is_none());
+    }
+
+    #[test]
+    fn test_probe_position() {
+        let game = Game::new();
+        let won = Position::from_fen("3k4/8/3K4/8/3P4/8/8/8 b - - 0 1", &game).unwrap();
+        assert!(probe_position(&won).unwrap() >= KPK_WIN_SCORE);
+        let black_won = Position::from_fen("8/8/8/3p4/8/3k4/8/3K4 w - - 0 1", &game).unwrap();
+        assert!(probe_position(&black_won).unwrap() <= -KPK_WIN_SCORE);
+        let drawn = Position::from_fen("k7/8/8/P7/8/8/8/7K w - - 0 1", &game).unwrap();
+        assert_eq!(probe_position(&drawn), Some(0));
+        assert_eq!(probe_position(&Position::new(&game)), None);
+    }
+}
@@ -0,0 +1,466 @@
+//! High level board API.
+//!
+//! `Board` bundles a `Position` with the lookup tables needed to generate its
+//! moves, so callers can query and play moves without touching piece indices,
+//! occupancy bitboards or the packed move encoding.
+
+use alloc::{format, string::String, vec::Vec};
+use crate::Game;
+use crate::chess_move::Move;
+use crate::position::{Position, Color, PieceType, Square, position_to_bit};
+use crate::utils::bit_scan;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// FEN of the standard starting position.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// A chess position together with everything needed to play moves on it.
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::Board;
+///
+/// let board = Board::new();
+/// assert_eq!(board.legal_moves().len(), 20);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Board {
+    game: Game,
+}
+
+impl Board {
+    /// Creates a board in the standard starting position.
+    pub fn new() -> Board {
+        Board { game: Game::new() }
+    }
+
+    /// Creates a board from a FEN string.
+    ///
+    /// # Arguments
+    ///
+    /// * `fen` - The six-field FEN string describing the position
+    ///
+    /// # Returns
+    ///
+    /// * The board, or a message describing why the FEN could not be parsed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustchess::Board;
+    /// use rustchess::position::Color;
+    ///
+    /// let board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R b K - 0 1").unwrap();
+    /// assert_eq!(board.side_to_move(), Color::Black);
+    /// assert!(Board::from_fen("not a fen").is_err());
+    /// ```
+    pub fn from_fen(fen: &str) -> Result<Board, String> {
+        let mut game = Game::new();
+        game.position = Position::from_fen(fen, &game)?;
+        Ok(Board { game })
+    }
+
+    /// Creates a board for a position built with the lower level API.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position; its legal moves are regenerated
+    pub fn from_position(position: Position) -> Board {
+        Board::with_tables(position, &Game::new())
+    }
+
+    /// Creates a board for a position, reusing the attack tables of `tables`
+    /// instead of building new ones.
+    pub(crate) fn with_tables(position: Position, tables: &Game) -> Board {
+        let mut game = tables.clone();
+        game.position = position;
+        game.position.update_all_legal_moves(tables);
+        Board { game }
+    }
+
+    /// Returns all legal moves for the side to move.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let position = &self.game.position;
+        position.get_all_legal_moves(&self.game)
+            .into_iter()
+            .map(|mov| Move::from_u64(mov, position))
+            .collect()
+    }
+
+    /// Parses a move in long algebraic notation (`e2e4`, `e7e8q`) and checks
+    /// that it is legal in the current position.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The move text
+    ///
+    /// # Returns
+    ///
+    /// * The matching legal move, or a message explaining why it was rejected
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustchess::Board;
+    ///
+    /// let board = Board::new();
+    /// let mv = board.parse_move("g1f3").unwrap();
+    /// assert_eq!(mv.to_string(), "g1f3");
+    /// assert!(board.parse_move("e2e5").is_err());
+    /// ```
+    pub fn parse_move(&self, text: &str) -> Result<Move, String> {
+        let text = text.trim();
+        if !text.is_ascii() || (text.len() != 4 && text.len() != 5) {
+            return Err(format!("Move must be in format 'e2e4', got '{}'", text));
+        }
+        let from = bit_scan(position_to_bit(&text[0..2])?);
+        let to = bit_scan(position_to_bit(&text[2..4])?);
+        // A promotion without a piece letter promotes to a queen
+        let promotion = match text[4..].to_ascii_lowercase().as_str() {
+            "" | "q" => PieceType::Queen,
+            "r" => PieceType::Rook,
+            "b" => PieceType::Bishop,
+            "n" => PieceType::Knight,
+            other => return Err(format!("Invalid promotion piece '{}'", other)),
+        };
+
+        self.legal_moves()
+            .into_iter()
+            .find(|mv| mv.from() == from && mv.to() == to
+                && mv.promotion().is_none_or(|piece_type| piece_type == promotion))
+            .ok_or_else(|| format!("Illegal move: {}", text))
+    }
+
+    /// Plays a move, switching the side to move.
+    ///
+    /// # Arguments
+    ///
+    /// * `mv` - The move to play; it must be legal in the current position
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the move was played, or an error if it is not legal
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustchess::Board;
+    /// use rustchess::position::{Color, PieceType};
+    ///
+    /// let mut board = Board::new();
+    /// let mv = board.parse_move("e2e4").unwrap();
+    /// board.play(mv).unwrap();
+    /// assert_eq!(board.side_to_move(), Color::Black);
+    /// assert_eq!(board.piece_at(28), Some((Color::White, PieceType::Pawn)));
+    /// ```
+    pub fn play(&mut self, mv: Move) -> Result<(), String> {
+        if !self.legal_moves().contains(&mv) {
+            return Err(format!("Illegal move: {}", mv));
+        }
+        let game = self.game.clone();
+        self.game.position.make_move(mv.to_u64());
+        self.game.position.update_all_legal_moves(&game);
+        Ok(())
+    }
+
+    /// The color whose turn it is.
+    pub fn side_to_move(&self) -> Color {
+        self.game.position.active_color
+    }
+
+    /// Returns the color and type of the piece on `square` (0-63, a1 = 0).
+    pub fn piece_at(&self, square: usize) -> Option<(Color, PieceType)> {
+        match self.game.position.squares.get(square)? {
+            Square::Empty => None,
+            Square::Occupied(idx) => {
+                let piece = &self.game.position.pieces[*idx];
+                Some((piece.color, piece.piece_type))
+            }
+        }
+    }
+
+    /// Whether the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        self.game.position.is_in_check(&self.game)
+    }
+
+    /// Whether `mv` checks the opponent, worked out without playing it.
+    pub fn gives_check(&self, mv: Move) -> bool {
+        self.game.position.gives_check(&self.game, mv.to_u64())
+    }
+
+    /// Read-only access to the underlying position.
+    pub fn position(&self) -> &Position {
+        &self.game.position
+    }
+
+    /// The Zobrist hash of the position, as opening books and the
+    /// transposition table key it.
+    pub fn hash(&self) -> u64 {
+        self.game.position.get_hash(&self.game)
+    }
+
+    /// Writes castling as the king taking its own rook, as Chess960 games
+    /// do, or as the king's two-square move. Positions whose kings or rooks
+    /// start elsewhere than in standard chess always use the former.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        let game = self.game.clone();
+        self.game.position.set_chess960(&game, chess960);
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
+/// Builds a board from a starting position and the moves played from it,
+/// as in UCI's `position startpos moves e2e4 e7e5`. Every move is checked
+/// for legality when the board is built.
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::PositionBuilder;
+///
+/// let board = PositionBuilder::startpos().moves(["e2e4", "e7e5", "g1f3"]).build().unwrap();
+/// assert_eq!(board.legal_moves().len(), 29);
+///
+/// let spec = PositionBuilder::parse("fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves h1h8").unwrap();
+/// assert!(spec.build().is_ok());
+/// assert!(PositionBuilder::startpos().moves(["e2e4", "e2e4"]).build().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionBuilder {
+    /// FEN of the starting position
+    fen: String,
+    /// Moves in long algebraic notation
+    moves: Vec<String>,
+    /// Whether castling moves are written as the king taking its own rook
+    chess960: bool,
+}
+
+impl PositionBuilder {
+    /// Starts from the standard starting position.
+    pub fn startpos() -> Self {
+        PositionBuilder::fen(START_FEN)
+    }
+
+    /// Starts from a position given as FEN.
+    pub fn fen(fen: &str) -> Self {
+        PositionBuilder { fen: String::from(fen.trim()), moves: Vec::new(), chess960: false }
+    }
+
+    /// Adds moves in long algebraic notation (`e2e4`, `e7e8q`) to replay.
+    pub fn moves<I, S>(mut self, moves: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.moves.extend(moves.into_iter().map(|mv| String::from(mv.as_ref())));
+        self
+    }
+
+    /// Reads castling moves as the king taking its own rook (`e1h1`), as in
+    /// Chess960, even from the standard starting position.
+    pub fn chess960(mut self, chess960: bool) -> Self {
+        self.chess960 = chess960;
+        self
+    }
+
+    /// Parses a specification in the form of UCI's `position` arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - `startpos` or `fen <fields>`, optionally followed by
+    ///   `moves <move>...`
+    ///
+    /// # Returns
+    ///
+    /// * The builder, or an error if the specification has neither form;
+    ///   the FEN and moves are only checked by `build`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = spec.split_whitespace().collect();
+        PositionBuilder::from_tokens(&tokens)
+    }
+
+    /// Like `parse`, for a specification already split into tokens.
+    pub fn from_tokens(tokens: &[&str]) -> Result<Self, String> {
+        let moves_index = tokens.iter().position(|&token| token == "moves").unwrap_or(tokens.len());
+        let (setup, moves) = tokens.split_at(moves_index);
+        let builder = match setup.split_first() {
+            Some((&"startpos", [])) => PositionBuilder::startpos(),
+            Some((&"fen", fields)) if !fields.is_empty() => PositionBuilder::fen(&fields.join(" ")),
+            _ => return Err(format!("Expected 'startpos' or 'fen <fen>', got '{}'", tokens.join(" "))),
+        };
+        Ok(builder.moves(moves.iter().skip(1)))
+    }
+
+    /// Sets up the starting position and replays the moves.
+    ///
+    /// # Returns
+    ///
+    /// * The board after the last move, or an error naming the invalid FEN
+    ///   or the first illegal move and its number
+    pub fn build(&self) -> Result<Board, String> {
+        let mut board = Board::from_fen(&self.fen)?;
+        if self.chess960 {
+            board.set_chess960(true);
+        }
+        for (index, text) in self.moves.iter().enumerate() {
+            board.parse_move(text)
+                .and_then(|mv| board.play(mv))
+                .map_err(|e| format!("Move {} ({}): {}", index + 1, text, e))?;
+        }
+        Ok(board)
+    }
+}
+
+/// A game as front ends save and restore it: the position it started from
+/// and the moves played since, packed as by `Move::to_u64`. With the `serde`
+/// feature it serializes to JSON, bincode or any other serde format.
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::{Board, GameState};
+///
+/// let mut state = GameState::new(Board::new().position().clone());
+/// let board = Board::new();
+/// state.push(board.parse_move("e2e4").unwrap());
+/// assert_eq!(state.board().unwrap().legal_moves().len(), 20);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameState {
+    /// The position before the first move
+    pub start: Position,
+    /// The moves played from `start`, in order
+    pub moves: Vec<u64>,
+}
+
+impl GameState {
+    /// Starts a game with no moves from `start`.
+    pub fn new(start: Position) -> Self {
+        GameState { start, moves: Vec::new() }
+    }
+
+    /// Records a move played after the existing ones.
+    pub fn push(&mut self, mv: Move) {
+        self.moves.push(mv.to_u64());
+    }
+
+    /// Sets up the starting position and replays the moves, as when
+    /// restoring a saved game.
+    ///
+    /// # Returns
+    ///
+    /// * The board after the last move, or an error naming the first
+    ///   illegal move and its number
+    pub fn board(&self) -> Result<Board, String> {
+        let mut board = Board::from_position(self.start.clone());
+        for (index, &packed) in self.moves.iter().enumerate() {
+            let mv = Move::from_u64(packed, board.position());
+            board.play(mv).map_err(|e| format!("Move {}: {}", index + 1, e))?;
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_capture_updates_squares() {
+        let mut board = Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mv = board.parse_move("e4d5").unwrap();
+        assert!(mv.is_capture());
+
+        board.play(mv).unwrap();
+        assert_eq!(board.piece_at(35), Some((Color::White, PieceType::Pawn)));
+        assert_eq!(board.piece_at(28), None);
+    }
+
+    #[test]
+    fn test_play_rejects_illegal_move() {
+        let mut board = Board::new();
+        let black_move = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+            .unwrap()
+            .parse_move("e7e5")
+            .unwrap();
+        assert!(board.play(black_move).is_err());
+        assert_eq!(board.side_to_move(), Color::White);
+    }
+
+    #[test]
+    fn test_parse_promotions() {
+        let board = Board::from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.parse_move("a7a8").unwrap().promotion(), Some(PieceType::Queen));
+        assert_eq!(board.parse_move("a7b8n").unwrap().promotion(), Some(PieceType::Knight));
+        assert!(board.parse_move("a7b8n").unwrap().is_capture());
+        assert!(board.parse_move("a7a8k").is_err());
+
+        let mut board = board;
+        board.play(board.parse_move("a7b8r").unwrap()).unwrap();
+        assert_eq!(board.piece_at(57), Some((Color::White, PieceType::Rook)));
+    }
+
+    #[test]
+    fn test_piece_at_matches_fen_layout() {
+        let board = Board::new();
+        assert_eq!(board.piece_at(3), Some((Color::White, PieceType::Queen)));
+        assert_eq!(board.piece_at(4), Some((Color::White, PieceType::King)));
+        assert_eq!(board.piece_at(60), Some((Color::Black, PieceType::King)));
+        assert_eq!(board.piece_at(64), None);
+    }
+
+    #[test]
+    fn test_position_builder_reports_the_failing_move() {
+        let spec = PositionBuilder::parse("startpos moves e2e4 e7e5 e1e3").unwrap();
+        assert_eq!(spec, PositionBuilder::startpos().moves(["e2e4", "e7e5", "e1e3"]));
+        assert_eq!(spec.build().unwrap_err(), "Move 3 (e1e3): Illegal move: e1e3");
+
+        let board = PositionBuilder::parse("fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4").unwrap().build().unwrap();
+        assert_eq!(board.piece_at(28), Some((Color::White, PieceType::Pawn)));
+        assert!(PositionBuilder::parse("fen 8/8/8/8/8/8/8/8 w - - 0 1").unwrap().build().is_err());
+        assert!(PositionBuilder::parse("moves e2e4").is_err());
+    }
+
+    #[test]
+    fn test_game_state_replays_its_moves() {
+        let mut state = GameState::new(Board::new().position().clone());
+        let mut board = Board::new();
+        for text in ["e2e4", "e7e5", "g1f3"] {
+            let mv = board.parse_move(text).unwrap();
+            board.play(mv).unwrap();
+            state.push(mv);
+        }
+        assert_eq!(state.board().unwrap().position().to_fen(), board.position().to_fen());
+
+        state.moves.push(state.moves[0]);
+        assert!(state.board().unwrap_err().starts_with("Move 4: Illegal move"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_game_state_json_round_trip() {
+        let start = Board::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 12").unwrap();
+        let mut state = GameState::new(start.position().clone());
+        state.push(start.parse_move("e5d6").unwrap());
+
+        let json = serde_json::to_string(&state).unwrap();
+        let restored: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.start.to_fen(), start.position().to_fen());
+        assert_eq!(restored.start.castling_rights, start.position().castling_rights);
+        assert_eq!(restored.moves, state.moves);
+        assert_eq!(restored.board().unwrap().position().to_fen(), state.board().unwrap().position().to_fen());
+
+        // A restored position generates its moves again
+        assert_eq!(Board::from_position(restored.start).legal_moves(), start.legal_moves());
+        assert!(serde_json::from_str::<GameState>(&json.replace("\"castling_rights\":9", "\"castling_rights\":99")).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Named bitboard constants.
+//!
+//! Files, ranks, centre squares, king zones and castling paths, so that move
+//! generation and evaluation refer to squares by name instead of by inline
+//! hex literals. Square 0 is a1, square 7 is h1 and square 63 is h8.
+
+use crate::utils::Bitboard;
+
+pub const FILE_A: Bitboard = 0x0101010101010101;
+pub const FILE_B: Bitboard = FILE_A << 1;
+pub const FILE_C: Bitboard = FILE_A << 2;
+pub const FILE_D: Bitboard = FILE_A << 3;
+pub const FILE_E: Bitboard = FILE_A << 4;
+pub const FILE_F: Bitboard = FILE_A << 5;
+pub const FILE_G: Bitboard = FILE_A << 6;
+pub const FILE_H: Bitboard = FILE_A << 7;
+
+/// The files from a to h.
+pub const FILES: [Bitboard; 8] = [FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H];
+
+pub const RANK_1: Bitboard = 0xFF;
+pub const RANK_2: Bitboard = RANK_1 << 8;
+pub const RANK_3: Bitboard = RANK_1 << 16;
+pub const RANK_4: Bitboard = RANK_1 << 24;
+pub const RANK_5: Bitboard = RANK_1 << 32;
+pub const RANK_6: Bitboard = RANK_1 << 40;
+pub const RANK_7: Bitboard = RANK_1 << 48;
+pub const RANK_8: Bitboard = RANK_1 << 56;
+
+/// The ranks from 1 to 8.
+pub const RANKS: [Bitboard; 8] = [RANK_1, RANK_2, RANK_3, RANK_4, RANK_5, RANK_6, RANK_7, RANK_8];
+
+/// d4, e4, d5 and e5.
+pub const CENTER: Bitboard = (FILE_D | FILE_E) & (RANK_4 | RANK_5);
+/// The sixteen squares from c3 to f6.
+pub const EXTENDED_CENTER: Bitboard = (FILE_C | FILE_D | FILE_E | FILE_F) & (RANK_3 | RANK_4 | RANK_5 | RANK_6);
+
+/// Squares between the king and the rook that must be empty to castle.
+pub const WHITE_KINGSIDE_PATH: Bitboard = (FILE_F | FILE_G) & RANK_1;
+pub const WHITE_QUEENSIDE_PATH: Bitboard = (FILE_B | FILE_C | FILE_D) & RANK_1;
+pub const BLACK_KINGSIDE_PATH: Bitboard = (FILE_F | FILE_G) & RANK_8;
+pub const BLACK_QUEENSIDE_PATH: Bitboard = (FILE_B | FILE_C | FILE_D) & RANK_8;
+
+/// The files next to `file`, without wrapping around the board edge.
+///
+/// # Arguments
+///
+/// * `file` - The file index (0-7, a to h)
+pub const fn adjacent_files(file: usize) -> Bitboard {
+    let left = if file > 0 { FILES[file - 1] } else { 0 };
+    let right = if file < 7 { FILES[file + 1] } else { 0 };
+    left | right
+}
+
+/// The king's square and the squares around it.
+///
+/// # Arguments
+///
+/// * `square` - The king's square (0-63)
+pub const fn king_zone(square: usize) -> Bitboard {
+    let king = 1u64 << square;
+    let files = king | ((king << 1) & !FILE_A) | ((king >> 1) & !FILE_H);
+    files | (files << 8) | (files >> 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_files_and_ranks() {
+        assert_eq!(FILE_H, 0x8080808080808080);
+        assert_eq!(RANK_8, 0xFF00000000000000);
+        assert_eq!(FILES.iter().fold(0, |all, file| all | file), u64::MAX);
+        assert_eq!(RANKS.iter().fold(0, |all, rank| all | rank), u64::MAX);
+        assert!(FILES.iter().chain(RANKS.iter()).all(|mask| mask.count_ones() == 8));
+    }
+
+    #[test]
+    fn test_named_squares() {
+        let squares = |bits: Bitboard| -> Vec<usize> { (0..64).filter(|&square| bits & (1 << square) != 0).collect() };
+        assert_eq!(squares(CENTER), [27, 28, 35, 36]);  // d4, e4, d5, e5
+        assert_eq!(EXTENDED_CENTER.count_ones(), 16);
+        assert_eq!(squares(WHITE_KINGSIDE_PATH), [5, 6]);  // f1, g1
+        assert_eq!(squares(WHITE_QUEENSIDE_PATH), [1, 2, 3]);  // b1, c1, d1
+        assert_eq!(BLACK_KINGSIDE_PATH, WHITE_KINGSIDE_PATH << 56);
+        assert_eq!(BLACK_QUEENSIDE_PATH, WHITE_QUEENSIDE_PATH << 56);
+    }
+
+    #[test]
+    fn test_adjacent_files_and_king_zones() {
+        assert_eq!(adjacent_files(0), FILE_B);
+        assert_eq!(adjacent_files(4), FILE_D | FILE_F);
+        assert_eq!(adjacent_files(7), FILE_G);
+
+        assert_eq!(king_zone(0).count_ones(), 4);  // a1 corner
+        assert_eq!(king_zone(7), 0xC0C0);  // h1 does not wrap to the a-file
+        assert_eq!(king_zone(36).count_ones(), 9);  // e5
+        assert_eq!(king_zone(60) & RANK_1, 0);  // e8 does not wrap to the first rank
+    }
+}
@@ -0,0 +1,561 @@
+//! Opening books.
+//!
+//! Front ends read books through the `OpeningBook` trait, and `load_book`
+//! picks the backend from the file's contents:
+//!
+//! - The self-learning book (`LearningBook`) maps Zobrist hashes of
+//!   positions to the moves the engine has played from them, each with a
+//!   weight. After every finished game the engine's opening moves are
+//!   credited with the result (won lines gain weight, lost lines lose it),
+//!   and the book is written back to disk so the engine's opening choices
+//!   adapt over time. The file format is plain text, one entry per line:
+//!   `<hash as hex> <packed move as hex> <weight> <wins> <draws> <losses>`.
+//! - Polyglot `.bin` books (see `polyglot`) are read-only.
+//!
+//! ChessBase CTG books and other formats are refused with an error naming
+//! the format.
+//!
+//! A bad or poisoned line can lead the engine into a lost position, so
+//! before playing a book move `verify_book_move` can search it against the
+//! engine's own choice; a book move scoring clearly worse is dropped and the
+//! engine leaves the book.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::player::{Player, SearchLimits};
+use crate::polyglot::PolyglotBook;
+use crate::position::Position;
+
+/// Number of plies from the start of a game that the book learns from.
+pub const BOOK_PLIES: usize = 20;
+/// Weight given to a move the first time it is added to the book.
+const INITIAL_WEIGHT: i32 = 10;
+const WIN_BONUS: i32 = 4;
+const DRAW_BONUS: i32 = 1;
+const LOSS_PENALTY: i32 = 6;
+/// How many centipawns worse than the engine's own choice a book move may
+/// score before `verify_book_move` refutes it.
+pub const DEFAULT_EXIT_MARGIN: i32 = 100;
+
+/// Result of a game from the engine's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameOutcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// A move stored in the book together with its learning statistics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookMove {
+    pub mov: u64,
+    /// Moves with a weight of zero or less are never chosen
+    pub weight: i32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl BookMove {
+    /// Number of finished games the move was played in.
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Share of the points scored with the move, from 0.0 to 1.0, for the
+    /// side that played it; `None` if it has no finished games.
+    pub fn score(&self) -> Option<f64> {
+        let games = self.games();
+        (games > 0).then(|| (self.wins as f64 + self.draws as f64 / 2.0) / games as f64)
+    }
+}
+
+/// The book file formats that can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookFormat {
+    /// The engine's own self-learning text book
+    Learning,
+    Polyglot,
+}
+
+impl BookFormat {
+    /// Tells a book's format from its file name and contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The book's path; only the extension is looked at
+    /// * `bytes` - The file's contents
+    ///
+    /// # Returns
+    ///
+    /// * The format, or an error naming the format when it is known but
+    ///   not supported, as for ChessBase CTG and Arena ABK books
+    pub fn detect(path: &Path, bytes: &[u8]) -> Result<BookFormat, String> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match extension.as_str() {
+            "ctg" | "ctb" | "cto" => return Err(format!(
+                "{} is a ChessBase CTG book, which is not supported; convert it to a Polyglot .bin book", path.display())),
+            "abk" => return Err(format!(
+                "{} is an Arena ABK book, which is not supported; convert it to a Polyglot .bin book", path.display())),
+            _ => {}
+        }
+
+        let text = std::str::from_utf8(bytes).ok();
+        let is_learning = text.is_some_and(|text| text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .all(|line| parse_line(line).is_ok()));
+        if extension != "bin" && is_learning {
+            return Ok(BookFormat::Learning);
+        }
+        match PolyglotBook::from_bytes(bytes) {
+            Ok(_) => Ok(BookFormat::Polyglot),
+            Err(e) if extension == "bin" => Err(format!("{} is not a valid Polyglot book: {}", path.display(), e)),
+            Err(_) if text.is_some_and(|text| text.trim_start().starts_with('[')) => Err(format!(
+                "{} looks like a PGN file, not a book; play games with `--book` to build one", path.display())),
+            Err(_) => Err(format!(
+                "{} is not a book this engine reads: expected a Polyglot .bin book or a text book of \
+                 `<hash> <move> <weight> <wins> <draws> <losses>` lines", path.display())),
+        }
+    }
+}
+
+impl fmt::Display for BookFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookFormat::Learning => write!(f, "learning book"),
+            BookFormat::Polyglot => write!(f, "Polyglot book"),
+        }
+    }
+}
+
+/// A book the engine and the opening explorer can take moves from.
+pub trait OpeningBook {
+    fn format(&self) -> BookFormat;
+
+    /// The book's moves for a position. Moves are packed as by
+    /// `Move::encode` and, after a hash collision, may be illegal.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position
+    /// * `hash` - The engine's Zobrist hash of it, from `Position::get_hash`
+    fn moves(&self, position: &Position, hash: u64) -> Vec<BookMove>;
+
+    /// Credits the engine's moves from a finished game with its result;
+    /// books that do not learn ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine_moves` - `(position hash, move)` for each move the engine
+    ///   played, in game order
+    /// * `outcome` - The result from the engine's point of view
+    fn learn(&mut self, _engine_moves: &[(u64, u64)], _outcome: GameOutcome) {}
+
+    /// Writes what the book learned back to its file; read-only books have
+    /// nothing to write.
+    fn save(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Approximate bytes the book takes in memory.
+    fn memory_bytes(&self) -> usize;
+}
+
+/// Loads a book of either format, telling them apart by their contents.
+/// A missing file gives an empty learning book that will be created on the
+/// first save, unless its name ends in `.bin`.
+///
+/// # Arguments
+///
+/// * `path` - Location of the book file
+///
+/// # Returns
+///
+/// * The book, or an error if the file cannot be read, is in a format that
+///   is not supported, or is corrupt
+pub fn load_book<P: AsRef<Path>>(path: P) -> Result<Box<dyn OpeningBook>, String> {
+    let path = path.as_ref();
+    if !path.exists() {
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("bin")) {
+            return Err(format!("Polyglot book {} does not exist", path.display()));
+        }
+        return Ok(Box::new(LearningBook::load(path)?));
+    }
+    let bytes = fs::read(path).map_err(|e| format!("Could not read book {}: {}", path.display(), e))?;
+    match BookFormat::detect(path, &bytes)? {
+        BookFormat::Learning => Ok(Box::new(LearningBook::load(path)?)),
+        BookFormat::Polyglot => Ok(Box::new(PolyglotBook::from_bytes(&bytes)?)),
+    }
+}
+
+/// What a verification search made of a book move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookCheck {
+    /// The book move scores within the margin of the best alternative, or
+    /// is the only legal move
+    Confirmed,
+    /// The book move scores more than the margin below `best`
+    Refuted { best: Move, book_score: i32, best_score: i32 },
+    /// The player reports no scores, e.g. an external engine, so the book
+    /// move could not be checked
+    Unverified,
+}
+
+/// The move an engine playing from its own book makes on `board`: the
+/// book's most heavily weighted legal move, or `None` out of book.
+pub fn book_move(book: &dyn OpeningBook, board: &Board) -> Option<Move> {
+    let legal = board.legal_moves();
+    book.moves(board.position(), board.hash()).into_iter()
+        .filter(|entry| entry.weight > 0)
+        .map(|entry| (Move::from_u64(entry.mov, board.position()), entry.weight))
+        .filter(|(mv, _)| legal.contains(mv))
+        .max_by_key(|&(_, weight)| weight)
+        .map(|(mv, _)| mv)
+}
+
+/// Searches a book move and the position's other moves, and compares their
+/// scores.
+///
+/// # Arguments
+///
+/// * `player` - The engine that would play the move
+/// * `position` - The position the book move is played from
+/// * `book_move` - The packed book move, which must be legal
+/// * `limits` - How long to search each of the two
+/// * `margin` - How many centipawns worse than the best alternative the
+///   book move may score
+///
+/// # Returns
+///
+/// * Whether the book move holds up
+pub fn verify_book_move(player: &mut dyn Player, position: &Position, book_move: u64, limits: &SearchLimits, margin: i32) -> BookCheck {
+    let book_move = Move::from_u64(book_move, position);
+    let only = SearchLimits { search_moves: vec![book_move], ..limits.clone() };
+    let Some(book_analysis) = player.choose_move_analyzed(position, &only).1 else {
+        return BookCheck::Unverified;
+    };
+    let others = SearchLimits { excluded_moves: vec![book_move], ..limits.clone() };
+    match player.choose_move_analyzed(position, &others) {
+        (Some(best), Some(analysis)) if analysis.score - book_analysis.score > margin => BookCheck::Refuted {
+            best,
+            book_score: book_analysis.score,
+            best_score: analysis.score,
+        },
+        (Some(_), None) => BookCheck::Unverified,
+        _ => BookCheck::Confirmed,
+    }
+}
+
+/// Self-learning opening book keyed by position hash, optionally backed by
+/// a file.
+#[derive(Debug, Clone, Default)]
+pub struct LearningBook {
+    entries: HashMap<u64, Vec<BookMove>>,
+    path: Option<PathBuf>,
+}
+
+impl LearningBook {
+    /// Creates an empty book that is not backed by a file.
+    pub fn new() -> Self {
+        LearningBook::default()
+    }
+
+    /// Loads a book from disk. A missing file gives an empty book that will
+    /// be created on the first `save`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Location of the book file
+    ///
+    /// # Returns
+    ///
+    /// * The book, or an error if the file exists but cannot be read or parsed
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut book = LearningBook {
+            entries: HashMap::new(),
+            path: Some(path.to_path_buf()),
+        };
+        if !path.exists() {
+            return Ok(book);
+        }
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read book {}: {}", path.display(), e))?;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (hash, book_move) = parse_line(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+            book.entries.entry(hash).or_default().push(book_move);
+        }
+        Ok(book)
+    }
+
+    /// Writes the book back to the file it was loaded from.
+    ///
+    /// The file is written to a temporary path first and then renamed, so an
+    /// interrupted save never leaves a truncated book behind.
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Err("Book has no file to save to".to_string());
+        };
+
+        let mut hashes: Vec<&u64> = self.entries.keys().collect();
+        hashes.sort();
+        let mut text = String::new();
+        for hash in hashes {
+            for m in &self.entries[hash] {
+                text.push_str(&format!("{:016x} {:x} {} {} {} {}\n",
+                    hash, m.mov, m.weight, m.wins, m.draws, m.losses));
+            }
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("Could not write book {}: {}", path.display(), e))
+    }
+
+    /// Approximate bytes the book takes in memory.
+    pub fn memory_bytes(&self) -> usize {
+        let slots = self.entries.capacity() * (std::mem::size_of::<(u64, Vec<BookMove>)>() + 1);
+        let moves: usize = self.entries.values().map(|moves| moves.capacity() * std::mem::size_of::<BookMove>()).sum();
+        slots + moves
+    }
+
+    /// Returns the stored moves for a position, if any.
+    pub fn moves(&self, hash: u64) -> &[BookMove] {
+        self.entries.get(&hash).map_or(&[], |moves| moves.as_slice())
+    }
+
+    /// Picks the highest weighted move for a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Zobrist hash of the position
+    ///
+    /// # Returns
+    ///
+    /// * The packed move, or `None` if the position is unknown or every
+    ///   stored move has been unlearned
+    pub fn choose_move(&self, hash: u64) -> Option<u64> {
+        self.moves(hash)
+            .iter()
+            .filter(|m| m.weight > 0)
+            .max_by_key(|m| m.weight)
+            .map(|m| m.mov)
+    }
+
+    /// Credits the engine's moves from a finished game with its result.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine_moves` - `(position hash, move)` for each move the engine
+    ///   played, in game order
+    /// * `outcome` - The result from the engine's point of view
+    pub fn learn(&mut self, engine_moves: &[(u64, u64)], outcome: GameOutcome) {
+        // The engine plays every other ply, so this covers the first BOOK_PLIES plies
+        for &(hash, mov) in engine_moves.iter().take(BOOK_PLIES / 2) {
+            let moves = self.entries.entry(hash).or_default();
+            let index = match moves.iter().position(|m| m.mov == mov) {
+                Some(index) => index,
+                None => {
+                    moves.push(BookMove { mov, weight: INITIAL_WEIGHT, wins: 0, draws: 0, losses: 0 });
+                    moves.len() - 1
+                }
+            };
+
+            let entry = &mut moves[index];
+            match outcome {
+                GameOutcome::Win => {
+                    entry.wins += 1;
+                    entry.weight += WIN_BONUS;
+                }
+                GameOutcome::Draw => {
+                    entry.draws += 1;
+                    entry.weight += DRAW_BONUS;
+                }
+                GameOutcome::Loss => {
+                    entry.losses += 1;
+                    entry.weight -= LOSS_PENALTY;
+                }
+            }
+        }
+    }
+}
+
+impl OpeningBook for LearningBook {
+    fn format(&self) -> BookFormat {
+        BookFormat::Learning
+    }
+
+    fn moves(&self, _position: &Position, hash: u64) -> Vec<BookMove> {
+        LearningBook::moves(self, hash).to_vec()
+    }
+
+    fn learn(&mut self, engine_moves: &[(u64, u64)], outcome: GameOutcome) {
+        LearningBook::learn(self, engine_moves, outcome);
+    }
+
+    fn save(&self) -> Result<(), String> {
+        LearningBook::save(self)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        LearningBook::memory_bytes(self)
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u64, BookMove), String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 6 {
+        return Err(format!("Expected 6 fields, found {}", fields.len()));
+    }
+    let hex = |s: &str| u64::from_str_radix(s, 16).map_err(|_| format!("Invalid hex value '{}'", s));
+    let count = |s: &str| s.parse::<u32>().map_err(|_| format!("Invalid count '{}'", s));
+
+    let hash = hex(fields[0])?;
+    let book_move = BookMove {
+        mov: hex(fields[1])?,
+        weight: fields[2].parse().map_err(|_| format!("Invalid weight '{}'", fields[2]))?,
+        wins: count(fields[3])?,
+        draws: count(fields[4])?,
+        losses: count(fields[5])?,
+    };
+    Ok((hash, book_move))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lost_line_is_avoided() {
+        let mut book = LearningBook::new();
+        book.learn(&[(1, 100)], GameOutcome::Win);
+        book.learn(&[(1, 200)], GameOutcome::Draw);
+        assert_eq!(book.choose_move(1), Some(100));
+
+        book.learn(&[(1, 100)], GameOutcome::Loss);
+        book.learn(&[(1, 100)], GameOutcome::Loss);
+        assert_eq!(book.choose_move(1), Some(200));
+
+        book.learn(&[(1, 200)], GameOutcome::Loss);
+        book.learn(&[(1, 200)], GameOutcome::Loss);
+        assert_eq!(book.choose_move(1), Some(100));
+
+        book.learn(&[(1, 100)], GameOutcome::Loss);
+        assert_eq!(book.choose_move(1), None);
+    }
+
+    #[test]
+    fn test_book_move_score() {
+        let mut book = LearningBook::new();
+        book.learn(&[(1, 100)], GameOutcome::Win);
+        book.learn(&[(1, 100)], GameOutcome::Draw);
+        book.learn(&[(1, 100)], GameOutcome::Loss);
+        book.learn(&[(1, 100)], GameOutcome::Win);
+        let entry = &book.moves(1)[0];
+        assert_eq!(entry.games(), 4);
+        assert_eq!(entry.score(), Some(0.625));
+
+        let unplayed = BookMove { mov: 5, weight: 10, wins: 0, draws: 0, losses: 0 };
+        assert_eq!(unplayed.score(), None);
+    }
+
+    #[test]
+    fn test_only_opening_moves_are_learned() {
+        let mut book = LearningBook::new();
+        let game: Vec<(u64, u64)> = (0..40).map(|i| (i, i)).collect();
+        book.learn(&game, GameOutcome::Win);
+        assert_eq!(book.choose_move(0), Some(0));
+        assert_eq!(book.choose_move((BOOK_PLIES / 2) as u64), None);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("rustchess_book_{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut book = LearningBook::load(&path).unwrap();
+        book.learn(&[(0xabcdef, 0x1234), (42, 7)], GameOutcome::Loss);
+        book.save().unwrap();
+
+        let loaded = LearningBook::load(&path).unwrap();
+        assert_eq!(loaded.moves(0xabcdef), book.moves(0xabcdef));
+        assert_eq!(loaded.moves(42)[0].losses, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_book_format_is_detected() {
+        let text = b"# learned\n0000000000abcdef 1234 10 1 0 0\n";
+        assert_eq!(BookFormat::detect(Path::new("book.txt"), text), Ok(BookFormat::Learning));
+        assert_eq!(BookFormat::detect(Path::new("book.txt"), b""), Ok(BookFormat::Learning));
+
+        // One Polyglot entry: the start position's key, e2e4, weight 1
+        let mut entry = 0x463b96181691fc9cu64.to_be_bytes().to_vec();
+        entry.extend([0x03, 0x1c, 0x00, 0x01, 0, 0, 0, 0]);
+        assert_eq!(BookFormat::detect(Path::new("book.bin"), &entry), Ok(BookFormat::Polyglot));
+        assert_eq!(BookFormat::detect(Path::new("openings"), &entry), Ok(BookFormat::Polyglot));
+
+        assert!(BookFormat::detect(Path::new("book.ctg"), &entry).unwrap_err().contains("CTG"));
+        assert!(BookFormat::detect(Path::new("book.bin"), &entry[..10]).unwrap_err().contains("not a valid Polyglot"));
+        assert!(BookFormat::detect(Path::new("games.pgn"), b"[Event \"?\"]\n\n1. e4 *\n").unwrap_err().contains("PGN"));
+        assert!(BookFormat::detect(Path::new("notes.txt"), b"e4 is best\n").unwrap_err().contains("not a book"));
+
+        let path = std::env::temp_dir().join(format!("rustchess_book_{}.bin", std::process::id()));
+        assert!(load_book(&path).is_err());
+        fs::write(&path, &entry).unwrap();
+        let book = load_book(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(book.format(), BookFormat::Polyglot);
+        let start = crate::board::Board::new();
+        let moves = book.moves(start.position(), 0);
+        assert_eq!(moves.iter().map(|m| m.mov).collect::<Vec<_>>(), vec![crate::chess_move::Move::encode(12, 28, None)]);
+
+        let missing = load_book(std::env::temp_dir().join("rustchess_no_such_book.txt")).unwrap();
+        assert_eq!(missing.format(), BookFormat::Learning);
+    }
+
+    #[test]
+    fn test_losing_book_moves_are_refuted() {
+        use crate::board::Board;
+        use crate::player::RandomPlayer;
+        use crate::search::Search;
+
+        // a3 leaves White's queen to be taken; Qxd5 takes Black's
+        let board = Board::from_fen("4k3/8/8/3q4/8/8/P7/3QK3 w - - 0 1").unwrap();
+        let (a3, takes) = (board.parse_move("a2a3").unwrap(), board.parse_move("d1d5").unwrap());
+        let limits = SearchLimits { depth: Some(3), ..SearchLimits::default() };
+        let mut search = Search::new();
+        match verify_book_move(&mut search, board.position(), a3.to_u64(), &limits, DEFAULT_EXIT_MARGIN) {
+            BookCheck::Refuted { best, book_score, best_score } => {
+                assert_eq!(best, takes);
+                assert!(best_score - book_score > 1000);
+            }
+            check => panic!("a3 should be refuted, got {:?}", check),
+        }
+        assert_eq!(verify_book_move(&mut search, board.position(), takes.to_u64(), &limits, DEFAULT_EXIT_MARGIN), BookCheck::Confirmed);
+        // Any move is within a large enough margin
+        assert_eq!(verify_book_move(&mut search, board.position(), a3.to_u64(), &limits, 10_000), BookCheck::Confirmed);
+
+        let mut random = RandomPlayer::new(1);
+        assert_eq!(verify_book_move(&mut random, board.position(), a3.to_u64(), &limits, DEFAULT_EXIT_MARGIN), BookCheck::Unverified);
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_file() {
+        let path = std::env::temp_dir().join(format!("rustchess_bad_book_{}.txt", std::process::id()));
+        fs::write(&path, "zz 1 2 3\n").unwrap();
+        assert!(LearningBook::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
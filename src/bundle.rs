@@ -0,0 +1,173 @@
+//! Debug bundles for bug reports.
+//!
+//! A bundle is one zip file holding what it takes to reproduce a game the
+//! engine misplayed, so a user can attach a single file to an issue:
+//!
+//! - `position.fen` - The current position
+//! - `game.pgn` - Every move from the start position, in long algebraic notation
+//! - `settings.txt` - The tab's engine settings as `key=value` lines
+//! - `search.toml` - The search parameters, including the variety seed
+//! - `search_log.txt` - The most recent searches, oldest first
+//! - `version.txt` - Engine version, platform, build profile and features
+
+use std::collections::VecDeque;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::remote::{AnalysisLine, AnalysisSource};
+
+/// Most searches a log keeps; older ones are dropped.
+pub const MAX_LOG_ENTRIES: usize = 50;
+
+/// The most recent searches, one line each.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLog {
+    entries: VecDeque<String>,
+}
+
+impl SearchLog {
+    pub fn new() -> Self {
+        SearchLog::default()
+    }
+
+    /// Records a finished search, dropping the oldest past `MAX_LOG_ENTRIES`.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What the search was for, e.g. `engine move`
+    /// * `fen` - The position searched
+    /// * `line` - What the search found
+    /// * `nodes` - Nodes searched, when known
+    /// * `elapsed` - Time the search took, when known
+    pub fn record(&mut self, kind: &str, fen: &str, line: &AnalysisLine, nodes: Option<u64>, elapsed: Option<Duration>) {
+        let mut entry = format!("{} | {} | depth {} score {:+}", kind, fen, line.depth, line.score);
+        if let Some(nodes) = nodes {
+            entry.push_str(&format!(" nodes {}", nodes));
+        }
+        if let Some(elapsed) = elapsed {
+            entry.push_str(&format!(" time {} ms", elapsed.as_millis()));
+        }
+        let source = match line.source {
+            AnalysisSource::Remote => "remote",
+            AnalysisSource::Local => "local",
+        };
+        entry.push_str(&format!(" ({}) | pv {}", source, line.pv.join(" ")));
+        if self.entries.len() == MAX_LOG_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.entries.iter().map(|entry| format!("{}\n", entry)).collect()
+    }
+}
+
+/// The contents of a debug bundle, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugBundle {
+    pub fen: String,
+    pub pgn: String,
+    pub settings: String,
+    pub search_params: String,
+    pub search_log: String,
+}
+
+impl DebugBundle {
+    /// The files of a bundle, in the order they are written.
+    pub const FILES: [&'static str; 6] =
+        ["position.fen", "game.pgn", "settings.txt", "search.toml", "search_log.txt", "version.txt"];
+
+    /// The bundle as the bytes of a zip file.
+    pub fn to_zip(&self) -> Result<Vec<u8>, String> {
+        let version = version_info();
+        let contents = [&self.fen, &self.pgn, &self.settings, &self.search_params, &self.search_log, &version];
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, text) in Self::FILES.iter().zip(contents) {
+            zip.start_file(*name, options).map_err(|e| format!("Could not add {}: {}", name, e))?;
+            zip.write_all(text.as_bytes()).map_err(|e| format!("Could not add {}: {}", name, e))?;
+        }
+        zip.finish()
+            .map(Cursor::into_inner)
+            .map_err(|e| format!("Could not finish the bundle: {}", e))
+    }
+
+    /// Writes the bundle as a zip file.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_zip()?)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// Engine version, platform, build profile and enabled features.
+pub fn version_info() -> String {
+    let features: Vec<&str> = [
+        ("std", cfg!(feature = "std")),
+        ("cli", cfg!(feature = "cli")),
+        ("gui", cfg!(feature = "gui")),
+        ("render", cfg!(feature = "render")),
+        ("server", cfg!(feature = "server")),
+        ("remote", cfg!(feature = "remote")),
+    ].into_iter().filter(|&(_, enabled)| enabled).map(|(name, _)| name).collect();
+    format!("{} {}\nplatform: {} {}\nprofile: {}\nfeatures: {}\n",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS, std::env::consts::ARCH,
+        if cfg!(debug_assertions) { "debug" } else { "release" },
+        features.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_bundle_holds_every_file() {
+        let line = AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: "e2e4".to_string(),
+            score: 25,
+            pv: vec!["e2e4".to_string(), "e7e5".to_string()],
+            depth: 9,
+        };
+        let mut log = SearchLog::new();
+        for _ in 0..MAX_LOG_ENTRIES + 5 {
+            log.record("analysis", crate::board::START_FEN, &line, None, None);
+        }
+        log.record("engine move", crate::board::START_FEN, &line, Some(1234), Some(Duration::from_millis(500)));
+        assert_eq!(log.len(), MAX_LOG_ENTRIES);
+        assert!(log.to_text().ends_with(
+            "engine move | rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | depth 9 score +25 nodes 1234 time 500 ms (local) | pv e2e4 e7e5\n"));
+
+        let bundle = DebugBundle {
+            fen: crate::board::START_FEN.to_string(),
+            pgn: "[Result \"*\"]\n\n*\n".to_string(),
+            settings: "hash_mb=32\n".to_string(),
+            search_params: "null_move_reduction = 2\n".to_string(),
+            search_log: log.to_text(),
+        };
+        let mut archive = zip::ZipArchive::new(Cursor::new(bundle.to_zip().unwrap())).unwrap();
+        assert_eq!(archive.file_names().count(), DebugBundle::FILES.len());
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+        assert_eq!(read("position.fen"), bundle.fen);
+        assert_eq!(read("search_log.txt"), bundle.search_log);
+        assert!(read("version.txt").contains(env!("CARGO_PKG_VERSION")));
+    }
+}
@@ -0,0 +1,296 @@
+//! Strength calibration against weak reference opponents.
+//!
+//! The engine plays fixed-time matches against bundled opponents of known,
+//! nominal strength: a random mover and material-only searchers at depths
+//! 1 to 3. The results give a maximum likelihood Elo estimate with a 95%
+//! confidence interval, so strength can be compared across releases.
+//!
+//! The ratings of the reference opponents are rough anchors rather than
+//! measured values; estimates are only comparable with each other.
+
+use std::fmt;
+
+use rand::prelude::*;
+
+use crate::board::Board;
+use crate::player::{GreedyMaterialPlayer, Player, RandomPlayer, SearchLimits};
+use crate::position::Color;
+
+/// Games longer than this are adjudicated as draws.
+const MAX_PLIES: usize = 200;
+/// Bounds of the rating search, far outside anything the anchors can support.
+const MIN_ELO: f64 = -1000.0;
+const MAX_ELO: f64 = 4000.0;
+/// Normal quantile for a 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// A bundled opponent of nominal strength.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceOpponent {
+    /// Plays a uniformly random legal move
+    Random,
+    /// Alpha-beta search to `depth` plies counting material only
+    Material { depth: u32 },
+}
+
+impl ReferenceOpponent {
+    /// The standard calibration set, weakest first.
+    pub const ALL: [ReferenceOpponent; 4] = [
+        ReferenceOpponent::Random,
+        ReferenceOpponent::Material { depth: 1 },
+        ReferenceOpponent::Material { depth: 2 },
+        ReferenceOpponent::Material { depth: 3 },
+    ];
+
+    /// Nominal Elo rating used as the anchor for this opponent.
+    pub fn rating(self) -> f64 {
+        match self {
+            ReferenceOpponent::Random => 200.0,
+            ReferenceOpponent::Material { depth: 1 } => 600.0,
+            ReferenceOpponent::Material { depth: 2 } => 850.0,
+            ReferenceOpponent::Material { .. } => 1050.0,
+        }
+    }
+
+    /// A fresh player of this strength, with random choices fixed by `seed`.
+    pub fn player(self, seed: u64) -> Box<dyn Player + Send> {
+        match self {
+            ReferenceOpponent::Random => Box::new(RandomPlayer::new(seed)),
+            ReferenceOpponent::Material { depth } => Box::new(GreedyMaterialPlayer::new(depth, seed)),
+        }
+    }
+}
+
+impl fmt::Display for ReferenceOpponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceOpponent::Random => write!(f, "random mover"),
+            ReferenceOpponent::Material { depth } => write!(f, "material depth {}", depth),
+        }
+    }
+}
+
+/// Result of a single game from the engine's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchResult {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The engine's record against one opponent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpponentRecord {
+    pub opponent: ReferenceOpponent,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl OpponentRecord {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points scored, one per win and a half per draw.
+    fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+}
+
+/// Outcome of a calibration run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationReport {
+    pub records: Vec<OpponentRecord>,
+    /// Estimated rating
+    pub elo: f64,
+    /// Half-width of the 95% confidence interval
+    pub margin: f64,
+}
+
+impl fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<18} {:>6} {:>4} {:>4} {:>4} {:>6}", "Opponent", "Elo", "W", "D", "L", "Score")?;
+        for record in &self.records {
+            writeln!(f, "{:<18} {:>6.0} {:>4} {:>4} {:>4} {:>5.0}%",
+                record.opponent.to_string(), record.opponent.rating(),
+                record.wins, record.draws, record.losses,
+                100.0 * record.points() / record.games().max(1) as f64)?;
+        }
+        write!(f, "Estimated Elo: {:.0} +/- {:.0} (95%)", self.elo, self.margin)
+    }
+}
+
+/// Plays one game between the engine and an opponent.
+///
+/// # Arguments
+///
+/// * `engine` - The player being calibrated
+/// * `opponent` - The opponent
+/// * `engine_color` - The side the engine plays
+/// * `limits` - Thinking time per move, for both sides
+///
+/// # Returns
+///
+/// * The result from the engine's point of view. Games reaching `MAX_PLIES`
+///   or a position where neither side can mate are drawn.
+pub fn play_game(engine: &mut dyn Player, opponent: &mut dyn Player, engine_color: Color, limits: &SearchLimits) -> MatchResult {
+    play_game_from(&Board::new(), engine, opponent, engine_color, limits)
+}
+
+/// Like `play_game`, but starts from `start` instead of the initial position.
+pub fn play_game_from(start: &Board, engine: &mut dyn Player, opponent: &mut dyn Player, engine_color: Color, limits: &SearchLimits) -> MatchResult {
+    let mut board = start.clone();
+    for _ in 0..MAX_PLIES {
+        if board.legal_moves().is_empty() {
+            return if !board.is_check() {
+                MatchResult::Draw
+            } else if board.side_to_move() == engine_color {
+                MatchResult::Loss
+            } else {
+                MatchResult::Win
+            };
+        }
+        if board.position().is_insufficient_material() {
+            return MatchResult::Draw;
+        }
+
+        let mv = if board.side_to_move() == engine_color {
+            engine.choose_move(board.position(), limits)
+        } else {
+            opponent.choose_move(board.position(), limits)
+        };
+        match mv.map(|mv| board.play(mv)) {
+            Some(Ok(())) => {}
+            // A side that cannot produce a legal move forfeits
+            _ if board.side_to_move() == engine_color => return MatchResult::Loss,
+            _ => return MatchResult::Win,
+        }
+    }
+    MatchResult::Draw
+}
+
+/// Plays `games_per_opponent` games against each opponent, alternating
+/// colors, and estimates the engine's rating.
+///
+/// # Arguments
+///
+/// * `engine` - The player being calibrated, usually the `Engine`
+/// * `opponents` - The opponents to play, e.g. `ReferenceOpponent::ALL`
+/// * `games_per_opponent` - Games against each opponent
+/// * `limits` - Thinking time per move
+/// * `seed` - Seed for the opponents' random choices
+/// * `on_game` - Called after every game with the opponent and result
+pub fn calibrate<F: FnMut(ReferenceOpponent, MatchResult)>(
+    engine: &mut dyn Player,
+    opponents: &[ReferenceOpponent],
+    games_per_opponent: u32,
+    limits: &SearchLimits,
+    seed: u64,
+    mut on_game: F,
+) -> CalibrationReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut records = Vec::new();
+    for &opponent in opponents {
+        let mut record = OpponentRecord { opponent, wins: 0, draws: 0, losses: 0 };
+        let mut player = opponent.player(rng.gen());
+        for game in 0..games_per_opponent {
+            let engine_color = if game % 2 == 0 { Color::White } else { Color::Black };
+            let result = play_game(engine, player.as_mut(), engine_color, limits);
+            match result {
+                MatchResult::Win => record.wins += 1,
+                MatchResult::Draw => record.draws += 1,
+                MatchResult::Loss => record.losses += 1,
+            }
+            on_game(opponent, result);
+        }
+        records.push(record);
+    }
+
+    let (elo, margin) = estimate_elo(&records);
+    CalibrationReport { records, elo, margin }
+}
+
+/// Maximum likelihood rating from results against opponents of known rating,
+/// with the half-width of its 95% confidence interval.
+///
+/// A perfect or zero score has no finite estimate, so the total score is
+/// kept at least half a point away from either extreme.
+pub fn estimate_elo(records: &[OpponentRecord]) -> (f64, f64) {
+    let games: u32 = records.iter().map(OpponentRecord::games).sum();
+    if games == 0 {
+        return (0.0, 0.0);
+    }
+    let n = games as f64;
+    let points: f64 = records.iter().map(OpponentRecord::points).sum();
+    let score = points.clamp(0.5, n - 0.5);
+
+    // Per-game variance of the score, counting draws as half points
+    let mean = score / n;
+    let squares: f64 = records.iter().map(|r| r.wins as f64 + r.draws as f64 / 4.0).sum();
+    let variance = (squares / n - mean * mean).max(0.0);
+    let deviation = Z_95 * (variance * n).sqrt();
+
+    let elo = rating_for_score(records, score);
+    let low = rating_for_score(records, (score - deviation).max(0.5));
+    let high = rating_for_score(records, (score + deviation).min(n - 0.5));
+    (elo, (high - low) / 2.0)
+}
+
+/// The rating whose expected total score against the opponents is `score`.
+fn rating_for_score(records: &[OpponentRecord], score: f64) -> f64 {
+    let expected = |rating: f64| -> f64 {
+        records.iter()
+            .map(|r| r.games() as f64 / (1.0 + 10f64.powf((r.opponent.rating() - rating) / 400.0)))
+            .sum()
+    };
+    // The expected score rises with the rating, so bisect
+    let (mut low, mut high) = (MIN_ELO, MAX_ELO);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if expected(mid) < score {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(opponent: ReferenceOpponent, wins: u32, draws: u32, losses: u32) -> OpponentRecord {
+        OpponentRecord { opponent, wins, draws, losses }
+    }
+
+    #[test]
+    fn test_estimate_elo() {
+        let depth_two = ReferenceOpponent::Material { depth: 2 };
+        let (even, margin) = estimate_elo(&[record(depth_two, 5, 10, 5)]);
+        assert!((even - depth_two.rating()).abs() < 1.0);
+        assert!(margin > 0.0);
+
+        // 75% is about 191 points stronger
+        let (stronger, _) = estimate_elo(&[record(depth_two, 15, 0, 5)]);
+        assert!((stronger - depth_two.rating() - 191.0).abs() < 2.0);
+
+        // More games narrow the interval; a perfect score stays finite
+        let (_, wide) = estimate_elo(&[record(depth_two, 6, 0, 2)]);
+        let (_, narrow) = estimate_elo(&[record(depth_two, 60, 0, 20)]);
+        assert!(narrow < wide);
+        let (perfect, _) = estimate_elo(&[record(ReferenceOpponent::Random, 10, 0, 0)]);
+        assert!(perfect > 500.0 && perfect < MAX_ELO - 1.0);
+    }
+
+    #[test]
+    fn test_calibration_plays_every_game() {
+        let mut engine = crate::engine::Engine::new();
+        let mut played = 0;
+        let report = calibrate(&mut engine, &[ReferenceOpponent::Random], 2, &SearchLimits::movetime_ms(20), 7, |_, _| played += 1);
+        assert_eq!(played, 2);
+        assert_eq!(report.records[0].wins + report.records[0].draws + report.records[0].losses, 2);
+        assert!(report.to_string().contains("Estimated Elo"));
+    }
+}
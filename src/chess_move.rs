@@ -1,12 +1,179 @@
-use crate::position::{Square, PieceType};
+//! Chess move representation.
+//!
+//! Search and move generation work with moves packed into a `u64`
+//! (from square in bits 0-5, to square in bits 6-11, promotion flag in bit 12
+//! and the promotion piece in bits 13-14). `Move::encode` and
+//! `Move::promotion_of` are the one place that layout is built and read.
+//! `Move` is the richer, public form of the same information, carrying the
+//! move's squares together with flags describing what kind of move it is.
 
+use core::fmt;
+use crate::position::{Position, PieceType, Square, index_to_position};
+
+/// Bit marking a packed move as a promotion.
+pub const PROMOTION_FLAG: u64 = 1 << 12;
+/// Shift of the two bits selecting the promotion piece, indexing `PROMOTION_PIECES`.
+const PROMOTION_PIECE_SHIFT: u64 = 13;
+
+/// The pieces a pawn can promote to. Queen comes first, so a bare
+/// `PROMOTION_FLAG` means a queen promotion.
+pub const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+/// A single chess move.
+///
+/// Squares are indexed 0-63 starting from a1, so e2 is 12 and e4 is 28.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Move {
-    from: Square,
-    to: Square,
+    from: usize,
+    to: usize,
     promotion: Option<PieceType>,
     is_capture: bool,
     is_castle: bool,
     is_en_passant: bool,
 }
 
+impl Move {
+    /// Decodes a packed move, using `position` (the position the move is
+    /// played from) to fill in the capture, castling and en passant flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `mov` - The packed move as produced by move generation
+    /// * `position` - The position the move is played from
+    ///
+    /// # Returns
+    ///
+    /// * The decoded `Move`
+    pub fn from_u64(mov: u64, position: &Position) -> Move {
+        let from = (mov & 0x3F) as usize;
+        let to = ((mov >> 6) & 0x3F) as usize;
+
+        let moving = match position.squares[from] {
+            Square::Occupied(idx) => Some(position.pieces[idx]),
+            Square::Empty => None,
+        };
+        let moving_type = moving.map(|p| p.piece_type);
+
+        let is_en_passant = moving_type == Some(PieceType::Pawn)
+            && from % 8 != to % 8
+            && position.en_passant == Some(1u64 << to)
+            && position.squares[to] == Square::Empty;
+        let is_castle = moving_type == Some(PieceType::King)
+            && moving.and_then(|king| position.castling_move(king.color, from, to)).is_some();
+        let promotion = Move::promotion_of(mov);
+
+        Move {
+            from,
+            to,
+            promotion,
+            is_capture: position.is_capture(mov) || is_en_passant,
+            is_castle,
+            is_en_passant,
+        }
+    }
+
+    /// Packs the move back into the `u64` encoding used by search.
+    pub fn to_u64(&self) -> u64 {
+        Move::encode(self.from, self.to, self.promotion)
+    }
+
+    /// Packs squares and an optional promotion piece into the `u64` encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The square the piece moves from (0-63)
+    /// * `to` - The square the piece moves to (0-63)
+    /// * `promotion` - The piece a pawn promotes to; anything other than a
+    ///   queen, rook, bishop or knight is treated as a queen
+    ///
+    /// # Returns
+    ///
+    /// * The packed move
+    pub fn encode(from: usize, to: usize, promotion: Option<PieceType>) -> u64 {
+        let mut mov = from as u64 | ((to as u64) << 6);
+        if let Some(piece_type) = promotion {
+            let code = PROMOTION_PIECES.iter().position(|&p| p == piece_type).unwrap_or(0);
+            mov |= PROMOTION_FLAG | ((code as u64) << PROMOTION_PIECE_SHIFT);
+        }
+        mov
+    }
+
+    /// The promotion piece of a packed move, if it is a promotion.
+    pub fn promotion_of(mov: u64) -> Option<PieceType> {
+        if mov & PROMOTION_FLAG == 0 {
+            return None;
+        }
+        Some(PROMOTION_PIECES[((mov >> PROMOTION_PIECE_SHIFT) & 3) as usize])
+    }
+
+    /// The square the piece moves from (0-63).
+    pub fn from(&self) -> usize {
+        self.from
+    }
+
+    /// The square the piece moves to (0-63).
+    pub fn to(&self) -> usize {
+        self.to
+    }
+
+    /// The piece a pawn promotes to, if this is a promotion.
+    pub fn promotion(&self) -> Option<PieceType> {
+        self.promotion
+    }
+
+    /// Whether the move captures a piece (including en passant).
+    pub fn is_capture(&self) -> bool {
+        self.is_capture
+    }
+
+    /// Whether the move is a castling king move.
+    pub fn is_castle(&self) -> bool {
+        self.is_castle
+    }
+
+    /// Whether the move is an en passant capture.
+    pub fn is_en_passant(&self) -> bool {
+        self.is_en_passant
+    }
+}
+
+/// Formats the move in long algebraic notation, e.g. `e2e4` or `e7e8q`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", index_to_position(self.from), index_to_position(self.to))?;
+        if let Some(piece_type) = self.promotion {
+            let symbol = match piece_type {
+                PieceType::Queen => 'q',
+                PieceType::Rook => 'r',
+                PieceType::Bishop => 'b',
+                PieceType::Knight => 'n',
+                PieceType::Pawn | PieceType::King => '?',
+            };
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_round_trip() {
+        assert_eq!(Move::promotion_of(Move::encode(52, 60, None)), None);
+        for piece_type in PROMOTION_PIECES {
+            let mov = Move::encode(52, 60, Some(piece_type));
+            assert_eq!(mov & 0xFFF, 52 | (60 << 6));
+            assert_eq!(Move::promotion_of(mov), Some(piece_type));
+        }
+        // A bare flag, as older code produced, is a queen promotion
+        assert_eq!(Move::promotion_of(52 | (60 << 6) | PROMOTION_FLAG), Some(PieceType::Queen));
+    }
+}
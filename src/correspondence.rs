@@ -0,0 +1,341 @@
+//! Correspondence games.
+//!
+//! Long-running games are stored one per file in a games directory, as
+//! `<id>.game`, so the engine can think for hours on a move across several
+//! runs of the program. While thinking, the engine's best line is written
+//! back to the file at regular checkpoints; an interrupted think resumes
+//! from the time already spent on the current move.
+//!
+//! The file format is plain text, one `key value` pair per line:
+//!
+//! ```text
+//! fen <starting FEN>
+//! moves <moves in long algebraic notation, space separated>
+//! checkpoint <ply> <milliseconds thought> <score> <principal variation>
+//! ```
+//!
+//! The `checkpoint` line only belongs to the position after `<ply>` moves and
+//! is dropped once a move is played.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, START_FEN};
+use crate::engine::Engine;
+
+/// Extension of correspondence game files.
+const GAME_EXTENSION: &str = "game";
+
+/// The engine's best line so far for the current move of a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of moves played when the line was found
+    pub ply: usize,
+    /// Total time spent thinking about this move, across runs
+    pub thought_ms: u64,
+    /// Score in centipawns from the point of view of the side to move
+    pub score: i32,
+    /// Principal variation in long algebraic notation; never empty
+    pub pv: Vec<String>,
+}
+
+impl Checkpoint {
+    /// The move the engine would play.
+    pub fn best_move(&self) -> &str {
+        &self.pv[0]
+    }
+}
+
+/// A correspondence game and the file it is stored in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrespondenceGame {
+    pub id: String,
+    pub start_fen: String,
+    /// Moves played so far in long algebraic notation
+    pub moves: Vec<String>,
+    /// Best line found for the current move, if the engine has thought about it
+    pub checkpoint: Option<Checkpoint>,
+    path: PathBuf,
+}
+
+impl CorrespondenceGame {
+    /// Starts a new game in `dir` and writes it to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The games directory; created if missing
+    /// * `id` - Name for the game, or `None` to number it after the existing games
+    /// * `fen` - Starting position, or `None` for the standard one
+    ///
+    /// # Returns
+    ///
+    /// * The game, or an error if the ID is taken or invalid, the FEN is
+    ///   invalid or the file cannot be written
+    pub fn create<P: AsRef<Path>>(dir: P, id: Option<&str>, fen: Option<&str>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => {
+                let existing = Self::list(dir)?;
+                (1..).map(|n: u32| n.to_string())
+                    .find(|candidate| !existing.iter().any(|game| &game.id == candidate))
+                    .unwrap()
+            }
+        };
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("Invalid game ID '{}': use letters, digits, '-' and '_'", id));
+        }
+        let path = game_path(dir, &id);
+        if path.exists() {
+            return Err(format!("Game '{}' already exists", id));
+        }
+
+        let start_fen = fen.unwrap_or(START_FEN).trim().to_string();
+        Board::from_fen(&start_fen)?;
+        let game = CorrespondenceGame { id, start_fen, moves: Vec::new(), checkpoint: None, path };
+        game.save()?;
+        Ok(game)
+    }
+
+    /// Loads the game `id` from `dir`.
+    ///
+    /// # Returns
+    ///
+    /// * The game, or an error if it does not exist or cannot be parsed
+    pub fn load<P: AsRef<Path>>(dir: P, id: &str) -> Result<Self, String> {
+        let path = game_path(dir.as_ref(), id);
+        if !path.exists() {
+            return Err(format!("No game '{}' in {}", id, dir.as_ref().display()));
+        }
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+        let mut game = CorrespondenceGame {
+            id: id.to_string(),
+            start_fen: START_FEN.to_string(),
+            moves: Vec::new(),
+            checkpoint: None,
+            path,
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "fen" => game.start_fen = value.trim().to_string(),
+                "moves" => game.moves = value.split_whitespace().map(str::to_string).collect(),
+                "checkpoint" => game.checkpoint = Some(parse_checkpoint(value)
+                    .map_err(|e| format!("{} line {}: {}", game.path.display(), number + 1, e))?),
+                _ => return Err(format!("{} line {}: unknown key '{}'", game.path.display(), number + 1, key)),
+            }
+        }
+
+        // Replaying the moves validates the whole file
+        game.board()?;
+        if game.checkpoint.as_ref().is_some_and(|checkpoint| checkpoint.ply != game.moves.len()) {
+            game.checkpoint = None;
+        }
+        Ok(game)
+    }
+
+    /// Loads every game in `dir`, sorted by ID. A missing directory has no games.
+    pub fn list<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, String> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("Could not read {}: {}", dir.display(), e))?;
+
+        let mut games = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(GAME_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                games.push(Self::load(dir, id)?);
+            }
+        }
+        games.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(games)
+    }
+
+    /// Writes the game back to its file.
+    ///
+    /// The file is written to a temporary path first and then renamed, so a
+    /// checkpoint interrupted mid-write never corrupts the game.
+    pub fn save(&self) -> Result<(), String> {
+        let mut text = format!("fen {}\nmoves {}\n", self.start_fen, self.moves.join(" "));
+        if let Some(checkpoint) = &self.checkpoint {
+            text.push_str(&format!("checkpoint {} {} {} {}\n",
+                checkpoint.ply, checkpoint.thought_ms, checkpoint.score, checkpoint.pv.join(" ")));
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, text)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("Could not write {}: {}", self.path.display(), e))
+    }
+
+    /// The current position of the game.
+    pub fn board(&self) -> Result<Board, String> {
+        let mut board = Board::from_fen(&self.start_fen)?;
+        for text in &self.moves {
+            board.play(board.parse_move(text)?)?;
+        }
+        Ok(board)
+    }
+
+    /// Plays a move, by either side, and saves the game.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The move in long algebraic notation, e.g. `e2e4`
+    ///
+    /// # Returns
+    ///
+    /// * An error if the move is illegal or the game cannot be saved
+    pub fn play(&mut self, text: &str) -> Result<(), String> {
+        let mut board = self.board()?;
+        let mv = board.parse_move(text)?;
+        board.play(mv)?;
+        self.moves.push(mv.to_string());
+        self.checkpoint = None;
+        self.save()
+    }
+
+    /// Lets the engine think about the current move, checkpointing its best
+    /// line to disk as it goes.
+    ///
+    /// Thinking time already recorded in a checkpoint for this move counts
+    /// against `budget`, so an interrupted think resumes where it stopped.
+    /// The transposition table carries over between checkpoints, so each
+    /// one starts from the previous search's results.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to think with
+    /// * `budget` - Total thinking time for this move
+    /// * `interval` - Time between checkpoints
+    /// * `on_checkpoint` - Called after each checkpoint has been saved
+    ///
+    /// # Returns
+    ///
+    /// * The final checkpoint, or an error if the side to move has no legal
+    ///   moves or the game cannot be saved. The move itself is not played.
+    pub fn think<F: FnMut(&Checkpoint)>(
+        &mut self,
+        engine: &mut Engine,
+        budget: Duration,
+        interval: Duration,
+        mut on_checkpoint: F,
+    ) -> Result<Checkpoint, String> {
+        let board = self.board()?;
+        if board.legal_moves().is_empty() {
+            return Err(format!("Game '{}' is over", self.id));
+        }
+        let interval = interval.max(Duration::from_millis(1));
+
+        loop {
+            let thought = Duration::from_millis(self.checkpoint.as_ref().map_or(0, |c| c.thought_ms));
+            if self.checkpoint.is_some() && thought >= budget {
+                break;
+            }
+
+            let slice = interval.min(budget.saturating_sub(thought)).max(Duration::from_millis(1));
+            engine.set_max_time_ms(slice.as_millis() as u64);
+            let started = Instant::now();
+            let analysis = engine.analyze(&board)
+                .ok_or_else(|| format!("Game '{}' is over", self.id))?;
+
+            self.checkpoint = Some(Checkpoint {
+                ply: self.moves.len(),
+                thought_ms: (thought + started.elapsed()).as_millis() as u64,
+                score: analysis.score,
+                pv: analysis.pv.iter().map(|mv| mv.to_string()).collect(),
+            });
+            self.save()?;
+            on_checkpoint(self.checkpoint.as_ref().unwrap());
+        }
+        Ok(self.checkpoint.clone().unwrap())
+    }
+}
+
+fn game_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.{}", id, GAME_EXTENSION))
+}
+
+fn parse_checkpoint(value: &str) -> Result<Checkpoint, String> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err("checkpoint needs a ply, a time, a score and at least one move".to_string());
+    }
+    Ok(Checkpoint {
+        ply: fields[0].parse().map_err(|_| format!("Invalid ply '{}'", fields[0]))?,
+        thought_ms: fields[1].parse().map_err(|_| format!("Invalid time '{}'", fields[1]))?,
+        score: fields[2].parse().map_err(|_| format!("Invalid score '{}'", fields[2]))?,
+        pv: fields[3..].iter().map(|mv| mv.to_string()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn games_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustchess_corr_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_games_persist_and_get_ids() {
+        let dir = games_dir("persist");
+        let mut first = CorrespondenceGame::create(&dir, None, None).unwrap();
+        let second = CorrespondenceGame::create(&dir, None, None).unwrap();
+        assert_eq!((first.id.as_str(), second.id.as_str()), ("1", "2"));
+        assert!(CorrespondenceGame::create(&dir, Some("1"), None).is_err());
+        assert!(CorrespondenceGame::create(&dir, Some("../x"), None).is_err());
+
+        first.play("e2e4").unwrap();
+        assert!(first.play("e2e4").is_err());
+        let loaded = CorrespondenceGame::load(&dir, "1").unwrap();
+        assert_eq!(loaded, first);
+        assert_eq!(CorrespondenceGame::list(&dir).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_think_checkpoints_and_resumes() {
+        let dir = games_dir("think");
+        let mut game = CorrespondenceGame::create(&dir, Some("fools"), None).unwrap();
+        for mv in ["f2f3", "e7e5", "g2g4"] {
+            game.play(mv).unwrap();
+        }
+
+        let mut engine = Engine::new();
+        let mut checkpoints = 0;
+        let line = game.think(&mut engine, Duration::from_millis(300), Duration::from_millis(100), |_| checkpoints += 1).unwrap();
+        assert!(checkpoints >= 2);
+        assert_eq!(line.best_move(), "d8h4");
+        assert!(line.thought_ms >= 300);
+
+        // A later run finds the budget already spent and thinks no more
+        let mut resumed = CorrespondenceGame::load(&dir, "fools").unwrap();
+        assert_eq!(resumed.checkpoint.as_ref(), Some(&line));
+        let again = resumed.think(&mut engine, Duration::from_millis(300), Duration::from_millis(100), |_| panic!("thought again")).unwrap();
+        assert_eq!(again, line);
+
+        resumed.play(line.best_move()).unwrap();
+        assert_eq!(CorrespondenceGame::load(&dir, "fools").unwrap().checkpoint, None);
+        assert!(resumed.think(&mut engine, Duration::from_millis(100), Duration::from_millis(100), |_| {}).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+//! Reading positions from text diagrams.
+//!
+//! Bug reports, books and forum posts show positions as eight lines of
+//! squares rather than as FEN. `diagram_to_fen` reads such a diagram back:
+//! the board `ui::display_board` prints, plain eight-letter ranks
+//! (`rnbqkbnr`), grids drawn with `|` and `+---+`, and Unicode chess
+//! symbols. Empty squares may be written `.`, `-`, `_`, `*`, `#` or as a
+//! blank grid cell.
+//!
+//! A diagram shows only the pieces, so the rest of the FEN is guessed:
+//! White is to move unless the text says "Black to move" (or "to play"),
+//! castling is allowed wherever king and rook stand on their starting
+//! squares, and there is no en passant square. Rank and file labels are
+//! optional; when they show the board from Black's side it is turned round.
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+use crate::board::Board;
+
+/// Characters marking an empty square.
+const EMPTY_SQUARES: [char; 7] = ['.', '-', '_', '*', '#', '·', '0'];
+
+/// Reads a text diagram into a FEN string.
+///
+/// # Arguments
+///
+/// * `text` - The diagram, possibly with labels, borders and a line saying
+///   who is to move around it
+///
+/// # Returns
+///
+/// * The FEN, or a message saying why the text is not a diagram of a legal
+///   position
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::diagram::diagram_to_fen;
+///
+/// let diagram = "
+///     . . . . k . . .
+///     . . . . . . . .
+///     . . . . . . . .
+///     . . . . . . . .
+///     . . . . . . . .
+///     . . . . . . . .
+///     . . . . . . . .
+///     R . . . K . . .
+///     Black to move";
+/// assert_eq!(diagram_to_fen(diagram).unwrap(), "4k3/8/8/8/8/8/8/R3K3 b Q - 0 1");
+/// ```
+pub fn diagram_to_fen(text: &str) -> Result<String, String> {
+    let mut ranks: Vec<Vec<Option<char>>> = Vec::new();
+    let mut first_label = None;
+    let mut flipped_files = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        let (label, squares) = read_rank(trimmed);
+        if let Some(squares) = squares {
+            if first_label.is_none() {
+                first_label = label;
+            }
+            ranks.push(squares);
+        } else if is_file_labels(trimmed) {
+            flipped_files = trimmed.trim_start().starts_with('h');
+        }
+    }
+    if ranks.len() != 8 {
+        return Err(format!("A diagram needs 8 ranks of 8 squares; found {}", ranks.len()));
+    }
+
+    // Labels from 1 at the top, or files from h on the left, mean the
+    // board is seen from Black's side
+    if first_label == Some(1) || (first_label.is_none() && flipped_files) {
+        ranks.reverse();
+        for rank in &mut ranks {
+            rank.reverse();
+        }
+    }
+
+    let mut fen = String::new();
+    for (index, rank) in ranks.iter().enumerate() {
+        let mut empty = 0;
+        for square in rank {
+            match square {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(*piece);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if index < 7 {
+            fen.push('/');
+        }
+    }
+
+    let lower = text.to_lowercase();
+    let black = ["black to move", "black to play", "black moves", "black plays"].iter().any(|phrase| lower.contains(phrase));
+    let castling = castling_rights(&ranks);
+    fen = format!("{} {} {} - 0 1", fen, if black { 'b' } else { 'w' }, castling);
+    Board::from_fen(&fen)?;
+    Ok(fen)
+}
+
+/// Reads a text diagram into a board, see `diagram_to_fen`.
+pub fn parse_diagram(text: &str) -> Result<Board, String> {
+    Board::from_fen(&diagram_to_fen(text)?)
+}
+
+/// Reads one line of a diagram as a rank.
+///
+/// # Returns
+///
+/// * The rank label the line starts or ends with, if any, and its eight
+///   squares as FEN piece letters, or `None` if it is not a rank
+fn read_rank(line: &str) -> (Option<u8>, Option<Vec<Option<char>>>) {
+    let mut body = line.trim();
+    let mut label = None;
+    if let Some(digit) = body.chars().next().and_then(|c| c.to_digit(10)) {
+        let rest = &body[1..];
+        if (1..=8).contains(&digit) && rest.starts_with(|c: char| c.is_whitespace() || c == '|') {
+            label = Some(digit as u8);
+            body = rest.trim();
+        }
+    }
+    if let Some(digit) = body.chars().last().and_then(|c| c.to_digit(10)) {
+        let rest = &body[..body.len() - 1];
+        if (1..=8).contains(&digit) && rest.ends_with(|c: char| c.is_whitespace() || c == '|') {
+            label = label.or(Some(digit as u8));
+            body = rest.trim();
+        }
+    }
+    let body = body.strip_prefix(['|', '[']).unwrap_or(body);
+    let body = body.strip_suffix(['|', ']']).unwrap_or(body);
+
+    // A grid gives every square a cell between bars, blank if empty
+    let squares: Option<Vec<Option<char>>> = if body.matches('|').count() == 7 {
+        body.split('|').map(|cell| match cell.trim() {
+            "" => Some(None),
+            cell => {
+                let mut chars = cell.chars();
+                let square = read_square(chars.next()?)?;
+                chars.next().is_none().then_some(square)
+            }
+        }).collect()
+    } else {
+        body.chars().filter(|c| !c.is_whitespace()).map(read_square).collect()
+    };
+    (label, squares.filter(|squares| squares.len() == 8))
+}
+
+/// The FEN letter of the piece on a diagram square, `Some(None)` for an
+/// empty square, or `None` if the character is neither.
+fn read_square(c: char) -> Option<Option<char>> {
+    if EMPTY_SQUARES.contains(&c) {
+        return Some(None);
+    }
+    let piece = match c {
+        'K' | 'Q' | 'R' | 'B' | 'N' | 'P' | 'k' | 'q' | 'r' | 'b' | 'n' | 'p' => c,
+        '♔' => 'K',
+        '♕' => 'Q',
+        '♖' => 'R',
+        '♗' => 'B',
+        '♘' => 'N',
+        '♙' => 'P',
+        '♚' => 'k',
+        '♛' => 'q',
+        '♜' => 'r',
+        '♝' => 'b',
+        '♞' => 'n',
+        '♟' => 'p',
+        _ => return None,
+    };
+    Some(Some(piece))
+}
+
+/// Whether a line is the file labels `a` to `h`, in either order.
+fn is_file_labels(line: &str) -> bool {
+    let files: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    files == "abcdefgh" || files == "hgfedcba"
+}
+
+/// Castling rights for kings and rooks on their starting squares, with
+/// rank 8 first in `ranks`.
+fn castling_rights(ranks: &[Vec<Option<char>>]) -> String {
+    let at = |rank: usize, file: usize| ranks[7 - rank][file];
+    let mut rights = String::new();
+    for (rank, king, rook, kingside) in [(0, 'K', 'R', 'K'), (0, 'K', 'R', 'Q'), (7, 'k', 'r', 'k'), (7, 'k', 'r', 'q')] {
+        let rook_file = if kingside.eq_ignore_ascii_case(&'K') { 7 } else { 0 };
+        if at(rank, 4) == Some(king) && at(rank, rook_file) == Some(rook) {
+            rights.push(kingside);
+        }
+    }
+    if rights.is_empty() { "-".to_string() } else { rights }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+
+    #[test]
+    fn test_reads_the_console_board() {
+        let printed = "
+  +-----------------+
+8 | r n b q k b n r |
+7 | p p p p p p p p |
+6 | . . . . . . . . |
+5 | . . . . . . . . |
+4 | . . . . . . . . |
+3 | . . . . . . . . |
+2 | P P P P P P P P |
+1 | R N B Q K B N R |
+  +-----------------+
+    a b c d e f g h
+";
+        assert_eq!(diagram_to_fen(printed).unwrap(), START_FEN);
+    }
+
+    #[test]
+    fn test_reads_other_diagram_styles() {
+        let grid = "
++---+---+---+---+---+---+---+---+
+|   |   |   |   | k |   |   |   |
++---+---+---+---+---+---+---+---+
+|   | P |   |   |   |   |   |   |
++---+---+---+---+---+---+---+---+
+|   |   |   |   |   |   |   |   |
+|   |   |   | p |   |   |   |   |
+|   |   | N |   | P |   |   |   |
+|   |   |   |   |   |   |   |   |
+|   |   |   |   | K |   |   |   |
+| R |   |   |   |   |   |   | R |
++---+---+---+---+---+---+---+---+
+White to play";
+        assert_eq!(diagram_to_fen(grid).unwrap(), "4k3/1P6/8/3p4/2N1P3/8/4K3/R6R w - - 0 1");
+
+        let unicode = "♜ - - - ♚ - - ♜\n- - - - - - - -\n- - - - - - - -\n- - - - - - - -\n\
+            - - - - - - - -\n- - - - - - - -\n- - - - - - - -\n♖ - - - ♔ - - -\nBlack to move";
+        assert_eq!(diagram_to_fen(unicode).unwrap(), "r3k2r/8/8/8/8/8/8/R3K3 b Qkq - 0 1");
+
+        // Seen from Black's side, as the labels show
+        let flipped = "1 . . . K . . . R\n2 . . . . . . . .\n3 . . . . . . . .\n4 . . . . . . . .\n\
+            5 . . . . . . . .\n6 . . . . . . . .\n7 . . . . . . . .\n8 . . . k . . . .\n  h g f e d c b a";
+        assert_eq!(diagram_to_fen(flipped).unwrap(), "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+    }
+
+    #[test]
+    fn test_rejects_what_is_not_a_position() {
+        assert!(diagram_to_fen("1. e4 e5 2. Nf3").is_err());
+        let seven = "rnbqkbnr\npppppppp\n........\n........\n........\nPPPPPPPP\nRNBQKBNR";
+        assert!(diagram_to_fen(seven).unwrap_err().contains("found 7"));
+        // Eight ranks, but no black king
+        let kingless = "rnbq.bnr\npppppppp\n........\n........\n........\n........\nPPPPPPPP\nRNBQKBNR";
+        assert!(diagram_to_fen(kingless).is_err());
+        assert!(parse_diagram("rnbqkbnr\npppppppp\n........\n........\n........\n........\nPPPPPPPP\nRNBQKBNR").is_ok());
+    }
+}
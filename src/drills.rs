@@ -0,0 +1,349 @@
+//! Mate-in-N training drills.
+//!
+//! Positions from played games, or from a small bundled set, are searched
+//! for forced mates. A position with one becomes a `MateDrill`: the player
+//! plays the attacking side, every move is checked against the proof tree
+//! the search built, and the defender answers with the reply that holds out
+//! longest. `DrillStats` counts solved drills and streaks of drills solved
+//! without a wrong move.
+//!
+//! The search is exhaustive, so drills are limited to `MAX_MATE_MOVES`.
+
+use crate::position::{Color, Position};
+use crate::Game;
+
+/// Longest mate searched for, in moves of the attacking side.
+pub const MAX_MATE_MOVES: u32 = 3;
+
+/// Drill positions that come with the engine, each with a forced mate in at
+/// most `MAX_MATE_MOVES`.
+pub const BUNDLED_DRILLS: [&str; 6] = [
+    // Back rank mates in one, for either side
+    "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+    "3r2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1",
+    // Smothered mate in one
+    "6rk/6pp/8/6N1/8/8/6PP/6K1 w - - 0 1",
+    // King and queen, and king and rook, against king: mates in two
+    "k7/8/1K6/8/8/8/8/1Q6 w - - 0 1",
+    "k7/8/2K5/8/8/8/8/7R w - - 0 1",
+    // King and rook against king: mate in three
+    "2k5/8/8/3K4/8/8/8/7R w - - 0 1",
+];
+
+/// Attacking moves that mate in time from one position, each with the
+/// defence it leaves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct AttackNode {
+    mates: Vec<(u64, DefenceNode)>,
+}
+
+/// Every defending reply after an attacking move, each with the attacking
+/// moves that still mate in time; no replies means checkmate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DefenceNode {
+    replies: Vec<(u64, AttackNode)>,
+}
+
+impl AttackNode {
+    /// Moves needed to mate from here against the best defence.
+    fn distance(&self) -> u32 {
+        self.mates.iter().map(|(_, defence)| 1 + defence.distance()).min().unwrap_or(u32::MAX)
+    }
+}
+
+impl DefenceNode {
+    fn distance(&self) -> u32 {
+        self.replies.iter().map(|(_, attack)| attack.distance()).max().unwrap_or(0)
+    }
+}
+
+/// The attacking moves that mate within `moves` moves from `position`.
+fn attack(position: &Position, game: &Game, moves: u32) -> AttackNode {
+    let mut node = AttackNode::default();
+    if moves == 0 {
+        return node;
+    }
+    for mov in position.get_all_legal_moves(game) {
+        let mut after = position.clone();
+        after.make_move(mov);
+        after.update_all_legal_moves(game);
+        if let Some(defence) = defend(&after, game, moves - 1) {
+            node.mates.push((mov, defence));
+        }
+    }
+    node
+}
+
+/// The proof that every reply in `position` still loses within `moves`
+/// more attacking moves, or `None` if one escapes or it is stalemate.
+fn defend(position: &Position, game: &Game, moves: u32) -> Option<DefenceNode> {
+    let replies = position.get_all_legal_moves(game);
+    if replies.is_empty() {
+        return position.is_in_check(game).then(DefenceNode::default);
+    }
+    let mut node = DefenceNode::default();
+    for reply in replies {
+        let mut after = position.clone();
+        after.make_move(reply);
+        after.update_all_legal_moves(game);
+        let attack = attack(&after, game, moves);
+        if attack.mates.is_empty() {
+            return None;
+        }
+        node.replies.push((reply, attack));
+    }
+    Some(node)
+}
+
+/// What happened to a move played in a drill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrillStep {
+    /// The move does not force mate in the moves left; it is not played
+    Wrong,
+    /// The move is good and the defender answers with this move
+    Reply(u64),
+    /// The move mates
+    Solved,
+}
+
+/// A "Mate in N" challenge and the player's progress through it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MateDrill {
+    /// The starting position
+    pub fen: String,
+    /// Moves the mate takes against the best defence
+    pub moves: u32,
+    /// The attacking side
+    pub attacker: Color,
+    /// The proof tree from the position reached
+    node: AttackNode,
+    /// Whether the player has tried a wrong move
+    missed: bool,
+    solved: bool,
+}
+
+impl MateDrill {
+    /// Searches a position for the shortest forced mate by the side to
+    /// move.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to search
+    /// * `game` - The game providing the attack tables
+    /// * `max_moves` - Longest mate to look for, at most `MAX_MATE_MOVES`
+    ///
+    /// # Returns
+    ///
+    /// * The drill, or `None` if there is no mate within `max_moves`
+    pub fn find(position: &Position, game: &Game, max_moves: u32) -> Option<Self> {
+        let mut position = position.clone();
+        position.update_all_legal_moves(game);
+        (1..=max_moves.min(MAX_MATE_MOVES)).find_map(|moves| {
+            let node = attack(&position, game, moves);
+            (!node.mates.is_empty()).then(|| MateDrill {
+                fen: position.to_fen(),
+                moves,
+                attacker: position.active_color,
+                node,
+                missed: false,
+                solved: false,
+            })
+        })
+    }
+
+    /// Plays the player's move and, if the mate goes on, the defender's
+    /// reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `mov` - The player's move, which must be legal
+    ///
+    /// # Returns
+    ///
+    /// * What came of the move; a wrong move leaves the drill where it was
+    pub fn play(&mut self, mov: u64) -> DrillStep {
+        let Some(index) = self.node.mates.iter().position(|(mate, _)| *mate == mov) else {
+            self.missed = true;
+            return DrillStep::Wrong;
+        };
+        let (_, defence) = self.node.mates.swap_remove(index);
+        // The defender holds out as long as it can
+        match defence.replies.into_iter().max_by_key(|(_, attack)| attack.distance()) {
+            Some((reply, attack)) => {
+                self.node = attack;
+                DrillStep::Reply(reply)
+            }
+            None => {
+                self.node = AttackNode::default();
+                self.solved = true;
+                DrillStep::Solved
+            }
+        }
+    }
+
+    /// A move that keeps the mate on schedule, for when the player is
+    /// stuck. Asking for one counts as a miss.
+    pub fn hint(&mut self) -> Option<u64> {
+        let best = self.node.mates.iter().min_by_key(|(_, defence)| defence.distance())?.0;
+        self.missed = true;
+        Some(best)
+    }
+
+    /// Moves left to mate against the best defence.
+    pub fn moves_left(&self) -> u32 {
+        if self.solved { 0 } else { self.node.distance() }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.solved
+    }
+
+    /// Whether the player has made a wrong move or asked for a hint.
+    pub fn missed(&self) -> bool {
+        self.missed
+    }
+}
+
+/// Searches every position of a game for mates by the side to move.
+///
+/// # Arguments
+///
+/// * `start_fen` - The position the game started from
+/// * `moves` - The moves played
+/// * `max_moves` - Longest mate to look for
+///
+/// # Returns
+///
+/// * A drill for every position with a mate, in game order
+pub fn drills_from_game(start_fen: &str, moves: &[u64], max_moves: u32) -> Vec<MateDrill> {
+    let game = Game::new();
+    let Ok(mut position) = Position::from_fen(start_fen, &game) else {
+        return Vec::new();
+    };
+    position.update_all_legal_moves(&game);
+    let mut drills = Vec::new();
+    for &mov in moves {
+        drills.extend(MateDrill::find(&position, &game, max_moves));
+        position.make_move(mov);
+        position.update_all_legal_moves(&game);
+    }
+    drills.extend(MateDrill::find(&position, &game, max_moves));
+    drills
+}
+
+/// The drills of `BUNDLED_DRILLS`.
+pub fn bundled_drills() -> Vec<MateDrill> {
+    let game = Game::new();
+    BUNDLED_DRILLS.iter()
+        .filter_map(|fen| Position::from_fen(fen, &game).ok())
+        .filter_map(|position| MateDrill::find(&position, &game, MAX_MATE_MOVES))
+        .collect()
+}
+
+/// Drill results, remembered between runs as `key=value` lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrillStats {
+    /// Drills solved without a wrong move or a hint
+    pub solved: u32,
+    /// Drills solved only after one
+    pub missed: u32,
+    /// Drills solved in a row without a wrong move or a hint
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+impl DrillStats {
+    /// Records a finished drill.
+    ///
+    /// # Arguments
+    ///
+    /// * `clean` - Whether it was solved without a wrong move or a hint
+    pub fn record(&mut self, clean: bool) {
+        if clean {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.missed += 1;
+            self.streak = 0;
+        }
+    }
+
+    /// Reads stored stats; unknown keys and unreadable values are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut stats = DrillStats::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let field = match key.trim() {
+                "solved" => &mut stats.solved,
+                "missed" => &mut stats.missed,
+                "streak" => &mut stats.streak,
+                "best_streak" => &mut stats.best_streak,
+                _ => continue,
+            };
+            *field = value.trim().parse().unwrap_or(*field);
+        }
+        stats
+    }
+
+    pub fn to_text(self) -> String {
+        format!("solved={}\nmissed={}\nstreak={}\nbest_streak={}\n", self.solved, self.missed, self.streak, self.best_streak)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_move::Move;
+
+    #[test]
+    fn test_mate_drill_follows_the_proof() {
+        let game = Game::new();
+        let position = Position::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", &game).unwrap();
+        let mut drill = MateDrill::find(&position, &game, MAX_MATE_MOVES).unwrap();
+        assert_eq!((drill.moves, drill.attacker, drill.moves_left()), (2, Color::White, 2));
+        assert!(MateDrill::find(&position, &game, 1).is_none());
+
+        // Rh2 lets the king out through a7; Kb6 closes it in
+        assert_eq!(drill.play(Move::encode(7, 15, None)), DrillStep::Wrong);
+        assert!(drill.missed());
+        let mut clean = drill.clone();
+        clean.missed = false;
+        assert!(matches!(clean.play(Move::encode(42, 41, None)), DrillStep::Reply(_)));
+        assert_eq!(clean.moves_left(), 1);
+        let mate = clean.hint().unwrap();
+        assert_eq!(clean.play(mate), DrillStep::Solved);
+        assert!(clean.is_solved() && clean.missed());
+        assert_eq!(clean.moves_left(), 0);
+
+        let mut after_hint = drill.clone();
+        while !after_hint.is_solved() {
+            let mov = after_hint.hint().unwrap();
+            assert_ne!(after_hint.play(mov), DrillStep::Wrong);
+        }
+    }
+
+    #[test]
+    fn test_drills_from_games_and_bundled_set() {
+        // f3 e5 g4, and Black mates with Qh4
+        let moves = [Move::encode(13, 21, None), Move::encode(52, 36, None), Move::encode(14, 30, None)];
+        let drills = drills_from_game(crate::board::START_FEN, &moves, 1);
+        assert_eq!(drills.len(), 1);
+        assert_eq!(drills[0].attacker, Color::Black);
+        assert!(drills[0].fen.starts_with("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b "), "{}", drills[0].fen);
+
+        let bundled: Vec<u32> = bundled_drills().iter().map(|drill| drill.moves).collect();
+        assert_eq!(bundled, vec![1, 1, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn test_drill_stats_track_streaks() {
+        let mut stats = DrillStats::default();
+        for clean in [true, true, false, true] {
+            stats.record(clean);
+        }
+        assert_eq!(stats, DrillStats { solved: 3, missed: 1, streak: 1, best_streak: 2 });
+        assert_eq!(DrillStats::parse(&stats.to_text()), stats);
+        assert_eq!(DrillStats::parse("streak=lots\ncolour=blue"), DrillStats::default());
+    }
+}
@@ -1,13 +1,290 @@
+//! Engine front end.
+//!
+//! `Engine` wraps `Search` behind the public `Board`/`Move` types so callers
+//! can ask for a move without dealing with the packed move encoding.
+
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::AtomicBool;
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::options::EngineOptions;
+use crate::player::{Player, SearchLimits};
+use crate::position::Position;
+use crate::search::{InfoThrottle, IterationInfo, Search};
+use crate::search_params::SearchParams;
+use crate::time_manager::Clock;
+
+/// What the engine found in a position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub best_move: Move,
+    /// Score in centipawns from the point of view of the side to move
+    pub score: i32,
+    /// Principal variation, starting with `best_move`
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    /// Depth of the last completed iteration, in plies
+    pub depth: i32,
+    /// The search reached its node ceiling, `SearchParams::max_nodes`, and
+    /// this is the best line found until then
+    pub truncated: bool,
+}
+
+/// A chess engine that picks moves for a [`Board`].
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::{Board, Engine};
+///
+/// // White mates with Qh5xf7
+/// let board = Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1").unwrap();
+/// let mut engine = Engine::new();
+/// engine.set_max_time(1);
+/// let mv = engine.best_move(&board).unwrap();
+/// assert!(board.legal_moves().contains(&mv));
+/// ```
+#[derive(Clone)]
 pub struct Engine {
-    game: Game,
     search: Search,
-    evaluation: Evaluation,
-    transposition_table: TranspositionTable,
-    move_gen_tables: MoveGenTables,
 }
 
 impl Engine {
-    pub fn search_position(&mut self, time_ms: u64) -> Move {
-        // Coordinate search within time constraints
+    /// Creates an engine with the default five second search budget.
+    pub fn new() -> Engine {
+        Engine { search: Search::new() }
+    }
+
+    /// Creates an engine with the search parameters and hash file the
+    /// environment names, see `Search::from_config`.
+    ///
+    /// # Returns
+    ///
+    /// * The engine, and an error for each file that could not be read
+    pub fn from_config() -> (Engine, Vec<String>) {
+        let (search, errors) = Search::from_config();
+        (Engine { search }, errors)
+    }
+
+    /// Pruning, reduction and extension settings of the search.
+    pub fn params(&self) -> &SearchParams {
+        self.search.params()
+    }
+
+    /// Limits how long `best_move` may search, in seconds.
+    pub fn set_max_time(&mut self, seconds: u64) {
+        self.search.set_max_time(seconds);
+    }
+
+    /// Limits how long `best_move` may search, in milliseconds.
+    pub fn set_max_time_ms(&mut self, millis: u64) {
+        self.search.set_max_time_ms(millis);
+    }
+
+    /// Limits how deep `best_move` may search, in plies, see
+    /// `Search::set_max_depth`.
+    pub fn set_max_depth(&mut self, depth: i32) {
+        self.search.set_max_depth(depth);
+    }
+
+    /// Depth limit of `best_move`, in plies.
+    pub fn max_depth(&self) -> i32 {
+        self.search.max_depth()
+    }
+
+    /// Limits how many nodes `best_move` may visit, replacing
+    /// `SearchParams::max_nodes`.
+    pub fn set_max_nodes(&mut self, nodes: u64) {
+        self.search.set_params(SearchParams { max_nodes: nodes.max(1), ..*self.search.params() });
+    }
+
+    /// Makes the next `best_move` manage its time from the side to move's
+    /// clock, see `Search::set_clock`.
+    pub fn set_clock(&mut self, clock: &Clock, board: &Board) {
+        self.search.set_clock(clock, board.position());
+    }
+
+    /// Restricts the root moves of the following searches, as UCI
+    /// `go searchmoves` does, see `Search::set_root_moves`.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_moves` - The only moves to consider; empty allows all
+    /// * `excluded_moves` - Moves never to choose
+    pub fn set_root_moves(&mut self, search_moves: &[Move], excluded_moves: &[Move]) {
+        let to_u64 = |moves: &[Move]| -> Vec<u64> { moves.iter().map(|mv| mv.to_u64()).collect() };
+        self.search.set_root_moves(&to_u64(search_moves), &to_u64(excluded_moves));
+    }
+
+    /// Streams the progress of later searches to `sender`, see
+    /// `Search::set_info_sender`; `None` stops it.
+    pub fn set_info_sender(&mut self, sender: Option<mpsc::Sender<IterationInfo>>) {
+        self.search.set_info_sender(sender);
+    }
+
+    /// Sets how often unfinished iterations are reported, see `InfoThrottle`.
+    pub fn set_info_throttle(&mut self, throttle: InfoThrottle) {
+        self.search.set_info_throttle(throttle);
+    }
+
+    /// A copy of the engine with an empty hash table of `size_mb`
+    /// megabytes, see `Search::with_hash_size`.
+    pub fn with_hash_size(&self, size_mb: usize) -> Engine {
+        Engine { search: self.search.with_hash_size(size_mb) }
+    }
+
+    /// Size of the hash table in megabytes, see `Search::hash_size`.
+    pub fn hash_size(&self) -> usize {
+        self.search.hash_size()
+    }
+
+    /// Number of nodes visited by the last `best_move` call.
+    pub fn nodes_searched(&self) -> u64 {
+        self.search.nodes_searched()
+    }
+
+    /// Number of those nodes that were visited by the quiescence search.
+    pub fn quiescence_nodes(&self) -> u64 {
+        self.search.quiescence_nodes()
+    }
+
+    /// Whether the last `best_move` call stopped at the node ceiling, see
+    /// `Search::truncated`.
+    pub fn truncated(&self) -> bool {
+        self.search.truncated()
+    }
+
+    /// Whether the last `best_move` call overran the search watchdog, see
+    /// `Search::abandoned`.
+    pub fn abandoned(&self) -> bool {
+        self.search.abandoned()
+    }
+
+    /// Score of the last `best_move` call in centipawns, from the point of
+    /// view of the side that was to move.
+    pub fn last_score(&self) -> i32 {
+        self.search.best_score()
+    }
+
+    /// Flag that makes a running `best_move` return early when set.
+    ///
+    /// The engine never clears it, so reset it before the next search.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.search.stop_flag()
+    }
+
+    /// Flag that makes the following searches ponder while set, see
+    /// `Search::ponder_flag`. Clear it on a ponder hit; set the stop flag
+    /// when the opponent played another move.
+    pub fn ponder_flag(&self) -> Arc<AtomicBool> {
+        self.search.ponder_flag()
+    }
+
+    /// The opponent's reply to `best` that the last search expects, to
+    /// ponder on, if its principal variation goes that far.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The board the last search was made on
+    /// * `best` - The move it found
+    pub fn ponder_move(&self, board: &Board, best: Move) -> Option<Move> {
+        let mut after = board.clone();
+        after.play(best).ok()?;
+        let reply = self.search.ponder_move(board.position(), best.to_u64())?;
+        Some(Move::from_u64(reply, after.position()))
+    }
+
+    /// Searches the board's position and returns the best move found.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The board to search; it is not modified
+    ///
+    /// # Returns
+    ///
+    /// * The best move, or `None` if the side to move has no legal moves
+    pub fn best_move(&mut self, board: &Board) -> Option<Move> {
+        let mut position = board.position().clone();
+        let mov = self.search.find_best_move(&mut position)?;
+        Some(Move::from_u64(mov, board.position()))
+    }
+
+    /// Searches the board's position and reports the score and principal
+    /// variation along with the best move.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The board to search; it is not modified
+    ///
+    /// # Returns
+    ///
+    /// * The analysis, or `None` if the side to move has no legal moves
+    pub fn analyze(&mut self, board: &Board) -> Option<Analysis> {
+        let mut position = board.position().clone();
+        let best = self.search.find_best_move(&mut position)?;
+
+        let mut pv = Vec::new();
+        let mut line = board.clone();
+        for mov in self.search.principal_variation(board.position(), best) {
+            let mv = Move::from_u64(mov, line.position());
+            if line.play(mv).is_err() {
+                break;
+            }
+            pv.push(mv);
+        }
+
+        Some(Analysis {
+            best_move: Move::from_u64(best, board.position()),
+            score: self.search.best_score(),
+            pv,
+            nodes: self.search.nodes_searched(),
+            depth: self.search.completed_depth(),
+            truncated: self.search.truncated(),
+        })
+    }
+}
+
+impl Player for Engine {
+    fn choose_move(&mut self, position: &Position, limits: &SearchLimits) -> Option<Move> {
+        self.search.choose_move(position, limits)
+    }
+
+    fn choose_move_analyzed(&mut self, position: &Position, limits: &SearchLimits) -> (Option<Move>, Option<Analysis>) {
+        self.search.choose_move_analyzed(position, limits)
+    }
+
+    fn name(&self) -> String {
+        self.search.name()
+    }
+
+    fn ponder_flag(&self) -> Option<Arc<AtomicBool>> {
+        Some(Engine::ponder_flag(self))
+    }
+
+    fn reload_config(&mut self) -> Result<String, String> {
+        Player::reload_config(&mut self.search)
+    }
+
+    fn save_hash(&self) -> Result<(), String> {
+        Player::save_hash(&self.search)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.search.memory_bytes()
+    }
+
+    fn set_hash_size(&mut self, size_mb: usize) -> Result<(), String> {
+        Player::set_hash_size(&mut self.search, size_mb)
+    }
+
+    fn set_options(&mut self, options: &EngineOptions) {
+        self.search.set_options(options);
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
     }
 }
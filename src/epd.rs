@@ -0,0 +1,223 @@
+//! EPD test positions.
+//!
+//! An EPD record is the first four fields of a FEN followed by operations,
+//! each an opcode and its operands ended by a semicolon:
+//!
+//! ```text
+//! 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id "back rank";
+//! ```
+//!
+//! The opcodes read here are `bm` (the best moves), `am` (moves to avoid),
+//! `id` (the record's name), `ce` (the evaluation in centipawns), and the
+//! clocks `hmvc` and `fmvn`; every operation is also kept as text. Moves are
+//! in SAN, or in long algebraic notation. `run` searches each position and
+//! checks the move found against `bm` and `am`, as test suites are scored.
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::notation;
+use crate::player::{Player, SearchLimits};
+use crate::search::Search;
+
+/// One EPD record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpdRecord {
+    /// The position as a full FEN, with the clocks from `hmvc` and `fmvn`
+    pub fen: String,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub id: Option<String>,
+    /// Evaluation in centipawns from the side to move's point of view
+    pub centipawns: Option<i32>,
+    /// Every operation as (opcode, operands), in order
+    pub operations: Vec<(String, String)>,
+}
+
+impl EpdRecord {
+    /// Parses one record.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The record, e.g. `<placement> w KQkq - bm e4; id "start";`
+    ///
+    /// # Returns
+    ///
+    /// * The record, or why the position or an operation is malformed
+    pub fn parse(line: &str) -> Result<EpdRecord, String> {
+        let mut rest = line.trim();
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return Err(format!("EPD needs four position fields: '{}'", line));
+            }
+            fields.push(field);
+            rest = tail.trim_start();
+        }
+
+        let operations = split_operations(rest)?;
+        let operand = |opcode: &str| operations.iter().find(|(op, _)| op == opcode).map(|(_, operand)| operand.as_str());
+        let clock = |opcode: &str, default: u32| match operand(opcode) {
+            Some(value) => value.parse::<u32>().map_err(|_| format!("{} expects a number, got '{}'", opcode, value)),
+            None => Ok(default),
+        };
+        let fen = format!("{} {} {}", fields.join(" "), clock("hmvc", 0)?, clock("fmvn", 1)?);
+        let board = Board::from_fen(&fen)?;
+
+        let moves = |opcode: &str| -> Result<Vec<Move>, String> {
+            operand(opcode).map_or(Ok(Vec::new()), |operand| {
+                operand.split_whitespace()
+                    .map(|text| notation::parse_san(&board, text).map_err(|e| format!("{} {}: {}", opcode, text, e)))
+                    .collect()
+            })
+        };
+        let centipawns = match operand("ce") {
+            Some(value) => Some(value.parse::<i32>().map_err(|_| format!("ce expects centipawns, got '{}'", value))?),
+            None => None,
+        };
+        Ok(EpdRecord {
+            fen,
+            best_moves: moves("bm")?,
+            avoid_moves: moves("am")?,
+            id: operand("id").map(str::to_string),
+            centipawns,
+            operations,
+        })
+    }
+
+    /// Whether `mv` passes the record: it is one of the best moves, or,
+    /// without any, not one of the moves to avoid.
+    pub fn accepts(&self, mv: Move) -> bool {
+        if self.best_moves.is_empty() {
+            !self.avoid_moves.contains(&mv)
+        } else {
+            self.best_moves.contains(&mv)
+        }
+    }
+}
+
+/// Splits operations at semicolons outside quotes into (opcode, operands),
+/// with the quotes taken off string operands.
+fn split_operations(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut operations = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                operations.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("Unterminated string in '{}'", text));
+    }
+    if !current.trim().is_empty() {
+        return Err(format!("Operation '{}' is not ended by a semicolon", current.trim()));
+    }
+    Ok(operations.into_iter()
+        .filter(|operation| !operation.is_empty())
+        .map(|operation| match operation.split_once(char::is_whitespace) {
+            Some((opcode, operands)) => (opcode.to_string(), operands.trim().to_string()),
+            None => (operation, String::new()),
+        })
+        .collect())
+}
+
+/// Parses a file of records, one per line; blank lines and lines starting
+/// with `#` are skipped.
+///
+/// # Returns
+///
+/// * The records, or the first error with its line number
+pub fn parse_epd(text: &str) -> Result<Vec<EpdRecord>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| EpdRecord::parse(line).map_err(|e| format!("Line {}: {}", index + 1, e)))
+        .collect()
+}
+
+/// How the search did on one record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpdResult {
+    /// The record's `id`, or its FEN without one
+    pub name: String,
+    /// The move the search chose, `None` if it found none
+    pub found: Option<Move>,
+    pub solved: bool,
+}
+
+/// Searches every record's position and checks the move found.
+///
+/// # Arguments
+///
+/// * `records` - The test positions
+/// * `search` - The search to test
+/// * `limits` - How long to search each position
+///
+/// # Returns
+///
+/// * One result per record, in order
+pub fn run(records: &[EpdRecord], search: &mut Search, limits: &SearchLimits) -> Vec<EpdResult> {
+    records.iter()
+        .map(|record| {
+            // Records were checked when parsed
+            let board = Board::from_fen(&record.fen).unwrap_or_default();
+            let found = search.choose_move(board.position(), limits);
+            EpdResult {
+                name: record.id.clone().unwrap_or_else(|| record.fen.clone()),
+                found,
+                solved: found.is_some_and(|mv| record.accepts(mv)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        let record = EpdRecord::parse(
+            r#"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5 Bc4; am a3; id "Ruy; or Italian"; ce 35; hmvc 2; fmvn 3; c0 "quiet";"#).unwrap();
+        let board = Board::from_fen(&record.fen).unwrap();
+        assert_eq!(record.fen, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        assert_eq!(record.best_moves, vec![board.parse_move("f1b5").unwrap(), board.parse_move("f1c4").unwrap()]);
+        assert_eq!(record.avoid_moves, vec![board.parse_move("a2a3").unwrap()]);
+        assert_eq!(record.id.as_deref(), Some("Ruy; or Italian"));
+        assert_eq!(record.centipawns, Some(35));
+        assert_eq!(record.operations.last(), Some(&("c0".to_string(), "quiet".to_string())));
+        assert!(record.accepts(board.parse_move("f1c4").unwrap()));
+        assert!(!record.accepts(board.parse_move("d2d4").unwrap()));
+
+        // Without clocks the position starts them over; long algebraic moves are read too
+        let record = EpdRecord::parse("4k3/8/8/8/8/8/8/4K2R w K - am h1h8;").unwrap();
+        assert_eq!(record.fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert!(!record.accepts(record.avoid_moves[0]));
+
+        assert!(EpdRecord::parse("8/8/8/8 w").is_err());
+        assert!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K2R w K - bm Qh5;").unwrap_err().contains("bm Qh5"));
+        assert!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K2R w K - bm Rh8").is_err());
+        assert!(EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K2R w K - id "open;"#).is_err());
+        assert!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K2R w K - ce high;").is_err());
+        assert_eq!(parse_epd("# suite\n\n4k3/8/8/8/8/8/8/4K2R w K -\nbad").unwrap_err().split(':').next(), Some("Line 4"));
+    }
+
+    #[test]
+    fn test_run_checks_the_best_move() {
+        let records = parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"back rank\";\n\
+            6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Kf1;\n\
+            6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - am Ra8#;").unwrap();
+        let mut search = Search::new();
+        let results = run(&records, &mut search, &SearchLimits { depth: Some(2), ..SearchLimits::default() });
+        assert_eq!(results.iter().map(|result| result.solved).collect::<Vec<_>>(), vec![true, false, false]);
+        assert_eq!(results[0].name, "back rank");
+        assert_eq!(results[1].name, "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(results[0].found.map(|mv| mv.to_string()), Some("a1a8".to_string()));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::board_consts::*;
 use crate::position::{Position, Color, PieceType};
 use crate::utils::bit_scan;
 
@@ -82,23 +83,63 @@ const CENTRAL_PAWN_BONUS: i32 = 20;  // Bonus for controlling central squares (e
 const DOUBLED_PAWN_PENALTY: i32 = -20;  // Penalty for doubled pawns
 const ISOLATED_PAWN_PENALTY: i32 = -10;  // Penalty for isolated pawns
 
-// Central squares for pawn evaluation
-const CENTRAL_SQUARES: u64 = 0x0000001818000000;  // e4,d4,e5,d5
-
 // Additional positional bonuses
-const SPACE_BONUS: i32 = 10;  // Bonus for each pawn advanced beyond rank 3/4
+const SPACE_WEIGHT: i32 = 1;  // Per safe space square, per own minor piece
 const CENTER_CONTROL_BONUS: i32 = 15;  // Bonus for controlling e4/d4 vs e5/d5
 const DEVELOPMENT_BONUS: i32 = 10;  // Bonus for each piece that can develop
 
+// Space is counted on the extended center, c3-f6 seen from White; squares
+// behind one's own pawns count twice
+const WHITE_SPACE_ZONE: u64 = EXTENDED_CENTER;
+
 // Center squares (e4,d4 for White, e5,d5 for Black)
-const WHITE_CENTER: u64 = 0x0000001818000000;  // e4,d4
-const BLACK_CENTER: u64 = 0x0000000000181800;  // e5,d5
+const WHITE_CENTER: u64 = CENTER & RANK_4;
+const BLACK_CENTER: u64 = CENTER & RANK_5;
+
+// Mop-up bonuses once one side is down to a bare king and the other has
+// enough material to mate: drive the lone king to the edge and bring the
+// attacking king closer, rather than just collecting more material
+const MOP_UP_MIN_MATERIAL: i32 = ROOK_VALUE;
+const MOP_UP_EDGE_WEIGHT: i32 = 10;  // Per step the lone king is from the center
+const MOP_UP_KING_PROXIMITY_WEIGHT: i32 = 4;  // Per step the kings are closer
+
+// King safety: the pawns in front of a king on either wing, counted
+// while the enemy queen is on, and pawn storms against the enemy king once
+// the kings are on opposite wings, counted while one's own queen is on.
+// Indexed by the rank of the pawn, seen from its own side
+const KINGSIDE_FILES: u64 = FILE_F | FILE_G | FILE_H;
+const QUEENSIDE_FILES: u64 = FILE_A | FILE_B | FILE_C;
+const SHIELD_PUSH_PENALTY: [i32; 8] = [0, 0, -10, -25, -35, -35, -35, 0];  // Rearmost shield pawn of a file
+const HALF_OPEN_KING_FILE_PENALTY: i32 = -30;  // No own pawn in front of the king on a file
+const OPEN_KING_FILE_PENALTY: i32 = -15;  // ...and no enemy pawn either
+const STORM_BONUS: [i32; 8] = [0, 0, 5, 15, 25, 35, 35, 0];  // Foremost pawn of a file on the enemy king's wing
+
+// Material imbalance: corrections to the fixed piece values for the
+// combination of pieces each side has
+const KNIGHT_PAWN_ADJUSTMENT: i32 = 6;  // Per knight, per own pawn above five: knights like closed positions
+const ROOK_PAWN_ADJUSTMENT: i32 = -12;  // Per rook, per own pawn above five: rooks like open files
+const IMBALANCE_PAWN_BASE: i32 = 5;
+const ROOK_PAIR_PENALTY: i32 = -20;  // Two rooks overlap in what they do
+const QUEEN_KNIGHT_BONUS: i32 = 15;  // Queen and knight cooperate better than queen and bishop
+const MINORS_VS_ROOK_BONUS: i32 = 40;  // Two minor pieces outplay a rook in the middlegame
+
+/// The pieces of one side, counted for the imbalance term.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PieceCounts {
+    pawns: i32,
+    knights: i32,
+    bishops: i32,
+    rooks: i32,
+    queens: i32,
+}
 
+/// Static evaluation of one position, in centipawns from White's side.
 pub struct Evaluation {
     position: Position,
 }
 
 impl Evaluation {
+    /// Wraps `position` for evaluation; see `evaluate_position`.
     pub fn new(position: Position) -> Self {
         Evaluation { position }
     }
@@ -109,8 +150,78 @@ impl Evaluation {
         let material_score = self.evaluate_material();
         let positional_score = self.evaluate_piece_positions();
         
+        let imbalance_score = self.evaluate_imbalance();
+        let mop_up_score = self.evaluate_mop_up(material_score);
+
         // Always return score from White's perspective
-        material_score + positional_score
+        material_score + positional_score + imbalance_score + mop_up_score
+    }
+
+    fn piece_counts(&self, color: Color) -> PieceCounts {
+        let mut counts = PieceCounts::default();
+        for piece in self.position.pieces.iter().filter(|p| p.color == color) {
+            match piece.piece_type {
+                PieceType::Pawn => counts.pawns += 1,
+                PieceType::Knight => counts.knights += 1,
+                PieceType::Bishop => counts.bishops += 1,
+                PieceType::Rook => counts.rooks += 1,
+                PieceType::Queen => counts.queens += 1,
+                PieceType::King => {}
+            }
+        }
+        counts
+    }
+
+    /// Evaluates piece-combination synergies that the fixed piece values miss
+    fn evaluate_imbalance(&self) -> i32 {
+        let white = self.piece_counts(Color::White);
+        let black = self.piece_counts(Color::Black);
+        Self::imbalance(&white, &black) - Self::imbalance(&black, &white)
+    }
+
+    /// The imbalance bonus of the side with `own` pieces against `other`
+    fn imbalance(own: &PieceCounts, other: &PieceCounts) -> i32 {
+        let extra_pawns = own.pawns - IMBALANCE_PAWN_BASE;
+        let mut score = own.knights * extra_pawns * KNIGHT_PAWN_ADJUSTMENT
+            + own.rooks * extra_pawns * ROOK_PAWN_ADJUSTMENT;
+
+        if own.rooks >= 2 {
+            score += ROOK_PAIR_PENALTY;
+        }
+        if own.queens > 0 && own.knights > 0 {
+            score += QUEEN_KNIGHT_BONUS;
+        }
+        // Two minors for a rook, with the queens still on
+        let extra_minors = (own.knights + own.bishops) - (other.knights + other.bishops);
+        if extra_minors >= 2 && other.rooks > own.rooks && own.queens + other.queens > 0 {
+            score += MINORS_VS_ROOK_BONUS;
+        }
+        score
+    }
+
+    /// Rewards progress in won endings against a bare king
+    fn evaluate_mop_up(&self, material_score: i32) -> i32 {
+        let bare = |color: Color| self.position.pieces.iter().all(|p| p.color != color || p.piece_type == PieceType::King);
+        let king_square = |color: Color| self.position.king_square(color);
+
+        let strong = if material_score >= MOP_UP_MIN_MATERIAL && bare(Color::Black) {
+            Color::White
+        } else if material_score <= -MOP_UP_MIN_MATERIAL && bare(Color::White) {
+            Color::Black
+        } else {
+            return 0;
+        };
+        let weak = if strong == Color::White { Color::Black } else { Color::White };
+        let (Some(strong_king), Some(weak_king)) = (king_square(strong), king_square(weak)) else {
+            return 0;
+        };
+
+        let (file, rank) = ((weak_king % 8) as i32, (weak_king / 8) as i32);
+        let center_distance = (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+        let king_distance = ((strong_king % 8) as i32 - file).abs() + ((strong_king / 8) as i32 - rank).abs();
+        let bonus = center_distance * MOP_UP_EDGE_WEIGHT + (14 - king_distance) * MOP_UP_KING_PROXIMITY_WEIGHT;
+
+        if strong == Color::White { bonus } else { -bonus }
     }
 
     /// Evaluates material balance of the position
@@ -118,11 +229,6 @@ impl Evaluation {
         let mut score = 0;
         
         for piece in &self.position.pieces {
-            // Skip captured pieces
-            if piece.position == 0 {
-                continue;
-            }
-
             let piece_value = match piece.piece_type {
                 PieceType::Pawn => PAWN_VALUE,
                 PieceType::Knight => KNIGHT_VALUE,
@@ -151,11 +257,6 @@ impl Evaluation {
         let mut black_pawns = 0u64;
         
         for piece in &self.position.pieces {
-            // Skip captured pieces
-            if piece.position == 0 {
-                continue;
-            }
-
             if piece.piece_type == PieceType::Pawn {
                 if piece.color == Color::White {
                     white_pawns |= piece.position;
@@ -193,20 +294,49 @@ impl Evaluation {
         
         // Evaluate space and center control
         score += self.evaluate_space_and_center(white_pawns, black_pawns);
+
+        // Evaluate the pawns around the kings
+        score += self.evaluate_king_pawns(white_pawns, black_pawns);
         
         score
     }
 
+    /// Evaluates pawn shields and pawn storms around the castled kings
+    fn evaluate_king_pawns(&self, white_pawns: u64, black_pawns: u64) -> i32 {
+        let (Some(white_king), Some(black_king)) = (self.position.king_square(Color::White), self.position.king_square(Color::Black)) else {
+            return 0;
+        };
+        let white_queen = self.piece_counts(Color::White).queens > 0;
+        let black_queen = self.piece_counts(Color::Black).queens > 0;
+
+        let mut score = 0;
+        if black_queen {
+            score += shield_penalty(white_king, white_pawns, black_pawns);
+        }
+        if white_queen {
+            score += storm_bonus(white_king, black_king, white_pawns);
+        }
+        // Black's terms are White's on the mirrored board
+        let (white_king, black_king) = (white_king ^ 56, black_king ^ 56);
+        let (white_pawns, black_pawns) = (white_pawns.swap_bytes(), black_pawns.swap_bytes());
+        if white_queen {
+            score -= shield_penalty(black_king, black_pawns, white_pawns);
+        }
+        if black_queen {
+            score -= storm_bonus(black_king, white_king, black_pawns);
+        }
+        score
+    }
+
     fn evaluate_pawn_structure(&self, white_pawns: u64, black_pawns: u64) -> i32 {
         let mut score = 0;
 
         // Central pawn control
-        score += (white_pawns & CENTRAL_SQUARES).count_ones() as i32 * CENTRAL_PAWN_BONUS;
-        score -= (black_pawns & CENTRAL_SQUARES).count_ones() as i32 * CENTRAL_PAWN_BONUS;
+        score += (white_pawns & CENTER).count_ones() as i32 * CENTRAL_PAWN_BONUS;
+        score -= (black_pawns & CENTER).count_ones() as i32 * CENTRAL_PAWN_BONUS;
 
         // Evaluate doubled pawns (multiple pawns on same file)
-        for file in 0..8 {
-            let file_mask = 0x0101010101010101u64 << file;
+        for file_mask in FILES {
             let white_pawns_in_file = (white_pawns & file_mask).count_ones();
             let black_pawns_in_file = (black_pawns & file_mask).count_ones();
             
@@ -219,15 +349,8 @@ impl Evaluation {
         }
 
         // Evaluate isolated pawns (no friendly pawns on adjacent files)
-        for file in 0..8 {
-            let file_mask = 0x0101010101010101u64 << file;
-            let adjacent_files_mask = if file == 0 {
-                0x0202020202020202u64 // Only right file
-            } else if file == 7 {
-                0x4040404040404040u64 // Only left file
-            } else {
-                (0x0101010101010101u64 << (file - 1)) | (0x0101010101010101u64 << (file + 1))
-            };
+        for (file, &file_mask) in FILES.iter().enumerate() {
+            let adjacent_files_mask = adjacent_files(file);
 
             // Check white pawns
             if (white_pawns & file_mask) != 0 && (white_pawns & adjacent_files_mask) == 0 {
@@ -245,11 +368,14 @@ impl Evaluation {
     fn evaluate_space_and_center(&self, white_pawns: u64, black_pawns: u64) -> i32 {
         let mut score = 0;
 
-        // Space advantage - count pawns beyond rank 3 for White, rank 6 for Black
-        let white_advanced = white_pawns & 0x00FFFFFF000000;  // Ranks 4-6
-        let black_advanced = black_pawns & 0x000000FFFFFF00;  // Ranks 3-5
-        score += (white_advanced.count_ones() as i32) * SPACE_BONUS;
-        score -= (black_advanced.count_ones() as i32) * SPACE_BONUS;
+        // Space advantage, worth more with minor pieces to use it
+        let white_minors = self.piece_counts(Color::White);
+        let black_minors = self.piece_counts(Color::Black);
+        score += space_squares(white_pawns, black_pawns)
+            * (white_minors.knights + white_minors.bishops) * SPACE_WEIGHT;
+        // Black's space is White's on the mirrored board
+        score -= space_squares(black_pawns.swap_bytes(), white_pawns.swap_bytes())
+            * (black_minors.knights + black_minors.bishops) * SPACE_WEIGHT;
 
         // Center control
         let white_center_control = white_pawns & WHITE_CENTER;
@@ -258,10 +384,10 @@ impl Evaluation {
         score -= (black_center_control.count_ones() as i32) * CENTER_CONTROL_BONUS;
 
         // Development potential - check if center pawns have moved
-        if (white_pawns & 0x0000000000001000) == 0 {  // e2 pawn moved
+        if (white_pawns & FILE_E & RANK_2) == 0 {  // e2 pawn moved
             score += DEVELOPMENT_BONUS;  // Light squared bishop can develop
         }
-        if (black_pawns & 0x0010000000000000) == 0 {  // e7 pawn moved
+        if (black_pawns & FILE_E & RANK_7) == 0 {  // e7 pawn moved
             score -= DEVELOPMENT_BONUS;  // Light squared bishop can develop
         }
 
@@ -269,11 +395,110 @@ impl Evaluation {
     }
 }
 
+/// Counts White's space: squares of the space zone that are neither
+/// occupied by White pawns nor attacked by Black pawns, counting the ones
+/// behind White's pawn chain twice.
+///
+/// # Arguments
+///
+/// * `own_pawns` - White's pawns
+/// * `enemy_pawns` - Black's pawns
+///
+/// # Returns
+///
+/// * The weighted number of safe squares
+fn space_squares(own_pawns: u64, enemy_pawns: u64) -> i32 {
+    let enemy_attacks = ((enemy_pawns >> 7) & !FILE_A) | ((enemy_pawns >> 9) & !FILE_H);
+    let safe = WHITE_SPACE_ZONE & !own_pawns & !enemy_attacks;
+
+    // Fill every pawn's file down to the first rank
+    let mut behind = own_pawns >> 8;
+    behind |= behind >> 8;
+    behind |= behind >> 16;
+    behind |= behind >> 32;
+
+    (safe.count_ones() + (safe & behind).count_ones()) as i32
+}
+
+/// The three files of the wing a king stands on, or `None` for a king on
+/// the d or e file.
+fn king_wing(square: usize) -> Option<u64> {
+    match square % 8 {
+        0..=2 => Some(QUEENSIDE_FILES),
+        5..=7 => Some(KINGSIDE_FILES),
+        _ => None,
+    }
+}
+
+/// Penalises White for pushed or missing pawns in front of its king, once
+/// the king has gone to a wing on its first two ranks.
+///
+/// # Arguments
+///
+/// * `king` - White's king square
+/// * `own_pawns` - White's pawns
+/// * `enemy_pawns` - Black's pawns
+///
+/// # Returns
+///
+/// * The penalty, zero or negative
+fn shield_penalty(king: usize, own_pawns: u64, enemy_pawns: u64) -> i32 {
+    let Some(wing) = king_wing(king).filter(|_| king < 16) else {
+        return 0;
+    };
+    let mut score = 0;
+    for file in FILES.iter().map(|&file| file & wing).filter(|&file| file != 0) {
+        let shield = own_pawns & file;
+        if shield == 0 {
+            score += HALF_OPEN_KING_FILE_PENALTY;
+            if enemy_pawns & file == 0 {
+                score += OPEN_KING_FILE_PENALTY;
+            }
+        } else {
+            score += SHIELD_PUSH_PENALTY[bit_scan(shield) / 8];
+        }
+    }
+    score
+}
+
+/// Rewards White for pawns advanced against Black's king when the kings
+/// are on opposite wings.
+///
+/// # Arguments
+///
+/// * `own_king` - White's king square
+/// * `enemy_king` - Black's king square
+/// * `own_pawns` - White's pawns
+///
+/// # Returns
+///
+/// * The bonus, zero or positive
+fn storm_bonus(own_king: usize, enemy_king: usize, own_pawns: u64) -> i32 {
+    let (Some(own_wing), Some(enemy_wing)) = (king_wing(own_king), king_wing(enemy_king)) else {
+        return 0;
+    };
+    if own_wing == enemy_wing {
+        return 0;
+    }
+    FILES.iter()
+        .map(|&file| own_pawns & file & enemy_wing)
+        .filter(|&storm| storm != 0)
+        .map(|storm| STORM_BONUS[(63 - storm.leading_zeros() as usize) / 8])
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::Game;
 
+    /// The squares of `color`'s pawns in the evaluated position.
+    fn pawns(evaluation: &Evaluation, color: Color) -> u64 {
+        evaluation.position.pieces.iter()
+            .filter(|p| p.color == color && p.piece_type == PieceType::Pawn)
+            .fold(0u64, |pawns, p| pawns | p.position)
+    }
+
     #[test]
     fn test_initial_position_evaluation() {
         let game = Game::new();
@@ -299,6 +524,106 @@ mod tests {
         assert!(evaluation.evaluate_position() <= KNIGHT_VALUE + 50);
     }
 
+    #[test]
+    fn test_mop_up_prefers_lone_king_on_edge() {
+        let game = Game::new();
+        let centralized = Evaluation::new(Position::read_FEN("8/8/8/3k4/8/8/8/KQ6 w - - 0 1", &game));
+        let cornered = Evaluation::new(Position::read_FEN("k7/8/8/8/8/8/8/KQ6 w - - 0 1", &game));
+        assert!(cornered.evaluate_position() > centralized.evaluate_position());
+
+        // Same for Black, and bringing the attacking king closer also helps
+        let far = Evaluation::new(Position::read_FEN("kq6/8/8/8/8/8/8/7K w - - 0 1", &game));
+        let near = Evaluation::new(Position::read_FEN("1q6/8/8/8/8/8/5k2/7K w - - 0 1", &game));
+        assert!(near.evaluate_mop_up(-QUEEN_VALUE) < far.evaluate_mop_up(-QUEEN_VALUE));
+        assert!(far.evaluate_mop_up(-QUEEN_VALUE) < 0);
+
+        // No bonus without enough material to mate
+        let pawn_up = Evaluation::new(Position::read_FEN("k7/8/8/8/8/8/P7/K7 w - - 0 1", &game));
+        assert_eq!(pawn_up.evaluate_mop_up(PAWN_VALUE), 0);
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let game = Game::new();
+        let imbalance = |fen: &str| Evaluation::new(Position::read_FEN(fen, &game)).evaluate_imbalance();
+
+        // Symmetric material has no imbalance
+        assert_eq!(imbalance("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 0);
+
+        // Knights gain and rooks lose value with many pawns on the board
+        let knight_closed = imbalance("4k3/pppppppp/8/8/8/8/PPPPPPPP/1N2K3 w - - 0 1");
+        let knight_open = imbalance("4k3/ppp5/8/8/8/8/PPP5/1N2K3 w - - 0 1");
+        assert!(knight_closed > knight_open);
+        let rook_closed = imbalance("4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 w - - 0 1");
+        let rook_open = imbalance("4k3/ppp5/8/8/8/8/PPP5/R3K3 w - - 0 1");
+        assert!(rook_closed < rook_open);
+
+        // Two minors against a rook favour the minors while queens are on
+        let minors = imbalance("r2qk3/pppppppp/8/8/8/8/PPPPPPPP/1NBQK3 w - - 0 1");
+        let minors_without_queens = imbalance("r3k3/pppppppp/8/8/8/8/PPPPPPPP/1NB1K3 w - - 0 1");
+        assert!(minors > minors_without_queens);
+
+        // The rook pair is penalised, queen and knight rewarded
+        assert_eq!(Evaluation::imbalance(&PieceCounts { rooks: 2, pawns: 5, ..Default::default() }, &PieceCounts::default()), ROOK_PAIR_PENALTY);
+        assert_eq!(Evaluation::imbalance(&PieceCounts { queens: 1, knights: 1, pawns: 5, ..Default::default() }, &PieceCounts::default()), QUEEN_KNIGHT_BONUS);
+    }
+
+    #[test]
+    fn test_space() {
+        let game = Game::new();
+        let space = |fen: &str| {
+            let evaluation = Evaluation::new(Position::read_FEN(fen, &game));
+            evaluation.evaluate_space_and_center(pawns(&evaluation, Color::White), pawns(&evaluation, Color::Black))
+        };
+
+        assert_eq!(space("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 0);
+        // The advance French: White's chain on d4 and e5 cramps Black
+        assert!(space("rnbqkbnr/pp3ppp/4p3/2ppP3/3P4/8/PPP2PPP/RNBQKBNR w KQkq - 0 1") > 0);
+        // Without minor pieces space is worth nothing
+        let cramped = "r2qk2r/pp3ppp/4p3/2ppP3/3P4/8/PPP2PPP/R2QK2R w KQkq - 0 1";
+        let with_minors = "rn1qk2r/pp3ppp/4p3/2ppP3/3P4/8/PPP2PPP/RN1QK2R w KQkq - 0 1";
+        assert!(space(with_minors) > space(cramped));
+
+        // Squares attacked by enemy pawns are not safe; squares behind pawns count twice
+        assert_eq!(space_squares(RANK_2, RANK_7), 12);
+        assert_eq!(space_squares(RANK_2, RANK_7 | (FILE_D & RANK_5)), 10);
+        // A pawn on e5 takes its own square but doubles e4 and e3
+        assert_eq!(space_squares((RANK_2 & !FILE_E) | (FILE_E & RANK_5), RANK_7), 13);
+    }
+
+    #[test]
+    fn test_king_pawns() {
+        let game = Game::new();
+        let king_pawns = |fen: &str| {
+            let evaluation = Evaluation::new(Position::read_FEN(fen, &game));
+            evaluation.evaluate_king_pawns(pawns(&evaluation, Color::White), pawns(&evaluation, Color::Black))
+        };
+
+        // Castled on opposite wings, White storms Black's king with the g- and h-pawns
+        let storm = king_pawns("r2q1rk1/ppp2ppp/8/6PP/8/8/PPPQ1P2/2KR3R w - - 0 1");
+        assert!(storm > 0);
+        assert_eq!(king_pawns("2kr3r/pppq1p2/8/8/6pp/8/PPP2PPP/R2Q1RK1 b - - 0 1"), -storm);
+        // Without queens neither shields nor storms count
+        assert_eq!(king_pawns("r4rk1/ppp2ppp/8/6PP/8/8/PPP2P2/2KR3R w - - 0 1"), 0);
+
+        // Pushing pawns in front of one's own king costs, a half-open file
+        // more, an open file more still
+        let wing = FILE_G | FILE_H;
+        assert_eq!(shield_penalty(6, RANK_2, RANK_7), 0);
+        assert_eq!(shield_penalty(6, (RANK_2 & !wing) | (RANK_3 & wing), RANK_7), 2 * SHIELD_PUSH_PENALTY[2]);
+        assert_eq!(shield_penalty(6, RANK_2 & !FILE_G, RANK_7), HALF_OPEN_KING_FILE_PENALTY);
+        assert_eq!(shield_penalty(6, RANK_2 & !FILE_G, RANK_7 & !FILE_G), HALF_OPEN_KING_FILE_PENALTY + OPEN_KING_FILE_PENALTY);
+        // Only for a king on a wing and on its first two ranks
+        assert_eq!(shield_penalty(4, 0, 0), 0);
+        assert_eq!(shield_penalty(22, 0, 0), 0);
+
+        // Storms count only against a king on the other wing
+        let storming = (RANK_2 & !wing) | (RANK_5 & wing);
+        assert_eq!(storm_bonus(2, 62, storming), 2 * STORM_BONUS[4]);
+        assert_eq!(storm_bonus(6, 62, storming), 0);
+        assert_eq!(storm_bonus(2, 60, storming), 0);
+    }
+
     #[test]
     fn test_pawn_structure_evaluation() {
         let game = Game::new();
@@ -0,0 +1,220 @@
+//! External engines.
+//!
+//! `UciEngine` runs another chess engine as a child process and talks UCI to
+//! it over its stdin and stdout, so it can play anywhere a [`Player`] can:
+//! against the user in the command-line game or in calibration matches.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::perft::Divide;
+use crate::player::{Player, SearchLimits};
+use crate::position::{Color, Position};
+use crate::time_manager::Clock;
+
+/// Search time asked for when the limits give neither a time nor a depth.
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+
+/// A UCI engine running as a child process.
+pub struct UciEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciEngine {
+    /// Starts the engine and waits until it is ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Path of the engine executable
+    /// * `args` - Arguments to start it with
+    ///
+    /// # Returns
+    ///
+    /// * The engine, or an error if it cannot be started or does not answer
+    ///   the UCI handshake
+    pub fn spawn(command: &str, args: &[&str]) -> Result<Self, String> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Could not start {}: {}", command, e))?;
+        let stdin = child.stdin.take().ok_or("Engine has no stdin")?;
+        let stdout = BufReader::new(child.stdout.take().ok_or("Engine has no stdout")?);
+        let mut engine = UciEngine { name: command.to_string(), child, stdin, stdout };
+
+        engine.send("uci")?;
+        for line in engine.read_until("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        engine.send("isready")?;
+        engine.read_until("readyok")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Could not write to {}: {}", self.name, e))
+    }
+
+    /// Reads lines up to and including the first one starting with `prefix`.
+    fn read_until(&mut self, prefix: &str) -> Result<Vec<String>, String> {
+        self.read_until_any(&[prefix])
+    }
+
+    /// Reads lines up to and including the first one starting with any of
+    /// `prefixes`.
+    fn read_until_any(&mut self, prefixes: &[&str]) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = self.stdout.read_line(&mut line)
+                .map_err(|e| format!("Could not read from {}: {}", self.name, e))?;
+            if read == 0 {
+                return Err(format!("{} exited while waiting for '{}'", self.name, prefixes.join("' or '")));
+            }
+            let done = prefixes.iter().any(|prefix| line.starts_with(prefix));
+            lines.push(line.trim_end().to_string());
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+
+    /// Asks the engine for its perft divide counts with `go perft`, which
+    /// Stockfish and many other engines support.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The root position
+    /// * `moves` - Moves played from the root, in long algebraic notation
+    /// * `depth` - Plies to count, including the root move
+    ///
+    /// # Returns
+    ///
+    /// * The leaf count of every move, or an error if the engine answers
+    ///   with a search instead
+    pub fn perft_divide(&mut self, position: &Position, moves: &[String], depth: i32) -> Result<Divide, String> {
+        let mut command = format!("position fen {}", position.to_fen());
+        if !moves.is_empty() {
+            command.push_str(" moves ");
+            command.push_str(&moves.join(" "));
+        }
+        self.send(&command)?;
+        self.send(&format!("go perft {}", depth))?;
+
+        let lines = self.read_until_any(&["Nodes searched", "bestmove"])?;
+        if lines.last().is_some_and(|line| line.starts_with("bestmove")) {
+            return Err(format!("{} does not support go perft", self.name));
+        }
+        Ok(lines.iter()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(mv, _)| matches!(mv.trim().len(), 4 | 5))
+            .filter_map(|(mv, nodes)| Some((mv.trim().to_ascii_lowercase(), nodes.trim().parse().ok()?)))
+            .collect())
+    }
+
+    /// Asks the engine for its move in the position.
+    fn best_move(&mut self, position: &Position, limits: &SearchLimits) -> Result<Option<Move>, String> {
+        let fen = position.to_fen();
+        self.send(&format!("position fen {}", fen))?;
+        let go = match (limits.clock, limits.movetime, limits.depth) {
+            _ if limits.instant => "go depth 1".to_string(),
+            (Some(clock), _, _) => clock_command(&clock, position.active_color),
+            (None, Some(movetime), _) => format!("go movetime {}", movetime.as_millis()),
+            (None, None, Some(depth)) => format!("go depth {}", depth),
+            (None, None, None) => format!("go movetime {}", DEFAULT_MOVETIME_MS),
+        };
+        self.send(&go)?;
+
+        let lines = self.read_until("bestmove")?;
+        let reply = lines.last().and_then(|line| line.split_whitespace().nth(1)).unwrap_or("(none)");
+        if reply == "(none)" || reply == "0000" {
+            return Ok(None);
+        }
+        Board::from_fen(&fen)?.parse_move(reply)
+            .map(Some)
+            .map_err(|e| format!("{} played {}: {}", self.name, reply, e))
+    }
+}
+
+impl Player for UciEngine {
+    fn choose_move(&mut self, position: &Position, limits: &SearchLimits) -> Option<Move> {
+        match self.best_move(position, limits) {
+            Ok(mv) => mv,
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Sets the engine's standard `Hash` option.
+    fn set_hash_size(&mut self, size_mb: usize) -> Result<(), String> {
+        self.send(&format!("setoption name Hash value {}", size_mb))?;
+        self.send("isready")?;
+        self.read_until("readyok").map(|_| ())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() || self.child.try_wait().ok().flatten().is_none() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// The `go` command for playing on the side to move's clock.
+fn clock_command(clock: &Clock, side: Color) -> String {
+    let prefix = if side == Color::White { 'w' } else { 'b' };
+    let mut go = format!("go {}time {} {}inc {}", prefix, clock.remaining.as_millis(), prefix, clock.increment.as_millis());
+    if let Some(moves) = clock.moves_to_go {
+        go.push_str(&format!(" movestogo {}", moves));
+    }
+    go
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_uci_engine_plays_through_player() {
+        // A stand-in engine that always answers e7e5
+        let script = std::env::temp_dir().join(format!("rustchess_fake_uci_{}.sh", std::process::id()));
+        std::fs::write(&script, "while read cmd; do case \"$cmd\" in \
+            uci) echo 'id name Fake'; echo uciok;; \
+            isready) echo readyok;; \
+            go*) echo 'info depth 1'; echo 'bestmove e7e5';; \
+            quit) exit 0;; esac; done\n").unwrap();
+
+        let mut engine = UciEngine::spawn("sh", &[script.to_str().unwrap()]).unwrap();
+        assert_eq!(engine.name(), "Fake");
+        let mut board = Board::new();
+        board.play(board.parse_move("e2e4").unwrap()).unwrap();
+        let mv = engine.choose_move(board.position(), &SearchLimits::movetime_ms(10)).unwrap();
+        assert_eq!(mv.to_string(), "e7e5");
+
+        // The reply is illegal from the starting position
+        assert_eq!(engine.choose_move(Board::new().position(), &SearchLimits::default()), None);
+        drop(engine);
+        std::fs::remove_file(&script).unwrap();
+    }
+}
+
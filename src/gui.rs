@@ -1,552 +1,3396 @@
-use eframe::egui;
+use eframe::egui::{self, accesskit};
 use crate::Game;
-use crate::position::{Color, PieceType};
+use crate::position::{Color, PieceType, Position};
+use crate::chess_move::Move;
 use crate::utils::bit_scan;
 use crate::evaluation::Evaluation;
-use crate::search::Search;
+use crate::search::{Search, DEFAULT_HASH_MB};
+use crate::player::{Player, SearchLimits};
+use crate::calibration::ReferenceOpponent;
+use crate::render::{self, ExportOptions, DARK_SQUARE, LIGHT_SQUARE};
+use crate::board::{Board, START_FEN};
+use crate::notation::{self, piece_name, square_name};
+use crate::pgn::{self, ImportFormat, ImportedGame, MoveNode, MoveTree, MovetextItem, Nag};
+use crate::engine::{Analysis, Engine};
+use crate::book::{self, BookMove, OpeningBook};
+use crate::remote::{self, AnalysisLine, AnalysisSource, RemoteAnalyzer};
+use crate::report::{self, GameAnalysis, ReportFormat};
+use crate::library::{Bookmark, PositionLibrary};
+use crate::drills::{self, DrillStats, DrillStep, MateDrill};
+use crate::repertoire::{RecallStats, Trainer, TrainerStep};
+use crate::tablebase::{self, Ending, Verdict};
+use crate::assets::{self, Asset, AssetStatus};
+use crate::analysis_cache::{AnalysisCache, DEFAULT_MAX_POSITIONS};
+use crate::bundle::{DebugBundle, SearchLog};
+use crate::memory::{self, MemoryUse};
+use crate::time_manager::{self, Clock, FlagResult, LOW_TIME_WARNING};
+use crate::strength::{self, StrengthRecord};
+use crate::options::{self, EngineOptions, OptionKind, OptionValue, MAX_HASH_MB};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// Environment variable naming a `Chess_Engine serve` instance (`host:port`)
+/// to offload analysis to
+const REMOTE_ENV: &str = "RUSTCHESS_REMOTE";
+/// Search time for the analysis panel, remote or local
+const ANALYSIS_TIME_MS: u64 = 2000;
+/// Search time per move in engine-versus-engine tabs, so matches play out
+/// at a watchable pace
+const ENGINE_MATCH_TIME_MS: u64 = 1000;
+/// Shortest time the engine takes to answer the user, so that even easy
+/// replies do not appear the instant the user lets go of a piece
+const MIN_THINK_TIME: Duration = Duration::from_millis(500);
+/// Upper end of the minimum think time setting
+const MAX_MIN_THINK_MS: u64 = 5000;
+/// Storage key of the remembered `Settings`
+const SETTINGS_KEY: &str = "engine_settings";
+/// Storage key of the position library
+const LIBRARY_KEY: &str = "position_library";
+/// Storage key of the mate drill results
+const DRILL_STATS_KEY: &str = "drill_stats";
+/// Storage key of the last repertoire imported into the trainer
+const REPERTOIRE_KEY: &str = "repertoire";
+/// Storage key of the repertoire trainer's recall records
+const RECALL_STATS_KEY: &str = "repertoire_recall";
+/// Storage key of the analysis cache, and of whether it is kept between runs
+const ANALYSIS_CACHE_KEY: &str = "analysis_cache";
+const KEEP_ANALYSIS_KEY: &str = "keep_analysis";
+/// Longest mate looked for in played games, so scanning a game stays quick
+const GAME_DRILL_MOVES: u32 = 2;
+/// Weak opponents that can stand in for the engine
+const BASELINE_OPPONENTS: [ReferenceOpponent; 3] = [
+    ReferenceOpponent::Random,
+    ReferenceOpponent::Material { depth: 1 },
+    ReferenceOpponent::Material { depth: 2 },
+];
+/// How long a moving piece takes to slide to its new square
+const MOVE_ANIMATION: Duration = Duration::from_millis(150);
+/// How long a captured piece takes to fade out
+const CAPTURE_FADE: Duration = Duration::from_millis(250);
+/// How long the checking piece's square flashes, and the length of one flash
+const CHECK_FLASH: Duration = Duration::from_millis(600);
+const FLASH_PERIOD: Duration = Duration::from_millis(150);
+/// How long a toast stays up
+const TOAST_TIME: Duration = Duration::from_secs(5);
+/// Most toasts shown at once; the oldest go first
+const MAX_TOASTS: usize = 4;
+/// Clocks offered for games against the engine, as base time and increment
+/// in seconds
+const TIME_CONTROLS: [(u64, u64); 4] = [(60, 0), (180, 2), (300, 0), (900, 10)];
+/// What the warm-up thread prepares behind the splash screen, in order
+const WARM_UP_STEPS: [&str; 2] = ["Endgame tables", "KPK bitbase"];
 
-#[derive(Clone)]
-pub struct ChessGUI {
+
+/// Where the game stands after the last move, whichever side made it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GameResult {
+    Ongoing,
+    Checkmate { winner: Color },
+    Stalemate,
+    /// A clock ran out and the other side could still mate
+    OnTime { winner: Color },
+    /// A clock ran out but the other side had no mating material left
+    TimeoutDraw,
+}
+
+impl GameResult {
+    /// Works out the result for the side to move in `game`.
+    fn of(game: &Game) -> GameResult {
+        let mut position = game.position.clone();
+        position.update_all_legal_moves(game);
+        if !position.get_all_legal_moves(game).is_empty() {
+            GameResult::Ongoing
+        } else if position.is_in_check(game) {
+            let winner = if position.active_color == Color::White { Color::Black } else { Color::White };
+            GameResult::Checkmate { winner }
+        } else {
+            GameResult::Stalemate
+        }
+    }
+
+    fn is_over(self) -> bool {
+        self != GameResult::Ongoing
+    }
+
+    /// Status line shown to a player of `player_color` once the game is over.
+    fn announcement(self, player_color: Color) -> &'static str {
+        match self {
+            GameResult::Ongoing => "",
+            GameResult::Checkmate { winner } if winner == player_color => "Checkmate! You win!",
+            GameResult::Checkmate { .. } => "Checkmate! The engine wins.",
+            GameResult::Stalemate => "Stalemate! Game is drawn.",
+            GameResult::OnTime { winner } if winner == player_color => "The engine ran out of time. You win!",
+            GameResult::OnTime { .. } => "You ran out of time. The engine wins.",
+            GameResult::TimeoutDraw => "Out of time, but nobody can mate. Game is drawn.",
+        }
+    }
+
+    /// Status line for a game the user is not playing in.
+    fn summary(self) -> &'static str {
+        match self {
+            GameResult::Ongoing => "",
+            GameResult::Checkmate { winner: Color::White } => "Checkmate! White wins.",
+            GameResult::Checkmate { .. } => "Checkmate! Black wins.",
+            GameResult::Stalemate => "Stalemate! Game is drawn.",
+            GameResult::OnTime { winner: Color::White } => "White wins on time.",
+            GameResult::OnTime { .. } => "Black wins on time.",
+            GameResult::TimeoutDraw => "Out of time, but nobody can mate. Game is drawn.",
+        }
+    }
+}
+
+/// Both sides' clocks in a game against the engine. The clock of the side
+/// to move runs from the first move on; each move adds the increment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GameClocks {
+    remaining: [Duration; 2],  // White's and Black's time left when their clock last stopped
+    increment: Duration,
+    running: Option<(Color, Instant)>,  // The side whose clock runs, and since when
+}
+
+impl GameClocks {
+    fn new(base: Duration, increment: Duration) -> Self {
+        GameClocks { remaining: [base; 2], increment, running: None }
+    }
+
+    fn index(color: Color) -> usize {
+        if color == Color::White { 0 } else { 1 }
+    }
+
+    /// Time `color` has left at `now`.
+    fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let left = self.remaining[Self::index(color)];
+        match self.running {
+            Some((side, since)) if side == color => left.saturating_sub(now.saturating_duration_since(since)),
+            _ => left,
+        }
+    }
+
+    /// Ends `mover`'s turn at `now`: charges its thinking time, adds the
+    /// increment and starts the other side's clock.
+    fn press(&mut self, mover: Color, now: Instant) {
+        let left = self.remaining(mover, now);
+        self.remaining[Self::index(mover)] = left + self.increment;
+        let other = if mover == Color::White { Color::Black } else { Color::White };
+        self.running = Some((other, now));
+    }
+
+    /// Stops the running clock at `now`, when the game is over.
+    fn stop(&mut self, now: Instant) {
+        if let Some((side, _)) = self.running {
+            self.remaining[Self::index(side)] = self.remaining(side, now);
+            self.running = None;
+        }
+    }
+
+    /// The side whose time has run out at `now`, if any.
+    fn flagged(&self, now: Instant) -> Option<Color> {
+        self.running.map(|(side, _)| side).filter(|&side| self.remaining(side, now).is_zero())
+    }
+
+    /// `color`'s clock for its search.
+    fn clock(&self, color: Color, now: Instant) -> Clock {
+        Clock { remaining: self.remaining(color, now), increment: self.increment, moves_to_go: None }
+    }
+}
+
+/// Clock time as minutes and seconds, with tenths below `LOW_TIME_WARNING`.
+fn format_clock(time: Duration) -> String {
+    if time < LOW_TIME_WARNING {
+        format!("0:{:02}.{}", time.as_secs(), time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+    }
+}
+
+/// Something visible that happened on the board. `play_move` reports these
+/// for the animator rather than letting the pieces jump to their new squares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BoardEvent {
+    PieceMoved { from: usize, to: usize, piece: PieceType, color: Color },
+    PieceCaptured { square: usize, piece: PieceType, color: Color },
+    Check { checker: usize },
+}
+
+impl BoardEvent {
+    /// Works out what changed between two positions one move apart, by
+    /// comparing where each piece, known by its id, stands before and after.
+    fn between(before: &Position, after: &Position, game: &Game) -> Vec<BoardEvent> {
+        let mut events = Vec::new();
+        for old in &before.pieces {
+            let from = bit_scan(old.position);
+            match after.piece_by_id(old.id) {
+                None => events.push(BoardEvent::PieceCaptured { square: from, piece: old.piece_type, color: old.color }),
+                // A promoting pawn slides as a pawn and lands as its new piece
+                Some(new) if new.position != old.position => events.push(BoardEvent::PieceMoved {
+                    from, to: bit_scan(new.position), piece: old.piece_type, color: old.color,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        let mut checkers = after.checkers(game);
+        while checkers != 0 {
+            let checker = bit_scan(checkers);
+            events.push(BoardEvent::Check { checker });
+            checkers &= checkers - 1;
+        }
+        events
+    }
+
+    fn duration(self) -> Duration {
+        match self {
+            BoardEvent::PieceMoved { .. } => MOVE_ANIMATION,
+            BoardEvent::PieceCaptured { .. } => CAPTURE_FADE,
+            BoardEvent::Check { .. } => CHECK_FLASH,
+        }
+    }
+}
+
+/// Board events being animated, each with the time it started.
+#[derive(Default)]
+struct Animator {
+    active: Vec<(BoardEvent, Instant)>,
+}
+
+impl Animator {
+    fn start(&mut self, events: &[BoardEvent], now: Instant) {
+        self.active.extend(events.iter().map(|&event| (event, now)));
+    }
+
+    /// Drops finished animations.
+    fn tick(&mut self, now: Instant) {
+        self.active.retain(|(event, started)| now.duration_since(*started) < event.duration());
+    }
+
+    fn clear(&mut self) {
+        self.active.clear();
+    }
+
+    /// The running animations with how far along each is, from 0.0 to 1.0.
+    fn progress(&self, now: Instant) -> impl Iterator<Item = (BoardEvent, f32)> + '_ {
+        self.active.iter().map(move |&(event, started)| {
+            let t = now.duration_since(started).as_secs_f32() / event.duration().as_secs_f32();
+            (event, t.min(1.0))
+        })
+    }
+
+    /// Whether the piece standing on `square` is still sliding there.
+    fn is_arriving(&self, square: usize) -> bool {
+        self.active.iter().any(|(event, _)| matches!(event, BoardEvent::PieceMoved { to, .. } if *to == square))
+    }
+
+    /// Whether `square` is lit in the current flash of a checking piece.
+    fn is_flashing(&self, square: usize, now: Instant) -> bool {
+        self.active.iter().any(|&(event, started)| {
+            let flash = (now.duration_since(started).as_millis() / FLASH_PERIOD.as_millis()) & 1 == 0;
+            flash && event == BoardEvent::Check { checker: square }
+        })
+    }
+}
+
+/// Who makes the moves in a tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TabMode {
+    /// The user plays `player_color` against the engine
+    VsEngine,
+    /// The engine plays both sides
+    EngineMatch,
+    /// The user plays both sides, e.g. to study a pasted position
+    Analysis,
+}
+
+/// What a copy action puts on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CopyItem {
+    /// The position as FEN
+    Fen,
+    /// The game with its variations, in SAN
+    Pgn,
+    /// The engine's line from the position, in SAN with move numbers
+    BestLine,
+    /// The engine's score and line from the position
+    EvalSummary,
+}
+
+impl CopyItem {
+    const ALL: [CopyItem; 4] = [CopyItem::Fen, CopyItem::Pgn, CopyItem::BestLine, CopyItem::EvalSummary];
+
+    fn label(self) -> &'static str {
+        match self {
+            CopyItem::Fen => "Copy FEN",
+            CopyItem::Pgn => "Copy PGN",
+            CopyItem::BestLine => "Copy best line (SAN)",
+            CopyItem::EvalSummary => "Copy eval summary",
+        }
+    }
+}
+
+/// Whether a notice reports something that went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoticeLevel {
+    Info,
+    Error,
+}
+
+/// A message for the user, shown as a toast and in the status bar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Notice {
+    level: NoticeLevel,
+    text: String,
+}
+
+impl Notice {
+    fn info(text: impl Into<String>) -> Self {
+        Notice { level: NoticeLevel::Info, text: text.into() }
+    }
+
+    fn error(text: impl Into<String>) -> Self {
+        Notice { level: NoticeLevel::Error, text: text.into() }
+    }
+}
+
+/// The notices the user has been given: toasts in the corner of the window
+/// that go away after a while, and the newest, kept in the status bar until
+/// the next one.
+#[derive(Default)]
+struct Notices {
+    toasts: Vec<(Notice, Instant)>,  // Shown, oldest first, with when each was posted
+    latest: Option<Notice>,
+}
+
+impl Notices {
+    fn push(&mut self, notice: Notice) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.toasts.push((notice.clone(), Instant::now()));
+        self.latest = Some(notice);
+    }
+
+    /// Takes down the toasts that have been up for `TOAST_TIME` by `now`.
+    fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|(_, posted)| now.duration_since(*posted) < TOAST_TIME);
+    }
+
+    /// Draws the toasts in the bottom right corner, newest at the bottom.
+    /// Clicking one dismisses it.
+    fn draw(&mut self, ctx: &egui::Context) {
+        self.expire(Instant::now());
+        let mut dismissed = None;
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -50.0))
+            .show(ctx, |ui| {
+                for (index, (notice, _)) in self.toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        if ui.add(egui::Label::new(notice_text(notice)).sense(egui::Sense::click())).clicked() {
+                            dismissed = Some(index);
+                        }
+                    });
+                }
+            });
+        if let Some(index) = dismissed {
+            self.toasts.remove(index);
+        }
+    }
+}
+
+/// A notice's text, in red for errors.
+fn notice_text(notice: &Notice) -> egui::RichText {
+    let text = egui::RichText::new(&notice.text);
+    match notice.level {
+        NoticeLevel::Info => text,
+        NoticeLevel::Error => text.color(egui::Color32::LIGHT_RED),
+    }
+}
+
+/// Startup work done on a background thread while the splash screen shows,
+/// so no game stalls on its first endgame or table lookup.
+struct WarmUp {
+    steps: mpsc::Receiver<(&'static str, Duration)>,
+    done: Vec<(&'static str, Duration)>,  // Finished steps with how long each took
+}
+
+impl WarmUp {
+    /// Starts loading or generating the endgame tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `tables` - How long building the move generation tables took
+    fn start(tables: Duration) -> Self {
+        let (sender, steps) = mpsc::channel();
+        thread::spawn(move || {
+            let timed = |step, work: &dyn Fn()| {
+                let start = Instant::now();
+                work();
+                let _ = sender.send((step, start.elapsed()));
+            };
+            timed(WARM_UP_STEPS[0], &|| { tablebase::tablebases(); });
+            timed(WARM_UP_STEPS[1], &|| { crate::bitbase::kpk(); });
+        });
+        WarmUp { steps, done: vec![("Move generation tables", tables)] }
+    }
+
+    /// Collects the steps finished since the last call.
+    ///
+    /// # Returns
+    ///
+    /// * Whether every step is done
+    fn poll(&mut self) -> bool {
+        loop {
+            match self.steps.try_recv() {
+                Ok(step) => self.done.push(step),
+                Err(mpsc::TryRecvError::Empty) => return false,
+                Err(mpsc::TryRecvError::Disconnected) => return true,
+            }
+        }
+    }
+
+    /// How long startup took, step by step.
+    fn summary(&self) -> String {
+        let total: Duration = self.done.iter().map(|(_, time)| *time).sum();
+        let steps: Vec<String> = self.done.iter()
+            .map(|(step, time)| format!("{} {} ms", step.to_lowercase(), time.as_millis()))
+            .collect();
+        format!("Engine ready in {} ms ({})", total.as_millis(), steps.join(", "))
+    }
+
+    /// The splash screen, listing each step as done or in progress.
+    fn draw(&self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 3.0);
+                ui.heading("RustChess Engine");
+                ui.label("Preparing the engine...");
+                ui.add_space(10.0);
+                for (step, time) in &self.done {
+                    ui.label(format!("{}: {} ms", step, time.as_millis()));
+                }
+                if let Some(step) = WARM_UP_STEPS.get(self.done.len() - 1) {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(*step);
+                    });
+                }
+            });
+        });
+    }
+}
+
+/// A move of the game as shown in the move list, with the engine's line for
+/// the position it was played from when one was found during the game.
+struct HistoryEntry {
+    text: String,
+    spoken: String,  // The move in words, as announced to screen readers
+    analysis: Option<AnalysisLine>,
+    hash: u64,  // Of the position the move was played from, for the analysis cache
+}
+
+/// What the engine's search thread sends back: the move, its analysis and
+/// how long the search took.
+type EngineReply = (Option<u64>, Option<Analysis>, Duration);
+/// The analysis panel's line with why the remote was not used, or why
+/// there is no line, and how long it took.
+type AnalysisReply = (Result<(AnalysisLine, Option<String>), String>, Duration);
+
+/// The engine thinking on the user's time about the reply it expects.
+struct Ponder {
+    reply: u64,
+    flag: Arc<AtomicBool>,  // The player's ponder flag; cleared on a ponder hit
+    receiver: mpsc::Receiver<EngineReply>,  // Where the ponder search answers
+}
+
+/// One game in its own tab, with its own board, engine, move list and
+/// analysis.
+///
+/// Engine moves are searched on a background thread, so a tab whose engine
+/// is thinking never blocks the others.
+struct GameController {
+    title: String,
+    mode: TabMode,
     game: Game,
+    start_fen: String,  // Position the game started from
     selected_square: Option<usize>,
     is_player_turn: bool,
     evaluation: i32,  // Current position evaluation in centipawns
     player_color: Color,  // Added player color field
-    search: Search,  // Added search engine
-    engine_thinking: bool,  // Flag to prevent multiple engine moves
-    move_history: Vec<String>,  // Add move history
+    opponent: Option<ReferenceOpponent>,  // Baseline opponent, or `None` for the engine
+    player: Arc<Mutex<Box<dyn Player + Send>>>,  // Shared with the thread choosing the engine's move
+    limits: SearchLimits,  // Thinking time of the engine's side
+    time_control: Option<(Duration, Duration)>,  // Base time and increment of the next game against the engine
+    clocks: Option<GameClocks>,  // Set while the game is played on a clock
+    pondering: bool,  // Whether the engine thinks on the user's time
+    ponder: Option<Ponder>,  // Set while the engine ponders
+    stop: Arc<AtomicBool>,  // Aborts the running engine search
+    engine_move: Option<mpsc::Receiver<EngineReply>>,  // Set while the engine is thinking
+    move_history: Vec<HistoryEntry>,  // Add move history
+    viewed_ply: Option<usize>,  // Move selected in the move list, whose cached line the analysis panel shows
     dragging_piece: Option<(usize, egui::Pos2)>,  // Add drag and drop support
+    result: GameResult,  // Updated after every move, player or engine
+    in_check: bool,  // Whether the side to move is in check; updated with `result`
+    rejection: Option<String>,  // Why the user's last move attempt was refused
+    typed_file: Option<char>,  // File of a square being typed on the keyboard, waiting for its rank
+    keyboard_feedback: Option<String>,  // What the last typed square selected or played
+    notices: Vec<Notice>,  // Messages for the user not yet handed to the window
+    played_moves: Vec<u64>,  // Moves of the current game, for export
+    line: Vec<Move>,  // The same moves decoded: the path to the current position in `variations`
+    variations: MoveTree,  // Every line played or pasted from the start position, with comments
+    analysis_engine: Arc<Mutex<Engine>>,  // Local fallback for the analysis panel; shared with the thread analyzing
+    analysis_request: Option<(Vec<Move>, mpsc::Receiver<AnalysisReply>)>,  // Set while the analysis panel waits, with the line to the position analyzed
+    hash_mb: usize,  // Hash table size of the tab's engines, in megabytes
+    engine_options: EngineOptions,  // Skill level, move overhead and the rest, given to the player before each search
+    pending_hash_mb: Option<usize>,  // A new hash size for the player, set while it was searching
+    search_log: SearchLog,  // The tab's recent searches, for debug bundles
+    analysis: Option<AnalysisLine>,  // Line for the current position; moves into the history when a move is played
+    what_if: Option<(Vec<Move>, AnalysisLine)>,  // Line for the current position with the listed moves ruled out
+    drill: Option<MateDrill>,  // The mate the user is asked to find, in a drill tab
+    drill_result: Option<bool>,  // Set when the drill is solved, to whether it went without a miss
+    practice: Option<Practice>,  // The ending the user is converting, in a practice tab
+    trainer: Option<Trainer>,  // The repertoire being quizzed, in a trainer tab
+    trainer_result: Option<(Vec<Move>, bool)>,  // Set when a line is finished, to the line and whether it went without a miss
+    animator: Animator,  // Animates the events of the moves played
 }
 
-impl ChessGUI {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
+impl GameController {
+    fn new(title: String, mode: TabMode) -> Self {
+        let search = Search::new();
+        let stop = search.stop_flag();
+        let mut tab = Self {
+            title,
+            mode,
             game: Game::new(),
+            start_fen: START_FEN.to_string(),
             selected_square: None,
             is_player_turn: true,
             evaluation: 0,
             player_color: Color::White,  // Default to white
-            search: Search::new(),
-            engine_thinking: false,
+            opponent: None,
+            player: Arc::new(Mutex::new(Box::new(search))),
+            limits: match mode {
+                TabMode::VsEngine => SearchLimits { min_time: Some(MIN_THINK_TIME), ..SearchLimits::default() },
+                TabMode::Analysis => SearchLimits::default(),
+                TabMode::EngineMatch => SearchLimits::movetime_ms(ENGINE_MATCH_TIME_MS),
+            },
+            time_control: None,
+            clocks: None,
+            pondering: false,
+            ponder: None,
+            stop,
+            engine_move: None,
             move_history: Vec::new(),
+            viewed_ply: None,
             dragging_piece: None,
-        }
+            result: GameResult::Ongoing,
+            in_check: false,
+            rejection: None,
+            typed_file: None,
+            keyboard_feedback: None,
+            notices: Vec::new(),
+            played_moves: Vec::new(),
+            line: Vec::new(),
+            variations: MoveTree::new(START_FEN),
+            analysis_engine: Arc::new(Mutex::new(Engine::new())),
+            analysis_request: None,
+            hash_mb: DEFAULT_HASH_MB,
+            engine_options: EngineOptions::default(),
+            pending_hash_mb: None,
+            search_log: SearchLog::new(),
+            analysis: None,
+            what_if: None,
+            drill: None,
+            drill_result: None,
+            practice: None,
+            trainer: None,
+            trainer_result: None,
+            animator: Animator::default(),
+        };
+        tab.set_opponent(None);
+        tab.new_game(Color::White);
+        tab
     }
 
-    fn format_move(&self, from: usize, to: usize, piece_type: PieceType) -> String {
-        let files = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
-        let piece_symbol = match piece_type {
-            PieceType::King => "K",
-            PieceType::Queen => "Q",
-            PieceType::Rook => "R",
-            PieceType::Bishop => "B",
-            PieceType::Knight => "N",
-            PieceType::Pawn => "",
+    /// Switches who plays the engine's side, from its next move on.
+    ///
+    /// # Arguments
+    ///
+    /// * `opponent` - A baseline opponent, or `None` for the full engine
+    fn set_opponent(&mut self, opponent: Option<ReferenceOpponent>) {
+        self.stop_pondering();
+        let player: Box<dyn Player + Send> = match opponent {
+            Some(baseline) => baseline.player(rand::random()),
+            None => {
+                let (mut search, errors) = Search::from_config();
+                self.notices.extend(errors.into_iter().map(Notice::error));
+                if self.hash_mb != search.hash_size() {
+                    search.set_hash_size(self.hash_mb);
+                }
+                self.stop = search.stop_flag();
+                Box::new(search)
+            }
         };
-        
-        let from_file = files[from % 8];
-        let from_rank = (from / 8) + 1;
-        let to_file = files[to % 8];
-        let to_rank = (to / 8) + 1;
-        
-        format!("{}{}{}{}{}", piece_symbol, from_file, from_rank, to_file, to_rank)
+        self.opponent = opponent;
+        self.player = Arc::new(Mutex::new(player));
+        self.pending_hash_mb = None;
     }
 
-    fn make_engine_move(&mut self) {
-        if self.is_player_turn || self.engine_thinking {
-            return;
+    /// Gives the tab's engines empty hash tables of `size_mb` megabytes. A
+    /// player busy searching gets its table before its next search.
+    fn set_hash_size(&mut self, size_mb: usize) {
+        self.hash_mb = size_mb;
+        // Engines always have a hash table. One busy analyzing gets its
+        // table before its next analysis.
+        if let Ok(mut engine) = self.analysis_engine.try_lock() {
+            let _ = Player::set_hash_size(&mut *engine, size_mb);
         }
-
-        // Verify it's actually the engine's turn based on colors
-        if (self.player_color == Color::White && self.game.position.active_color == Color::White) ||
-           (self.player_color == Color::Black && self.game.position.active_color == Color::Black) {
-            return;
+        match self.player.try_lock() {
+            // Baseline opponents have no table to resize
+            Ok(mut player) => {
+                let _ = player.set_hash_size(size_mb);
+            }
+            Err(_) => self.pending_hash_mb = Some(size_mb),
         }
+    }
 
-        self.engine_thinking = true;
+    /// Bytes the tab's engines take. An engine busy searching is assumed to
+    /// fill its table.
+    fn memory_bytes(&self) -> usize {
+        let player = self.player.try_lock().map_or(self.hash_mb << 20, |player| player.memory_bytes());
+        let analysis = self.analysis_engine.try_lock().map_or(self.hash_mb << 20, |engine| engine.memory_bytes());
+        player + analysis
+    }
 
-        // Update legal moves before searching
+    /// Starts over from the initial position, abandoning any engine search.
+    fn new_game(&mut self, player_color: Color) {
+        self.stop_pondering();
+        self.stop.store(true, Ordering::Relaxed);
+        self.engine_move = None;
+
+        self.game = Game::new();
+        self.start_fen = START_FEN.to_string();
+        self.player_color = player_color;
+        // White moves first; in an engine match the user never moves
+        self.is_player_turn = match self.mode {
+            TabMode::VsEngine => player_color == Color::White,
+            TabMode::EngineMatch => false,
+            TabMode::Analysis => true,
+        };
+        self.selected_square = None;
+        self.dragging_piece = None;
+        self.evaluation = 0;
+        self.result = GameResult::Ongoing;
+        self.in_check = false;
+        self.rejection = None;
+        self.typed_file = None;
+        self.keyboard_feedback = None;
+        self.move_history.clear();
+        self.viewed_ply = None;
+        self.played_moves.clear();
+        self.line.clear();
+        self.variations = MoveTree::new(START_FEN);
+        self.analysis = None;
+        self.analysis_request = None;
+        self.what_if = None;
+        self.animator.clear();
+        self.clocks = self.time_control.filter(|_| self.mode == TabMode::VsEngine)
+            .map(|(base, increment)| GameClocks::new(base, increment));
+
+        // Force update of legal moves
         let game_copy = self.game.clone();
         self.game.position.update_all_legal_moves(&game_copy);
+        self.game.position.active_color = Color::White;  // Ensure White moves first
+    }
 
-        // Check for checkmate/stalemate
-        if self.game.position.get_all_legal_moves(&game_copy).is_empty() {
-            if self.game.position.is_in_check(&game_copy) {
-                println!("Checkmate! Player wins!");
-            } else {
-                println!("Stalemate! Game is drawn.");
-            }
-            self.engine_thinking = false;
-            return;
+    /// Replaces the game with an imported one, replaying its moves so the
+    /// move list and analysis start from the imported position.
+    ///
+    /// # Arguments
+    ///
+    /// * `imported` - The game, e.g. from a pasted FEN or PGN
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or an error if its start position cannot be set up
+    fn load(&mut self, imported: &ImportedGame) -> Result<(), String> {
+        let mut game = Game::new();
+        game.position = Position::from_fen(&imported.start_fen, &game)?;
+        self.new_game(game.position.active_color);
+        self.game = game;
+        self.start_fen = imported.start_fen.clone();
+        self.variations = MoveTree::new(&imported.start_fen);
+        for mv in &imported.moves {
+            self.play_move(mv.to_u64());
         }
+        // Replaying the moves took no one's time
+        self.clocks = self.time_control.filter(|_| self.mode == TabMode::VsEngine)
+            .map(|(base, increment)| GameClocks::new(base, increment));
+        self.animator.clear();
+        self.update_result();
+        Ok(())
+    }
 
-        // Find best move using alpha-beta search
-        let mut position_copy = self.game.position.clone();
-        if let Some(best_move) = self.search.find_best_move(&mut position_copy) {
-            let from_square = (best_move & 0x3F) as usize;
-            let to_square = ((best_move >> 6) & 0x3F) as usize;
-            
-            // Get piece type for move notation
-            let piece_type = self.game.position.pieces.iter()
-                .find(|p| bit_scan(p.position) == from_square)
-                .map(|p| p.piece_type)
-                .unwrap_or(PieceType::Pawn);
-            
-            // Make the move
-            self.game.position.make_move(best_move);
-            
-            // Add to move history
-            let move_text = self.format_move(from_square, to_square, piece_type);
-            self.move_history.push(format!("{}. ... {}", self.move_history.len() / 2 + 1, move_text));
-            
-            // Update evaluation
-            let eval = Evaluation::new(self.game.position.clone());
-            self.evaluation = eval.evaluate_position();
-            
-            self.is_player_turn = true;
+    /// The color whose pieces the user may move: their own, or in an
+    /// analysis tab whichever side is to move.
+    fn mover(&self) -> Color {
+        match self.mode {
+            TabMode::Analysis => self.game.position.active_color,
+            _ => self.player_color,
         }
-        
-        self.engine_thinking = false;
     }
 
-    fn handle_square_click(&mut self, square: usize, pointer_pos: Option<egui::Pos2>) {
-        // Validate square is in bounds
-        if square >= 64 {
-            return;
+    fn engine_thinking(&self) -> bool {
+        self.engine_move.is_some()
+    }
+
+    /// Status line for the bottom panel.
+    fn status(&self) -> &'static str {
+        match (self.result.is_over(), self.mode) {
+            (true, TabMode::VsEngine) => self.result.announcement(self.player_color),
+            (true, _) => self.result.summary(),
+            (false, TabMode::VsEngine) if self.is_player_turn => "Your turn to move",
+            (false, TabMode::Analysis) if self.game.position.active_color == Color::White => "White to move",
+            (false, TabMode::Analysis) => "Black to move",
+            (false, _) => "Engine is thinking...",
         }
+    }
+
+    /// Analyzes the current position for the analysis panel, on the remote
+    /// server when one is configured and reachable and locally otherwise.
+    ///
+    /// The analysis runs on a background thread; `poll_analysis` shows the
+    /// line once found.
+    fn analyze_position(&mut self, remote: Option<&RemoteAnalyzer>) {
+        // The remote replays the game from the start, so send the moves as text
+        let moves: Vec<String> = self.game_moves().iter().map(|mv| mv.to_string()).collect();
+        let engine = Arc::clone(&self.analysis_engine);
+        let remote = remote.cloned();
+        let start_fen = self.start_fen.clone();
+        let hash_mb = self.hash_mb;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut engine = engine.lock().unwrap();
+            // The hash size may have changed while the engine was busy
+            if engine.hash_size() != hash_mb {
+                let _ = Player::set_hash_size(&mut *engine, hash_mb);
+            }
+            let result = remote::analyze_with_fallback(remote.as_ref(), &mut engine, &start_fen, &moves, ANALYSIS_TIME_MS);
+            let _ = sender.send((result, started.elapsed()));
+        });
+        self.analysis_request = Some((self.line.clone(), receiver));
+    }
 
-        if !self.is_player_turn {
+    fn analyzing(&self) -> bool {
+        self.analysis_request.is_some()
+    }
+
+    /// Shows the line of the analysis panel if its analysis has finished.
+    /// A line for a position the game has since left is dropped.
+    fn poll_analysis(&mut self) {
+        let Some((line, receiver)) = &self.analysis_request else {
             return;
+        };
+        let (result, elapsed) = match receiver.try_recv() {
+            Ok(found) => found,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => (Err("the analysis stopped without a line".to_string()), Duration::ZERO),
+        };
+        let current = *line == self.line;
+        self.analysis_request = None;
+        if let Ok((_, Some(fallback))) = &result {
+            self.notices.push(Notice::error(format!("{}; analyzed locally", fallback)));
         }
+        match result.map(|(line, _)| line) {
+            Ok(line) if current => {
+                self.search_log.record("analysis", &self.game.position.to_fen(), &line, None, Some(elapsed));
+                self.analysis = Some(line);
+            }
+            Ok(_) => {}
+            Err(e) => self.notices.push(Notice::error(format!("Analysis failed: {}", e))),
+        }
+    }
 
-        // Verify it's the player's turn based on colors
-        if (self.player_color == Color::White && self.game.position.active_color == Color::Black) ||
-           (self.player_color == Color::Black && self.game.position.active_color == Color::White) {
+    /// Analyzes the current position again with the best move found so far
+    /// ruled out, answering "What if I don't play it?". Each further call
+    /// rules out the best move of the previous answer as well.
+    ///
+    /// The remote server cannot exclude moves, so this always searches
+    /// locally.
+    fn analyze_without_best(&mut self) {
+        let (mut excluded, line) = match self.what_if.take() {
+            Some((excluded, line)) => (excluded, line),
+            None => match self.analysis.clone() {
+                Some(line) => (Vec::new(), line),
+                None => return,
+            },
+        };
+        let board = Board::from_position(self.game.position.clone());
+        let Ok(best) = board.parse_move(&line.best_move) else {
             return;
+        };
+        excluded.push(best);
+
+        let moves: Vec<String> = self.game_moves().iter().map(|mv| mv.to_string()).collect();
+        let mut engine = self.analysis_engine.lock().unwrap();
+        engine.set_root_moves(&[], &excluded);
+        let result = remote::analyze_locally(&mut engine, &self.start_fen, &moves, ANALYSIS_TIME_MS);
+        engine.set_root_moves(&[], &[]);
+        drop(engine);
+        match result {
+            Ok(line) => self.what_if = Some((excluded, line)),
+            Err(e) => self.notices.push(Notice::error(format!("Analysis failed: {}", e))),
         }
+    }
 
-        let internal_square = if self.player_color == Color::Black {
-            let rank = 7 - (square / 8);
-            let file = 7 - (square % 8);
-            rank * 8 + file
-        } else {
-            square
-        };
+    /// The moves played so far.
+    fn game_moves(&self) -> Vec<Move> {
+        self.line.clone()
+    }
 
-        if let Some(pos) = pointer_pos {
-            // Start dragging
-            let has_piece = self.game.position.pieces.iter().any(|p| {
-                bit_scan(p.position) == internal_square && p.color == self.player_color
-            });
-            
-            if has_piece {
-                self.dragging_piece = Some((square, pos));
-                self.selected_square = Some(square);
-            }
-            return;
+    /// The line shown in the analysis panel: the one cached for the position
+    /// before the move selected in the move list, or the current position's.
+    fn shown_analysis(&self) -> Option<&AnalysisLine> {
+        self.analysis_after(&self.line[..self.viewed_ply.unwrap_or(self.line.len())])
+    }
+
+    /// The engine line cached for the position after `line`, if it is part
+    /// of the current line.
+    fn analysis_after(&self, line: &[Move]) -> Option<&AnalysisLine> {
+        if !self.line.starts_with(line) {
+            return None;
+        }
+        match self.move_history.get(line.len()) {
+            Some(entry) => entry.analysis.as_ref(),
+            None => self.analysis.as_ref(),
         }
+    }
 
-        // Handle piece drop or regular click
-        if let Some((selected, _)) = self.dragging_piece.take() {
-            if selected != square {  // Only make a move if the destination is different
-                self.handle_move(selected, square);
-            }
-            self.selected_square = None;
-        } else if let Some(selected) = self.selected_square {
-            if selected != square {  // Only make a move if the destination is different
-                self.handle_move(selected, square);
-            }
-            self.selected_square = None;
-        } else {
-            // Select the square if it contains a piece of the current player's color
-            let has_piece = self.game.position.pieces.iter().any(|p| {
-                bit_scan(p.position) == internal_square && p.color == self.player_color
-            });
-            
-            if has_piece {
-                self.selected_square = Some(square);
-            }
+    /// The position after `line` as FEN.
+    fn fen_after(&self, line: &[Move]) -> String {
+        let mut board = Board::from_fen(&self.start_fen).unwrap_or_default();
+        for mv in line {
+            let _ = board.play(*mv);
         }
+        board.position().to_fen()
     }
 
-    fn handle_move(&mut self, from_square: usize, to_square: usize) {
-        // Validate squares are in bounds
-        if from_square >= 64 || to_square >= 64 {
-            return;
+    /// The text a copy action puts on the clipboard for the position after
+    /// `line`.
+    ///
+    /// # Returns
+    ///
+    /// * The text, or `None` for an engine line that was not found
+    fn copy_text(&self, item: CopyItem, line: &[Move]) -> Option<String> {
+        match item {
+            CopyItem::Fen => Some(self.fen_after(line)),
+            CopyItem::Pgn => Some(self.write_pgn(notation::to_san)),
+            CopyItem::BestLine => Some(self.line_to_san(line, &self.analysis_after(line)?.pv)),
+            CopyItem::EvalSummary => {
+                let analysis = self.analysis_after(line)?;
+                let source = match analysis.source {
+                    AnalysisSource::Remote => "remote",
+                    AnalysisSource::Local => "local",
+                };
+                let black = self.fen_after(line).split_whitespace().nth(1) == Some("b");
+                let side = if black { "Black" } else { "White" };
+                Some(format!("{} to move: {:+.2} ({} engine), best line {}", side,
+                    analysis.score as f32 / 100.0, source, self.line_to_san(line, &analysis.pv)))
+            }
         }
+    }
 
-        let internal_from = if self.player_color == Color::Black {
-            let rank = 7 - (from_square / 8);
-            let file = 7 - (from_square % 8);
-            rank * 8 + file
-        } else {
-            from_square
+    /// An engine line from the position after `line` in SAN with move
+    /// numbers, up to its first move that does not read as legal.
+    fn line_to_san(&self, line: &[Move], pv: &[String]) -> String {
+        let fen = self.fen_after(line);
+        let Ok(board) = Board::from_fen(&fen) else {
+            return String::new();
         };
+        let mut tree = MoveTree::new(&fen);
+        tree.add_line(&legal_prefix(board, pv));
+        tree.to_movetext(notation::to_san)
+    }
 
-        let internal_to = if self.player_color == Color::Black {
-            let rank = 7 - (to_square / 8);
-            let file = 7 - (to_square % 8);
-            rank * 8 + file
-        } else {
-            to_square
-        };
+    /// Puts the text of a copy action on the clipboard.
+    fn copy(&self, ui: &egui::Ui, item: CopyItem, line: &[Move]) {
+        if let Some(text) = self.copy_text(item, line) {
+            ui.output_mut(|output| output.copied_text = text);
+        }
+    }
 
-        let piece_index = self.game.position.pieces.iter().position(|p| {
-            bit_scan(p.position) == internal_from && p.color == self.player_color
-        });
+    fn draw_analysis(&mut self, ui: &mut egui::Ui, remote: Option<&RemoteAnalyzer>) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(20.0);
+            ui.heading("Analysis");
+            ui.add_space(10.0);
+            if let Some(entry) = self.viewed_ply.and_then(|ply| self.move_history.get(ply)) {
+                ui.label(format!("Before {}", entry.text));
+            } else if self.analyzing() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Analyzing...");
+                });
+            } else if ui.button("Analyze position").clicked() {
+                self.analyze_position(remote);
+            }
+            if let Some(line) = self.shown_analysis() {
+                let source = match line.source {
+                    AnalysisSource::Remote => "remote",
+                    AnalysisSource::Local => "local",
+                };
+                ui.label(format!("{:+.2} ({})", line.score as f32 / 100.0, source));
+                ui.label(line.pv.join(" "));
+            } else if self.viewed_ply.is_some() {
+                ui.label("Not analyzed during the game");
+            }
+            let shown = self.line[..self.viewed_ply.unwrap_or(self.line.len())].to_vec();
+            let analyzed = self.shown_analysis().is_some();
+            ui.horizontal_wrapped(|ui| {
+                if let Some(item) = draw_copy_buttons(ui, analyzed) {
+                    self.copy(ui, item, &shown);
+                }
+            });
 
-        if let Some(piece_index) = piece_index {
-            let game_copy = self.game.clone();
-            self.game.position.update_all_legal_moves(&game_copy);
-            
-            let legal_moves = self.game.position.piece_legal_moves[piece_index];
-            
-            if (legal_moves & (1u64 << internal_to)) != 0 {
-                let mov = internal_from as u64 | ((internal_to as u64) << 6);
-                
-                // Get piece type for move notation
-                let piece_type = self.game.position.pieces[piece_index].piece_type;
-                
-                // Make the move
-                self.game.position.make_move(mov);
-                
-                // Add to move history
-                let move_text = self.format_move(internal_from, internal_to, piece_type);
-                if self.player_color == Color::White {
-                    self.move_history.push(format!("{}. {}", self.move_history.len() / 2 + 1, move_text));
-                } else {
-                    self.move_history.push(format!("{}. ... {}", self.move_history.len() / 2 + 1, move_text));
-                }
-                
-                // Update evaluation
-                let eval = Evaluation::new(self.game.position.clone());
-                self.evaluation = eval.evaluate_position();
-                
-                // Check for game end conditions
-                self.game.position.update_all_legal_moves(&game_copy);
-                if self.game.position.get_all_legal_moves(&game_copy).is_empty() {
-                    if self.game.position.is_in_check(&game_copy) {
-                        println!("Checkmate! Player wins!");
-                    } else {
-                        println!("Stalemate! Game is drawn.");
+            if self.viewed_ply.is_none() {
+                if let Some((excluded, line)) = &self.what_if {
+                    let excluded: Vec<String> = excluded.iter().map(|mv| mv.to_string()).collect();
+                    ui.add_space(10.0);
+                    ui.label(format!("Without {}:", excluded.join(", ")));
+                    ui.label(format!("{:+.2}", line.score as f32 / 100.0));
+                    ui.label(line.pv.join(" "));
+                }
+                let best = self.what_if.as_ref().map(|(_, line)| line).or(self.analysis.as_ref())
+                    .map(|line| line.best_move.clone());
+                if let Some(best) = best.filter(|_| !self.analyzing()) {
+                    if ui.button(format!("What if I don't play {}?", best)).clicked() {
+                        self.analyze_without_best();
+                    }
+                }
+                let pv = self.analysis.as_ref().map(|line| line.pv.clone()).filter(|_| self.explores_variations());
+                if let Some(pv) = pv {
+                    if ui.button("Add line as variation").clicked() {
+                        self.add_variation(&pv);
                     }
-                } else {
-                    // Switch turns only if the move was successful
-                    self.is_player_turn = false;
                 }
             }
-        }
+        });
     }
 
-    fn draw_evaluation_bar(&self, ui: &mut egui::Ui) {
-        let bar_height = ui.available_height() * 0.8;
-        let bar_width = 20.0;
-        let max_eval = 1000; // Maximum evaluation in centipawns (10 pawns)
-    
-        ui.vertical(|ui| {
-            ui.add_space(20.0); // Add padding from top
-    
-            let rect = egui::Rect::from_min_size(
-                egui::pos2(ui.available_width() / 2.0 - bar_width / 2.0, 60.0), // Center horizontally
-                egui::vec2(bar_width, bar_height - 20.0), // Adjust height for better proportions
-            );
-    
-            // Background
-            ui.painter().rect_filled(rect, 4.0, egui::Color32::DARK_GRAY);
-    
-            let normalized_eval = (self.evaluation.clamp(-max_eval, max_eval) + max_eval) as f32 / (2.0 * max_eval as f32);
-            let fill_height = bar_height * normalized_eval;
-    
-            // Fill rectangle
-            let fill_rect = egui::Rect::from_min_size(
-                egui::pos2(rect.min.x, rect.max.y - fill_height),
-                egui::vec2(bar_width, fill_height),
-            );
-    
-            // Color based on advantage
-            let fill_color = if self.evaluation > 0 {
-                egui::Color32::from_rgb(100, 200, 100) // Green for white advantage
-            } else if self.evaluation < 0 {
-                egui::Color32::from_rgb(200, 100, 100) // Red for black advantage
-            } else {
-                egui::Color32::GRAY // Gray for equal
+    /// Shares the tab's engine lines with the analysis cache: lines found in
+    /// the tab are stored, and deeper ones known for its positions, from
+    /// any tab or an earlier run, are shown instead.
+    fn sync_analysis(&mut self, cache: &mut AnalysisCache) {
+        let current = self.game.position.get_hash(&self.game);
+        let positions = self.move_history.iter_mut()
+            .map(|entry| (entry.hash, &mut entry.analysis))
+            .chain(std::iter::once((current, &mut self.analysis)));
+        for (hash, analysis) in positions {
+            if let Some(line) = analysis.as_ref() {
+                cache.store(hash, line);
+            }
+            if let Some(known) = cache.get(hash).filter(|known| analysis.as_ref() != Some(*known)) {
+                *analysis = Some(known.clone());
+            }
+        }
+    }
+
+    /// Whether the move panel shows the variation tree to explore: in
+    /// analysis tabs that are not running a drill, practice or trainer.
+    fn explores_variations(&self) -> bool {
+        self.mode == TabMode::Analysis && self.drill.is_none() && self.practice.is_none() && self.trainer.is_none()
+    }
+
+    /// Adds an engine line from the current position to the variation tree,
+    /// up to its first move that does not read as legal.
+    fn add_variation(&mut self, pv: &[String]) {
+        let board = Board::from_position(self.game.position.clone());
+        let mut line = self.line.clone();
+        line.extend(legal_prefix(board, pv));
+        self.variations.add_line(&line);
+    }
+
+    /// Sets the board to the position after `line`, a line of the variation
+    /// tree. Engine lines cached for the moves it shares with the current
+    /// line are kept.
+    fn go_to(&mut self, line: &[Move]) {
+        if line == self.line {
+            return;
+        }
+        let shared = self.line.iter().zip(line).take_while(|(a, b)| a == b).count();
+        let mut cached: Vec<Option<AnalysisLine>> = self.move_history.iter_mut().map(|entry| entry.analysis.take()).collect();
+        cached.push(self.analysis.take());
+        // Going back, the new position's line was cached with the move played from it
+        let analysis = if shared == line.len() { cached[shared].take() } else { None };
+
+        let variations = std::mem::replace(&mut self.variations, MoveTree::new(&self.start_fen));
+        let player_color = self.player_color;
+        let start = ImportedGame { format: ImportFormat::Pgn, start_fen: self.start_fen.clone(), moves: line.to_vec() };
+        if let Err(e) = self.load(&start) {
+            self.notices.push(Notice::error(e));
+        }
+        self.variations = variations;
+        self.player_color = player_color;
+        for (entry, analysis) in self.move_history.iter_mut().zip(cached).take(shared) {
+            entry.analysis = analysis;
+        }
+        self.analysis = analysis;
+    }
+
+    /// Draws the variation tree in the move panel. Clicking a move goes to
+    /// the position after it; right-clicking annotates it, copies the
+    /// position after it, or offers to promote the variation or delete the
+    /// move and what follows.
+    fn draw_variations(&mut self, ui: &mut egui::Ui) {
+        let items = self.variations.items(notation::to_san);
+        let mut go_to = None;
+        let mut promote = None;
+        let mut delete = None;
+        ui.horizontal_wrapped(|ui| {
+            for item in items {
+                match item {
+                    MovetextItem::Number(number) => {
+                        ui.label(egui::RichText::new(number).weak());
+                    }
+                    MovetextItem::Move { line, text } => {
+                        let response = ui.selectable_label(line == self.line, text);
+                        if response.clicked() {
+                            go_to = Some(line.clone());
+                        }
+                        response.context_menu(|ui| {
+                            if let Some(node) = self.variations.node_mut(&line) {
+                                draw_annotation_menu(ui, node);
+                                ui.separator();
+                            }
+                            if let Some(item) = draw_copy_buttons(ui, self.analysis_after(&line).is_some()) {
+                                self.copy(ui, item, &line);
+                            }
+                            ui.separator();
+                            if ui.button("Promote variation").clicked() {
+                                promote = Some(line.clone());
+                                ui.close_menu();
+                            }
+                            if ui.button("Delete from here").clicked() {
+                                delete = Some(line.clone());
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                    MovetextItem::Comment(comment) => {
+                        ui.label(egui::RichText::new(comment).italics());
+                    }
+                    MovetextItem::Open => {
+                        ui.label("(");
+                    }
+                    MovetextItem::Close => {
+                        ui.label(")");
+                    }
+                }
+            }
+        });
+        if let Some(line) = go_to {
+            self.go_to(&line);
+        }
+        if let Some(line) = promote {
+            self.variations.promote(&line);
+        }
+        if let Some(line) = delete {
+            self.delete_variation(&line);
+        }
+    }
+
+    /// Deletes the last move of `line` and everything after it from the
+    /// variation tree, first stepping back if the board is past it.
+    fn delete_variation(&mut self, line: &[Move]) {
+        if self.line.starts_with(line) {
+            self.go_to(&line[..line.len() - 1]);
+        }
+        self.variations.remove(line);
+    }
+
+    /// Re-evaluates the game result and check state after a move by either
+    /// side and announces the result when the game has just ended.
+    ///
+    /// Drawing reads the stored state, so nothing is regenerated per frame.
+    fn update_result(&mut self) {
+        let game_copy = self.game.clone();
+        self.game.position.update_all_legal_moves(&game_copy);
+        self.result = GameResult::of(&self.game);
+        self.in_check = self.game.position.is_in_check(&self.game);
+        if self.result.is_over() {
+            if let Some(clocks) = &mut self.clocks {
+                clocks.stop(Instant::now());
+            }
+            self.notices.push(Notice::info(self.status()));
+        }
+    }
+
+    /// Ends the game if the side to move has run out of time at `now`: a
+    /// loss, or a draw if the other side has nothing left to mate with.
+    fn check_flag(&mut self, now: Instant) {
+        if self.result.is_over() {
+            return;
+        }
+        let Some(flagged) = self.clocks.as_ref().and_then(|clocks| clocks.flagged(now)) else {
+            return;
+        };
+        self.result = match time_manager::adjudicate_flag(&self.game.position, flagged) {
+            FlagResult::Win(winner) => GameResult::OnTime { winner },
+            FlagResult::Draw => GameResult::TimeoutDraw,
+        };
+        // The engine's move would come too late
+        self.stop.store(true, Ordering::Relaxed);
+        self.engine_move = None;
+        if let Some(clocks) = &mut self.clocks {
+            clocks.stop(now);
+        }
+        self.notices.push(Notice::info(self.status()));
+    }
+
+    /// Whether either side's clock is below `LOW_TIME_WARNING` at `now`.
+    fn low_on_time(&self, now: Instant) -> bool {
+        self.clocks.as_ref().is_some_and(|clocks| {
+            [Color::White, Color::Black].into_iter().any(|color| clocks.remaining(color, now) < LOW_TIME_WARNING)
+        })
+    }
+
+    /// Starts searching for the engine's move on a background thread, if it
+    /// is the engine's turn; `poll_engine_move` plays the move once found.
+    fn make_engine_move(&mut self) {
+        if self.is_player_turn || self.engine_thinking() || self.result.is_over() {
+            return;
+        }
+
+        // Verify it's actually the engine's turn based on colors
+        if self.mode == TabMode::VsEngine && self.game.position.active_color == self.player_color {
+            return;
+        }
+
+        // A ponder search on the move the user played becomes the real one
+        if let Some(ponder) = self.ponder.take() {
+            ponder.flag.store(false, Ordering::Relaxed);
+            if self.played_moves.last() == Some(&ponder.reply) {
+                self.engine_move = Some(ponder.receiver);
+                return;
+            }
+            self.stop.store(true, Ordering::Relaxed);
+        }
+
+        // Update legal moves before searching
+        let game_copy = self.game.clone();
+        self.game.position.update_all_legal_moves(&game_copy);
+
+        // Choose the move on a background thread
+        let position = self.game.position.clone();
+        self.engine_move = Some(self.spawn_search(position));
+    }
+
+    /// Starts the engine thinking about the user's reply on the user's time,
+    /// if pondering is on and the engine's last search expected a reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `analysis` - The analysis of the engine's last move
+    fn start_pondering(&mut self, analysis: Option<&Analysis>) {
+        if !self.pondering || self.mode != TabMode::VsEngine || self.result.is_over() {
+            return;
+        }
+        let Some(reply) = analysis.and_then(|analysis| analysis.pv.get(1)).map(|mv| mv.to_u64()) else {
+            return;
+        };
+        let Some(flag) = self.player.lock().unwrap().ponder_flag() else {
+            return;
+        };
+        let game_copy = self.game.clone();
+        let mut position = self.game.position.clone();
+        position.update_all_legal_moves(&game_copy);
+        if !position.get_all_legal_moves(&game_copy).contains(&reply) {
+            return;
+        }
+        position.make_move(reply);
+        position.update_all_legal_moves(&game_copy);
+
+        flag.store(true, Ordering::Relaxed);
+        let receiver = self.spawn_search(position);
+        self.ponder = Some(Ponder { reply, flag, receiver });
+    }
+
+    /// Ends pondering, if the engine is, without using its search.
+    fn stop_pondering(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            ponder.flag.store(false, Ordering::Relaxed);
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Chooses the engine's move in `position` on a background thread.
+    ///
+    /// # Returns
+    ///
+    /// * Where the thread sends the move once found
+    fn spawn_search(&mut self, position: Position) -> mpsc::Receiver<EngineReply> {
+        let player = Arc::clone(&self.player);
+        let mut limits = self.limits.clone();
+        limits.clock = self.clocks.map(|clocks| clocks.clock(position.active_color, Instant::now()));
+        let stop = Arc::clone(&self.stop);
+        let hash_mb = self.pending_hash_mb.take();
+        // The memory budget decides the hash size
+        let options = EngineOptions { hash_mb: self.hash_mb, ..self.engine_options };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let (mov, analysis, elapsed) = {
+                let mut player = player.lock().unwrap();
+                if let Some(hash_mb) = hash_mb {
+                    let _ = player.set_hash_size(hash_mb);
+                }
+                player.set_options(&options);
+                // A search abandoned by `new_game` may have left the flag set
+                stop.store(false, Ordering::Relaxed);
+                let (mov, analysis) = player.choose_move_analyzed(&position, &limits);
+                (mov, analysis, started.elapsed())
             };
-    
-            ui.painter().rect_filled(fill_rect, 4.0, fill_color);
-    
-            // Draw evaluation text
-            let eval_text = format!("{:+.1}", self.evaluation as f32 / 100.0);
-            ui.label(egui::RichText::new(eval_text).size(16.0).strong());
+            // Wait without holding the player, so a new game can start searching
+            limits.wait_for_min_time(started);
+            let _ = sender.send((mov.map(|mov| mov.to_u64()), analysis, elapsed));
         });
+        receiver
     }
-    
 
-    fn draw_board(&mut self, ui: &mut egui::Ui) {
-        let board_size = ui.available_width().min(ui.available_height()) - 40.0;
-        let square_size = board_size / 8.0;
+    /// Plays the engine's move if its search has finished.
+    fn poll_engine_move(&mut self) {
+        let Some(receiver) = &self.engine_move else {
+            return;
+        };
+        let (best_move, analysis, elapsed) = match receiver.try_recv() {
+            Ok(found) => found,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.notices.push(Notice::error("The engine stopped without a move"));
+                (None, None, Duration::ZERO)
+            }
+        };
+        self.engine_move = None;
 
-        let board_rect = egui::Rect::from_min_size(
-            ui.cursor().min,
-            egui::vec2(board_size, board_size),
-        );
-        let board_response = ui.allocate_rect(board_rect, egui::Sense::click_and_drag());
+        if let Some(best_move) = best_move {
+            // Kept with the move, so going back to it needs no new search
+            if let Some(analysis) = &analysis {
+                let line = AnalysisLine::local(analysis);
+                self.search_log.record("engine move", &self.game.position.to_fen(), &line, Some(analysis.nodes), Some(elapsed));
+                self.analysis = Some(line);
+            }
+            self.play_move(best_move);
+            
+            // In an engine match the engine keeps the move
+            self.is_player_turn = self.mode == TabMode::VsEngine;
+        }
 
-        // Handle mouse interactions
-        if let Some(pointer_pos) = board_response.hover_pos() {
-            let file = ((pointer_pos.x - board_rect.min.x) / square_size).floor() as isize;
-            let rank = 7 - ((pointer_pos.y - board_rect.min.y) / square_size).floor() as isize;
+        // The engine may just have mated or stalemated the player
+        self.update_result();
+        if best_move.is_some() {
+            self.start_pondering(analysis.as_ref());
+        }
+    }
+
+    /// Plays a move for whichever side is to move, records it with the
+    /// analysis of the position it was played from and starts animating what
+    /// it changed on the board.
+    ///
+    /// # Returns
+    ///
+    /// * The board events of the move
+    fn play_move(&mut self, mov: u64) -> Vec<BoardEvent> {
+        let mover = self.game.position.active_color;
+        let before = self.game.position.clone();
+        let move_text = notation::san_of(&before, &self.game, mov);
+        let spoken = notation::speech_of(&before, &self.game, mov);
+        if let Some(clocks) = &mut self.clocks {
+            clocks.press(mover, Instant::now());
+        }
+
+        // Make the move
+        self.game.position.make_move(mov);
+        self.played_moves.push(mov);
+        self.line.push(Move::from_u64(mov, &before));
+        self.variations.add_line(&self.line);
+        let events = BoardEvent::between(&before, &self.game.position, &self.game);
+        self.animator.start(&events, Instant::now());
+
+        // Add to move history
+        let text = if mover == Color::White {
+            format!("{}. {}", self.move_history.len() / 2 + 1, move_text)
+        } else {
+            format!("{}. ... {}", self.move_history.len() / 2 + 1, move_text)
+        };
+        let hash = before.get_hash(&self.game);
+        self.move_history.push(HistoryEntry { text, spoken, analysis: self.analysis.take(), hash });
+        self.what_if = None;
+        self.viewed_ply = None;
+
+        // Update evaluation
+        let eval = Evaluation::new(self.game.position.clone());
+        self.evaluation = eval.evaluate_position();
+        events
+    }
+
+    fn handle_square_click(&mut self, square: usize, pointer_pos: Option<egui::Pos2>) {
+        // Validate square is in bounds
+        if square >= 64 {
+            return;
+        }
+        self.keyboard_feedback = None;
+
+        if self.result.is_over() {
+            return;
+        }
+
+        // Verify it's the player's turn based on colors
+        let mover = self.mover();
+        if !self.is_player_turn || mover != self.game.position.active_color {
+            if pointer_pos.is_some() {
+                self.rejection = Some("Not your turn: the engine is thinking".to_string());
+            }
+            return;
+        }
+
+        if let Some(pos) = pointer_pos {
+            // Start dragging
+            let has_piece = self.game.position.pieces.iter().any(|p| {
+                bit_scan(p.position) == square && p.color == mover
+            });
             
-            if file >= 0 && file < 8 && rank >= 0 && rank < 8 {
-                let square = (rank * 8 + file) as usize;
-                
-                if board_response.clicked() {
-                    self.handle_square_click(square, Some(pointer_pos));
-                } else if board_response.drag_released() {
-                    self.handle_square_click(square, None);
+            if has_piece {
+                self.dragging_piece = Some((square, pos));
+                self.selected_square = Some(square);
+            }
+            return;
+        }
+
+        // Handle piece drop or regular click
+        if let Some((selected, _)) = self.dragging_piece.take() {
+            if selected != square {  // Only make a move if the destination is different
+                self.handle_move(selected, square);
+            }
+            self.selected_square = None;
+        } else if let Some(selected) = self.selected_square {
+            if selected != square {  // Only make a move if the destination is different
+                self.handle_move(selected, square);
+            }
+            self.selected_square = None;
+        } else {
+            // Select the square if it contains a piece of the current player's color
+            let has_piece = self.game.position.pieces.iter().any(|p| {
+                bit_scan(p.position) == square && p.color == mover
+            });
+            
+            if has_piece {
+                self.selected_square = Some(square);
+            }
+        }
+    }
+
+    /// Reads squares typed as coordinates, so the board can be played
+    /// without a mouse: a piece is picked with its square ("e2") and moved
+    /// with its destination ("e4"). Other characters are ignored.
+    fn handle_typed_text(&mut self, text: &str) {
+        for c in text.chars().map(|c| c.to_ascii_lowercase()) {
+            match (self.typed_file, c) {
+                (_, 'a'..='h') => self.typed_file = Some(c),
+                (Some(file), '1'..='8') => {
+                    self.typed_file = None;
+                    let square = (c as u8 - b'1') as usize * 8 + (file as u8 - b'a') as usize;
+                    self.handle_typed_square(square);
                 }
+                _ => {}
             }
         }
+    }
 
-        // Draw the board
-        for rank in 0..8 {
-            for file in 0..8 {
-                // Adjust rank and file based on player color
-                let (display_rank, display_file) = if self.player_color == Color::White {
-                    (rank, file)
-                } else {
-                    (7 - rank, 7 - file)
-                };
+    /// Selects or moves to a square typed on the keyboard, and says what
+    /// happened in `keyboard_feedback`: the piece picked and where it can
+    /// go, or the move played.
+    fn handle_typed_square(&mut self, square: usize) {
+        if self.result.is_over() {
+            self.keyboard_feedback = Some("The game is over".to_string());
+            return;
+        }
+        let mover = self.mover();
+        if !self.is_player_turn || mover != self.game.position.active_color {
+            self.keyboard_feedback = Some("Not your turn: the engine is thinking".to_string());
+            return;
+        }
 
-                let square = if self.player_color == Color::White {
-                    rank * 8 + file
-                } else {
-                    (7 - rank) * 8 + (7 - file)
-                };
+        let own_piece = self.game.position.pieces.iter().any(|p| bit_scan(p.position) == square && p.color == mover);
+        match self.selected_square {
+            Some(selected) if selected == square => {
+                self.selected_square = None;
+                self.keyboard_feedback = Some(format!("{} put back", square_name(square)));
+            }
+            Some(selected) if !own_piece => {
+                let (before, played) = (self.game.clone(), self.played_moves.len());
+                self.handle_move(selected, square);
+                self.selected_square = None;
+                // A refused move is explained by `rejection`
+                self.keyboard_feedback = self.played_moves.get(played)
+                    .map(|&mov| format!("Played {}", notation::san_of(&before.position, &before, mov)));
+            }
+            _ if own_piece => {
+                self.selected_square = Some(square);
+                self.keyboard_feedback = Some(self.describe_piece(square));
+            }
+            _ => {
+                let side = if mover == Color::White { "white" } else { "black" };
+                self.keyboard_feedback = Some(format!("No {} piece on {}", side, square_name(square)));
+            }
+        }
+    }
+
+    /// Names the piece on a square and the squares it can move to, as in
+    /// "White knight on g1: f3, h3".
+    fn describe_piece(&mut self, square: usize) -> String {
+        let Some(piece_index) = self.game.position.pieces.iter().position(|p| bit_scan(p.position) == square) else {
+            return format!("{} is empty", square_name(square));
+        };
+        let game_copy = self.game.clone();
+        self.game.position.update_all_legal_moves(&game_copy);
+        let piece = &self.game.position.pieces[piece_index];
+        let side = if piece.color == Color::White { "White" } else { "Black" };
+        let name = format!("{} {} on {}", side, piece_name(piece.piece_type), square_name(square));
+        let destinations: Vec<String> = (0..64)
+            .filter(|to| self.game.position.piece_legal_moves[piece_index] & (1u64 << to) != 0)
+            .map(square_name)
+            .collect();
+        if destinations.is_empty() {
+            format!("{} has no legal moves", name)
+        } else {
+            format!("{}: {}", name, destinations.join(", "))
+        }
+    }
+
+    fn handle_move(&mut self, from_square: usize, to_square: usize) {
+        // Validate squares are in bounds
+        if from_square >= 64 || to_square >= 64 {
+            return;
+        }
+
+        let mover = self.mover();
+        let piece_index = self.game.position.pieces.iter().position(|p| {
+            bit_scan(p.position) == from_square && p.color == mover
+        });
+
+        if let Some(piece_index) = piece_index {
+            let game_copy = self.game.clone();
+            self.game.position.update_all_legal_moves(&game_copy);
+            
+            let legal_moves = self.game.position.piece_legal_moves[piece_index];
+            
+            if (legal_moves & (1u64 << to_square)) != 0 {
+                let piece_type = self.game.position.pieces[piece_index].piece_type;
 
-                let is_light = (rank + file) % 2 == 0;
-                let rect = egui::Rect::from_min_size(
-                    egui::pos2(
-                        board_rect.min.x + file as f32 * square_size,
-                        board_rect.min.y + (7 - rank) as f32 * square_size,
-                    ),
-                    egui::vec2(square_size, square_size),
-                );
-
-                // Square color
-                let color = if Some(square) == self.selected_square {
-                    egui::Color32::from_rgb(255, 255, 0) // Bright yellow for selected
-                } else if is_light {
-                    egui::Color32::from_rgb(240, 217, 181) // Light squares
+                // Pawns reaching the last rank always promote to a queen
+                let promotion = if piece_type == PieceType::Pawn && (to_square / 8 == 7 || to_square / 8 == 0) {
+                    Some(PieceType::Queen)
                 } else {
-                    egui::Color32::from_rgb(181, 136, 99) // Dark squares
+                    None
                 };
+                self.finish_user_move(Move::encode(from_square, to_square, promotion));
+            } else {
+                self.rejection = Some(self.game.position.explain_illegal_move(&self.game, from_square, to_square));
+            }
+        }
+    }
+
+    /// Plays a legal move made by the user and hands the turn to the engine.
+    ///
+    /// In a drill tab only moves that keep the mate on schedule are played,
+    /// each answered at once by the defender, and in a trainer tab only
+    /// repertoire moves, answered from the repertoire.
+    fn finish_user_move(&mut self, mov: u64) {
+        if self.trainer.is_some() {
+            self.quiz_move(mov);
+            return;
+        }
+        match self.drill.as_mut().map(|drill| drill.play(mov)) {
+            Some(DrillStep::Wrong) => {
+                let moves_left = self.drill.as_ref().map_or(0, MateDrill::moves_left);
+                self.rejection = Some(format!("That does not force mate in {}. Try again", moves_left));
+                return;
+            }
+            Some(DrillStep::Reply(reply)) => {
+                self.play_move(mov);
+                self.play_move(reply);
+            }
+            Some(DrillStep::Solved) => {
+                self.play_move(mov);
+                self.drill_result = self.drill.as_ref().map(|drill| !drill.missed());
+            }
+            None => {
+                self.play_move(mov);
+                if self.practice.is_some() {
+                    self.defend_practice();
+                }
+            }
+        }
+        self.rejection = None;
+
+        // Check for game end conditions
+        self.update_result();
+        if !self.result.is_over() && self.mode != TabMode::Analysis {
+            // Switch turns only if the game goes on
+            self.is_player_turn = false;
+        }
+    }
+
+    /// Checks the user's move in a trainer tab against the repertoire.
+    fn quiz_move(&mut self, mov: u64) {
+        match self.trainer.as_mut().map(|trainer| trainer.play(mov)) {
+            Some(TrainerStep::Wrong) => {
+                self.rejection = Some("That is not in your repertoire. Try again".to_string());
+                return;
+            }
+            Some(TrainerStep::Reply(reply)) => {
+                self.play_move(mov);
+                self.play_move(reply);
+            }
+            Some(TrainerStep::Finished) => {
+                self.play_move(mov);
+                self.trainer_result = self.trainer.as_ref().map(|trainer| (trainer.line().to_vec(), !trainer.missed()));
+            }
+            None => return,
+        }
+        self.rejection = None;
+        self.update_result();
+    }
+
+    /// Sets a trainer tab back to the start of the repertoire for the next
+    /// line to quiz, and plays the opponent's first move if it has one.
+    fn next_line(&mut self, stats: &RecallStats) {
+        let Some(trainer) = &mut self.trainer else {
+            return;
+        };
+        let opening = trainer.next_line(stats);
+        let side = trainer.side;
+        let start = ImportedGame { format: ImportFormat::Fen, start_fen: trainer.start_fen.clone(), moves: Vec::new() };
+        if self.load(&start).is_ok() {
+            self.player_color = side;
+            if let Some(mov) = opening {
+                self.play_move(mov);
+                self.animator.clear();
+                self.update_result();
+            }
+        }
+    }
+
+    /// Draws the trainer's progress and the line's record in the top panel.
+    fn draw_trainer(&mut self, ui: &mut egui::Ui, stats: &RecallStats) {
+        let Some(trainer) = &mut self.trainer else {
+            return;
+        };
+        ui.add_space(20.0);
+        if trainer.is_finished() {
+            let text = if trainer.missed() { "Line complete, with help" } else { "Line recalled" };
+            ui.label(egui::RichText::new(text).strong());
+            if ui.button("Next line").clicked() {
+                self.next_line(stats);
+                return;
+            }
+        } else {
+            ui.label(egui::RichText::new("Play your repertoire move").strong());
+            if ui.button("Show move").clicked() {
+                if let Some(mov) = trainer.hint() {
+                    self.finish_user_move(mov);
+                }
+                return;
+            }
+        }
+        let record = stats.get(trainer.line());
+        let totals = stats.totals();
+        ui.label(format!(
+            "This line: {} recalled, {} missed; all {} lines: {} recalled, {} missed",
+            record.recalled, record.missed, trainer.line_count(), totals.recalled, totals.missed,
+        ));
+    }
+
+    /// Counts the user's move in an endgame practice and answers it with
+    /// the tablebase's best defence.
+    fn defend_practice(&mut self) {
+        if let Some(practice) = &mut self.practice {
+            practice.moves += 1;
+        }
+        if let Some(reply) = tablebase::tablebases().best_move(&self.game.position, &self.game) {
+            self.play_move(reply);
+        }
+    }
+
+    /// Draws how the user's moves in an endgame practice compare with
+    /// perfect play.
+    fn draw_practice(&self, ui: &mut egui::Ui) {
+        let Some(practice) = &self.practice else {
+            return;
+        };
+        ui.add_space(20.0);
+        let verdict = tablebase::tablebases().probe(&self.game.position);
+        let text = match (self.result, verdict) {
+            (GameResult::Checkmate { .. }, _) => {
+                format!("Mate in {} moves; perfect play needs {}", practice.moves, practice.optimal_moves)
+            }
+            (_, Some(verdict @ Verdict::Win(_))) => format!(
+                "{}: {} moves played, mate in {} more; perfect play needs {}",
+                practice.ending.name(), practice.moves, verdict.moves_to_mate().unwrap_or(0), practice.optimal_moves,
+            ),
+            _ => "The win is gone: the position is a draw".to_string(),
+        };
+        ui.label(egui::RichText::new(text).strong());
+    }
+
+    /// Plays the next move of the mate for a stuck user; it counts as a miss.
+    fn play_hint(&mut self) {
+        if let Some(mov) = self.drill.as_mut().and_then(MateDrill::hint) {
+            self.finish_user_move(mov);
+        }
+    }
+
+    /// Draws the drill's task and the user's record in the top panel.
+    fn draw_drill(&mut self, ui: &mut egui::Ui, stats: &DrillStats) {
+        let Some(drill) = &self.drill else {
+            return;
+        };
+        ui.add_space(20.0);
+        let attacker = if drill.attacker == Color::White { "White" } else { "Black" };
+        if drill.is_solved() {
+            ui.label(egui::RichText::new(format!("Solved: {} mates in {}", attacker, drill.moves)).strong());
+        } else {
+            ui.label(egui::RichText::new(format!("{} to mate in {}", attacker, drill.moves)).strong());
+            if ui.button("Show move").clicked() {
+                self.play_hint();
+            }
+        }
+        ui.label(format!("Streak {} (best {}), solved {}", stats.streak, stats.best_streak, stats.solved));
+    }
+
+    /// Book moves for the current position that are legal in it, most
+    /// heavily weighted first.
+    fn book_moves(&self, book: &dyn OpeningBook) -> Vec<BookMove> {
+        let hash = self.game.position.get_hash(&self.game);
+        let legal = self.game.position.get_all_legal_moves(&self.game);
+        let mut moves: Vec<BookMove> = book.moves(&self.game.position, hash).into_iter()
+            .filter(|entry| legal.contains(&entry.mov))
+            .collect();
+        moves.sort_by_key(|entry| std::cmp::Reverse(entry.weight));
+        moves
+    }
+
+    /// Plays the engine's move from the opening book, for the `OwnBook`
+    /// option, if it is the engine's turn and the position is in the book.
+    fn play_engine_book_move(&mut self, book: &dyn OpeningBook) {
+        if self.is_player_turn || self.engine_thinking() || self.result.is_over() {
+            return;
+        }
+        if self.mode == TabMode::VsEngine && self.game.position.active_color == self.player_color {
+            return;
+        }
+        let Some(entry) = self.book_moves(book).into_iter().find(|entry| entry.weight > 0) else {
+            return;
+        };
+        self.stop_pondering();
+        self.play_move(entry.mov);
+        self.is_player_turn = self.mode == TabMode::VsEngine;
+        self.update_result();
+    }
+
+    /// Plays a move picked in the opening explorer, if it is the user's turn.
+    fn play_book_move(&mut self, mov: u64) {
+        if self.result.is_over() {
+            return;
+        }
+        if !self.is_player_turn || self.mover() != self.game.position.active_color {
+            self.rejection = Some("Not your turn: the engine is thinking".to_string());
+            return;
+        }
+        if self.game.position.get_all_legal_moves(&self.game).contains(&mov) {
+            self.finish_user_move(mov);
+        }
+    }
+
+    /// The opening explorer: the book's moves for the current position with
+    /// their weights and scores. Clicking a move plays it.
+    fn draw_explorer(&mut self, ui: &mut egui::Ui, book: Option<&dyn OpeningBook>) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(20.0);
+            ui.heading("Opening Explorer");
+            ui.add_space(10.0);
+            let Some(book) = book else {
+                ui.label("No book found; see Settings > Assets");
+                return;
+            };
+            let moves = self.book_moves(book);
+            if moves.is_empty() {
+                ui.label("Out of book");
+            }
+            egui::Grid::new("explorer").striped(true).show(ui, |ui| {
+                ui.label("Move");
+                ui.label("Weight");
+                ui.label("Score");
+                ui.end_row();
+                for entry in moves {
+                    let text = notation::san_of(&self.game.position, &self.game, entry.mov);
+                    if ui.button(text).clicked() {
+                        self.play_book_move(entry.mov);
+                    }
+                    ui.label(entry.weight.to_string());
+                    ui.label(entry.score().map_or("-".to_string(),
+                        |score| format!("{:.0}% ({})", score * 100.0, entry.games())));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn draw_evaluation_bar(&self, ui: &mut egui::Ui) {
+        let bar_height = ui.available_height() * 0.8;
+        let bar_width = 20.0;
+        let max_eval = 1000; // Maximum evaluation in centipawns (10 pawns)
+    
+        ui.vertical(|ui| {
+            ui.add_space(20.0); // Add padding from top
+    
+            let rect = egui::Rect::from_min_size(
+                egui::pos2(ui.available_width() / 2.0 - bar_width / 2.0, 60.0), // Center horizontally
+                egui::vec2(bar_width, bar_height - 20.0), // Adjust height for better proportions
+            );
+    
+            // Background
+            ui.painter().rect_filled(rect, 4.0, egui::Color32::DARK_GRAY);
+    
+            let normalized_eval = (self.evaluation.clamp(-max_eval, max_eval) + max_eval) as f32 / (2.0 * max_eval as f32);
+            let fill_height = bar_height * normalized_eval;
+    
+            // Fill rectangle
+            let fill_rect = egui::Rect::from_min_size(
+                egui::pos2(rect.min.x, rect.max.y - fill_height),
+                egui::vec2(bar_width, fill_height),
+            );
+    
+            // Color based on advantage
+            let fill_color = if self.evaluation > 0 {
+                egui::Color32::from_rgb(100, 200, 100) // Green for white advantage
+            } else if self.evaluation < 0 {
+                egui::Color32::from_rgb(200, 100, 100) // Red for black advantage
+            } else {
+                egui::Color32::GRAY // Gray for equal
+            };
+    
+            ui.painter().rect_filled(fill_rect, 4.0, fill_color);
+    
+            // Draw evaluation text
+            let eval_text = format!("{:+.1}", self.evaluation as f32 / 100.0);
+            ui.label(egui::RichText::new(eval_text).size(16.0).strong());
+        });
+    }
+    
+
+    /// Gives screen readers the board as a grid named after the whole
+    /// position, with one cell per square naming the piece on it.
+    fn describe_board_to_screen_readers(&self, ctx: &egui::Context, board_id: egui::Id, board_rect: egui::Rect) {
+        let described = ctx.accesskit_node_builder(board_id, |builder| {
+            let board = Board::with_tables(self.game.position.clone(), &self.game);
+            builder.set_role(accesskit::Role::Grid);
+            builder.set_name(format!("Chessboard. {}", notation::describe_board(&board)));
+        });
+        if described.is_none() {
+            return;
+        }
+        ctx.with_accessibility_parent(board_id, || {
+            for square in 0..64 {
+                let rect = square_rect(board_rect, square, self.player_color);
+                let piece = self.game.position.pieces.iter().find(|p| bit_scan(p.position) == square);
+                ctx.accesskit_node_builder(board_id.with(square), |builder| {
+                    builder.set_role(accesskit::Role::Cell);
+                    builder.set_name(match piece {
+                        Some(piece) => format!("{}, {} {}", square_name(square),
+                            if piece.color == Color::White { "white" } else { "black" }, piece_name(piece.piece_type)),
+                        None => square_name(square),
+                    });
+                    builder.set_bounds(accesskit::Rect {
+                        x0: rect.min.x.into(),
+                        y0: rect.min.y.into(),
+                        x1: rect.max.x.into(),
+                        y1: rect.max.y.into(),
+                    });
+                    if Some(square) == self.selected_square {
+                        builder.set_selected(true);
+                    }
+                });
+            }
+        });
+    }
+
+    fn draw_board(&mut self, ui: &mut egui::Ui) {
+        let board_size = ui.available_width().min(ui.available_height()) - 40.0;
+        let square_size = board_size / 8.0;
+        let orientation = self.player_color;
+
+        let board_rect = egui::Rect::from_min_size(
+            ui.cursor().min,
+            egui::vec2(board_size, board_size),
+        );
+        let board_response = ui.allocate_rect(board_rect, egui::Sense::click_and_drag());
+        self.describe_board_to_screen_readers(ui.ctx(), board_response.id, board_rect);
+        let now = Instant::now();
+        self.animator.tick(now);
+
+        // Handle mouse interactions
+        if let Some(pointer_pos) = board_response.hover_pos() {
+            if let Some(square) = square_at(board_rect, pointer_pos, orientation) {
+                if board_response.clicked() {
+                    self.handle_square_click(square, Some(pointer_pos));
+                } else if board_response.drag_released() {
+                    self.handle_square_click(square, None);
+                }
+            }
+        }
+
+        // Draw the board
+        let last_move = self.played_moves.last().copied();
+        let piece_at = |square: usize| self.game.position.pieces.iter().find(|p| bit_scan(p.position) == square);
+        for square in 0..64 {
+            let rect = square_rect(board_rect, square, orientation);
+
+            // The king of the side to move is marked while in check
+            let king_to_move = piece_at(square).is_some_and(|piece| {
+                piece.piece_type == PieceType::King && piece.color == self.game.position.active_color
+            });
+            let [r, g, b] = render::square_color(square, last_move);
+            let color = if self.animator.is_flashing(square, now) {
+                egui::Color32::from_rgb(255, 140, 0) // Orange flash for the checking piece
+            } else if king_to_move && matches!(self.result, GameResult::Checkmate { .. }) {
+                egui::Color32::from_rgb(255, 0, 0) // Red for checkmate
+            } else if (king_to_move && self.in_check) || Some(square) == self.selected_square {
+                egui::Color32::from_rgb(255, 255, 0) // Yellow for check and the selected square
+            } else {
+                egui::Color32::from_rgb(r, g, b)
+            };
+            ui.painter().rect_filled(rect, 0.0, color);
+
+            // Rank numbers down the left edge and file letters along the
+            // bottom, in the colour of the other squares
+            let (row, col) = render::square_cell(square, orientation);
+            let [r, g, b] = if render::is_light_square(square) { DARK_SQUARE } else { LIGHT_SQUARE };
+            let label_font = egui::FontId::proportional(square_size * 0.2);
+            if col == 0 {
+                ui.painter().text(rect.left_top() + egui::vec2(2.0, 1.0), egui::Align2::LEFT_TOP,
+                    (square / 8 + 1).to_string(), label_font.clone(), egui::Color32::from_rgb(r, g, b));
+            }
+            if row == 7 {
+                ui.painter().text(rect.right_bottom() - egui::vec2(2.0, 1.0), egui::Align2::RIGHT_BOTTOM,
+                    ((b'a' + (square % 8) as u8) as char).to_string(), label_font, egui::Color32::from_rgb(r, g, b));
+            }
+
+            // Fade out pieces captured on this square
+            for (event, t) in self.animator.progress(now) {
+                if let BoardEvent::PieceCaptured { square: captured, piece, color } = event {
+                    if captured == square {
+                        draw_glyph(ui.painter(), rect.center(), piece, color, square_size, 1.0 - t);
+                    }
+                }
+            }
+
+            // Draw piece if present, unless it is still sliding here
+            if self.animator.is_arriving(square) {
+                continue;
+            }
+            if let Some(piece) = piece_at(square) {
+                draw_glyph(ui.painter(), rect.center(), piece.piece_type, piece.color, square_size, 1.0);
+            }
+        }
+
+        // Slide moving pieces over the board
+        let center = |square: usize| square_rect(board_rect, square, orientation).center();
+        for (event, t) in self.animator.progress(now) {
+            if let BoardEvent::PieceMoved { from, to, piece, color } = event {
+                let eased = t * t * (3.0 - 2.0 * t);
+                let pos = center(from).lerp(center(to), eased);
+                draw_glyph(ui.painter(), pos, piece, color, square_size, 1.0);
+            }
+        }
+
+        // Draw dragged piece if any
+        if let Some((square, pos)) = self.dragging_piece {
+            if let Some(piece) = piece_at(square) {
+                draw_glyph(ui.painter(), pos, piece.piece_type, piece.color, square_size, 1.0);
+            }
+        }
+    }
+
+    /// Settings for how quickly the engine answers in this tab.
+    fn draw_engine_settings(&mut self, ui: &mut egui::Ui) {
+        let mut min_ms = self.limits.min_time.map_or(0, |min_time| min_time.as_millis() as u64);
+        ui.add_enabled(!self.limits.instant,
+            egui::Slider::new(&mut min_ms, 0..=MAX_MIN_THINK_MS).text("Minimum think time (ms)"));
+        self.limits.min_time = (min_ms > 0).then(|| Duration::from_millis(min_ms));
+        ui.checkbox(&mut self.limits.instant, "Move instantly (for testing)");
+        let name = |control: Option<(Duration, Duration)>| control.map_or("No clock".to_string(),
+            |(base, increment)| format!("{}+{}", base.as_secs() / 60, increment.as_secs()));
+        if ui.checkbox(&mut self.pondering, "Think on your time (ponder)").changed() && !self.pondering {
+            self.stop_pondering();
+        }
+        egui::ComboBox::from_label("Clock (from the next game)")
+            .selected_text(name(self.time_control))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.time_control, None, name(None));
+                for (base, increment) in TIME_CONTROLS {
+                    let control = Some((Duration::from_secs(base), Duration::from_secs(increment)));
+                    ui.selectable_value(&mut self.time_control, control, name(control));
+                }
+            });
+    }
+
+    /// Whether the game has moves and is still being played, so quitting
+    /// would lose it. Engine matches are never worth keeping.
+    fn is_unfinished(&self) -> bool {
+        self.mode != TabMode::EngineMatch && !self.played_moves.is_empty() && !self.result.is_over()
+    }
+
+    /// The game as PGN with its variations and comments, with moves in long
+    /// algebraic notation so that pasting it back in restores it.
+    fn to_pgn(&self) -> String {
+        self.write_pgn(|_, mv| mv.to_string())
+    }
+
+    /// The game as PGN with its variations and comments, with moves written
+    /// by `write`.
+    fn write_pgn(&self, write: impl Fn(&Board, Move) -> String) -> String {
+        let mut pgn = format!("[Event \"{}\"]\n", self.title);
+        if self.start_fen != START_FEN {
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", self.start_fen));
+        }
+        pgn.push_str("[Result \"*\"]\n\n");
+        let movetext = self.variations.to_movetext(write);
+        if !movetext.is_empty() {
+            pgn.push_str(&movetext);
+            pgn.push(' ');
+        }
+        pgn.push_str("*\n");
+        pgn
+    }
+
+    /// What a bug report needs to reproduce the tab's game: the position,
+    /// the moves, the engine settings and the recent searches.
+    fn debug_bundle(&self) -> DebugBundle {
+        let opponent = self.opponent.map_or("Engine".to_string(), |opponent| opponent.to_string());
+        let millis = |time: Option<Duration>| time.map_or("-".to_string(), |time| time.as_millis().to_string());
+        let settings = format!(
+            "mode={:?}\nplayer_color={:?}\nopponent={}\nmovetime_ms={}\nmin_think_ms={}\ninstant={}\nhash_mb={}\n",
+            self.mode, self.player_color, opponent, millis(self.limits.movetime), millis(self.limits.min_time),
+            self.limits.instant, self.hash_mb);
+        DebugBundle {
+            fen: format!("{}\n", self.game.position.to_fen()),
+            pgn: self.to_pgn(),
+            settings,
+            search_params: self.analysis_engine.lock().unwrap().params().to_toml(),
+            search_log: self.search_log.to_text(),
+        }
+    }
+
+    /// Writes the tab's debug bundle, see `debug_bundle`, as a zip file.
+    fn export_debug_bundle(&mut self, path: &str) {
+        match self.debug_bundle().write(std::path::Path::new(path)) {
+            Ok(()) => self.notices.push(Notice::info(format!("Debug bundle written to {}; attach it to your bug report", path))),
+            Err(e) => self.notices.push(Notice::error(format!("Export failed: {}", e))),
+        }
+    }
+
+    /// Writes the moves played so far as an animated GIF, seen from the
+    /// player's side of the board.
+    fn export_gif(&mut self, path: &str) {
+        let options = ExportOptions { orientation: self.player_color, ..ExportOptions::default() };
+        let game = Game::new();
+        let start = Position::from_fen(&self.start_fen, &game).unwrap_or_else(|_| Position::new(&game));
+        match render::export_gif(&start, &self.played_moves, &options, path) {
+            Ok(()) => self.notices.push(Notice::info(format!("Game exported to {}", path))),
+            Err(e) => self.notices.push(Notice::error(format!("Export failed: {}", e))),
+        }
+    }
+
+    /// Writes a Markdown report of the game next to `pgn_path`, searching only
+    /// the positions the engine did not already evaluate during the game.
+    fn export_report(&mut self, pgn_path: &str) {
+        let mut board = Board::from_fen(&self.start_fen).unwrap_or_default();
+        let moves = self.game_moves();
+        let mut cache = Vec::with_capacity(moves.len() + 1);
+        for (entry, mv) in self.move_history.iter().zip(&moves) {
+            cache.push(entry.analysis.as_ref().and_then(|line| line.to_analysis(&board)));
+            let _ = board.play(*mv);
+        }
+        cache.push(self.analysis.as_ref().and_then(|line| line.to_analysis(&board)));
+
+        let texts: Vec<String> = moves.iter().map(|mv| mv.to_string()).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let start_fen = (self.start_fen != START_FEN).then_some(self.start_fen.as_str());
+        let mut engine = self.analysis_engine.lock().unwrap();
+        engine.set_max_time_ms(ANALYSIS_TIME_MS);
+        let analysis = report::analyze_game_cached(start_fen, &texts, &cache, &mut engine);
+        drop(engine);
+        if let Ok(analysis) = &analysis {
+            self.annotate(analysis);
+        }
+        let written = analysis.and_then(|analysis| analysis.write_next_to(pgn_path, ReportFormat::Markdown));
+        match written {
+            Ok(path) => self.notices.push(Notice::info(format!("Report written to {}", path.display()))),
+            Err(e) => self.notices.push(Notice::error(format!("Report failed: {}", e))),
+        }
+    }
+
+    /// Marks the moves a report judged inaccurate, mistaken or blundered
+    /// with `?!`, `?` or `??`, leaving annotations the user made alone.
+    fn annotate(&mut self, analysis: &GameAnalysis) {
+        for (ply, judged) in analysis.plies.iter().enumerate() {
+            let node = self.line.get(..=ply).and_then(|line| self.variations.node_mut(line));
+            if let (Some(node), Some(judgement)) = (node, judged.judgement) {
+                node.nag.get_or_insert(judgement.nag());
+            }
+        }
+    }
+
+    // Add a function to draw the move list
+    fn draw_move_list(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(20.0);
+            ui.heading("Move History");
+            ui.add_space(10.0);
+            
+            egui::ScrollArea::vertical()
+                .max_height(ui.available_height() - 60.0)
+                .show(ui, |ui| {
+                    if self.explores_variations() {
+                        self.draw_variations(ui);
+                        return;
+                    }
+                    // Selecting a move shows its cached line; selecting it again
+                    // goes back. Right-clicking annotates it or copies the
+                    // position it was played from
+                    let mut copy = None;
+                    for (ply, entry) in self.move_history.iter().enumerate() {
+                        let selected = self.viewed_ply == Some(ply);
+                        let mut node = self.line.get(..=ply).and_then(|line| self.variations.node_mut(line));
+                        let symbol = node.as_ref().and_then(|node| node.nag).map_or("", Nag::symbol);
+                        let response = ui.selectable_label(selected, format!("{}{}", entry.text, symbol));
+                        if response.clicked() {
+                            self.viewed_ply = if selected { None } else { Some(ply) };
+                        }
+                        response.context_menu(|ui| {
+                            if let Some(node) = node.as_deref_mut() {
+                                draw_annotation_menu(ui, node);
+                                ui.separator();
+                            }
+                            if let Some(item) = draw_copy_buttons(ui, entry.analysis.is_some()) {
+                                copy = Some((item, ply));
+                            }
+                        });
+                        if let Some(node) = node.filter(|node| !node.comment.is_empty()) {
+                            ui.label(egui::RichText::new(&node.comment).italics());
+                        }
+                    }
+                    if let Some((item, ply)) = copy {
+                        self.copy(ui, item, &self.line[..ply]);
+                    }
+                });
+        });
+    }
+}
+
+/// The moves of an engine line in long algebraic notation, up to the first
+/// that does not read as legal.
+fn legal_prefix(mut board: Board, pv: &[String]) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for text in pv {
+        let Ok(mv) = board.parse_move(text) else {
+            break;
+        };
+        if board.play(mv).is_err() {
+            break;
+        }
+        moves.push(mv);
+    }
+    moves
+}
+
+/// Draws where each optional asset was found, or for a missing one what
+/// is off without it, where it was looked for and how to get it.
+fn draw_assets(ui: &mut egui::Ui, assets: &[AssetStatus]) {
+    for status in assets {
+        ui.label(egui::RichText::new(status.asset.name()).strong());
+        match &status.path {
+            Some(path) => {
+                ui.label(format!("Found at {}", path.display()));
+            }
+            None => {
+                ui.colored_label(egui::Color32::YELLOW, format!("Missing. {}", status.asset.fallback()));
+                ui.label(status.asset.hint()).on_hover_ui(|ui| {
+                    ui.label("Looked in:");
+                    for path in &status.searched {
+                        ui.label(path.display().to_string());
+                    }
+                });
+            }
+        }
+        ui.add_space(6.0);
+    }
+}
+
+/// Draws a button for each copy action; the ones needing an engine line
+/// are disabled without one.
+///
+/// # Returns
+///
+/// * The action clicked, if any
+fn draw_copy_buttons(ui: &mut egui::Ui, analyzed: bool) -> Option<CopyItem> {
+    let mut clicked = None;
+    for item in CopyItem::ALL {
+        let enabled = analyzed || matches!(item, CopyItem::Fen | CopyItem::Pgn);
+        if ui.add_enabled(enabled, egui::Button::new(item.label())).clicked() {
+            clicked = Some(item);
+            ui.close_menu();
+        }
+    }
+    clicked
+}
+
+/// Draws the right-click menu items annotating a move: its symbol, picked
+/// again to clear it, and its comment.
+fn draw_annotation_menu(ui: &mut egui::Ui, node: &mut MoveNode) {
+    ui.horizontal(|ui| {
+        for nag in Nag::ALL {
+            if ui.selectable_label(node.nag == Some(nag), nag.symbol()).clicked() {
+                node.nag = if node.nag == Some(nag) { None } else { Some(nag) };
+            }
+        }
+    });
+    ui.label("Comment");
+    ui.text_edit_multiline(&mut node.comment);
+}
+
+/// Draws a piece glyph for an animation, `opacity` from 0.0 to 1.0.
+/// The on-screen rectangle of a square on a board seen from `orientation`'s
+/// side.
+fn square_rect(board_rect: egui::Rect, square: usize, orientation: Color) -> egui::Rect {
+    let square_size = board_rect.width() / 8.0;
+    let (row, col) = render::square_cell(square, orientation);
+    egui::Rect::from_min_size(
+        board_rect.min + egui::vec2(col as f32 * square_size, row as f32 * square_size),
+        egui::vec2(square_size, square_size),
+    )
+}
+
+/// The square under a point, if the point is on the board; the inverse of
+/// `square_rect`.
+/// Shows a label screen readers announce whenever its text changes, under
+/// an id of its own so that it stays the same node as the panel around it
+/// changes.
+fn live_label(ui: &mut egui::Ui, id_source: &str, text: &str) {
+    let response = ui.push_id(id_source, |ui| ui.label(text)).inner;
+    ui.ctx().accesskit_node_builder(response.id, |builder| builder.set_live(accesskit::Live::Polite));
+}
+
+fn square_at(board_rect: egui::Rect, pos: egui::Pos2, orientation: Color) -> Option<usize> {
+    if !board_rect.contains(pos) {
+        return None;
+    }
+    let square_size = board_rect.width() / 8.0;
+    let col = ((pos.x - board_rect.min.x) / square_size) as usize;
+    let row = ((pos.y - board_rect.min.y) / square_size) as usize;
+    (row < 8 && col < 8).then(|| render::cell_square(row, col, orientation))
+}
+
+fn draw_glyph(painter: &egui::Painter, pos: egui::Pos2, piece: PieceType, color: Color, square_size: f32, opacity: f32) {
+    let shade = if color == Color::White { 255 } else { 0 };
+    painter.text(
+        pos,
+        egui::Align2::CENTER_CENTER,
+        render::piece_glyph(piece, color),
+        egui::FontId::proportional(square_size * 0.8),
+        egui::Color32::from_rgba_unmultiplied(shade, shade, shade, (opacity.clamp(0.0, 1.0) * 255.0) as u8),
+    );
+}
+
+/// The application window: a tab bar over any number of games.
+pub struct ChessGUI {
+    tabs: Vec<GameController>,
+    active: usize,  // Index of the tab shown
+    tabs_opened: usize,  // For numbering new tabs
+    remote: Option<RemoteAnalyzer>,  // Remote analysis server, if configured
+    paste: Option<PasteDialog>,  // Open while the user is pasting a position
+    book: Option<Box<dyn OpeningBook>>,  // Book shown in the opening explorer, if one was found
+    assets: Vec<AssetStatus>,  // Where each optional asset was found at startup
+    settings: Settings,  // Engine settings for new games, remembered between runs
+    drill_stats: DrillStats,  // Mate drill results, remembered between runs
+    drills: Vec<MateDrill>,  // Drills not yet opened, those from played games first
+    drill_scan: Option<mpsc::Receiver<Vec<MateDrill>>>,  // Set while played games are searched for mates
+    recall_stats: RecallStats,  // Repertoire lines recalled in the trainer, remembered between runs
+    repertoire: Option<RepertoireDialog>,  // Open while the user is importing a repertoire
+    repertoire_pgn: String,  // The last repertoire imported, remembered between runs
+    library: PositionLibrary,  // Bookmarked positions, remembered between runs
+    library_window: Option<LibraryWindow>,  // Open while the position library is shown
+    memory_window: Option<MemoryWindow>,  // Open while memory use is shown
+    strength_window: Option<StrengthWindow>,  // Open while the strength history is shown
+    engine_options: EngineOptions,  // Given to every tab's engine; read from the options file at startup
+    options_window: Option<OptionsWindow>,  // Open while the engine options are edited
+    game_log: bool,  // Whether the game is also shown as text, for screen readers
+    quit: QuitState,  // Whether the user is being asked about unfinished games
+    notices: Notices,  // Toasts and the status bar message
+    analysis_cache: AnalysisCache,  // Deepest engine line known per position, shared by the tabs
+    keep_analysis: bool,  // Whether the cache is stored between runs
+    warm_up: Option<WarmUp>,  // Set while the splash screen shows
+}
+
+/// Engine settings remembered between runs, stored as `key=value` lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Settings {
+    min_think_ms: u64,
+    instant: bool,
+    hash_mb: usize,  // Hash table size of each tab's engines
+    cache_positions: usize,  // Most positions the analysis cache keeps
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            min_think_ms: MIN_THINK_TIME.as_millis() as u64,
+            instant: false,
+            hash_mb: DEFAULT_HASH_MB,
+            cache_positions: DEFAULT_MAX_POSITIONS,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads stored settings; unknown keys and unreadable values keep their
+    /// defaults, so settings from other versions still load.
+    fn parse(text: &str) -> Self {
+        let mut settings = Settings::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            match key.trim() {
+                "min_think_ms" => settings.min_think_ms = value.trim().parse().unwrap_or(settings.min_think_ms),
+                "instant" => settings.instant = value.trim().parse().unwrap_or(settings.instant),
+                "hash_mb" => settings.hash_mb = value.trim().parse().unwrap_or(settings.hash_mb).clamp(1, MAX_HASH_MB),
+                "cache_positions" => settings.cache_positions = value.trim().parse().unwrap_or(settings.cache_positions).max(1),
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    fn to_text(self) -> String {
+        format!("min_think_ms={}\ninstant={}\nhash_mb={}\ncache_positions={}\n",
+            self.min_think_ms, self.instant, self.hash_mb, self.cache_positions)
+    }
+
+    /// Limits of the engine playing against the user.
+    fn limits(self) -> SearchLimits {
+        let min_time = (self.min_think_ms > 0).then(|| Duration::from_millis(self.min_think_ms.min(MAX_MIN_THINK_MS)));
+        SearchLimits { min_time, instant: self.instant, ..SearchLimits::default() }
+    }
+
+    /// The settings with those of a game's limits.
+    fn with_limits(self, limits: &SearchLimits) -> Self {
+        Settings {
+            min_think_ms: limits.min_time.map_or(0, |min_time| min_time.as_millis() as u64),
+            instant: limits.instant,
+            ..self
+        }
+    }
+}
+
+/// Progress of closing the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuitState {
+    Running,
+    /// Asking whether to save unfinished games first
+    Asking,
+    /// Confirmed; the next close request goes through
+    Confirmed,
+}
+
+/// The "Paste position" window, holding the pasted text until it loads.
+#[derive(Default)]
+struct PasteDialog {
+    text: String,
+    error: Option<String>,  // Why the text last failed to load
+}
+
+/// The "Repertoire trainer" window, holding the repertoire PGN until it
+/// loads.
+struct RepertoireDialog {
+    text: String,
+    side: Color,  // The side the repertoire is for
+    error: Option<String>,  // Why the text last failed to load
+}
+
+/// An ending the user converts against perfect defence, and the moves it
+/// has taken so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Practice {
+    ending: Ending,
+    optimal_moves: u32,  // Moves to mate from the start with perfect play
+    moves: u32,  // Moves the user has played
+}
+
+/// The "Memory" window, holding the budgets being edited until applied.
+struct MemoryWindow {
+    hash_mb: usize,
+    cache_positions: usize,
+}
+
+/// The "Engine options" window, holding the options being edited until
+/// applied.
+struct OptionsWindow {
+    options: EngineOptions,
+    error: Option<String>,  // Why the options file could not be written
+}
+
+/// The "Strength history" window, holding the calibration runs read when
+/// it was opened.
+struct StrengthWindow {
+    history: Vec<StrengthRecord>,
+    error: Option<String>,  // Why the history could not be read
+}
+
+impl StrengthWindow {
+    fn load() -> Self {
+        match strength::load(&strength::history_path()) {
+            Ok(history) => StrengthWindow { history, error: None },
+            Err(e) => StrengthWindow { history: Vec::new(), error: Some(e) },
+        }
+    }
+}
+
+/// The "Position library" window, holding the bookmark being written.
+#[derive(Default)]
+struct LibraryWindow {
+    draft: Bookmark,  // Name, note and folder for the next bookmark; the FEN is filled in on saving
+    error: Option<String>,  // Why the last bookmark or reload failed
+}
+
+impl ChessGUI {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let tables = crate::init_tables();
+        let settings = cc.storage
+            .and_then(|storage| storage.get_string(SETTINGS_KEY))
+            .map_or_else(Settings::default, |text| Settings::parse(&text));
+        let mut gui = Self::with_settings(settings);
+        gui.warm_up = Some(WarmUp::start(tables));
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(DRILL_STATS_KEY)) {
+            gui.drill_stats = DrillStats::parse(&text);
+        }
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(RECALL_STATS_KEY)) {
+            gui.recall_stats = RecallStats::parse(&text);
+        }
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(REPERTOIRE_KEY)) {
+            gui.repertoire_pgn = text;
+        }
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(KEEP_ANALYSIS_KEY)) {
+            gui.keep_analysis = text != "false";
+        }
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(ANALYSIS_CACHE_KEY)) {
+            gui.analysis_cache = AnalysisCache::parse(&text);
+            gui.analysis_cache.set_max_positions(gui.settings.cache_positions);
+        }
+        // An unreadable library is reported in the library window, which
+        // starts out open
+        if let Some(text) = cc.storage.and_then(|storage| storage.get_string(LIBRARY_KEY)) {
+            match PositionLibrary::from_json(&text) {
+                Ok(library) => gui.library = library,
+                Err(e) => {
+                    gui.notices.push(Notice::error(e.clone()));
+                    gui.library_window = Some(LibraryWindow { error: Some(e), ..LibraryWindow::default() });
+                }
+            }
+        }
+        gui
+    }
+
+    fn with_settings(settings: Settings) -> Self {
+        let mut gui = Self {
+            tabs: Vec::new(),
+            active: 0,
+            tabs_opened: 0,
+            remote: std::env::var(REMOTE_ENV).ok().map(|addr| RemoteAnalyzer::new(&addr)),
+            paste: None,
+            book: None,
+            assets: assets::resolve_all(),
+            settings,
+            drill_stats: DrillStats::default(),
+            drills: Vec::new(),
+            drill_scan: None,
+            recall_stats: RecallStats::default(),
+            repertoire: None,
+            repertoire_pgn: String::new(),
+            library: PositionLibrary::new(),
+            library_window: None,
+            memory_window: None,
+            strength_window: None,
+            engine_options: EngineOptions::default(),
+            options_window: None,
+            game_log: false,
+            quit: QuitState::Running,
+            notices: Notices::default(),
+            analysis_cache: AnalysisCache::new(),
+            keep_analysis: true,
+            warm_up: None,
+        };
+        gui.analysis_cache.set_max_positions(settings.cache_positions);
+        // A missing book only turns the explorer off; an unreadable one is
+        // worth telling the user about
+        if let Some(path) = gui.asset_path(Asset::OpeningBook) {
+            match book::load_book(&path) {
+                Ok(book) => gui.book = Some(book),
+                Err(e) => gui.notices.push(Notice::error(format!("{}; the opening explorer is off", e))),
+            }
+        }
+        if let Some(path) = gui.asset_path(Asset::EngineOptions) {
+            match EngineOptions::load(&path) {
+                Ok(options) => gui.engine_options = options,
+                Err(e) => gui.notices.push(Notice::error(format!("{}; using the default engine options", e))),
+            }
+        }
+        gui.open_tab(TabMode::VsEngine);
+        gui
+    }
+
+    /// Where an asset was found at startup.
+    fn asset_path(&self, asset: Asset) -> Option<std::path::PathBuf> {
+        self.assets.iter().find(|status| status.asset == asset).and_then(|status| status.path.clone())
+    }
+
+    /// Opens a new tab and switches to it.
+    fn open_tab(&mut self, mode: TabMode) {
+        self.tabs_opened += 1;
+        let title = match mode {
+            TabMode::VsEngine => format!("Game {}", self.tabs_opened),
+            TabMode::EngineMatch => format!("Match {}", self.tabs_opened),
+            TabMode::Analysis => format!("Analysis {}", self.tabs_opened),
+        };
+        let mut tab = GameController::new(title, mode);
+        if mode == TabMode::VsEngine {
+            tab.limits = self.settings.limits();
+        }
+        if self.settings.hash_mb != tab.hash_mb {
+            tab.set_hash_size(self.settings.hash_mb);
+        }
+        tab.engine_options = self.engine_options;
+        self.tabs.push(tab);
+        self.active = self.tabs.len() - 1;
+    }
+
+    /// Stops every tab's engine, so no search outlives the window.
+    fn stop_engines(&mut self) {
+        for tab in &mut self.tabs {
+            tab.stop.store(true, Ordering::Relaxed);
+            tab.engine_move = None;
+        }
+    }
+
+    /// Saves every unfinished game as PGN in the working directory, named
+    /// after its tab.
+    ///
+    /// # Returns
+    ///
+    /// * The files written, or the first error
+    fn save_unfinished_games(&self) -> Result<Vec<String>, String> {
+        let mut written = Vec::new();
+        for tab in self.tabs.iter().filter(|tab| tab.is_unfinished()) {
+            let path = format!("{}.pgn", tab.title.to_lowercase().replace(' ', "_"));
+            std::fs::write(&path, tab.to_pgn()).map_err(|e| format!("Could not save {}: {}", path, e))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Asks whether to save unfinished games before quitting.
+    fn draw_quit_prompt(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if self.quit != QuitState::Asking {
+            return;
+        }
+        let mut choice = None;
+        egui::Window::new("Quit")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("Some games are unfinished. Save them before quitting?");
+                ui.horizontal(|ui| {
+                    if ui.button("Save and quit").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Quit without saving").clicked() {
+                        choice = Some(false);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.quit = QuitState::Running;
+                    }
+                });
+            });
+        if let Some(save) = choice {
+            if save {
+                match self.save_unfinished_games() {
+                    Ok(paths) => self.notices.push(Notice::info(format!("Saved {}", paths.join(", ")))),
+                    Err(e) => {
+                        // Stay open rather than lose the games
+                        self.notices.push(Notice::error(e));
+                        self.quit = QuitState::Running;
+                        return;
+                    }
+                }
+            }
+            self.quit = QuitState::Confirmed;
+            frame.close();
+        }
+    }
+
+    /// Opens a FEN, board diagram, PGN or move list in a new analysis tab.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The pasted text
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or why the text could not be read; no tab is opened then
+    fn open_pasted(&mut self, text: &str) -> Result<(), String> {
+        let imported = pgn::import(text)?;
+        self.open_tab(TabMode::Analysis);
+        let tab = &mut self.tabs[self.active];
+        let result = tab.load(&imported);
+        if result.is_err() {
+            self.close_tab(self.active);
+        } else if imported.format == ImportFormat::Pgn {
+            // Keep the game's variations and comments too
+            if let Some(tree) = pgn::import_tree(text).ok().filter(|tree| tree.main_line() == imported.moves) {
+                tab.variations = tree;
+            }
+        }
+        result
+    }
+
+    /// Hands text typed outside a text field to the active tab as square
+    /// coordinates; Escape drops the selection and any half-typed square.
+    fn handle_typed_squares(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let (typed, escape) = ctx.input(|input| {
+            let typed: String = input.events.iter().filter_map(|event| match event {
+                egui::Event::Text(text) => Some(text.as_str()),
+                _ => None,
+            }).collect();
+            (typed, input.key_pressed(egui::Key::Escape))
+        });
+        let tab = &mut self.tabs[self.active];
+        if escape && (tab.selected_square.is_some() || tab.typed_file.is_some()) {
+            tab.selected_square = None;
+            tab.typed_file = None;
+            tab.keyboard_feedback = Some("Selection cleared".to_string());
+        }
+        tab.handle_typed_text(&typed);
+    }
+
+    /// Loads text pasted anywhere outside a text field, and draws the paste
+    /// window with its last error while it is open.
+    fn handle_paste(&mut self, ctx: &egui::Context) {
+        if !ctx.wants_keyboard_input() {
+            let pasted = ctx.input(|input| input.events.iter().find_map(|event| match event {
+                egui::Event::Paste(text) => Some(text.clone()),
+                _ => None,
+            }));
+            if let Some(text) = pasted {
+                // Failures open the window so the error can be fixed in place
+                if let Err(e) = self.open_pasted(&text) {
+                    self.paste = Some(PasteDialog { text, error: Some(e) });
+                }
+            }
+        }
+
+        let Some(mut dialog) = self.paste.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut load = false;
+        egui::Window::new("Paste position")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Paste a FEN, a PGN game or a list of moves:");
+                ui.add(egui::TextEdit::multiline(&mut dialog.text).desired_rows(8).code_editor());
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                }
+                load = ui.button("Load").clicked();
+            });
+        if load {
+            match self.open_pasted(&dialog.text) {
+                Ok(()) => return,
+                Err(e) => dialog.error = Some(e),
+            }
+        }
+        if open {
+            self.paste = Some(dialog);
+        }
+    }
+
+    /// Bookmarks the position shown in the active tab.
+    ///
+    /// # Arguments
+    ///
+    /// * `draft` - The bookmark's name, note and folder
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or why the bookmark was refused
+    fn bookmark_position(&mut self, draft: &Bookmark) -> Result<(), String> {
+        let fen = self.tabs[self.active].game.position.to_fen();
+        self.library.add(Bookmark { fen, ..draft.clone() })
+    }
+
+    /// Opens a bookmarked position in a new analysis tab named after it.
+    fn open_bookmark(&mut self, index: usize) -> Result<(), String> {
+        let bookmark = self.library.bookmarks().get(index).cloned().ok_or("No such bookmark")?;
+        self.open_pasted(&bookmark.fen)?;
+        self.tabs[self.active].title = bookmark.name;
+        Ok(())
+    }
+
+    /// What each tab's engines, the analysis cache, the book and the loaded
+    /// endgame tables take.
+    fn memory_use(&self) -> Vec<MemoryUse> {
+        let mut uses: Vec<MemoryUse> = self.tabs.iter()
+            .map(|tab| MemoryUse::new(&format!("{} engines", tab.title), tab.memory_bytes()))
+            .collect();
+        uses.push(MemoryUse::new(&format!("Analysis cache ({} positions)", self.analysis_cache.len()),
+            self.analysis_cache.memory_bytes()));
+        if let Some(book) = &self.book {
+            uses.push(MemoryUse::new("Opening book", book.memory_bytes()));
+        }
+        uses.extend(memory::endgame_tables());
+        uses
+    }
+
+    /// Sets the memory budgets and remembers them for later runs. A new
+    /// hash size empties every engine's table.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash_mb` - Hash table size of each tab's engines, in megabytes
+    /// * `cache_positions` - Most positions the analysis cache keeps
+    fn set_memory_budgets(&mut self, hash_mb: usize, cache_positions: usize) {
+        let hash_mb = hash_mb.clamp(1, MAX_HASH_MB);
+        if hash_mb != self.settings.hash_mb {
+            for tab in &mut self.tabs {
+                tab.set_hash_size(hash_mb);
+            }
+        }
+        self.settings.hash_mb = hash_mb;
+        self.analysis_cache.set_max_positions(cache_positions);
+        self.settings.cache_positions = self.analysis_cache.max_positions();
+    }
+
+    /// Gives every tab's engine new options, from its next search on.
+    fn set_engine_options(&mut self, options: EngineOptions) {
+        self.engine_options = options;
+        for tab in &mut self.tabs {
+            tab.engine_options = options;
+        }
+    }
+
+    /// Draws the engine options window while it is open: a control for
+    /// every option of `options::OPTIONS` but the hash size, which the
+    /// memory window sets.
+    fn draw_engine_options(&mut self, ctx: &egui::Context) {
+        let Some(mut window) = self.options_window.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        let mut save = false;
+        let path = options::config_path();
+        egui::Window::new("Engine options")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("engine_options").num_columns(2).show(ui, |ui| {
+                    for option in options::OPTIONS.iter().filter(|&&option| option != options::HASH) {
+                        ui.label(option.name).on_hover_text(option.help);
+                        let value = match (option.kind, window.options.get(option)) {
+                            (OptionKind::Spin { min, max, .. }, OptionValue::Spin(mut n)) => {
+                                ui.add_enabled(min < max, egui::DragValue::new(&mut n).clamp_range(min..=max));
+                                OptionValue::Spin(n)
+                            }
+                            (_, OptionValue::Check(mut on)) => {
+                                ui.checkbox(&mut on, "");
+                                OptionValue::Check(on)
+                            }
+                            (_, value) => value,
+                        };
+                        let _ = window.options.set_value(option, value);
+                        ui.end_row();
+                    }
+                });
+                ui.label(egui::RichText::new("The hash size is set under Memory").weak());
+                if let Some(error) = &window.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                }
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(window.options != self.engine_options, egui::Button::new("Apply")).clicked();
+                    save = ui.button("Save as defaults")
+                        .on_hover_text(format!("Writes {}, which the GUI and UCI read at startup", path.display()))
+                        .clicked();
+                });
+            });
+        if apply || save {
+            self.set_engine_options(window.options);
+        }
+        if save {
+            match (EngineOptions { hash_mb: self.settings.hash_mb, ..window.options }).save(&path) {
+                Ok(()) => {
+                    window.error = None;
+                    self.notices.push(Notice::info(format!("Saved the engine options to {}", path.display())));
+                }
+                Err(e) => window.error = Some(e),
+            }
+        }
+        if open {
+            self.options_window = Some(window);
+        }
+    }
+
+    /// Draws the memory window while it is open: what each part takes, the
+    /// budgets, and buttons freeing memory now.
+    fn draw_memory(&mut self, ctx: &egui::Context) {
+        let Some(mut window) = self.memory_window.take() else {
+            return;
+        };
+        let uses = self.memory_use();
+        let mut open = true;
+        let mut apply = false;
+        let mut clear_hash = false;
+        let mut shrink_cache = false;
+        egui::Window::new("Memory")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("memory_use").num_columns(2).striped(true).show(ui, |ui| {
+                    for part in &uses {
+                        ui.label(&part.name);
+                        ui.label(memory::format_bytes(part.bytes));
+                        ui.end_row();
+                    }
+                    let total = uses.iter().map(|part| part.bytes).sum();
+                    ui.label(egui::RichText::new("Total").strong());
+                    ui.label(egui::RichText::new(memory::format_bytes(total)).strong());
+                    ui.end_row();
+                });
+                ui.separator();
+                egui::Grid::new("memory_budgets").num_columns(2).show(ui, |ui| {
+                    ui.label("Hash table per engine (MB)");
+                    ui.add(egui::DragValue::new(&mut window.hash_mb).clamp_range(1..=MAX_HASH_MB));
+                    ui.end_row();
+                    ui.label("Analysis cache (positions)");
+                    ui.add(egui::DragValue::new(&mut window.cache_positions).clamp_range(1..=1_000_000).speed(10));
+                    ui.end_row();
+                });
+                let changed = window.hash_mb != self.settings.hash_mb || window.cache_positions != self.settings.cache_positions;
+                apply = ui.add_enabled(changed, egui::Button::new("Apply budgets"))
+                    .on_hover_text("A new hash size empties the engines' tables")
+                    .clicked();
+                ui.separator();
+                ui.horizontal(|ui| {
+                    clear_hash = ui.button("Clear hash tables").clicked();
+                    shrink_cache = ui.add_enabled(self.analysis_cache.len() > 1, egui::Button::new("Shrink analysis cache"))
+                        .on_hover_text("Drops the shallower half of the cached lines")
+                        .clicked();
+                });
+            });
+        if apply {
+            self.set_memory_budgets(window.hash_mb, window.cache_positions);
+        }
+        if clear_hash {
+            for tab in &mut self.tabs {
+                tab.set_hash_size(tab.hash_mb);
+            }
+            self.notices.push(Notice::info("Cleared the engines' hash tables"));
+        }
+        if shrink_cache {
+            let dropped = self.analysis_cache.shrink_to(self.analysis_cache.len() / 2);
+            self.notices.push(Notice::info(format!("Dropped {} cached lines", dropped)));
+        }
+        if open {
+            self.memory_window = Some(window);
+        }
+    }
+
+    /// Draws the active game as text while it is shown: its state, the
+    /// position in words and every move as it was announced.
+    fn draw_game_log(&mut self, ctx: &egui::Context) {
+        if !self.game_log {
+            return;
+        }
+        let tab = &self.tabs[self.active];
+        let board = Board::with_tables(tab.game.position.clone(), &tab.game);
+        let mut open = true;
+        egui::Window::new("Game log")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(tab.status()).strong());
+                ui.label(notation::describe_board(&board));
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(400.0).stick_to_bottom(true).show(ui, |ui| {
+                    if tab.move_history.is_empty() {
+                        ui.label("No moves yet");
+                    }
+                    for (ply, entry) in tab.move_history.iter().enumerate() {
+                        let dots = if ply % 2 == 0 { "." } else { "..." };
+                        ui.label(format!("{}{} {}", ply / 2 + 1, dots, entry.spoken));
+                    }
+                });
+            });
+        self.game_log = open;
+    }
+
+    /// Draws the strength history while it is open: the Elo of each
+    /// calibration run with its confidence interval, and the runs newest
+    /// first, regressions marked with the parameter changes behind them.
+    fn draw_strength(&mut self, ctx: &egui::Context) {
+        let Some(mut window) = self.strength_window.take() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Strength history")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} runs in {}", window.history.len(), strength::history_path().display()));
+                    if ui.button("Reload").clicked() {
+                        window = StrengthWindow::load();
+                    }
+                });
+                if let Some(error) = &window.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                if window.history.is_empty() {
+                    ui.label("No calibration runs yet; `Chess_Engine calibrate` records one.");
+                    return;
+                }
+
+                let regressions = strength::regressions(&window.history);
+                let run = |index: usize| (index + 1) as f64;
+                let elo: Vec<[f64; 2]> = window.history.iter().enumerate().map(|(i, record)| [run(i), record.elo]).collect();
+                let regressed: Vec<[f64; 2]> = regressions.iter().map(|&i| [run(i), window.history[i].elo]).collect();
+                egui::plot::Plot::new("strength_plot")
+                    .height(200.0)
+                    .allow_scroll(false)
+                    .label_formatter(|_, point| format!("run {:.0}\n{:.0} Elo", point.x, point.y))
+                    .show(ui, |plot| {
+                        for (i, record) in window.history.iter().enumerate() {
+                            let interval = vec![[run(i), record.elo - record.margin], [run(i), record.elo + record.margin]];
+                            plot.line(egui::plot::Line::new(interval).color(egui::Color32::GRAY));
+                        }
+                        plot.line(egui::plot::Line::new(elo.clone()).name("Elo"));
+                        plot.points(egui::plot::Points::new(elo).radius(3.0));
+                        plot.points(egui::plot::Points::new(regressed).radius(5.0).color(egui::Color32::RED).name("Regression"));
+                    });
+
+                egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    egui::Grid::new("strength_runs").num_columns(4).striped(true).show(ui, |ui| {
+                        for (i, record) in window.history.iter().enumerate().rev() {
+                            ui.label(format!("{}. {}", i + 1, record.date()));
+                            ui.label(record.build());
+                            ui.label(format!("{:.0} ± {:.0}", record.elo, record.margin))
+                                .on_hover_text(format!("{} games at {} ms per move", record.games, record.movetime_ms));
+                            if regressions.contains(&i) {
+                                let before = &window.history[i - 1];
+                                let changes = strength::config_changes(before, record);
+                                let summary = if changes.is_empty() { "no parameter changes".to_string() } else { changes.join("\n") };
+                                ui.colored_label(egui::Color32::RED, format!("-{:.0} since {}", before.elo - record.elo, before.build()))
+                                    .on_hover_text(summary);
+                            } else {
+                                ui.label("");
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        if open {
+            self.strength_window = Some(window);
+        }
+    }
+
+    /// Draws the position library while it is open: a form bookmarking the
+    /// active tab's position, and the bookmarks by folder.
+    fn draw_library(&mut self, ctx: &egui::Context) {
+        let Some(mut window) = self.library_window.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut save = false;
+        let mut reload = None;
+        let mut delete = None;
+        let mut refile = None;
+        let folders: Vec<String> = self.library.folders().into_iter().map(str::to_string).collect();
+        egui::Window::new("Position library")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("bookmark_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut window.draft.name);
+                    ui.end_row();
+                    ui.label("Folder");
+                    ui.text_edit_singleline(&mut window.draft.folder);
+                    ui.end_row();
+                    ui.label("Note");
+                    ui.text_edit_multiline(&mut window.draft.note);
+                    ui.end_row();
+                });
+                save = ui.button("Bookmark current position").clicked();
+                if let Some(error) = &window.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                }
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    if folders.is_empty() {
+                        ui.label("No bookmarks yet.");
+                    }
+                    for folder in &folders {
+                        let title = if folder.is_empty() { "Unfiled" } else { folder.as_str() };
+                        egui::CollapsingHeader::new(title).default_open(true).show(ui, |ui| {
+                            for (index, bookmark) in self.library.in_folder(folder) {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Open").clicked() {
+                                        reload = Some(index);
+                                    }
+                                    if ui.small_button("Delete").clicked() {
+                                        delete = Some(index);
+                                    }
+                                    ui.menu_button("Move to", |ui| {
+                                        for target in folders.iter().filter(|target| *target != folder) {
+                                            let label = if target.is_empty() { "Unfiled" } else { target.as_str() };
+                                            if ui.button(label).clicked() {
+                                                refile = Some((index, target.clone()));
+                                                ui.close_menu();
+                                            }
+                                        }
+                                        let new_folder = window.draft.folder.trim();
+                                        if !new_folder.is_empty() && !folders.iter().any(|target| target == new_folder)
+                                            && ui.button(format!("New folder \"{}\"", new_folder)).clicked()
+                                        {
+                                            refile = Some((index, new_folder.to_string()));
+                                            ui.close_menu();
+                                        }
+                                    });
+                                    ui.label(egui::RichText::new(&bookmark.name).strong());
+                                });
+                                if !bookmark.note.is_empty() {
+                                    ui.label(egui::RichText::new(&bookmark.note).weak());
+                                }
+                            }
+                        });
+                    }
+                });
+            });
+
+        if save {
+            match self.bookmark_position(&window.draft) {
+                Ok(()) => window = LibraryWindow { draft: Bookmark { folder: window.draft.folder, ..Bookmark::default() }, error: None },
+                Err(e) => window.error = Some(e),
+            }
+        }
+        if let Some(index) = reload {
+            if let Err(e) = self.open_bookmark(index) {
+                window.error = Some(e);
+            }
+        }
+        if let Some(index) = delete {
+            self.library.remove(index);
+        }
+        if let Some((index, folder)) = refile {
+            self.library.move_to(index, &folder);
+        }
+        if open {
+            self.library_window = Some(window);
+        }
+    }
+
+    /// Opens the next mate drill in a new tab. Once the queue runs out it is
+    /// refilled with the bundled drills, and the games in the open tabs are
+    /// searched for more in the background.
+    fn open_drill(&mut self) -> Result<(), String> {
+        if self.drills.is_empty() {
+            self.drills = drills::bundled_drills();
+            self.scan_games_for_drills();
+        }
+        if self.drills.is_empty() {
+            return Err("No drills found".to_string());
+        }
+        let drill = self.drills.remove(0);
+        self.open_pasted(&drill.fen)?;
+        let tab = &mut self.tabs[self.active];
+        tab.title = format!("Mate in {}", drill.moves);
+        tab.drill = Some(drill);
+        Ok(())
+    }
+
+    /// Opens a won position of `ending` in a new tab, for the user to
+    /// convert against the tablebase's defence. Positions are picked among
+    /// the longest quarter of mates, so there is something to learn.
+    fn open_practice(&mut self, ending: Ending) -> Result<(), String> {
+        let tables = tablebase::tablebases();
+        let longest = Verdict::Win(tables.longest_mate(ending)).moves_to_mate().unwrap_or(0);
+        let starts = tables.won_positions(ending, longest * 3 / 4);
+        if starts.is_empty() {
+            return Err(format!("No {} positions to practise", ending.name()));
+        }
+        let fen = &starts[rand::random::<usize>() % starts.len()];
+        self.open_pasted(fen)?;
+        let tab = &mut self.tabs[self.active];
+        let optimal_moves = tables.probe(&tab.game.position).and_then(Verdict::moves_to_mate).unwrap_or(0);
+        tab.title = format!("{} practice", ending.name());
+        tab.practice = Some(Practice { ending, optimal_moves, moves: 0 });
+        Ok(())
+    }
 
-                // Check if this square contains a king in check/checkmate
-                let mut is_check = false;
-                let mut is_checkmate = false;
-                if let Some(piece) = self.game.position.pieces.iter().find(|p| {
-                    let piece_square = bit_scan(p.position);
-                    if self.player_color == Color::White {
-                        piece_square == (rank * 8 + file)
-                    } else {
-                        piece_square == ((7 - rank) * 8 + (7 - file))
-                    }
-                }) {
-                    if piece.piece_type == PieceType::King && piece.color == self.game.position.active_color {
-                        is_check = self.game.position.is_in_check(&self.game);
-                        if is_check {
-                            let legal_moves = self.game.position.get_all_legal_moves(&self.game);
-                            is_checkmate = legal_moves.is_empty();
-                        }
-                    }
+    /// Opens a repertoire trainer in a new tab, starting with the line
+    /// recalled worst so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The repertoire as PGN, with its lines as games or
+    ///   variations
+    /// * `side` - The side the user plays
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or why the repertoire could not be read
+    fn open_trainer(&mut self, text: &str, side: Color) -> Result<(), String> {
+        let tree = pgn::import_tree(text)?;
+        let trainer = Trainer::new(&tree, side)?;
+        self.open_pasted(&tree.start_fen)?;
+        self.repertoire_pgn = text.to_string();
+        let tab = &mut self.tabs[self.active];
+        tab.title = format!("{} repertoire", if side == Color::White { "White" } else { "Black" });
+        tab.trainer = Some(trainer);
+        tab.next_line(&self.recall_stats);
+        Ok(())
+    }
+
+    /// Draws the repertoire import window while it is open.
+    fn draw_repertoire_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.repertoire.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut load = false;
+        egui::Window::new("Repertoire trainer")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Paste your repertoire as PGN, with the lines as games or variations:");
+                ui.add(egui::TextEdit::multiline(&mut dialog.text).desired_rows(8).code_editor());
+                ui.horizontal(|ui| {
+                    ui.label("I play");
+                    ui.radio_value(&mut dialog.side, Color::White, "White");
+                    ui.radio_value(&mut dialog.side, Color::Black, "Black");
+                });
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
                 }
+                load = ui.button("Start training").clicked();
+            });
+        if load {
+            match self.open_trainer(&dialog.text, dialog.side) {
+                Ok(()) => return,
+                Err(e) => dialog.error = Some(e),
+            }
+        }
+        if open {
+            self.repertoire = Some(dialog);
+        }
+    }
 
-                // Draw square with appropriate color
-                let final_color = if is_checkmate {
-                    egui::Color32::from_rgb(255, 0, 0) // Red for checkmate
-                } else if is_check {
-                    egui::Color32::from_rgb(255, 255, 0) // Yellow for check
-                } else {
-                    color
-                };
+    /// Starts searching the games played in the open tabs for mates the
+    /// players had, unless a search is already running.
+    fn scan_games_for_drills(&mut self) {
+        let games: Vec<(String, Vec<u64>)> = self.tabs.iter()
+            .filter(|tab| tab.drill.is_none() && !tab.played_moves.is_empty())
+            .map(|tab| (tab.start_fen.clone(), tab.played_moves.clone()))
+            .collect();
+        if games.is_empty() || self.drill_scan.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let found = games.iter()
+                .flat_map(|(fen, moves)| drills::drills_from_game(fen, moves, GAME_DRILL_MOVES))
+                .collect();
+            let _ = sender.send(found);
+        });
+        self.drill_scan = Some(receiver);
+    }
 
-                ui.painter().rect_filled(rect, 0.0, final_color);
-
-                // Draw piece if present
-                if let Some(piece) = self.game.position.pieces.iter().find(|p| {
-                    let piece_square = bit_scan(p.position);
-                    if self.player_color == Color::White {
-                        piece_square == (rank * 8 + file)
-                    } else {
-                        piece_square == ((7 - rank) * 8 + (7 - file))
-                    }
-                }) {
-                    let piece_char = match (piece.piece_type, piece.color) {
-                        (PieceType::Pawn, Color::White) => "♙",
-                        (PieceType::Knight, Color::White) => "♘",
-                        (PieceType::Bishop, Color::White) => "♗",
-                        (PieceType::Rook, Color::White) => "♖",
-                        (PieceType::Queen, Color::White) => "♕",
-                        (PieceType::King, Color::White) => "♔",
-                        (PieceType::Pawn, Color::Black) => "♟",
-                        (PieceType::Knight, Color::Black) => "♞",
-                        (PieceType::Bishop, Color::Black) => "♝",
-                        (PieceType::Rook, Color::Black) => "♜",
-                        (PieceType::Queen, Color::Black) => "♛",
-                        (PieceType::King, Color::Black) => "♚",
-                    };
-
-                    ui.painter().text(
-                        rect.center(),
-                        egui::Align2::CENTER_CENTER,
-                        piece_char,
-                        egui::FontId::proportional(square_size * 0.8),
-                        if piece.color == Color::White {
-                            egui::Color32::WHITE
-                        } else {
-                            egui::Color32::BLACK
-                        },
-                    );
+    /// Queues the drills found in played games ahead of the bundled ones,
+    /// and records the drills solved and repertoire lines finished since
+    /// the last frame.
+    fn poll_drills(&mut self) {
+        if let Some(receiver) = &self.drill_scan {
+            match receiver.try_recv() {
+                Ok(mut found) => {
+                    found.retain(|drill| !self.drills.iter().any(|queued| queued.fen == drill.fen));
+                    self.drills.splice(0..0, found);
+                    self.drill_scan = None;
                 }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.drill_scan = None,
             }
         }
-
-        // Draw dragged piece if any
-        if let Some((square, pos)) = self.dragging_piece {
-            if let Some(piece) = self.game.position.pieces.iter().find(|p| {
-                let piece_square = bit_scan(p.position);
-                if self.player_color == Color::White {
-                    piece_square == square
-                } else {
-                    piece_square == ((7 - square / 8) * 8 + (7 - square % 8))
-                }
-            }) {
-                // Draw piece at cursor position
-                let piece_char = match (piece.piece_type, piece.color) {
-                    (PieceType::Pawn, Color::White) => "♙",
-                    (PieceType::Knight, Color::White) => "♘",
-                    (PieceType::Bishop, Color::White) => "♗",
-                    (PieceType::Rook, Color::White) => "♖",
-                    (PieceType::Queen, Color::White) => "♕",
-                    (PieceType::King, Color::White) => "♔",
-                    (PieceType::Pawn, Color::Black) => "♟",
-                    (PieceType::Knight, Color::Black) => "♞",
-                    (PieceType::Bishop, Color::Black) => "♝",
-                    (PieceType::Rook, Color::Black) => "♜",
-                    (PieceType::Queen, Color::Black) => "♛",
-                    (PieceType::King, Color::Black) => "♚",
-                };
-                ui.painter().text(
-                    pos,
-                    egui::Align2::CENTER_CENTER,
-                    piece_char,
-                    egui::FontId::proportional(square_size * 0.8),
-                    if piece.color == Color::White {
-                        egui::Color32::WHITE
-                    } else {
-                        egui::Color32::BLACK
-                    },
-                );
+        for tab in &mut self.tabs {
+            if let Some(clean) = tab.drill_result.take() {
+                self.drill_stats.record(clean);
+            }
+            if let Some((line, clean)) = tab.trainer_result.take() {
+                self.recall_stats.record(&line, clean);
             }
         }
     }
 
-    fn draw_color_selector(&mut self, ui: &mut egui::Ui) {
-        ui.horizontal(|ui| {
-            ui.heading("Choose your color:");
-            if ui.button("Play as White").clicked() {
-                // Reset everything first
-                self.game = Game::new();
-                self.player_color = Color::White;
-                self.is_player_turn = true;  // White (player) moves first
-                self.selected_square = None;
-                self.evaluation = 0;
-                self.engine_thinking = false;
-                
-                // Force update of legal moves
-                let game_copy = self.game.clone();
-                self.game.position.update_all_legal_moves(&game_copy);
-                self.game.position.active_color = Color::White;  // Ensure White moves first
-                println!("Starting new game - player as White"); // Debug print
-            }
-            if ui.button("Play as Black").clicked() {
-                // Reset everything first
-                self.game = Game::new();
-                self.player_color = Color::Black;
-                self.is_player_turn = false;  // White (engine) moves first
-                self.selected_square = None;
-                self.evaluation = 0;
-                self.engine_thinking = false;
-                
-                // Force update of legal moves and active color
-                let game_copy = self.game.clone();
-                self.game.position.update_all_legal_moves(&game_copy);
-                self.game.position.active_color = Color::White;  // Ensure White moves first
-                println!("Starting new game - player as Black"); // Debug print
-                
-                // Make first move as White
-                self.make_engine_move();
+    /// Hands the tabs' notices to the window, naming the tab for those
+    /// from tabs in the background.
+    fn collect_notices(&mut self) {
+        for (index, tab) in self.tabs.iter_mut().enumerate() {
+            for mut notice in tab.notices.drain(..) {
+                if index != self.active {
+                    notice.text = format!("{}: {}", tab.title, notice.text);
+                }
+                self.notices.push(notice);
             }
-        });
+        }
     }
 
-    // Add a function to draw the move list
-    fn draw_move_list(&self, ui: &mut egui::Ui) {
-        ui.vertical_centered(|ui| {
-            ui.add_space(20.0);
-            ui.heading("Move History");
-            ui.add_space(10.0);
-            
-            egui::ScrollArea::vertical()
-                .max_height(ui.available_height() - 60.0)
-                .show(ui, |ui| {
-                    for move_text in &self.move_history {
-                        ui.label(move_text);
+    /// Closes a tab, stopping its engine. The last tab cannot be closed.
+    fn close_tab(&mut self, index: usize) {
+        if self.tabs.len() <= 1 {
+            return;
+        }
+        let tab = self.tabs.remove(index);
+        tab.stop.store(true, Ordering::Relaxed);
+        if self.active >= index && self.active > 0 {
+            self.active -= 1;
+        }
+    }
+
+    fn draw_tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut close = None;
+        ui.horizontal(|ui| {
+            for (index, tab) in self.tabs.iter().enumerate() {
+                let label = if tab.engine_thinking() { format!("{} *", tab.title) } else { tab.title.clone() };
+                if ui.selectable_label(index == self.active, label).clicked() {
+                    self.active = index;
+                }
+                if self.tabs.len() > 1 && ui.small_button("x").clicked() {
+                    close = Some(index);
+                }
+                ui.add_space(8.0);
+            }
+            if ui.button("+ Game").clicked() {
+                self.open_tab(TabMode::VsEngine);
+            }
+            if ui.button("+ Engine match").clicked() {
+                self.open_tab(TabMode::EngineMatch);
+            }
+            if ui.button("Paste position").clicked() {
+                self.paste.get_or_insert_with(PasteDialog::default);
+            }
+            if ui.button("Position library").clicked() {
+                self.library_window.get_or_insert_with(LibraryWindow::default);
+            }
+            if ui.button("Mate drill").clicked() {
+                if let Err(e) = self.open_drill() {
+                    self.notices.push(Notice::error(e));
+                }
+            }
+            if ui.button("Repertoire trainer").clicked() && self.repertoire.is_none() {
+                self.repertoire = Some(RepertoireDialog { text: self.repertoire_pgn.clone(), side: Color::White, error: None });
+            }
+            ui.menu_button("Endgame practice", |ui| {
+                for ending in Ending::ALL {
+                    if ui.button(ending.name()).clicked() {
+                        if let Err(e) = self.open_practice(ending) {
+                            self.notices.push(Notice::error(e));
+                        }
+                        ui.close_menu();
                     }
-                });
+                }
+            });
         });
+        if let Some(index) = close {
+            self.close_tab(index);
+        }
     }
 }
 
 impl eframe::App for ChessGUI {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // Set dark mode
         ctx.set_visuals(egui::Visuals::dark());
 
-        // Top panel for title and color selection
+        if let Some(warm_up) = &mut self.warm_up {
+            if !warm_up.poll() {
+                warm_up.draw(ctx);
+                ctx.request_repaint_after(Duration::from_millis(50));
+                return;
+            }
+            self.notices.push(Notice::info(warm_up.summary()));
+            self.warm_up = None;
+        }
+        debug_assert_eq!(crate::tables_built(), 1, "Lookup tables were rebuilt during play");
+
+        // Every tab's engine keeps playing, shown or not, and every clock runs
+        let now = Instant::now();
+        for tab in &mut self.tabs {
+            tab.check_flag(now);
+            tab.poll_engine_move();
+            tab.poll_analysis();
+            if !tab.is_player_turn {
+                if let Some(book) = self.book.as_deref().filter(|_| self.engine_options.own_book) {
+                    tab.play_engine_book_move(book);
+                }
+                tab.make_engine_move();
+            }
+            tab.sync_analysis(&mut self.analysis_cache);
+        }
+        if self.tabs.iter().any(|tab| tab.clocks.is_some_and(|clocks| clocks.running.is_some())) {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        if self.tabs.iter().any(GameController::analyzing) {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+
+        self.poll_drills();
+        self.collect_notices();
+        self.notices.draw(ctx);
+        self.handle_paste(ctx);
+        self.handle_typed_squares(ctx);
+        self.draw_library(ctx);
+        self.draw_memory(ctx);
+        self.draw_engine_options(ctx);
+        self.draw_strength(ctx);
+        self.draw_game_log(ctx);
+        self.draw_repertoire_dialog(ctx);
+        self.draw_quit_prompt(ctx, frame);
+        let remote = self.remote.clone();
+
+        // Top panel for title, tabs and color selection
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.add_space(10.0);
+            self.draw_tab_bar(ui);
+            ui.add_space(10.0);
+            let tab = &mut self.tabs[self.active];
             ui.horizontal(|ui| {
                 ui.heading("RustChess Engine");
                 ui.add_space(20.0);
-                if ui.button("Play as White").clicked() {
-                    self.game = Game::new();
-                    self.player_color = Color::White;
-                    self.is_player_turn = true;
-                    self.selected_square = None;
-                    self.evaluation = 0;
-                    self.engine_thinking = false;
-                    let game_copy = self.game.clone();
-                    self.game.position.update_all_legal_moves(&game_copy);
-                    self.game.position.active_color = Color::White;
-                }
-                if ui.button("Play as Black").clicked() {
-                    self.game = Game::new();
-                    self.player_color = Color::Black;
-                    self.is_player_turn = false;
-                    self.selected_square = None;
-                    self.evaluation = 0;
-                    self.engine_thinking = false;
-                    let game_copy = self.game.clone();
-                    self.game.position.update_all_legal_moves(&game_copy);
-                    self.game.position.active_color = Color::White;
-                    self.make_engine_move();
+                match tab.mode {
+                    TabMode::VsEngine => {
+                        if ui.button("Play as White").clicked() {
+                            tab.new_game(Color::White);
+                        }
+                        if ui.button("Play as Black").clicked() {
+                            tab.new_game(Color::Black);
+                            tab.make_engine_move();
+                        }
+                    }
+                    TabMode::EngineMatch => {
+                        if ui.button("Restart match").clicked() {
+                            tab.new_game(Color::White);
+                        }
+                    }
+                    TabMode::Analysis => {
+                        if ui.button("Flip board").clicked() {
+                            tab.player_color = match tab.player_color {
+                                Color::White => Color::Black,
+                                Color::Black => Color::White,
+                            };
+                        }
+                        tab.draw_drill(ui, &self.drill_stats);
+                        tab.draw_practice(ui);
+                        tab.draw_trainer(ui, &self.recall_stats);
+                    }
+                }
+                if tab.mode != TabMode::Analysis {
+                    ui.add_space(20.0);
+                    let mut opponent = tab.opponent;
+                    let name = |opponent: Option<ReferenceOpponent>| opponent.map_or("Engine".to_string(), |o| o.to_string());
+                    egui::ComboBox::from_label("Opponent")
+                        .selected_text(name(opponent))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut opponent, None, name(None));
+                            for baseline in BASELINE_OPPONENTS {
+                                ui.selectable_value(&mut opponent, Some(baseline), name(Some(baseline)));
+                            }
+                        });
+                    if opponent != tab.opponent {
+                        tab.set_opponent(opponent);
+                    }
+                }
+                ui.add_space(20.0);
+                ui.toggle_value(&mut self.game_log, "Game log")
+                    .on_hover_text("The position and every move in words");
+                if ui.button("Export GIF").clicked() {
+                    tab.export_gif("game.gif");
+                }
+                if ui.button("Export report").clicked() {
+                    tab.export_report("game.pgn");
+                }
+                if ui.button("Export debug bundle").clicked() {
+                    tab.export_debug_bundle("debug_bundle.zip");
+                }
+                ui.menu_button("Settings", |ui| {
+                    tab.draw_engine_settings(ui);
+                    ui.separator();
+                    ui.checkbox(&mut self.keep_analysis, "Keep analysis between runs");
+                    let cached = self.analysis_cache.len();
+                    if ui.add_enabled(cached > 0, egui::Button::new(format!("Clear analysis cache ({} positions)", cached))).clicked() {
+                        self.analysis_cache.clear();
+                    }
+                    ui.separator();
+                    if ui.button("Engine options...").clicked() {
+                        self.options_window = Some(OptionsWindow { options: self.engine_options, error: None });
+                        ui.close_menu();
+                    }
+                    if ui.button("Memory...").clicked() {
+                        self.memory_window = Some(MemoryWindow {
+                            hash_mb: self.settings.hash_mb,
+                            cache_positions: self.settings.cache_positions,
+                        });
+                        ui.close_menu();
+                    }
+                    if ui.button("Strength history...").clicked() {
+                        self.strength_window = Some(StrengthWindow::load());
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Assets", |ui| draw_assets(ui, &self.assets));
+                });
+                if tab.mode == TabMode::VsEngine {
+                    self.settings = self.settings.with_limits(&tab.limits);
                 }
             });
             ui.add_space(10.0);
         });
 
+        let book = self.book.as_deref();
+        let busy = self.drill_scan.is_some().then_some("Collecting mate drills...");
+        let tab = &mut self.tabs[self.active];
+
         // Left panel for evaluation bar
         egui::SidePanel::left("eval_panel")
             .exact_width(60.0)
@@ -556,7 +3400,7 @@ impl eframe::App for ChessGUI {
                     ui.add_space(20.0);
                     ui.heading("Eval");
                     ui.add_space(10.0);
-                    self.draw_evaluation_bar(ui);
+                    tab.draw_evaluation_bar(ui);
                 });
             });
 
@@ -565,14 +3409,16 @@ impl eframe::App for ChessGUI {
             .exact_width(200.0)
             .resizable(false)
             .show(ctx, |ui| {
-                self.draw_move_list(ui);
+                tab.draw_analysis(ui, remote.as_ref());
+                tab.draw_explorer(ui, book);
+                tab.draw_move_list(ui);
             });
 
         // Central panel for the chess board
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(20.0);
-                self.draw_board(ui);
+                tab.draw_board(ui);
                 ui.add_space(20.0);
             });
         });
@@ -581,26 +3427,84 @@ impl eframe::App for ChessGUI {
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
             ui.add_space(10.0);
             ui.horizontal(|ui| {
-                ui.label(if self.is_player_turn {
-                    "Your turn to move"
+                if tab.result.is_over() {
+                    ui.label(egui::RichText::new(tab.status()).strong());
                 } else {
-                    "Engine is thinking..."
-                });
-                if self.game.position.is_in_check(&self.game) {
-                    ui.label("CHECK!");
+                    ui.label(tab.status());
+                    if tab.in_check {
+                        ui.label("CHECK!");
+                    }
+                }
+                if tab.engine_thinking() {
+                    ui.spinner();
+                }
+                if let Some(clocks) = &tab.clocks {
+                    ui.add_space(20.0);
+                    for (name, color) in [("White", Color::White), ("Black", Color::Black)] {
+                        let left = clocks.remaining(color, now);
+                        let text = egui::RichText::new(format!("{} {}", name, format_clock(left))).monospace();
+                        let ticking = clocks.running.is_some_and(|(side, _)| side == color);
+                        ui.label(if ticking { text.strong() } else { text });
+                    }
+                    if tab.low_on_time(now) {
+                        ui.colored_label(egui::Color32::LIGHT_RED, "⚠ Low on time");
+                    }
+                }
+                if let Some(file) = tab.typed_file {
+                    ui.add_space(20.0);
+                    ui.monospace(format!("{}_", file));
+                } else if let Some(feedback) = &tab.keyboard_feedback {
+                    ui.add_space(20.0);
+                    live_label(ui, "keyboard_feedback", feedback);
+                }
+                if let Some(last) = tab.move_history.last() {
+                    ui.add_space(20.0);
+                    live_label(ui, "move_announcement", &last.spoken);
+                }
+                if let Some(rejection) = &tab.rejection {
+                    ui.add_space(20.0);
+                    ui.colored_label(egui::Color32::LIGHT_RED, rejection);
+                }
+                if let Some(busy) = busy {
+                    ui.add_space(20.0);
+                    ui.spinner();
+                    ui.label(busy);
+                }
+                if let Some(notice) = &self.notices.latest {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(notice_text(notice));
+                    });
                 }
             });
             ui.add_space(10.0);
         });
 
-        // If it's the engine's turn, make a move
-        if !self.is_player_turn {
-            self.make_engine_move();
-        }
-
         // Request continuous redraws
         ctx.request_repaint();
     }
+
+    /// Asks about unfinished games before letting the window close, and
+    /// stops every engine once it may.
+    fn on_close_event(&mut self) -> bool {
+        if self.quit != QuitState::Confirmed && self.tabs.iter().any(GameController::is_unfinished) {
+            self.quit = QuitState::Asking;
+            return false;
+        }
+        self.stop_engines();
+        true
+    }
+
+    /// Called periodically and on exit; eframe flushes the storage afterwards.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(SETTINGS_KEY, self.settings.to_text());
+        storage.set_string(LIBRARY_KEY, self.library.to_json());
+        storage.set_string(DRILL_STATS_KEY, self.drill_stats.to_text());
+        storage.set_string(RECALL_STATS_KEY, self.recall_stats.to_text());
+        storage.set_string(REPERTOIRE_KEY, self.repertoire_pgn.clone());
+        storage.set_string(KEEP_ANALYSIS_KEY, self.keep_analysis.to_string());
+        let cache = if self.keep_analysis { self.analysis_cache.to_text() } else { String::new() };
+        storage.set_string(ANALYSIS_CACHE_KEY, cache);
+    }
 }
 
 pub fn run_gui() -> Result<(), eframe::Error> {
@@ -615,3 +3519,767 @@ pub fn run_gui() -> Result<(), eframe::Error> {
         Box::new(|cc| Box::new(ChessGUI::new(cc)))
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{GameOutcome, LearningBook};
+    use crate::report::Judgement;
+    use crate::position::Position;
+
+    fn game_at(fen: &str) -> Game {
+        let mut game = Game::new();
+        game.position = Position::from_fen(fen, &game).unwrap();
+        game
+    }
+
+    #[test]
+    fn test_game_result_after_either_side_moves() {
+        assert_eq!(GameResult::of(&Game::new()), GameResult::Ongoing);
+
+        // Fool's mate: the engine (Black) has just mated the player
+        let mated = game_at("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        let result = GameResult::of(&mated);
+        assert_eq!(result, GameResult::Checkmate { winner: Color::Black });
+        assert_eq!(result.announcement(Color::White), "Checkmate! The engine wins.");
+        assert_eq!(result.announcement(Color::Black), "Checkmate! You win!");
+
+        let stalemate = game_at("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(GameResult::of(&stalemate), GameResult::Stalemate);
+    }
+
+    #[test]
+    fn test_engine_match_tab_plays_both_sides() {
+        let mut tab = GameController::new("Match 1".to_string(), TabMode::EngineMatch);
+        tab.set_opponent(Some(ReferenceOpponent::Random));
+        assert_eq!(tab.player.lock().unwrap().name(), "random mover");
+        assert!(!tab.is_player_turn);
+
+        let started = std::time::Instant::now();
+        while tab.played_moves.len() < 2 {
+            assert!(started.elapsed() < std::time::Duration::from_secs(10), "engine match stalled");
+            tab.make_engine_move();
+            tab.poll_engine_move();
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(tab.move_history[0].text.starts_with("1. "));
+        assert!(tab.move_history[1].text.starts_with("1. ... "));
+        assert!(!tab.is_player_turn);
+
+        tab.new_game(Color::White);
+        assert!(tab.played_moves.is_empty() && tab.move_history.is_empty());
+        assert!(!tab.engine_thinking());
+    }
+
+    #[test]
+    fn test_engine_ponders_on_the_expected_reply() {
+        fn wait_for_ply(tab: &mut GameController, plies: usize) {
+            let started = Instant::now();
+            while tab.played_moves.len() < plies {
+                assert!(started.elapsed() < Duration::from_secs(10), "engine never answered");
+                tab.make_engine_move();
+                tab.poll_engine_move();
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.limits = SearchLimits { depth: Some(3), ..SearchLimits::default() };
+        tab.pondering = true;
+        tab.handle_move(12, 28);  // e2e4
+        wait_for_ply(&mut tab, 2);
+
+        // Playing the expected reply hands the ponder search over
+        let reply = tab.ponder.as_ref().expect("not pondering").reply;
+        tab.finish_user_move(reply);
+        tab.make_engine_move();
+        assert!(tab.ponder.is_none() && tab.engine_thinking());
+        wait_for_ply(&mut tab, 4);
+
+        // Any other move stops it and searches afresh
+        let reply = tab.ponder.as_ref().expect("not pondering").reply;
+        let game_copy = tab.game.clone();
+        let other = tab.game.position.get_all_legal_moves(&game_copy).into_iter().find(|&mov| mov != reply).unwrap();
+        tab.finish_user_move(other);
+        wait_for_ply(&mut tab, 6);
+        assert_eq!(tab.played_moves[4], other);
+
+        tab.new_game(Color::White);
+        assert!(tab.ponder.is_none());
+    }
+
+    #[test]
+    fn test_engine_lines_are_kept_with_the_moves() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.limits = SearchLimits { depth: Some(2), ..SearchLimits::default() };
+        tab.handle_move(12, 28);  // e2e4
+        let started = std::time::Instant::now();
+        while tab.played_moves.len() < 2 {
+            assert!(started.elapsed() < std::time::Duration::from_secs(10), "engine never answered");
+            tab.make_engine_move();
+            tab.poll_engine_move();
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // The user's move was never analyzed; the engine's reply carries its line
+        assert!(tab.move_history[0].analysis.is_none());
+        let line = tab.move_history[1].analysis.clone().unwrap();
+        assert_eq!(line.best_move, tab.game_moves()[1].to_string());
+        assert_eq!(line.pv[0], line.best_move);
+        assert!(tab.analysis.is_none());
+
+        // Going back to the reply shows its line without searching again
+        tab.viewed_ply = Some(1);
+        assert_eq!(tab.shown_analysis(), Some(&line));
+        tab.viewed_ply = Some(0);
+        assert_eq!(tab.shown_analysis(), None);
+
+        // The search is logged for debug bundles, from the position searched
+        let bundle = tab.debug_bundle();
+        assert!(bundle.search_log.starts_with(
+            "engine move | rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 | depth 2 "), "{}", bundle.search_log);
+        assert_eq!(bundle.fen, format!("{}\n", tab.game.position.to_fen()));
+        assert!(bundle.pgn.contains(&format!("1. e2e4 {}", line.best_move)), "{}", bundle.pgn);
+        assert!(bundle.settings.contains("mode=VsEngine\n"));
+    }
+
+    #[test]
+    fn test_squares_map_to_the_screen_both_ways() {
+        let board = egui::Rect::from_min_size(egui::pos2(10.0, 20.0), egui::vec2(400.0, 400.0));
+        // a1 is bottom left for White and top right for Black
+        assert_eq!(square_rect(board, 0, Color::White).left_bottom(), board.left_bottom());
+        assert_eq!(square_rect(board, 0, Color::Black).right_top(), board.right_top());
+        assert_eq!(square_at(board, egui::pos2(15.0, 25.0), Color::White), Some(56));  // a8
+        assert_eq!(square_at(board, egui::pos2(15.0, 25.0), Color::Black), Some(7));  // h1
+        assert_eq!(square_at(board, egui::pos2(5.0, 25.0), Color::White), None);
+        for orientation in [Color::White, Color::Black] {
+            for square in 0..64 {
+                let rect = square_rect(board, square, orientation);
+                assert_eq!(square_at(board, rect.center(), orientation), Some(square));
+            }
+        }
+
+        // Clicks select and move by board square whichever side is shown
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::Analysis);
+        tab.player_color = Color::Black;
+        tab.handle_square_click(12, Some(egui::pos2(0.0, 0.0)));  // e2
+        assert_eq!(tab.selected_square, Some(12));
+        tab.handle_square_click(28, None);  // e4
+        assert_eq!(tab.played_moves, vec![Move::encode(12, 28, None)]);
+    }
+
+    #[test]
+    fn test_engine_waits_for_the_player() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        assert!(tab.is_player_turn);
+        tab.make_engine_move();
+        assert!(!tab.engine_thinking());
+        assert_eq!(tab.status(), "Your turn to move");
+    }
+
+    #[test]
+    fn test_clocks_run_out() {
+        let start = Instant::now();
+        let mut clocks = GameClocks::new(Duration::from_secs(5), Duration::from_secs(1));
+        assert_eq!(clocks.flagged(start + Duration::from_secs(60)), None);
+        clocks.press(Color::White, start);
+        clocks.press(Color::Black, start + Duration::from_secs(2));
+        assert_eq!(clocks.remaining(Color::Black, start + Duration::from_secs(3)), Duration::from_secs(4));
+        assert_eq!(clocks.remaining(Color::White, start + Duration::from_secs(3)), Duration::from_secs(5));
+        assert_eq!(clocks.flagged(start + Duration::from_secs(8)), Some(Color::White));
+
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.time_control = Some((Duration::from_secs(5), Duration::ZERO));
+        tab.new_game(Color::White);
+        tab.play_move(Move::encode(12, 28, None));
+        assert!(tab.low_on_time(Instant::now()));
+        tab.check_flag(Instant::now() + Duration::from_secs(6));
+        assert_eq!(tab.result, GameResult::OnTime { winner: Color::White });
+        assert_eq!(tab.status(), "The engine ran out of time. You win!");
+
+        // A flag wins only for a side that could still mate
+        tab.game = game_at("4k3/8/8/8/8/8/8/4KR2 b - - 0 1");
+        tab.result = GameResult::Ongoing;
+        tab.clocks = Some(GameClocks::new(Duration::from_secs(5), Duration::ZERO));
+        tab.clocks.as_mut().unwrap().press(Color::White, Instant::now());
+        tab.check_flag(Instant::now() + Duration::from_secs(6));
+        assert_eq!(tab.result, GameResult::OnTime { winner: Color::White });
+        tab.game = game_at("4k3/8/8/8/8/8/8/4KR2 w - - 0 1");
+        tab.result = GameResult::Ongoing;
+        tab.clocks = Some(GameClocks::new(Duration::from_secs(5), Duration::ZERO));
+        tab.clocks.as_mut().unwrap().press(Color::Black, Instant::now());
+        tab.check_flag(Instant::now() + Duration::from_secs(6));
+        assert_eq!(tab.result, GameResult::TimeoutDraw);
+    }
+
+    #[test]
+    fn test_capture_with_check_animates_all_three_events() {
+        let game = game_at("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1");
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.game = game;
+
+        // Qh5xf7+ (h5 = 39, f7 = 53)
+        let events = tab.play_move(Move::encode(39, 53, None));
+        assert_eq!(events, vec![
+            BoardEvent::PieceMoved { from: 39, to: 53, piece: PieceType::Queen, color: Color::White },
+            BoardEvent::PieceCaptured { square: 53, piece: PieceType::Pawn, color: Color::Black },
+            BoardEvent::Check { checker: 53 },
+        ]);
+        assert_eq!(tab.move_history.iter().map(|entry| entry.text.as_str()).collect::<Vec<_>>(), ["1. Qxf7#"]);
+
+        let start = tab.animator.active[0].1;
+        assert!(tab.animator.is_arriving(53));
+        assert!(tab.animator.is_flashing(53, start));
+        assert!(!tab.animator.is_flashing(53, start + FLASH_PERIOD));
+        tab.animator.tick(start + MOVE_ANIMATION);
+        assert!(!tab.animator.is_arriving(53));
+        tab.animator.tick(start + CHECK_FLASH);
+        assert!(tab.animator.active.is_empty());
+    }
+
+    /// Waits for the tab's analysis panel to get its line.
+    fn finish_analysis(tab: &mut GameController) {
+        while tab.analyzing() {
+            thread::sleep(Duration::from_millis(10));
+            tab.poll_analysis();
+        }
+    }
+
+    #[test]
+    fn test_analysis_runs_in_the_background() {
+        let mut tab = GameController::new("Analysis 1".to_string(), TabMode::Analysis);
+        let started = Instant::now();
+        tab.analyze_position(None);
+        assert!(started.elapsed() < Duration::from_millis(ANALYSIS_TIME_MS / 2));
+        assert!(tab.analyzing() && tab.analysis.is_none());
+        finish_analysis(&mut tab);
+        assert!(tab.analysis.is_some());
+
+        // A line for a position the game has left is not shown
+        tab.analysis = None;
+        tab.analyze_position(None);
+        tab.handle_move(12, 28);  // e2e4
+        finish_analysis(&mut tab);
+        assert!(tab.analysis.is_none());
+        assert!(tab.notices.is_empty());
+    }
+
+    #[test]
+    fn test_remote_failure_is_shown() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let mut tab = GameController::new("Analysis 1".to_string(), TabMode::Analysis);
+        tab.analyze_position(Some(&RemoteAnalyzer::new(&addr)));
+        finish_analysis(&mut tab);
+        assert_eq!(tab.analysis.as_ref().unwrap().source, AnalysisSource::Local);
+        assert_eq!(tab.notices.len(), 1);
+        assert!(tab.notices[0].text.ends_with("; analyzed locally"), "{}", tab.notices[0].text);
+    }
+
+    #[test]
+    fn test_what_if_rules_out_the_best_moves() {
+        let mut tab = GameController::new("Analysis 1".to_string(), TabMode::Analysis);
+        tab.analyze_position(None);
+        finish_analysis(&mut tab);
+        let best = tab.analysis.as_ref().unwrap().best_move.clone();
+
+        tab.analyze_without_best();
+        let (excluded, line) = tab.what_if.clone().unwrap();
+        assert_eq!(excluded.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), vec![best.clone()]);
+        assert_ne!(line.best_move, best);
+        tab.analyze_without_best();
+        assert_eq!(tab.what_if.as_ref().unwrap().0.len(), 2);
+
+        tab.handle_move(12, 28);  // e2e4
+        assert!(tab.what_if.is_none());
+    }
+
+    #[test]
+    fn test_pasted_pgn_loads_into_an_analysis_tab() {
+        let imported = pgn::import("[FEN \"4k3/8/8/8/8/8/8/R3K3 w - - 0 1\"]\n1. Ra7 Kf8 *").unwrap();
+        let mut tab = GameController::new("Analysis 1".to_string(), TabMode::Analysis);
+        tab.load(&imported).unwrap();
+        assert_eq!(tab.start_fen, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(tab.move_history.len(), 2);
+        assert_eq!(tab.status(), "White to move");
+        assert!(tab.animator.active.is_empty());
+
+        // Either side's pieces can be moved: Kf2 (e1 = 4, f2 = 13)
+        tab.handle_move(4, 13);
+        assert_eq!(tab.played_moves.len(), 3);
+        assert!(tab.is_player_turn);
+        assert_eq!(tab.status(), "Black to move");
+    }
+
+    #[test]
+    fn test_moves_typed_as_coordinates() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.handle_typed_text("g1");
+        assert_eq!(tab.selected_square, Some(6));
+        assert_eq!(tab.keyboard_feedback.as_deref(), Some("White knight on g1: f3, h3"));
+
+        // Another piece of one's own is picked instead; the same square again puts it back
+        tab.handle_typed_text("E");
+        assert_eq!(tab.typed_file, Some('e'));
+        tab.handle_typed_text("2");
+        assert_eq!(tab.keyboard_feedback.as_deref(), Some("White pawn on e2: e3, e4"));
+        tab.handle_typed_text("e2");
+        assert_eq!(tab.selected_square, None);
+        tab.handle_typed_text("e5");
+        assert_eq!(tab.keyboard_feedback.as_deref(), Some("No white piece on e5"));
+
+        // Spaces and typos in between are skipped
+        tab.handle_typed_text("e2 x e4");
+        assert_eq!(tab.played_moves.len(), 1);
+        assert_eq!(tab.keyboard_feedback.as_deref(), Some("Played e4"));
+        assert!(!tab.is_player_turn);
+        tab.handle_typed_text("e7");
+        assert_eq!(tab.keyboard_feedback.as_deref(), Some("Not your turn: the engine is thinking"));
+
+        // A refused move is explained as a click would be
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.handle_typed_text("b1b3");
+        assert_eq!(tab.selected_square, None);
+        assert!(tab.played_moves.is_empty());
+        assert_eq!(tab.keyboard_feedback, None);
+        assert!(tab.rejection.is_some());
+    }
+
+    #[test]
+    fn test_moves_are_announced() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::Analysis);
+        for (from, to) in [(12, 28), (51, 35), (28, 35), (59, 35)] {
+            tab.play_move(Move::encode(from, to, None));
+        }
+        let spoken: Vec<&str> = tab.move_history.iter().map(|entry| entry.spoken.as_str()).collect();
+        assert_eq!(spoken, ["White pawn to e4", "Black pawn to d5", "White pawn takes pawn on d5", "Black queen takes pawn on d5"]);
+        assert_eq!(tab.move_history[3].text, "2. ... Qxd5");
+    }
+
+    #[test]
+    fn test_rejected_moves_are_explained() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.game = game_at("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1");
+
+        // Ne2-c3 (e2 = 12, c3 = 18) would expose the king
+        tab.handle_move(12, 18);
+        assert!(tab.played_moves.is_empty());
+        assert_eq!(tab.rejection.as_deref(), Some("That knight is pinned to your king"));
+
+        // Ke1-d1 (e1 = 4, d1 = 3) is fine and clears the message
+        tab.handle_move(4, 3);
+        assert_eq!(tab.played_moves.len(), 1);
+        assert_eq!(tab.rejection, None);
+
+        tab.handle_square_click(60, Some(egui::pos2(0.0, 0.0)));
+        assert_eq!(tab.rejection.as_deref(), Some("Not your turn: the engine is thinking"));
+    }
+
+    #[test]
+    fn test_explorer_lists_and_plays_book_moves() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        let start = tab.game.position.get_hash(&tab.game);
+        let (e4, d4) = (Move::encode(12, 28, None), Move::encode(11, 27, None));
+        let mut book = LearningBook::new();
+        book.learn(&[(start, d4)], GameOutcome::Loss);
+        book.learn(&[(start, e4)], GameOutcome::Win);
+        // Hash collisions may store moves that are illegal here
+        book.learn(&[(start, Move::encode(12, 44, None))], GameOutcome::Win);
+
+        let moves: Vec<u64> = tab.book_moves(&book).iter().map(|entry| entry.mov).collect();
+        assert_eq!(moves, vec![e4, d4]);
+
+        tab.play_book_move(e4);
+        assert_eq!(tab.played_moves, vec![e4]);
+        assert!(!tab.is_player_turn);
+        tab.play_book_move(d4);
+        assert_eq!(tab.played_moves.len(), 1);
+    }
+
+    #[test]
+    fn test_check_state_is_updated_once_per_move() {
+        let mut tab = GameController::new("Game 1".to_string(), TabMode::VsEngine);
+        tab.game = game_at("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1");
+        tab.update_result();
+        assert!(!tab.in_check);
+
+        // Qh5xf7#
+        tab.play_move(Move::encode(39, 53, None));
+        tab.update_result();
+        assert!(tab.in_check);
+        assert_eq!(tab.result, GameResult::Checkmate { winner: Color::White });
+
+        tab.new_game(Color::White);
+        assert!(!tab.in_check);
+        assert_eq!(tab.result, GameResult::Ongoing);
+    }
+
+    #[test]
+    fn test_settings_are_remembered() {
+        let settings = Settings { min_think_ms: 1200, instant: true, hash_mb: 4, cache_positions: 300 };
+        assert_eq!(Settings::parse(&settings.to_text()), settings);
+        assert_eq!(Settings::parse("min_think_ms=soon\ncolour=blue"), Settings::default());
+
+        // New games against the engine start from the remembered settings
+        let gui = ChessGUI::with_settings(settings);
+        assert_eq!(gui.tabs[0].limits.min_time, Some(Duration::from_millis(1200)));
+        assert!(gui.tabs[0].limits.instant);
+        assert_eq!(Settings::default().with_limits(&gui.tabs[0].limits),
+            Settings { hash_mb: DEFAULT_HASH_MB, cache_positions: DEFAULT_MAX_POSITIONS, ..settings });
+        assert_eq!(gui.tabs[0].hash_mb, 4);
+        assert_eq!(gui.analysis_cache.max_positions(), 300);
+    }
+
+    #[test]
+    fn test_engine_options_reach_every_engine() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        let options = EngineOptions { skill_level: 4, own_book: true, ..EngineOptions::default() };
+        gui.set_engine_options(options);
+        gui.open_tab(TabMode::VsEngine);
+        assert!(gui.tabs.iter().all(|tab| tab.engine_options == options));
+
+        // The engine answers 1. e4 from the book without searching
+        let tab = &mut gui.tabs[1];
+        tab.handle_move(12, 28);
+        let after_e4 = tab.game.position.get_hash(&tab.game);
+        let mut learned = LearningBook::new();
+        learned.learn(&[(after_e4, Move::encode(50, 34, None))], GameOutcome::Win);
+        tab.play_engine_book_move(&learned);
+        assert_eq!(tab.move_history.last().map(|entry| entry.text.as_str()), Some("1. ... c5"));
+        assert!(tab.is_player_turn && !tab.engine_thinking());
+        // Out of the book nothing is played; the search takes over
+        tab.is_player_turn = false;
+        tab.play_engine_book_move(&learned);
+        assert_eq!(tab.played_moves.len(), 2);
+    }
+
+    #[test]
+    fn test_memory_budgets() {
+        let mut gui = ChessGUI::with_settings(Settings { hash_mb: 2, ..Settings::default() });
+        gui.open_tab(TabMode::Analysis);
+        let engines = |gui: &ChessGUI| gui.memory_use().iter().filter(|part| part.name.ends_with("engines")).map(|part| part.bytes).collect::<Vec<_>>();
+        assert_eq!(engines(&gui).len(), 2);
+        assert!(engines(&gui).iter().all(|&bytes| bytes > 3 << 20 && bytes <= 4 << 20));
+
+        // A busy player gets its new table before its next search
+        let busy = gui.tabs[0].player.clone();
+        let guard = busy.lock().unwrap();
+        gui.set_memory_budgets(1, 2);
+        assert_eq!(gui.tabs[0].pending_hash_mb, Some(1));
+        drop(guard);
+        assert_eq!(gui.tabs[1].memory_bytes(), gui.tabs[1].analysis_engine.lock().unwrap().memory_bytes() * 2);
+        assert!(gui.tabs[1].memory_bytes() <= 2 << 20);
+        assert_eq!((gui.settings.hash_mb, gui.settings.cache_positions), (1, 2));
+
+        let line = |depth| AnalysisLine { source: AnalysisSource::Local, best_move: "e2e4".to_string(), score: 0, pv: vec!["e2e4".to_string()], depth };
+        for hash in 0..3 {
+            gui.analysis_cache.store(hash, &line(hash as i32));
+        }
+        assert_eq!(gui.analysis_cache.len(), 2);
+        assert!(gui.analysis_cache.get(0).is_none());
+    }
+
+    #[test]
+    fn test_bookmarks_reopen_in_analysis_tabs() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_pasted("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let draft = Bookmark { name: "Rook ending".to_string(), folder: "Endgames".to_string(), ..Bookmark::default() };
+        gui.bookmark_position(&draft).unwrap();
+        assert!(gui.bookmark_position(&Bookmark::default()).is_err());
+        assert_eq!(gui.library.bookmarks()[0].fen, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        gui.open_bookmark(0).unwrap();
+        assert_eq!(gui.tabs.len(), 3);
+        assert_eq!(gui.tabs[gui.active].title, "Rook ending");
+        assert_eq!(gui.tabs[gui.active].mode, TabMode::Analysis);
+        assert!(gui.open_bookmark(1).is_err());
+    }
+
+    #[test]
+    fn test_mate_drills_check_moves_and_count_streaks() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_drill().unwrap();
+        assert_eq!(gui.drills.len(), drills::BUNDLED_DRILLS.len() - 1);
+        let tab = &mut gui.tabs[gui.active];
+        assert_eq!(tab.title, "Mate in 1");
+
+        // Ra2 is legal but no mate; Ra8 mates on the back rank
+        tab.finish_user_move(Move::encode(0, 8, None));
+        assert!(tab.played_moves.is_empty());
+        assert!(tab.rejection.as_deref().is_some_and(|text| text.contains("mate in 1")));
+        tab.finish_user_move(Move::encode(0, 56, None));
+        assert_eq!(tab.result, GameResult::Checkmate { winner: Color::White });
+        gui.poll_drills();
+        assert_eq!(gui.drill_stats, DrillStats { solved: 0, missed: 1, streak: 0, best_streak: 0 });
+
+        // The next drill is Black's, solved cleanly with Rd1
+        gui.open_drill().unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        tab.finish_user_move(Move::encode(59, 3, None));
+        assert_eq!(tab.result, GameResult::Checkmate { winner: Color::Black });
+        gui.poll_drills();
+        assert_eq!(gui.drill_stats.streak, 1);
+        assert_eq!(DrillStats::parse(&gui.drill_stats.to_text()), gui.drill_stats);
+    }
+
+    #[test]
+    fn test_repertoire_trainer_replies_and_records_lines() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        let repertoire = "1. e4 c5 2. Nf3 *\n\n[Event \"French\"]\n1. e4 e6 2. d4 d5 *";
+        assert!(gui.open_trainer("1. e4 *", Color::Black).is_err());
+        assert!(gui.open_trainer("1. e4 (", Color::White).is_err());
+        assert_eq!(gui.tabs.len(), 1);
+        gui.open_trainer(repertoire, Color::White).unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        assert_eq!(tab.title, "White repertoire");
+        assert_eq!(tab.player_color, Color::White);
+
+        // 1. d4 is refused; 1. e4 is answered from the repertoire
+        tab.finish_user_move(Move::encode(11, 27, None));
+        assert!(tab.played_moves.is_empty());
+        assert!(tab.rejection.is_some());
+        tab.finish_user_move(Move::encode(12, 28, None));
+        assert_eq!(tab.played_moves, [Move::encode(12, 28, None), Move::encode(50, 34, None)]);
+        tab.finish_user_move(Move::encode(6, 21, None));
+        assert!(tab.trainer.as_ref().unwrap().is_finished());
+        gui.poll_drills();
+        assert_eq!(gui.recall_stats.totals().missed, 1);
+
+        // The next line starts over from the initial position
+        let tab = &mut gui.tabs[gui.active];
+        tab.next_line(&gui.recall_stats);
+        assert!(tab.played_moves.is_empty());
+        assert_eq!(gui.repertoire_pgn, repertoire);
+    }
+
+    #[test]
+    fn test_endgame_practice_defends_perfectly() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_practice(Ending::Rook).unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        assert_eq!(tab.title, "KR vs K practice");
+        let optimal = tab.practice.unwrap().optimal_moves;
+        assert!(optimal >= 12, "{}", optimal);
+
+        // Perfect play mates in exactly the optimal number of moves
+        let tables = tablebase::tablebases();
+        while !tab.result.is_over() {
+            let mov = tables.best_move(&tab.game.position, &tab.game).unwrap();
+            tab.finish_user_move(mov);
+            assert!(tab.practice.unwrap().moves <= optimal);
+        }
+        assert_eq!(tab.result, GameResult::Checkmate { winner: Color::White });
+        assert_eq!(tab.practice.unwrap().moves, optimal);
+    }
+
+    #[test]
+    fn test_variations_are_explored_edited_and_exported() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_pasted("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 {main line} Nc6").unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        assert!(tab.explores_variations());
+        assert_eq!(tab.played_moves.len(), 4);
+        assert_eq!(tab.variations.lines().len(), 2);
+        let main = tab.line.clone();
+
+        // Going back into the variation keeps the tree; an engine line
+        // there branches off it, and is cached with the move played from it
+        let c5 = tab.variations.lines()[1][..2].to_vec();
+        tab.go_to(&c5);
+        assert_eq!(tab.game_moves(), c5);
+        assert_eq!(tab.game.position.active_color, Color::White);
+        let line = AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: "d2d4".to_string(),
+            score: 0,
+            pv: vec!["d2d4".to_string(), "c5d4".to_string(), "g1f3".to_string(), "e7e7".to_string()],
+            depth: 1,
+        };
+        tab.analysis = Some(line.clone());
+        tab.add_variation(&line.pv);
+        assert_eq!(tab.variations.lines().len(), 3);
+        assert_eq!(tab.variations.main_line(), main);
+        tab.finish_user_move(Move::encode(11, 27, None));  // d4
+        assert_eq!(tab.variations.lines().len(), 3);
+        assert_eq!(tab.analysis, None);
+        tab.go_to(&c5);
+        assert_eq!(tab.analysis, Some(line));
+        assert_eq!(tab.move_history.len(), 2);
+
+        // The PGN pastes back in with the same tree
+        let pgn = tab.to_pgn();
+        assert!(pgn.contains("1. e2e4 e7e5 (1... c7c5 2. g1f3 (2. d2d4 c5d4 3. g1f3)) 2. g1f3 {main line} 2... b8c6 *"), "{}", pgn);
+        let variations = tab.variations.clone();
+        gui.open_pasted(&pgn).unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        assert_eq!(tab.variations, variations);
+
+        // Promoting the variation makes it the main line; deleting it steps
+        // the board back out of it
+        tab.variations.promote(&c5);
+        assert_eq!(tab.variations.main_line()[..2], c5[..]);
+        tab.go_to(&tab.variations.main_line());
+        tab.delete_variation(&c5);
+        assert_eq!(tab.game_moves(), c5[..1]);
+        assert_eq!(tab.variations.main_line(), main);
+        assert_eq!(tab.variations.lines().len(), 1);
+    }
+
+    #[test]
+    fn test_moves_are_annotated_by_hand_and_from_reports() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_pasted("1. e4 e5?! {loosens f7?} 2. Qh5 $2 Nc6").unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        let line = tab.line.clone();
+        assert_eq!(tab.variations.node(&line[..2]).unwrap().comment, "loosens f7?");
+
+        // The report's judgements fill in moves the user left unannotated
+        let ply = |ply: usize, judgement| report::PlyAnalysis {
+            before: Board::new(),
+            move_number: ply / 2 + 1,
+            played: line[ply],
+            best: None,
+            eval: 0,
+            loss: 0,
+            judgement,
+        };
+        let analysis = GameAnalysis {
+            start_fen: None,
+            plies: vec![ply(0, None), ply(1, Some(Judgement::Blunder)), ply(2, None), ply(3, Some(Judgement::Inaccuracy))],
+        };
+        tab.annotate(&analysis);
+        tab.variations.node_mut(&line[..1]).unwrap().nag = Some(Nag::Good);
+        let nags: Vec<Option<Nag>> = (1..=4).map(|plies| tab.variations.node(&line[..plies]).unwrap().nag).collect();
+        assert_eq!(nags, [Some(Nag::Good), Some(Nag::Dubious), Some(Nag::Mistake), Some(Nag::Dubious)]);
+
+        let pgn = tab.to_pgn();
+        assert!(pgn.contains("1. e2e4! e7e5?! {loosens f7?} 2. d1h5? b8c6?! *"), "{}", pgn);
+        let variations = tab.variations.clone();
+        gui.open_pasted(&pgn).unwrap();
+        assert_eq!(gui.tabs[gui.active].variations, variations);
+    }
+
+    #[test]
+    fn test_copy_actions() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_pasted("1. e4 e5 2. Nf3").unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        let line = tab.line.clone();
+        tab.analysis = Some(AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: "b8c6".to_string(),
+            score: -25,
+            pv: vec!["b8c6".to_string(), "f1b5".to_string(), "a7a6".to_string(), "e1e1".to_string()],
+            depth: 1,
+        });
+
+        assert_eq!(tab.copy_text(CopyItem::Fen, &line).unwrap(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert!(tab.copy_text(CopyItem::Pgn, &line).unwrap().ends_with("\n\n1. e4 e5 2. Nf3 *\n"));
+        assert_eq!(tab.copy_text(CopyItem::BestLine, &line).unwrap(), "2... Nc6 3. Bb5 a6");
+        assert_eq!(tab.copy_text(CopyItem::EvalSummary, &line).unwrap(),
+            "Black to move: -0.25 (local engine), best line 2... Nc6 3. Bb5 a6");
+
+        // Earlier positions have no engine line cached
+        assert_eq!(tab.copy_text(CopyItem::BestLine, &line[..1]), None);
+        assert!(tab.copy_text(CopyItem::Fen, &line[..1]).unwrap().starts_with("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq"));
+    }
+
+    #[test]
+    fn test_analysis_is_shared_between_tabs() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        let line = |depth, best: &str| AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: best.to_string(),
+            score: 20,
+            pv: vec![best.to_string()],
+            depth,
+        };
+        gui.open_pasted("1. e4 e5").unwrap();
+        let first = gui.active;
+        gui.tabs[first].move_history[1].analysis = Some(line(8, "c7c5"));
+        gui.tabs[first].analysis = Some(line(6, "g1f3"));
+        gui.tabs[first].sync_analysis(&mut gui.analysis_cache);
+        assert_eq!(gui.analysis_cache.len(), 2);
+
+        // Another tab reaching the same positions shows their lines; a
+        // shallower line found there does not replace a deeper one
+        gui.open_pasted("1. e4").unwrap();
+        let tab = &mut gui.tabs[gui.active];
+        tab.analysis = Some(line(3, "d7d5"));
+        tab.sync_analysis(&mut gui.analysis_cache);
+        assert_eq!(tab.analysis, Some(line(8, "c7c5")));
+        tab.finish_user_move(Move::encode(52, 36, None));  // e5
+        tab.sync_analysis(&mut gui.analysis_cache);
+        assert_eq!(tab.analysis, Some(line(6, "g1f3")));
+
+        // A deeper line refines the cache for the first tab too
+        tab.analysis = Some(line(10, "b1c3"));
+        tab.sync_analysis(&mut gui.analysis_cache);
+        gui.tabs[first].sync_analysis(&mut gui.analysis_cache);
+        assert_eq!(gui.tabs[first].analysis, Some(line(10, "b1c3")));
+    }
+
+    #[test]
+    fn test_warm_up_reports_every_step() {
+        let mut warm_up = WarmUp::start(crate::init_tables());
+        let started = Instant::now();
+        while !warm_up.poll() {
+            assert!(started.elapsed() < Duration::from_secs(120), "warm-up never finished");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(warm_up.done.len(), 1 + WARM_UP_STEPS.len());
+        assert!(warm_up.summary().starts_with("Engine ready in "), "{}", warm_up.summary());
+
+        // Games created since share the tables built once
+        ChessGUI::with_settings(Settings::default()).open_tab(TabMode::Analysis);
+        assert_eq!(crate::tables_built(), 1);
+    }
+
+    #[test]
+    fn test_notices_are_collected_and_expire() {
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        gui.open_pasted("1. f3 e5 2. g4").unwrap();
+        let background = gui.active;
+        gui.open_tab(TabMode::Analysis);
+
+        // A mate in a background tab is announced under the tab's name
+        gui.tabs[background].finish_user_move(Move::encode(59, 31, None));  // Qh4#
+        gui.collect_notices();
+        let latest = gui.notices.latest.clone().unwrap();
+        assert_eq!(latest.level, NoticeLevel::Info);
+        assert_eq!(latest.text, format!("{}: {}", gui.tabs[background].title, gui.tabs[background].status()));
+        assert!(gui.tabs[background].notices.is_empty());
+
+        // Only the newest toasts stay up, and only for a while; the status
+        // bar keeps the latest message
+        for n in 0..MAX_TOASTS {
+            gui.notices.push(Notice::error(format!("Error {}", n)));
+        }
+        assert_eq!(gui.notices.toasts.len(), MAX_TOASTS);
+        assert_eq!(gui.notices.toasts[0].0, Notice::error("Error 0"));
+        gui.notices.expire(Instant::now() + TOAST_TIME);
+        assert!(gui.notices.toasts.is_empty());
+        assert_eq!(gui.notices.latest, Some(Notice::error(format!("Error {}", MAX_TOASTS - 1))));
+    }
+
+    #[test]
+    fn test_closing_asks_about_unfinished_games() {
+        use eframe::App;
+
+        let mut gui = ChessGUI::with_settings(Settings::default());
+        assert!(gui.on_close_event());
+
+        gui.open_pasted("[FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 0 1\"]\n1... Kf8 2. Ra7").unwrap();
+        assert!(!gui.on_close_event());
+        assert_eq!(gui.quit, QuitState::Asking);
+
+        // The saved game pastes back in as it was
+        let pgn = gui.tabs[gui.active].to_pgn();
+        assert!(pgn.contains("1... e8f8 2. a1a7 *"), "{}", pgn);
+        let imported = pgn::import(&pgn).unwrap();
+        assert_eq!(imported.start_fen, "4k3/8/8/8/8/8/8/R3K3 b - - 0 1");
+        assert_eq!(imported.moves.len(), 2);
+
+        gui.quit = QuitState::Confirmed;
+        assert!(gui.on_close_event());
+        assert!(gui.tabs.iter().all(|tab| tab.stop.load(Ordering::Relaxed)));
+    }
+}
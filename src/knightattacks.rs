@@ -4,6 +4,7 @@
 //! It pre-computes all possible knight moves from each square for efficient
 //! move generation during gameplay.
 
+use alloc::{vec, vec::Vec};
 use crate::utils::*;
 
 /// Type alias for a 64-bit integer representing a chess board
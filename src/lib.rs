@@ -0,0 +1,349 @@
+//! RustChess engine library.
+//! 
+//! This crate contains the board representation, move generation, evaluation
+//! and search used by the RustChess front ends. Most users only need the
+//! [`Board`], [`Move`] and [`Engine`] types re-exported at the crate root:
+//! 
+//! ```
+//! use rustchess::{Board, Engine};
+//! 
+//! let mut board = Board::new();
+//! let mv = board.parse_move("e2e4").unwrap();
+//! board.play(mv).unwrap();
+//! 
+//! let mut engine = Engine::new();
+//! engine.set_max_time(1);
+//! let reply = engine.best_move(&board).unwrap();
+//! assert!(board.legal_moves().contains(&reply));
+//! ```
+//! 
+//! Engines and tools that need more control use the types under them, also
+//! re-exported at the root: [`Game`] holds the shared lookup tables,
+//! [`Position`] the pieces and side to move, [`Search`] the timed
+//! alpha-beta search and [`Evaluation`] the static evaluation. Moves are
+//! passed between them packed into a `u64`, which [`Move::encode`] and
+//! [`Move::from_u64`] convert.
+//!
+//! ```
+//! use rustchess::{Evaluation, Game, Move, Position, Search};
+//!
+//! let game = Game::new();
+//! let mut position = Position::from_fen("4k3/8/8/8/8/8/3Q4/4K3 w - - 0 1", &game).unwrap();
+//! assert!(Evaluation::new(position.clone()).evaluate_position() > 0);
+//!
+//! let mut search = Search::new();
+//! search.set_max_time_ms(100);
+//! let best = search.find_best_move(&mut position).unwrap();
+//! assert!(position.get_all_legal_moves(&game).contains(&best));
+//! println!("{}", Move::from_u64(best, &position));
+//! ```
+//!
+//! The types re-exported at the root and their public methods are the
+//! stable API, changed only with a new minor version while below 1.0. The
+//! lower level modules (`position`, `movegeneration`, `search`, ...) stay
+//! public for the front ends and for tests, but their layout is an
+//! implementation detail.
+//! 
+//! # Features
+//! 
+//! * `std` - timed search (`search`, `Engine`) and console helpers. Without it
+//!   the board, move generation and evaluation build as `no_std` + `alloc`.
+//! * `cli` - the command-line interface in `ui` and command scripts in
+//!   `script` (implies `std` and `uci`)
+//! * `uci` - the UCI protocol front end in `uci` (implies `std`)
+//! * `tuning` - SPSA tuning by self-play in `tuning` and the strength history
+//!   of calibration runs in `strength` (implies `std`)
+//! * `gui` - the egui front end in `gui`, which also graphs the strength
+//!   history (implies `std`, `render` and `remote`)
+//! * `render` - headless board images and game export in `render`
+//! * `server` - the headless HTTP/JSON API in `server` (implies `std`)
+//! * `remote` - the client in `remote` for analyzing on another machine's server
+//! * `serde` - `Serialize` and `Deserialize` for `Position`, its parts and
+//!   [`GameState`], for saving games (works without `std`)
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod position;
+pub mod attackmaps;
+pub mod utils;
+pub mod board_consts;
+pub mod knightattacks;
+pub mod rayattacks;
+pub mod movegen_tables;
+pub mod movegeneration;
+pub mod pawnattacks;
+pub mod perft;
+pub mod moveorder;
+pub mod evaluation;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "std")]
+pub mod search_params;
+#[cfg(feature = "std")]
+pub mod options;
+#[cfg(feature = "std")]
+pub mod time_manager;
+#[cfg(feature = "std")]
+pub mod player;
+#[cfg(feature = "std")]
+pub mod external;
+#[cfg(feature = "cli")]
+pub mod ui;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "gui")]
+pub mod library;
+#[cfg(feature = "gui")]
+pub mod analysis_cache;
+#[cfg(feature = "gui")]
+pub mod bundle;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod zorbrist;
+pub mod transposition;
+pub mod chess_move;
+pub mod board;
+pub mod notation;
+pub mod diagram;
+pub mod pgn;
+#[cfg(feature = "std")]
+pub mod engine;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "std")]
+pub mod correspondence;
+#[cfg(feature = "std")]
+pub mod drills;
+#[cfg(feature = "std")]
+pub mod epd;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod repertoire;
+#[cfg(feature = "std")]
+pub mod calibration;
+#[cfg(feature = "tuning")]
+pub mod tuning;
+#[cfg(any(feature = "tuning", feature = "gui"))]
+pub mod strength;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "uci")]
+pub mod uci;
+#[cfg(feature = "cli")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod book;
+#[cfg(feature = "std")]
+pub mod polyglot;
+#[cfg(feature = "std")]
+pub mod opponent;
+#[cfg(feature = "std")]
+pub mod bitbase;
+#[cfg(feature = "std")]
+pub mod tablebase;
+#[cfg(feature = "std")]
+pub mod assets;
+#[cfg(feature = "std")]
+pub mod memory;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use pawnattacks::PawnAttacks;
+use rayattacks::Rays;
+use movegen_tables::MoveGenTables;
+use perft::Perft;
+use zorbrist::Zobrist;
+
+pub use board::{Board, GameState, PositionBuilder};
+pub use chess_move::Move;
+pub use position::{CastlingRights, Color, Piece, PieceType, Position};
+pub use evaluation::Evaluation;
+#[cfg(feature = "std")]
+pub use engine::Engine;
+#[cfg(feature = "std")]
+pub use search::Search;
+
+/// Times the lookup tables have been built, for checking that play never
+/// rebuilds them.
+static TABLES_BUILT: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "std")]
+static TABLES: std::sync::OnceLock<Tables> = std::sync::OnceLock::new();
+
+/// The pre-computed lookup tables, shared by every `Game` so that creating
+/// or cloning a game does not copy them.
+#[derive(Debug, Clone)]
+struct Tables {
+    pawn_attacks: Arc<PawnAttacks>,
+    rays: Arc<Rays>,
+    move_gen_tables: Arc<MoveGenTables>,
+    zobrist: Arc<Zobrist>,
+}
+
+impl Tables {
+    fn build() -> Tables {
+        TABLES_BUILT.fetch_add(1, Ordering::Relaxed);
+        Tables {
+            pawn_attacks: Arc::new(PawnAttacks::new()),
+            rays: Arc::new(Rays::new()),
+            move_gen_tables: Arc::new(MoveGenTables::new()),
+            zobrist: Arc::new(Zobrist::new()),
+        }
+    }
+
+    /// The tables, built on first use. Without `std` there is nowhere to
+    /// keep them, so every new game builds its own.
+    fn shared() -> Tables {
+        #[cfg(feature = "std")]
+        return TABLES.get_or_init(Tables::build).clone();
+        #[cfg(not(feature = "std"))]
+        Tables::build()
+    }
+}
+
+/// Builds the lookup tables now if they have not been built yet, so the
+/// first game created does not have to wait for them.
+///
+/// # Returns
+///
+/// * How long building took; zero if they were already built
+#[cfg(feature = "std")]
+pub fn init_tables() -> std::time::Duration {
+    let start = std::time::Instant::now();
+    Tables::shared();
+    start.elapsed()
+}
+
+/// How many times the lookup tables have been built. With `std` this stays
+/// at one once any game has been created.
+pub fn tables_built() -> usize {
+    TABLES_BUILT.load(Ordering::Relaxed)
+}
+
+/// The main game structure that holds the current position and pre-computed tables.
+/// 
+/// This struct serves as the central point for managing the game state and
+/// providing access to various pre-computed lookup tables used for efficient
+/// move generation and position evaluation. The tables are shared between
+/// games, so cloning a game only copies its position.
+#[derive(Debug, Clone)]
+pub struct Game {
+    /// The current position of the game
+    position: Position,
+    /// Pre-computed pawn move and attack patterns
+    pawn_attacks: Arc<PawnAttacks>,
+    /// Pre-computed ray attacks for sliding pieces
+    rays: Arc<Rays>,
+    /// Pre-computed move generation tables
+    move_gen_tables: Arc<MoveGenTables>,
+    /// Zobrist hashing for positions
+    pub zobrist: Arc<Zobrist>,
+}
+
+impl Game {
+    /// Creates a new game instance with the standard starting position.
+    /// 
+    /// This function initializes all pre-computed tables and sets up
+    /// the board in the standard chess starting position.
+    /// 
+    /// # Returns
+    /// 
+    /// * A new `Game` instance ready for play
+    pub fn new() -> Game {
+        let temp_game = Game::with_tables(Position {
+            pieces: vec![],
+            squares: vec![],
+            active_color: Color::White,
+            castling_rights: CastlingRights::ALL,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            white_occupancy: 0,
+            black_occupancy: 0,
+            white_kingside_path_attacked: false,
+            white_queenside_path_attacked: false,
+            black_kingside_path_attacked: false,
+            black_queenside_path_attacked: false,
+            piece_legal_moves: vec![],
+            white_king_moved: false,
+            black_king_moved: false,
+            white_kingside_rook_moved: false,
+            white_queenside_rook_moved: false,
+            black_kingside_rook_moved: false,
+            black_queenside_rook_moved: false,
+            castling: movegeneration::STANDARD_CASTLING,
+            chess960: false,
+            legal_moves_key: None,
+            king_squares: [None; 2],
+        }, Tables::shared());
+
+        Game {
+            position: Position::new(&temp_game),
+            ..temp_game
+        }
+    }
+
+    fn with_tables(position: Position, tables: Tables) -> Game {
+        let Tables { pawn_attacks, rays, move_gen_tables, zobrist } = tables;
+        Game { position, pawn_attacks, rays, move_gen_tables, zobrist }
+    }
+
+    /// Creates a new game instance from a FEN string.
+    /// 
+    /// This function allows initializing the game from any valid position
+    /// specified in Forsyth–Edwards Notation (FEN).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `fen` - A string containing the FEN representation of the position
+    /// 
+    /// # Returns
+    /// 
+    /// * A new `Game` instance with the specified position
+    pub fn from_fen(fen: &str) -> Game {
+        let game = Game::new();
+        Game {
+            position: Position::read_FEN(fen, &game),
+            ..game
+        }
+    }
+
+    /// The current position of the game.
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn from_not_alot(not_alot: &str) -> Game {
+        let game = Game::new();
+        let position = Position::read_FEN(not_alot, &game);
+        Game {
+            position,
+            ..game
+        }
+    }
+
+    pub fn perft(not_alot: &str, depth: usize) -> usize {
+        let game = Game::new();
+        let position = Position::read_FEN(not_alot, &game);
+        let mut perft = Perft::new();
+        perft.run(&position, depth as i32) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_games_share_their_tables() {
+        let game = Game::new();
+        let other = Game::from_fen("8/8/8/8/8/8/8/K6k w - - 0 1");
+        assert!(Arc::ptr_eq(&game.move_gen_tables, &other.move_gen_tables));
+        assert!(Arc::ptr_eq(&game.rays, &game.clone().rays));
+        assert_eq!(tables_built(), 1);
+    }
+}
@@ -0,0 +1,133 @@
+//! Position library: bookmarked positions for the GUI.
+//!
+//! A bookmark is a FEN with a name, a note and the folder it is filed
+//! under; the empty folder is the top level. The GUI keeps the library in
+//! eframe's storage as JSON, so bookmarks survive restarts.
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::Board;
+
+/// A bookmarked position.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    /// Free text shown with the bookmark
+    #[serde(default)]
+    pub note: String,
+    /// Folder the bookmark is filed under; empty for the top level
+    #[serde(default)]
+    pub folder: String,
+    pub fen: String,
+}
+
+/// Bookmarks in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionLibrary {
+    bookmarks: Vec<Bookmark>,
+}
+
+impl PositionLibrary {
+    /// Creates an empty library.
+    pub fn new() -> Self {
+        PositionLibrary::default()
+    }
+
+    /// Adds a bookmark.
+    ///
+    /// # Arguments
+    ///
+    /// * `bookmark` - The bookmark; surrounding whitespace is trimmed from
+    ///   its name and folder
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or an error if the name is empty or the FEN is not a
+    ///   legal position
+    pub fn add(&mut self, mut bookmark: Bookmark) -> Result<(), String> {
+        bookmark.name = bookmark.name.trim().to_string();
+        bookmark.folder = bookmark.folder.trim().to_string();
+        if bookmark.name.is_empty() {
+            return Err("A bookmark needs a name".to_string());
+        }
+        Board::from_fen(&bookmark.fen)?;
+        self.bookmarks.push(bookmark);
+        Ok(())
+    }
+
+    /// Removes the bookmark at `index`, if there is one.
+    pub fn remove(&mut self, index: usize) -> Option<Bookmark> {
+        (index < self.bookmarks.len()).then(|| self.bookmarks.remove(index))
+    }
+
+    /// Files the bookmark at `index` under another folder.
+    pub fn move_to(&mut self, index: usize, folder: &str) {
+        if let Some(bookmark) = self.bookmarks.get_mut(index) {
+            bookmark.folder = folder.trim().to_string();
+        }
+    }
+
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// The folders in use, sorted, with the top level first if it has
+    /// bookmarks.
+    pub fn folders(&self) -> Vec<&str> {
+        let mut folders: Vec<&str> = self.bookmarks.iter().map(|bookmark| bookmark.folder.as_str()).collect();
+        folders.sort_unstable();
+        folders.dedup();
+        folders
+    }
+
+    /// The bookmarks filed under `folder`, with their indices.
+    pub fn in_folder<'a>(&'a self, folder: &'a str) -> impl Iterator<Item = (usize, &'a Bookmark)> + 'a {
+        self.bookmarks.iter().enumerate().filter(move |(_, bookmark)| bookmark.folder == folder)
+    }
+
+    /// The library as JSON, for storage.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("bookmarks always serialize")
+    }
+
+    /// Reads a library stored by `to_json`.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| format!("Invalid position library: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+
+    fn bookmark(name: &str, folder: &str, fen: &str) -> Bookmark {
+        Bookmark { name: name.to_string(), note: String::new(), folder: folder.to_string(), fen: fen.to_string() }
+    }
+
+    #[test]
+    fn test_library_folders_and_storage() {
+        let mut library = PositionLibrary::new();
+        let lucena = "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1";
+        library.add(bookmark("Start", "", START_FEN)).unwrap();
+        library.add(bookmark(" Lucena ", " Endgames ", lucena)).unwrap();
+        assert!(library.add(bookmark("", "", START_FEN)).is_err());
+        assert!(library.add(bookmark("Broken", "", "8/8/8 w - - 0 1")).is_err());
+
+        assert_eq!(library.folders(), vec!["", "Endgames"]);
+        let endgames: Vec<_> = library.in_folder("Endgames").collect();
+        assert_eq!(endgames.len(), 1);
+        assert_eq!((endgames[0].0, endgames[0].1.name.as_str()), (1, "Lucena"));
+
+        library.move_to(0, "Openings");
+        assert_eq!(library.folders(), vec!["Endgames", "Openings"]);
+
+        let stored = PositionLibrary::from_json(&library.to_json()).unwrap();
+        assert_eq!(stored, library);
+        assert!(PositionLibrary::from_json("{").is_err());
+
+        assert_eq!(library.remove(1).unwrap().fen, lucena);
+        assert!(library.remove(5).is_none());
+        assert_eq!(library.bookmarks().len(), 1);
+    }
+}
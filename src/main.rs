@@ -1,152 +1,771 @@
 //! Chess engine main module.
 //! 
-//! This module serves as the entry point for the chess engine and coordinates
-//! the interaction between various components such as position management,
-//! move generation, and attack pattern calculation.
-
-pub mod position;
-pub mod utils;
-pub mod knightattacks;
-pub mod rayattacks;
-pub mod movegen_tables;
-pub mod movegeneration;
-pub mod pawnattacks;
-pub mod perft;
-pub mod moveorder;
-pub mod evaluation;
-pub mod search;
-pub mod ui;
-pub mod gui;
-pub mod zorbrist;
-pub mod transposition;
-pub mod chess_move;
-use position::*;
-use knightattacks::KnightAttacks;
-use pawnattacks::PawnAttacks;
-use rayattacks::Rays;
-use movegen_tables::MoveGenTables;
-use perft::Perft;
-use gui::run_gui;
-use ui::ChessUI;
-use zorbrist::Zobrist;
-
-/// The main game structure that holds the current position and pre-computed tables.
-/// 
-/// This struct serves as the central point for managing the game state and
-/// providing access to various pre-computed lookup tables used for efficient
-/// move generation and position evaluation.
-#[derive(Debug, Clone)]
-pub struct Game {
-    /// The current position of the game
-    position: Position,
-    /// Pre-computed knight attack patterns
-    knight_attacks: KnightAttacks,
-    /// Pre-computed pawn move and attack patterns
-    pawn_attacks: PawnAttacks,
-    /// Pre-computed ray attacks for sliding pieces
-    rays: Rays,
-    /// Pre-computed move generation tables
-    move_gen_tables: MoveGenTables,
-    /// Zobrist hashing for positions
-    pub zobrist: Zobrist,
-}
-
-impl Game {
-    /// Creates a new game instance with the standard starting position.
-    /// 
-    /// This function initializes all pre-computed tables and sets up
-    /// the board in the standard chess starting position.
-    /// 
-    /// # Returns
-    /// 
-    /// * A new `Game` instance ready for play
-    pub fn new() -> Game {
-        let temp_game = Game {
-            position: Position {
-                pieces: vec![],
-                squares: vec![],
-                active_color: Color::White,
-                castling_rights: CastlingRights::ALL,
-                en_passant: None,
-                halfmove_clock: 0,
-                fullmove_number: 1,
-                white_occupancy: 0,
-                black_occupancy: 0,
-                white_kingside_path_attacked: false,
-                white_queenside_path_attacked: false,
-                black_kingside_path_attacked: false,
-                black_queenside_path_attacked: false,
-                piece_legal_moves: vec![],
-                white_king_moved: false,
-                black_king_moved: false,
-                white_kingside_rook_moved: false,
-                white_queenside_rook_moved: false,
-                black_kingside_rook_moved: false,
-                black_queenside_rook_moved: false,
+//! This module serves as the entry point for the chess engine and starts the
+//! command-line interface on top of the `rustchess` library. Running
+//! `Chess_Engine uci` speaks the UCI protocol on stdin/stdout instead, and
+//! `Chess_Engine serve [--port N] [--threads N]` starts the HTTP/JSON server, and
+//! `Chess_Engine img --fen <fen> -o <file>` writes a PNG or SVG of a position,
+//! and `Chess_Engine report --moves "<moves>" -o <game.pgn>` analyzes a game
+//! and writes a Markdown (or `--html`) report next to it.
+//! `Chess_Engine corr <list|new|show|move|think>` manages correspondence
+//! games that the engine may think about for hours per move, and
+//! `Chess_Engine calibrate` estimates the engine's Elo against weak
+//! reference opponents and keeps the result in a strength history, which
+//! `calibrate --history` lists.
+//! `Chess_Engine script [--json] [file]` runs line-based commands from a
+//! file or stdin and answers each with one machine-readable line (see
+//! `script`); `--json` also makes `calibrate` print its report as JSON.
+//! `Chess_Engine perft <depth> [--fen <fen>] [--hash-mb N]` counts the legal
+//! move tree, caching subtree counts in an N megabyte table, and with
+//! `--diff <engine>` compares the counts with an external UCI engine to find
+//! the line where the move generators disagree.
+//! `Chess_Engine --perft <fen> <depth>` prints the same counts divided by
+//! root move, as `go perft` does.
+//! `Chess_Engine epd <file> [--movetime MS] [--depth N]` searches the
+//! positions of an EPD test suite and scores the moves found against `bm`
+//! and `am`.
+//! `Chess_Engine bench [depth]` searches a fixed set of positions to a
+//! fixed depth and prints the total nodes, a signature of the engine that
+//! changes with its play, and the nodes per second.
+//! `Chess_Engine --analyze-json <fen> [--movetime MS] [--depth N]` prints
+//! the search of a position as one JSON object per iteration (depth,
+//! seldepth, score, PV, nodes and time) and then the best move, with
+//! whether the node ceiling cut the search short.
+//! `Chess_Engine tune [--iterations N] [-o <file>]` tunes the search
+//! parameters by self-play and writes them to the search config file.
+//! `--book <file>` makes the command-line game play from, and learn into, a
+//! local opening book, and `--engine <path> [--movetime MS]` plays against an
+//! external UCI engine instead of the built-in search.
+//!
+//! Each command is only built with its cargo feature (`uci`, `serve` with
+//! `server`, `script`, `--analyze-json` and the game with `cli`, `tune` and
+//! `calibrate` with `tuning`, `img` with `render`). A build without `cli`
+//! starts in UCI mode when given no command.
+
+#[cfg(feature = "uci")]
+use rustchess::uci::UCI;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        #[cfg(feature = "uci")]
+        Some("uci") => {
+            UCI::new().run();
+            return;
+        }
+        #[cfg(feature = "server")]
+        Some("serve") => {
+            run_server(&args[1..]);
+            return;
+        }
+        Some("report") => {
+            write_report(&args[1..]);
+            return;
+        }
+        Some("corr") => {
+            run_correspondence(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "tuning")]
+        Some("calibrate") => {
+            run_calibration(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "cli")]
+        Some("script") => {
+            run_script(&args[1..]);
+            return;
+        }
+        Some("perft") => {
+            run_perft(&args[1..]);
+            return;
+        }
+        Some("epd") => {
+            run_epd(&args[1..]);
+            return;
+        }
+        Some("--perft") => {
+            perft_divide(&args[1..]);
+            return;
+        }
+        Some("bench") => {
+            run_bench(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "cli")]
+        Some("--analyze-json") => {
+            analyze_json(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "tuning")]
+        Some("tune") => {
+            run_tuning(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "render")]
+        Some("img") => {
+            render_image(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
+
+    play_game(&args);
+}
+
+/// The search with the parameters and hash file the environment names, see
+/// `Search::from_config`. Files that cannot be read are reported on stderr.
+fn configured_search() -> rustchess::search::Search {
+    let (search, errors) = rustchess::search::Search::from_config();
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    search
+}
+
+/// The engine with the parameters and hash file the environment names,
+/// reporting files that cannot be read like `configured_search`.
+fn configured_engine() -> rustchess::engine::Engine {
+    let (engine, errors) = rustchess::engine::Engine::from_config();
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    engine
+}
+
+/// Plays a game against the user on the command line, from `--book <file>`
+/// when given and against `--engine <path>` (at `--movetime MS`) instead of
+/// the built-in search. Book moves scoring more than `--book-margin CP`
+/// (default 100) below the engine's own choice are not played, and
+/// `--no-book-check` plays them without searching.
+#[cfg(feature = "cli")]
+fn play_game(args: &[String]) {
+    use rustchess::book;
+    use rustchess::external::UciEngine;
+    use rustchess::player::{Player, SearchLimits};
+    use rustchess::ui::ChessUI;
+
+    let mut ui = ChessUI::new();
+    ui.set_engine(Box::new(configured_search()), SearchLimits::default());
+    if let Some(index) = args.iter().position(|arg| arg == "--book") {
+        let Some(path) = args.get(index + 1) else {
+            eprintln!("--book expects a file path");
+            std::process::exit(2);
+        };
+        match book::load_book(path) {
+            Ok(book) => ui.set_book(book),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--book-margin") {
+        match args.get(index + 1).and_then(|n| n.parse::<i32>().ok()) {
+            Some(margin) => ui.set_book_margin(Some(margin)),
+            None => {
+                eprintln!("--book-margin expects a number of centipawns");
+                std::process::exit(2);
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "--no-book-check") {
+        ui.set_book_margin(None);
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--engine") {
+        let Some(path) = args.get(index + 1) else {
+            eprintln!("--engine expects the path of a UCI engine");
+            std::process::exit(2);
+        };
+        let movetime = match args.iter().position(|arg| arg == "--movetime") {
+            Some(index) => match args.get(index + 1).and_then(|n| n.parse::<u64>().ok()) {
+                Some(millis) => millis,
+                None => {
+                    eprintln!("--movetime expects a number of milliseconds");
+                    std::process::exit(2);
+                }
             },
-            rays: Rays::new(),
-            move_gen_tables: MoveGenTables::new(),
-            pawn_attacks: PawnAttacks::new(),
-            knight_attacks: KnightAttacks::new(),
-            zobrist: Zobrist::new(),
+            None => 1000,
         };
+        match UciEngine::spawn(path, &[]) {
+            Ok(engine) => {
+                println!("Playing against {}", engine.name());
+                ui.set_engine(Box::new(engine), SearchLimits::movetime_ms(movetime));
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    ui.play_session();
+}
 
-        Game {
-            position: Position::new(&temp_game),
-            rays: Rays::new(),
-            move_gen_tables: MoveGenTables::new(),
-            pawn_attacks: PawnAttacks::new(),
-            knight_attacks: KnightAttacks::new(),
-            zobrist: Zobrist::new(),
-        }
-    }
-
-    /// Creates a new game instance from a FEN string.
-    /// 
-    /// This function allows initializing the game from any valid position
-    /// specified in Forsyth–Edwards Notation (FEN).
-    /// 
-    /// # Arguments
-    /// 
-    /// * `fen` - A string containing the FEN representation of the position
-    /// 
-    /// # Returns
-    /// 
-    /// * A new `Game` instance with the specified position
-    pub fn from_fen(fen: &str) -> Game {
-        let game = Game::new();
-        Game {
-            position: Position::read_FEN(fen, &game),
-            rays: Rays::new(),
-            move_gen_tables: MoveGenTables::new(),
-            pawn_attacks: PawnAttacks::new(),
-            knight_attacks: KnightAttacks::new(),
-            zobrist: Zobrist::new(),
-        }
-    }
-
-    pub fn from_not_alot(not_alot: &str) -> Game {
-        let game = Game::new();
-        let position = Position::read_FEN(not_alot, &game);
-        Game {
-            position,
-            rays: Rays::new(),
-            move_gen_tables: MoveGenTables::new(),
-            pawn_attacks: PawnAttacks::new(),
-            knight_attacks: KnightAttacks::new(),
-            zobrist: Zobrist::new(),
-        }
-    }
-
-    pub fn perft(not_alot: &str, depth: usize) -> usize {
-        let game = Game::new();
-        let position = Position::read_FEN(not_alot, &game);
-        let mut perft = Perft::new();
-        perft.run(&position, depth as i32) as usize
+/// Builds without the command-line interface speak UCI when no command is
+/// given, so a tournament manager can start the binary as it is.
+#[cfg(all(not(feature = "cli"), feature = "uci"))]
+fn play_game(_args: &[String]) {
+    UCI::new().run();
+}
+
+#[cfg(not(any(feature = "cli", feature = "uci")))]
+fn play_game(_args: &[String]) {
+    eprintln!("This build has no interactive front end; build it with the `cli` or `uci` feature");
+    std::process::exit(2);
+}
+
+/// Starts the HTTP server on localhost, on `--port N` or 8080, searching
+/// batches on `--threads N` workers (default one per CPU).
+#[cfg(feature = "server")]
+fn run_server(args: &[String]) {
+    let port = match args.iter().position(|arg| arg == "--port") {
+        Some(index) => match args.get(index + 1).and_then(|p| p.parse::<u16>().ok()) {
+            Some(port) => port,
+            None => {
+                eprintln!("--port expects a port number");
+                std::process::exit(2);
+            }
+        },
+        None => 8080,
+    };
+    let workers = match args.iter().position(|arg| arg == "--threads") {
+        Some(index) => match args.get(index + 1).and_then(|n| n.parse::<usize>().ok()).filter(|&n| n > 0) {
+            Some(workers) => workers,
+            None => {
+                eprintln!("--threads expects a positive number");
+                std::process::exit(2);
+            }
+        },
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    if let Err(e) = rustchess::server::serve_with_workers(&format!("127.0.0.1:{}", port), workers) {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
 }
 
-fn main() {
-    let mut ui = ChessUI::new();
-    ui.play_game();
-}
\ No newline at end of file
+/// Writes a PNG or SVG of `--fen <fen>` (default: the starting position) to
+/// `-o <file>`, with `--size N` pixels per square and `--black` to view the
+/// board from Black's side.
+#[cfg(feature = "render")]
+fn render_image(args: &[String]) {
+    use rustchess::board::Board;
+    use rustchess::position::Color;
+    use rustchess::render::BoardRenderer;
+
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| args.get(index + 1));
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+
+    let board = match value_of("--fen") {
+        Some(Some(fen)) => Board::from_fen(fen).unwrap_or_else(|e| fail(e)),
+        Some(None) => fail("--fen expects a FEN string".to_string()),
+        None => Board::new(),
+    };
+    let Some(Some(output)) = value_of("-o") else {
+        fail("usage: Chess_Engine img [--fen <fen>] -o <file.png|file.svg> [--size N] [--black]".to_string());
+    };
+    let square_size = match value_of("--size") {
+        Some(size) => size.and_then(|s| s.parse::<u32>().ok()).unwrap_or_else(|| fail("--size expects a number of pixels".to_string())),
+        None => 48,
+    };
+    let orientation = if args.iter().any(|arg| arg == "--black") { Color::Black } else { Color::White };
+
+    if let Err(e) = BoardRenderer::new(square_size, orientation).save(board.position(), None, output) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Analyzes `--moves "<moves>"` (from `--fen <fen>` or the starting position)
+/// with `--movetime MS` per position, and writes the report next to the game
+/// file given by `-o`.
+fn write_report(args: &[String]) {
+    use rustchess::report::{analyze_game, ReportFormat};
+
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1));
+    let (Some(moves), Some(output)) = (value_of("--moves"), value_of("-o")) else {
+        eprintln!("usage: Chess_Engine report --moves \"e2e4 e7e5 ...\" -o <game.pgn> [--fen <fen>] [--movetime MS] [--html]");
+        std::process::exit(2);
+    };
+    let movetime = match value_of("--movetime").map(|ms| ms.parse::<u64>()) {
+        Some(Ok(ms)) => ms,
+        Some(Err(_)) => {
+            eprintln!("--movetime expects milliseconds");
+            std::process::exit(2);
+        }
+        None => 1000,
+    };
+    let format = if args.iter().any(|arg| arg == "--html") { ReportFormat::Html } else { ReportFormat::Markdown };
+
+    let mut engine = configured_engine();
+    engine.set_max_time_ms(movetime);
+    let moves: Vec<&str> = moves.split_whitespace().collect();
+    let result = analyze_game(value_of("--fen").map(String::as_str), &moves, &mut engine)
+        .and_then(|analysis| analysis.write_next_to(output, format));
+    match result {
+        Ok(path) => println!("Report written to {}", path.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the script in the file given, or on stdin when there is none or it
+/// is `-`, and exits with status 1 if any command failed. `--json` answers
+/// in JSON.
+#[cfg(feature = "cli")]
+fn run_script(args: &[String]) {
+    use std::fs::File;
+    use std::io::{self, BufReader};
+    use rustchess::script::Script;
+
+    let mut script = Script::new(io::stdout());
+    script.set_json(args.iter().any(|arg| arg == "--json"));
+    let result = match args.iter().find(|arg| *arg != "--json").map(String::as_str) {
+        None | Some("-") => script.run(io::stdin().lock()),
+        Some(path) => File::open(path)
+            .map_err(|e| format!("Could not open {}: {}", path, e))
+            .and_then(|file| script.run(BufReader::new(file))),
+    };
+    match result {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Counts the leaf nodes `depth` plies below the start position or `--fen`,
+/// with a `--hash-mb N` cache of subtree counts (default 16, 0 for none).
+/// With `--diff <engine>` the counts are compared with the engine's
+/// `go perft` instead, descending into the first differing move.
+fn run_perft(args: &[String]) {
+    use std::time::Instant;
+    use rustchess::Board;
+    use rustchess::external::UciEngine;
+    use rustchess::perft::{Divide, Perft};
+    use rustchess::player::Player;
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).unwrap_or_else(|| fail(format!("{} expects a value", flag)))
+    });
+    let depth = match args.first().map(|depth| depth.parse::<i32>()) {
+        Some(Ok(depth)) if depth >= 0 => depth,
+        _ => fail("usage: Chess_Engine perft <depth> [--fen <fen>] [--hash-mb N] [--diff <engine>]".to_string()),
+    };
+    let hash_mb = value_of("--hash-mb").map_or(16, |mb| {
+        mb.parse::<usize>().unwrap_or_else(|_| fail("--hash-mb expects a number of megabytes".to_string()))
+    });
+    let board = match value_of("--fen") {
+        Some(fen) => Board::from_fen(fen).unwrap_or_else(|e| fail(e)),
+        None => Board::new(),
+    };
+
+    let mut perft = Perft::with_hash(hash_mb);
+    if let Some(path) = value_of("--diff") {
+        let mut engine = UciEngine::spawn(path, &[]).unwrap_or_else(|e| fail(e));
+        let name = engine.name();
+        let reference = |line: &[String], depth| engine.perft_divide(board.position(), line, depth);
+        let trace = |line: &[String], ours: &Divide, theirs: &Divide| {
+            let moves = if line.is_empty() { "root".to_string() } else { line.join(" ") };
+            println!("{}, depth {}: {} here, {} for {}",
+                moves, depth - line.len() as i32, ours.values().sum::<u64>(), theirs.values().sum::<u64>(), name);
+            for (mv, nodes) in ours {
+                if let Some(theirs) = theirs.get(mv).filter(|&theirs| theirs != nodes) {
+                    println!("  {:<6} {:>12} {:>12}", mv, nodes, theirs);
+                }
+            }
+        };
+        match perft.find_divergence(&board, depth, reference, trace) {
+            Ok(None) => println!("perft({}) agrees with {}", depth, name),
+            Ok(Some(divergence)) => {
+                println!("Move generation diverges after: {}", divergence.line.join(" "));
+                if !divergence.missing.is_empty() {
+                    println!("  not generated here: {}", divergence.missing.join(" "));
+                }
+                if !divergence.extra.is_empty() {
+                    println!("  not generated by {}: {}", name, divergence.extra.join(" "));
+                }
+                std::process::exit(1);
+            }
+            Err(e) => fail(e),
+        }
+        return;
+    }
+    let start = Instant::now();
+    let nodes = perft.run(board.position(), depth);
+    let seconds = start.elapsed().as_secs_f64();
+    println!("perft({}) = {}", depth, nodes);
+    println!("{:.2}s, {:.0} nodes/s, {} cache hits", seconds, nodes as f64 / seconds.max(1e-9), perft.hits());
+}
+
+/// Prints the leaf count below every move of `<fen>` (or `startpos`) at
+/// `<depth>`, in the format of UCI `go perft`, to compare line by line with
+/// a reference engine's divide output.
+fn perft_divide(args: &[String]) {
+    use std::time::Instant;
+    use rustchess::Board;
+    use rustchess::perft::{divide_lines, Perft};
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let (Some(fen), Some(depth)) = (args.first(), args.get(1).and_then(|depth| depth.parse::<i32>().ok()).filter(|&depth| depth > 0)) else {
+        fail("usage: Chess_Engine --perft <fen>|startpos <depth>".to_string());
+    };
+    let board = match fen.as_str() {
+        "startpos" => Board::new(),
+        fen => Board::from_fen(fen).unwrap_or_else(|e| fail(e)),
+    };
+
+    let start = Instant::now();
+    let divide = Perft::with_hash(16).divide(board.position(), depth);
+    for line in divide_lines(&divide) {
+        println!("{}", line);
+    }
+    eprintln!("{:.2}s", start.elapsed().as_secs_f64());
+}
+
+/// Searches every position of the EPD file given for `--movetime MS`
+/// (default 1000) or to `--depth N`, prints whether each move found passes
+/// the record, and exits with status 1 if any does not.
+fn run_epd(args: &[String]) {
+    use std::time::Duration;
+    use rustchess::epd::{parse_epd, run};
+    use rustchess::player::SearchLimits;
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or_else(|| fail(format!("{} expects a number", flag)))
+    });
+    let Some(path) = args.first().filter(|arg| !arg.starts_with("--")) else {
+        fail("usage: Chess_Engine epd <file> [--movetime MS] [--depth N]".to_string());
+    };
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| fail(format!("Could not read {}: {}", path, e)));
+    let records = parse_epd(&text).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    let limits = SearchLimits {
+        movetime: Some(Duration::from_millis(value_of("--movetime").unwrap_or(1000))),
+        depth: value_of("--depth").map(|depth| depth as i32),
+        ..SearchLimits::default()
+    };
+
+    let results = run(&records, &mut configured_search(), &limits);
+    for result in &results {
+        let found = result.found.map_or("none".to_string(), |mv| mv.to_string());
+        println!("{} {} ({})", if result.solved { "ok  " } else { "FAIL" }, result.name, found);
+    }
+    let solved = results.iter().filter(|result| result.solved).count();
+    println!("{}/{} solved", solved, results.len());
+    if solved < results.len() {
+        std::process::exit(1);
+    }
+}
+
+/// Searches the benchmark positions to `[depth]` (default
+/// `DEFAULT_BENCH_DEPTH`) and prints each one's nodes, then the total nodes,
+/// the engine's signature, and the nodes per second.
+fn run_bench(args: &[String]) {
+    use rustchess::bench::{run, DEFAULT_BENCH_DEPTH};
+
+    let depth = match args.first().map(|depth| depth.parse::<i32>()) {
+        None => DEFAULT_BENCH_DEPTH,
+        Some(Ok(depth)) if depth > 0 => depth,
+        _ => {
+            eprintln!("usage: Chess_Engine bench [depth]");
+            std::process::exit(2);
+        }
+    };
+    let report = run(&mut configured_search(), depth);
+    for (index, position) in report.positions.iter().enumerate() {
+        println!("{:>2} {:>10} nodes  {}", index + 1, position.nodes, position.fen);
+    }
+    println!("depth {}: {} nodes in {:.2}s, {} nodes/s",
+        depth, report.nodes(), report.elapsed().as_secs_f64(), report.nodes_per_second());
+}
+
+/// Tunes the search parameters by SPSA for `--iterations N` self-play
+/// matches (default 100) of `--pairs N` game pairs (default 4) at
+/// `--movetime MS` per move (default 50), starting from the current search
+/// config. The parameters are written to `-o <file>` (default the config
+/// file the engine loads) after every iteration, so an interrupted run
+/// keeps its progress.
+/// Searches `<fen>` for `--movetime MS` (default 5000) or to `--depth N`,
+/// printing one JSON object per completed iteration and a last one with the
+/// best move and whether the node ceiling cut the search short, for tools
+/// that read the engine's analysis.
+#[cfg(feature = "cli")]
+fn analyze_json(args: &[String]) {
+    use std::sync::mpsc;
+    use rustchess::{Game, Move, Position};
+    use serde_json::json;
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or_else(|| fail(format!("{} expects a number", flag)))
+    });
+    let Some(fen) = args.first().filter(|arg| !arg.starts_with("--")) else {
+        fail("usage: Chess_Engine --analyze-json <fen> [--movetime MS] [--depth N]".to_string());
+    };
+    let game = Game::new();
+    let mut position = Position::from_fen(fen, &game).unwrap_or_else(|e| fail(e));
+
+    let (sender, receiver) = mpsc::channel();
+    let mut search = configured_search();
+    search.set_max_time_ms(value_of("--movetime").unwrap_or(5000));
+    if let Some(depth) = value_of("--depth") {
+        search.set_max_depth(depth as i32);
+    }
+    search.set_info_sender(Some(sender));
+    let root = position.clone();
+    let worker = std::thread::spawn(move || {
+        let best = search.find_best_move(&mut position);
+        (best, search.truncated())
+    });
+
+    // The channel closes once the search and its sender are dropped
+    for info in receiver.iter().filter(|info| info.complete) {
+        // Each move is named in the position it is played from
+        let mut line = root.clone();
+        let pv: Vec<String> = info.pv.iter().map(|&mov| {
+            let text = Move::from_u64(mov, &line).to_string();
+            line.make_move(mov);
+            text
+        }).collect();
+        println!("{}", json!({
+            "depth": info.depth,
+            "seldepth": info.seldepth,
+            "score": info.score,
+            "pv": pv,
+            "nodes": info.nodes,
+            "time_ms": info.time.as_millis() as u64,
+        }));
+    }
+    let (best, truncated) = worker.join().unwrap_or_else(|_| fail("The search thread panicked".to_string()));
+    println!("{}", json!({ "bestmove": best.map(|mov| Move::from_u64(mov, &root).to_string()), "truncated": truncated }));
+}
+
+#[cfg(feature = "tuning")]
+fn run_tuning(args: &[String]) {
+    use std::path::PathBuf;
+    use rustchess::player::SearchLimits;
+    use rustchess::search_params::{CONFIG_ENV, DEFAULT_CONFIG_FILE};
+    use rustchess::tuning::{Spsa, SpsaSettings, Tunable};
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).unwrap_or_else(|| fail(format!("{} expects a value", flag)))
+    });
+    let number_of = |flag: &str, default: u64| value_of(flag).map_or(default, |n| {
+        n.parse::<u64>().unwrap_or_else(|_| fail(format!("{} expects a number", flag)))
+    });
+    let output = value_of("-o").map(PathBuf::from)
+        .or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+    let settings = SpsaSettings {
+        game_pairs: number_of("--pairs", 4) as u32,
+        limits: SearchLimits::movetime_ms(number_of("--movetime", 50)),
+        seed: number_of("--seed", 0),
+        ..SpsaSettings::default()
+    };
+
+    let start = *configured_search().params();
+    let mut spsa = Spsa::new(start, &Tunable::ALL, settings);
+    spsa.run(number_of("--iterations", 100) as u32, |report| {
+        let values: Vec<String> = Tunable::ALL.iter()
+            .map(|tunable| format!("{}={}", tunable.name(), tunable.get(&report.params)))
+            .collect();
+        println!("{:>4} score {:+.2}  {}", report.iteration, report.score, values.join(" "));
+        if let Err(e) = report.params.save(&output) {
+            fail(e);
+        }
+    });
+    println!("Wrote {}", output.display());
+}
+
+/// Plays `--games N` games (default 10) at `--movetime MS` per engine move
+/// (default 100) against each reference opponent and prints the estimated
+/// Elo. `--seed S` fixes the opponents' random choices, and `--json` prints
+/// only the final report, as JSON.
+///
+/// The result is appended to the strength history (see `strength`) unless
+/// `--no-record` is given; `--history` prints the history instead of
+/// playing, with regressions and the parameter changes behind them.
+#[cfg(feature = "tuning")]
+fn run_calibration(args: &[String]) {
+    use rustchess::calibration::{calibrate, ReferenceOpponent};
+    use rustchess::player::SearchLimits;
+    use rustchess::strength::{self, StrengthRecord};
+
+    let history_path = strength::history_path();
+    if args.iter().any(|arg| arg == "--history") {
+        match strength::load(&history_path) {
+            Ok(history) if history.is_empty() => println!("No runs recorded in {}", history_path.display()),
+            Ok(history) => print!("{}", strength::report(&history)),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let number_of = |flag: &str, default: u64| match args.iter().position(|arg| arg == flag) {
+        Some(index) => match args.get(index + 1).and_then(|n| n.parse::<u64>().ok()) {
+            Some(n) => n,
+            None => {
+                eprintln!("{} expects a number", flag);
+                std::process::exit(2);
+            }
+        },
+        None => default,
+    };
+    let games = number_of("--games", 10) as u32;
+    let movetime_ms = number_of("--movetime", 100);
+    let limits = SearchLimits::movetime_ms(movetime_ms);
+
+    let json = args.iter().any(|arg| arg == "--json");
+    let mut engine = configured_engine();
+    let report = calibrate(&mut engine, &ReferenceOpponent::ALL, games, &limits, number_of("--seed", 0), |opponent, result| {
+        if !json {
+            println!("vs {}: {:?}", opponent, result);
+        }
+    });
+    if json {
+        let records: Vec<_> = report.records.iter().map(|record| serde_json::json!({
+            "opponent": record.opponent.to_string(),
+            "rating": record.opponent.rating(),
+            "wins": record.wins,
+            "draws": record.draws,
+            "losses": record.losses,
+        })).collect();
+        println!("{}", serde_json::json!({ "elo": report.elo, "margin": report.margin, "records": records }));
+    } else {
+        println!("\n{}", report);
+    }
+
+    if !args.iter().any(|arg| arg == "--no-record") {
+        let record = StrengthRecord::new(&report, movetime_ms, &engine.params().to_toml());
+        match strength::append(&history_path, &record) {
+            Ok(()) if !json => println!("Recorded in {}", history_path.display()),
+            Ok(()) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// Runs a correspondence subcommand on the games in `--dir <dir>` (default
+/// `correspondence`):
+///
+/// * `list` - every game with its move count and the engine's current line
+/// * `new [id] [--fen <fen>]` - starts a game
+/// * `show <id>` - the game's moves and position
+/// * `move <id> <move>` - records a move by either side
+/// * `think <id> [--hours H] [--checkpoint MINUTES] [--play]` - thinks about
+///   the current move, resuming any earlier think, and with `--play` plays
+///   the result
+fn run_correspondence(args: &[String]) {
+    use std::time::Duration;
+    use rustchess::correspondence::{Checkpoint, CorrespondenceGame};
+
+    const USAGE: &str = "usage: Chess_Engine corr <list|new [id]|show <id>|move <id> <move>|think <id>> \
+        [--dir <dir>] [--fen <fen>] [--hours H] [--checkpoint MINUTES] [--play]";
+    let fail = |msg: &str| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    };
+    let value_of = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).map(String::as_str).unwrap_or_else(|| fail(&format!("{} expects a value", flag)))
+    });
+    let number_of = |flag: &str, default: f64| value_of(flag).map_or(default, |value| {
+        value.parse::<f64>().ok().filter(|n| *n > 0.0).unwrap_or_else(|| fail(&format!("{} expects a positive number", flag)))
+    });
+    // Positional arguments are everything that is not a flag or a flag's value
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--play" => {}
+            flag if flag.starts_with("--") => {
+                iter.next();
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let dir = value_of("--dir").unwrap_or("correspondence");
+    let describe = |checkpoint: &Checkpoint| format!("{} ({:+.2}, {} thought) pv {}",
+        checkpoint.best_move(), checkpoint.score as f64 / 100.0,
+        format_duration(checkpoint.thought_ms), checkpoint.pv.join(" "));
+
+    let result = match positional.as_slice() {
+        ["list"] => CorrespondenceGame::list(dir).map(|games| {
+            if games.is_empty() {
+                println!("No correspondence games in {}", dir);
+            }
+            for game in games {
+                let line = game.checkpoint.as_ref().map_or("not analyzed".to_string(), describe);
+                println!("{:<12} {:>3} moves  {}", game.id, game.moves.len(), line);
+            }
+        }),
+        ["new", rest @ ..] if rest.len() <= 1 => CorrespondenceGame::create(dir, rest.first().copied(), value_of("--fen"))
+            .map(|game| println!("Started game {}", game.id)),
+        ["show", id] => CorrespondenceGame::load(dir, id).and_then(|game| {
+            let board = game.board()?;
+            println!("Game {} from {}", game.id, game.start_fen);
+            println!("Moves: {}", if game.moves.is_empty() { "(none)".to_string() } else { game.moves.join(" ") });
+            println!("{:?} to move{}", board.side_to_move(), if board.is_check() { ", in check" } else { "" });
+            if let Some(checkpoint) = &game.checkpoint {
+                println!("Engine: {}", describe(checkpoint));
+            }
+            Ok(())
+        }),
+        ["move", id, mv] => CorrespondenceGame::load(dir, id).and_then(|mut game| game.play(mv)),
+        ["think", id] => CorrespondenceGame::load(dir, id).and_then(|mut game| {
+            let budget = Duration::from_secs_f64(number_of("--hours", 1.0) * 3600.0);
+            let interval = Duration::from_secs_f64(number_of("--checkpoint", 5.0) * 60.0);
+            let mut engine = configured_engine();
+            let line = game.think(&mut engine, budget, interval, |checkpoint| {
+                println!("checkpoint: {}", describe(checkpoint));
+            })?;
+            if args.iter().any(|arg| arg == "--play") {
+                game.play(line.best_move())?;
+                println!("Played {}", line.best_move());
+            } else {
+                println!("bestmove {}", line.best_move());
+            }
+            Ok(())
+        }),
+        _ => fail(USAGE),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Formats a duration in milliseconds as `1h02m03s`.
+fn format_duration(millis: u64) -> String {
+    let seconds = millis / 1000;
+    format!("{}h{:02}m{:02}s", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
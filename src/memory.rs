@@ -0,0 +1,71 @@
+//! Memory use of the engine's caches and tables.
+//!
+//! Front ends list what each part takes so a user can see where memory goes
+//! and set budgets for the caches that grow: the engine's hash table and the
+//! GUI's analysis cache. The endgame tables are only listed once loaded.
+//! Sizes of tables made of hash maps and strings are estimates.
+
+/// One part of the engine and the bytes it takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryUse {
+    pub name: String,
+    pub bytes: usize,
+}
+
+impl MemoryUse {
+    pub fn new(name: &str, bytes: usize) -> Self {
+        MemoryUse { name: name.to_string(), bytes }
+    }
+}
+
+/// The endgame tables in use, without loading any that are not.
+pub fn endgame_tables() -> Vec<MemoryUse> {
+    let mut uses = Vec::new();
+    if let Some(tables) = crate::tablebase::loaded_tablebases() {
+        uses.push(MemoryUse::new("Endgame tables", tables.memory_bytes()));
+    }
+    if let Some(kpk) = crate::bitbase::loaded_kpk() {
+        uses.push(MemoryUse::new("KPK bitbase", kpk.memory_bytes()));
+    }
+    uses
+}
+
+/// Formats a byte count for people, e.g. `32.0 MB`.
+pub fn format_bytes(bytes: usize) -> String {
+    const KB: usize = 1024;
+    const MB: usize = 1024 * KB;
+    if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.1} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Lists each part with its size, then the total.
+pub fn report(uses: &[MemoryUse]) -> String {
+    let width = uses.iter().map(|part| part.name.len()).max().unwrap_or(0).max("Total".len());
+    let mut text = String::new();
+    for part in uses {
+        text.push_str(&format!("{:<width$}  {:>10}\n", part.name, format_bytes(part.bytes), width = width));
+    }
+    let total = uses.iter().map(|part| part.bytes).sum();
+    text.push_str(&format!("{:<width$}  {:>10}\n", "Total", format_bytes(total), width = width));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(32 * 1024 * 1024), "32.0 MB");
+
+        let text = report(&[MemoryUse::new("Hash table", 2 * 1024 * 1024), MemoryUse::new("Book", 1024)]);
+        assert_eq!(text, "Hash table      2.0 MB\nBook            1.0 KB\nTotal           2.0 MB\n");
+    }
+}
@@ -4,6 +4,8 @@
 //! and attack patterns. It uses bitboards for efficient move generation and
 //! position evaluation.
 
+use alloc::{vec, vec::Vec};
+
 
 /// A collection of pre-computed lookup tables for chess move generation.
 /// 
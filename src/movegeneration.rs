@@ -3,20 +3,135 @@
 //! This module handles the generation of legal chess moves for all piece types.
 //! It uses bitboard operations for efficient move generation and validates moves
 //! against the current game state.
+//!
+//! `generate_evasions` and `generate_checks` produce the legal moves of one
+//! kind without generating every legal move first: the way out of check at
+//! in-check nodes, and checking moves for quiescence and check extensions.
 
+use alloc::vec::Vec;
+use crate::board_consts::*;
 use crate::position::*;
 #[allow(unused_imports)]
 use crate::knightattacks::*;
 use crate::position::PieceType::*;
-use crate::utils::{bit_scan_safe, extract_bits};
+use crate::utils::{bit_scan, bit_scan_safe, extract_bits, Bitboard};
+use crate::chess_move::{Move, PROMOTION_PIECES};
 use crate::Game;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CastlingSide {
     Kingside,
     Queenside,
 }
 
+/// The geometry of one castling move: where the king and rook start and
+/// end, the squares that must be empty and the right it needs. Movegen,
+/// `make_move` and the castling-rights updates all read it from here, in
+/// the copy each `Position` keeps so that Chess960 positions can carry
+/// their own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Castling {
+    pub color: Color,
+    pub side: CastlingSide,
+    pub right: CastlingRights,
+    pub king_from: usize,
+    pub king_to: usize,
+    pub rook_from: usize,
+    pub rook_to: usize,
+    /// Squares between the king and rook and their destinations, which must
+    /// be empty apart from the castling king and rook themselves
+    pub path: Bitboard,
+}
+
+/// Standard castling, indexed by `Castling::index`.
+pub const STANDARD_CASTLING: [Castling; 4] = [
+    Castling::new(Color::White, CastlingSide::Kingside, 4, 7),
+    Castling::new(Color::White, CastlingSide::Queenside, 4, 0),
+    Castling::new(Color::Black, CastlingSide::Kingside, 4, 7),
+    Castling::new(Color::Black, CastlingSide::Queenside, 4, 0),
+];
+
+impl Castling {
+    /// Builds the geometry of castling from the king's and rook's starting
+    /// files, so variants with other starting squares (Chess960) can reuse it.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The side castling
+    /// * `side` - Kingside or queenside
+    /// * `king_file` - The king's starting file (0-7)
+    /// * `rook_file` - The rook's starting file (0-7)
+    pub const fn new(color: Color, side: CastlingSide, king_file: usize, rook_file: usize) -> Castling {
+        let (back_rank, right) = match (color, side) {
+            (Color::White, CastlingSide::Kingside) => (0, CastlingRights::WHITEKINGSIDE),
+            (Color::White, CastlingSide::Queenside) => (0, CastlingRights::WHITEQUEENSIDE),
+            (Color::Black, CastlingSide::Kingside) => (56, CastlingRights::BLACKKINGSIDE),
+            (Color::Black, CastlingSide::Queenside) => (56, CastlingRights::BLACKQUEENSIDE),
+        };
+        // The king ends on the g- or c-file with the rook next to it
+        let (king_to_file, rook_to_file) = match side {
+            CastlingSide::Kingside => (6, 5),
+            CastlingSide::Queenside => (2, 3),
+        };
+
+        let mut path = 0;
+        let mut file = 0;
+        while file < 8 {
+            let king_span = between(king_file, king_to_file, file);
+            let rook_span = between(rook_file, rook_to_file, file);
+            if (king_span || rook_span) && file != king_file && file != rook_file {
+                path |= 1u64 << (back_rank + file);
+            }
+            file += 1;
+        }
+
+        Castling {
+            color,
+            side,
+            right,
+            king_from: back_rank + king_file,
+            king_to: back_rank + king_to_file,
+            rook_from: back_rank + rook_file,
+            rook_to: back_rank + rook_to_file,
+            path,
+        }
+    }
+
+    /// Position of this castling move in `STANDARD_CASTLING` and in
+    /// `Position::castling`.
+    pub const fn index(color: Color, side: CastlingSide) -> usize {
+        let color = match color {
+            Color::White => 0,
+            Color::Black => 2,
+        };
+        match side {
+            CastlingSide::Kingside => color,
+            CastlingSide::Queenside => color + 1,
+        }
+    }
+
+    /// Standard castling for `color` on `side`.
+    pub fn standard(color: Color, side: CastlingSide) -> &'static Castling {
+        &STANDARD_CASTLING[Castling::index(color, side)]
+    }
+
+    /// The squares the king passes over, including where it starts and ends,
+    /// none of which may be attacked.
+    pub fn king_path(&self) -> Bitboard {
+        (self.king_from.min(self.king_to)..=self.king_from.max(self.king_to))
+            .fold(0, |path, square| path | (1u64 << square))
+    }
+}
+
+/// Whether `file` lies between `from` and `to`, both included.
+const fn between(from: usize, to: usize, file: usize) -> bool {
+    (from <= file && file <= to) || (to <= file && file <= from)
+}
+
 /// Generates all legal moves for the current position.
 /// 
 /// This function iterates through all pieces of the active color and generates
@@ -110,9 +225,6 @@ pub fn generate_moves(game: &Game) -> Vec<Position> {
 /// * A vector of new positions representing legal pawn moves
 fn generate_pawn_moves(piece: &Piece, game: &Game, all_occupancy: u64, opponent_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         // Use the correct forward and diagonal moves based on color
         let (forward_moves, diagonal_moves) = match piece.color {
@@ -126,24 +238,19 @@ fn generate_pawn_moves(piece: &Piece, game: &Game, all_occupancy: u64, opponent_
             ),
         };
         
-        // Forward moves (not blocked)
-        let single_forward = forward_moves & !all_occupancy;
-        let double_forward = if piece.color == Color::White && square / 8 == 1 {
-            // For white pawns on second rank, check if both squares are empty
-            let single_empty = (forward_moves & !all_occupancy) != 0;
-            if single_empty {
-                forward_moves & !all_occupancy & (0xFF << 16) // Only allow double moves to rank 4
-            } else {
-                0
-            }
+        // The rank a double move lands on, for pawns on their starting rank
+        let double_rank = if piece.color == Color::White && square / 8 == 1 {
+            RANK_4
         } else if piece.color == Color::Black && square / 8 == 6 {
-            // For black pawns on seventh rank, check if both squares are empty
-            let single_empty = (forward_moves & !all_occupancy) != 0;
-            if single_empty {
-                forward_moves & !all_occupancy & (0xFF << 32) // Only allow double moves to rank 5
-            } else {
-                0
-            }
+            RANK_5
+        } else {
+            0
+        };
+
+        // Forward moves (not blocked); a double move also needs the square it passes empty
+        let single_forward = forward_moves & !all_occupancy & !double_rank;
+        let double_forward = if single_forward != 0 {
+            forward_moves & !all_occupancy & double_rank
         } else {
             0
         };
@@ -199,9 +306,6 @@ fn generate_pawn_moves(piece: &Piece, game: &Game, all_occupancy: u64, opponent_
 /// * A vector of new positions representing legal knight moves
 fn generate_knight_moves(piece: &Piece, game: &Game, own_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         let mut attacks = game.move_gen_tables.knight_attacks[square];
         attacks &= !own_occupancy;
@@ -229,9 +333,6 @@ fn generate_knight_moves(piece: &Piece, game: &Game, own_occupancy: u64) -> Vec<
 /// * A vector of new positions representing legal bishop moves
 fn generate_bishop_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         let attacks = game.rays.get_bishop_attacks(square, all_occupancy, piece.color, own_occupancy);
         let valid_moves = attacks & !own_occupancy;
@@ -259,9 +360,6 @@ fn generate_bishop_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occ
 /// * A vector of new positions representing legal rook moves
 fn generate_rook_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         let attacks = game.rays.get_rook_attacks(square, all_occupancy);
         let valid_moves = attacks & !own_occupancy;
@@ -292,9 +390,6 @@ fn generate_rook_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occup
 /// * A vector of new positions representing legal queen moves
 fn generate_queen_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         let attacks = game.rays.get_queen_attacks(square, all_occupancy);
         let valid_moves = attacks & !own_occupancy;
@@ -324,9 +419,6 @@ fn generate_queen_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occu
 /// * A vector of new positions representing legal king moves
 fn generate_king_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occupancy: u64) -> Vec<Position> {
     let mut new_positions = Vec::new();
-    if piece.position == 0 {
-        return new_positions;  // Skip captured pieces
-    }
     if let Some(square) = bit_scan_safe(piece.position) {
         let mut attacks = game.move_gen_tables.king_attacks[square];
         attacks &= !own_occupancy;
@@ -361,86 +453,21 @@ fn generate_king_moves(piece: &Piece, game: &Game, own_occupancy: u64, all_occup
 /// 
 /// * `true` if castling is legal, `false` otherwise
 pub fn can_castle(position: &Position, color: Color, side: CastlingSide) -> bool {
-    // Check if the king has moved
-    if (color == Color::White && position.white_king_moved) ||
-       (color == Color::Black && position.black_king_moved) {
-        return false;
-    }
+    let castling = position.castling(color, side);
 
-    // Check if the appropriate rook has moved
-    match (color, side) {
-        (Color::White, CastlingSide::Kingside) => {
-            if position.white_kingside_rook_moved {
-                return false;
-            }
-        },
-        (Color::White, CastlingSide::Queenside) => {
-            if position.white_queenside_rook_moved {
-                return false;
-            }
-        },
-        (Color::Black, CastlingSide::Kingside) => {
-            if position.black_kingside_rook_moved {
-                return false;
-            }
-        },
-        (Color::Black, CastlingSide::Queenside) => {
-            if position.black_queenside_rook_moved {
-                return false;
-            }
-        },
+    // The king and the rook must not have moved, and the right must remain
+    if position.king_moved(color) || position.rook_moved(castling)
+        || !position.castling_rights.contains(castling.right) {
+        return false;
     }
 
     // Check if the path is blocked by any pieces
-    let path = match (color, side) {
-        (Color::White, CastlingSide::Kingside) => 0x60,  // f1 and g1
-        (Color::White, CastlingSide::Queenside) => 0xE,  // b1, c1, and d1
-        (Color::Black, CastlingSide::Kingside) => 0x6000000000000000,  // f8 and g8
-        (Color::Black, CastlingSide::Queenside) => 0xE00000000000000,  // b8, c8, and d8
-    };
-
     let all_pieces = position.white_occupancy | position.black_occupancy;
-    if (path & all_pieces) != 0 {
-        return false;
-    }
-
-    // Check if the castling path is attacked for the correct side
-    match (color, side) {
-        (Color::White, CastlingSide::Kingside) => {
-            if position.white_kingside_path_attacked {
-                return false;
-            }
-        },
-        (Color::White, CastlingSide::Queenside) => {
-            if position.white_queenside_path_attacked {
-                return false;
-            }
-        },
-        (Color::Black, CastlingSide::Kingside) => {
-            if position.black_kingside_path_attacked {
-                return false;
-            }
-        },
-        (Color::Black, CastlingSide::Queenside) => {
-            if position.black_queenside_path_attacked {
-                return false;
-            }
-        },
-    }
-
-    // Check castling rights
-    let required_rights = match (color, side) {
-        (Color::White, CastlingSide::Kingside) => CastlingRights::WHITEKINGSIDE,
-        (Color::White, CastlingSide::Queenside) => CastlingRights::WHITEQUEENSIDE,
-        (Color::Black, CastlingSide::Kingside) => CastlingRights::BLACKKINGSIDE,
-        (Color::Black, CastlingSide::Queenside) => CastlingRights::BLACKQUEENSIDE,
-    };
-
-    if position.castling_rights & required_rights == CastlingRights::NONE {
+    if (castling.path & all_pieces) != 0 {
         return false;
     }
 
-    true
+    !position.castling_path_attacked(castling)
 }
 
 /// Adds legal castling moves to the list of moves.
@@ -452,63 +479,197 @@ pub fn can_castle(position: &Position, color: Color, side: CastlingSide) -> bool
 /// * `new_positions` - Vector to add castling moves to
 /// * `side` - The castling side
 fn add_castling_moves(piece: &Piece, game: &Game, new_positions: &mut Vec<Position>, side: CastlingSide) {
-    if piece.position == 0 {
-        return;  // Skip captured pieces
-    }
-    if let Some(king_pos) = bit_scan_safe(piece.position) {
-        let mut new_position = game.position.clone();
-        let (new_king_pos, new_rook_pos, old_rook_pos) = match (piece.color, side) {
-            (Color::White, CastlingSide::Kingside) => (6, 5, 7),   // g1, f1, h1
-            (Color::White, CastlingSide::Queenside) => (2, 3, 0),  // c1, d1, a1
-            (Color::Black, CastlingSide::Kingside) => (62, 61, 63),  // g8, f8, h8
-            (Color::Black, CastlingSide::Queenside) => (58, 59, 56),  // c8, d8, a8
-        };
+    let castling = *game.position.castling(piece.color, side);
+    if castling.king_from != bit_scan(piece.position) {
+        return;
+    }
+    let mut new_position = game.position.clone();
+    new_position.castle(&castling);
 
-        // Move the king
-        let king_piece = new_position.pieces.iter_mut()
-            .find(|p| p.piece_type == PieceType::King && p.color == piece.color)
-            .unwrap();
-        king_piece.position = 1u64 << new_king_pos;
+    // Update castling flags
+    new_position.set_king_moved(piece.color);
+    new_position.set_rook_moved(&castling);
 
-        // Move the rook
-        let rook_piece = new_position.pieces.iter_mut()
-            .find(|p| p.piece_type == PieceType::Rook && p.color == piece.color && p.position == 1u64 << old_rook_pos)
-            .unwrap();
-        rook_piece.position = 1u64 << new_rook_pos;
-
-        // Update occupancy bitboards
-        if piece.color == Color::White {
-            new_position.white_occupancy = new_position.pieces.iter()
-                .filter(|p| p.color == Color::White)
-                .map(|p| p.position)
-                .fold(0, |acc, pos| acc | pos);
-        } else {
-            new_position.black_occupancy = new_position.pieces.iter()
-                .filter(|p| p.color == Color::Black)
-                .map(|p| p.position)
-                .fold(0, |acc, pos| acc | pos);
+    new_positions.push(new_position);
+}
+
+/// The squares strictly between two squares on the same rank, file or
+/// diagonal; empty if they are not on one line or are adjacent.
+pub fn squares_between(a: usize, b: usize) -> Bitboard {
+    let (file_step, rank_step) = ((b % 8) as i32 - (a % 8) as i32, (b / 8) as i32 - (a / 8) as i32);
+    if a == b || (file_step != 0 && rank_step != 0 && file_step.abs() != rank_step.abs()) {
+        return 0;
+    }
+    let step = rank_step.signum() * 8 + file_step.signum();
+    let mut between = 0;
+    let mut square = a as i32 + step;
+    while square != b as i32 {
+        between |= 1u64 << square;
+        square += step;
+    }
+    between
+}
+
+/// Legal moves that get the side to move out of check.
+///
+/// Only moves that can help are tried: king moves and, against a single
+/// checker, captures of it (en passant included) and moves onto the
+/// squares between it and the king. In double check only the king may
+/// move. Each candidate is then tested for leaving the king attacked, which
+/// rules out pinned pieces.
+///
+/// # Arguments
+///
+/// * `position` - The position, with the side to move in check
+/// * `game` - The game holding the attack tables
+///
+/// # Returns
+///
+/// * The packed moves, in the order `Position::get_all_legal_moves` lists
+///   them; none if the side to move is not in check
+pub fn generate_evasions(position: &Position, game: &Game) -> Vec<u64> {
+    let checkers = position.checkers(game);
+    let Some(king) = position.king_square(position.active_color) else {
+        return Vec::new();
+    };
+    if checkers == 0 {
+        return Vec::new();
+    }
+
+    // Squares other pieces may move to: none in double check
+    let mut blocks = 0;
+    let mut en_passant = 0;
+    if checkers.count_ones() == 1 {
+        let checker = bit_scan(checkers);
+        blocks = checkers | squares_between(king, checker);
+        // A pawn that just advanced two squares may be taken en passant
+        if let Some(target) = position.en_passant {
+            let passed = if position.active_color == Color::White { bit_scan(target) - 8 } else { bit_scan(target) + 8 };
+            if passed == checker {
+                en_passant = target;
+            }
+        }
+    }
+
+    let mut moves = Vec::new();
+    for (index, piece) in position.pieces.iter().enumerate().filter(|(_, p)| p.color == position.active_color) {
+        let Some(from) = bit_scan_safe(piece.position) else {
+            continue;
+        };
+        let allowed = match piece.piece_type {
+            King => !0,
+            Pawn => blocks | en_passant,
+            _ => blocks,
+        };
+        for to in extract_bits(position.pseudo_legal_targets(game, piece, from) & allowed) {
+            if position.keeps_king_safe(game, index, to) {
+                push_moves(&mut moves, piece, from, to);
+            }
+        }
+    }
+    moves
+}
+
+/// Legal moves that give check, directly or by moving a piece off the line
+/// between the opponent's king and a sliding piece behind it, promotions
+/// included.
+///
+/// Candidates are the moves onto squares the piece attacks the king from,
+/// moves of pieces standing between the king and one of their sliders,
+/// promotions, en passant captures and castling; each is then played out
+/// on the placement to confirm the check and its legality. Castling counts
+/// as allowed if the last `Position::update_all_legal_moves` allowed it.
+///
+/// # Arguments
+///
+/// * `position` - The position
+/// * `game` - The game holding the attack tables
+///
+/// # Returns
+///
+/// * The packed moves, in the order `Position::get_all_legal_moves` lists
+///   them
+pub fn generate_checks(position: &Position, game: &Game) -> Vec<u64> {
+    let us = position.active_color;
+    let them = if us == Color::White { Color::Black } else { Color::White };
+    let Some(their_king) = position.king_square(them) else {
+        return Vec::new();
+    };
+    let king_bitboard = 1u64 << their_king;
+    let all_occupancy = position.white_occupancy | position.black_occupancy;
+    let own_occupancy = if us == Color::White { position.white_occupancy } else { position.black_occupancy };
+
+    // Our pieces that alone shield the king from one of our sliders
+    let mut blockers = 0;
+    for slider in position.pieces.iter().filter(|p| p.color == us && matches!(p.piece_type, Bishop | Rook | Queen)) {
+        let square = bit_scan(slider.position);
+        let straight = square % 8 == their_king % 8 || square / 8 == their_king / 8;
+        let fits = match slider.piece_type {
+            Bishop => !straight,
+            Rook => straight,
+            _ => true,
+        };
+        let between = squares_between(their_king, square);
+        if fits && (between & all_occupancy).count_ones() == 1 {
+            blockers |= between & own_occupancy;
         }
+    }
+
+    // The castled rook may check along its new file or rank
+    let castlings = position.legal_castlings(game);
+    let castling_targets = castlings.iter().fold(0, |targets, castling| targets | 1u64 << position.castling_target(castling));
+
+    let mut moves = Vec::new();
+    for (index, piece) in position.pieces.iter().enumerate().filter(|(_, p)| p.color == us) {
+        let Some(from) = bit_scan_safe(piece.position) else {
+            continue;
+        };
+        let discovers = blockers & piece.position != 0;
+        let castles = if piece.piece_type == King { castling_targets } else { 0 };
+        for to in extract_bits(position.pseudo_legal_targets(game, piece, from) | castles) {
+            let to_bitboard = 1u64 << to;
+            if let Some(castling) = castlings.iter().find(|castling| castles & to_bitboard != 0 && position.castling_target(castling) == to) {
+                let mut after = position.clone();
+                after.castle(castling);
+                after.active_color = them;
+                if after.is_in_check(game) {
+                    moves.push(Move::encode(from, to, None));
+                }
+                continue;
+            }
+            let promotes = piece.piece_type == Pawn && to_bitboard & (RANK_1 | RANK_8) != 0;
+            let takes_en_passant = piece.piece_type == Pawn && position.en_passant == Some(to_bitboard);
+            let occupancy = (all_occupancy & !piece.position) | to_bitboard;
+            let moved = Piece { position: to_bitboard, ..*piece };
+            let direct = moved.attacks(game, occupancy) & king_bitboard != 0;
+            if !(direct || discovers || promotes || takes_en_passant) {
+                continue;
+            }
 
-        // Update castling flags
-        match piece.color {
-            Color::White => {
-                new_position.white_king_moved = true;
-                match side {
-                    CastlingSide::Kingside => new_position.white_kingside_rook_moved = true,
-                    CastlingSide::Queenside => new_position.white_queenside_rook_moved = true,
+            let mut after = position.with_piece_moved(index, to);
+            after.active_color = us;
+            if after.is_in_check(game) {
+                continue;
+            }
+            after.active_color = them;
+            if !promotes {
+                if after.is_in_check(game) {
+                    moves.push(Move::encode(from, to, None));
                 }
-            },
-            Color::Black => {
-                new_position.black_king_moved = true;
-                match side {
-                    CastlingSide::Kingside => new_position.black_kingside_rook_moved = true,
-                    CastlingSide::Queenside => new_position.black_queenside_rook_moved = true,
+                continue;
+            }
+            let Some(promoted) = after.squares[to].get_piece_index() else {
+                continue;
+            };
+            for promotion in PROMOTION_PIECES {
+                after.pieces[promoted].piece_type = promotion;
+                if after.is_in_check(game) {
+                    moves.push(Move::encode(from, to, Some(promotion)));
                 }
-            },
+            }
         }
-
-        new_positions.push(new_position);
     }
+    moves
 }
 
 #[cfg(test)]
@@ -534,7 +695,7 @@ mod tests {
     fn test_can_castle_path_blocked() {
         let mut game = Game::new();
         // Place a piece on f1 to block the kingside castling path
-        game.position.white_occupancy |= 0x20;
+        game.position.white_occupancy |= FILE_F & RANK_1;
         assert!(!can_castle(&game.position, Color::White, CastlingSide::Kingside));
     }
 
@@ -545,6 +706,24 @@ mod tests {
         assert!(!can_castle(&game.position, Color::White, CastlingSide::Kingside));
     }
 
+    #[test]
+    fn test_castling_table() {
+        let white_kingside = Castling::standard(Color::White, CastlingSide::Kingside);
+        assert_eq!((white_kingside.king_from, white_kingside.king_to), (4, 6));  // e1-g1
+        assert_eq!((white_kingside.rook_from, white_kingside.rook_to), (7, 5));  // h1-f1
+        let black_queenside = Castling::standard(Color::Black, CastlingSide::Queenside);
+        assert_eq!((black_queenside.king_to, black_queenside.rook_from, black_queenside.rook_to), (58, 56, 59));
+        assert_eq!(black_queenside.right, CastlingRights::BLACKQUEENSIDE);
+
+        let paths: Vec<Bitboard> = STANDARD_CASTLING.iter().map(|castling| castling.path).collect();
+        assert_eq!(paths, [WHITE_KINGSIDE_PATH, WHITE_QUEENSIDE_PATH, BLACK_KINGSIDE_PATH, BLACK_QUEENSIDE_PATH]);
+        assert_eq!(white_kingside.king_path(), 0x70);  // e1, f1, g1
+
+        // Chess960: king on b1, rook on a1 castling queenside passes over c1 and d1
+        let chess960 = Castling::new(Color::White, CastlingSide::Queenside, 1, 0);
+        assert_eq!(chess960.path, 0b1100);
+    }
+
     #[test]
     fn test_add_castling_moves_kingside() {
         let mut game = Game::new();
@@ -570,4 +749,74 @@ mod tests {
         
         assert_eq!(new_positions.len(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_squares_between() {
+        assert_eq!(squares_between(4, 60), 0x0010_1010_1010_1000);  // e1 to e8
+        assert_eq!(squares_between(0, 63), squares_between(63, 0));
+        assert_eq!(squares_between(0, 27), (1 << 9) | (1 << 18));  // a1 to d4
+        assert_eq!(squares_between(0, 17), 0);  // a knight's move apart
+        assert_eq!(squares_between(0, 1), 0);
+    }
+
+    /// Checks both generators against every legal move, played out.
+    fn assert_matches_legal_moves(board: &crate::board::Board, game: &Game) {
+        let legal: Vec<u64> = board.legal_moves().iter().map(Move::to_u64).collect();
+        let checking: Vec<u64> = legal.iter().copied()
+            .filter(|&mov| {
+                let mut after = board.clone();
+                after.play(Move::from_u64(mov, board.position())).unwrap();
+                after.is_check()
+            })
+            .collect();
+        let fen = board.position().to_fen();
+        assert_eq!(generate_checks(board.position(), game), checking, "checks in {}", fen);
+        let evasions = if board.is_check() { legal } else { Vec::new() };
+        assert_eq!(generate_evasions(board.position(), game), evasions, "evasions in {}", fen);
+    }
+
+    #[test]
+    fn test_checks_and_evasions_match_legal_moves() {
+        let game = Game::new();
+        for fen in [
+            // Double check: only the king moves
+            "4r1k1/8/8/8/8/3n4/8/R3K3 w - - 0 1",
+            // A rook check to block or take, with a pinned bishop that may not
+            "4k3/4r3/8/8/8/8/2N1B3/b3K3 w - - 0 1",
+            "4k3/4r3/8/8/8/2B5/3N4/R3K3 w - - 0 1",
+            // The checking pawn taken en passant
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            // Every knight move uncovers the rook
+            "4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1",
+            // Promotions that check, and en passant captures uncovering a rook
+            "3k4/P7/8/8/8/8/8/4K3 w - - 0 1",
+            "8/8/8/r2pP1K1/8/8/8/3k4 w - d6 0 1",
+            "k7/8/8/K2pP2r/8/8/8/8 w - d6 0 1",
+            // Castling that checks with the rook, on both wings and in Chess960
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            "5k2/8/8/8/8/8/8/1R3KR1 w GB - 0 1",
+        ] {
+            let board = crate::board::Board::from_fen(fen).unwrap();
+            assert_matches_legal_moves(&board, &game);
+        }
+        let board = crate::board::Board::from_fen("5k2/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let castle = board.parse_move("e1g1").unwrap().to_u64();
+        assert!(generate_checks(board.position(), &game).contains(&castle));
+
+        // Positions from random games, with a fixed linear congruential generator
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..12 {
+            let mut board = crate::board::Board::new();
+            for _ in 0..40 {
+                assert_matches_legal_moves(&board, &game);
+                let moves = board.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                board.play(moves[(seed >> 33) as usize % moves.len()]).unwrap();
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use alloc::vec::Vec;
 use crate::position::Position;
 use crate::Game;
 use crate::utils::*;
 use crate::position::*;
 use crate::chess_move::*;
+use crate::attackmaps::AttackMaps;
 
 
 // Move scoring constants
@@ -15,6 +17,14 @@ const PIECE_VALUES: [i32; 6] = [
     900,   // Queen
     0,     // King (not used for captures)
 ];
+/// Quiet moves get this share of the moving piece's value for saving a
+/// threatened piece, and lose it for stepping onto a square an enemy pawn
+/// guards. Both stay far below `CAPTURE_SCORE_BASE`.
+const THREAT_DIVISOR: i32 = 10;
+/// Bonus for a move that gives check, putting quiet checks ahead of the
+/// other quiet moves but behind every capture. Double checks get twice it,
+/// since they force a king move.
+const CHECK_SCORE: i32 = 200;
 
 #[derive(Clone)]
 pub struct MoveOrderer {
@@ -31,10 +41,11 @@ impl MoveOrderer {
     // Score and sort moves based on various heuristics
     pub fn order_moves(&mut self, position: &Position, moves: &[u64], game: &Game) -> Vec<u64> {
         self.move_scores.clear();
+        let threats = position.attack_maps(game);
         
         // Score each move
         for &mov in moves {
-            let score = self.score_move(position, mov, game);
+            let score = self.score_move(position, mov, game, &threats);
             self.move_scores.push((mov, score));
         }
 
@@ -45,7 +56,7 @@ impl MoveOrderer {
         self.move_scores.iter().map(|(mov, _)| *mov).collect()
     }
 
-    fn score_move(&self, position: &Position, mov: u64, _game: &Game) -> i32 {
+    fn score_move(&self, position: &Position, mov: u64, game: &Game, threats: &AttackMaps) -> i32 {
         let mut score = 0;
         let from_square = mov & 0x3F;  // Extract from_square from bits 0-5
         let to_square = (mov >> 6) & 0x3F;  // Extract to_square from bits 6-11
@@ -55,18 +66,40 @@ impl MoveOrderer {
             let moving_piece = &position.pieces[piece_idx];
             
             // Score captures
+            let mut is_capture = false;
             if let Some(target_idx) = position.squares[to_square as usize].get_piece_index() {
                 let target_piece = &position.pieces[target_idx];
-                if target_piece.position != 0 && target_piece.color != moving_piece.color {
+                if target_piece.color != moving_piece.color {
                     // MVV-LVA scoring: Most Valuable Victim - Least Valuable Attacker
                     let victim_value = PIECE_VALUES[target_piece.piece_type as usize];
                     let attacker_value = PIECE_VALUES[moving_piece.piece_type as usize];
                     score += CAPTURE_SCORE_BASE + victim_value - (attacker_value / 100);
+                    is_capture = true;
+                }
+            }
+
+            // Among quiet moves, try saving threatened pieces first and
+            // walking into pawn attacks last
+            if !is_capture {
+                let enemy = if moving_piece.color == Color::White { Color::Black } else { Color::White };
+                let value = PIECE_VALUES[moving_piece.piece_type as usize] / THREAT_DIVISOR;
+                if threats.threatened(moving_piece.color) & (1u64 << from_square) != 0 {
+                    score += value;
+                }
+                if moving_piece.piece_type != PieceType::Pawn
+                    && threats.attacks_by(enemy, PieceType::Pawn) & (1u64 << to_square) != 0 {
+                    score -= value;
                 }
             }
             
-            // Score promotions
-            if (mov & (1 << 12)) != 0 {
+            score += match position.check_kind(game, mov) {
+                Some(CheckKind::Double) => 2 * CHECK_SCORE,
+                Some(_) => CHECK_SCORE,
+                None => 0,
+            };
+
+            // Score promotions; underpromotions are rarely best, so they get no bonus
+            if Move::promotion_of(mov) == Some(PieceType::Queen) {
                 score += 100000;  // Much higher than any capture
             }
         }
@@ -92,9 +125,6 @@ mod tests {
         
         // Print each piece's position and legal moves
         for (i, piece) in position.pieces.iter().enumerate() {
-            if piece.position == 0 {
-                continue;
-            }
             println!("Piece {}: {:?} {:?} at square {}, legal moves: {:?}", 
                 i, piece.color, piece.piece_type, 
                 bit_scan_safe(piece.position).unwrap_or(64),
@@ -133,7 +163,7 @@ mod tests {
         for mov in &ordered_moves {
             let from_sq = mov & 0x3F;
             let to_sq = (mov >> 6) & 0x3F;
-            let score = orderer.score_move(&position, *mov, &game);
+            let score = orderer.score_move(&position, *mov, &game, &position.attack_maps(&game));
             println!("Move from {} to {}, score: {}, is_capture: {}", 
                 from_sq, to_sq, score, position.is_capture(*mov));
         }
@@ -198,4 +228,23 @@ mod tests {
             assert!(position.is_promotion(first_move));
         }
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_threatened_pieces_move_first() {
+        let game = Game::new();
+        // The d6 pawn attacks the knight on e5
+        let position = Position::read_FEN("4k3/8/3p4/4N3/8/8/P7/4K3 w - - 0 1", &game);
+        let mut moves = Vec::new();
+        for (i, legal_moves_bitboard) in position.piece_legal_moves.iter().enumerate() {
+            let from_square = bit_scan(position.pieces[i].position) as u64;
+            for to_square in extract_bits(*legal_moves_bitboard) {
+                moves.push(from_square | ((to_square as u64) << 6));
+            }
+        }
+
+        let ordered_moves = MoveOrderer::new().order_moves(&position, &moves, &game);
+        let knight_moves = moves.iter().filter(|&&mov| mov & 0x3F == 36).count();
+        assert!(knight_moves > 0);
+        assert!(ordered_moves[..knight_moves].iter().all(|&mov| mov & 0x3F == 36));
+    }
+}
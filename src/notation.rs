@@ -0,0 +1,393 @@
+//! Standard algebraic notation (SAN).
+//!
+//! `to_san` writes a legal move the way it appears in printed games and PGN
+//! (`Nbd7`, `exd5`, `O-O`, `e8=Q+`, `Qh4#`), naming the origin square only
+//! as far as needed to tell it from another piece of the same kind, and
+//! `parse_san` reads it back, also accepting long algebraic notation
+//! (`g1f3`). `san_of` writes a packed move played in a `Position`, for the
+//! front ends built on the lower level API.
+//!
+//! `to_speech` and `describe_board` put moves and positions in words
+//! ("White knight to f3, check"), for screen readers and text-only views.
+
+use alloc::{format, string::String, vec::Vec};
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::Game;
+use crate::position::{Color, PieceType, Position};
+
+/// Parses a move in standard algebraic notation, also accepting long
+/// algebraic notation.
+///
+/// # Arguments
+///
+/// * `board` - The position the move is played in
+/// * `text` - The move, e.g. `Nbd7`, `exd5`, `O-O` or `e7e8q`; check and
+///   annotation symbols are ignored
+///
+/// # Returns
+///
+/// * The matching legal move, or a message explaining why it was rejected
+pub fn parse_san(board: &Board, text: &str) -> Result<Move, String> {
+    let san = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if san.len() >= 4 && san.len() <= 5 && san.as_bytes()[0].is_ascii_lowercase() {
+        if let Ok(mv) = board.parse_move(san) {
+            return Ok(mv);
+        }
+    }
+
+    let castle = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(kingside) = castle {
+        return board.legal_moves().into_iter()
+            .find(|mv| mv.is_castle() && (mv.to() > mv.from()) == kingside)
+            .ok_or_else(|| format!("Illegal move: {}", text));
+    }
+
+    let (piece_type, rest) = match san.chars().next().and_then(piece_of) {
+        Some(piece_type) => (piece_type, &san[1..]),
+        None => (PieceType::Pawn, san),
+    };
+    let (rest, promotion) = match rest.char_indices().last() {
+        Some((index, c)) if piece_type == PieceType::Pawn && c.is_ascii_uppercase() => {
+            let promotion = piece_of(c).ok_or_else(|| format!("Invalid promotion piece '{}'", c))?;
+            (rest[..index].trim_end_matches('='), Some(promotion))
+        }
+        _ => (rest, None),
+    };
+    let squares: Vec<u8> = rest.bytes().filter(|&b| b != b'x' && b != b'-').collect();
+    if squares.len() < 2 || !squares.is_ascii() {
+        return Err(format!("Unreadable move '{}'", text));
+    }
+    let (hint, target) = squares.split_at(squares.len() - 2);
+    let to = square_of(target[0], target[1]).ok_or_else(|| format!("Unreadable move '{}'", text))?;
+
+    let mut candidates = board.legal_moves().into_iter().filter(|mv| {
+        mv.to() == to
+            && mv.promotion() == promotion
+            && board.piece_at(mv.from()).map(|(_, p)| p) == Some(piece_type)
+            && hint.iter().all(|&c| match c {
+                b'a'..=b'h' => mv.from() % 8 == (c - b'a') as usize,
+                b'1'..=b'8' => mv.from() / 8 == (c - b'1') as usize,
+                _ => false,
+            })
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Ok(mv),
+        (Some(_), Some(_)) => Err(format!("Ambiguous move: {}", text)),
+        (None, _) => Err(format!("Illegal move: {}", text)),
+    }
+}
+
+/// Writes a legal move in standard algebraic notation, e.g. `Nbd7`, `exd5`,
+/// `O-O` or `e8=Q+`.
+///
+/// # Arguments
+///
+/// * `board` - The position the move is played in
+/// * `mv` - The move; it must be legal on `board`
+///
+/// # Returns
+///
+/// * The move in SAN, with `+` or `#` when it gives check or mate
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    let piece_type = board.piece_at(mv.from()).map_or(PieceType::Pawn, |(_, piece_type)| piece_type);
+    let file = |square: usize| (b'a' + (square % 8) as u8) as char;
+    let rank = |square: usize| (b'1' + (square / 8) as u8) as char;
+    if mv.is_castle() {
+        san.push_str(if mv.to() > mv.from() { "O-O" } else { "O-O-O" });
+    } else {
+        let capture = board.piece_at(mv.to()).is_some() || mv.is_en_passant();
+        if piece_type == PieceType::Pawn {
+            if capture {
+                san.push(file(mv.from()));
+            }
+        } else {
+            san.push(letter_of(piece_type));
+            // Name the origin's file, else its rank, else both, when another
+            // piece of the same kind could also go there
+            let rivals: Vec<usize> = board.legal_moves().into_iter()
+                .filter(|other| other.to() == mv.to() && other.from() != mv.from()
+                    && board.piece_at(other.from()).map(|(_, p)| p) == Some(piece_type))
+                .map(|other| other.from())
+                .collect();
+            if !rivals.is_empty() {
+                if rivals.iter().all(|&from| from % 8 != mv.from() % 8) {
+                    san.push(file(mv.from()));
+                } else if rivals.iter().all(|&from| from / 8 != mv.from() / 8) {
+                    san.push(rank(mv.from()));
+                } else {
+                    san.push(file(mv.from()));
+                    san.push(rank(mv.from()));
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push(file(mv.to()));
+        san.push(rank(mv.to()));
+        if let Some(promotion) = mv.promotion() {
+            san.push('=');
+            san.push(letter_of(promotion));
+        }
+    }
+
+    if let Some(mate) = check_of(board, mv) {
+        san.push(if mate { '#' } else { '+' });
+    }
+    san
+}
+
+/// Whether a move gives check, and if so whether it mates.
+fn check_of(board: &Board, mv: Move) -> Option<bool> {
+    if !board.gives_check(mv) {
+        return None;
+    }
+    // Only mate needs the move played, to see whether any reply is left
+    let mut after = board.clone();
+    after.play(mv).ok().map(|_| after.legal_moves().is_empty())
+}
+
+/// Puts a legal move in words, for screen readers and text-only views.
+///
+/// # Arguments
+///
+/// * `board` - The position the move is played in
+/// * `mv` - The move; it must be legal on `board`
+///
+/// # Returns
+///
+/// * A sentence such as "White knight to f3, check", "Black bishop takes
+///   knight on f3", "White castles kingside" or "White pawn to e8, promotes
+///   to queen, checkmate"
+pub fn to_speech(board: &Board, mv: Move) -> String {
+    let Some((color, piece_type)) = board.piece_at(mv.from()) else {
+        return format!("{} to {}", square_name(mv.from()), square_name(mv.to()));
+    };
+    let mut speech = if mv.is_castle() {
+        format!("{} castles {}", color_name(color), if mv.to() > mv.from() { "kingside" } else { "queenside" })
+    } else if mv.is_en_passant() {
+        format!("{} pawn takes {} en passant", color_name(color), square_name(mv.to()))
+    } else if let Some((_, captured)) = board.piece_at(mv.to()) {
+        format!("{} {} takes {} on {}", color_name(color), piece_name(piece_type), piece_name(captured), square_name(mv.to()))
+    } else {
+        format!("{} {} to {}", color_name(color), piece_name(piece_type), square_name(mv.to()))
+    };
+    if let Some(promotion) = mv.promotion() {
+        speech.push_str(", promotes to ");
+        speech.push_str(piece_name(promotion));
+    }
+    match check_of(board, mv) {
+        Some(true) => speech.push_str(", checkmate"),
+        Some(false) => speech.push_str(", check"),
+        None => {}
+    }
+    speech
+}
+
+/// Puts a position in words, for screen readers and text-only views.
+///
+/// # Arguments
+///
+/// * `board` - The position
+///
+/// # Returns
+///
+/// * Each side's pieces and their squares, most valuable first, and the
+///   side to move, e.g. "White: king e1, pawns d2 e4. Black: king e8.
+///   Black to move."
+pub fn describe_board(board: &Board) -> String {
+    const ORDER: [PieceType; 6] = [PieceType::King, PieceType::Queen, PieceType::Rook,
+        PieceType::Bishop, PieceType::Knight, PieceType::Pawn];
+    let mut text = String::new();
+    for color in [Color::White, Color::Black] {
+        let groups: Vec<String> = ORDER.iter().filter_map(|&piece_type| {
+            let squares: Vec<String> = (0..64)
+                .filter(|&square| board.piece_at(square) == Some((color, piece_type)))
+                .map(square_name)
+                .collect();
+            match squares.len() {
+                0 => None,
+                1 => Some(format!("{} {}", piece_name(piece_type), squares[0])),
+                _ => Some(format!("{}s {}", piece_name(piece_type), squares.join(" "))),
+            }
+        }).collect();
+        text.push_str(&format!("{}: {}. ", color_name(color), groups.join(", ")));
+    }
+    text.push_str(&format!("{} to move.", color_name(board.side_to_move())));
+    text
+}
+
+/// A square's coordinates, as in "e4".
+pub fn square_name(square: usize) -> String {
+    format!("{}{}", (b'a' + (square % 8) as u8) as char, square / 8 + 1)
+}
+
+/// A piece's name in lower case, for text read to the user.
+pub fn piece_name(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "king",
+        PieceType::Queen => "queen",
+        PieceType::Rook => "rook",
+        PieceType::Bishop => "bishop",
+        PieceType::Knight => "knight",
+        PieceType::Pawn => "pawn",
+    }
+}
+
+/// A side's name, capitalised to start a sentence.
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+/// The uppercase SAN letter of a piece.
+fn letter_of(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::King => 'K',
+        PieceType::Queen => 'Q',
+        PieceType::Rook => 'R',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Pawn => 'P',
+    }
+}
+
+/// The piece named by an uppercase SAN letter.
+fn piece_of(c: char) -> Option<PieceType> {
+    match c {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'R' => Some(PieceType::Rook),
+        'B' => Some(PieceType::Bishop),
+        'N' => Some(PieceType::Knight),
+        'P' => Some(PieceType::Pawn),
+        _ => None,
+    }
+}
+
+/// Square index of a file letter and rank digit.
+fn square_of(file: u8, rank: u8) -> Option<usize> {
+    if (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank) {
+        Some((rank - b'1') as usize * 8 + (file - b'a') as usize)
+    } else {
+        None
+    }
+}
+
+/// Writes a packed move in standard algebraic notation.
+///
+/// # Arguments
+///
+/// * `position` - The position the move is played in
+/// * `game` - The game holding the attack tables
+/// * `mov` - The packed move; it must be legal in `position`
+///
+/// # Returns
+///
+/// * The move in SAN, as `to_san` writes it
+pub fn san_of(position: &Position, game: &Game, mov: u64) -> String {
+    let board = Board::with_tables(position.clone(), game);
+    to_san(&board, Move::from_u64(mov, position))
+}
+
+/// Puts a packed move in words.
+///
+/// # Arguments
+///
+/// * `position` - The position the move is played in
+/// * `game` - The game holding the attack tables
+/// * `mov` - The packed move; it must be legal in `position`
+///
+/// # Returns
+///
+/// * The move as `to_speech` says it
+pub fn speech_of(position: &Position, game: &Game, mov: u64) -> String {
+    let board = Board::with_tables(position.clone(), game);
+    to_speech(&board, Move::from_u64(mov, position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_parse_san() {
+        let board = Board::from_fen("4k3/1P6/8/3p4/2N1P3/8/4K3/R6R w - - 0 1").unwrap();
+        let san = |text: &str| parse_san(&board, text).map(|mv| mv.to_string());
+        assert_eq!(san("exd5").unwrap(), "e4d5");
+        assert_eq!(san("Nxd5"), Err("Illegal move: Nxd5".to_string()));
+        assert_eq!(san("Ne3+").unwrap(), "c4e3");
+        assert_eq!(san("b8=N").unwrap(), "b7b8n");
+        assert_eq!(san("b8Q").unwrap(), "b7b8q");
+        assert_eq!(san("Rad1").unwrap(), "a1d1");
+        assert_eq!(san("Rd1"), Err("Ambiguous move: Rd1".to_string()));
+        assert_eq!(san("c4e5").unwrap(), "c4e5");
+        assert!(san("Zz9").is_err());
+    }
+
+    #[test]
+    fn test_to_san() {
+        let board = Board::from_fen("4k3/1P6/8/3p4/2N1P3/8/4K3/R6R w - - 0 1").unwrap();
+        let san = |text: &str| to_san(&board, board.parse_move(text).unwrap());
+        assert_eq!(san("e4d5"), "exd5");
+        assert_eq!(san("c4e3"), "Ne3");
+        assert_eq!(san("b7b8n"), "b8=N");
+        assert_eq!(san("b7b8q"), "b8=Q+");
+        assert_eq!(san("a1d1"), "Rad1");
+        assert_eq!(san("h1h8"), "Rh8+");
+
+        let mate = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(to_san(&mate, mate.parse_move("d8h4").unwrap()), "Qh4#");
+
+        // Every legal move reads back as itself
+        for fen in [START_FEN, "4k3/1P6/8/3p4/2N1P3/8/4K3/R6R w - - 0 1", "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 12"] {
+            let board = Board::from_fen(fen).unwrap();
+            for mv in board.legal_moves() {
+                assert_eq!(parse_san(&board, &to_san(&board, mv)), Ok(mv), "{} in {}", mv, fen);
+            }
+        }
+    }
+    #[test]
+    fn test_to_speech() {
+        let board = Board::from_fen("4k3/1P6/8/3p4/2N1P3/8/4K3/R6R w - - 0 1").unwrap();
+        let speech = |text: &str| to_speech(&board, board.parse_move(text).unwrap());
+        assert_eq!(speech("e4d5"), "White pawn takes pawn on d5");
+        assert_eq!(speech("c4e3"), "White knight to e3");
+        assert_eq!(speech("b7b8q"), "White pawn to b8, promotes to queen, check");
+        assert_eq!(speech("h1h8"), "White rook to h8, check");
+
+        let mate = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(to_speech(&mate, mate.parse_move("d8h4").unwrap()), "Black queen to h4, checkmate");
+
+        let game = Game::new();
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", &game).unwrap();
+        assert_eq!(speech_of(&position, &game, Move::encode(60, 62, None)), "Black castles kingside");
+        assert_eq!(speech_of(&position, &game, Move::encode(63, 7, None)), "Black rook takes rook on h1, check");
+    }
+
+    #[test]
+    fn test_describe_board() {
+        let board = Board::from_fen("4k3/8/8/8/4P3/8/3P4/R3K1N1 b - - 0 1").unwrap();
+        assert_eq!(describe_board(&board),
+            "White: king e1, rook a1, knight g1, pawns d2 e4. Black: king e8. Black to move.");
+    }
+
+    #[test]
+    fn test_san_of() {
+        let game = Game::new();
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", &game).unwrap();
+        assert_eq!(san_of(&position, &game, Move::encode(60, 62, None)), "O-O");
+        assert_eq!(san_of(&position, &game, Move::encode(63, 7, None)), "Rxh1+");
+        assert_eq!(san_of(&position, &game, Move::encode(56, 0, None)), "Rxa1+");
+    }
+}
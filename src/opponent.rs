@@ -0,0 +1,148 @@
+//! What the engine remembers about its current opponent.
+//!
+//! Within a session the engine notes which opening moves it played against
+//! the opponent and how those games ended. When choosing a book move it then
+//! avoids lines it lost in this session, which the opponent may know a trap
+//! in, and prefers the moves it has played least often, so a rematch does
+//! not repeat the previous game move for move.
+//!
+//! Ties between equally fresh moves are broken at random. A model created
+//! with `with_seed` always makes the same choices, for tests.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::prelude::*;
+
+use crate::book::{BookMove, GameOutcome, OpeningBook, BOOK_PLIES};
+use crate::position::Position;
+
+/// The engine's opening history against one opponent.
+#[derive(Debug, Clone)]
+pub struct OpponentModel {
+    name: String,
+    /// Engine moves played from each opening position, with the game's result
+    played: HashMap<u64, Vec<(u64, GameOutcome)>>,
+    games: u32,
+    rng: StdRng,
+}
+
+impl OpponentModel {
+    /// Starts a session against `name`, with random tie-breaks.
+    pub fn new(name: &str) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        OpponentModel::with_seed(name, seed)
+    }
+
+    /// Starts a session whose tie-breaks are fixed by `seed`.
+    pub fn with_seed(name: &str, seed: u64) -> Self {
+        OpponentModel {
+            name: name.to_string(),
+            played: HashMap::new(),
+            games: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Games recorded against the opponent this session.
+    pub fn games(&self) -> u32 {
+        self.games
+    }
+
+    /// Remembers the engine's opening moves from a finished game.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine_moves` - `(position hash, move)` for each move the engine
+    ///   played, in game order; only the opening is kept, as in the book
+    /// * `outcome` - The result from the engine's point of view
+    pub fn record_game(&mut self, engine_moves: &[(u64, u64)], outcome: GameOutcome) {
+        for &(hash, mov) in engine_moves.iter().take(BOOK_PLIES / 2) {
+            self.played.entry(hash).or_default().push((mov, outcome));
+        }
+        self.games += 1;
+    }
+
+    /// How often the engine played `mov` from the position this session.
+    pub fn times_played(&self, hash: u64, mov: u64) -> usize {
+        self.played.get(&hash).map_or(0, |moves| moves.iter().filter(|&&(m, _)| m == mov).count())
+    }
+
+    /// Whether a game this session was lost after playing `mov` from the position.
+    pub fn lost_with(&self, hash: u64, mov: u64) -> bool {
+        self.played.get(&hash).is_some_and(|moves| moves.contains(&(mov, GameOutcome::Loss)))
+    }
+
+    /// Picks a book move for a position with the opponent in mind.
+    ///
+    /// Moves that lost this session are skipped while the book has others.
+    /// Of the rest, those played least often against the opponent are
+    /// preferred, then the highest weights; ties are broken at random.
+    ///
+    /// # Arguments
+    ///
+    /// * `book` - The opening book
+    /// * `position` - The position
+    /// * `hash` - Zobrist hash of the position
+    ///
+    /// # Returns
+    ///
+    /// * The packed move, or `None` if the book has no playable move
+    pub fn choose_book_move(&mut self, book: &dyn OpeningBook, position: &Position, hash: u64) -> Option<u64> {
+        let moves = book.moves(position, hash);
+        let playable: Vec<_> = moves.iter().filter(|m| m.weight > 0).collect();
+        let safe: Vec<_> = playable.iter().copied().filter(|m| !self.lost_with(hash, m.mov)).collect();
+        let candidates = if safe.is_empty() { playable } else { safe };
+
+        let rank = |m: &&BookMove| (self.times_played(hash, m.mov), -m.weight);
+        let best = candidates.iter().map(rank).min()?;
+        let freshest: Vec<u64> = candidates.iter().filter(|m| rank(m) == best).map(|m| m.mov).collect();
+        freshest.choose(&mut self.rng).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::book::LearningBook;
+    use crate::chess_move::Move;
+
+    #[test]
+    fn test_rematches_vary_and_avoid_lost_lines() {
+        let start = 1;
+        let position = Board::new().position().clone();
+        let (e4, d4, c4) = (Move::encode(12, 28, None), Move::encode(11, 27, None), Move::encode(10, 26, None));
+        let mut book = LearningBook::new();
+        for mov in [e4, d4, c4] {
+            book.learn(&[(start, mov)], GameOutcome::Win);
+        }
+        book.learn(&[(start, e4)], GameOutcome::Win);  // e4 has the highest weight
+
+        let mut model = OpponentModel::with_seed("tester", 7);
+        assert_eq!(model.choose_book_move(&book, &position, start), Some(e4));
+        model.record_game(&[(start, e4)], GameOutcome::Loss);
+        assert!(model.lost_with(start, e4));
+
+        // The lost line is avoided and the remaining moves take turns
+        let second = model.choose_book_move(&book, &position, start).unwrap();
+        assert!(second == d4 || second == c4);
+        model.record_game(&[(start, second)], GameOutcome::Draw);
+        let third = model.choose_book_move(&book, &position, start).unwrap();
+        assert!(third != e4 && third != second);
+        model.record_game(&[(start, third)], GameOutcome::Draw);
+        assert_eq!(model.games(), 3);
+
+        // The same seed makes the same choices
+        let mut again = OpponentModel::with_seed("tester", 7);
+        again.choose_book_move(&book, &position, start);
+        again.record_game(&[(start, e4)], GameOutcome::Loss);
+        assert_eq!(again.choose_book_move(&book, &position, start), Some(second));
+
+        assert_eq!(model.choose_book_move(&book, &position, 2), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! Engine options.
+//!
+//! The settings a user may change about the engine itself, rather than
+//! about one search, are registered once in `OPTIONS` with their type,
+//! default and range, and every front end sets them through it: UCI
+//! `setoption` lists and checks them, the GUI draws its "Engine options"
+//! panel from them, and both start from the options file. The file is
+//! TOML with the option names as keys:
+//!
+//! ```toml
+//! # options.toml
+//! Hash = 64
+//! MoveOverhead = 100
+//! SkillLevel = 12
+//! OwnBook = true
+//! ```
+//!
+//! Options left out keep their defaults. The file is the one named by
+//! `RUSTCHESS_OPTIONS`, or the first `options.toml` found among the asset
+//! paths (see `assets`).
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::assets::Asset;
+use crate::search::{DEFAULT_HASH_MB, MAX_SKILL_LEVEL};
+use crate::time_manager;
+
+/// Environment variable naming the options file.
+pub const CONFIG_ENV: &str = "RUSTCHESS_OPTIONS";
+/// File looked for when `CONFIG_ENV` is not set.
+pub const DEFAULT_CONFIG_FILE: &str = "options.toml";
+/// Largest hash table, in megabytes.
+pub const MAX_HASH_MB: usize = 4096;
+/// Most search threads; the search runs on one.
+pub const MAX_THREADS: usize = 1;
+/// Longest move overhead, in milliseconds.
+pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+/// The type of an option, with its default and range, as UCI describes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    /// A whole number from `min` to `max`
+    Spin { default: i64, min: i64, max: i64 },
+    /// On or off
+    Check { default: bool },
+}
+
+/// The value of an option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Spin(value) => write!(f, "{}", value),
+            OptionValue::Check(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A registered option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineOption {
+    /// The UCI name, also the key in the options file
+    pub name: &'static str,
+    pub kind: OptionKind,
+    /// What the option does, for settings panels
+    pub help: &'static str,
+}
+
+pub const HASH: EngineOption = EngineOption {
+    name: "Hash",
+    kind: OptionKind::Spin { default: DEFAULT_HASH_MB as i64, min: 1, max: MAX_HASH_MB as i64 },
+    help: "Size of the hash table in megabytes; changing it empties the table",
+};
+pub const THREADS: EngineOption = EngineOption {
+    name: "Threads",
+    kind: OptionKind::Spin { default: 1, min: 1, max: MAX_THREADS as i64 },
+    help: "Search threads",
+};
+pub const MOVE_OVERHEAD: EngineOption = EngineOption {
+    name: "MoveOverhead",
+    kind: OptionKind::Spin { default: time_manager::MOVE_OVERHEAD.as_millis() as i64, min: 0, max: MAX_MOVE_OVERHEAD_MS as i64 },
+    help: "Milliseconds kept back on every move for the GUI or the network to pass the move on",
+};
+pub const SKILL_LEVEL: EngineOption = EngineOption {
+    name: "SkillLevel",
+    kind: OptionKind::Spin { default: MAX_SKILL_LEVEL as i64, min: 0, max: MAX_SKILL_LEVEL as i64 },
+    help: "Playing strength; below the top level the engine searches shallower and picks less carefully",
+};
+pub const OWN_BOOK: EngineOption = EngineOption {
+    name: "OwnBook",
+    kind: OptionKind::Check { default: false },
+    help: "Play the opening book's moves, when one is found, without searching",
+};
+
+/// Every option, in the order front ends list them.
+pub const OPTIONS: [EngineOption; 5] = [HASH, THREADS, MOVE_OVERHEAD, SKILL_LEVEL, OWN_BOOK];
+
+impl EngineOption {
+    /// Looks up an option by name, ignoring case as UCI does.
+    pub fn find(name: &str) -> Option<EngineOption> {
+        OPTIONS.iter().copied().find(|option| option.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn default_value(&self) -> OptionValue {
+        match self.kind {
+            OptionKind::Spin { default, .. } => OptionValue::Spin(default),
+            OptionKind::Check { default } => OptionValue::Check(default),
+        }
+    }
+
+    /// The option as the `uci` command announces it.
+    pub fn uci_line(&self) -> String {
+        match self.kind {
+            OptionKind::Spin { default, min, max } =>
+                format!("option name {} type spin default {} min {} max {}", self.name, default, min, max),
+            OptionKind::Check { default } => format!("option name {} type check default {}", self.name, default),
+        }
+    }
+
+    /// Reads a value of the option.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - A number for a spin option, `true` or `false` for a check
+    ///
+    /// # Returns
+    ///
+    /// * The value, or an error naming the values the option takes
+    pub fn parse(&self, text: &str) -> Result<OptionValue, String> {
+        self.check(match self.kind {
+            OptionKind::Spin { .. } => text.trim().parse().map(OptionValue::Spin).ok(),
+            OptionKind::Check { .. } => text.trim().to_ascii_lowercase().parse().map(OptionValue::Check).ok(),
+        })
+    }
+
+    /// Checks that a value has the option's type and is in its range.
+    fn check(&self, value: Option<OptionValue>) -> Result<OptionValue, String> {
+        match (self.kind, value) {
+            (OptionKind::Spin { min, max, .. }, Some(OptionValue::Spin(n))) if (min..=max).contains(&n) => Ok(OptionValue::Spin(n)),
+            (OptionKind::Spin { min, max, .. }, _) => Err(format!("{} expects {} to {}", self.name, min, max)),
+            (OptionKind::Check { .. }, Some(OptionValue::Check(on))) => Ok(OptionValue::Check(on)),
+            (OptionKind::Check { .. }, _) => Err(format!("{} expects true or false", self.name)),
+        }
+    }
+}
+
+/// The value of every option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineOptions {
+    pub hash_mb: usize,
+    /// Accepted for GUIs that set it; the search runs on one thread
+    pub threads: usize,
+    pub move_overhead: Duration,
+    /// 0 to `MAX_SKILL_LEVEL`, which is full strength
+    pub skill_level: u32,
+    pub own_book: bool,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            hash_mb: DEFAULT_HASH_MB,
+            threads: 1,
+            move_overhead: time_manager::MOVE_OVERHEAD,
+            skill_level: MAX_SKILL_LEVEL,
+            own_book: false,
+        }
+    }
+}
+
+impl EngineOptions {
+    /// The value of a registered option.
+    pub fn get(&self, option: &EngineOption) -> OptionValue {
+        match option.name {
+            "Hash" => OptionValue::Spin(self.hash_mb as i64),
+            "Threads" => OptionValue::Spin(self.threads as i64),
+            "MoveOverhead" => OptionValue::Spin(self.move_overhead.as_millis() as i64),
+            "SkillLevel" => OptionValue::Spin(self.skill_level as i64),
+            "OwnBook" => OptionValue::Check(self.own_book),
+            _ => option.default_value(),
+        }
+    }
+
+    /// Changes a registered option.
+    ///
+    /// # Returns
+    ///
+    /// * An error, and no change, if the value is of the wrong type or out
+    ///   of range
+    pub fn set_value(&mut self, option: &EngineOption, value: OptionValue) -> Result<(), String> {
+        match (option.name, option.check(Some(value))?) {
+            ("Hash", OptionValue::Spin(n)) => self.hash_mb = n as usize,
+            ("Threads", OptionValue::Spin(n)) => self.threads = n as usize,
+            ("MoveOverhead", OptionValue::Spin(n)) => self.move_overhead = Duration::from_millis(n as u64),
+            ("SkillLevel", OptionValue::Spin(n)) => self.skill_level = n as u32,
+            ("OwnBook", OptionValue::Check(on)) => self.own_book = on,
+            _ => return Err(format!("Unknown option {}", option.name)),
+        }
+        Ok(())
+    }
+
+    /// Changes an option given by name, as `setoption` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The option's name, in any case
+    /// * `value` - The new value as text
+    ///
+    /// # Returns
+    ///
+    /// * The option changed, or an error if there is no such option or the
+    ///   value does not fit it
+    pub fn set(&mut self, name: &str, value: &str) -> Result<EngineOption, String> {
+        let option = EngineOption::find(name).ok_or_else(|| format!("Unknown option {}", name))?;
+        self.set_value(&option, option.parse(value)?)?;
+        Ok(option)
+    }
+
+    /// Parses an options file, starting from the defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - `Name = value` lines; unknown names are rejected so that
+    ///   typos do not go unnoticed
+    ///
+    /// # Returns
+    ///
+    /// * The options, or an error naming the offending option
+    pub fn from_toml(text: &str) -> Result<EngineOptions, String> {
+        let table: toml::Table = text.parse().map_err(|e| format!("Invalid options file: {}", e))?;
+        let mut options = EngineOptions::default();
+        for (key, value) in &table {
+            let option = EngineOption::find(key).ok_or_else(|| format!("Options file: unknown option '{}'", key))?;
+            let value = match option.kind {
+                OptionKind::Spin { .. } => value.as_integer().map(OptionValue::Spin),
+                OptionKind::Check { .. } => value.as_bool().map(OptionValue::Check),
+            };
+            options.set_value(&option, option.check(value).map_err(|e| format!("Options file: {}", e))?)?;
+        }
+        Ok(options)
+    }
+
+    /// The options as TOML, one `Name = value` line each.
+    pub fn to_toml(&self) -> String {
+        OPTIONS.iter().map(|option| format!("{} = {}\n", option.name, self.get(option))).collect()
+    }
+
+    /// Reads options from a TOML file.
+    pub fn load(path: &Path) -> Result<EngineOptions, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        EngineOptions::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the options to a TOML file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// The options of the options file, or the defaults if there is none.
+    /// An unreadable file is reported on stderr and ignored.
+    pub fn load_config() -> EngineOptions {
+        match Asset::EngineOptions.locate().map(|path| EngineOptions::load(&path)) {
+            Some(Ok(options)) => options,
+            Some(Err(e)) => {
+                eprintln!("Using default engine options: {}", e);
+                EngineOptions::default()
+            }
+            None => EngineOptions::default(),
+        }
+    }
+}
+
+/// Where to save the options: the options file in use, or a new one in the
+/// working directory.
+pub fn config_path() -> PathBuf {
+    Asset::EngineOptions.locate().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_are_checked_against_the_registry() {
+        assert_eq!(HASH.uci_line(), format!("option name Hash type spin default {} min 1 max 4096", DEFAULT_HASH_MB));
+        assert_eq!(OWN_BOOK.uci_line(), "option name OwnBook type check default false");
+        for option in OPTIONS {
+            assert_eq!(EngineOptions::default().get(&option), option.default_value(), "{}", option.name);
+        }
+
+        let mut options = EngineOptions::default();
+        assert_eq!(options.set("hash", "64").unwrap(), HASH);
+        assert_eq!(options.hash_mb, 64);
+        options.set("MoveOverhead", "250").unwrap();
+        assert_eq!(options.move_overhead, Duration::from_millis(250));
+        options.set("OwnBook", "TRUE").unwrap();
+        assert!(options.own_book);
+
+        assert_eq!(options.set("Hash", "0").unwrap_err(), "Hash expects 1 to 4096");
+        assert_eq!(options.set("SkillLevel", "strong").unwrap_err(), "SkillLevel expects 0 to 20");
+        assert_eq!(options.set("OwnBook", "1").unwrap_err(), "OwnBook expects true or false");
+        assert_eq!(options.set("Contempt", "10").unwrap_err(), "Unknown option Contempt");
+        assert_eq!(options.hash_mb, 64);
+    }
+
+    #[test]
+    fn test_options_round_trip_through_toml() {
+        assert_eq!(EngineOptions::from_toml("").unwrap(), EngineOptions::default());
+
+        let options = EngineOptions::from_toml("# weaker\nSkillLevel = 5\nOwnBook = true\n").unwrap();
+        assert_eq!(options.skill_level, 5);
+        assert!(options.own_book);
+        assert_eq!(options.hash_mb, DEFAULT_HASH_MB);
+        assert_eq!(EngineOptions::from_toml(&options.to_toml()).unwrap(), options);
+
+        assert_eq!(EngineOptions::from_toml("Hsh = 1").unwrap_err(), "Options file: unknown option 'Hsh'");
+        assert_eq!(EngineOptions::from_toml("Threads = 8").unwrap_err(), "Options file: Threads expects 1 to 1");
+        assert!(EngineOptions::from_toml("OwnBook = \"yes\"").is_err());
+    }
+}
@@ -4,6 +4,7 @@
 //! It pre-computes all possible pawn moves and attacks from each square for both
 //! white and black pawns, including forward moves and diagonal captures.
 
+use alloc::vec::Vec;
 use crate::utils::*;
 use crate::position::Color;
 
@@ -1,26 +1,312 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::{format, vec, vec::Vec};
+use crate::board::Board;
+use crate::chess_move::Move;
 use crate::position::Position;
-    
+use crate::Game;
+
+/// Leaf counts below each root move, keyed by the move in long algebraic
+/// notation (`e2e4`, `e7e8q`).
+pub type Divide = BTreeMap<String, u64>;
+
+/// Divide counts as engines print them for `go perft`: a `move: count`
+/// line per root move, an empty line and the total, so the output can be
+/// compared line by line with a reference engine's.
+pub fn divide_lines(divide: &Divide) -> Vec<String> {
+    let mut lines: Vec<String> = divide.iter().map(|(mv, nodes)| format!("{}: {}", mv, nodes)).collect();
+    lines.push(String::new());
+    lines.push(format!("Nodes searched: {}", divide.values().sum::<u64>()));
+    lines
+}
+
+/// The first position where this engine's move generation disagrees with a
+/// reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Moves from the root to the position
+    pub line: Vec<String>,
+    /// Moves the reference generates there but this engine does not
+    pub missing: Vec<String>,
+    /// Moves this engine generates there but the reference does not
+    pub extra: Vec<String>,
+}
+
+/// A cached subtree count.
+#[derive(Debug, Clone, Copy, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: i32,
+    nodes: u64,
+}
+
+/// Counts the leaf nodes of the legal move tree to a fixed depth, for
+/// checking move generation against published counts.
 pub struct Perft {
+    game: Game,
     nodes: u64,
     captures: u64,
     en_passants: u64,
     castles: u64,
     promotions: u64,
+    /// Subtree counts keyed by position and depth; empty when hashing is off
+    table: Vec<PerftEntry>,
+    hits: u64,
 }
 
 impl Perft {
     pub fn new() -> Self {
         Perft {
+            game: Game::new(),
             nodes: 0,
             captures: 0,
             en_passants: 0,
             castles: 0,
             promotions: 0,
+            table: Vec::new(),
+            hits: 0,
         }
     }
 
+    /// Creates a perft counter that caches subtree counts, so positions
+    /// reached by transposition are only counted once.
+    ///
+    /// # Arguments
+    ///
+    /// * `size_mb` - Size of the cache in megabytes; 0 turns hashing off
+    pub fn with_hash(size_mb: usize) -> Self {
+        let entries = size_mb * 1024 * 1024 / core::mem::size_of::<PerftEntry>();
+        Perft { table: vec![PerftEntry::default(); entries], ..Perft::new() }
+    }
+
+    /// Counts the positions reached after exactly `depth` legal moves.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The root position
+    /// * `depth` - Plies to play; 0 counts the root itself
+    ///
+    /// # Returns
+    ///
+    /// * The number of leaf nodes
     pub fn run(&mut self, position: &Position, depth: i32) -> u64 {
-        // Performance test implementation
+        self.hits = 0;
+        self.nodes = self.count(position, depth);
         self.nodes
     }
+
+    /// Counts the leaf nodes below each legal move of the root.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The root position
+    /// * `depth` - Plies to play, counting the root move; at least 1
+    ///
+    /// # Returns
+    ///
+    /// * The leaf count of every root move
+    pub fn divide(&mut self, position: &Position, depth: i32) -> Divide {
+        self.hits = 0;
+        let mut position = position.clone();
+        position.update_all_legal_moves(&self.game);
+        let mut divide = Divide::new();
+        for mov in position.get_all_legal_moves(&self.game) {
+            let mut child = position.clone();
+            child.make_move(mov);
+            let nodes = self.count(&child, depth - 1);
+            divide.insert(Move::from_u64(mov, &position).to_string(), nodes);
+        }
+        self.nodes = divide.values().sum();
+        divide
+    }
+
+    /// Compares divide counts with a reference, descending into the first
+    /// root move whose count differs until the position is found where the
+    /// two generate different moves.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The root position
+    /// * `depth` - Plies to count from the root
+    /// * `reference` - Divide counts of the reference, given the moves from
+    ///   the root and the depth left
+    /// * `trace` - Called with the line, this engine's and the reference's
+    ///   counts at every position compared
+    ///
+    /// # Returns
+    ///
+    /// * Where the move generation diverges, `None` if the counts agree, or
+    ///   the reference's error
+    pub fn find_divergence<R, T>(&mut self, board: &Board, depth: i32, mut reference: R, mut trace: T) -> Result<Option<Divergence>, String>
+    where
+        R: FnMut(&[String], i32) -> Result<Divide, String>,
+        T: FnMut(&[String], &Divide, &Divide),
+    {
+        let mut board = board.clone();
+        let mut line = Vec::new();
+        for depth in (1..=depth).rev() {
+            let ours = self.divide(board.position(), depth);
+            let theirs = reference(&line, depth)?;
+            trace(&line, &ours, &theirs);
+
+            let missing: Vec<String> = theirs.keys().filter(|mv| !ours.contains_key(*mv)).cloned().collect();
+            let extra: Vec<String> = ours.keys().filter(|mv| !theirs.contains_key(*mv)).cloned().collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                return Ok(Some(Divergence { line, missing, extra }));
+            }
+            let Some(mv) = ours.iter().find(|&(mv, nodes)| theirs[mv] != *nodes).map(|(mv, _)| mv.clone()) else {
+                return Ok(None);
+            };
+            board.play(board.parse_move(&mv)?)?;
+            line.push(mv);
+        }
+        Err(format!("The reference counts more than one leaf for a move after {}", line.join(" ")))
+    }
+
+    /// Subtree counts served from the cache during the last `run`.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    fn count(&mut self, position: &Position, depth: i32) -> u64 {
+        if depth <= 0 {
+            return 1;
+        }
+
+        let key = position.legal_moves_key(&self.game);
+        let slot = self.slot(key, depth);
+        if let Some(slot) = slot {
+            let entry = self.table[slot];
+            if entry.key == key && entry.depth == depth {
+                self.hits += 1;
+                return entry.nodes;
+            }
+        }
+
+        let mut position = position.clone();
+        position.update_all_legal_moves(&self.game);
+        let moves = position.get_all_legal_moves(&self.game);
+        let nodes = if depth == 1 {
+            // Leaves need not be played out
+            moves.len() as u64
+        } else {
+            let mut nodes = 0;
+            for mov in moves {
+                let mut child = position.clone();
+                child.make_move(mov);
+                nodes += self.count(&child, depth - 1);
+            }
+            nodes
+        };
+
+        if let Some(slot) = slot {
+            self.table[slot] = PerftEntry { key, depth, nodes };
+        }
+        nodes
+    }
+
+    /// The table slot for a position at a depth, if hashing is on.
+    fn slot(&self, key: u64, depth: i32) -> Option<usize> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let mixed = key ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        Some((mixed % self.table.len() as u64) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft_from_the_start() {
+        let game = Game::new();
+        let mut perft = Perft::new();
+        let counts: alloc::vec::Vec<u64> = (0..4).map(|depth| perft.run(&game.position, depth)).collect();
+        assert_eq!(counts, [1, 20, 400, 8902]);
+    }
+
+    #[test]
+    fn test_perft_with_castling() {
+        let game = Game::new();
+        let kiwipete = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &game).unwrap();
+        assert_eq!(Perft::new().run(&kiwipete, 2), 2039);
+        // Chess960, with castling rights on both wings for both sides
+        let chess960 = Position::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &game).unwrap();
+        assert_eq!(Perft::new().run(&chess960, 3), 12189);
+        // A king may take on the square its rook castled from once the rook
+        // is gone; that is a capture, not castling
+        let expected = [
+            ("rk2r3/8/8/8/8/8/8/RK2R3 w AEae - 0 1", 3, 9899),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 4, 667_366),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 4, 1_171_749),
+        ];
+        for (fen, depth, nodes) in expected {
+            let position = Position::from_fen(fen, &game).unwrap();
+            assert_eq!(Perft::with_hash(16).run(&position, depth), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_hashed_perft_matches() {
+        let game = Game::new();
+        let mut hashed = Perft::with_hash(1);
+        assert_eq!(hashed.run(&game.position, 4), 197_281);
+        // 1. e3 a6 2. d3 and 1. d3 a6 2. e3 reach the same position
+        assert!(hashed.hits() > 0);
+        assert_eq!(hashed.run(&game.position, 4), 197_281);
+
+        let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &game).unwrap();
+        assert_eq!(Perft::with_hash(1).run(&position, 2), Perft::new().run(&position, 2));
+    }
+
+    #[test]
+    fn test_divide_lines() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let lines = divide_lines(&Perft::new().divide(board.position(), 2));
+        assert_eq!(lines.len(), 14 + 2);
+        assert_eq!(lines[0], "e1d1: 5");
+        assert!(lines.contains(&"h1h8: 3".to_string()));
+        assert_eq!(lines[lines.len() - 2..], ["", "Nodes searched: 63"]);
+    }
+
+    #[test]
+    fn test_divergence_is_pinpointed() {
+        let board = Board::new();
+        let divide = Perft::new().divide(board.position(), 3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide["e2e4"], 600);
+        assert_eq!(divide.values().sum::<u64>(), 8902);
+
+        // A reference that never plays ...Qh4 after 1. e4 e5 2. Qh5
+        let mut reference = |line: &[String], depth: i32| {
+            let mut board = Board::new();
+            for mv in line {
+                board.play(board.parse_move(mv).unwrap()).unwrap();
+            }
+            let mut divide = Perft::new().divide(board.position(), depth);
+            if line.is_empty() && depth == 4 {
+                *divide.get_mut("e2e4").unwrap() -= 1;
+            } else if line == ["e2e4"] {
+                *divide.get_mut("e7e5").unwrap() -= 1;
+            } else if line == ["e2e4", "e7e5"] {
+                *divide.get_mut("d1h5").unwrap() -= 1;
+            } else if line == ["e2e4", "e7e5", "d1h5"] {
+                divide.remove("d8h4");
+            }
+            Ok(divide)
+        };
+        let mut levels = 0;
+        let divergence = Perft::with_hash(1).find_divergence(&board, 4, &mut reference, |_, _, _| levels += 1).unwrap();
+        assert_eq!(divergence, Some(Divergence {
+            line: vec!["e2e4".to_string(), "e7e5".to_string(), "d1h5".to_string()],
+            missing: vec![],
+            extra: vec!["d8h4".to_string()],
+        }));
+        assert_eq!(levels, 4);
+
+        let agree = |_: &[String], depth| Ok(Perft::new().divide(board.position(), depth));
+        assert_eq!(Perft::new().find_divergence(&board, 2, agree, |_, _, _| {}), Ok(None));
+    }
 }
@@ -0,0 +1,736 @@
+//! Reading games and positions from text.
+//!
+//! `import` accepts whatever a user is likely to paste: a FEN string, a board
+//! diagram (see `diagram`), a PGN game (tags, comments, variations and
+//! annotations are tolerated) or a bare list of moves from the starting
+//! position, and works out which it is.
+//! Moves may be in standard algebraic notation (`Nf3`, `exd5`, `e8=Q+`) or
+//! long algebraic notation (`g1f3`), read with `notation::parse_san`;
+//! `to_san` writes the former.
+//! `import_tree` keeps the variations, comments and move annotations (NAGs)
+//! as well, reading one or more games into a `MoveTree`, which
+//! `MoveTree::to_movetext` writes back.
+
+use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+use crate::board::{Board, START_FEN};
+use crate::chess_move::Move;
+use crate::diagram;
+use crate::position::Color;
+pub use crate::notation::{parse_san, to_san};
+
+/// What kind of text was imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Fen,
+    Diagram,
+    Pgn,
+    MoveList,
+}
+
+/// A game read from text: a start position and the moves played from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedGame {
+    pub format: ImportFormat,
+    pub start_fen: String,
+    pub moves: Vec<Move>,
+}
+
+/// Reads a FEN string, a board diagram, a PGN game or a move list,
+/// detecting which it is.
+///
+/// # Arguments
+///
+/// * `text` - The text to read
+///
+/// # Returns
+///
+/// * The game, or a message saying what could not be read
+///
+/// # Examples
+///
+/// ```
+/// use rustchess::pgn::{import, ImportFormat};
+///
+/// let game = import("1. e4 e5 2. Nf3 Nc6 *").unwrap();
+/// assert_eq!(game.format, ImportFormat::Pgn);
+/// assert_eq!(game.moves.len(), 4);
+/// ```
+pub fn import(text: &str) -> Result<ImportedGame, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Nothing to read".to_string());
+    }
+
+    let first_field = text.split_whitespace().next().unwrap_or("");
+    if !text.contains('\n') && first_field.matches('/').count() == 7 {
+        Board::from_fen(text)?;
+        return Ok(ImportedGame { format: ImportFormat::Fen, start_fen: text.to_string(), moves: Vec::new() });
+    }
+    if let Ok(start_fen) = diagram::diagram_to_fen(text) {
+        return Ok(ImportedGame { format: ImportFormat::Diagram, start_fen, moves: Vec::new() });
+    }
+
+    let is_pgn = text.lines().any(|line| line.trim_start().starts_with('['))
+        || text.split_whitespace().any(|token| move_number_len(token) > 0);
+    let mut start_fen = START_FEN.to_string();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if let Some(fen) = tag_value(line, "FEN") {
+                start_fen = fen.to_string();
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let board = Board::from_fen(&start_fen).map_err(|e| format!("Invalid FEN tag: {}", e))?;
+    let moves = parse_movetext(board, &movetext)?;
+    let format = if is_pgn { ImportFormat::Pgn } else { ImportFormat::MoveList };
+    Ok(ImportedGame { format, start_fen, moves })
+}
+
+/// Reads the moves of PGN movetext, skipping move numbers, comments,
+/// variations, NAGs and the result.
+///
+/// # Arguments
+///
+/// * `board` - The position the moves start from
+/// * `movetext` - The moves
+///
+/// # Returns
+///
+/// * The moves, or a message naming the first move that could not be read
+pub fn parse_movetext(mut board: Board, movetext: &str) -> Result<Vec<Move>, String> {
+    let mut moves = Vec::new();
+    let mut depth = 0;  // Nesting of variations being skipped
+    for token in tokenize(movetext) {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            token if depth == 0 => {
+                let Some(text) = move_token(token) else {
+                    continue;
+                };
+                let mv = parse_san(&board, text)
+                    .map_err(|e| format!("Move {} ({}): {}", moves.len() / 2 + 1, text, e))?;
+                board.play(mv)?;
+                moves.push(mv);
+            }
+            _ => {}
+        }
+    }
+    Ok(moves)
+}
+
+/// A move annotation: one of the first six numeric annotation glyphs of
+/// PGN, written `$1` to `$6` or as the symbol after the move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nag {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+}
+
+impl Nag {
+    /// Every annotation, in the order of their codes.
+    pub const ALL: [Nag; 6] = [Nag::Good, Nag::Mistake, Nag::Brilliant, Nag::Blunder, Nag::Interesting, Nag::Dubious];
+
+    /// The annotation's code, as in `$4`.
+    pub fn code(self) -> u8 {
+        Nag::ALL.iter().position(|&nag| nag == self).unwrap_or(0) as u8 + 1
+    }
+
+    pub fn from_code(code: u8) -> Option<Nag> {
+        Nag::ALL.get(usize::from(code).checked_sub(1)?).copied()
+    }
+
+    /// The symbol written after the move, e.g. `?!`.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Nag::Good => "!",
+            Nag::Mistake => "?",
+            Nag::Brilliant => "!!",
+            Nag::Blunder => "??",
+            Nag::Interesting => "!?",
+            Nag::Dubious => "?!",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Nag> {
+        Nag::ALL.into_iter().find(|nag| nag.symbol() == symbol)
+    }
+}
+
+/// A move in a `MoveTree` and the moves that can follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveNode {
+    pub mv: Move,
+    pub nag: Option<Nag>,
+    /// Comment on the move; empty if there is none
+    pub comment: String,
+    /// Continuations, the main line first
+    pub children: Vec<MoveNode>,
+}
+
+/// A piece of movetext as `MoveTree::items` lays it out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovetextItem {
+    /// A move number such as `12.` or `12...`
+    Number(String),
+    /// A move with its annotation symbol, and the line from the start
+    /// position that ends with it
+    Move { line: Vec<Move>, text: String },
+    Comment(String),
+    /// The start of a variation
+    Open,
+    /// The end of a variation
+    Close,
+}
+
+/// Lines of play from one start position, such as a PGN game with its
+/// variations or a whole opening repertoire.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveTree {
+    pub start_fen: String,
+    /// First moves, the main line first
+    pub roots: Vec<MoveNode>,
+}
+
+impl MoveTree {
+    /// Creates a tree with no moves.
+    pub fn new(start_fen: &str) -> Self {
+        MoveTree { start_fen: start_fen.to_string(), roots: Vec::new() }
+    }
+
+    /// Adds a line, sharing the moves it starts with with the lines already
+    /// in the tree. New moves go after the existing continuations.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - Moves from the start position; they are not checked
+    pub fn add_line(&mut self, line: &[Move]) {
+        let mut nodes = &mut self.roots;
+        for &mv in line {
+            let index = match nodes.iter().position(|node| node.mv == mv) {
+                Some(index) => index,
+                None => {
+                    nodes.push(MoveNode { mv, nag: None, comment: String::new(), children: Vec::new() });
+                    nodes.len() - 1
+                }
+            };
+            nodes = &mut nodes[index].children;
+        }
+    }
+
+    /// The moves that can follow `line`, or `None` if it leaves the tree.
+    pub fn continuations(&self, line: &[Move]) -> Option<&[MoveNode]> {
+        let mut nodes = self.roots.as_slice();
+        for mv in line {
+            nodes = &nodes.iter().find(|node| node.mv == *mv)?.children;
+        }
+        Some(nodes)
+    }
+
+    /// The node of the last move of `line`, if the line is in the tree.
+    pub fn node(&self, line: &[Move]) -> Option<&MoveNode> {
+        let (last, before) = line.split_last()?;
+        self.continuations(before)?.iter().find(|node| node.mv == *last)
+    }
+
+    pub fn node_mut(&mut self, line: &[Move]) -> Option<&mut MoveNode> {
+        let (last, before) = line.split_last()?;
+        self.continuations_mut(before)?.iter_mut().find(|node| node.mv == *last)
+    }
+
+    fn continuations_mut(&mut self, line: &[Move]) -> Option<&mut Vec<MoveNode>> {
+        let mut nodes = &mut self.roots;
+        for mv in line {
+            nodes = &mut nodes.iter_mut().find(|node| node.mv == *mv)?.children;
+        }
+        Some(nodes)
+    }
+
+    /// Removes the last move of `line` from the tree, with every move after
+    /// it.
+    ///
+    /// # Returns
+    ///
+    /// * The removed move, or `None` if the line is not in the tree
+    pub fn remove(&mut self, line: &[Move]) -> Option<MoveNode> {
+        let (last, before) = line.split_last()?;
+        let nodes = self.continuations_mut(before)?;
+        let index = nodes.iter().position(|node| node.mv == *last)?;
+        Some(nodes.remove(index))
+    }
+
+    /// Promotes the variation `line` ends in: where it last branches off a
+    /// main continuation, it becomes the main continuation, and the old one
+    /// its first alternative.
+    ///
+    /// # Returns
+    ///
+    /// * Whether anything changed; a line in the main line stays as it is
+    pub fn promote(&mut self, line: &[Move]) -> bool {
+        let mut branch = None;  // Length of the line before the branch, and the variation's index there
+        let mut nodes = self.roots.as_slice();
+        for (ply, mv) in line.iter().enumerate() {
+            let Some(index) = nodes.iter().position(|node| node.mv == *mv) else {
+                return false;
+            };
+            if index > 0 {
+                branch = Some((ply, index));
+            }
+            nodes = &nodes[index].children;
+        }
+        let Some((ply, index)) = branch else {
+            return false;
+        };
+        let Some(nodes) = self.continuations_mut(&line[..ply]) else {
+            return false;
+        };
+        let variation = nodes.remove(index);
+        nodes.insert(0, variation);
+        true
+    }
+
+    /// The line following the first continuation of every move.
+    pub fn main_line(&self) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut nodes = self.roots.as_slice();
+        while let Some(node) = nodes.first() {
+            line.push(node.mv);
+            nodes = &node.children;
+        }
+        line
+    }
+
+    /// Every line from the start to the end of a variation, the main line
+    /// first.
+    pub fn lines(&self) -> Vec<Vec<Move>> {
+        fn collect(nodes: &[MoveNode], line: &mut Vec<Move>, lines: &mut Vec<Vec<Move>>) {
+            for node in nodes {
+                line.push(node.mv);
+                if node.children.is_empty() {
+                    lines.push(line.clone());
+                } else {
+                    collect(&node.children, line, lines);
+                }
+                line.pop();
+            }
+        }
+        let mut lines = Vec::new();
+        collect(&self.roots, &mut Vec::new(), &mut lines);
+        lines
+    }
+}
+
+impl MoveTree {
+    /// Lays the tree out as PGN movetext: the main line, with each
+    /// variation in parentheses after the move it is an alternative to.
+    ///
+    /// # Arguments
+    ///
+    /// * `write` - Writes a move played in a position, e.g. `to_san`
+    ///
+    /// # Returns
+    ///
+    /// * The numbers, moves, comments and variation brackets in order;
+    ///   nothing if the start position cannot be read
+    pub fn items(&self, write: impl Fn(&Board, Move) -> String) -> Vec<MovetextItem> {
+        let Ok(board) = Board::from_fen(&self.start_fen) else {
+            return Vec::new();
+        };
+        let mut writer = MovetextWriter {
+            write,
+            first_ply: (board.position().fullmove_number.max(1) - 1) * 2 + usize::from(board.side_to_move() == Color::Black),
+            items: Vec::new(),
+        };
+        writer.nodes(&self.roots, &board, &mut Vec::new(), true);
+        writer.items
+    }
+
+    /// The tree as PGN movetext, without a result.
+    ///
+    /// # Arguments
+    ///
+    /// * `write` - Writes a move played in a position, e.g. `to_san`
+    pub fn to_movetext(&self, write: impl Fn(&Board, Move) -> String) -> String {
+        let mut text = String::new();
+        for item in self.items(write) {
+            if item == MovetextItem::Close {
+                text.push(')');
+                continue;
+            }
+            if !text.is_empty() && !text.ends_with('(') {
+                text.push(' ');
+            }
+            match item {
+                MovetextItem::Number(number) => text.push_str(&number),
+                MovetextItem::Move { text: mv, .. } => text.push_str(&mv),
+                MovetextItem::Comment(comment) => text.push_str(&format!("{{{}}}", comment)),
+                MovetextItem::Open => text.push('('),
+                MovetextItem::Close => {}
+            }
+        }
+        text
+    }
+}
+
+/// Builds the items of `MoveTree::items`.
+struct MovetextWriter<F> {
+    write: F,
+    first_ply: usize,  // Plies played before the start position, counted from move 1 for White
+    items: Vec<MovetextItem>,
+}
+
+impl<F: Fn(&Board, Move) -> String> MovetextWriter<F> {
+    /// Writes the moves that can follow `line`: the main continuation, its
+    /// alternatives as variations, then the rest of the main line.
+    ///
+    /// # Arguments
+    ///
+    /// * `numbered` - Whether a Black move needs its number, as it does
+    ///   after a comment or variation
+    fn nodes(&mut self, nodes: &[MoveNode], board: &Board, line: &mut Vec<Move>, numbered: bool) {
+        let Some((main, alternatives)) = nodes.split_first() else {
+            return;
+        };
+        let after = self.node(main, board, line, numbered);
+        for alternative in alternatives {
+            self.items.push(MovetextItem::Open);
+            let after_alternative = self.node(alternative, board, line, true);
+            line.push(alternative.mv);
+            self.nodes(&alternative.children, &after_alternative, line, !alternative.comment.is_empty());
+            line.pop();
+            self.items.push(MovetextItem::Close);
+        }
+        line.push(main.mv);
+        self.nodes(&main.children, &after, line, !alternatives.is_empty() || !main.comment.is_empty());
+        line.pop();
+    }
+
+    /// Writes one move with its number and comment.
+    ///
+    /// # Returns
+    ///
+    /// * The position after the move
+    fn node(&mut self, node: &MoveNode, board: &Board, line: &[Move], numbered: bool) -> Board {
+        let ply = self.first_ply + line.len();
+        match (ply % 2, numbered) {
+            (0, _) => self.items.push(MovetextItem::Number(format!("{}.", ply / 2 + 1))),
+            (_, true) => self.items.push(MovetextItem::Number(format!("{}...", ply / 2 + 1))),
+            _ => {}
+        }
+        let mut full_line = line.to_vec();
+        full_line.push(node.mv);
+        let mut text = (self.write)(board, node.mv);
+        text.push_str(node.nag.map_or("", Nag::symbol));
+        self.items.push(MovetextItem::Move { line: full_line, text });
+        if !node.comment.is_empty() {
+            // A closing brace would end the comment early
+            self.items.push(MovetextItem::Comment(node.comment.replace('}', "")));
+        }
+        let mut after = board.clone();
+        let _ = after.play(node.mv);
+        after
+    }
+}
+
+/// Reads one or more PGN games, or a move list, into a tree of their moves,
+/// variations and comments.
+///
+/// # Arguments
+///
+/// * `text` - The games; each starts with its tag lines, and all of them
+///   must start from the same position
+///
+/// # Returns
+///
+/// * The tree, or a message saying what could not be read
+pub fn import_tree(text: &str) -> Result<MoveTree, String> {
+    let mut games: Vec<(String, String)> = Vec::new();  // Start FEN and movetext of each game
+    let mut in_tags = false;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if line.starts_with('[') {
+            if !in_tags || games.is_empty() {
+                games.push((START_FEN.to_string(), String::new()));
+                in_tags = true;
+            }
+            if let Some(fen) = tag_value(line, "FEN") {
+                games.last_mut().expect("a game was just added").0 = fen.to_string();
+            }
+        } else {
+            if games.is_empty() {
+                games.push((START_FEN.to_string(), String::new()));
+            }
+            in_tags = false;
+            let movetext = &mut games.last_mut().expect("a game was just added").1;
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    let Some(start_fen) = games.first().map(|(fen, _)| fen.clone()) else {
+        return Err("Nothing to read".to_string());
+    };
+
+    let mut tree = MoveTree::new(&start_fen);
+    let count = games.len();
+    for (number, (fen, movetext)) in games.into_iter().enumerate() {
+        let name = |e: String| if count > 1 { format!("Game {}: {}", number + 1, e) } else { e };
+        if fen != start_fen {
+            return Err(name("Starts from a different position than the first game".to_string()));
+        }
+        let board = Board::from_fen(&fen).map_err(|e| name(format!("Invalid FEN tag: {}", e)))?;
+        add_variations(&mut tree, board, &movetext).map_err(name)?;
+    }
+    Ok(tree)
+}
+
+/// Adds the moves of PGN movetext to a tree, variations included.
+fn add_variations(tree: &mut MoveTree, board: Board, movetext: &str) -> Result<(), String> {
+    // The line being read, with the position before each of its moves and
+    // the one after the last
+    let mut line = Vec::new();
+    let mut boards = vec![board];
+    let mut outer = Vec::new();  // The lines the open variations branch off
+    for token in tokenize(movetext) {
+        match token.as_str() {
+            "(" => {
+                // A variation replaces the last move of the line it is in
+                if line.is_empty() {
+                    return Err("Variation before the first move".to_string());
+                }
+                outer.push((line.clone(), boards.clone()));
+                line.pop();
+                boards.pop();
+            }
+            ")" => (line, boards) = outer.pop().ok_or_else(|| "Unmatched ')'".to_string())?,
+            nag if nag.starts_with('$') || is_annotation(nag) => {
+                let nag = nag.strip_prefix('$').map_or_else(|| Nag::from_symbol(nag), |code| code.parse().ok().and_then(Nag::from_code));
+                if let Some(node) = tree.node_mut(&line).filter(|_| nag.is_some()) {
+                    node.nag = nag;
+                }
+            }
+            comment if comment.starts_with('{') => {
+                let comment = comment.trim_start_matches('{').trim_end_matches('}');
+                if let Some(node) = tree.node_mut(&line).filter(|_| !comment.is_empty()) {
+                    if !node.comment.is_empty() {
+                        node.comment.push(' ');
+                    }
+                    node.comment.push_str(comment);
+                }
+            }
+            token => {
+                let Some(text) = move_token(token) else {
+                    continue;
+                };
+                let mut board = boards.last().expect("there is always a current position").clone();
+                let mv = parse_san(&board, text)
+                    .map_err(|e| format!("Move {} ({}): {}", line.len() / 2 + 1, text, e))?;
+                board.play(mv)?;
+                line.push(mv);
+                boards.push(board);
+                tree.add_line(&line);
+                // An annotation written after the move, as in `Nf3!?`
+                let symbol = &text[text.trim_end_matches(['!', '?']).len()..];
+                if let Some(nag) = Nag::from_symbol(symbol) {
+                    if let Some(node) = tree.node_mut(&line) {
+                        node.nag = Some(nag);
+                    }
+                }
+            }
+        }
+    }
+    if outer.is_empty() { Ok(()) } else { Err("Unclosed variation".to_string()) }
+}
+
+/// Splits movetext into tokens, with the parentheses around variations as
+/// tokens of their own and each comment a token in braces.
+fn tokenize(movetext: &str) -> Vec<String> {
+    let mut chars = movetext.chars();
+    let mut token = String::new();
+    let mut tokens = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            // Comments run to the closing brace, or to the end of the line
+            '{' | ';' => {
+                let end = if c == '{' { '}' } else { '\n' };
+                let comment: String = chars.by_ref().take_while(|&c| c != end).collect();
+                tokens.push(core::mem::take(&mut token));
+                let comment = comment.replace('}', "");
+                tokens.push(format!("{{{}}}", comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            '(' | ')' => {
+                tokens.push(core::mem::take(&mut token));
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => tokens.push(core::mem::take(&mut token)),
+            c => token.push(c),
+        }
+    }
+    tokens.push(token);
+    tokens.retain(|token| !token.is_empty());
+    tokens
+}
+
+/// The move in a movetext token, without its move number, or `None` for
+/// tokens that are not moves: comments, NAGs, results and bare move numbers.
+fn move_token(token: &str) -> Option<&str> {
+    let text = &token[move_number_len(token)..];
+    let skip = text.is_empty() || text.starts_with(['$', '{']) || is_annotation(text)
+        || ["1-0", "0-1", "1/2-1/2", "*"].contains(&text);
+    (!skip).then_some(text)
+}
+
+/// Whether a token is an annotation symbol on its own, such as `!?`.
+fn is_annotation(token: &str) -> bool {
+    !token.is_empty() && token.bytes().all(|b| b == b'!' || b == b'?')
+}
+
+/// Length of a leading move number such as `12.` or `12...`, or 0.
+fn move_number_len(token: &str) -> usize {
+    let digits = token.bytes().take_while(u8::is_ascii_digit).count();
+    let dots = token[digits..].bytes().take_while(|&b| b == b'.').count();
+    if digits > 0 && dots > 0 { digits + dots } else { 0 }
+}
+
+/// The value of a PGN tag line such as `[FEN "..."]`, if it is tag `name`.
+fn tag_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let value = inner.strip_prefix(name)?.trim();
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_detects_the_format() {
+        let fen = import("  4k3/8/8/8/8/8/8/4K2R b K - 0 1\n").unwrap();
+        assert_eq!(fen.format, ImportFormat::Fen);
+        assert_eq!(fen.start_fen, "4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+        assert!(fen.moves.is_empty());
+
+        let diagram = import("....k...\n........\n........\n........\n........\n........\n........\nR...K...").unwrap();
+        assert_eq!(diagram.format, ImportFormat::Diagram);
+        assert_eq!(diagram.start_fen, "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+
+        let moves = import("e4 e5 Nf3").unwrap();
+        assert_eq!(moves.format, ImportFormat::MoveList);
+        assert_eq!(moves.start_fen, START_FEN);
+        assert_eq!(moves.moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), ["e2e4", "e7e5", "g1f3"]);
+
+        let pgn = import("[Event \"Casual\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 w - - 0 1\"]\n\n\
+            1. Ra7 {cuts the king off} (1. Ra8+ $2 Kd7) Kf8 2. Kf2 1-0").unwrap();
+        assert_eq!(pgn.format, ImportFormat::Pgn);
+        assert_eq!(pgn.start_fen, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(pgn.moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), ["a1a7", "e8f8", "e1f2"]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        assert!(import("   ").is_err());
+        assert!(import("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1").is_err());
+        let error = import("1. e4 e5 2. Ke3").unwrap_err();
+        assert!(error.contains("Move 2 (Ke3)"), "{}", error);
+    }
+
+    #[test]
+    fn test_import_tree_keeps_variations() {
+        let moves = |line: &[Move]| line.iter().map(|mv| mv.to_string()).collect::<Vec<_>>().join(" ");
+        let tree = import_tree("[Event \"Sicilian\"]\n\n1. e4 c5 (1... e5 2. Nf3 (2. Bc4) Nc6) 2. Nf3 *\n\n\
+            [Event \"French\"]\n1. e4 e6 2. d4 {main} d5 *\n\n[Event \"Repeat\"]\n1. e4 c5 2. Nf3 d6 *").unwrap();
+        assert_eq!(tree.start_fen, START_FEN);
+        let lines: Vec<String> = tree.lines().iter().map(|line| moves(line)).collect();
+        assert_eq!(lines, [
+            "e2e4 c7c5 g1f3 d7d6",
+            "e2e4 e7e5 g1f3 b8c6",
+            "e2e4 e7e5 f1c4",
+            "e2e4 e7e6 d2d4 d7d5",
+        ]);
+        let e4 = tree.roots[0].mv;
+        assert_eq!(tree.continuations(&[e4]).unwrap().len(), 3);
+        assert!(tree.continuations(&[tree.roots[0].children[0].children[0].mv]).is_none());
+
+        // Variations stay out of the main line
+        let board = Board::new();
+        assert_eq!(moves(&parse_movetext(board, "1. e4 c5 (1... e5 2. Nf3) 2. Nf3").unwrap()), "e2e4 c7c5 g1f3");
+
+        assert_eq!(import_tree("1. e4 (e5)").unwrap_err(), "Move 1 (e5): Illegal move: e5");
+        assert_eq!(import_tree("1. e4 e5 ( 1... c5").unwrap_err(), "Unclosed variation");
+        assert_eq!(import_tree("(1. e4)").unwrap_err(), "Variation before the first move");
+        let error = import_tree("1. e4 *\n\n[FEN \"4k3/8/8/8/8/8/8/4K3 w - - 0 1\"]\n1. Kd2 *").unwrap_err();
+        assert!(error.starts_with("Game 2: "), "{}", error);
+    }
+
+    #[test]
+    fn test_move_tree_edits_and_round_trips() {
+        let text = "1. e4 {best by test} c5 (1... e5 2. Nf3 (2. Bc4) Nc6) (1... e6) 2. Nf3 d6 ; the Najdorf\n3. d4";
+        let mut tree = import_tree(text).unwrap();
+        let san = tree.to_movetext(to_san);
+        assert_eq!(san, "1. e4 {best by test} 1... c5 (1... e5 2. Nf3 (2. Bc4) 2... Nc6) (1... e6) 2. Nf3 d6 {the Najdorf} 3. d4");
+        assert_eq!(import_tree(&san).unwrap(), tree);
+
+        let long = tree.to_movetext(|_, mv| mv.to_string());
+        assert!(long.starts_with("1. e2e4 {best by test} 1... c7c5 (1... e7e5"), "{}", long);
+        assert_eq!(import_tree(&long).unwrap(), tree);
+
+        let main = tree.main_line();
+        assert_eq!(main.len(), 5);
+        assert_eq!(tree.node(&main[..1]).unwrap().comment, "best by test");
+        let e5 = [main[0], tree.roots[0].children[1].mv];
+        let bc4 = tree.lines()[2].clone();
+        assert_eq!(bc4.len(), 3);
+
+        // 2. Bc4 becomes the main reply to 1... e5, then 1... e5 the main line
+        assert!(tree.promote(&bc4));
+        assert_eq!(tree.lines()[1], bc4);
+        assert!(tree.promote(&bc4));
+        assert_eq!(tree.main_line(), bc4);
+        assert!(!tree.promote(&bc4));
+        assert!(tree.promote(&main));
+        assert_eq!(tree.main_line(), main);
+        assert!(!tree.promote(&main));
+
+        assert_eq!(tree.remove(&e5).unwrap().children.len(), 2);
+        assert_eq!(tree.main_line(), main);
+        assert!(tree.remove(&e5).is_none());
+        assert_eq!(tree.lines().len(), 2);
+
+        // Numbering follows the start position
+        let tree = import_tree("[FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 0 7\"]\n7... Kf8 (7... Kd8) 8. Ra7").unwrap();
+        assert_eq!(tree.to_movetext(to_san), "7... Kf8 (7... Kd8) 8. Ra7");
+    }
+
+    #[test]
+    fn test_nags_are_read_and_written() {
+        assert!(Nag::ALL.iter().all(|&nag| Nag::from_code(nag.code()) == Some(nag)));
+        assert!(Nag::ALL.iter().all(|&nag| Nag::from_symbol(nag.symbol()) == Some(nag)));
+        assert_eq!((Nag::Blunder.code(), Nag::from_code(0), Nag::from_code(7)), (4, None, None));
+
+        let mut tree = import_tree("1. e4! e5 $6 2. Qh5?! $10 Nc6 ?? (2... Nf6 !!) 3. Bc4 Nf6?? 4. Qxf7#").unwrap();
+        let nags: Vec<Option<Nag>> = tree.lines()[0].iter().enumerate()
+            .map(|(ply, _)| tree.node(&tree.lines()[0][..=ply]).unwrap().nag)
+            .collect();
+        assert_eq!(nags, [Some(Nag::Good), Some(Nag::Dubious), Some(Nag::Dubious), Some(Nag::Blunder), None, Some(Nag::Blunder), None]);
+        let nf6 = tree.lines()[1].clone();
+        assert_eq!(tree.node(&nf6).unwrap().nag, Some(Nag::Brilliant));
+
+        tree.node_mut(&nf6).unwrap().comment = "the {only} move".to_string();
+        let san = tree.to_movetext(to_san);
+        assert_eq!(san, "1. e4! e5?! 2. Qh5?! Nc6?? (2... Nf6!! {the {only move}) 3. Bc4 Nf6?? 4. Qxf7#");
+        assert_eq!(import_tree(&san).unwrap().to_movetext(to_san), san);
+        let long = tree.to_movetext(|_, mv| mv.to_string());
+        assert_eq!(import_tree(&long).unwrap().to_movetext(to_san), san);
+
+        // The main line reads through the annotations
+        assert_eq!(import(&san).unwrap().moves, tree.main_line());
+    }
+}
@@ -0,0 +1,476 @@
+//! Players.
+//!
+//! `Player` is the common interface for anything that chooses moves: the
+//! full `Search` (and the `Engine` wrapping it), the simple baseline
+//! opponents defined here, external engines driven over UCI
+//! (`external::UciEngine`) and people, through `HumanPlayer`. The GUI, the
+//! command-line game and calibration all drive games through it.
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+
+use crate::chess_move::Move;
+use crate::engine::Analysis;
+use crate::options::EngineOptions;
+use crate::position::{PieceType, Position};
+use crate::search::Search;
+use crate::time_manager::Clock;
+use crate::Game;
+
+const MATE_SCORE: i32 = 100000;
+/// Beyond any score, mates included
+const INFINITY: i32 = 2 * MATE_SCORE;
+
+/// How long a player may think about a move. Limits left as `None` fall
+/// back to the player's own settings; players that do not search ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub movetime: Option<Duration>,
+    /// The side to move's clock when playing on one; searches then budget
+    /// their own time, and `movetime` is ignored
+    pub clock: Option<Clock>,
+    /// Maximum search depth in plies
+    pub depth: Option<i32>,
+    /// Shortest time `Player::choose_move_paced` takes, so that easy moves
+    /// do not come back the instant the user has moved
+    pub min_time: Option<Duration>,
+    /// Answer at once, after a one ply search and without `min_time`; for
+    /// testing
+    pub instant: bool,
+    /// Root moves the search may choose from, like UCI `go searchmoves`;
+    /// empty allows every legal move
+    pub search_moves: Vec<Move>,
+    /// Root moves the search must not choose, e.g. to ask what else the
+    /// position offers
+    pub excluded_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    /// Limits that allow `millis` milliseconds per move.
+    pub fn movetime_ms(millis: u64) -> Self {
+        SearchLimits { movetime: Some(Duration::from_millis(millis)), ..SearchLimits::default() }
+    }
+
+    /// Sleeps until `min_time` has passed since `started`, unless moving
+    /// instantly.
+    pub fn wait_for_min_time(&self, started: Instant) {
+        if let (Some(min_time), false) = (self.min_time, self.instant) {
+            if let Some(remaining) = min_time.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+}
+
+/// Something that chooses moves.
+pub trait Player {
+    /// Chooses a move for the side to move in `position`.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to move in
+    /// * `limits` - How long the player may think
+    ///
+    /// # Returns
+    ///
+    /// * The move, or `None` if the player has no move to make, e.g. because
+    ///   the side to move has no legal moves or a human gave up
+    fn choose_move(&mut self, position: &Position, limits: &SearchLimits) -> Option<Move>;
+
+    /// Like `choose_move`, but does not answer before `limits.min_time` has
+    /// passed. Front ends playing against a person use this one.
+    fn choose_move_paced(&mut self, position: &Position, limits: &SearchLimits) -> Option<Move> {
+        let started = Instant::now();
+        let mv = self.choose_move(position, limits);
+        limits.wait_for_min_time(started);
+        mv
+    }
+
+    /// Like `choose_move`, but also reports the score and principal variation
+    /// the player found, so front ends can keep them with the game. Players
+    /// that do not search report no analysis.
+    fn choose_move_analyzed(&mut self, position: &Position, limits: &SearchLimits) -> (Option<Move>, Option<Analysis>) {
+        (self.choose_move(position, limits), None)
+    }
+
+    /// Short description for menus and reports.
+    fn name(&self) -> String;
+
+    /// Flag that makes the player's searches ponder while set, see
+    /// `Search::ponder_flag`, or `None` for players that cannot ponder.
+    fn ponder_flag(&self) -> Option<Arc<AtomicBool>> {
+        None
+    }
+
+    /// Reads the player's search parameter file again.
+    ///
+    /// # Returns
+    ///
+    /// * A description of what was loaded, or an error if the player has no
+    ///   parameter file or it cannot be read
+    fn reload_config(&mut self) -> Result<String, String> {
+        Err(format!("The {} player has no search configuration", self.name()))
+    }
+
+    /// Saves the player's hash file, if it has one, so its analysis carries
+    /// over to the next session. Players without one have nothing to save.
+    fn save_hash(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Memory the player's hash table and caches take, in bytes. Players
+    /// without any, or running in another process, report none.
+    fn memory_bytes(&self) -> usize {
+        0
+    }
+
+    /// Replaces the player's hash table with an empty one of another size.
+    ///
+    /// # Arguments
+    ///
+    /// * `size_mb` - The new size in megabytes
+    ///
+    /// # Returns
+    ///
+    /// * An error if the player has no hash table
+    fn set_hash_size(&mut self, size_mb: usize) -> Result<(), String> {
+        let _ = size_mb;
+        Err(format!("The {} player has no hash table", self.name()))
+    }
+
+    /// Applies the engine options (see `options`) from the player's next
+    /// move on. The hash table is only replaced when its size changes.
+    /// Players without such settings ignore them.
+    fn set_options(&mut self, options: &EngineOptions) {
+        let _ = options;
+    }
+}
+
+/// The limits apply to this move only; the search's own settings are
+/// restored afterwards.
+impl Player for Search {
+    fn choose_move(&mut self, position: &Position, limits: &SearchLimits) -> Option<Move> {
+        let (max_time, max_depth) = (self.max_time(), self.max_depth());
+        if let Some(clock) = &limits.clock {
+            self.set_clock(clock, position);
+        } else if let Some(movetime) = limits.movetime {
+            self.set_max_time_ms(movetime.as_millis() as u64);
+        }
+        if let Some(depth) = limits.depth {
+            self.set_max_depth(depth);
+        }
+        if limits.instant {
+            self.set_max_depth(1);
+        }
+        let to_u64 = |moves: &[Move]| -> Vec<u64> { moves.iter().map(|mv| mv.to_u64()).collect() };
+        self.set_root_moves(&to_u64(&limits.search_moves), &to_u64(&limits.excluded_moves));
+        let mov = self.find_best_move(&mut position.clone());
+        self.set_max_time_ms(max_time.as_millis() as u64);
+        self.set_max_depth(max_depth);
+        self.set_root_moves(&[], &[]);
+        Some(Move::from_u64(mov?, position))
+    }
+
+    fn choose_move_analyzed(&mut self, position: &Position, limits: &SearchLimits) -> (Option<Move>, Option<Analysis>) {
+        let Some(best_move) = self.choose_move(position, limits) else {
+            return (None, None);
+        };
+        let mut line = position.clone();
+        let pv = self.principal_variation(position, best_move.to_u64()).into_iter()
+            .map(|mov| {
+                let mv = Move::from_u64(mov, &line);
+                line.make_move(mov);
+                mv
+            })
+            .collect();
+        let analysis = Analysis {
+            best_move,
+            score: self.best_score(),
+            pv,
+            nodes: self.nodes_searched(),
+            depth: self.completed_depth(),
+            truncated: self.truncated(),
+        };
+        (Some(best_move), Some(analysis))
+    }
+
+    fn name(&self) -> String {
+        "engine".to_string()
+    }
+
+    fn ponder_flag(&self) -> Option<Arc<AtomicBool>> {
+        Some(Search::ponder_flag(self))
+    }
+
+    fn reload_config(&mut self) -> Result<String, String> {
+        let path = Search::reload_config(self)?;
+        Ok(format!("Reloaded search parameters from {}", path.display()))
+    }
+
+    fn save_hash(&self) -> Result<(), String> {
+        match self.hash_file() {
+            Some(path) => Search::save_hash(self, path).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        Search::memory_bytes(self)
+    }
+
+    fn set_hash_size(&mut self, size_mb: usize) -> Result<(), String> {
+        Search::set_hash_size(self, size_mb);
+        Ok(())
+    }
+
+    fn set_options(&mut self, options: &EngineOptions) {
+        if options.hash_mb != self.hash_size() {
+            Search::set_hash_size(self, options.hash_mb);
+        }
+        self.set_move_overhead(options.move_overhead);
+        self.set_skill_level(options.skill_level);
+    }
+}
+
+/// Plays a uniformly random legal move.
+pub struct RandomPlayer {
+    game: Game,
+    rng: StdRng,
+}
+
+impl RandomPlayer {
+    /// Creates a random player whose choices are fixed by `seed`.
+    pub fn new(seed: u64) -> Self {
+        RandomPlayer { game: Game::new(), rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Player for RandomPlayer {
+    fn choose_move(&mut self, position: &Position, _limits: &SearchLimits) -> Option<Move> {
+        let mov = legal_moves(position, &self.game).choose(&mut self.rng).copied()?;
+        Some(Move::from_u64(mov, position))
+    }
+
+    fn name(&self) -> String {
+        "random mover".to_string()
+    }
+}
+
+/// Searches `depth` plies with alpha-beta, counting nothing but material.
+///
+/// Equally good moves are chosen between at random, so repeated games
+/// against it differ. Its depth is fixed, whatever the search limits.
+pub struct GreedyMaterialPlayer {
+    depth: u32,
+    game: Game,
+    rng: StdRng,
+}
+
+impl GreedyMaterialPlayer {
+    /// Creates a player searching `depth` plies (at least one), with ties
+    /// broken by `seed`.
+    pub fn new(depth: u32, seed: u64) -> Self {
+        GreedyMaterialPlayer { depth: depth.max(1), game: Game::new(), rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Player for GreedyMaterialPlayer {
+    fn choose_move(&mut self, position: &Position, _limits: &SearchLimits) -> Option<Move> {
+        let mut moves = legal_moves(position, &self.game);
+        moves.shuffle(&mut self.rng);
+
+        let mut best = None;
+        let mut alpha = -INFINITY;
+        for mov in moves {
+            let mut child = position.clone();
+            child.make_move(mov);
+            let score = -material_search(&mut child, &self.game, self.depth - 1, -INFINITY, -alpha);
+            if score > alpha {
+                alpha = score;
+                best = Some(mov);
+            }
+        }
+        best.map(|mov| Move::from_u64(mov, position))
+    }
+
+    fn name(&self) -> String {
+        format!("material depth {}", self.depth)
+    }
+}
+
+/// A person choosing moves through some front end.
+///
+/// The adapter calls `ask` with the position whenever a move is needed; the
+/// front end prompts for the move however it likes and returns `None` if the
+/// person resigns or quits.
+pub struct HumanPlayer<F: FnMut(&Position) -> Option<Move>> {
+    name: String,
+    ask: F,
+}
+
+impl<F: FnMut(&Position) -> Option<Move>> HumanPlayer<F> {
+    pub fn new(name: &str, ask: F) -> Self {
+        HumanPlayer { name: name.to_string(), ask }
+    }
+}
+
+impl<F: FnMut(&Position) -> Option<Move>> Player for HumanPlayer<F> {
+    fn choose_move(&mut self, position: &Position, _limits: &SearchLimits) -> Option<Move> {
+        (self.ask)(position)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Legal moves of the side to move in a copy of `position`.
+fn legal_moves(position: &Position, game: &Game) -> Vec<u64> {
+    let mut position = position.clone();
+    position.update_all_legal_moves(game);
+    position.get_all_legal_moves(game)
+}
+
+/// Negamax with alpha-beta over material only, from the side to move's view.
+fn material_search(position: &mut Position, game: &Game, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    position.update_all_legal_moves(game);
+    let moves = position.get_all_legal_moves(game);
+    if moves.is_empty() {
+        // Prefer quicker mates by scoring them higher at greater remaining depth
+        return if position.is_in_check(game) { -MATE_SCORE - depth as i32 } else { 0 };
+    }
+    if depth == 0 {
+        return material(position);
+    }
+
+    for mov in moves {
+        let mut child = position.clone();
+        child.make_move(mov);
+        let score = -material_search(&mut child, game, depth - 1, -beta, -alpha);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// Material balance in centipawns from the side to move's view.
+fn material(position: &Position) -> i32 {
+    position.pieces.iter()
+        .map(|piece| {
+            let value = match piece.piece_type {
+                PieceType::Pawn => 100,
+                PieceType::Knight | PieceType::Bishop => 300,
+                PieceType::Rook => 500,
+                PieceType::Queen => 900,
+                PieceType::King => 0,
+            };
+            if piece.color == position.active_color { value } else { -value }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::chess_move::Move;
+
+    fn pick(player: &mut dyn Player, fen: &str) -> String {
+        let board = Board::from_fen(fen).unwrap();
+        player.choose_move(board.position(), &SearchLimits::default()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_greedy_player_takes_the_queen_and_mates() {
+        // The black queen on d5 hangs to the e4 pawn
+        let hanging = "rnb1kbnr/pppp1ppp/8/3q4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3";
+        assert_eq!(pick(&mut GreedyMaterialPlayer::new(1, 1), hanging), "e4d5");
+
+        // Fool's mate in one for Black
+        let fools = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2";
+        assert_eq!(pick(&mut GreedyMaterialPlayer::new(2, 1), fools), "d8h4");
+    }
+
+    #[test]
+    fn test_random_player_plays_legal_moves() {
+        let mut player = RandomPlayer::new(3);
+        let board = Board::new();
+        for _ in 0..10 {
+            let mv = player.choose_move(board.position(), &SearchLimits::default()).unwrap();
+            assert!(board.legal_moves().contains(&mv));
+        }
+        let mated = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(player.choose_move(mated.position(), &SearchLimits::default()), None);
+        assert_eq!(player.name(), "random mover");
+    }
+
+    #[test]
+    fn test_search_and_human_players_share_the_interface() {
+        let board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        let mut players: Vec<Box<dyn Player>> = vec![
+            Box::new(Search::new()),
+            // d8 = 59, h4 = 31
+            Box::new(HumanPlayer::new("you", |position: &Position| Some(Move::from_u64(Move::encode(59, 31, None), position)))),
+        ];
+        for player in &mut players {
+            let mv = player.choose_move(board.position(), &SearchLimits::movetime_ms(200)).unwrap();
+            assert_eq!(mv.to_string(), "d8h4", "{}", player.name());
+        }
+    }
+
+    #[test]
+    fn test_min_time_and_instant_moves() {
+        let board = Board::new();
+        let paced = SearchLimits { min_time: Some(Duration::from_millis(300)), ..SearchLimits::default() };
+        let started = Instant::now();
+        assert!(RandomPlayer::new(1).choose_move_paced(board.position(), &paced).is_some());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // Moving instantly skips both the minimum time and the search's own budget
+        let mut search = Search::new();
+        search.set_max_time(30);
+        let started = Instant::now();
+        let instant = SearchLimits { instant: true, ..paced };
+        assert!(search.choose_move_paced(board.position(), &instant).is_some());
+        assert!(started.elapsed() < Duration::from_millis(300));
+        assert_eq!((search.max_depth(), search.max_time()), (Search::new().max_depth(), Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_searching_players_report_their_analysis() {
+        let board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        let (mv, analysis) = Search::new().choose_move_analyzed(board.position(), &SearchLimits::movetime_ms(200));
+        let analysis = analysis.unwrap();
+        assert_eq!(Some(analysis.best_move), mv);
+        assert_eq!(analysis.pv[0].to_string(), "d8h4");
+        assert!(analysis.score > 90_000, "{}", analysis.score);
+
+        let (mv, analysis) = RandomPlayer::new(1).choose_move_analyzed(board.position(), &SearchLimits::default());
+        assert!(mv.is_some() && analysis.is_none());
+    }
+
+    #[test]
+    fn test_root_move_restrictions() {
+        // Qh4# mates; ruling it out leaves the search another move
+        let board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        let mate = board.parse_move("d8h4").unwrap();
+        let mut search = Search::new();
+        let without = SearchLimits { excluded_moves: vec![mate], ..SearchLimits::movetime_ms(200) };
+        let mv = search.choose_move(board.position(), &without).unwrap();
+        assert_ne!(mv, mate);
+
+        let only = SearchLimits { search_moves: vec![board.parse_move("a7a6").unwrap()], ..SearchLimits::movetime_ms(200) };
+        assert_eq!(search.choose_move(board.position(), &only).unwrap().to_string(), "a7a6");
+        let nothing = SearchLimits { search_moves: vec![mate], excluded_moves: vec![mate], ..SearchLimits::default() };
+        assert_eq!(search.choose_move(board.position(), &nothing), None);
+
+        // The restrictions last for one move only
+        assert_eq!(search.choose_move(board.position(), &SearchLimits::movetime_ms(200)), Some(mate));
+    }
+}
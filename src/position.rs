@@ -1,19 +1,26 @@
+use alloc::{format, vec, borrow::ToOwned, string::{String, ToString}, vec::Vec};
 use bitflags::bitflags;
-use std::collections::VecDeque;
+use crate::attackmaps::AttackMaps;
 use crate::utils::*;
+use crate::board_consts::{RANK_1, RANK_3, RANK_6, RANK_8};
 use crate::knightattacks::*;
 use crate::rayattacks::*;
 use crate::movegen_tables::*;
 use crate::Game;
-use crate::movegeneration::{can_castle, CastlingSide};
+use crate::movegeneration::{can_castle, Castling, CastlingSide, STANDARD_CASTLING};
 use crate::utils::bit_scan_safe;
+use crate::chess_move::{Move, PROMOTION_FLAG, PROMOTION_PIECES};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 type PiecePosition = u64;
+/// Identifies a piece for as long as it stays on the board, through moves,
+/// promotion and other pieces being captured.
+pub type PieceId = u8;
 type Bitboard = u64;
-
-// File masks for pawn attacks
-const FILE_A: u64 = 0x0101010101010101;
-const FILE_H: u64 = 0x8080808080808080;
+/// Piece values used by static exchange evaluation, indexed by
+/// `PieceType as usize`. The king's value ends any exchange it is taken in.
+const SEE_VALUES: [i32; 6] = [100, 500, 320, 330, 900, 20000];
 
 pub fn bit_to_position(bit: PiecePosition) -> Result<String, String> {
     if bit == 0 {
@@ -63,6 +70,7 @@ pub fn index_to_position(index: usize) -> String {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Color {
     White,
     Black
@@ -70,6 +78,7 @@ pub enum Color {
 use Color::*;
 
 #[derive(Debug, PartialEq, Copy, Clone, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PieceType {
     Pawn,
     Rook,
@@ -80,13 +89,45 @@ pub enum PieceType {
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Piece {
     pub position: PiecePosition,
     pub color: Color,
-    pub piece_type: PieceType
+    pub piece_type: PieceType,
+    /// Numbered in FEN order when the position is set up
+    pub id: PieceId,
 }
 
 impl Piece {
+    /// The squares the piece attacks, whether empty or occupied by either side.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game holding the attack tables
+    /// * `occupancy` - All occupied squares, which block sliding pieces
+    pub fn attacks(&self, game: &Game, occupancy: Bitboard) -> Bitboard {
+        let Some(square) = bit_scan_safe(self.position) else {
+            return 0;
+        };
+        match self.piece_type {
+            PieceType::Pawn => {
+                if self.color == Color::White {
+                    game.pawn_attacks.white_diagonal_moves[square]
+                } else {
+                    game.pawn_attacks.black_diagonal_moves[square]
+                }
+            },
+            PieceType::Knight => game.move_gen_tables.knight_attacks[square],
+            PieceType::Bishop => game.rays.get_bishop_attacks(square, occupancy, self.color, 0),
+            PieceType::Rook => game.rays.get_rook_attacks(square, occupancy),
+            PieceType::Queen => {
+                game.rays.get_bishop_attacks(square, occupancy, self.color, 0) |
+                game.rays.get_rook_attacks(square, occupancy)
+            },
+            PieceType::King => game.move_gen_tables.king_attacks[square],
+        }
+    }
+
     fn to_string(&self) -> String {
         let mut result = match self.piece_type {
             PieceType::Pawn => "p ",
@@ -105,7 +146,19 @@ impl Piece {
     }
 }   
 
+/// How a move checks the enemy king, see `Position::check_kind`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheckKind {
+    /// The moved piece attacks the king
+    Direct,
+    /// Moving the piece uncovers one of its own sliders behind it
+    Discovered,
+    /// Both at once, which only a king move can answer
+    Double,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Square {
     Empty,
     Occupied(usize),
@@ -136,14 +189,34 @@ bitflags! {
     }
 }
 
+/// Castling rights are saved as their bits, which is compact in bincode and
+/// still readable in JSON.
+#[cfg(feature = "serde")]
+impl Serialize for CastlingRights {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CastlingRights {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bits = u8::deserialize(deserializer)?;
+        CastlingRights::from_bits(bits)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid castling rights: {:#x}", bits)))
+    }
+}
+
 /// Represents a complete chess position.
 /// 
 /// This struct contains all information needed to fully describe a chess position,
 /// including piece placement, castling rights, en passant targets, and move counters.
 /// It also maintains bitboards for efficient position manipulation and evaluation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Position {
-    /// Vector of all pieces on the board
+    /// The pieces on the board. Captured pieces are removed, so the indices
+    /// of other pieces can change with a capture; their `id`s do not.
     pub pieces: Vec<Piece>,
     /// Vector mapping squares to pieces (Empty or Occupied with piece index)
     pub squares: Vec<Square>,
@@ -183,6 +256,21 @@ pub struct Position {
     pub black_kingside_rook_moved: bool,
     /// Whether black queenside rook has moved from its starting square
     pub black_queenside_rook_moved: bool,
+    /// The four castling moves, indexed by `Castling::index`: the standard
+    /// ones, or those of the kings' and rooks' starting files in Chess960
+    #[cfg_attr(feature = "serde", serde(default = "standard_castling"))]
+    pub castling: [Castling; 4],
+    /// Whether the position is from a Chess960 game, whose castling moves
+    /// are written as the king taking its own rook
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chess960: bool,
+    /// Key of the position `piece_legal_moves` was generated for, if any.
+    /// Not saved, so a restored position regenerates its moves.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) legal_moves_key: Option<u64>,
+    /// Where the white and black kings stand, kept up to date by the moves
+    /// made through `Position`; see `king_square`
+    pub(crate) king_squares: [Option<usize>; 2],
 }
 
 impl Position {
@@ -191,7 +279,8 @@ impl Position {
                              piece_type: PieceType, index: &mut usize) {
         self.pieces.push(Piece { position: (1 as u64) << position,
                                  color: color,
-                                 piece_type: piece_type });
+                                 piece_type: piece_type,
+                                 id: *index as PieceId });
         self.squares.push(Square::Occupied(*index));
 
         let bitboard = 1 << position;
@@ -234,7 +323,94 @@ impl Position {
     }
 
 
+    /// The position as a six-field FEN string: piece placement, side to
+    /// move, castling rights, en passant square and the two move clocks.
+    /// Parsing it with [`Position::read_FEN`] gives the position back.
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.squares[rank * 8 + file] {
+                    Square::Empty => empty += 1,
+                    Square::Occupied(index) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let piece = &self.pieces[index];
+                        let symbol = match piece.piece_type {
+                            PieceType::Pawn => 'p',
+                            PieceType::Knight => 'n',
+                            PieceType::Bishop => 'b',
+                            PieceType::Rook => 'r',
+                            PieceType::Queen => 'q',
+                            PieceType::King => 'k',
+                        };
+                        placement.push(if piece.color == Color::White { symbol.to_ascii_uppercase() } else { symbol });
+                    }
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let side = if self.active_color == Color::White { "w" } else { "b" };
+        // X-FEN: KQkq for the outermost rooks, which are all of them outside
+        // Chess960, and the rook's file for an inner one
+        let mut castling = String::new();
+        for entry in self.castling.iter().filter(|castling| self.castling_rights.contains(castling.right)) {
+            let rook_file = entry.rook_from % 8;
+            let symbol = if !self.chess960 || self.outermost_rook_file(entry.color, entry.side) == Some(rook_file) {
+                castling_symbol(entry.right)
+            } else {
+                (b'a' + rook_file as u8) as char
+            };
+            castling.push(if entry.color == Color::White { symbol.to_ascii_uppercase() } else { symbol.to_ascii_lowercase() });
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = self.en_passant.map_or("-".to_string(), |square| index_to_position(bit_scan(square)));
+
+        format!("{} {} {} {} {} {}", placement, side, castling, en_passant, self.halfmove_clock, self.fullmove_number)
+    }
+
+    /// Parses a position from a FEN string, panicking on malformed input.
+    /// The position is not checked for legality, so studies and tests can
+    /// set up positions without kings.
+    /// 
+    /// Prefer [`Position::from_fen`] when the FEN comes from user input.
     pub fn read_FEN(fen: &str, game: &Game) -> Position {
+        match Position::parse_fen(fen, game) {
+            Ok(position) => position,
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    /// Parses a position from a FEN string and checks that it is legal.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `fen` - The six-field FEN string to parse
+    /// * `game` - Game providing the attack tables used to compute legal moves
+    /// 
+    /// # Returns
+    /// 
+    /// * The parsed position, or a message describing the first malformed
+    ///   field or why the position is illegal (see [`Position::validate`])
+    pub fn from_fen(fen: &str, game: &Game) -> Result<Position, String> {
+        let position = Position::parse_fen(fen, game)?;
+        position.validate(game)?;
+        Ok(position)
+    }
+
+    /// Parses a position from a FEN string without checking its legality.
+    fn parse_fen(fen: &str, game: &Game) -> Result<Position, String> {
         let mut position = Position {
             pieces: Vec::new(),
             squares: Vec::new(),
@@ -256,26 +432,30 @@ impl Position {
             white_queenside_rook_moved: false,
             black_kingside_rook_moved: false,
             black_queenside_rook_moved: false,
+            castling: STANDARD_CASTLING,
+            chess960: false,
+            legal_moves_key: None,
+            king_squares: [None; 2],
         };
 
         let parts: Vec<&str> = fen.split_whitespace().collect();
         if parts.len() != 6 {
-            panic!("Invalid FEN string: wrong number of fields");
+            return Err("Invalid FEN string: wrong number of fields".to_string());
         }
 
         // Parse board position
         let rows: Vec<&str> = parts[0].split('/').collect();
         if rows.len() != 8 {
-            panic!("Invalid FEN string: wrong number of ranks");
+            return Err("Invalid FEN string: wrong number of ranks".to_string());
         }
 
         let mut piece_index = 0;
         let mut piece_position = 0;
 
         for row in rows.iter().rev() {
-            let (mut pieces, mut squares) = parse_row(row, piece_index, piece_position);
+            let (mut pieces, squares) = parse_row(row, piece_index, piece_position)?;
             position.pieces.append(&mut pieces);
-            position.squares.append(&mut squares.iter().cloned().collect());
+            position.squares.extend(squares);
             piece_index = position.pieces.len();
             piece_position += 8;
         }
@@ -284,202 +464,349 @@ impl Position {
         position.active_color = match parts[1] {
             "w" => Color::White,
             "b" => Color::Black,
-            _ => panic!("Invalid FEN string: invalid active color"),
+            _ => return Err("Invalid FEN string: invalid active color".to_string()),
         };
 
-        // Parse castling rights
-        let mut castling = CastlingRights::NONE;
-        for ch in parts[2].chars() {
-            match ch {
-                'K' => castling |= CastlingRights::WHITEKINGSIDE,
-                'Q' => castling |= CastlingRights::WHITEQUEENSIDE,
-                'k' => castling |= CastlingRights::BLACKKINGSIDE,
-                'q' => castling |= CastlingRights::BLACKQUEENSIDE,
-                '-' => (),
-                other => panic!("Invalid character in castling rights: '{}'", other),
+        // Parse castling rights: KQkq, taking the outermost rook on each
+        // side of the king (X-FEN), or the files of the castling rooks,
+        // HAha in the standard position (Shredder-FEN)
+        if parts[2] != "-" {
+            for ch in parts[2].chars() {
+                let color = if ch.is_ascii_uppercase() { Color::White } else { Color::Black };
+                let king_file = position.home_king_file(color);
+                let (side, rook_file) = match ch.to_ascii_lowercase() {
+                    'k' => (CastlingSide::Kingside, position.outermost_rook_file(color, CastlingSide::Kingside)),
+                    'q' => (CastlingSide::Queenside, position.outermost_rook_file(color, CastlingSide::Queenside)),
+                    file @ 'a'..='h' => {
+                        let file = (file as u8 - b'a') as usize;
+                        match king_file {
+                            Some(king_file) if file > king_file => (CastlingSide::Kingside, Some(file)),
+                            Some(king_file) if file < king_file => (CastlingSide::Queenside, Some(file)),
+                            _ => return Err(format!("Invalid castling rights: no king beside the rook file '{}'", ch)),
+                        }
+                    }
+                    _ => return Err(format!("Invalid character in castling rights: '{}'", ch)),
+                };
+                let index = Castling::index(color, side);
+                position.castling_rights |= STANDARD_CASTLING[index].right;
+                // Without a king and rook at home the right keeps the standard
+                // squares, for `validate` to reject
+                if let (Some(king_file), Some(rook_file)) = (king_file, rook_file) {
+                    position.castling[index] = Castling::new(color, side, king_file, rook_file);
+                }
             }
+            // Other starting files than the standard ones mean Chess960
+            position.chess960 = position.castling != STANDARD_CASTLING;
         }
-        position.castling_rights = castling;
 
         // Parse en passant square
         position.en_passant = match parts[3] {
             "-" => None,
-            square => match position_to_bit(square) {
-                Ok(bit) => Some(bit),
-                Err(msg) => panic!("{}", msg),
-            },
+            square => Some(position_to_bit(square)?),
         };
 
         // Parse halfmove clock
-        position.halfmove_clock = parts[4].parse().unwrap();
+        position.halfmove_clock = parts[4].parse()
+            .map_err(|_| format!("Invalid halfmove clock: '{}'", parts[4]))?;
 
         // Parse fullmove number
-        position.fullmove_number = parts[5].parse().unwrap();
+        position.fullmove_number = parts[5].parse()
+            .map_err(|_| format!("Invalid fullmove number: '{}'", parts[5]))?;
 
         // Update occupancy bitboards
         for piece in &position.pieces {
-            if piece.position != 0 {
-                match piece.color {
-                    Color::White => position.white_occupancy |= piece.position,
-                    Color::Black => position.black_occupancy |= piece.position,
-                }
+            match piece.color {
+                Color::White => position.white_occupancy |= piece.position,
+                Color::Black => position.black_occupancy |= piece.position,
             }
         }
 
+        for color in [Color::White, Color::Black] {
+            position.king_squares[color as usize] = position.find_king(color);
+        }
+
         // Update legal moves
         position.update_all_legal_moves(game);
 
-        position
+        Ok(position)
     }
 
+    /// Checks that the position could arise in a game: one king per side,
+    /// the side that just moved not left in check, no pawns on the first or
+    /// last rank, an en passant square behind a pawn that just moved two
+    /// squares, and castling rights only with the king and rook at home.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game providing the attack tables
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or an error naming the first problem found
+    pub fn validate(&self, game: &Game) -> Result<(), String> {
+        for (color, name) in [(Color::White, "White"), (Color::Black, "Black")] {
+            match self.pieces.iter().filter(|p| p.color == color && p.piece_type == PieceType::King).count() {
+                1 => {}
+                0 => return Err(format!("Illegal position: {} has no king", name)),
+                kings => return Err(format!("Illegal position: {} has {} kings", name, kings)),
+            }
+        }
+
+        if let Some(pawn) = self.pieces.iter().find(|p| p.piece_type == PieceType::Pawn && p.position & (RANK_1 | RANK_8) != 0) {
+            return Err(format!("Illegal position: pawn on {}", index_to_position(bit_scan(pawn.position))));
+        }
+
+        let waiting = if self.active_color == Color::White { Color::Black } else { Color::White };
+        let mut other_side = self.clone();
+        other_side.active_color = waiting;
+        if other_side.is_in_check(game) {
+            let name = if waiting == Color::White { "White" } else { "Black" };
+            return Err(format!("Illegal position: {} is in check but it is not their move", name));
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            // The pawn that just moved two squares stands in front of the
+            // en passant square, which must be on the right rank and empty
+            let (rank, pawn) = match self.active_color {
+                Color::White => (RANK_6, en_passant >> 8),
+                Color::Black => (RANK_3, en_passant << 8),
+            };
+            let pawn_moved = self.pieces.iter().any(|p| p.position == pawn && p.color == waiting && p.piece_type == PieceType::Pawn);
+            let occupied = self.white_occupancy | self.black_occupancy;
+            if en_passant & rank == 0 || en_passant & occupied != 0 || !pawn_moved {
+                return Err(format!("Illegal position: en passant square {} does not follow a double pawn move",
+                    index_to_position(bit_scan(en_passant))));
+            }
+        }
+
+        for castling in self.castling.iter().filter(|castling| self.castling_rights.contains(castling.right)) {
+            let at_home = |square: usize, piece_type: PieceType| self.pieces.iter()
+                .any(|p| p.position == 1u64 << square && p.color == castling.color && p.piece_type == piece_type);
+            if !at_home(castling.king_from, PieceType::King) || !at_home(castling.rook_from, PieceType::Rook) {
+                return Err(format!("Illegal position: castling right {} needs the king on {} and a rook on {}",
+                    castling_symbol(castling.right), index_to_position(castling.king_from), index_to_position(castling.rook_from)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates the legal moves of every piece of the side to move into
+    /// `piece_legal_moves`.
+    ///
+    /// The moves are cached under the position's key, so calling this again
+    /// before the position changes costs one Zobrist hash.
     pub fn update_all_legal_moves(&mut self, game: &Game) {
+        let key = self.legal_moves_key(game);
+        if self.legal_moves_key == Some(key) && self.piece_legal_moves.len() == self.pieces.len() {
+            return;
+        }
+        self.legal_moves_key = Some(key);
+
         // Clear and resize the legal moves vector
         self.piece_legal_moves.clear();
         self.piece_legal_moves.resize(self.pieces.len(), 0);
 
-        let all_occupancy = self.white_occupancy | self.black_occupancy;
-
         // First pass: Calculate pseudo-legal moves for each piece
         for (i, piece) in self.pieces.iter().enumerate() {
-            if piece.position == 0 {
-                continue;  // Skip captured pieces
-            }
             if piece.color != self.active_color {
                 continue;  // Skip opponent's pieces
             }
             if let Some(square) = bit_scan_safe(piece.position) {
-                let own_occupancy = if piece.color == Color::White { self.white_occupancy } else { self.black_occupancy };
-                let opponent_occupancy = if piece.color == Color::White { self.black_occupancy } else { self.white_occupancy };
-                
                 // Calculate all possible moves for this piece
-                let moves = match piece.piece_type {
-                    PieceType::Pawn => {
-                        if piece.color == Color::White {
-                            // Forward moves - only if square is empty
-                            let one_step = (piece.position << 8) & !all_occupancy;
-                            // Double move only allowed from starting rank and if both squares are empty
-                            let two_step = if square >= 8 && square < 16 && one_step != 0 {
-                                (one_step << 8) & !all_occupancy
-                            } else {
-                                0
-                            };
-                            // Diagonal captures - ONLY if there's an opponent piece to capture
-                            let diagonal_captures = game.pawn_attacks.white_diagonal_moves[square] & opponent_occupancy;
-                            // En passant captures - only if pawn is on rank 5 (squares 32-39)
-                            let en_passant_captures = if let Some(ep_square) = self.en_passant {
-                                if square >= 32 && square < 40 {  // Only on rank 5
-                                    game.pawn_attacks.white_diagonal_moves[square] & ep_square
-                                } else {
-                                    0
-                                }
-                            } else {
-                                0
-                            };
-                            // Combine all legal moves
-                            one_step | two_step | diagonal_captures | en_passant_captures
-                        } else {
-                            // Forward moves - only if square is empty
-                            let one_step = (piece.position >> 8) & !all_occupancy;
-                            // Double move only allowed from starting rank and if both squares are empty
-                            let two_step = if square >= 48 && square < 56 && one_step != 0 {
-                                (one_step >> 8) & !all_occupancy
-                            } else {
-                                0
-                            };
-                            // Diagonal captures - ONLY if there's an opponent piece to capture
-                            let diagonal_captures = game.pawn_attacks.black_diagonal_moves[square] & opponent_occupancy;
-                            // En passant captures - only if pawn is on rank 4 (squares 24-31)
-                            let en_passant_captures = if let Some(ep_square) = self.en_passant {
-                                if square >= 24 && square < 32 {  // Only on rank 4
-                                    game.pawn_attacks.black_diagonal_moves[square] & ep_square
-                                } else {
-                                    0
-                                }
-                            } else {
-                                0
-                            };
-                            // Combine all legal moves
-                            one_step | two_step | diagonal_captures | en_passant_captures
-                        }
-                    },
-                    PieceType::Knight => {
-                        let attacks = game.move_gen_tables.knight_attacks[square];
-                        // Allow moves to empty squares or squares with opponent pieces
-                        attacks & !own_occupancy
-                    },
-                    PieceType::Bishop => {
-                        let attacks = game.rays.get_bishop_attacks(square, all_occupancy, piece.color, 0);
-                        // Allow moves to empty squares or squares with opponent pieces
-                        attacks & !own_occupancy
-                    },
-                    PieceType::Rook => {
-                        let attacks = game.rays.get_rook_attacks(square, all_occupancy);
-                        // Allow moves to empty squares or squares with opponent pieces
-                        attacks & !own_occupancy
-                    },
-                    PieceType::Queen => {
-                        let bishop_attacks = game.rays.get_bishop_attacks(square, all_occupancy, piece.color, 0);
-                        let rook_attacks = game.rays.get_rook_attacks(square, all_occupancy);
-                        // Allow moves to empty squares or squares with opponent pieces
-                        (bishop_attacks | rook_attacks) & !own_occupancy
-                    },
-                    PieceType::King => {
-                        let attacks = game.move_gen_tables.king_attacks[square];
-                        // Allow moves to empty squares or squares with opponent pieces
-                        attacks & !own_occupancy
-                    },
-                };
+                let moves = self.pseudo_legal_targets(game, piece, square);
 
                 // Filter out moves that would leave the king in check
                 let mut legal_moves = 0u64;
                 for to_square in extract_bits(moves) {
-                    let mut test_position = self.clone();
-                    let from_bitboard = 1u64 << square;
-                    let to_bitboard = 1u64 << to_square;
-                    
-                    // Update piece position
-                    test_position.pieces[i].position = to_bitboard;
-                    
-                    // Update occupancy bitboards
-                    if piece.color == Color::White {
-                        test_position.white_occupancy &= !from_bitboard;
-                        test_position.white_occupancy |= to_bitboard;
-                    } else {
-                        test_position.black_occupancy &= !from_bitboard;
-                        test_position.black_occupancy |= to_bitboard;
-                    }
-                    
-                    // If there was a capture, remove the captured piece
-                    if let Some(captured_idx) = test_position.squares[to_square as usize].get_piece_index() {
-                        test_position.pieces[captured_idx].position = 0;
-                        if test_position.pieces[captured_idx].color == Color::White {
-                            test_position.white_occupancy &= !to_bitboard;
-                        } else {
-                            test_position.black_occupancy &= !to_bitboard;
-                        }
-                    }
-                    
-                    // Update squares array
-                    test_position.squares[square as usize] = Square::Empty;
-                    test_position.squares[to_square as usize] = Square::Occupied(i);
-                    
-                    // Save the original active color
-                    let original_active_color = test_position.active_color;
-                    // Set active color to the moving piece's color to check if that side's king is in check
-                    test_position.active_color = piece.color;
-                    
-                    // If this move doesn't leave the king in check, it's legal
-                    if !test_position.is_in_check(game) {
-                        legal_moves |= to_bitboard;
+                    if self.keeps_king_safe(game, i, to_square) {
+                        legal_moves |= 1u64 << to_square;
                     }
-                    
-                    // Restore active color
-                    test_position.active_color = original_active_color;
                 }
                 
                 self.piece_legal_moves[i] = legal_moves;
             }
         }
+
+        self.update_castling_paths(game);
+        // Castling joins the king's moves, to the square it is written to
+        if let Some(king) = self.king_square(self.active_color).and_then(|square| self.squares[square].get_piece_index()) {
+            for castling in self.legal_castlings(game) {
+                self.piece_legal_moves[king] |= 1u64 << self.castling_target(&castling);
+            }
+        }
+    }
+
+    /// The castling moves the side to move may make, going by the attacked
+    /// paths worked out by the last `update_all_legal_moves`.
+    pub(crate) fn legal_castlings(&self, game: &Game) -> Vec<Castling> {
+        let color = self.active_color;
+        [CastlingSide::Kingside, CastlingSide::Queenside].into_iter()
+            .map(|side| *self.castling(color, side))
+            .filter(|castling| can_castle(self, color, castling.side) && self.castling_keeps_king_safe(game, castling))
+            .collect()
+    }
+
+    /// Works out for each castling move still allowed whether the opponent
+    /// attacks a square the king stands on, crosses or lands on.
+    fn update_castling_paths(&mut self, game: &Game) {
+        for castling in self.castling {
+            let attacked = self.castling_rights.contains(castling.right) && {
+                let opponent = if castling.color == Color::White { Color::Black } else { Color::White };
+                self.attackers(game, castling.king_path(), opponent) != 0
+            };
+            self.set_castling_path_attacked(&castling, attacked);
+        }
+    }
+
+    /// Whether the king is safe once castled. Only Chess960 needs the move
+    /// played: there the rook may leave a square that shielded the king's
+    /// destination along the first rank.
+    fn castling_keeps_king_safe(&self, game: &Game, castling: &Castling) -> bool {
+        if !self.chess960 {
+            return true;
+        }
+        let mut castled = self.clone();
+        castled.castle(castling);
+        !castled.is_in_check(game)
+    }
+
+    /// The position with piece `index` moved to `to`, taking what stands
+    /// there and, for a pawn moving to the en passant square, the pawn beside
+    /// it. Only the placement changes: the side to move, castling rights,
+    /// clocks and legal moves stay as they were, so this is for testing
+    /// attacks rather than for playing moves.
+    pub(crate) fn with_piece_moved(&self, index: usize, to: usize) -> Position {
+        let mut test_position = self.clone();
+        let piece = self.pieces[index];
+        let from = bit_scan(piece.position);
+        let from_bitboard = 1u64 << from;
+        let to_bitboard = 1u64 << to;
+
+        // If there was a capture, remove the captured piece
+        test_position.remove_piece(to);
+
+        // En passant also removes the pawn beside the mover,
+        // which may have been shielding the king along the rank
+        if piece.piece_type == PieceType::Pawn && self.en_passant == Some(to_bitboard) {
+            let captured_square = if piece.color == Color::White { to - 8 } else { to + 8 };
+            test_position.remove_piece(captured_square);
+        }
+
+        // Update piece position; a capture may have moved it in the list
+        let mover = test_position.squares[from].get_piece_index().unwrap_or(index);
+        test_position.pieces[mover].position = to_bitboard;
+        if piece.piece_type == PieceType::King {
+            test_position.king_squares[piece.color as usize] = Some(to);
+        }
+
+        // Update occupancy bitboards
+        if piece.color == Color::White {
+            test_position.white_occupancy &= !from_bitboard;
+            test_position.white_occupancy |= to_bitboard;
+        } else {
+            test_position.black_occupancy &= !from_bitboard;
+            test_position.black_occupancy |= to_bitboard;
+        }
+
+        // Update squares array
+        test_position.squares[from] = Square::Empty;
+        test_position.squares[to] = Square::Occupied(mover);
+        test_position
+    }
+
+    /// Whether moving piece `index` to `to` leaves its own king out of check.
+    pub(crate) fn keeps_king_safe(&self, game: &Game, index: usize, to: usize) -> bool {
+        let mut test_position = self.with_piece_moved(index, to);
+        // Set active color to the moving piece's color to check if that side's king is in check
+        test_position.active_color = self.pieces[index].color;
+        !test_position.is_in_check(game)
+    }
+
+    /// Squares `piece`, standing on `square`, could move to if its own king
+    /// were not in danger: empty squares and enemy pieces it reaches, pawn
+    /// pushes and en passant. Castling is not included.
+    pub(crate) fn pseudo_legal_targets(&self, game: &Game, piece: &Piece, square: usize) -> Bitboard {
+        let all_occupancy = self.white_occupancy | self.black_occupancy;
+        let own_occupancy = if piece.color == Color::White { self.white_occupancy } else { self.black_occupancy };
+        let opponent_occupancy = if piece.color == Color::White { self.black_occupancy } else { self.white_occupancy };
+        
+        match piece.piece_type {
+            PieceType::Pawn => {
+                if piece.color == Color::White {
+                    // Forward moves - only if square is empty
+                    let one_step = (piece.position << 8) & !all_occupancy;
+                    // Double move only allowed from starting rank and if both squares are empty
+                    let two_step = if square >= 8 && square < 16 && one_step != 0 {
+                        (one_step << 8) & !all_occupancy
+                    } else {
+                        0
+                    };
+                    // Diagonal captures - ONLY if there's an opponent piece to capture
+                    let diagonal_captures = game.pawn_attacks.white_diagonal_moves[square] & opponent_occupancy;
+                    // En passant captures - only if pawn is on rank 5 (squares 32-39)
+                    let en_passant_captures = if let Some(ep_square) = self.en_passant {
+                        if square >= 32 && square < 40 {  // Only on rank 5
+                            game.pawn_attacks.white_diagonal_moves[square] & ep_square
+                        } else {
+                            0
+                        }
+                    } else {
+                        0
+                    };
+                    // Combine all legal moves
+                    one_step | two_step | diagonal_captures | en_passant_captures
+                } else {
+                    // Forward moves - only if square is empty
+                    let one_step = (piece.position >> 8) & !all_occupancy;
+                    // Double move only allowed from starting rank and if both squares are empty
+                    let two_step = if square >= 48 && square < 56 && one_step != 0 {
+                        (one_step >> 8) & !all_occupancy
+                    } else {
+                        0
+                    };
+                    // Diagonal captures - ONLY if there's an opponent piece to capture
+                    let diagonal_captures = game.pawn_attacks.black_diagonal_moves[square] & opponent_occupancy;
+                    // En passant captures - only if pawn is on rank 4 (squares 24-31)
+                    let en_passant_captures = if let Some(ep_square) = self.en_passant {
+                        if square >= 24 && square < 32 {  // Only on rank 4
+                            game.pawn_attacks.black_diagonal_moves[square] & ep_square
+                        } else {
+                            0
+                        }
+                    } else {
+                        0
+                    };
+                    // Combine all legal moves
+                    one_step | two_step | diagonal_captures | en_passant_captures
+                }
+            },
+            PieceType::Knight => {
+                let attacks = game.move_gen_tables.knight_attacks[square];
+                // Allow moves to empty squares or squares with opponent pieces
+                attacks & !own_occupancy
+            },
+            PieceType::Bishop => {
+                let attacks = game.rays.get_bishop_attacks(square, all_occupancy, piece.color, 0);
+                // Allow moves to empty squares or squares with opponent pieces
+                attacks & !own_occupancy
+            },
+            PieceType::Rook => {
+                let attacks = game.rays.get_rook_attacks(square, all_occupancy);
+                // Allow moves to empty squares or squares with opponent pieces
+                attacks & !own_occupancy
+            },
+            PieceType::Queen => {
+                let bishop_attacks = game.rays.get_bishop_attacks(square, all_occupancy, piece.color, 0);
+                let rook_attacks = game.rays.get_rook_attacks(square, all_occupancy);
+                // Allow moves to empty squares or squares with opponent pieces
+                (bishop_attacks | rook_attacks) & !own_occupancy
+            },
+            PieceType::King => {
+                let attacks = game.move_gen_tables.king_attacks[square];
+                // Allow moves to empty squares or squares with opponent pieces
+                attacks & !own_occupancy
+            },
+        }
     }
 
     pub fn move_piece(&mut self, piece_position: Bitboard, new_position: usize, game: &Game) {
@@ -494,16 +821,10 @@ impl Position {
         let old_pos_bit = piece_position;
         let piece_color = self.pieces[piece_index].color;
 
-        // First handle capture if there is one
-        if let Square::Occupied(captured_idx) = self.squares[new_position] {
-            // Mark the captured piece as captured by setting its position to 0
-            self.pieces[captured_idx].position = 0;
-            // Remove the captured piece from the appropriate occupancy bitboard
-            match self.pieces[captured_idx].color {
-                Color::White => self.white_occupancy &= !new_pos_bit,
-                Color::Black => self.black_occupancy &= !new_pos_bit,
-            }
-        }
+        // First handle capture if there is one; the moving piece may take
+        // the captured piece's place in the list
+        self.remove_piece(new_position);
+        let piece_index = self.squares[square_index].get_piece_index().unwrap_or(piece_index);
 
         // Update squares array
         self.squares[square_index] = Square::Empty;
@@ -519,6 +840,9 @@ impl Position {
             }
         }
         self.pieces[piece_index].position = new_pos_bit;
+        if self.pieces[piece_index].piece_type == PieceType::King {
+            self.king_squares[piece_color as usize] = Some(new_position);
+        }
 
         // Check if this is a pawn making a two-square move
         let is_pawn_double_move = {
@@ -549,36 +873,8 @@ impl Position {
         }
 
         // Update castling rights if king or rook moves
-        let moving_piece = &self.pieces[piece_index];
-        match (moving_piece.piece_type, moving_piece.color) {
-            (PieceType::King, Color::White) => {
-                self.castling_rights &= !(CastlingRights::WHITEKINGSIDE | CastlingRights::WHITEQUEENSIDE);
-                self.white_king_moved = true;
-            }
-            (PieceType::King, Color::Black) => {
-                self.castling_rights &= !(CastlingRights::BLACKKINGSIDE | CastlingRights::BLACKQUEENSIDE);
-                self.black_king_moved = true;
-            }
-            (PieceType::Rook, Color::White) => {
-                if square_index == 0 {  // a1
-                    self.castling_rights &= !CastlingRights::WHITEQUEENSIDE;
-                    self.white_queenside_rook_moved = true;
-                } else if square_index == 7 {  // h1
-                    self.castling_rights &= !CastlingRights::WHITEKINGSIDE;
-                    self.white_kingside_rook_moved = true;
-                }
-            }
-            (PieceType::Rook, Color::Black) => {
-                if square_index == 56 {  // a8
-                    self.castling_rights &= !CastlingRights::BLACKQUEENSIDE;
-                    self.black_queenside_rook_moved = true;
-                } else if square_index == 63 {  // h8
-                    self.castling_rights &= !CastlingRights::BLACKKINGSIDE;
-                    self.black_kingside_rook_moved = true;
-                }
-            }
-            _ => {}
-        }
+        let moving_piece = self.pieces[piece_index];
+        self.update_castling_rights(moving_piece, square_index);
 
         // Update all legal moves after the move
         self.update_all_legal_moves(game);
@@ -592,29 +888,194 @@ impl Position {
                 continue;
             }
             let piece = &self.pieces[i];
-            if piece.position == 0 {
-                continue;  // Skip pieces that have been captured
-            }
             if let Some(from_square) = bit_scan_safe(piece.position) {
                 for to_square in extract_bits(*legal_moves_bitboard) {
-                    // Encode move: from_square in lower 6 bits, to_square in next 6 bits
-                    let mut mov = (from_square as u64) | ((to_square as u64) << 6);
-                    
-                    // Set promotion flag for pawns moving to the last rank
-                    if piece.piece_type == PieceType::Pawn {
-                        let to_rank = to_square / 8;
-                        if (piece.color == Color::White && to_rank == 7) || 
-                           (piece.color == Color::Black && to_rank == 0) {
-                            mov |= 1 << 12;  // Set promotion flag
-                        }
-                    }
-                    moves.push(mov);
+                    push_moves(&mut moves, piece, from_square, to_square);
                 }
             }
         }
         moves
     }
 
+    /// All legal moves of the side to move in standard algebraic notation.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game providing the attack tables
+    ///
+    /// # Returns
+    ///
+    /// * The moves in SAN, e.g. `Nf3` or `exd5`, in move generation order
+    pub fn legal_moves_san(&self, game: &Game) -> Vec<String> {
+        let board = crate::board::Board::with_tables(self.clone(), game);
+        board.legal_moves().into_iter().map(|mv| crate::notation::to_san(&board, mv)).collect()
+    }
+
+    /// Removes the castling rights a king or rook move from `from` gives up.
+    fn update_castling_rights(&mut self, piece: Piece, from: usize) {
+        for castling in self.castling.into_iter().filter(|castling| castling.color == piece.color) {
+            match piece.piece_type {
+                PieceType::King => {
+                    self.castling_rights &= !castling.right;
+                    self.set_king_moved(piece.color);
+                }
+                PieceType::Rook if from == castling.rook_from => {
+                    self.castling_rights &= !castling.right;
+                    self.set_rook_moved(&castling);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the king of `color` has moved, which rules out castling.
+    pub fn king_moved(&self, color: Color) -> bool {
+        match color {
+            Color::White => self.white_king_moved,
+            Color::Black => self.black_king_moved,
+        }
+    }
+
+    pub(crate) fn set_king_moved(&mut self, color: Color) {
+        match color {
+            Color::White => self.white_king_moved = true,
+            Color::Black => self.black_king_moved = true,
+        }
+    }
+
+    /// Whether the rook that `castling` uses has moved.
+    pub fn rook_moved(&self, castling: &Castling) -> bool {
+        match (castling.color, castling.side) {
+            (Color::White, CastlingSide::Kingside) => self.white_kingside_rook_moved,
+            (Color::White, CastlingSide::Queenside) => self.white_queenside_rook_moved,
+            (Color::Black, CastlingSide::Kingside) => self.black_kingside_rook_moved,
+            (Color::Black, CastlingSide::Queenside) => self.black_queenside_rook_moved,
+        }
+    }
+
+    pub(crate) fn set_rook_moved(&mut self, castling: &Castling) {
+        match (castling.color, castling.side) {
+            (Color::White, CastlingSide::Kingside) => self.white_kingside_rook_moved = true,
+            (Color::White, CastlingSide::Queenside) => self.white_queenside_rook_moved = true,
+            (Color::Black, CastlingSide::Kingside) => self.black_kingside_rook_moved = true,
+            (Color::Black, CastlingSide::Queenside) => self.black_queenside_rook_moved = true,
+        }
+    }
+
+    /// Whether the opponent attacks the squares the king crosses when castling.
+    pub fn castling_path_attacked(&self, castling: &Castling) -> bool {
+        match (castling.color, castling.side) {
+            (Color::White, CastlingSide::Kingside) => self.white_kingside_path_attacked,
+            (Color::White, CastlingSide::Queenside) => self.white_queenside_path_attacked,
+            (Color::Black, CastlingSide::Kingside) => self.black_kingside_path_attacked,
+            (Color::Black, CastlingSide::Queenside) => self.black_queenside_path_attacked,
+        }
+    }
+
+    fn set_castling_path_attacked(&mut self, castling: &Castling, attacked: bool) {
+        match (castling.color, castling.side) {
+            (Color::White, CastlingSide::Kingside) => self.white_kingside_path_attacked = attacked,
+            (Color::White, CastlingSide::Queenside) => self.white_queenside_path_attacked = attacked,
+            (Color::Black, CastlingSide::Kingside) => self.black_kingside_path_attacked = attacked,
+            (Color::Black, CastlingSide::Queenside) => self.black_queenside_path_attacked = attacked,
+        }
+    }
+
+    /// The castling move of `color` on `side` in this position.
+    pub fn castling(&self, color: Color, side: CastlingSide) -> &Castling {
+        &self.castling[Castling::index(color, side)]
+    }
+
+    /// The square a castling move is written to: where the king lands, or
+    /// in Chess960 the rook's square, since the king may not move at all or
+    /// move just one square.
+    pub fn castling_target(&self, castling: &Castling) -> usize {
+        if self.chess960 { castling.rook_from } else { castling.king_to }
+    }
+
+    /// The castling move a king move from `from` to `to` stands for, if any.
+    /// Only a castling that is still allowed, with its rook on its square,
+    /// counts; otherwise the king is just moving, perhaps taking a piece.
+    pub fn castling_move(&self, color: Color, from: usize, to: usize) -> Option<Castling> {
+        self.castling.iter()
+            .find(|castling| castling.color == color && castling.king_from == from && self.castling_target(castling) == to)
+            .filter(|castling| self.castling_rights.contains(castling.right))
+            .filter(|castling| self.is_piece_on(castling.rook_from, color, PieceType::Rook))
+            .copied()
+    }
+
+    /// Marks the position as from a Chess960 game or not, which changes how
+    /// its castling moves are written, and generates its moves again.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game providing the attack tables
+    /// * `chess960` - Whether castling is written as the king taking its own
+    ///   rook; positions with other starting files than the standard ones
+    ///   stay Chess960 positions
+    pub fn set_chess960(&mut self, game: &Game, chess960: bool) {
+        self.chess960 = chess960 || self.castling != STANDARD_CASTLING;
+        self.legal_moves_key = None;
+        self.update_all_legal_moves(game);
+    }
+
+    /// The file of `color`'s king if it stands on its first rank.
+    fn home_king_file(&self, color: Color) -> Option<usize> {
+        let back_rank = if color == Color::White { 0 } else { 56 };
+        (back_rank..back_rank + 8).find(|&square| self.is_piece_on(square, color, PieceType::King))
+            .map(|square| square % 8)
+    }
+
+    /// The file of `color`'s rook on its first rank furthest from the king
+    /// on `side`, the one X-FEN's K and Q castle with.
+    fn outermost_rook_file(&self, color: Color, side: CastlingSide) -> Option<usize> {
+        let king_file = self.home_king_file(color)?;
+        let back_rank = if color == Color::White { 0 } else { 56 };
+        let is_rook = |file: &usize| self.is_piece_on(back_rank + file, color, PieceType::Rook);
+        match side {
+            CastlingSide::Kingside => (king_file + 1..8).rev().find(is_rook),
+            CastlingSide::Queenside => (0..king_file).find(is_rook),
+        }
+    }
+
+    fn is_piece_on(&self, square: usize, color: Color, piece_type: PieceType) -> bool {
+        self.squares.get(square)
+            .and_then(Square::get_piece_index)
+            .is_some_and(|index| self.pieces[index].color == color && self.pieces[index].piece_type == piece_type)
+    }
+
+    /// Takes the piece on `square` off the board. The last piece in `pieces`
+    /// moves into its slot, keeping the list free of captured pieces.
+    ///
+    /// # Arguments
+    ///
+    /// * `square` - The square to clear (0-63)
+    ///
+    /// # Returns
+    ///
+    /// * The removed piece, or `None` if the square was empty
+    pub fn remove_piece(&mut self, square: usize) -> Option<Piece> {
+        let index = self.squares[square].get_piece_index()?;
+        let bitboard = 1u64 << square;
+        self.white_occupancy &= !bitboard;
+        self.black_occupancy &= !bitboard;
+        self.squares[square] = Square::Empty;
+
+        let piece = self.pieces.swap_remove(index);
+        if index < self.piece_legal_moves.len() {
+            self.piece_legal_moves.swap_remove(index);
+        }
+        if let Some(moved) = self.pieces.get(index) {
+            self.squares[bit_scan(moved.position)] = Square::Occupied(index);
+        }
+        Some(piece)
+    }
+
+    /// The piece with the given id, if it is still on the board.
+    pub fn piece_by_id(&self, id: PieceId) -> Option<&Piece> {
+        self.pieces.iter().find(|piece| piece.id == id)
+    }
+
     /// Make a move on the board and return the new position
     pub fn make_move(&mut self, mov: u64) {
         let from_square = mov & 0x3F;
@@ -623,20 +1084,47 @@ impl Position {
         let to_bitboard = 1u64 << to_square;
 
         // Find the piece being moved
-        if let Some(piece_idx) = self.pieces.iter().position(|p| p.position == from_bitboard) {
-            // Handle capture if there is one
-            if let Square::Occupied(captured_idx) = self.squares[to_square as usize] {
-                // Remove the captured piece from the appropriate occupancy bitboard
-                match self.pieces[captured_idx].color {
-                    Color::White => self.white_occupancy &= !to_bitboard,
-                    Color::Black => self.black_occupancy &= !to_bitboard,
-                }
-                // Mark the captured piece as captured by setting its position to 0
-                self.pieces[captured_idx].position = 0;
+        if let Some(piece_idx) = self.squares[from_square as usize].get_piece_index() {
+            // Castling moves the king and rook together, in Chess960 onto
+            // each other's squares, and gives up both castling rights
+            let piece = self.pieces[piece_idx];
+            let castling = self.castling_move(piece.color, from_square as usize, to_square as usize)
+                .filter(|_| piece.piece_type == PieceType::King);
+            if let Some(castling) = castling {
+                self.castle(&castling);
+                self.update_castling_rights(piece, from_square as usize);
+                self.en_passant = None;
+                self.count_move(piece.color, false);
+                self.active_color = if piece.color == Color::White { Color::Black } else { Color::White };
+                return;
+            }
+
+            let is_pawn = self.pieces[piece_idx].piece_type == PieceType::Pawn;
+            let is_capture = self.squares[to_square as usize].get_piece_index().is_some()
+                || (is_pawn && self.en_passant == Some(to_bitboard));
+            self.count_move(piece.color, is_pawn || is_capture);
+            if is_pawn && self.en_passant == Some(to_bitboard) {
+                // En passant takes the pawn that passed over the target square
+                let captured_square = if self.pieces[piece_idx].color == Color::White { to_square - 8 } else { to_square + 8 };
+                self.remove_piece(captured_square as usize);
             }
+            // A double pawn push can be taken en passant on the next move only
+            self.en_passant = if is_pawn && from_square.abs_diff(to_square) == 16 {
+                Some(1u64 << ((from_square + to_square) / 2))
+            } else {
+                None
+            };
+
+            // Handle capture if there is one; the moving piece may take the
+            // captured piece's place in the list
+            self.remove_piece(to_square as usize);
+            let piece_idx = self.squares[from_square as usize].get_piece_index().unwrap_or(piece_idx);
 
             // Update piece position
             self.pieces[piece_idx].position = to_bitboard;
+            if self.pieces[piece_idx].piece_type == PieceType::King {
+                self.king_squares[self.pieces[piece_idx].color as usize] = Some(to_square as usize);
+            }
 
             // Update squares
             self.squares[from_square as usize] = Square::Empty;
@@ -653,9 +1141,15 @@ impl Position {
             }
 
             // Handle promotions
-            if mov & (1 << 12) != 0 {
-                // Promote to queen
-                self.pieces[piece_idx].piece_type = PieceType::Queen;
+            if let Some(piece_type) = Move::promotion_of(mov) {
+                self.pieces[piece_idx].piece_type = piece_type;
+            }
+
+            // Any king or rook move or rook capture gives up castling rights
+            let piece = self.pieces[piece_idx];
+            self.update_castling_rights(piece, from_square as usize);
+            for castling in self.castling.into_iter().filter(|castling| castling.rook_from == to_square as usize) {
+                self.castling_rights &= !castling.right;
             }
 
             // Switch active color
@@ -666,56 +1160,287 @@ impl Position {
         }
     }
 
+    /// Advances the move clocks for a move by `color`: the halfmove clock
+    /// restarts on pawn moves and captures, and the fullmove number goes
+    /// up once Black has moved.
+    fn count_move(&mut self, color: Color, resets_clock: bool) {
+        self.halfmove_clock = if resets_clock { 0 } else { self.halfmove_clock + 1 };
+        if color == Color::Black {
+            self.fullmove_number += 1;
+        }
+    }
+
+    /// Puts the king and rook of a castling move on their squares. Both
+    /// leave before either lands, since in Chess960 one may land where the
+    /// other stood; a missing rook leaves the king to move alone.
+    pub(crate) fn castle(&mut self, castling: &Castling) {
+        let king = self.squares[castling.king_from].get_piece_index();
+        let rook = self.squares[castling.rook_from].get_piece_index()
+            .filter(|&rook| self.pieces[rook].piece_type == PieceType::Rook && self.pieces[rook].color == castling.color);
+        let moves = [(king, castling.king_from, castling.king_to), (rook, castling.rook_from, castling.rook_to)];
+        let (mut from, mut to) = (0, 0);
+        for (_, origin, _) in moves.iter().filter(|(index, ..)| index.is_some()) {
+            self.squares[*origin] = Square::Empty;
+            from |= 1u64 << origin;
+        }
+        for (index, _, target) in moves {
+            if let Some(index) = index {
+                self.squares[target] = Square::Occupied(index);
+                self.pieces[index].position = 1u64 << target;
+                to |= 1u64 << target;
+            }
+        }
+        if king.is_some() {
+            self.king_squares[castling.color as usize] = Some(castling.king_to);
+        }
+        match castling.color {
+            Color::White => self.white_occupancy = (self.white_occupancy & !from) | to,
+            Color::Black => self.black_occupancy = (self.black_occupancy & !from) | to,
+        }
+    }
+
     /// Check if the current side to move is in check
     pub fn is_in_check(&self, game: &Game) -> bool {
-        // Find the king of the current side
-        let king = self.pieces.iter().find(|p| {
-            p.piece_type == PieceType::King && p.color == self.active_color
+        self.checkers(game) != 0
+    }
+
+    /// Bitboard of the opponent's pieces giving check to the side to move
+    pub fn checkers(&self, game: &Game) -> Bitboard {
+        let opponent_color = if self.active_color == Color::White { Color::Black } else { Color::White };
+        match self.king_square(self.active_color) {
+            Some(square) => self.attackers(game, 1u64 << square, opponent_color),
+            None => 0,
+        }
+    }
+
+    /// Whether `mov` checks the enemy king, without making it.
+    pub fn gives_check(&self, game: &Game, mov: u64) -> bool {
+        self.check_kind(game, mov).is_some()
+    }
+
+    /// How `mov` checks the enemy king, worked out before making it.
+    ///
+    /// The moved piece checks directly if it attacks the king from its new
+    /// square, as its promoted type or, for castling, as the rook. It checks
+    /// by discovery if the squares it leaves, including an en passant
+    /// victim's, open a line from the king to one of its own sliders, found
+    /// by looking out from the king along the lines through them. The move
+    /// is assumed to be legal.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game holding the attack tables
+    /// * `mov` - The packed move
+    ///
+    /// # Returns
+    ///
+    /// * The kind of check, `None` if the move gives none or there is no
+    ///   piece on its origin
+    pub fn check_kind(&self, game: &Game, mov: u64) -> Option<CheckKind> {
+        let from_square = (mov & 0x3F) as usize;
+        let to_square = ((mov >> 6) & 0x3F) as usize;
+        let mover = self.pieces[self.squares[from_square].get_piece_index()?];
+        let enemy = if mover.color == Color::White { Color::Black } else { Color::White };
+        let king_square = self.king_square(enemy)?;
+        let king_bitboard = 1u64 << king_square;
+        let to_bitboard = 1u64 << to_square;
+
+        let mut occupancy = ((self.white_occupancy | self.black_occupancy) & !mover.position) | to_bitboard;
+        let mut moved = mover.position;
+        if mover.piece_type == PieceType::Pawn && self.en_passant == Some(to_bitboard) {
+            occupancy &= !(if mover.color == Color::White { to_bitboard >> 8 } else { to_bitboard << 8 });
+        }
+
+        // The piece that ends up checking directly: the mover, or the rook
+        // when the king castles
+        let mut checker = Piece {
+            position: to_bitboard,
+            piece_type: Move::promotion_of(mov).unwrap_or(mover.piece_type),
+            ..mover
+        };
+        if mover.piece_type == PieceType::King {
+            if let Some(castling) = self.castling_move(mover.color, from_square, to_square) {
+                occupancy = (self.white_occupancy | self.black_occupancy) & !(1u64 << castling.king_from) & !(1u64 << castling.rook_from)
+                    | (1u64 << castling.king_to) | (1u64 << castling.rook_to);
+                moved |= 1u64 << castling.rook_from;
+                checker = Piece { position: 1u64 << castling.rook_to, piece_type: PieceType::Rook, ..mover };
+            }
+        }
+        let direct = checker.attacks(game, occupancy) & king_bitboard != 0;
+
+        // Sliders the king sees through the squares the moved pieces leave
+        let straight = game.rays.get_rook_xray_attacks(king_square, occupancy | moved, moved);
+        let diagonal = game.rays.get_bishop_xray_attacks(king_square, occupancy | moved, moved);
+        let discovered = self.pieces.iter()
+            .filter(|p| p.color == mover.color && p.position & moved == 0)
+            .any(|p| match p.piece_type {
+                PieceType::Rook => p.position & straight != 0,
+                PieceType::Bishop => p.position & diagonal != 0,
+                PieceType::Queen => p.position & (straight | diagonal) != 0,
+                _ => false,
+            });
+
+        match (direct, discovered) {
+            (true, true) => Some(CheckKind::Double),
+            (true, false) => Some(CheckKind::Direct),
+            (false, true) => Some(CheckKind::Discovered),
+            (false, false) => None,
+        }
+    }
+
+    /// Whether `color` could still mate by some series of legal moves,
+    /// however unlikely, as FIDE asks before scoring a flag as a loss.
+    ///
+    /// A pawn, rook or queen, two knights, or bishops on both square colors
+    /// can always mate. A lone knight needs an enemy piece to hem the king
+    /// in, and bishops on one color need an enemy pawn, knight or bishop on
+    /// the other color; a bare king never mates.
+    pub fn can_mate(&self, color: Color) -> bool {
+        const LIGHT_SQUARES: Bitboard = 0x55AA_55AA_55AA_55AA;
+        let (ours, theirs): (Vec<&Piece>, Vec<&Piece>) = self.pieces.iter()
+            .filter(|p| p.piece_type != PieceType::King)
+            .partition(|p| p.color == color);
+        if ours.iter().any(|p| matches!(p.piece_type, PieceType::Pawn | PieceType::Rook | PieceType::Queen)) {
+            return true;
+        }
+        let knights = ours.iter().filter(|p| p.piece_type == PieceType::Knight).count();
+        let bishops = ours.iter().fold(0, |squares, p| if p.piece_type == PieceType::Bishop { squares | p.position } else { squares });
+        match (knights, bishops) {
+            (0, 0) => false,
+            (1, 0) => !theirs.is_empty(),
+            (0, _) if bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0 => {
+                let bishop_color = if bishops & LIGHT_SQUARES != 0 { LIGHT_SQUARES } else { !LIGHT_SQUARES };
+                theirs.iter().any(|p| match p.piece_type {
+                    PieceType::Pawn | PieceType::Knight => true,
+                    PieceType::Bishop => p.position & bishop_color == 0,
+                    _ => false,
+                })
+            },
+            _ => true,
+        }
+    }
+
+    /// Whether neither side can mate, which ends the game in a draw.
+    pub fn is_insufficient_material(&self) -> bool {
+        !self.can_mate(Color::White) && !self.can_mate(Color::Black)
+    }
+
+    /// The square of `color`'s king, if it has one.
+    ///
+    /// The square is cached, so this normally costs a couple of lookups;
+    /// if `pieces` was changed directly and the cache no longer matches,
+    /// the king is searched for instead.
+    pub fn king_square(&self, color: Color) -> Option<usize> {
+        let cached = self.king_squares[color as usize].filter(|&square| {
+            self.squares.get(square)
+                .and_then(Square::get_piece_index)
+                .and_then(|index| self.pieces.get(index))
+                .is_some_and(|piece| piece.piece_type == PieceType::King && piece.color == color
+                    && piece.position == 1u64 << square)
         });
+        cached.or_else(|| self.find_king(color))
+    }
 
-        if let Some(king) = king {
-            if king.position == 0 {
-                return false;  // King has been captured (shouldn't happen in a valid game)
+    /// Scans the piece list for `color`'s king.
+    fn find_king(&self, color: Color) -> Option<usize> {
+        self.pieces.iter()
+            .find(|p| p.piece_type == PieceType::King && p.color == color)
+            .map(|p| bit_scan(p.position))
+    }
+
+    /// Bitboard of the pieces of color `by` attacking any of the squares in
+    /// `target`.
+    pub fn attackers(&self, game: &Game, target: Bitboard, by: Color) -> Bitboard {
+        let mut attackers = 0;
+        let all_occupancy = self.white_occupancy | self.black_occupancy;
+        for piece in self.pieces.iter().filter(|p| p.color == by) {
+            if (piece.attacks(game, all_occupancy) & target) != 0 {
+                attackers |= piece.position;
             }
-            if let Some(king_square) = bit_scan_safe(king.position) {
-                let opponent_color = if self.active_color == Color::White { Color::Black } else { Color::White };
-                
-                // Check for attacks from opponent's pieces
-                for piece in self.pieces.iter().filter(|p| p.color == opponent_color) {
-                    if piece.position == 0 {
-                        continue;  // Skip captured pieces
-                    }
-                    if let Some(piece_square) = bit_scan_safe(piece.position) {
-                        let all_occupancy = self.white_occupancy | self.black_occupancy;
-                        
-                        // Calculate attack squares based on piece type
-                        let attacks = match piece.piece_type {
-                            PieceType::Pawn => {
-                                if piece.color == Color::White {
-                                    game.pawn_attacks.white_diagonal_moves[piece_square]
-                                } else {
-                                    game.pawn_attacks.black_diagonal_moves[piece_square]
-                                }
-                            },
-                            PieceType::Knight => game.move_gen_tables.knight_attacks[piece_square],
-                            PieceType::Bishop => game.rays.get_bishop_attacks(piece_square, all_occupancy, piece.color, 0),
-                            PieceType::Rook => game.rays.get_rook_attacks(piece_square, all_occupancy),
-                            PieceType::Queen => {
-                                game.rays.get_bishop_attacks(piece_square, all_occupancy, piece.color, 0) | 
-                                game.rays.get_rook_attacks(piece_square, all_occupancy)
-                            },
-                            PieceType::King => game.move_gen_tables.king_attacks[piece_square],
-                        };
-                        
-                        // If the king's square is in the attack set, it's in check
-                        if (attacks & king.position) != 0 {
-                            return true;
-                        }
-                    }
-                }
+        }
+        attackers
+    }
+
+    /// What each side attacks, by piece type, computed in one pass over the
+    /// pieces. The maps describe this position only; compute them again
+    /// after a move.
+    pub fn attack_maps(&self, game: &Game) -> AttackMaps {
+        AttackMaps::new(self, game)
+    }
+
+    /// Static exchange evaluation: the material the side to move wins or
+    /// loses on the target square of `mov` if both sides keep capturing
+    /// there with their least valuable piece and may stop whenever
+    /// continuing would lose more.
+    ///
+    /// Sliding pieces behind a capturer join in once it has moved. Pins and
+    /// promotions are ignored, and kings only capture last since their value
+    /// makes any recapture decisive.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game holding the attack tables
+    /// * `mov` - The move to evaluate, usually a capture
+    ///
+    /// # Returns
+    ///
+    /// * The expected material gain in centipawns; 0 for a quiet move to a
+    ///   square the opponent does not attack
+    pub fn see(&self, game: &Game, mov: u64) -> i32 {
+        let from_square = (mov & 0x3F) as usize;
+        let to_square = ((mov >> 6) & 0x3F) as usize;
+        let to_bitboard = 1u64 << to_square;
+        let Some(mover) = self.squares[from_square].get_piece_index().map(|index| self.pieces[index]) else {
+            return 0;
+        };
+
+        let mut occupancy = (self.white_occupancy | self.black_occupancy) & !mover.position;
+        let mut gain = [0i32; 32];
+        if let Some(victim) = self.squares[to_square].get_piece_index().filter(|&victim| self.pieces[victim].color != mover.color) {
+            gain[0] = SEE_VALUES[self.pieces[victim].piece_type as usize];
+        } else if mover.piece_type == PieceType::Pawn && self.en_passant == Some(to_bitboard) {
+            gain[0] = SEE_VALUES[PieceType::Pawn as usize];
+            occupancy &= !(if mover.color == Color::White { to_bitboard >> 8 } else { to_bitboard << 8 });
+        }
+
+        let sliders = |types: [PieceType; 2]| self.pieces.iter()
+            .filter(|p| types.contains(&p.piece_type))
+            .fold(0, |sliders, p| sliders | p.position);
+        let (straight, diagonal) = (sliders([PieceType::Rook, PieceType::Queen]), sliders([PieceType::Bishop, PieceType::Queen]));
+        let mut attackers = self.pieces.iter()
+            .filter(|p| p.position & occupancy != 0 && p.position != to_bitboard)
+            .filter(|p| p.attacks(game, occupancy) & to_bitboard != 0)
+            .fold(0, |attackers, p| attackers | p.position);
+
+        let mut on_square = SEE_VALUES[mover.piece_type as usize];
+        let mut side = if mover.color == Color::White { Color::Black } else { Color::White };
+        let mut depth = 0;
+        while depth + 1 < gain.len() {
+            // The least valuable piece of `side` still on the board that reaches the square
+            let Some(capturer) = self.pieces.iter()
+                .filter(|p| p.color == side && p.position & attackers & occupancy != 0)
+                .min_by_key(|p| SEE_VALUES[p.piece_type as usize]) else {
+                break;
+            };
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+            // Neither side can come out ahead by continuing
+            if (-gain[depth - 1]).max(gain[depth]) < 0 {
+                break;
             }
+            // Sliders lined up behind the capturer join in once it has gone
+            attackers |= game.rays.get_rook_xray_attacks(to_square, occupancy, capturer.position) & straight
+                | game.rays.get_bishop_xray_attacks(to_square, occupancy, capturer.position) & diagonal;
+            occupancy &= !capturer.position;
+            on_square = SEE_VALUES[capturer.piece_type as usize];
+            side = if side == Color::White { Color::Black } else { Color::White };
         }
-        false
+
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
     }
 
     /// Get all capturing moves in the current position
@@ -726,9 +1451,6 @@ impl Position {
                 continue;
             }
             let piece = &self.pieces[i];
-            if piece.position == 0 {
-                continue;  // Skip pieces that have been captured
-            }
             if let Some(from_square) = bit_scan_safe(piece.position) {
                 for to_square in extract_bits(*legal_moves_bitboard) {
                     let to_bitboard = 1u64 << to_square;
@@ -736,9 +1458,7 @@ impl Position {
                     
                     // Only include moves that capture opponent pieces
                     if to_bitboard & opponent_occupancy != 0 {
-                        // Encode move: from_square in lower 6 bits, to_square in next 6 bits
-                        let mov = (from_square as u64) | ((to_square as u64) << 6);
-                        captures.push(mov);
+                        push_moves(&mut captures, piece, from_square, to_square);
                     }
                 }
             }
@@ -756,7 +1476,7 @@ impl Position {
 
     pub fn get_piece_type_at(&self, square: u64) -> Option<PieceType> {
         self.pieces.iter()
-            .find(|p| p.position == square && p.position != 0)
+            .find(|p| p.position == square)
             .map(|p| p.piece_type)
     }
 
@@ -770,26 +1490,199 @@ impl Position {
             
             // Check if there's a piece at the target square and it belongs to the opponent
             if let Some(target_idx) = self.squares[to_square as usize].get_piece_index() {
-                let target_piece = &self.pieces[target_idx];
-                // Check if the target piece exists and belongs to the opponent
-                return target_piece.position != 0 && target_piece.color != moving_piece_color;
+                // Check if the target piece belongs to the opponent
+                return self.pieces[target_idx].color != moving_piece_color;
             }
         }
         false
     }
 
     pub fn is_promotion(&self, mov: u64) -> bool {
-        mov & (1 << 12) != 0
+        mov & PROMOTION_FLAG != 0
     }
 
     pub fn get_hash(&self, game: &Game) -> u64 {
         game.zobrist.hash_position(self)
     }
+
+    /// Deduces the legal move that turned `before` into `after`.
+    ///
+    /// Only the piece placement is compared, so it does not matter how
+    /// `after` was produced or whether its side to move, castling rights and
+    /// clocks were updated. The rook of a castling move and a pawn taken en
+    /// passant may be on either square, since not every move path moves them.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - The position the move was played from
+    /// * `after` - The position after the move
+    /// * `game` - The game providing the move generation tables
+    ///
+    /// # Returns
+    ///
+    /// * The move, or `None` if no single legal move explains the difference
+    pub fn infer_move(before: &Position, after: &Position, game: &Game) -> Option<Move> {
+        let occupant = |position: &Position, square: usize| match position.squares[square] {
+            Square::Occupied(idx) => Some((position.pieces[idx].color, position.pieces[idx].piece_type)),
+            Square::Empty => None,
+        };
+
+        let mut legal = before.clone();
+        legal.update_all_legal_moves(game);
+        legal.get_all_legal_moves(game).into_iter()
+            .map(|mov| Move::from_u64(mov, before))
+            .find(|mv| {
+                let Some((color, piece_type)) = occupant(before, mv.from()) else {
+                    return false;
+                };
+
+                // Squares other than the move's own may only differ for the
+                // castling king and rook or a pawn taken en passant
+                let mut touched = vec![mv.from(), mv.to()];
+                let castling = before.castling_move(color, mv.from(), mv.to()).filter(|_| mv.is_castle());
+                if let Some(castling) = castling {
+                    if occupant(after, castling.king_to) != Some((color, PieceType::King))
+                        || occupant(after, castling.rook_to) != Some((color, PieceType::Rook)) {
+                        return false;
+                    }
+                    touched.extend([castling.king_to, castling.rook_from, castling.rook_to]);
+                } else if occupant(after, mv.from()).is_some()
+                    || occupant(after, mv.to()) != Some((color, mv.promotion().unwrap_or(piece_type))) {
+                    return false;
+                }
+                if mv.is_en_passant() {
+                    touched.push(mv.from() / 8 * 8 + mv.to() % 8);
+                }
+                (0..64).all(|square| touched.contains(&square) || occupant(before, square) == occupant(after, square))
+            })
+    }
+
+    /// Explains why moving the piece on `from` to `to` is not legal, for
+    /// rejected player moves. Only meaningful for moves that are not among
+    /// the legal moves.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game providing the attack tables
+    /// * `from` - The square the player tried to move from (0-63)
+    /// * `to` - The square the player tried to move to (0-63)
+    ///
+    /// # Returns
+    ///
+    /// * A sentence naming the rule the move breaks
+    pub fn explain_illegal_move(&self, game: &Game, from: usize, to: usize) -> String {
+        let Some(piece) = self.squares[from].get_piece_index().map(|idx| self.pieces[idx]) else {
+            return format!("There is no piece on {}", index_to_position(from));
+        };
+        if piece.color != self.active_color {
+            let side = if self.active_color == Color::White { "White" } else { "Black" };
+            return format!("Not your turn: it is {} to move", side);
+        }
+        let name = match piece.piece_type {
+            PieceType::Pawn => "pawn",
+            PieceType::Knight => "knight",
+            PieceType::Bishop => "bishop",
+            PieceType::Rook => "rook",
+            PieceType::Queen => "queen",
+            PieceType::King => "king",
+        };
+
+        if piece.piece_type == PieceType::King && from / 8 == to / 8 && from.abs_diff(to) == 2 {
+            return self.explain_illegal_castling(game, from, to);
+        }
+        if self.pseudo_legal_targets(game, &piece, from) & (1u64 << to) == 0 {
+            return match self.squares[to].get_piece_index() {
+                Some(idx) if self.pieces[idx].color == piece.color => "You cannot capture your own piece".to_string(),
+                _ => format!("A {} cannot move from {} to {}", name, index_to_position(from), index_to_position(to)),
+            };
+        }
+
+        // The piece may move there, so the move must leave the king attacked
+        if piece.piece_type == PieceType::King {
+            format!("Your king would be in check on {}", index_to_position(to))
+        } else if self.is_in_check(game) {
+            "Your king is in check and this move does not get it out of check".to_string()
+        } else {
+            format!("That {} is pinned to your king", name)
+        }
+    }
+
+    /// Explains why the king on `from` cannot castle towards `to`.
+    fn explain_illegal_castling(&self, game: &Game, from: usize, to: usize) -> String {
+        let side = if to > from { CastlingSide::Kingside } else { CastlingSide::Queenside };
+        let castling = Castling::standard(self.active_color, side);
+        if !self.castling_rights.contains(castling.right) || self.king_moved(castling.color) || self.rook_moved(castling) {
+            return "You can no longer castle on that side: the king or rook has moved".to_string();
+        }
+
+        if castling.path & (self.white_occupancy | self.black_occupancy) != 0 {
+            return "Castling is blocked by pieces in the way".to_string();
+        }
+        if self.is_in_check(game) {
+            return "You cannot castle out of check".to_string();
+        }
+        let opponent = if self.active_color == Color::White { Color::Black } else { Color::White };
+        if self.attackers(game, castling.king_path(), opponent) != 0 {
+            return "The castling path is attacked".to_string();
+        }
+        "Castling is not available in this position".to_string()
+    }
+
+    /// Zobrist hash extended with the king and rook "moved" flags, which
+    /// castling legality depends on but the hash leaves out
+    pub(crate) fn legal_moves_key(&self, game: &Game) -> u64 {
+        let moved = [
+            self.white_king_moved,
+            self.black_king_moved,
+            self.white_kingside_rook_moved,
+            self.white_queenside_rook_moved,
+            self.black_kingside_rook_moved,
+            self.black_queenside_rook_moved,
+        ];
+        let flags = moved.iter().enumerate().fold(0u64, |acc, (bit, &set)| acc | ((set as u64) << bit));
+        self.get_hash(game) ^ flags.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+/// Adds the packed move(s) for `piece` going from `from_square` to
+/// `to_square`: one move normally, or one per promotion piece for a pawn
+/// reaching the last rank.
+pub(crate) fn push_moves(moves: &mut Vec<u64>, piece: &Piece, from_square: usize, to_square: usize) {
+    let to_rank = to_square / 8;
+    let promotes = piece.piece_type == PieceType::Pawn
+        && ((piece.color == Color::White && to_rank == 7) || (piece.color == Color::Black && to_rank == 0));
+    if promotes {
+        for promotion in PROMOTION_PIECES {
+            moves.push(Move::encode(from_square, to_square, Some(promotion)));
+        }
+    } else {
+        moves.push(Move::encode(from_square, to_square, None));
+    }
+}
+
+/// The castling of positions saved before each position carried its own.
+#[cfg(feature = "serde")]
+fn standard_castling() -> [Castling; 4] {
+    STANDARD_CASTLING
 }
 
-pub fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -> (Vec<Piece>, VecDeque<Square>) {
+/// The FEN letter of a single castling right.
+fn castling_symbol(right: CastlingRights) -> char {
+    if right == CastlingRights::WHITEKINGSIDE {
+        'K'
+    } else if right == CastlingRights::WHITEQUEENSIDE {
+        'Q'
+    } else if right == CastlingRights::BLACKKINGSIDE {
+        'k'
+    } else {
+        'q'
+    }
+}
+
+pub fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -> Result<(Vec<Piece>, Vec<Square>), String> {
     let mut pieces = Vec::new();
-    let mut squares = VecDeque::new();
+    let mut squares = Vec::new();
+    let rank_end = piece_position + 8;
 
     let mut color;
 
@@ -797,12 +1690,16 @@ pub fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -
     macro_rules! add_piece {
         ($piece_type:ident) => {
             {
+                if piece_position >= rank_end {
+                    return Err(format!("Invalid FEN string: too many squares in rank '{}'", row));
+                }
                 let piece = Piece {color: color,
                                position: (1 as u64) << piece_position,
-                               piece_type: PieceType::$piece_type};
+                               piece_type: PieceType::$piece_type,
+                               id: piece_index as PieceId};
                 let square = Square::Occupied(piece_index);
                 pieces.push(piece);
-                squares.push_front(square);
+                squares.push(square);
                 piece_position += 1;
                 piece_index += 1;
             }
@@ -821,17 +1718,23 @@ pub fn parse_row(row: &str, mut piece_index: usize, mut piece_position: usize) -
             'p' => add_piece!(Pawn),
             num => {
                 match num.to_digit(10) {
-                    None => panic!("Invalid input: {}", num),
-                    Some(number) => for i in 0..number {
-                        squares.push_front(Square::Empty);
-                        piece_position += 1;
+                    Some(number) if number >= 1 && piece_position + number as usize <= rank_end => {
+                        for _ in 0..number {
+                            squares.push(Square::Empty);
+                            piece_position += 1;
+                        }
                     }
+                    _ => return Err(format!("Invalid input: {}", num)),
                 }
             }
         }
     }
 
-    (pieces, squares)
+    if piece_position != rank_end {
+        return Err(format!("Invalid FEN string: rank '{}' does not have 8 squares", row));
+    }
+
+    Ok((pieces, squares))
 }
 
 #[cfg(test)]
@@ -858,6 +1761,10 @@ mod tests {
                               white_queenside_rook_moved: false,
                               black_kingside_rook_moved: false,
                               black_queenside_rook_moved: false,
+                              castling: STANDARD_CASTLING,
+                              chess960: false,
+                              legal_moves_key: None,
+                              king_squares: [None; 2],
         };
         let mut piece_index = 0;
 
@@ -999,6 +1906,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_fen_round_trips() {
+        let game = Game::new();
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 12",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/8/8/8/8/8/8/4K2k b - - 7 40",
+            "4k3/8/8/8/8/8/8/4K3 w - - 99 150",
+        ];
+        for fen in fens {
+            assert_eq!(Position::read_FEN(fen, &game).to_fen(), fen);
+        }
+        for rights in ["KQkq", "Kk", "Qq", "K", "q", "-"] {
+            let fen = format!("r3k2r/8/8/8/8/8/8/R3K2R b {} - 3 20", rights);
+            assert_eq!(Position::read_FEN(&fen, &game).to_fen(), fen);
+        }
+
+        // Moves made on the board advance both clocks
+        let mut board = crate::board::Board::new();
+        for text in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            let mv = board.parse_move(text).unwrap();
+            board.play(mv).unwrap();
+        }
+        let fen = board.position().to_fen();
+        assert_eq!(fen, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        assert_eq!(Position::read_FEN(&fen, &game).to_fen(), fen);
+        for text in ["f3e5", "c6e5"] {
+            let mv = board.parse_move(text).unwrap();
+            board.play(mv).unwrap();
+        }
+        assert!(board.position().to_fen().ends_with(" w KQkq - 0 4"));
+
+        // Every position of a game survives the round trip, clocks included
+        let mut board = crate::board::Board::from_fen(fens[2]).unwrap();
+        for _ in 0..40 {
+            let position = board.position();
+            let fen = position.to_fen();
+            let parsed = Position::read_FEN(&fen, &game);
+            assert_eq!(parsed.to_fen(), fen);
+            assert_eq!(parsed.get_hash(&game), position.get_hash(&game), "{}", fen);
+            assert_eq!((parsed.halfmove_clock, parsed.fullmove_number), (position.halfmove_clock, position.fullmove_number));
+            let moves = board.legal_moves();
+            let Some(&mv) = moves.get(fen.len() % moves.len().max(1)) else {
+                break;
+            };
+            board.play(mv).unwrap();
+        }
+    }
+
     #[test]
     fn test_occupancy_start_position() {
         let game = Game::new();
@@ -1169,6 +2128,50 @@ mod tests {
         assert!(position.black_kingside_path_attacked);
     }
 
+    #[test]
+    fn test_chess960_castling() {
+        let game = Game::new();
+        let legal = |position: &Position, from: usize, to: usize| {
+            position.get_all_legal_moves(&game).contains(&Move::encode(from, to, None))
+        };
+
+        // Shredder-FEN rook files are written back in X-FEN
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/1R3KR1 w GB - 0 1", &game).unwrap();
+        assert!(position.chess960);
+        assert_eq!(position.to_fen(), "4k3/8/8/8/8/8/8/1R3KR1 w KQ - 0 1");
+        assert_eq!(position.castling(Color::White, CastlingSide::Queenside).rook_from, 1);
+
+        // Castling is the king taking its own rook; the king and rook may
+        // swap squares
+        assert!(legal(&position, 5, 6) && legal(&position, 5, 1));
+        let mut kingside = position.clone();
+        kingside.make_move(Move::encode(5, 6, None));
+        assert_eq!(kingside.to_fen(), "4k3/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+        let mut queenside = position.clone();
+        queenside.make_move(Move::encode(5, 1, None));
+        assert_eq!(queenside.king_square(Color::White), Some(2));
+        assert!(queenside.squares[1] == Square::Empty && queenside.is_piece_on(3, Color::White, PieceType::Rook));
+
+        // An inner rook is named by its file; X-FEN's K and Q take the outer one
+        let inner = Position::from_fen("4k3/8/8/8/8/8/8/RR2K3 w B - 0 1", &game).unwrap();
+        assert_eq!(inner.castling(Color::White, CastlingSide::Queenside).rook_from, 1);
+        assert!(inner.to_fen().contains(" w B - "));
+        assert!(legal(&inner, 4, 1));
+        let outer = Position::from_fen("4k3/8/8/8/8/8/8/RR2K3 w Q - 0 1", &game).unwrap();
+        assert_eq!(outer.castling(Color::White, CastlingSide::Queenside).rook_from, 0);
+        assert!(!legal(&outer, 4, 0));
+
+        // The rook leaving b1 would expose the king, which stays on c1, to a1
+        let exposed = Position::from_fen("4k3/8/8/8/8/8/8/rRK5 w B - 0 1", &game).unwrap();
+        assert!(!legal(&exposed, 2, 1));
+
+        // Standard castling keeps the king's two-square move
+        let standard = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &game).unwrap();
+        assert!(!standard.chess960);
+        assert!(legal(&standard, 4, 6) && legal(&standard, 4, 2) && !legal(&standard, 4, 7));
+        assert!(Position::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1", &game).is_err());
+    }
+
     #[test]
     fn test_castling_rights() {
         let game = Game::new();
@@ -1212,7 +2215,7 @@ mod tests {
 
         // Find White's bishop
         let white_bishop_idx = position.pieces.iter().position(|p| {
-            p.piece_type == PieceType::Bishop && p.color == Color::White && p.position != 0
+            p.piece_type == PieceType::Bishop && p.color == Color::White
         }).unwrap();
 
         println!("Initial position:");
@@ -1230,27 +2233,29 @@ mod tests {
 
         // Find Black's bishop
         let black_bishop_idx = position.pieces.iter().position(|p| {
-            p.piece_type == PieceType::Bishop && p.color == Color::Black && p.position != 0
+            p.piece_type == PieceType::Bishop && p.color == Color::Black
         }).unwrap();
 
         println!("\nBlack bishop index: {}", black_bishop_idx);
         println!("Black bishop position: {}", position.pieces[black_bishop_idx].position);
 
         // Capture White's bishop with Black's bishop
+        let (white_bishop, black_bishop) = (position.pieces[white_bishop_idx].id, position.pieces[black_bishop_idx].id);
         let from_square = bit_scan(position.pieces[black_bishop_idx].position);
         position.move_piece(1u64 << from_square, 29, &game); // Capture on f4
+        let black_bishop_idx = position.pieces.iter().position(|p| p.id == black_bishop).unwrap();
 
         println!("\nAfter capturing white bishop:");
         println!("{}", position.to_string());
-        println!("White bishop position: {}", position.pieces[white_bishop_idx].position);
         println!("Black bishop position: {}", position.pieces[black_bishop_idx].position);
         println!("Square at f4: {:?}", position.squares[29]);
         println!("White occupancy at f4: {}", position.white_occupancy & (1u64 << 29));
         println!("Black occupancy at f4: {}", position.black_occupancy & (1u64 << 29));
 
         // Verify that:
-        // 1. White's bishop is removed (position = 0)
-        assert_eq!(position.pieces[white_bishop_idx].position, 0, "White's bishop should be captured (position = 0)");
+        // 1. White's bishop is removed from the piece list
+        assert_eq!(position.piece_by_id(white_bishop), None, "White's bishop should be captured");
+        assert_eq!(position.pieces.len(), 31);
         
         // 2. Black's bishop is on f4
         assert_eq!(position.pieces[black_bishop_idx].position, 1u64 << 29, "Black's bishop should be on f4");
@@ -1264,4 +2269,327 @@ mod tests {
         // 5. Black's occupancy includes f4
         assert_ne!(position.black_occupancy & (1u64 << 29), 0, "Black's occupancy should include f4");
     }
+
+    #[test]
+    fn test_checkers() {
+        let game = Game::new();
+        let position = Position::from_fen("4k3/8/8/1B6/8/8/4R3/4K3 b - - 0 1", &game).unwrap();
+        assert_eq!(position.checkers(&game), (1u64 << 33) | (1u64 << 12));
+        assert!(position.is_in_check(&game));
+
+        let quiet = Position::from_fen("4k3/8/8/8/8/8/3R4/4K3 b - - 0 1", &game).unwrap();
+        assert_eq!(quiet.checkers(&game), 0);
+        assert!(!quiet.is_in_check(&game));
+    }
+
+    #[test]
+    fn test_check_kind() {
+        let game = Game::new();
+        for (fen, mov, expected) in [
+            // Rook lift checks directly
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", Move::encode(0, 56, None), Some(CheckKind::Direct)),
+            // Knight steps off the e-file, uncovering the rook
+            ("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1", Move::encode(28, 11, None), Some(CheckKind::Discovered)),
+            // The same knight landing on f6 checks twice
+            ("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1", Move::encode(28, 45, None), Some(CheckKind::Double)),
+            // Capturing along the diagonal keeps the bishop blocked
+            ("7k/8/8/4n3/3P4/8/1B6/K7 w - - 0 1", Move::encode(27, 36, None), None),
+            // Pawn push off the bishop's diagonal
+            ("7k/8/8/8/8/2P5/1B6/K7 w - - 0 1", Move::encode(18, 26, None), Some(CheckKind::Discovered)),
+            // En passant clears both pawns off the fifth rank
+            ("8/8/8/R2pP2k/8/8/8/K7 w - d6 0 2", Move::encode(36, 43, None), Some(CheckKind::Discovered)),
+            // Promotion to a knight checks where a queen would not
+            ("8/4P3/8/3k4/8/8/8/K7 w - - 0 1", Move::encode(52, 60, Some(PieceType::Knight)), None),
+            ("8/4P3/5k2/8/8/8/8/K7 w - - 0 1", Move::encode(52, 60, Some(PieceType::Knight)), Some(CheckKind::Direct)),
+            ("8/4P3/5k2/8/8/8/8/K7 w - - 0 1", Move::encode(52, 60, Some(PieceType::Queen)), None),
+            // Castling checks with the rook
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", Move::encode(4, 6, None), Some(CheckKind::Direct)),
+            // Black discovers with the king itself
+            ("k7/8/8/8/8/8/1K6/7b w - - 0 1", Move::encode(9, 17, None), None),
+            ("K7/8/8/8/8/8/6k1/7q b - - 0 1", Move::encode(14, 22, None), Some(CheckKind::Discovered)),
+        ] {
+            let position = Position::from_fen(fen, &game).unwrap();
+            assert_eq!(position.check_kind(&game, mov), expected, "{} {:x}", fen, mov);
+        }
+    }
+
+    #[test]
+    fn test_gives_check_matches_making_the_move() {
+        let game = Game::new();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "3k4/8/8/2Pp4/8/8/8/B3K2R w K d6 0 2",
+            "4k3/8/2N5/8/1B6/8/3R4/Q3K3 w - - 0 1",
+        ] {
+            let position = Position::from_fen(fen, &game).unwrap();
+            for mov in position.get_all_legal_moves(&game) {
+                let mut after = position.clone();
+                after.make_move(mov);
+                let checkers = after.checkers(&game).count_ones();
+                let expected = match position.check_kind(&game, mov) {
+                    Some(CheckKind::Double) => 2,
+                    Some(_) => 1,
+                    None => 0,
+                };
+                assert_eq!(checkers.min(2), expected, "{} {:x}", fen, mov);
+                assert_eq!(position.gives_check(&game, mov), checkers > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let game = Game::new();
+        let can_mate = |fen: &str| {
+            let position = Position::from_fen(fen, &game).unwrap();
+            (position.can_mate(Color::White), position.can_mate(Color::Black))
+        };
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), (true, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/2B1K1B1 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1"), (true, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/4K1R1 w - - 0 1"), (true, false));
+        // A lone minor piece mates only with the enemy's help
+        assert_eq!(can_mate("4k3/4r3/8/8/8/8/8/4KN2 w - - 0 1"), (true, true));
+        assert_eq!(can_mate("4k3/4p3/8/8/8/8/8/4KB2 w - - 0 1"), (true, true));
+        assert_eq!(can_mate("4k3/4r3/8/8/8/8/8/4KB2 w - - 0 1"), (false, true));
+        assert_eq!(can_mate("3bk3/8/8/8/8/8/8/4KB2 w - - 0 1"), (true, true));
+        assert_eq!(can_mate("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1"), (false, false));
+
+        assert!(Position::from_fen("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1", &game).unwrap().is_insufficient_material());
+        assert!(!Position::new(&game).is_insufficient_material());
+    }
+
+    #[test]
+    fn test_infer_move() {
+        let game = Game::new();
+        for (fen, text) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1f3"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "a8a1"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"),
+            ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8n"),
+        ] {
+            let board = crate::Board::from_fen(fen).unwrap();
+            let mv = board.parse_move(text).unwrap();
+            let mut after = board.clone();
+            after.play(mv).unwrap();
+            assert_eq!(Position::infer_move(board.position(), after.position(), &game), Some(mv), "{}", fen);
+        }
+
+        // Unchanged, or changed by more than one move
+        let start = Position::new(&game);
+        assert_eq!(Position::infer_move(&start, &start, &game), None);
+        let mut two_moves = start.clone();
+        two_moves.make_move(Move::encode(12, 28, None));
+        two_moves.make_move(Move::encode(52, 36, None));
+        assert_eq!(Position::infer_move(&start, &two_moves, &game), None);
+    }
+
+    #[test]
+    fn test_generated_positions_match_legal_moves() {
+        // `generate_moves` returns positions rather than moves; inferring the
+        // move behind each one must reproduce `get_all_legal_moves`
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            "4k3/8/8/1B6/8/8/4R3/4K3 b - - 0 1",
+        ] {
+            let mut game = Game::new();
+            game.position = Position::from_fen(fen, &game).unwrap();
+            game.position.update_all_legal_moves(&game.clone());
+            let mut expected = game.position.get_all_legal_moves(&game);
+            expected.sort_unstable();
+
+            let mut inferred: Vec<u64> = crate::movegeneration::generate_moves(&game).iter()
+                .filter_map(|after| Position::infer_move(&game.position, after, &game))
+                .map(|mv| mv.to_u64())
+                .collect();
+            inferred.sort_unstable();
+            inferred.dedup();
+            assert_eq!(inferred, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_explain_illegal_move() {
+        let game = Game::new();
+        let explain = |fen: &str, from: &str, to: &str| {
+            let position = Position::from_fen(fen, &game).unwrap();
+            let square = |name: &str| bit_scan(position_to_bit(name).unwrap());
+            position.explain_illegal_move(&game, square(from), square(to))
+        };
+
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(explain(start, "e3", "e4"), "There is no piece on e3");
+        assert_eq!(explain(start, "e7", "e5"), "Not your turn: it is White to move");
+        assert_eq!(explain(start, "g1", "g3"), "A knight cannot move from g1 to g3");
+        assert_eq!(explain(start, "d1", "d2"), "You cannot capture your own piece");
+        assert_eq!(explain(start, "e1", "g1"), "Castling is blocked by pieces in the way");
+
+        // The e2 knight shields the king from the e8 rook
+        let pinned = "4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1";
+        assert_eq!(explain(pinned, "e2", "c3"), "That knight is pinned to your king");
+        assert_eq!(explain("5rk1/8/8/8/8/8/8/4K3 w - - 0 1", "e1", "f1"), "Your king would be in check on f1");
+        assert_eq!(explain("4k3/8/8/8/8/8/4r3/R3K3 w Q - 0 1", "a1", "a8"),
+            "Your king is in check and this move does not get it out of check");
+        assert_eq!(explain("4k3/8/8/8/8/8/8/R3K2r w Q - 0 1", "e1", "c1"), "You cannot castle out of check");
+        assert_eq!(explain("3rk3/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1", "c1"), "The castling path is attacked");
+        assert_eq!(explain("4k3/8/8/8/8/8/8/4K2R w - - 0 1", "e1", "g1"),
+            "You can no longer castle on that side: the king or rook has moved");
+    }
+
+    #[test]
+    fn test_legal_moves_are_cached_until_the_position_changes() {
+        let game = Game::new();
+        let mut position = Position::new(&game);
+        position.update_all_legal_moves(&game);
+        let moves = position.get_all_legal_moves(&game);
+        assert_eq!(moves.len(), 20);
+
+        // A repeated update on the same position is served from the cache
+        position.piece_legal_moves[0] = 0;
+        position.update_all_legal_moves(&game);
+        assert_eq!(position.piece_legal_moves[0], 0);
+
+        // Any change to the position regenerates the moves
+        position.white_king_moved = true;
+        position.update_all_legal_moves(&game);
+        assert_eq!(position.get_all_legal_moves(&game), moves);
+
+        position.make_move(Move::encode(12, 28, None));
+        position.update_all_legal_moves(&game);
+        assert_eq!(position.get_all_legal_moves(&game).len(), 20);
+        assert!(position.get_all_legal_moves(&game).iter().all(|&mov| mov & 0x3F >= 48));
+    }
+
+    #[test]
+    fn test_make_move_castles_and_updates_rights() {
+        let game = Game::new();
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &game).unwrap();
+        position.make_move(Move::encode(4, 6, None));  // e1g1
+        let rook = position.squares[5].get_piece_index().expect("rook on f1");
+        assert_eq!(position.pieces[rook].piece_type, PieceType::Rook);
+        assert_eq!(position.squares[7], Square::Empty);
+        assert_eq!(position.castling_rights, CastlingRights::BLACKKINGSIDE | CastlingRights::BLACKQUEENSIDE);
+        assert!(position.king_moved(Color::White));
+
+        // Moving a rook gives up its side only
+        position.make_move(Move::encode(56, 57, None));  // a8b8
+        assert_eq!(position.castling_rights, CastlingRights::BLACKKINGSIDE);
+        assert!(position.rook_moved(Castling::standard(Color::Black, CastlingSide::Queenside)));
+    }
+
+    #[test]
+    fn test_make_move_en_passant() {
+        let game = Game::new();
+        let mut position = Position::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1", &game).unwrap();
+        position.make_move(Move::encode(12, 28, None));  // e2e4
+        assert_eq!(position.en_passant, Some(1u64 << 20));  // e3
+        position.update_all_legal_moves(&game);
+        assert!(position.get_all_legal_moves(&game).contains(&Move::encode(27, 20, None)));
+
+        // The capture removes the pawn on e4, and the chance passes after one move
+        position.make_move(Move::encode(27, 20, None));  // d4xe3
+        assert_eq!(position.squares[28], Square::Empty);
+        assert_eq!(position.white_occupancy, 1u64 << 4);
+        assert_eq!(position.en_passant, None);
+
+        // Taking en passant here would expose the king along the fifth rank
+        let pinned = Position::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 2", &game).unwrap();
+        assert!(!pinned.get_all_legal_moves(&game).contains(&Move::encode(33, 42, None)));
+    }
+
+    #[test]
+    fn test_static_exchange_evaluation() {
+        let game = Game::new();
+        let see = |fen: &str, from: usize, to: usize| Position::from_fen(fen, &game).unwrap().see(&game, Move::encode(from, to, None));
+        // Rxe5 wins a knight, unless a pawn takes the rook back
+        assert_eq!(see("4k3/8/8/4n3/8/8/8/4RK2 w - - 0 1", 4, 36), 320);
+        assert_eq!(see("4k3/8/3p4/4n3/8/8/8/4RK2 w - - 0 1", 4, 36), -180);
+        // The second rook joins in once the first has gone
+        assert_eq!(see("4k3/8/3p4/4n3/8/8/4R3/4RK2 w - - 0 1", 12, 36), -80);
+        // A pawn takes a queen and the rook's recapture does not change that
+        assert_eq!(see("4k3/4r3/8/4q3/3P4/8/8/K7 w - - 0 1", 27, 36), 800);
+        assert_eq!(see("4k3/8/8/8/8/8/8/R4K2 w - - 0 1", 0, 8), 0);
+    }
+
+    #[test]
+    fn test_captures_leave_the_piece_list() {
+        let game = Game::new();
+        let mut position = Position::from_fen("4k3/1P6/8/8/8/8/r7/R3K3 w - - 0 1", &game).unwrap();
+        let id_on = |position: &Position, square: usize| position.pieces[position.squares[square].get_piece_index().unwrap()].id;
+        let (rook, pawn, black_rook) = (id_on(&position, 0), id_on(&position, 49), id_on(&position, 8));
+
+        position.make_move(Move::encode(0, 8, None));  // Rxa2
+        assert_eq!(position.pieces.len(), 4);
+        assert!(position.piece_by_id(black_rook).is_none());
+        assert_eq!(position.piece_by_id(rook).unwrap().position, 1u64 << 8);
+        for (index, piece) in position.pieces.iter().enumerate() {
+            assert_eq!(position.squares[bit_scan(piece.position)], Square::Occupied(index));
+        }
+
+        // A promoted pawn keeps its id
+        position.make_move(Move::encode(60, 51, None));  // Kd7
+        position.make_move(Move::encode(49, 57, Some(PieceType::Queen)));  // b8=Q
+        let queen = position.piece_by_id(pawn).unwrap();
+        assert_eq!((queen.piece_type, queen.position), (PieceType::Queen, 1u64 << 57));
+    }
+
+    #[test]
+    fn test_king_squares_follow_the_kings() {
+        let game = Game::new();
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &game).unwrap();
+        assert_eq!((position.king_square(Color::White), position.king_square(Color::Black)), (Some(4), Some(60)));
+
+        position.make_move(Move::encode(4, 6, None));  // O-O
+        position.make_move(Move::encode(60, 51, None));  // Kd7
+        assert_eq!((position.king_square(Color::White), position.king_square(Color::Black)), (Some(6), Some(51)));
+        assert_eq!(position.king_squares, [Some(6), Some(51)]);
+
+        // A king moved behind the cache's back is still found
+        let king = position.squares[6].get_piece_index().unwrap();
+        position.pieces[king].position = 1u64 << 7;
+        assert_eq!(position.king_square(Color::White), Some(7));
+
+        let kingless = Position::read_FEN("8/8/8/8/8/8/8/R7 w - - 0 1", &game);
+        assert_eq!(kingless.king_square(Color::White), None);
+        assert!(!kingless.is_in_check(&game));
+    }
+
+    #[test]
+    fn test_illegal_positions_are_reported() {
+        let game = Game::new();
+        let error = |fen: &str| Position::from_fen(fen, &game).err().unwrap_or_default();
+
+        assert!(Position::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 2", &game).is_ok());
+        assert_eq!(error("8/8/8/8/8/8/8/4K3 w - - 0 1"), "Illegal position: Black has no king");
+        assert_eq!(error("4k3/8/8/8/8/8/8/2K1K3 w - - 0 1"), "Illegal position: White has 2 kings");
+        assert_eq!(error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"), "Illegal position: pawn on a1");
+        assert_eq!(error("4k3/8/8/8/8/8/8/4K2r b - - 0 1"), "Illegal position: White is in check but it is not their move");
+        assert_eq!(error("4k3/8/8/3p4/8/8/8/4K3 w - e6 0 1"),
+            "Illegal position: en passant square e6 does not follow a double pawn move");
+        assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+            "Illegal position: castling right K needs the king on e1 and a rook on h1");
+
+        // The panicking constructor still accepts studies without kings
+        assert_eq!(Position::read_FEN("8/8/8/8/8/8/8/5R2 w - - 0 1", &game).pieces.len(), 1);
+    }
+
+    #[test]
+    fn test_legal_moves_san() {
+        let game = Game::new();
+        let moves = Position::new(&game).legal_moves_san(&game);
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(&"Nf3".to_string()) && moves.contains(&"e4".to_string()));
+
+        let position = Position::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", &game).unwrap();
+        let mut moves = position.legal_moves_san(&game);
+        moves.sort();
+        assert_eq!(moves, ["Kd1", "Kd2", "Ke2", "Kf1", "Kf2", "e5", "exd5"]);
+    }
 }
@@ -0,0 +1,274 @@
+//! Remote analysis client.
+//!
+//! Sends positions to another RustChess instance running `Chess_Engine serve`
+//! (typically on a faster machine) through its `POST /analyze` route, and
+//! falls back to searching locally when the remote is unreachable or fails.
+//! Positions are sent as a FEN plus the moves played from it, so callers
+//! that only track a game's moves can still be analyzed remotely.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::board::Board;
+use crate::engine::{Analysis, Engine};
+
+/// Time allowed for connecting and for the server's overhead on top of the
+/// requested search time.
+const NETWORK_SLACK: Duration = Duration::from_secs(2);
+
+/// Where an analysis line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisSource {
+    Remote,
+    Local,
+}
+
+/// An engine line in long algebraic notation, from either source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisLine {
+    pub source: AnalysisSource,
+    pub best_move: String,
+    /// Score in centipawns from the point of view of the side to move
+    pub score: i32,
+    /// Principal variation, starting with `best_move`
+    pub pv: Vec<String>,
+    /// Depth searched in plies; 0 when not known
+    pub depth: i32,
+}
+
+impl AnalysisLine {
+    /// The line of an analysis by the local engine.
+    pub fn local(analysis: &Analysis) -> Self {
+        AnalysisLine {
+            source: AnalysisSource::Local,
+            best_move: analysis.best_move.to_string(),
+            score: analysis.score,
+            pv: analysis.pv.iter().map(|mv| mv.to_string()).collect(),
+            depth: analysis.depth,
+        }
+    }
+
+    /// Reads the line's moves back on the board it was found on.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The analyzed position
+    ///
+    /// # Returns
+    ///
+    /// * The analysis, with the principal variation cut at the first move
+    ///   that cannot be played, or `None` if the best move is not legal
+    pub fn to_analysis(&self, board: &Board) -> Option<Analysis> {
+        let best_move = board.parse_move(&self.best_move).ok()?;
+        let mut line = board.clone();
+        let mut pv = Vec::new();
+        for text in &self.pv {
+            let Ok(mv) = line.parse_move(text) else {
+                break;
+            };
+            if line.play(mv).is_err() {
+                break;
+            }
+            pv.push(mv);
+        }
+        Some(Analysis { best_move, score: self.score, pv, nodes: 0, depth: self.depth, truncated: false })
+    }
+}
+
+/// Client for a remote `Chess_Engine serve` instance.
+#[derive(Debug, Clone)]
+pub struct RemoteAnalyzer {
+    addr: String,
+}
+
+impl RemoteAnalyzer {
+    /// Creates a client for the server at `addr`, e.g. `192.168.1.20:8080`.
+    pub fn new(addr: &str) -> Self {
+        RemoteAnalyzer { addr: addr.to_string() }
+    }
+
+    /// Asks the remote server to analyze a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `fen` - The starting position
+    /// * `moves` - Moves in long algebraic notation played from `fen`
+    /// * `movetime_ms` - Search time for the remote engine
+    ///
+    /// # Returns
+    ///
+    /// * The remote engine's line, or an error if the server cannot be
+    ///   reached, times out or rejects the position
+    pub fn analyze(&self, fen: &str, moves: &[String], movetime_ms: u64) -> Result<AnalysisLine, String> {
+        let body = json!({ "fen": fen, "moves": moves, "movetime": movetime_ms }).to_string();
+        let (status, payload) = self.post("/analyze", &body, Duration::from_millis(movetime_ms) + NETWORK_SLACK)?;
+        if status != 200 {
+            let message = payload["error"].as_str().unwrap_or("unknown error");
+            return Err(format!("Remote analysis failed ({}): {}", status, message));
+        }
+
+        let best_move = payload["bestmove"].as_str()
+            .ok_or_else(|| "Remote found no legal move".to_string())?
+            .to_string();
+        let pv = match payload["pv"].as_array() {
+            Some(moves) => moves.iter().filter_map(|mv| mv.as_str().map(str::to_string)).collect(),
+            None => vec![best_move.clone()],
+        };
+        Ok(AnalysisLine {
+            source: AnalysisSource::Remote,
+            best_move,
+            score: payload["score"].as_i64().unwrap_or(0) as i32,
+            pv,
+            // Servers from before depths were reported leave it out
+            depth: payload["depth"].as_i64().unwrap_or(0) as i32,
+        })
+    }
+
+    /// Sends a JSON POST request and returns the status code and JSON body.
+    fn post(&self, path: &str, body: &str, timeout: Duration) -> Result<(u16, Value), String> {
+        let addr = self.addr.to_socket_addrs()
+            .map_err(|e| format!("Invalid remote address {}: {}", self.addr, e))?
+            .next()
+            .ok_or_else(|| format!("Invalid remote address {}", self.addr))?;
+        let mut stream = TcpStream::connect_timeout(&addr, NETWORK_SLACK)
+            .map_err(|e| format!("Could not connect to {}: {}", self.addr, e))?;
+        stream.set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(NETWORK_SLACK)))
+            .map_err(|e| e.to_string())?;
+
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path, self.addr, body.len(), body);
+        let mut response = String::new();
+        stream.write_all(request.as_bytes())
+            .and_then(|_| stream.read_to_string(&mut response))
+            .map_err(|e| format!("Request to {} failed: {}", self.addr, e))?;
+
+        let (head, payload) = response.split_once("\r\n\r\n")
+            .ok_or_else(|| "Malformed HTTP response".to_string())?;
+        let status = head.split_whitespace().nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| "Malformed HTTP status line".to_string())?;
+        let payload = serde_json::from_str(payload)
+            .map_err(|e| format!("Invalid JSON from {}: {}", self.addr, e))?;
+        Ok((status, payload))
+    }
+}
+
+/// Analyzes locally with `engine`.
+///
+/// # Returns
+///
+/// * The line, or an error if the position is invalid or has no legal moves
+pub fn analyze_locally(engine: &mut Engine, fen: &str, moves: &[String], movetime_ms: u64) -> Result<AnalysisLine, String> {
+    let mut board = Board::from_fen(fen)?;
+    for text in moves {
+        board.play(board.parse_move(text)?)?;
+    }
+
+    engine.set_max_time_ms(movetime_ms);
+    let analysis = engine.analyze(&board).ok_or_else(|| "No legal moves".to_string())?;
+    Ok(AnalysisLine::local(&analysis))
+}
+
+/// Analyzes on the remote server if one is configured and reachable, and
+/// with the local engine otherwise.
+///
+/// # Arguments
+///
+/// * `remote` - The remote server, or `None` to always search locally
+/// * `engine` - Local engine used as the fallback
+/// * `fen` - The starting position
+/// * `moves` - Moves in long algebraic notation played from `fen`
+/// * `movetime_ms` - Search time, remote or local
+///
+/// # Returns
+///
+/// * The line, with why the remote failed if the line was found locally
+///   instead, for the caller to show; or an error if the local search
+///   fails as well
+pub fn analyze_with_fallback(
+    remote: Option<&RemoteAnalyzer>,
+    engine: &mut Engine,
+    fen: &str,
+    moves: &[String],
+    movetime_ms: u64,
+) -> Result<(AnalysisLine, Option<String>), String> {
+    let fallback = match remote.map(|remote| remote.analyze(fen, moves, movetime_ms)) {
+        Some(Ok(line)) => return Ok((line, None)),
+        Some(Err(e)) => Some(e),
+        None => None,
+    };
+    analyze_locally(engine, fen, moves, movetime_ms).map(|line| (line, fallback))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+    use std::net::TcpListener;
+
+    /// An address on localhost that nothing is listening on.
+    fn unused_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_falls_back_to_local_search_when_offline() {
+        let remote = RemoteAnalyzer::new(&unused_addr());
+        assert!(remote.analyze(START_FEN, &[], 50).is_err());
+
+        let mut engine = Engine::new();
+        let moves = vec!["f2f3".to_string(), "e7e5".to_string(), "g2g4".to_string()];
+        let (line, fallback) = analyze_with_fallback(Some(&remote), &mut engine, START_FEN, &moves, 300).unwrap();
+        assert_eq!(line.source, AnalysisSource::Local);
+        assert!(fallback.unwrap().starts_with("Could not connect"));
+        assert_eq!(line.best_move, "d8h4");
+        assert_eq!(line.pv[0], line.best_move);
+
+        // The line reads back as moves on the analyzed board
+        let mut board = Board::new();
+        for text in &moves {
+            board.play(board.parse_move(text).unwrap()).unwrap();
+        }
+        let analysis = line.to_analysis(&board).unwrap();
+        assert_eq!(AnalysisLine::local(&analysis), line);
+        assert!(line.to_analysis(&Board::new()).is_none());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_remote_analysis_through_server() {
+        let addr = unused_addr();
+        let server_addr = addr.clone();
+        std::thread::spawn(move || crate::server::serve(&server_addr));
+
+        let remote = RemoteAnalyzer::new(&addr);
+        let moves = vec!["f2f3".to_string(), "e7e5".to_string(), "g2g4".to_string()];
+        let mut engine = Engine::new();
+        let mut line = None;
+        // Give the server thread a moment to bind
+        for _ in 0..50 {
+            match remote.analyze(START_FEN, &moves, 300) {
+                Ok(found) => {
+                    line = Some(found);
+                    break;
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(50)),
+            }
+        }
+        let line = line.expect("server never answered");
+        assert_eq!(line.source, AnalysisSource::Remote);
+        assert_eq!(line.best_move, "d8h4");
+        assert!(line.score > 0);
+
+        let err = remote.analyze(START_FEN, &["e2e5".to_string()], 50).unwrap_err();
+        assert!(err.contains("400"), "{}", err);
+        let (line, fallback) = analyze_with_fallback(Some(&remote), &mut engine, START_FEN, &moves, 100).unwrap();
+        assert_eq!((line.source, fallback), (AnalysisSource::Remote, None));
+    }
+}
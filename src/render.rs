@@ -0,0 +1,526 @@
+//! Headless board rendering.
+//!
+//! Draws a `Position` into an RGBA image or an SVG document without opening a
+//! window, using the same square colours and orientation rules as the GUI.
+//! Raster pieces are drawn from small built-in sprites so no font or asset
+//! files are needed; SVG output uses the Unicode chess symbols. The renderer
+//! backs `Chess_Engine img`, the server's `/image` route and the export of
+//! finished games as animated GIFs or numbered PNG frames.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+
+use crate::position::{Color, PieceType, Position};
+use crate::utils::bit_scan_safe;
+
+/// Light square colour, shared with the GUI board
+pub const LIGHT_SQUARE: [u8; 3] = [240, 217, 181];
+/// Dark square colour, shared with the GUI board
+pub const DARK_SQUARE: [u8; 3] = [181, 136, 99];
+/// Colour of the from and to squares of the last move
+pub const LAST_MOVE: [u8; 3] = [205, 210, 106];
+
+const SPRITE_SIZE: usize = 16;
+
+// '#' is the outline, 'o' the body of the piece, '.' is transparent
+const PAWN_SPRITE: [&str; SPRITE_SIZE] = [
+    "................",
+    "................",
+    "................",
+    "......####......",
+    ".....#oooo#.....",
+    ".....#oooo#.....",
+    "......#oo#......",
+    ".....#oooo#.....",
+    "......#oo#......",
+    "......#oo#......",
+    ".....#oooo#.....",
+    "....#oooooo#....",
+    "...#oooooooo#...",
+    "...##########...",
+    "................",
+    "................",
+];
+
+const KNIGHT_SPRITE: [&str; SPRITE_SIZE] = [
+    "................",
+    "................",
+    "......##.#......",
+    ".....#oo#o#.....",
+    "....#oooooo#....",
+    "...#oo#ooooo#...",
+    "..#ooooooooo#...",
+    "..#oo###ooooo#..",
+    "...##..#oooo#...",
+    "......#ooooo#...",
+    ".....#oooooo#...",
+    "....#ooooooo#...",
+    "...#oooooooo#...",
+    "...##########...",
+    "................",
+    "................",
+];
+
+const BISHOP_SPRITE: [&str; SPRITE_SIZE] = [
+    "................",
+    ".......##.......",
+    "......#oo#......",
+    ".......##.......",
+    "......#oo#......",
+    ".....#oo#o#.....",
+    "....#oo#ooo#....",
+    "....#o#oooo#....",
+    "....#oooooo#....",
+    ".....#oooo#.....",
+    "......#oo#......",
+    ".....#oooo#.....",
+    "...#oooooooo#...",
+    "...##########...",
+    "................",
+    "................",
+];
+
+const ROOK_SPRITE: [&str; SPRITE_SIZE] = [
+    "................",
+    "................",
+    "..###.####.###..",
+    "..#o###oo###o#..",
+    "..#oooooooooo#..",
+    "...##########...",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "...#oooooooo#...",
+    "..#oooooooooo#..",
+    "..############..",
+    "................",
+    "................",
+];
+
+const QUEEN_SPRITE: [&str; SPRITE_SIZE] = [
+    "................",
+    ".#....#..#....#.",
+    ".##..##..##..##.",
+    ".#o#.#o##o#.#o#.",
+    ".#oo#oooooo#oo#.",
+    "..#oooooooooo#..",
+    "..#oooooooooo#..",
+    "...#oooooooo#...",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "...#oooooooo#...",
+    "..#oooooooooo#..",
+    "..#oooooooooo#..",
+    "..############..",
+    "................",
+    "................",
+];
+
+const KING_SPRITE: [&str; SPRITE_SIZE] = [
+    ".......##.......",
+    "......####......",
+    ".......##.......",
+    "...###.##.###...",
+    "..#ooo#oo#ooo#..",
+    ".#oooooooooooo#.",
+    ".#oooooooooooo#.",
+    "..#oooooooooo#..",
+    "...#oooooooo#...",
+    "....#oooooo#....",
+    "....#oooooo#....",
+    "...#oooooooo#...",
+    "..#oooooooooo#..",
+    "..############..",
+    "................",
+    "................",
+];
+
+/// Returns true for light squares (0 = a1, which is dark).
+pub fn is_light_square(square: usize) -> bool {
+    (square / 8 + square % 8) % 2 == 1
+}
+
+/// Returns the Unicode chess symbol for a piece.
+pub fn piece_glyph(piece_type: PieceType, color: Color) -> &'static str {
+    match (piece_type, color) {
+        (PieceType::Pawn, Color::White) => "♙",
+        (PieceType::Knight, Color::White) => "♘",
+        (PieceType::Bishop, Color::White) => "♗",
+        (PieceType::Rook, Color::White) => "♖",
+        (PieceType::Queen, Color::White) => "♕",
+        (PieceType::King, Color::White) => "♔",
+        (PieceType::Pawn, Color::Black) => "♟",
+        (PieceType::Knight, Color::Black) => "♞",
+        (PieceType::Bishop, Color::Black) => "♝",
+        (PieceType::Rook, Color::Black) => "♜",
+        (PieceType::Queen, Color::Black) => "♛",
+        (PieceType::King, Color::Black) => "♚",
+    }
+}
+
+/// Colour of a square, with the from and to squares of `last_move` highlighted.
+pub(crate) fn square_color(square: usize, last_move: Option<u64>) -> [u8; 3] {
+    match last_move {
+        Some(mov) if square as u64 == mov & 0x3F || square as u64 == (mov >> 6) & 0x3F => LAST_MOVE,
+        _ if is_light_square(square) => LIGHT_SQUARE,
+        _ => DARK_SQUARE,
+    }
+}
+
+/// Where a square is drawn on a board seen from `orientation`'s side: its
+/// row counted from the top and its column counted from the left.
+///
+/// # Arguments
+///
+/// * `square` - The square (0 = a1, 63 = h8)
+/// * `orientation` - The side shown at the bottom of the board
+///
+/// # Returns
+///
+/// * The square's `(row, column)`
+pub fn square_cell(square: usize, orientation: Color) -> (usize, usize) {
+    let (rank, file) = (square / 8, square % 8);
+    match orientation {
+        Color::White => (7 - rank, file),
+        Color::Black => (rank, 7 - file),
+    }
+}
+
+/// The square drawn at a row and column; the inverse of `square_cell`.
+pub fn cell_square(row: usize, col: usize, orientation: Color) -> usize {
+    match orientation {
+        Color::White => (7 - row) * 8 + col,
+        Color::Black => row * 8 + 7 - col,
+    }
+}
+
+fn sprite(piece_type: PieceType) -> &'static [&'static str; SPRITE_SIZE] {
+    match piece_type {
+        PieceType::Pawn => &PAWN_SPRITE,
+        PieceType::Knight => &KNIGHT_SPRITE,
+        PieceType::Bishop => &BISHOP_SPRITE,
+        PieceType::Rook => &ROOK_SPRITE,
+        PieceType::Queen => &QUEEN_SPRITE,
+        PieceType::King => &KING_SPRITE,
+    }
+}
+
+/// Draws positions into images of a fixed size.
+#[derive(Debug, Clone, Copy)]
+pub struct BoardRenderer {
+    square_size: u32,
+    orientation: Color,
+}
+
+impl BoardRenderer {
+    /// Creates a renderer.
+    ///
+    /// # Arguments
+    ///
+    /// * `square_size` - Width and height of one square in pixels
+    /// * `orientation` - The side shown at the bottom of the board
+    pub fn new(square_size: u32, orientation: Color) -> Self {
+        BoardRenderer { square_size: square_size.max(1), orientation }
+    }
+
+    /// Width and height of the rendered board in pixels.
+    pub fn board_size(&self) -> u32 {
+        self.square_size * 8
+    }
+
+    /// Returns the top-left pixel of a square (0 = a1, 63 = h8).
+    fn square_origin(&self, square: usize) -> (u32, u32) {
+        let (row, col) = square_cell(square, self.orientation);
+        (col as u32 * self.square_size, row as u32 * self.square_size)
+    }
+
+    /// Renders a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to draw
+    /// * `last_move` - Move whose from and to squares are highlighted, if any
+    ///
+    /// # Returns
+    ///
+    /// * An RGBA image `board_size()` pixels square
+    pub fn render(&self, position: &Position, last_move: Option<u64>) -> RgbaImage {
+        let size = self.board_size();
+        let mut image = RgbaImage::new(size, size);
+
+        for square in 0..64 {
+            let color = square_color(square, last_move);
+            self.fill_square(&mut image, square, Rgba([color[0], color[1], color[2], 255]));
+        }
+
+        for piece in &position.pieces {
+            if let Some(square) = bit_scan_safe(piece.position) {
+                self.draw_piece(&mut image, square, piece.piece_type, piece.color);
+            }
+        }
+        image
+    }
+
+    fn fill_square(&self, image: &mut RgbaImage, square: usize, color: Rgba<u8>) {
+        let (x0, y0) = self.square_origin(square);
+        for y in y0..y0 + self.square_size {
+            for x in x0..x0 + self.square_size {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn draw_piece(&self, image: &mut RgbaImage, square: usize, piece_type: PieceType, color: Color) {
+        let (body, outline) = match color {
+            Color::White => (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255])),
+            Color::Black => (Rgba([20, 20, 20, 255]), Rgba([200, 200, 200, 255])),
+        };
+        let rows = sprite(piece_type);
+        let (x0, y0) = self.square_origin(square);
+        let size = self.square_size as usize;
+        for py in 0..size {
+            let row = rows[py * SPRITE_SIZE / size].as_bytes();
+            for px in 0..size {
+                let pixel = match row[px * SPRITE_SIZE / size] {
+                    b'#' => outline,
+                    b'o' => body,
+                    _ => continue,
+                };
+                image.put_pixel(x0 + px as u32, y0 + py as u32, pixel);
+            }
+        }
+    }
+
+    /// Renders a position as a standalone SVG document.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to draw
+    /// * `last_move` - Move whose from and to squares are highlighted, if any
+    ///
+    /// # Returns
+    ///
+    /// * The SVG source, `board_size()` pixels square
+    pub fn render_svg(&self, position: &Position, last_move: Option<u64>) -> String {
+        let size = self.board_size();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+            size);
+
+        for square in 0..64 {
+            let (x, y) = self.square_origin(square);
+            let [r, g, b] = square_color(square, last_move);
+            let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"#{3:02x}{4:02x}{5:02x}\"/>",
+                x, y, self.square_size, r, g, b);
+        }
+
+        let font_size = self.square_size * 4 / 5;
+        for piece in &position.pieces {
+            if let Some(square) = bit_scan_safe(piece.position) {
+                let (x, y) = self.square_origin(square);
+                let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                    x + self.square_size / 2, y + self.square_size / 2, font_size,
+                    piece_glyph(piece.piece_type, piece.color));
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Writes a position to an image file. A `.svg` extension gives an SVG
+    /// document; any other extension (e.g. `.png`) picks the raster format.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to draw
+    /// * `last_move` - Move whose from and to squares are highlighted, if any
+    /// * `path` - Output file
+    pub fn save<P: AsRef<Path>>(&self, position: &Position, last_move: Option<u64>, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let is_svg = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        let result = if is_svg {
+            fs::write(path, self.render_svg(position, last_move)).map_err(|e| e.to_string())
+        } else {
+            self.render(position, last_move).save(path).map_err(|e| e.to_string())
+        };
+        result.map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Renders one frame per position of a game: the start position and the
+    /// position after each move, with that move highlighted.
+    pub fn render_game(&self, start: &Position, moves: &[u64]) -> Vec<RgbaImage> {
+        let mut position = start.clone();
+        let mut frames = vec![self.render(&position, None)];
+        for &mov in moves {
+            position.make_move(mov);
+            frames.push(self.render(&position, Some(mov)));
+        }
+        frames
+    }
+}
+
+/// Settings for exporting a game.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    /// Width and height of one square in pixels
+    pub square_size: u32,
+    /// How long each position is shown, in milliseconds
+    pub frame_delay_ms: u32,
+    /// The side shown at the bottom of the board
+    pub orientation: Color,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            square_size: 48,
+            frame_delay_ms: 1000,
+            orientation: Color::White,
+        }
+    }
+}
+
+/// Writes a game as an animated GIF that loops forever.
+///
+/// # Arguments
+///
+/// * `start` - The position the game started from
+/// * `moves` - The moves played, in order
+/// * `options` - Frame size, speed and orientation
+/// * `path` - Output file
+pub fn export_gif<P: AsRef<Path>>(start: &Position, moves: &[u64], options: &ExportOptions, path: P) -> Result<(), String> {
+    let path = path.as_ref();
+    let renderer = BoardRenderer::new(options.square_size, options.orientation);
+    let delay = Delay::from_numer_denom_ms(options.frame_delay_ms, 1);
+    let frames = renderer.render_game(start, moves)
+        .into_iter()
+        .map(|image| Frame::from_parts(image, 0, 0, delay));
+
+    let file = fs::File::create(path)
+        .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    let mut encoder = GifEncoder::new_with_speed(file, 10);
+    encoder.set_repeat(Repeat::Infinite)
+        .and_then(|_| encoder.encode_frames(frames))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+/// Writes a game as numbered PNG frames (`frame_000.png`, ...) in a directory,
+/// for assembling into a video with external tools.
+///
+/// # Returns
+///
+/// * The paths of the written frames, in game order
+pub fn export_png_frames<P: AsRef<Path>>(start: &Position, moves: &[u64], options: &ExportOptions, dir: P) -> Result<Vec<PathBuf>, String> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+
+    let renderer = BoardRenderer::new(options.square_size, options.orientation);
+    let mut paths = Vec::new();
+    for (index, image) in renderer.render_game(start, moves).into_iter().enumerate() {
+        let path = dir.join(format!("frame_{:03}.png", index));
+        image.save(&path)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_move::Move;
+    use crate::Game;
+
+    #[test]
+    fn test_sprites_are_square() {
+        for piece_type in [PieceType::Pawn, PieceType::Knight, PieceType::Bishop,
+                           PieceType::Rook, PieceType::Queen, PieceType::King] {
+            for row in sprite(piece_type) {
+                assert_eq!(row.len(), SPRITE_SIZE, "{:?} sprite row '{}'", piece_type, row);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_orientation_and_highlight() {
+        let game = Game::new();
+        let renderer = BoardRenderer::new(8, Color::White);
+        let e2e4 = Move::encode(12, 28, None);
+        let mut position = game.position.clone();
+        position.make_move(e2e4);
+        let image = renderer.render(&position, Some(e2e4));
+        assert_eq!(image.dimensions(), (64, 64));
+
+        // a1 is a dark square in the bottom-left corner, h1 light
+        assert_eq!(&image.get_pixel(0, 63).0[..3], &DARK_SQUARE);
+        assert_eq!(&image.get_pixel(63, 63).0[..3], &LIGHT_SQUARE);
+        // e2 (file 4, rank 2) is highlighted and now empty
+        assert_eq!(&image.get_pixel(4 * 8, 6 * 8).0[..3], &LAST_MOVE);
+
+        // Seen from Black, a1 is in the top-right corner
+        let flipped = BoardRenderer::new(8, Color::Black).render(&position, None);
+        assert_eq!(flipped.get_pixel(63, 0), image.get_pixel(0, 63));
+    }
+
+    #[test]
+    fn test_square_cells_flip_with_orientation() {
+        // a1, h1 and e8 seen from either side
+        assert_eq!(square_cell(0, Color::White), (7, 0));
+        assert_eq!(square_cell(0, Color::Black), (0, 7));
+        assert_eq!(square_cell(7, Color::Black), (0, 0));
+        assert_eq!(square_cell(60, Color::White), (0, 4));
+        assert_eq!(square_cell(60, Color::Black), (7, 3));
+        for orientation in [Color::White, Color::Black] {
+            for square in 0..64 {
+                let (row, col) = square_cell(square, orientation);
+                assert_eq!(cell_square(row, col, orientation), square);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_svg_and_save() {
+        let game = Game::new();
+        let svg = BoardRenderer::new(10, Color::White).render_svg(&game.position, None);
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 64);
+        assert_eq!(svg.matches("<text").count(), 32);
+        // White's king on e1 sits in the bottom row
+        assert!(svg.contains("<text x=\"45\" y=\"75\" font-size=\"8\" text-anchor=\"middle\" dominant-baseline=\"central\">♔</text>"));
+
+        let dir = std::env::temp_dir().join(format!("rustchess_img_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let renderer = BoardRenderer::new(4, Color::Black);
+        renderer.save(&game.position, None, dir.join("board.svg")).unwrap();
+        renderer.save(&game.position, None, dir.join("board.png")).unwrap();
+        assert!(fs::read_to_string(dir.join("board.svg")).unwrap().starts_with("<svg"));
+        assert!(fs::read(dir.join("board.png")).unwrap().starts_with(b"\x89PNG"));
+        assert!(renderer.save(&game.position, None, dir.join("board.unknown")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_gif_and_frames() {
+        let game = Game::new();
+        let moves = [Move::encode(12, 28, None), Move::encode(52, 36, None)];
+        let options = ExportOptions { square_size: 4, ..ExportOptions::default() };
+        let dir = std::env::temp_dir().join(format!("rustchess_export_{}", std::process::id()));
+
+        let gif = dir.join("game.gif");
+        fs::create_dir_all(&dir).unwrap();
+        export_gif(&game.position, &moves, &options, &gif).unwrap();
+        assert!(fs::read(&gif).unwrap().starts_with(b"GIF89a"));
+
+        let frames = export_png_frames(&game.position, &moves, &options, dir.join("frames")).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|p| p.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
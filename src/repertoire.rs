@@ -0,0 +1,322 @@
+//! Opening repertoire training.
+//!
+//! A repertoire is a `MoveTree` of the lines a player means to follow with
+//! one color, usually read from PGN with `pgn::import_tree`. A `Trainer`
+//! quizzes it a line at a time: it plays the opponent's moves from the tree
+//! and the player has to answer each with a repertoire move. `RecallStats`
+//! counts how often each line was recalled without a mistake, and the lines
+//! recalled worst come up first.
+
+use std::collections::BTreeMap;
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::pgn::MoveTree;
+use crate::position::Color;
+
+/// What happened to a move played in a quiz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainerStep {
+    /// The move is not in the repertoire; it is not played
+    Wrong,
+    /// The move is right and the opponent answers with this move
+    Reply(u64),
+    /// The move is right and ends the line
+    Finished,
+}
+
+/// How often a line has been recalled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineRecord {
+    /// Times the line was played through without a wrong move or a hint
+    pub recalled: u32,
+    /// Times it took one
+    pub missed: u32,
+}
+
+impl LineRecord {
+    /// Share of clean recalls, counting one clean and one missed attempt
+    /// in advance so untried lines sit between good and bad ones.
+    fn recall_rate(self) -> f64 {
+        (self.recalled + 1) as f64 / (self.recalled + self.missed + 2) as f64
+    }
+}
+
+/// Recall records of repertoire lines, remembered between runs as
+/// `<moves>=<recalled> <missed>` lines with the moves in long algebraic
+/// notation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecallStats {
+    lines: BTreeMap<String, LineRecord>,
+}
+
+impl RecallStats {
+    /// Records a line played through.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line's moves from the start position
+    /// * `clean` - Whether it went without a wrong move or a hint
+    pub fn record(&mut self, line: &[Move], clean: bool) {
+        let record = self.lines.entry(line_key(line)).or_default();
+        if clean {
+            record.recalled += 1;
+        } else {
+            record.missed += 1;
+        }
+    }
+
+    /// The record of a line; untried lines have an empty one.
+    pub fn get(&self, line: &[Move]) -> LineRecord {
+        self.lines.get(&line_key(line)).copied().unwrap_or_default()
+    }
+
+    /// Recalled and missed attempts over all lines.
+    pub fn totals(&self) -> LineRecord {
+        self.lines.values().fold(LineRecord::default(), |total, record| LineRecord {
+            recalled: total.recalled + record.recalled,
+            missed: total.missed + record.missed,
+        })
+    }
+
+    /// Reads stored stats; unreadable lines are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut stats = RecallStats::default();
+        for (line, counts) in text.lines().filter_map(|line| line.split_once('=')) {
+            let mut counts = counts.split_whitespace().map(str::parse);
+            if let (Some(Ok(recalled)), Some(Ok(missed))) = (counts.next(), counts.next()) {
+                stats.lines.insert(line.trim().to_string(), LineRecord { recalled, missed });
+            }
+        }
+        stats
+    }
+
+    pub fn to_text(&self) -> String {
+        self.lines.iter()
+            .map(|(line, record)| format!("{}={} {}\n", line, record.recalled, record.missed))
+            .collect()
+    }
+}
+
+/// The moves of a line in long algebraic notation, separated by spaces.
+fn line_key(line: &[Move]) -> String {
+    line.iter().map(Move::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// A repertoire quiz and the player's progress through its current line.
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    /// The side the player's repertoire is for
+    pub side: Color,
+    pub start_fen: String,
+    /// The lines quizzed, each ending with a move of the player's side, in
+    /// the order they come up
+    lines: Vec<Vec<Move>>,
+    line: usize,  // Index of the line being quizzed
+    played: usize,  // Moves of it played so far
+    missed: bool,  // Whether the player has made a wrong move or asked for a hint in it
+}
+
+impl Trainer {
+    /// Prepares a quiz of a repertoire.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The repertoire's lines
+    /// * `side` - The side the player plays
+    ///
+    /// # Returns
+    ///
+    /// * The trainer, or an error if the tree has no moves for `side` or
+    ///   does not start from a legal position
+    pub fn new(tree: &MoveTree, side: Color) -> Result<Self, String> {
+        let first = Board::from_fen(&tree.start_fen)?.side_to_move();
+        // Lines end with the player's move; what the opponent might play
+        // after it is not quizzed
+        let mut lines: Vec<Vec<Move>> = tree.lines().into_iter()
+            .map(|mut line| {
+                let end = (0..line.len()).rev().find(|ply| (ply % 2 == 0) == (first == side));
+                line.truncate(end.map_or(0, |ply| ply + 1));
+                line
+            })
+            .collect();
+        // A line cut short may now be the start of a longer one, or the same
+        // as its neighbour
+        let longer = lines.clone();
+        lines.retain(|line| !line.is_empty()
+            && !longer.iter().any(|other| other.len() > line.len() && other.starts_with(line)));
+        lines.dedup();
+        if lines.is_empty() {
+            let name = if side == Color::White { "White" } else { "Black" };
+            return Err(format!("The repertoire has no moves for {}", name));
+        }
+        Ok(Trainer { side, start_fen: tree.start_fen.clone(), lines, line: 0, played: 0, missed: false })
+    }
+
+    /// Starts over with the line recalled worst so far; of equally well
+    /// recalled lines, the one tried least comes first.
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - The recall records of the lines
+    ///
+    /// # Returns
+    ///
+    /// * The opponent's first move when the opponent moves first
+    pub fn next_line(&mut self, stats: &RecallStats) -> Option<u64> {
+        self.lines.sort_by(|a, b| {
+            let (a, b) = (stats.get(a), stats.get(b));
+            a.recall_rate().total_cmp(&b.recall_rate()).then((a.recalled + a.missed).cmp(&(b.recalled + b.missed)))
+        });
+        self.line = 0;
+        self.played = 0;
+        self.missed = false;
+        let first = Board::from_fen(&self.start_fen).map(|board| board.side_to_move()).ok()?;
+        if first == self.side {
+            return None;
+        }
+        self.played = 1;
+        Some(self.lines[0][0].to_u64())
+    }
+
+    /// Plays the player's move and, if the line goes on, the opponent's
+    /// reply. A repertoire move other than the one in the current line
+    /// switches to a line with that move.
+    ///
+    /// # Arguments
+    ///
+    /// * `mov` - The player's move
+    ///
+    /// # Returns
+    ///
+    /// * What came of the move; a wrong move leaves the quiz where it was
+    pub fn play(&mut self, mov: u64) -> TrainerStep {
+        if self.is_finished() {
+            return TrainerStep::Wrong;
+        }
+        let played = &self.lines[self.line][..self.played];
+        let Some(index) = self.lines.iter().position(|line| {
+            line.len() > played.len() && line.starts_with(played) && line[played.len()].to_u64() == mov
+        }) else {
+            self.missed = true;
+            return TrainerStep::Wrong;
+        };
+        self.line = index;
+        self.played += 1;
+        match self.lines[index].get(self.played) {
+            Some(reply) => {
+                self.played += 1;
+                TrainerStep::Reply(reply.to_u64())
+            }
+            None => TrainerStep::Finished,
+        }
+    }
+
+    /// The repertoire move for a stuck player. Asking for it counts as a
+    /// miss.
+    pub fn hint(&mut self) -> Option<u64> {
+        let mov = self.lines[self.line].get(self.played)?.to_u64();
+        self.missed = true;
+        Some(mov)
+    }
+
+    /// The line being quizzed, as far as it goes.
+    pub fn line(&self) -> &[Move] {
+        &self.lines[self.line]
+    }
+
+    /// Number of lines in the repertoire.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.played == self.lines[self.line].len()
+    }
+
+    /// Whether the player has made a wrong move or asked for a hint in the
+    /// current line.
+    pub fn missed(&self) -> bool {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn;
+
+    const REPERTOIRE: &str = "1. e4 c5 (1... e5 2. Nf3 Nc6 3. Bb5) (1... e6 2. d4) 2. Nf3 *";
+
+    fn uci(line: &[Move]) -> String {
+        line_key(line)
+    }
+
+    #[test]
+    fn test_trainer_quizzes_weakest_lines_first() {
+        let tree = pgn::import_tree(REPERTOIRE).unwrap();
+        let mut trainer = Trainer::new(&tree, Color::White).unwrap();
+        assert_eq!(trainer.line_count(), 3);
+        let mut stats = RecallStats::default();
+
+        // White moves first, so the quiz starts with the player's move
+        assert_eq!(trainer.next_line(&stats), None);
+        assert_eq!(uci(trainer.line()), "e2e4 c7c5 g1f3");
+        let (e4, c5, nf3) = (Move::encode(12, 28, None), Move::encode(50, 34, None), Move::encode(6, 21, None));
+        assert_eq!(trainer.play(Move::encode(11, 27, None)), TrainerStep::Wrong);
+        assert!(trainer.missed());
+        assert_eq!(trainer.play(e4), TrainerStep::Reply(c5));
+        assert_eq!(trainer.hint(), Some(nf3));
+        assert_eq!(trainer.play(nf3), TrainerStep::Finished);
+        assert!(trainer.is_finished());
+        stats.record(trainer.line(), !trainer.missed());
+
+        // The missed line comes back until it is recalled as well as the
+        // untried ones, which then go first
+        trainer.next_line(&stats);
+        assert_eq!(uci(trainer.line()), "e2e4 c7c5 g1f3");
+        assert!(!trainer.missed());
+        assert_eq!(trainer.play(e4), TrainerStep::Reply(c5));
+        assert_eq!(trainer.play(nf3), TrainerStep::Finished);
+        stats.record(trainer.line(), !trainer.missed());
+        trainer.next_line(&stats);
+        assert_eq!(uci(trainer.line()), "e2e4 e7e5 g1f3 b8c6 f1b5");
+        stats.record(trainer.line(), true);
+        trainer.next_line(&stats);
+        assert_eq!(uci(trainer.line()), "e2e4 e7e6 d2d4");
+    }
+
+    #[test]
+    fn test_trainer_for_black() {
+        let tree = pgn::import_tree(REPERTOIRE).unwrap();
+        let mut trainer = Trainer::new(&tree, Color::Black).unwrap();
+        // Lines stop after Black's last move, and 1... e6 has none after
+        // the opponent's 2. d4
+        let mut lines: Vec<String> = trainer.lines.iter().map(|line| uci(line)).collect();
+        lines.sort();
+        assert_eq!(lines, ["e2e4 c7c5", "e2e4 e7e5 g1f3 b8c6", "e2e4 e7e6"]);
+
+        assert_eq!(trainer.next_line(&RecallStats::default()), Some(Move::encode(12, 28, None)));
+        // 1... e6 is in the repertoire too, so it switches lines
+        assert_eq!(trainer.play(Move::encode(52, 44, None)), TrainerStep::Finished);
+        assert_eq!(uci(trainer.line()), "e2e4 e7e6");
+        assert!(!trainer.missed());
+
+        assert!(Trainer::new(&pgn::import_tree("1. e4 *").unwrap(), Color::Black).is_err());
+    }
+
+    #[test]
+    fn test_recall_stats_storage() {
+        let tree = pgn::import_tree(REPERTOIRE).unwrap();
+        let lines = tree.lines();
+        let mut stats = RecallStats::default();
+        stats.record(&lines[0], true);
+        stats.record(&lines[0], false);
+        stats.record(&lines[1], true);
+        assert_eq!(stats.get(&lines[0]), LineRecord { recalled: 1, missed: 1 });
+        assert_eq!(stats.get(&lines[2]), LineRecord::default());
+        assert_eq!(stats.totals(), LineRecord { recalled: 2, missed: 1 });
+        assert_eq!(RecallStats::parse(&stats.to_text()), stats);
+        assert_eq!(RecallStats::parse("e2e4=lots\ne2e4 e7e5"), RecallStats::default());
+    }
+}
@@ -0,0 +1,476 @@
+//! Post-game analysis reports.
+//!
+//! `analyze_game` replays a finished game with the engine, scoring every
+//! position and comparing each move with the engine's choice. The resulting
+//! `GameAnalysis` renders to a shareable Markdown or HTML report with the
+//! annotated move list, an evaluation graph and a board diagram plus the
+//! engine's preferred move for every mistake and blunder. Reports are written
+//! next to the game's PGN file, e.g. `game.pgn` gives `game.md`.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::engine::{Analysis, Engine};
+use crate::pgn::Nag;
+use crate::position::{Color, PieceType};
+
+/// Centipawns lost for a move to count as an inaccuracy
+pub const INACCURACY_LOSS: i32 = 50;
+/// Centipawns lost for a move to count as a mistake
+pub const MISTAKE_LOSS: i32 = 100;
+/// Centipawns lost for a move to count as a blunder
+pub const BLUNDER_LOSS: i32 = 300;
+/// Scores are clamped to this before comparing moves, so a missed mate
+/// counts as a blunder rather than as a loss of thousands of pawns
+const SCORE_CLAMP: i32 = 2000;
+/// Search scores beyond this are mates
+const MATE_THRESHOLD: i32 = 90_000;
+/// Score reported for the side to move when it is checkmated
+const MATED_SCORE: i32 = -100_000;
+/// The evaluation graph spans plus and minus this many centipawns
+const GRAPH_RANGE: i32 = 1000;
+
+/// How bad a move was compared with the engine's choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// Classifies a move by the centipawns it lost, or `None` for a good move.
+    pub fn from_loss(loss: i32) -> Option<Judgement> {
+        if loss >= BLUNDER_LOSS {
+            Some(Judgement::Blunder)
+        } else if loss >= MISTAKE_LOSS {
+            Some(Judgement::Mistake)
+        } else if loss >= INACCURACY_LOSS {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    /// The annotation symbol appended to the move (`?!`, `?` or `??`).
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+
+    /// The PGN annotation with the same symbol.
+    pub fn nag(self) -> Nag {
+        match self {
+            Judgement::Inaccuracy => Nag::Dubious,
+            Judgement::Mistake => Nag::Mistake,
+            Judgement::Blunder => Nag::Blunder,
+        }
+    }
+}
+
+/// Analysis of a single move.
+#[derive(Debug, Clone)]
+pub struct PlyAnalysis {
+    /// The board before the move was played
+    pub before: Board,
+    /// Full move number, counted from the starting position
+    pub move_number: usize,
+    pub played: Move,
+    /// The engine's choice in the same position
+    pub best: Option<Move>,
+    /// Engine score after the move, in centipawns from White's point of view
+    pub eval: i32,
+    /// Centipawns the move lost compared with the engine's choice
+    pub loss: i32,
+    pub judgement: Option<Judgement>,
+}
+
+/// A game replayed and scored by the engine.
+#[derive(Debug, Clone)]
+pub struct GameAnalysis {
+    /// Starting FEN, or `None` for the standard starting position
+    pub start_fen: Option<String>,
+    pub plies: Vec<PlyAnalysis>,
+}
+
+/// Output format of a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// File extension used for reports in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+}
+
+/// Searches a board, returning the engine's move and its score for the side to move.
+fn score_position(engine: &mut Engine, board: &Board) -> (Option<Move>, i32) {
+    match engine.best_move(board) {
+        Some(mv) => (Some(mv), engine.last_score()),
+        None if board.is_check() => (None, MATED_SCORE),
+        None => (None, 0),
+    }
+}
+
+/// The cached analysis of the position before ply `ply`, or else a search of it.
+fn cached_or_searched(cache: &[Option<Analysis>], ply: usize, engine: &mut Engine, board: &Board) -> (Option<Move>, i32) {
+    match cache.get(ply) {
+        Some(Some(analysis)) if board.legal_moves().contains(&analysis.best_move) => (Some(analysis.best_move), analysis.score),
+        _ => score_position(engine, board),
+    }
+}
+
+/// Replays a game with the engine and scores every move.
+///
+/// Every position is searched once with the engine's current time limit, so
+/// a game of N moves takes N + 1 searches.
+///
+/// # Arguments
+///
+/// * `start_fen` - The starting position, or `None` for the standard one
+/// * `moves` - The moves of the game in long algebraic notation (`e2e4`)
+/// * `engine` - The engine to analyze with
+///
+/// # Returns
+///
+/// * The analysis, or an error if the FEN or a move is invalid or illegal
+pub fn analyze_game(start_fen: Option<&str>, moves: &[&str], engine: &mut Engine) -> Result<GameAnalysis, String> {
+    analyze_game_cached(start_fen, moves, &[], engine)
+}
+
+/// Like `analyze_game`, but reuses evaluations already made during the
+/// game and only searches the positions that have none.
+///
+/// # Arguments
+///
+/// * `start_fen` - The starting position, or `None` for the standard one
+/// * `moves` - The moves of the game in long algebraic notation (`e2e4`)
+/// * `cache` - Analyses by position, the starting position first: entry `i`
+///   is the position before move `i`, and the entry after the last move is
+///   the final position. Missing entries, and entries whose best move is not
+///   legal in the position, are searched.
+/// * `engine` - The engine that searches the remaining positions
+///
+/// # Returns
+///
+/// * The analysis, or an error if the FEN or a move is invalid or illegal
+pub fn analyze_game_cached(
+    start_fen: Option<&str>,
+    moves: &[&str],
+    cache: &[Option<Analysis>],
+    engine: &mut Engine,
+) -> Result<GameAnalysis, String> {
+    let mut board = match start_fen {
+        Some(fen) => Board::from_fen(fen)?,
+        None => Board::new(),
+    };
+
+    let mut plies = Vec::with_capacity(moves.len());
+    let (mut best, mut score) = cached_or_searched(cache, 0, engine, &board);
+    for (ply, text) in moves.iter().enumerate() {
+        let played = board.parse_move(text)?;
+        let before = board.clone();
+        let move_number = before.position().fullmove_number;
+        let mover = board.side_to_move();
+        board.play(played)?;
+
+        let (next_best, next_score) = cached_or_searched(cache, ply + 1, engine, &board);
+        // Both scores from the mover's point of view
+        let score_before = score.clamp(-SCORE_CLAMP, SCORE_CLAMP);
+        let score_after = (-next_score).clamp(-SCORE_CLAMP, SCORE_CLAMP);
+        let loss = if best == Some(played) { 0 } else { (score_before - score_after).max(0) };
+
+        plies.push(PlyAnalysis {
+            before,
+            move_number,
+            played,
+            best,
+            eval: if mover == Color::White { -next_score } else { next_score },
+            loss,
+            judgement: Judgement::from_loss(loss),
+        });
+        best = next_best;
+        score = next_score;
+    }
+
+    Ok(GameAnalysis { start_fen: start_fen.map(str::to_string), plies })
+}
+
+/// Formats a White-perspective score as pawns (`+0.35`) or a mate (`#+`/`#-`).
+fn format_eval(eval: i32) -> String {
+    if eval >= MATE_THRESHOLD {
+        "#+".to_string()
+    } else if eval <= -MATE_THRESHOLD {
+        "#-".to_string()
+    } else {
+        format!("{:+.2}", eval as f32 / 100.0)
+    }
+}
+
+/// Text diagram of a board, White at the bottom.
+fn ascii_diagram(board: &Board) -> String {
+    let mut text = String::new();
+    for rank in (0..8).rev() {
+        let _ = write!(text, "{} ", rank + 1);
+        for file in 0..8 {
+            let symbol = match board.piece_at(rank * 8 + file) {
+                Some((color, piece_type)) => {
+                    let c = match piece_type {
+                        PieceType::Pawn => 'p',
+                        PieceType::Knight => 'n',
+                        PieceType::Bishop => 'b',
+                        PieceType::Rook => 'r',
+                        PieceType::Queen => 'q',
+                        PieceType::King => 'k',
+                    };
+                    if color == Color::White { c.to_ascii_uppercase() } else { c }
+                }
+                None => '.',
+            };
+            let _ = write!(text, " {}", symbol);
+        }
+        text.push('\n');
+    }
+    text.push_str("   a b c d e f g h\n");
+    text
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl PlyAnalysis {
+    /// Move number prefix, e.g. `12.` for White or `12...` for Black.
+    fn number_prefix(&self) -> String {
+        match self.before.side_to_move() {
+            Color::White => format!("{}.", self.move_number),
+            Color::Black => format!("{}...", self.move_number),
+        }
+    }
+
+    /// The move with its annotation symbol, e.g. `g8f6??`.
+    fn annotated(&self) -> String {
+        format!("{}{}", self.played, self.judgement.map_or("", Judgement::symbol))
+    }
+
+    /// Mistakes and blunders get a diagram and the engine's line in the report.
+    fn is_key_moment(&self) -> bool {
+        matches!(self.judgement, Some(Judgement::Mistake | Judgement::Blunder))
+    }
+
+    fn engine_line(&self) -> String {
+        match self.best {
+            Some(best) => format!("Engine preferred {} (lost {:.2} pawns)", best, self.loss as f32 / 100.0),
+            None => String::new(),
+        }
+    }
+}
+
+impl GameAnalysis {
+    /// The move list in PGN-like form with annotation symbols.
+    fn move_list(&self) -> String {
+        let mut text = String::new();
+        for (index, ply) in self.plies.iter().enumerate() {
+            let number = ply.number_prefix();
+            if index == 0 || ply.before.side_to_move() == Color::White {
+                let _ = write!(text, "{} ", number);
+            }
+            let _ = write!(text, "{} ", ply.annotated());
+        }
+        text.trim_end().to_string()
+    }
+
+    /// Renders the report as Markdown.
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::from("# Game report\n\n");
+        if let Some(fen) = &self.start_fen {
+            let _ = writeln!(md, "Starting position: `{}`\n", fen);
+        }
+
+        md.push_str("## Moves\n\n");
+        let _ = writeln!(md, "{}\n", self.move_list());
+
+        md.push_str("## Evaluation\n\n```\n");
+        for ply in &self.plies {
+            let eval = ply.eval.clamp(-GRAPH_RANGE, GRAPH_RANGE);
+            let bar_length = (eval.unsigned_abs() as usize * 20) / GRAPH_RANGE as usize;
+            let bar = if eval >= 0 { "+".repeat(bar_length) } else { "-".repeat(bar_length) };
+            let _ = writeln!(md, "{:>7} {:<8} {:>6} {}",
+                ply.number_prefix(), ply.annotated(), format_eval(ply.eval), bar);
+        }
+        md.push_str("```\n");
+
+        let key_moments: Vec<&PlyAnalysis> = self.plies.iter().filter(|p| p.is_key_moment()).collect();
+        if !key_moments.is_empty() {
+            md.push_str("\n## Key moments\n");
+            for ply in key_moments {
+                let _ = writeln!(md, "\n### {} {} ({})\n", ply.number_prefix(), ply.annotated(), format_eval(ply.eval));
+                let _ = writeln!(md, "```\n{}```\n", ascii_diagram(&ply.before));
+                let _ = writeln!(md, "{}", ply.engine_line());
+            }
+        }
+        md
+    }
+
+    /// Renders the report as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Game report</title>\n</head>\n<body>\n<h1>Game report</h1>\n");
+        if let Some(fen) = &self.start_fen {
+            let _ = writeln!(html, "<p>Starting position: <code>{}</code></p>", escape_html(fen));
+        }
+
+        let _ = writeln!(html, "<h2>Moves</h2>\n<p>{}</p>", escape_html(&self.move_list()));
+        let _ = writeln!(html, "<h2>Evaluation</h2>\n{}", self.eval_graph_svg());
+
+        let key_moments: Vec<&PlyAnalysis> = self.plies.iter().filter(|p| p.is_key_moment()).collect();
+        if !key_moments.is_empty() {
+            html.push_str("<h2>Key moments</h2>\n");
+            for ply in key_moments {
+                let _ = writeln!(html, "<h3>{} {} ({})</h3>", ply.number_prefix(), ply.annotated(), format_eval(ply.eval));
+                let _ = writeln!(html, "{}", diagram_html(ply));
+                let _ = writeln!(html, "<p>{}</p>", ply.engine_line());
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Evaluation after every ply as an SVG line chart, White's advantage upwards.
+    fn eval_graph_svg(&self) -> String {
+        const WIDTH: usize = 600;
+        const HEIGHT: i32 = 200;
+        let step = WIDTH as f32 / self.plies.len().max(1) as f32;
+        let points: Vec<String> = self.plies.iter().enumerate()
+            .map(|(index, ply)| {
+                let eval = ply.eval.clamp(-GRAPH_RANGE, GRAPH_RANGE);
+                let y = HEIGHT / 2 - eval * (HEIGHT / 2) / GRAPH_RANGE;
+                format!("{:.1},{}", (index + 1) as f32 * step, y)
+            })
+            .collect();
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\">\n\
+             <rect width=\"{w}\" height=\"{h}\" fill=\"#eeeeee\"/>\n\
+             <line x1=\"0\" y1=\"{mid}\" x2=\"{w}\" y2=\"{mid}\" stroke=\"#999999\"/>\n\
+             <polyline fill=\"none\" stroke=\"#333333\" stroke-width=\"2\" points=\"0,{mid} {points}\"/>\n\
+             </svg>",
+            w = WIDTH, h = HEIGHT, mid = HEIGHT / 2, points = points.join(" "))
+    }
+
+    /// Writes the report next to a game's PGN file, with the format's extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `pgn_path` - The saved game; `game.pgn` gives `game.md` or `game.html`
+    /// * `format` - Markdown or HTML
+    ///
+    /// # Returns
+    ///
+    /// * The path of the written report
+    pub fn write_next_to<P: AsRef<Path>>(&self, pgn_path: P, format: ReportFormat) -> Result<PathBuf, String> {
+        let path = pgn_path.as_ref().with_extension(format.extension());
+        let text = match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        };
+        fs::write(&path, text)
+            .map_err(|e| format!("Could not write report {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+/// Board diagram for the HTML report, highlighting the move that was played.
+#[cfg(feature = "render")]
+fn diagram_html(ply: &PlyAnalysis) -> String {
+    use crate::render::BoardRenderer;
+    BoardRenderer::new(40, Color::White).render_svg(ply.before.position(), Some(ply.played.to_u64()))
+}
+
+#[cfg(not(feature = "render"))]
+fn diagram_html(ply: &PlyAnalysis) -> String {
+    format!("<pre>{}</pre>", ascii_diagram(&ply.before))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judgement_thresholds() {
+        assert_eq!(Judgement::from_loss(0), None);
+        assert_eq!(Judgement::from_loss(INACCURACY_LOSS), Some(Judgement::Inaccuracy));
+        assert_eq!(Judgement::from_loss(MISTAKE_LOSS + 1), Some(Judgement::Mistake));
+        assert_eq!(Judgement::from_loss(BLUNDER_LOSS), Some(Judgement::Blunder));
+        for judgement in [Judgement::Inaccuracy, Judgement::Mistake, Judgement::Blunder] {
+            assert_eq!(judgement.nag().symbol(), judgement.symbol());
+        }
+        assert_eq!(format_eval(35), "+0.35");
+        assert_eq!(format_eval(-99_990), "#-");
+    }
+
+    #[test]
+    fn test_report_flags_blunder_allowing_mate() {
+        // 3...Nf6?? allows 4.Qxf7#
+        let moves = ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"];
+        let mut engine = Engine::new();
+        engine.set_max_time_ms(200);
+        let analysis = analyze_game(None, &moves, &mut engine).unwrap();
+
+        let blunder = &analysis.plies[5];
+        assert_eq!(blunder.judgement, Some(Judgement::Blunder));
+        assert!(analysis.plies[6].eval >= MATE_THRESHOLD);
+
+        let md = analysis.to_markdown();
+        assert!(md.contains("g8f6?? 4. h5f7"), "{}", md);
+        assert!(md.contains("### 3... g8f6??"));
+        assert!(md.contains("Engine preferred"));
+
+        let html = analysis.to_html();
+        assert!(html.contains("<polyline") && html.contains("<h3>3... g8f6??"));
+
+        let pgn = std::env::temp_dir().join(format!("rustchess_report_{}.pgn", std::process::id()));
+        let path = analysis.write_next_to(&pgn, ReportFormat::Html).unwrap();
+        assert_eq!(path.extension().unwrap(), "html");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_cached_evaluations_are_reused() {
+        let mut board = Board::new();
+        let mut cache = Vec::new();
+        for (text, score) in [("e2e4", 30), ("e7e5", -20), ("g1f3", 40)] {
+            let best_move = board.parse_move(text).unwrap();
+            cache.push(Some(Analysis { best_move, score, pv: vec![best_move], nodes: 0, depth: 1, truncated: false }));
+            board.play(best_move).unwrap();
+        }
+        let mut engine = Engine::new();
+        engine.set_max_time_ms(50);
+        let analysis = analyze_game_cached(None, &["e2e4", "e7e5"], &cache, &mut engine).unwrap();
+        assert_eq!(analysis.plies.iter().map(|ply| ply.eval).collect::<Vec<_>>(), [20, 40]);
+        assert_eq!(analysis.plies[1].best.unwrap().to_string(), "e7e5");
+
+        // An entry that does not fit its position is searched instead
+        cache.swap(0, 1);
+        let analysis = analyze_game_cached(None, &["e2e4", "e7e5"], &cache[..1], &mut engine).unwrap();
+        assert_ne!(analysis.plies[0].best.unwrap().to_string(), "e7e5");
+    }
+
+    #[test]
+    fn test_analyze_rejects_illegal_move() {
+        let mut engine = Engine::new();
+        engine.set_max_time_ms(50);
+        assert!(analyze_game(None, &["e2e5"], &mut engine).is_err());
+    }
+}
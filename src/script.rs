@@ -0,0 +1,277 @@
+//! Non-interactive command scripts.
+//!
+//! A lighter alternative to UCI for test scripts and internal tooling: one
+//! command per line, read from a file or a pipe, each answered with exactly
+//! one line that is easy to parse. Searches run on the calling thread, so
+//! output always follows input order.
+//!
+//! ```text
+//! position startpos            -> ok
+//! position fen <fen> [moves ..] -> ok
+//! moves e2e4 Nf6               -> ok            (long algebraic or SAN)
+//! go depth 6 | go movetime 500 -> bestmove e2e4 score 35 nodes 1234 pv e2e4 e7e5
+//! eval                         -> eval 35       (static, White's point of view)
+//! legal                        -> legal a2a3 a2a4 ...
+//! perft 3                      -> nodes 8902
+//! print                        -> fen <fen>
+//! quit                         -> (stops reading)
+//! ```
+//!
+//! Blank lines and lines starting with `#` are skipped. A command that fails
+//! answers `error <message>` and leaves the position unchanged.
+//!
+//! In JSON mode every answer is instead one JSON object with the same field
+//! names plus `command`, e.g. `{"command":"perft","depth":3,"nodes":8902}`;
+//! `ok` becomes `"ok":true` and errors `"error":"<message>"`.
+
+use std::io::{BufRead, Write};
+
+use serde_json::{Map, Value};
+
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::evaluation::Evaluation;
+use crate::perft::Perft;
+use crate::notation;
+use crate::player::{Player, SearchLimits};
+use crate::uci::parse_position;
+
+/// The fields of an answer, in the order the text form prints them.
+type Fields = Vec<(&'static str, Value)>;
+
+/// Search time for a bare `go`.
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+/// Search time for `go depth N`, long enough that only the depth ends it.
+const DEPTH_MOVETIME_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Runs script commands against a board and an engine.
+pub struct Script<W: Write> {
+    board: Board,
+    engine: Engine,
+    output: W,
+    /// Answer in JSON instead of text
+    json: bool,
+    /// Commands that answered `error`
+    errors: usize,
+}
+
+impl<W: Write> Script<W> {
+    /// Creates a script runner that writes its answers to `output`.
+    pub fn new(output: W) -> Self {
+        Script { board: Board::new(), engine: Engine::new(), output, json: false, errors: 0 }
+    }
+
+    /// Switches between JSON answers and the default text answers.
+    pub fn set_json(&mut self, json: bool) {
+        self.json = json;
+    }
+
+    /// Runs every command read from `input` until `quit` or end of input.
+    ///
+    /// # Returns
+    ///
+    /// * The number of commands that failed, or an error if reading or
+    ///   writing failed
+    pub fn run<R: BufRead>(&mut self, input: R) -> Result<usize, String> {
+        for line in input.lines() {
+            let line = line.map_err(|e| format!("Could not read script: {}", e))?;
+            if !self.handle_command(&line)? {
+                break;
+            }
+        }
+        Ok(self.errors)
+    }
+
+    /// Runs a single command and writes its answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - One line of the script
+    ///
+    /// # Returns
+    ///
+    /// * `false` once `quit` has been read, or an error if the answer could
+    ///   not be written
+    pub fn handle_command(&mut self, command: &str) -> Result<bool, String> {
+        let command = command.trim();
+        let tokens: Vec<&str> = command.split_whitespace().collect();
+        let Some((&name, args)) = tokens.split_first() else {
+            return Ok(true);
+        };
+        if name.starts_with('#') {
+            return Ok(true);
+        }
+
+        let answer = match name {
+            "position" => parse_position(args, false).map(|board| {
+                self.board = board;
+                Fields::new()
+            }),
+            "moves" => self.play_moves(args).map(|()| Fields::new()),
+            "go" => self.go(args),
+            "eval" => {
+                let eval = Evaluation::new(self.board.position().clone()).evaluate_position();
+                Ok(vec![("eval", eval.into())])
+            }
+            "legal" => {
+                let moves = self.board.legal_moves().iter().map(|mv| Value::from(mv.to_string())).collect();
+                Ok(vec![("legal", Value::Array(moves))])
+            }
+            "perft" => match args.first().and_then(|depth| depth.parse::<i32>().ok()) {
+                Some(depth) => {
+                    let nodes = Perft::new().run(self.board.position(), depth);
+                    Ok(vec![("depth", depth.into()), ("nodes", nodes.into())])
+                }
+                None => Err("perft expects a depth".to_string()),
+            },
+            "print" => Ok(vec![("fen", self.board.position().to_fen().into())]),
+            "quit" => return Ok(false),
+            _ => Err(format!("Unknown command: {}", name)),
+        };
+        if answer.is_err() {
+            self.errors += 1;
+        }
+        let line = if self.json { json_line(name, answer) } else { text_line(answer) };
+        writeln!(self.output, "{}", line)
+            .and_then(|()| self.output.flush())
+            .map_err(|e| format!("Could not write answer: {}", e))?;
+        Ok(true)
+    }
+
+    /// Plays moves on a copy of the board, keeping it only if all are legal.
+    fn play_moves(&mut self, args: &[&str]) -> Result<(), String> {
+        let mut board = self.board.clone();
+        for text in args {
+            let mv = notation::parse_san(&board, text)?;
+            board.play(mv)?;
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    /// Searches the board with `depth N` or `movetime MS` as the limit.
+    fn go(&mut self, args: &[&str]) -> Result<Fields, String> {
+        let mut limits = SearchLimits::movetime_ms(DEFAULT_MOVETIME_MS);
+        for pair in args.chunks(2) {
+            let value = |text: Option<&&str>| -> Result<u64, String> {
+                text.and_then(|text| text.parse().ok()).ok_or_else(|| format!("go {} expects a number", pair[0]))
+            };
+            match pair[0] {
+                "depth" => limits = SearchLimits { depth: Some(value(pair.get(1))? as i32), ..SearchLimits::movetime_ms(DEPTH_MOVETIME_MS) },
+                "movetime" => limits = SearchLimits::movetime_ms(value(pair.get(1))?),
+                other => return Err(format!("Unknown go limit: {}", other)),
+            }
+        }
+
+        match self.engine.choose_move_analyzed(self.board.position(), &limits) {
+            (_, Some(analysis)) => Ok(vec![
+                ("bestmove", analysis.best_move.to_string().into()),
+                ("score", analysis.score.into()),
+                ("nodes", analysis.nodes.into()),
+                ("pv", analysis.pv.iter().map(|mv| Value::from(mv.to_string())).collect()),
+            ]),
+            (Some(mv), None) => Ok(vec![("bestmove", mv.to_string().into())]),
+            (None, _) => Ok(vec![("bestmove", "0000".into())]),
+        }
+    }
+}
+
+/// The text form of an answer: `ok`, `error <message>` or `key value ...`,
+/// with the items of a list separated by spaces.
+fn text_line(answer: Result<Fields, String>) -> String {
+    let fields = match answer {
+        Ok(fields) if fields.is_empty() => return "ok".to_string(),
+        Ok(fields) => fields,
+        Err(e) => return format!("error {}", e),
+    };
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    fields.iter()
+        .map(|(key, value)| match value {
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(text).collect();
+                format!("{} {}", key, items.join(" ")).trim_end().to_string()
+            }
+            value => format!("{} {}", key, text(value)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The JSON form of an answer, as one line.
+fn json_line(command: &str, answer: Result<Fields, String>) -> String {
+    let mut object = Map::new();
+    object.insert("command".to_string(), command.into());
+    match answer {
+        Ok(fields) if fields.is_empty() => {
+            object.insert("ok".to_string(), true.into());
+        }
+        Ok(fields) => object.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value))),
+        Err(e) => {
+            object.insert("error".to_string(), e.into());
+        }
+    }
+    Value::Object(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &str) -> (Vec<String>, usize) {
+        let mut output = Vec::new();
+        let errors = Script::new(&mut output).run(script.as_bytes()).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        (text.lines().map(str::to_string).collect(), errors)
+    }
+
+    #[test]
+    fn test_script_answers_every_command() {
+        let (lines, errors) = run("# Fool's mate\n\
+            position startpos moves f2f3\n\
+            moves e5 g4\n\
+            print\n\
+            go depth 2\n\
+            moves Qh4\n\
+            go movetime 50\n");
+        assert_eq!(errors, 0);
+        assert_eq!(lines[..2], ["ok", "ok"]);
+        // Move counters and en passant squares are not tracked by `make_move`
+        assert!(lines[2].starts_with("fen rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq "), "{}", lines[2]);
+        assert!(lines[3].starts_with("bestmove d8h4 score "), "{}", lines[3]);
+        assert!(lines[3].contains(" pv d8h4"), "{}", lines[3]);
+        assert_eq!(lines[4..], ["ok", "bestmove 0000"]);
+    }
+
+    #[test]
+    fn test_script_errors_leave_the_position_alone() {
+        let (lines, errors) = run("moves e4 e5 Ke3\nprint\nfly\ngo depth x\nposition fen nonsense\nquit\nprint\n");
+        assert_eq!(errors, 4);
+        assert!(lines[0].starts_with("error Illegal move"), "{}", lines[0]);
+        assert_eq!(lines[1], format!("fen {}", crate::board::START_FEN));
+        assert_eq!(lines[2], "error Unknown command: fly");
+        assert_eq!(lines[3], "error go depth expects a number");
+        assert!(lines[4].starts_with("error "));
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn test_json_answers() {
+        let mut output = Vec::new();
+        let mut script = Script::new(&mut output);
+        script.set_json(true);
+        script.run("moves e4\nperft 2\nlegal\neval\ngo depth 1\nmoves e4\n".as_bytes()).unwrap();
+        let answers: Vec<Value> = String::from_utf8(output).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(answers[0], serde_json::json!({"command": "moves", "ok": true}));
+        assert_eq!(answers[1], serde_json::json!({"command": "perft", "depth": 2, "nodes": 600}));
+        assert_eq!(answers[2]["legal"].as_array().unwrap().len(), 20);
+        assert!(answers[3]["eval"].is_i64());
+        assert!(answers[4]["bestmove"].is_string() && answers[4]["pv"].is_array() && answers[4]["score"].is_i64());
+        assert!(answers[5]["error"].as_str().unwrap().contains("Illegal move"));
+    }
+}
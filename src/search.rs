@@ -1,107 +1,883 @@
-use crate::position::Position;
+use crate::position::{Position, PieceType, Color};
 use crate::evaluation::Evaluation;
+use crate::bitbase;
+use crate::movegeneration;
 use crate::Game;
+use std::mem;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::time::{Instant, Duration};
 use crate::moveorder::MoveOrderer;
 use crate::position::Square;
 use crate::utils::{bit_scan_safe, extract_bits};
 use crate::transposition::{TranspositionTable, NodeType};
+use crate::assets::Asset;
+use crate::search_params::{SearchParams, CONFIG_ENV, DEFAULT_CONFIG_FILE};
+use crate::time_manager::{Clock, TimeManager, MOVE_OVERHEAD};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::prelude::*;
 
 const MAX_SCORE: i32 = 100000;
 const MIN_SCORE: i32 = -100000;
-const MATE_SCORE: i32 = 99000;
-const MAX_DEPTH: i32 = 4;  // Reduced from 6 to 4 to prevent stack overflow
-const MAX_QUIESCENCE_DEPTH: i32 = 4;  // Add a limit to quiescence search depth
-const TT_SIZE: usize = 32;  // 32MB transposition table
+const MAX_DEPTH: i32 = 64;  // Iterative deepening ceiling; time usually ends the search first
+const MAX_PLY: i32 = 128;  // Hard cap on alpha-beta recursion, whatever the depth
+/// Default size of the transposition table in megabytes
+pub const DEFAULT_HASH_MB: usize = 32;
+const EASY_MOVE_MIN_DEPTH: i32 = 3;  // Shallowest iteration that may end the search on an easy capture
+const EASY_MOVE_GAIN: i32 = 200;  // How far an easy capture must lift the score above the static evaluation
+const LOW_TIME_DEPTH: i32 = 4;  // Iterative deepening ceiling in the time manager's low-time mode
+const MAX_PV_LENGTH: usize = 16;  // Longest principal variation read back from the table
+const WATCHDOG_GRACE: Duration = Duration::from_millis(100);  // Slack before the watchdog gives up on the worker
+const PONDER_POLL: Duration = Duration::from_millis(5);  // How often the watchdog checks for a ponder hit
+// Alpha-beta recurses once per ply (quiescence uses an explicit stack), so
+// the worker thread gets room for MAX_PLY frames. Unoptimized builds need
+// far more per frame than release builds, hence the generous budget.
+const STACK_PER_PLY: usize = 256 * 1024;
+const SEARCH_STACK_SIZE: usize = MAX_PLY as usize * STACK_PER_PLY;
+/// Environment variable naming the hash file `Search::from_config` loads and
+/// front ends save on exit, so analysis carries over between sessions.
+pub const HASH_FILE_ENV: &str = "RUSTCHESS_HASH_FILE";
+/// Shallowest table entries worth saving; the rest are cheap to find again.
+const SAVED_HASH_DEPTH: i32 = 3;
+/// Default rate limit of `InfoThrottle`, in updates per second.
+pub const DEFAULT_INFO_RATE: u32 = 10;
+/// Skill level of full strength; lower levels cap the depth and add random
+/// root bonuses.
+pub const MAX_SKILL_LEVEL: u32 = 20;
+/// Centipawns of random root bonus added per skill level below the maximum.
+const SKILL_VARIETY_STEP: i32 = 15;
 
+/// Iterative deepening alpha-beta search with its own transposition table,
+/// limited by time, depth or the stop flag.
 #[derive(Clone)]
 pub struct Search {
     nodes_searched: u64,
+    /// Of `nodes_searched`, those visited by the quiescence search
+    quiescence_nodes: u64,
+    /// The last search stopped at `params.max_nodes`
+    truncated: bool,
+    /// The last search overran the watchdog and was abandoned
+    abandoned: bool,
     start_time: Instant,
     max_time: Duration,
     game: Game,
     move_orderer: MoveOrderer,
     tt: TranspositionTable,
+    /// Size of `tt` in megabytes
+    hash_mb: usize,
+    stop: Arc<AtomicBool>,
+    /// Set while pondering; time limits wait until it is cleared
+    pondering: Arc<AtomicBool>,
+    max_depth: i32,
+    seldepth: i32,
+    completed_depth: i32,
+    best_score: i32,
+    params: SearchParams,
+    /// File `params` were read from, for `reload_config`
+    config_path: Option<PathBuf>,
+    /// Hash file named by `HASH_FILE_ENV`, for `hash_file`
+    hash_path: Option<PathBuf>,
+    /// Set when playing on a clock; `max_time` is then its maximum
+    time_manager: Option<TimeManager>,
+    /// Time kept back from the clock on every move
+    move_overhead: Duration,
+    /// 0 to `MAX_SKILL_LEVEL`
+    skill_level: u32,
+    /// Draws the root move bonuses of `params.root_variety`
+    rng: StdRng,
+    /// Root moves the search is restricted to; empty allows all
+    search_moves: Vec<u64>,
+    /// Root moves the search never chooses
+    excluded_moves: Vec<u64>,
+    /// Receives an `IterationInfo` after every completed iteration, and
+    /// during iterations as `info_throttle` allows
+    info_sender: Option<mpsc::Sender<IterationInfo>>,
+    info_throttle: InfoThrottle,
+    /// Time the worker sleeps before searching, to overrun the watchdog
+    #[cfg(test)]
+    stall: Duration,
+}
+
+/// What one iteration of the search found, for front ends that show the
+/// search as it deepens; see `Search::set_info_sender`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationInfo {
+    /// Whether the iteration is finished. Unfinished ones report the best
+    /// root move so far, when it changes or as a periodic update.
+    pub complete: bool,
+    pub depth: i32,
+    pub seldepth: i32,
+    /// In centipawns from the point of view of the side to move
+    pub score: i32,
+    /// The best move followed by the expected replies
+    pub pv: Vec<u64>,
+    /// Nodes searched since the search started
+    pub nodes: u64,
+    /// Time since the search started
+    pub time: Duration,
+}
+
+impl IterationInfo {
+    /// Moves to mate when `score` is a mate: positive when the side to move
+    /// mates, negative when it is mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        let plies = MAX_SCORE - self.score.abs();
+        (plies <= MAX_PLY).then(|| (plies + 1) / 2 * self.score.signum())
+    }
+}
+
+/// Limits how often the search sends `IterationInfo`, so deep searches do
+/// not flood the GUI channel or the UCI pipe. Completed iterations and
+/// changes of the best move always go out; other updates at most
+/// `max_per_second` times a second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InfoThrottle {
+    max_per_second: u32,
+    last_sent: Option<Instant>,
+    /// Best move of the last update sent
+    last_best: Option<u64>,
+}
+
+impl InfoThrottle {
+    /// A throttle passing at most `max_per_second` periodic updates a
+    /// second; 0 passes only the ones that are always sent.
+    pub fn new(max_per_second: u32) -> Self {
+        InfoThrottle { max_per_second, last_sent: None, last_best: None }
+    }
+
+    pub fn max_per_second(&self) -> u32 {
+        self.max_per_second
+    }
+
+    /// Decides whether an update goes out at `now`, and if so remembers it.
+    ///
+    /// # Arguments
+    ///
+    /// * `complete` - The update finishes an iteration
+    /// * `best` - The best move it reports
+    /// * `now` - The current time
+    pub fn allow(&mut self, complete: bool, best: u64, now: Instant) -> bool {
+        let due = self.max_per_second > 0 && self.last_sent.is_none_or(|last| {
+            now.duration_since(last) >= Duration::from_secs(1) / self.max_per_second
+        });
+        let send = complete || self.last_best != Some(best) || due;
+        if send {
+            self.last_sent = Some(now);
+            self.last_best = Some(best);
+        }
+        send
+    }
+
+    /// Forgets the last update, for a new search.
+    fn reset(&mut self) {
+        self.last_sent = None;
+        self.last_best = None;
+    }
+}
+
+impl Default for InfoThrottle {
+    fn default() -> Self {
+        InfoThrottle::new(DEFAULT_INFO_RATE)
+    }
+}
+
+/// One node of the iterative quiescence search
+struct QuiescenceFrame {
+    position: Position,
+    alpha: i32,
+    beta: i32,
+    depth: i32,
+    /// Captures, and quiet checks at the first ply if `quiescence_checks` is
+    /// on; in check, every evasion
+    captures: Vec<u64>,
+    next: usize,
 }
 
 impl Search {
+    /// Creates a search with the default parameters and an empty hash table.
+    /// Nothing is read from disk or the environment; see `from_config`.
     pub fn new() -> Self {
-        Self {
+        let mut search = Self {
             nodes_searched: 0,
+            quiescence_nodes: 0,
+            truncated: false,
+            abandoned: false,
             start_time: Instant::now(),
             max_time: Duration::from_secs(5),
             game: Game::new(),
             move_orderer: MoveOrderer::new(),
-            tt: TranspositionTable::new(TT_SIZE),
+            tt: TranspositionTable::new(DEFAULT_HASH_MB),
+            hash_mb: DEFAULT_HASH_MB,
+            stop: Arc::new(AtomicBool::new(false)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            max_depth: MAX_DEPTH,
+            seldepth: 0,
+            completed_depth: 0,
+            best_score: 0,
+            params: SearchParams::default(),
+            config_path: None,
+            hash_path: None,
+            time_manager: None,
+            move_overhead: MOVE_OVERHEAD,
+            skill_level: MAX_SKILL_LEVEL,
+            rng: StdRng::seed_from_u64(0),
+            search_moves: Vec::new(),
+            excluded_moves: Vec::new(),
+            info_sender: None,
+            info_throttle: InfoThrottle::default(),
+            #[cfg(test)]
+            stall: Duration::ZERO,
+        };
+        search.seed_rng();
+        search
+    }
+
+    /// Creates a search with the parameters from the file named by
+    /// `RUSTCHESS_SEARCH_CONFIG`, or `search.toml` if it exists, and the
+    /// table from the hash file named by `RUSTCHESS_HASH_FILE`, which front
+    /// ends save to on exit.
+    ///
+    /// # Returns
+    ///
+    /// * The search, and an error for each file that could not be read; the
+    ///   parameters or table it would have filled keep their defaults
+    pub fn from_config() -> (Self, Vec<String>) {
+        let hash_file = std::env::var_os(HASH_FILE_ENV).map(PathBuf::from);
+        Search::with_files(Asset::SearchConfig.locate().as_deref(), hash_file)
+    }
+
+    /// Like `from_config`, with the parameter and hash files given.
+    fn with_files(config: Option<&Path>, hash_file: Option<PathBuf>) -> (Self, Vec<String>) {
+        let mut search = Search::new();
+        let mut errors = Vec::new();
+        if let Some(path) = config {
+            if let Err(e) = search.load_config(path) {
+                errors.push(format!("Using default search parameters: {}", e));
+            }
         }
+        // A missing hash file is normal on the first run and created on save
+        if let Some(path) = hash_file {
+            if path.exists() {
+                if let Err(e) = search.load_hash(&path) {
+                    errors.push(format!("Starting with an empty hash table: {}", e));
+                }
+            }
+            search.hash_path = Some(path);
+        }
+        (search, errors)
+    }
+
+    /// Pruning, reduction and extension settings
+    pub fn params(&self) -> &SearchParams {
+        &self.params
+    }
+
+    /// Replaces the search parameters; takes effect from the next search
+    pub fn set_params(&mut self, params: SearchParams) {
+        self.params = params;
+        self.seed_rng();
+    }
+
+    /// Restarts the root move bonuses from `params.variety_seed`, or from
+    /// the clock if it is 0
+    fn seed_rng(&mut self) {
+        let seed = match self.params.variety_seed {
+            0 => SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64),
+            seed => seed,
+        };
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Reads the search parameters from a TOML file and remembers the path
+    /// for `reload_config`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The parameter file
+    ///
+    /// # Returns
+    ///
+    /// * An error if the file cannot be read or parsed; the parameters are
+    ///   then left unchanged
+    pub fn load_config(&mut self, path: &Path) -> Result<(), String> {
+        self.set_params(SearchParams::load(path)?);
+        self.config_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Reads the parameter file loaded last again, so edits apply without
+    /// restarting.
+    ///
+    /// # Returns
+    ///
+    /// * The file that was read, or an error if there is none or it cannot
+    ///   be parsed; the parameters are then left unchanged
+    pub fn reload_config(&mut self) -> Result<PathBuf, String> {
+        let path = self.config_path.clone()
+            .ok_or_else(|| format!("No search config loaded; set {} or create {}", CONFIG_ENV, DEFAULT_CONFIG_FILE))?;
+        self.load_config(&path)?;
+        Ok(path)
+    }
+
+    /// Fills the transposition table from a hash file saved by `save_hash`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The hash file
+    ///
+    /// # Returns
+    ///
+    /// * The number of entries read, or an error if the file cannot be read
+    ///   or was written by another version of the engine; the table then
+    ///   keeps what it had
+    pub fn load_hash(&mut self, path: &Path) -> Result<usize, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        self.tt.load_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the deeper entries of the transposition table to a hash file,
+    /// so a later session remembers this one's analysis.
+    ///
+    /// The file is written to a temporary path first and then renamed, so an
+    /// interrupted save never leaves a truncated file behind.
+    ///
+    /// # Returns
+    ///
+    /// * The number of bytes written, or an error if the file cannot be written
+    pub fn save_hash(&self, path: &Path) -> Result<usize, String> {
+        let bytes = self.tt.to_bytes(SAVED_HASH_DEPTH);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, &bytes)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        Ok(bytes.len())
+    }
+
+    /// The hash file named by `RUSTCHESS_HASH_FILE` when the search was
+    /// created, which front ends save to on exit
+    pub fn hash_file(&self) -> Option<&Path> {
+        self.hash_path.as_deref()
+    }
+
+    /// Size of the transposition table in megabytes
+    pub fn hash_size(&self) -> usize {
+        self.hash_mb
+    }
+
+    /// Replaces the transposition table with an empty one of `size_mb`
+    /// megabytes (at least one), so the search fits a memory budget.
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.hash_mb = size_mb.max(1);
+        self.tt.resize(self.hash_mb);
+    }
+
+    /// A search with the same settings and an empty hash table of `size_mb`
+    /// megabytes (at least one), for another thread to search with; unlike
+    /// `clone`, the table is not copied.
+    pub fn with_hash_size(&self, size_mb: usize) -> Search {
+        let mut search = self.fresh(size_mb.max(1));
+        search.hash_mb = size_mb.max(1);
+        search
+    }
+
+    /// Bytes the transposition table takes.
+    pub fn memory_bytes(&self) -> usize {
+        self.tt.memory_bytes()
     }
 
     pub fn set_max_time(&mut self, seconds: u64) {
         self.max_time = Duration::from_secs(seconds);
+        self.time_manager = None;
+    }
+
+    /// Like `set_max_time`, but in milliseconds for callers that need finer control
+    pub fn set_max_time_ms(&mut self, millis: u64) {
+        self.max_time = Duration::from_millis(millis);
+        self.time_manager = None;
+    }
+
+    /// Plays the next search on a clock instead of a fixed time: it stops
+    /// early when its best move is stable and thinks longer when it is not,
+    /// within a maximum that leaves time for the rest of the game.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The side to move's clock
+    /// * `position` - The position about to be searched
+    pub fn set_clock(&mut self, clock: &Clock, position: &Position) {
+        let manager = TimeManager::with_overhead(clock, position, self.move_overhead);
+        self.max_time = manager.maximum();
+        self.time_manager = Some(manager);
+    }
+
+    /// Restricts the root moves of the following searches, as UCI
+    /// `go searchmoves` does. Pass two empty lists to lift the restriction.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_moves` - The only root moves to consider; empty allows all
+    /// * `excluded_moves` - Root moves never to choose
+    pub fn set_root_moves(&mut self, search_moves: &[u64], excluded_moves: &[u64]) {
+        self.search_moves = search_moves.to_vec();
+        self.excluded_moves = excluded_moves.to_vec();
+    }
+
+    /// How long `find_best_move` may search
+    pub fn max_time(&self) -> Duration {
+        self.max_time
+    }
+
+    /// Number of nodes visited by the last call to `find_best_move`
+    pub fn nodes_searched(&self) -> u64 {
+        self.nodes_searched
+    }
+
+    /// Number of those nodes that were quiescence nodes
+    pub fn quiescence_nodes(&self) -> u64 {
+        self.quiescence_nodes
+    }
+
+    /// Whether the last search was cut short by the node ceiling,
+    /// `SearchParams::max_nodes`, rather than ending by time, depth or the
+    /// stop flag
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Whether the last search overran the watchdog, see `find_best_move`.
+    /// Its hash table and history were thrown away with it.
+    pub fn abandoned(&self) -> bool {
+        self.abandoned
+    }
+
+    /// Sends an `IterationInfo` to `sender` after every completed iteration
+    /// of later searches, and during them as the info throttle allows, or
+    /// stops reporting with `None`. The info is sent from the search thread,
+    /// so the receiver sees it while the search runs.
+    pub fn set_info_sender(&mut self, sender: Option<mpsc::Sender<IterationInfo>>) {
+        self.info_sender = sender;
+    }
+
+    /// Sets how often unfinished iterations are reported, see `InfoThrottle`.
+    pub fn set_info_throttle(&mut self, throttle: InfoThrottle) {
+        self.info_throttle = throttle;
+    }
+
+    pub fn info_throttle(&self) -> InfoThrottle {
+        self.info_throttle
+    }
+
+    /// Sends an `IterationInfo` for `best` at `depth` if there is a receiver
+    /// and the throttle lets it through.
+    fn report(&mut self, position: &Position, best: u64, depth: i32, score: i32, complete: bool) {
+        if self.info_sender.is_none() || !self.info_throttle.allow(complete, best, Instant::now()) {
+            return;
+        }
+        let info = IterationInfo {
+            complete,
+            depth,
+            seldepth: self.seldepth,
+            score,
+            pv: self.principal_variation(position, best),
+            nodes: self.nodes_searched,
+            time: self.start_time.elapsed(),
+        };
+        if let Some(sender) = &self.info_sender {
+            let _ = sender.send(info);
+        }
+    }
+
+    /// Sets the time kept back from the clock on every move, for the move
+    /// to reach the opponent; takes effect from the next `set_clock`.
+    pub fn set_move_overhead(&mut self, overhead: Duration) {
+        self.move_overhead = overhead;
+    }
+
+    /// Weakens the search below `MAX_SKILL_LEVEL`: each level down searches
+    /// one ply less every other level, from at most 10 plies down to 1, and
+    /// adds `SKILL_VARIETY_STEP` centipawns to the random root bonuses.
+    pub fn set_skill_level(&mut self, level: u32) {
+        self.skill_level = level.min(MAX_SKILL_LEVEL);
+    }
+
+    /// Playing strength, from 0 to `MAX_SKILL_LEVEL`
+    pub fn skill_level(&self) -> u32 {
+        self.skill_level
+    }
+
+    /// Depth ceiling and root bonus of the skill level.
+    fn skill_limits(&self) -> (i32, i32) {
+        if self.skill_level >= MAX_SKILL_LEVEL {
+            return (MAX_DEPTH, 0);
+        }
+        let below = (MAX_SKILL_LEVEL - self.skill_level) as i32;
+        (1 + self.skill_level as i32 / 2, below * SKILL_VARIETY_STEP)
+    }
+
+    /// Limits iterative deepening to `depth` plies (1 to `MAX_DEPTH`)
+    pub fn set_max_depth(&mut self, depth: i32) {
+        self.max_depth = depth.clamp(1, MAX_DEPTH);
+    }
+
+    /// Depth limit of iterative deepening, in plies
+    pub fn max_depth(&self) -> i32 {
+        self.max_depth
+    }
+
+    /// Deepest ply, including quiescence, reached by the last search
+    pub fn seldepth(&self) -> i32 {
+        self.seldepth
+    }
+
+    /// Depth of the last completed iteration of the last search; 0 if none
+    /// completed
+    pub fn completed_depth(&self) -> i32 {
+        self.completed_depth
+    }
+
+    /// Score of the last completed iteration, in centipawns from the point of
+    /// view of the side to move; 0 if no iteration completed
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+
+    /// Principal variation of the last search: `best_move` followed by the
+    /// best replies stored in the transposition table, as far as they stay legal
+    pub fn principal_variation(&self, position: &Position, best_move: u64) -> Vec<u64> {
+        let mut pv = vec![best_move];
+        let mut position = position.clone();
+        position.make_move(best_move);
+        while pv.len() < MAX_PV_LENGTH {
+            position.update_all_legal_moves(&self.game);
+            let hash = position.get_hash(&self.game);
+            let Some(mov) = self.tt.probe(hash).and_then(|entry| entry.best_move) else {
+                break;
+            };
+            if !position.get_all_legal_moves(&self.game).contains(&mov) {
+                break;
+            }
+            pv.push(mov);
+            position.make_move(mov);
+        }
+        pv
+    }
+
+    /// Flag that aborts a running search when set, e.g. from another thread.
+    ///
+    /// The search never clears the flag itself; callers reset it before
+    /// starting the next search.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Flag that makes the search ponder while set: think on the opponent's
+    /// time about the position after the reply it expects, ignoring its time
+    /// limits. Clearing the flag is a ponder hit: the search goes on and
+    /// stops once its time, counted from the start of pondering, is used up.
+    /// To ponder on the wrong move instead, set the stop flag.
+    ///
+    /// Like the stop flag, the search never changes it itself.
+    pub fn ponder_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.pondering)
+    }
+
+    fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    /// The reply to `best_move` the last search expects, which is the move
+    /// to ponder on: the second move of the principal variation.
+    pub fn ponder_move(&self, position: &Position, best_move: u64) -> Option<u64> {
+        self.principal_variation(position, best_move).get(1).copied()
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+            || self.nodes_searched >= self.params.max_nodes
+            || (!self.is_pondering() && self.start_time.elapsed() >= self.max_time)
+    }
+
+    /// Whether the time manager has put the search in low-time mode, see
+    /// `TimeManager::low_time`
+    pub fn low_time(&self) -> bool {
+        self.time_manager.is_some_and(|manager| manager.low_time())
+    }
+
+    /// Whether the time manager, if any, leaves time for another iteration
+    fn time_for_iteration(&self) -> bool {
+        if self.is_pondering() {
+            return true;
+        }
+        match &self.time_manager {
+            Some(manager) => manager.should_start_iteration(self.start_time.elapsed()),
+            None => true,
+        }
+    }
+
+    /// A search with the same limits and stop flag but otherwise fresh state
+    fn fresh(&self, tt_size: usize) -> Search {
+        Search {
+            nodes_searched: 0,
+            quiescence_nodes: 0,
+            truncated: false,
+            abandoned: false,
+            start_time: Instant::now(),
+            max_time: self.max_time,
+            game: Game::new(),
+            move_orderer: MoveOrderer::new(),
+            tt: TranspositionTable::new(tt_size),
+            hash_mb: self.hash_mb,
+            stop: Arc::clone(&self.stop),
+            pondering: Arc::clone(&self.pondering),
+            max_depth: self.max_depth,
+            seldepth: 0,
+            completed_depth: 0,
+            best_score: 0,
+            params: self.params,
+            config_path: self.config_path.clone(),
+            hash_path: self.hash_path.clone(),
+            time_manager: self.time_manager,
+            move_overhead: self.move_overhead,
+            skill_level: self.skill_level,
+            rng: self.rng.clone(),
+            search_moves: self.search_moves.clone(),
+            excluded_moves: self.excluded_moves.clone(),
+            info_sender: self.info_sender.clone(),
+            info_throttle: self.info_throttle,
+            #[cfg(test)]
+            stall: self.stall,
+        }
     }
 
     /// Find the best move in the current position
+    ///
+    /// The search runs on a worker thread watched by a hard deadline of the
+    /// time limit plus `WATCHDOG_GRACE`, which waits while the search
+    /// ponders. If the worker overruns it (or panics), it is told to stop and
+    /// abandoned, and the best root move of its last completed iteration is
+    /// returned instead. The worker has a stop flag of its own, so this
+    /// search's flag is left as the caller set it; `abandoned` reports the
+    /// loss of the worker's state.
+    ///
+    /// Returns `None` when the side to move has no legal move, or none that
+    /// `set_root_moves` allows.
     pub fn find_best_move(&mut self, position: &mut Position) -> Option<u64> {
+        position.update_all_legal_moves(&self.game);
+
+        let deadline = self.max_time + WATCHDOG_GRACE;
+        let completed = Arc::new(Mutex::new(None));
+        let (sender, receiver) = mpsc::channel();
+
+        // The worker takes over this search's state and hands it back when
+        // done. Stopping it must not stop later searches, so it gets its own
+        // flag, set from this search's while it runs.
+        let mut worker = mem::replace(self, self.fresh(0));
+        let worker_stop = Arc::new(AtomicBool::new(self.stop.load(Ordering::Relaxed)));
+        worker.stop = Arc::clone(&worker_stop);
+        let mut worker_position = position.clone();
+        let worker_completed = Arc::clone(&completed);
+        thread::Builder::new()
+            .name("search".to_string())
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                let best_move = worker.search_root(&mut worker_position, &worker_completed);
+                let _ = sender.send((worker, best_move));
+            })
+            .expect("Failed to spawn search thread");
+
+        let mut deadline = Instant::now() + deadline;
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                worker_stop.store(true, Ordering::Relaxed);
+            }
+            let wait = deadline.saturating_duration_since(Instant::now()).min(PONDER_POLL);
+            match receiver.recv_timeout(wait) {
+                Ok((mut search, best_move)) => {
+                    search.stop = Arc::clone(&self.stop);
+                    *self = search;
+                    return best_move;
+                }
+                // Pondering has no time limit, and a ponder hit may end it at once
+                Err(mpsc::RecvTimeoutError::Timeout) if self.is_pondering() => {
+                    deadline = deadline.max(Instant::now() + WATCHDOG_GRACE);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+                Err(_) => {
+                    worker_stop.store(true, Ordering::Relaxed);
+                    *self = self.fresh(self.hash_mb);
+                    self.abandoned = true;
+                    let fallback = *completed.lock().unwrap();
+                    return fallback;
+                }
+            }
+        }
+    }
+
+    /// Iterative deepening at the root, publishing the best move of every
+    /// completed iteration to `completed` for the watchdog
+    fn search_root(&mut self, position: &mut Position, completed: &Mutex<Option<u64>>) -> Option<u64> {
+        #[cfg(test)]
+        thread::sleep(self.stall);
         self.nodes_searched = 0;
+        self.quiescence_nodes = 0;
+        self.truncated = false;
+        self.abandoned = false;
+        self.seldepth = 0;
+        self.completed_depth = 0;
+        self.best_score = 0;
         self.start_time = Instant::now();
+        self.info_throttle.reset();
         self.tt.new_search();  // Update age for new search
+        if let Some(manager) = &mut self.time_manager {
+            manager.start();
+        }
         
-        let mut alpha = MIN_SCORE;
-        let beta = MAX_SCORE;
-        let mut best_move = None;
-        let mut best_score = MIN_SCORE;
-
         // Update legal moves before searching
         position.update_all_legal_moves(&self.game);
         let moves = position.get_all_legal_moves(&self.game);
         
-        // Skip pieces that have been captured (position == 0) or belong to wrong color
+        // Skip moves of the wrong color's pieces
         let valid_moves: Vec<u64> = moves.into_iter()
             .filter(|&mov| {
                 let from_square = mov & 0x3F;
                 match position.squares[from_square as usize] {
                     Square::Empty => false,
                     Square::Occupied(idx) => {
-                        let piece = &position.pieces[idx];
-                        piece.position != 0 && piece.color == position.active_color
+                        position.pieces[idx].color == position.active_color
                     }
                 }
             })
+            .filter(|mov| self.search_moves.is_empty() || self.search_moves.contains(mov))
+            .filter(|mov| !self.excluded_moves.contains(mov))
             .collect();
 
         if valid_moves.is_empty() {
             return None;
         }
 
+        // On a clock there is nothing to think about with a single legal move;
+        // the time saved stays on the clock for later moves
+        let easy_moves = self.params.easy_move && self.time_manager.is_some();
+        if easy_moves && valid_moves.len() == 1 {
+            let mut after = position.clone();
+            after.make_move(valid_moves[0]);
+            self.best_score = -self.evaluate_position(&after);
+            return Some(valid_moves[0]);
+        }
+        let static_eval = self.evaluate_position(position);
+
         let ordered_moves = self.move_orderer.order_moves(position, &valid_moves, &self.game);
+        // Any legal move beats none if not even the first iteration finishes
+        *completed.lock().unwrap() = ordered_moves.first().copied();
+
+        // Random bonuses that let near-equal root moves take turns between games
+        let (skill_depth, skill_variety) = self.skill_limits();
+        let variety = self.params.root_variety.max(skill_variety);
+        let mut ordered_moves: Vec<(u64, i32)> = ordered_moves.into_iter()
+            .map(|mov| (mov, if variety > 0 { self.rng.gen_range(0..=variety) } else { 0 }))
+            .collect();
+
+        // Best move of the last fully searched iteration
+        let mut best_move = None;
+
+        // Short of time, a shallow search now beats a deep one that flags
+        let max_depth = if self.low_time() { self.max_depth.min(LOW_TIME_DEPTH) } else { self.max_depth };
+        let max_depth = max_depth.min(skill_depth);
 
         // Start with a shallower depth and gradually increase
-        for depth in 1..=MAX_DEPTH {
-            if self.start_time.elapsed() >= self.max_time {
+        for depth in 1..=max_depth {
+            if depth > 1 && (self.should_stop() || !self.time_for_iteration()) {
+                self.truncated = self.nodes_searched >= self.params.max_nodes;
                 break;
             }
 
-            let mut current_alpha = alpha;
-            for &mov in &ordered_moves {
+            // Each iteration starts from a full window and finds its own best move
+            let mut alpha = MIN_SCORE;
+            let mut iteration_best = None;
+            let mut iteration_score = MIN_SCORE;
+            let mut interrupted = false;
+            for &(mov, bonus) in &ordered_moves {
                 let mut new_position = position.clone();
                 new_position.make_move(mov);
                 new_position.update_all_legal_moves(&self.game);
 
+                // With a bonus the move only has to come within it of alpha
                 let score = -self.alpha_beta(
-                    -beta,
-                    -current_alpha,
+                    -MAX_SCORE,
+                    -(alpha - bonus),
                     depth - 1,
-                    0,
+                    1,
                     &mut new_position
                 );
 
-                if score > best_score {
-                    best_score = score;
-                    best_move = Some(mov);
-                    current_alpha = score;
+                // A search cut off by the clock returns a meaningless score
+                if self.should_stop() {
+                    interrupted = true;
+                    break;
                 }
+
+                if score + bonus > alpha || iteration_best.is_none() {
+                    alpha = alpha.max(score + bonus);
+                    iteration_best = Some(mov);
+                    iteration_score = score;
+                    // Having beaten the last iteration's move, searched
+                    // first, it is the move to play even if the clock stops
+                    // this iteration
+                    if best_move.is_some() {
+                        *completed.lock().unwrap() = iteration_best;
+                    }
+                }
+                // Past the first iteration, report the best move so far
+                if let (Some(best), Some(_)) = (iteration_best, best_move) {
+                    self.report(position, best, depth, iteration_score, false);
+                }
+            }
+
+            if interrupted {
+                self.truncated = self.nodes_searched >= self.params.max_nodes;
+                // The previous iteration's move is searched first, so once it
+                // is done whatever is best so far has held up at this depth
+                // too; when even depth 1 was cut short take whatever it had
+                // looked at so far
+                if iteration_best.is_some() {
+                    best_move = iteration_best;
+                    self.best_score = iteration_score;
+                } else if best_move.is_none() {
+                    best_move = ordered_moves.first().map(|&(mov, _)| mov);
+                }
+                break;
+            }
+
+            // A capture that wins back material and stays best for two
+            // iterations in a row, like recapturing a piece, is played at once
+            let easy_capture = easy_moves
+                && depth >= EASY_MOVE_MIN_DEPTH
+                && iteration_best == best_move
+                && iteration_best.is_some_and(|mov| position.is_capture(mov))
+                && iteration_score >= static_eval + EASY_MOVE_GAIN;
+
+            best_move = iteration_best;
+            self.best_score = iteration_score;
+            self.completed_depth = depth;
+            if let (Some(manager), Some(mov)) = (&mut self.time_manager, best_move) {
+                manager.record_iteration(mov, iteration_score);
+            }
+            *completed.lock().unwrap() = best_move;
+            if let Some(mov) = best_move {
+                self.report(position, mov, depth, iteration_score, true);
+            }
+
+            if easy_capture {
+                break;
+            }
+
+            // Search the current best move first in the next iteration
+            if let Some(index) = ordered_moves.iter().position(|&(m, _)| Some(m) == best_move) {
+                let mov = ordered_moves.remove(index);
+                ordered_moves.insert(0, mov);
             }
-            alpha = current_alpha;
         }
 
         best_move
@@ -116,19 +892,22 @@ impl Search {
         ply_from_root: i32,
         position: &mut Position
     ) -> i32 {
-        if ply_from_root >= MAX_DEPTH * 2 {
+        if ply_from_root >= MAX_PLY {
             return self.evaluate_position(position);
         }
 
         self.nodes_searched += 1;
+        self.seldepth = self.seldepth.max(ply_from_root);
 
-        if self.start_time.elapsed() >= self.max_time {
+        if self.should_stop() {
             return 0;
         }
 
         // Probe transposition table
         let hash = position.get_hash(&self.game);
+        let mut tt_move = None;
         if let Some(entry) = self.tt.probe(hash) {
+            tt_move = entry.best_move;
             if entry.depth >= depth {
                 match entry.flag {
                     NodeType::Exact => return entry.value,
@@ -139,54 +918,94 @@ impl Search {
             }
         }
 
+        let in_check = position.is_in_check(&self.game);
+        let depth = if self.params.check_extension && in_check { depth + 1 } else { depth };
+
         if depth <= 0 {
-            return self.quiescence(alpha, beta, 0, position);
+            return self.quiescence(alpha, beta, ply_from_root, position);
         }
 
-        position.update_all_legal_moves(&self.game);
-        let moves = position.get_all_legal_moves(&self.game);
-        
-        // Filter valid moves
-        let valid_moves: Vec<u64> = moves.into_iter()
-            .filter(|&mov| {
-                let from_square = mov & 0x3F;
-                match position.squares[from_square as usize] {
-                    Square::Empty => false,
-                    Square::Occupied(idx) => {
-                        let piece = &position.pieces[idx];
-                        piece.position != 0 && piece.color == position.active_color
-                    }
-                }
-            })
-            .collect();
+        // Null move: if passing still fails high, a real move will too. Not
+        // tried in check, where passing is illegal, or with only pawns left,
+        // where zugzwang makes passing look better than it is.
+        let null_move_reduction = self.params.null_move_reduction;
+        if null_move_reduction > 0 && depth >= self.params.null_move_min_depth && !in_check && Self::has_pieces(position) {
+            let mut null_position = position.clone();
+            null_position.active_color = if position.active_color == Color::White { Color::Black } else { Color::White };
+            null_position.en_passant = None;
+            null_position.update_all_legal_moves(&self.game);
+            let score = -self.alpha_beta(-beta, -beta + 1, depth - 1 - null_move_reduction, ply_from_root + 1, &mut null_position);
+            if score >= beta && !self.should_stop() {
+                return beta;
+            }
+        }
 
+        // Quiet moves one ply from the horizon that cannot lift the score to
+        // alpha even with the margin are not worth searching
+        let futile_eval = if self.params.futility_margin > 0 && depth == 1 && !in_check {
+            Some(self.evaluate_position(position) + self.params.futility_margin)
+        } else {
+            None
+        };
+        let reduce_late_moves = self.params.lmr_reduction > 0 && depth >= self.params.lmr_min_depth && !in_check;
+
+        let valid_moves = self.node_moves(position, in_check);
         if valid_moves.is_empty() {
-            if position.is_in_check(&self.game) {
+            if in_check {
                 return MIN_SCORE + ply_from_root; // Prefer faster mate
             }
             return 0; // Stalemate
         }
 
-        let ordered_moves = self.move_orderer.order_moves(position, &valid_moves, &self.game);
+        let mut ordered_moves = self.move_orderer.order_moves(position, &valid_moves, &self.game);
+        // Short of time, trust the table's move over the ordering heuristics
+        if let Some(index) = tt_move.filter(|_| self.low_time()).and_then(|mov| ordered_moves.iter().position(|&m| m == mov)) {
+            let mov = ordered_moves.remove(index);
+            ordered_moves.insert(0, mov);
+        }
         let mut best_move = None;
         let old_alpha = alpha;
 
-        for &mov in &ordered_moves {
+        for (index, &mov) in ordered_moves.iter().enumerate() {
+            let quiet = !position.is_capture(mov) && !position.is_promotion(mov);
+            // Checks are never pruned or reduced
+            let prunable = quiet && (futile_eval.is_some() || reduce_late_moves) && !position.gives_check(&self.game, mov);
             let mut new_position = position.clone();
             new_position.make_move(mov);
+
+            if prunable && futile_eval.is_some_and(|eval| eval <= alpha) {
+                continue;
+            }
             new_position.update_all_legal_moves(&self.game);
 
-            let score = -self.alpha_beta(
-                -beta,
-                -alpha,
-                depth - 1,
-                ply_from_root + 1,
-                &mut new_position
-            );
+            // Late quiet moves are searched shallower with a null window first,
+            // and again at full depth only if they turn out to beat alpha
+            let reduced = prunable && reduce_late_moves && index >= self.params.lmr_min_moves;
+            let mut score = alpha + 1;
+            if reduced {
+                score = -self.alpha_beta(
+                    -alpha - 1,
+                    -alpha,
+                    depth - 1 - self.params.lmr_reduction,
+                    ply_from_root + 1,
+                    &mut new_position
+                );
+            }
+            if score > alpha {
+                score = -self.alpha_beta(
+                    -beta,
+                    -alpha,
+                    depth - 1,
+                    ply_from_root + 1,
+                    &mut new_position
+                );
+            }
 
             if score >= beta {
                 // Store beta cutoff in transposition table
-                self.tt.store(hash, depth, NodeType::Beta, beta, Some(mov));
+                if !self.should_stop() {
+                    self.tt.store(hash, depth, NodeType::Beta, beta, Some(mov));
+                }
                 return beta;
             }
             if score > alpha {
@@ -195,66 +1014,182 @@ impl Search {
             }
         }
 
-        // Store position in transposition table
-        let node_type = if alpha > old_alpha {
-            NodeType::Exact
-        } else {
-            NodeType::Alpha
-        };
-        self.tt.store(hash, depth, node_type, alpha, best_move);
+        // Store position in transposition table. Once stopped, scores rest
+        // on the 0 of unfinished subtrees and must not reach the table, or
+        // a saved hash file.
+        if !self.should_stop() {
+            let node_type = if alpha > old_alpha {
+                NodeType::Exact
+            } else {
+                NodeType::Alpha
+            };
+            self.tt.store(hash, depth, node_type, alpha, best_move);
+        }
 
         alpha
     }
 
+    /// The legal moves of the side to move at a search node. In check only
+    /// the evasions are generated, rather than every move.
+    fn node_moves(&self, position: &mut Position, in_check: bool) -> Vec<u64> {
+        if in_check {
+            return movegeneration::generate_evasions(position, &self.game);
+        }
+        position.update_all_legal_moves(&self.game);
+        let moves = position.get_all_legal_moves(&self.game);
+        
+        // Filter valid moves
+        moves.into_iter()
+            .filter(|&mov| {
+                let from_square = mov & 0x3F;
+                match position.squares[from_square as usize] {
+                    Square::Empty => false,
+                    Square::Occupied(idx) => {
+                        position.pieces[idx].color == position.active_color
+                    }
+                }
+            })
+            .collect()
+    }
+
     /// Quiescence search to handle tactical sequences
+    ///
+    /// Runs on an explicit stack of frames rather than recursing, so capture
+    /// sequences never add to the thread's stack depth.
     fn quiescence(
+        &mut self,
+        alpha: i32,
+        beta: i32,
+        ply_from_root: i32,
+        position: &Position
+    ) -> i32 {
+        let root = match self.enter_quiescence(alpha, beta, 0, ply_from_root, position.clone()) {
+            Ok(frame) => frame,
+            Err(score) => return score,
+        };
+        let mut stack = vec![root];
+        // Score returned by the most recently finished child node
+        let mut child_score: Option<i32> = None;
+
+        while let Some(frame) = stack.last_mut() {
+            if let Some(score) = child_score.take() {
+                let score = -score;
+                if score >= frame.beta {
+                    child_score = Some(frame.beta);
+                    stack.pop();
+                    continue;
+                }
+                frame.alpha = frame.alpha.max(score);
+            }
+
+            if frame.next < frame.captures.len() {
+                let mov = frame.captures[frame.next];
+                frame.next += 1;
+                let mut new_position = frame.position.clone();
+                new_position.make_move(mov);
+
+                let (child_alpha, child_beta, depth) = (-frame.beta, -frame.alpha, frame.depth + 1);
+                let ply = ply_from_root + depth;
+                match self.enter_quiescence(child_alpha, child_beta, depth, ply, new_position) {
+                    Ok(child) => stack.push(child),
+                    Err(score) => child_score = Some(score),
+                }
+            } else {
+                child_score = Some(frame.alpha);
+                stack.pop();
+            }
+        }
+
+        child_score.unwrap_or(alpha)
+    }
+
+    /// Opens a quiescence node, or scores it straight away if it needs no search
+    fn enter_quiescence(
         &mut self,
         mut alpha: i32,
         beta: i32,
         depth: i32,  // Add depth parameter to limit quiescence search
-        position: &mut Position
-    ) -> i32 {
+        ply_from_root: i32,
+        mut position: Position
+    ) -> Result<QuiescenceFrame, i32> {
         // Limit quiescence search depth
-        if depth >= MAX_QUIESCENCE_DEPTH {
-            return self.evaluate_position(position);
+        if depth >= self.params.quiescence_depth {
+            return Err(self.evaluate_position(&position));
         }
 
         self.nodes_searched += 1;
+        self.quiescence_nodes += 1;
+        self.seldepth = self.seldepth.max(ply_from_root);
 
-        let stand_pat = self.evaluate_position(position);
+        // A bare king may have no moves left. The static evaluation can't see
+        // that, and would score the stalemate as a win for the other side.
+        if Self::has_bare_king(&position) {
+            position.update_all_legal_moves(&self.game);
+            if position.get_all_legal_moves(&self.game).is_empty() {
+                // Mates are scored as in `alpha_beta`, nearer ones worse
+                return Err(if position.is_in_check(&self.game) { MIN_SCORE + ply_from_root } else { 0 });
+            }
+        }
+
+        // In check there is no standing pat: every evasion is searched, and
+        // without one the side to move is mated
+        if position.is_in_check(&self.game) {
+            let evasions = self.node_moves(&mut position, true);
+            if evasions.is_empty() {
+                return Err(MIN_SCORE + ply_from_root);
+            }
+            return Ok(QuiescenceFrame { position, alpha, beta, depth, captures: evasions, next: 0 });
+        }
+
+        let stand_pat = self.evaluate_position(&position);
 
         if stand_pat >= beta {
-            return beta;
+            return Err(beta);
         }
 
         alpha = alpha.max(stand_pat);
 
         position.update_all_legal_moves(&self.game);
-        let captures = position.get_captures(&self.game);
-
-        for &mov in &captures {
-            let mut new_position = position.clone();
-            new_position.make_move(mov);
+        let mut captures = position.get_captures(&self.game);
+        // Captures that lose material by static exchange are not worth a
+        // search; promotions are kept whatever the exchange
+        let low_time = self.low_time();
+        if self.params.quiescence_see_pruning || low_time {
+            let threshold = self.params.quiescence_see_threshold;
+            captures.retain(|&mov| position.is_promotion(mov) || position.see(&self.game, mov) >= threshold);
+        }
+        // Quiet checks at the first ply catch threats a capture-only search
+        // would stand pat against; short of time they are skipped
+        if depth == 0 && self.params.quiescence_checks && !low_time {
+            let checks = movegeneration::generate_checks(&position, &self.game).into_iter()
+                .filter(|&mov| !position.is_capture(mov) && !position.is_promotion(mov));
+            captures.extend(checks);
+        }
 
-            let score = -self.quiescence(
-                -beta,
-                -alpha,
-                depth + 1,
-                &mut new_position
-            );
+        Ok(QuiescenceFrame { position, alpha, beta, depth, captures, next: 0 })
+    }
 
-            if score >= beta {
-                return beta;
-            }
-            alpha = alpha.max(score);
-        }
+    /// Whether the side to move has nothing left but its king
+    fn has_bare_king(position: &Position) -> bool {
+        position.pieces.iter()
+            .filter(|p| p.color == position.active_color)
+            .all(|p| p.piece_type == PieceType::King)
+    }
 
-        alpha
+    /// Whether the side to move has anything besides pawns and its king
+    fn has_pieces(position: &Position) -> bool {
+        position.pieces.iter()
+            .any(|p| p.color == position.active_color
+                && !matches!(p.piece_type, PieceType::King | PieceType::Pawn))
     }
 
+    /// Static evaluation relative to the side to move, as negamax expects
     fn evaluate_position(&self, position: &Position) -> i32 {
-        let evaluation = Evaluation::new(position.clone());
-        evaluation.evaluate_position()
+        let score = match bitbase::probe_position(position) {
+            Some(score) => score,
+            None => Evaluation::new(position.clone()).evaluate_position(),
+        };
+        if position.active_color == Color::White { score } else { -score }
     }
 }
 
@@ -262,6 +1197,10 @@ impl Search {
 mod tests {
     use super::*;
     use crate::Game;
+    use crate::chess_move::Move;
+
+    /// Scores at least this far from 0 are mates
+    const MATE_SCORE: i32 = 99000;
 
     #[test]
     fn test_mate_in_one() {
@@ -293,9 +1232,6 @@ mod tests {
         
         // Print each piece's position and legal moves
         for (i, piece) in position.pieces.iter().enumerate() {
-            if piece.position == 0 {
-                continue;
-            }
             if piece.color == position.active_color {
                 println!("Piece {}: {:?} {:?} at square {}, legal moves: {:?}", 
                     i, piece.color, piece.piece_type, 
@@ -335,8 +1271,9 @@ mod tests {
     #[test]
     fn test_avoid_mate() {
         let game = Game::new();
+        // Black threatens Qh4# if White plays g4
         let mut position = Position::read_FEN(
-            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq - 0 2",
             &game
         );
         let mut search = Search::new();
@@ -348,6 +1285,510 @@ mod tests {
         assert!(best_move.is_some());
     }
 
+    #[test]
+    fn test_no_move_when_checkmated() {
+        let game = Game::new();
+        let mut position = Position::read_FEN(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 0 1",
+            &game
+        );
+        let mut search = Search::new();
+        search.set_max_time(1);
+
+        position.update_all_legal_moves(&game);
+        assert!(search.find_best_move(&mut position).is_none());
+    }
+
+    #[test]
+    fn test_returns_within_deadline() {
+        let game = Game::new();
+        let mut position = Position::read_FEN(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+            &game
+        );
+        let mut search = Search::new();
+        search.set_max_time_ms(0);
+
+        let start = Instant::now();
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(position.get_all_legal_moves(&game).contains(&best_move));
+
+        // The search is still usable afterwards
+        search.set_max_time_ms(200);
+        assert!(search.find_best_move(&mut position).is_some());
+    }
+
+    #[test]
+    fn test_deeper_iteration_overturns_shallow_choice() {
+        let game = Game::new();
+        // Rxd5 wins a pawn at depth 1 but loses to Ra1+ Rd1 Rxd1# once
+        // the search sees far enough
+        let mut position = Position::read_FEN("r5k1/5ppp/8/3p4/8/8/5PPP/3R2K1 w - - 0 1", &game);
+        let mut search = Search::new();
+        search.set_max_depth(5);
+        search.set_max_time(30);
+
+        let best_move = search.find_best_move(&mut position).unwrap();
+        let rook_takes_pawn = 3 | (35 << 6);
+        assert_ne!(best_move, rook_takes_pawn);
+    }
+
+    #[test]
+    fn test_finds_capture_promotion() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", &game);
+        position.update_all_legal_moves(&game);
+        let captures = position.get_captures(&game);
+        assert!(captures.contains(&Move::encode(49, 56, Some(PieceType::Queen))));
+        assert!(captures.contains(&Move::encode(49, 56, Some(PieceType::Knight))));
+
+        let mut search = Search::new();
+        search.set_max_depth(3);
+        search.set_max_time(30);
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert_eq!(best_move, Move::encode(49, 56, Some(PieceType::Queen)));
+    }
+
+    #[test]
+    fn test_finds_knight_underpromotion_fork() {
+        let game = Game::new();
+        // d8=N+ forks the king and the queen on b7; d8=Q wins nothing
+        let mut position = Position::read_FEN("8/1q1P1k2/8/8/8/7K/8/8 w - - 0 1", &game);
+        let mut search = Search::new();
+        search.set_max_depth(3);
+        search.set_max_time(30);
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert_eq!(best_move, Move::encode(51, 59, Some(PieceType::Knight)));
+    }
+
+    #[test]
+    fn test_depth_12_search_is_stack_safe() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("8/8/4k3/8/2K5/3P4/8/8 w - - 0 1", &game);
+        let mut search = Search::new();
+        search.set_max_depth(12);
+        search.set_max_time(60);
+
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert!(position.get_all_legal_moves(&game).contains(&best_move));
+        assert!(search.seldepth() >= 12, "only reached ply {}", search.seldepth());
+    }
+
+    #[test]
+    fn test_quiescence_options() {
+        let game = Game::new();
+        let mut search = Search::new();
+        // Qxd5 loses the queen to the e6 pawn, so pruning it by SEE saves
+        // nodes without changing the score
+        let position = Position::read_FEN("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", &game);
+        search.set_params(SearchParams::default());
+        let plain = search.quiescence(MIN_SCORE, MAX_SCORE, 0, &position);
+        let plain_nodes = search.quiescence_nodes();
+        search.quiescence_nodes = 0;
+        search.set_params(SearchParams { quiescence_see_pruning: true, ..SearchParams::default() });
+        assert_eq!(search.quiescence(MIN_SCORE, MAX_SCORE, 0, &position), plain);
+        assert!(search.quiescence_nodes() < plain_nodes);
+
+        // Without captures only the checks are searched
+        let position = Position::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &game);
+        search.quiescence_nodes = 0;
+        search.quiescence(MIN_SCORE, MAX_SCORE, 0, &position);
+        assert_eq!(search.quiescence_nodes(), 1);
+        search.quiescence_nodes = 0;
+        search.set_params(SearchParams { quiescence_checks: true, ..SearchParams::default() });
+        search.quiescence(MIN_SCORE, MAX_SCORE, 0, &position);
+        assert!(search.quiescence_nodes() > 1);
+    }
+
+    #[test]
+    fn test_with_hash_size_starts_an_empty_table() {
+        let game = Game::new();
+        let mut search = Search::new();
+        search.set_max_depth(3);
+        search.set_max_time(30);
+        search.find_best_move(&mut game.position.clone()).unwrap();
+
+        let worker = search.with_hash_size(DEFAULT_HASH_MB / 4);
+        assert_eq!(worker.hash_size(), DEFAULT_HASH_MB / 4);
+        assert!(worker.memory_bytes() <= (DEFAULT_HASH_MB / 4) << 20);
+        assert_eq!(worker.params(), search.params());
+        // Nothing is copied from the searched table
+        assert_eq!(worker.tt.to_bytes(0), TranspositionTable::new(1).to_bytes(0));
+        assert!(search.tt.to_bytes(0).len() > worker.tt.to_bytes(0).len());
+        assert_eq!(search.with_hash_size(0).hash_size(), 1);
+    }
+
+    #[test]
+    fn test_hash_file_carries_analysis_over() {
+        let game = Game::new();
+        let path = std::env::temp_dir().join(format!("rustchess-hash-{}.bin", std::process::id()));
+        let mut position = game.position.clone();
+        let mut search = Search::new();
+        search.set_max_depth(4);
+        search.set_max_time(30);
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert!(search.save_hash(&path).unwrap() > 0);
+
+        // The reply to the best move was searched three plies deep
+        let mut after = position.clone();
+        after.make_move(best_move);
+        let mut later = Search::new();
+        assert!(later.tt.probe(after.get_hash(&game)).is_none());
+        assert!(later.load_hash(&path).unwrap() > 0);
+        assert!(later.tt.probe(after.get_hash(&game)).is_some_and(|entry| entry.depth >= SAVED_HASH_DEPTH));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(later.load_hash(&path).is_err());
+    }
+
+    #[test]
+    fn test_hash_file_skips_aborted_iterations() {
+        let game = Game::new();
+        let path = std::env::temp_dir().join(format!("rustchess-aborted-{}.bin", std::process::id()));
+        let mut position = game.position.clone();
+        position.update_all_legal_moves(&game);
+        // The ceiling stops the search below the first move; the others
+        // return at once, and none of their 0 scores may be kept
+        let mut search = Search::new();
+        search.set_params(SearchParams { max_nodes: 2, ..SearchParams::default() });
+        search.start_time = Instant::now();
+        search.alpha_beta(MIN_SCORE, MAX_SCORE, SAVED_HASH_DEPTH, 0, &mut position);
+        assert!(search.should_stop());
+        assert!(search.tt.probe(position.get_hash(&game)).is_none());
+        search.save_hash(&path).unwrap();
+
+        let mut later = Search::new();
+        assert_eq!(later.load_hash(&path).unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_files_are_read_only_when_asked() {
+        let dir = std::env::temp_dir();
+        let config = dir.join(format!("rustchess-config-{}.toml", std::process::id()));
+        let hash_file = dir.join(format!("rustchess-garbage-{}.bin", std::process::id()));
+        std::fs::write(&config, "max_nodes = 12345\n").unwrap();
+        std::fs::write(&hash_file, b"not a hash file").unwrap();
+
+        let (search, errors) = Search::with_files(Some(&config), Some(hash_file.clone()));
+        assert_eq!(search.params().max_nodes, 12345);
+        assert_eq!(search.hash_file(), Some(hash_file.as_path()));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with("Starting with an empty hash table"), "{:?}", errors);
+
+        // A missing config is an error too, unlike a missing hash file
+        std::fs::remove_file(&hash_file).unwrap();
+        std::fs::remove_file(&config).unwrap();
+        let (search, errors) = Search::with_files(Some(&config), Some(hash_file.clone()));
+        assert_eq!(search.params(), &SearchParams::default());
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+
+        let search = Search::new();
+        assert_eq!((search.params(), search.hash_file()), (&SearchParams::default(), None));
+    }
+
+    #[test]
+    fn test_evasions_are_the_legal_moves_in_check() {
+        let game = Game::new();
+        for fen in [
+            "4r1k1/8/8/8/8/3n4/8/R3K3 w - - 0 1",
+            "4k3/4r3/8/8/8/2B5/3N4/R3K3 w - - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "rnbqkbnr/ppppp2p/5p2/6pQ/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 3",
+            "r1bqkbnr/pppp1Qpp/2n5/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+        ] {
+            let search = Search::new();
+            let mut position = Position::read_FEN(fen, &game);
+            assert!(position.is_in_check(&game), "{}", fen);
+            let mut all = search.node_moves(&mut position.clone(), false);
+            let mut evasions = search.node_moves(&mut position, true);
+            all.sort_unstable();
+            evasions.sort_unstable();
+            assert_eq!(evasions, all, "{}", fen);
+        }
+
+        // Quiescence does not stand pat in check; here it sees the mate
+        let position = Position::read_FEN("r1bqkbnr/pppp1Qpp/2n5/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4", &game);
+        assert_eq!(Search::new().quiescence(MIN_SCORE, MAX_SCORE, 2, &position), MIN_SCORE + 2);
+    }
+
+    #[test]
+    fn test_quiescence_sees_stalemate() {
+        let game = Game::new();
+        // Black's bare king has no moves but is not in check
+        let position = Position::read_FEN("k7/2K5/1Q6/8/8/8/8/8 b - - 0 1", &game);
+        let mut search = Search::new();
+        assert_eq!(search.quiescence(MIN_SCORE, MAX_SCORE, 0, &position), 0);
+    }
+
+    #[test]
+    fn test_quiescence_scores_mate_by_distance() {
+        let game = Game::new();
+        // Black's bare king is mated
+        let position = Position::read_FEN("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", &game);
+        let mut search = Search::new();
+        assert_eq!(search.quiescence(MIN_SCORE, MAX_SCORE, 3, &position), MIN_SCORE + 3);
+    }
+
+    #[test]
+    fn test_pruning_still_finds_mate() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", &game);
+        position.update_all_legal_moves(&game);
+
+        let mut search = Search::new();
+        search.set_params(SearchParams::from_toml(
+            "check_extension = true\nnull_move_reduction = 2\nfutility_margin = 100\nlmr_reduction = 1\nlmr_min_moves = 2"
+        ).unwrap());
+        search.set_max_depth(5);
+        search.set_max_time(30);
+        assert_eq!(search.find_best_move(&mut position), Some(Move::encode(0, 56, None)));  // Ra8#
+        assert!(search.best_score() >= MATE_SCORE);
+    }
+
+    #[test]
+    fn test_info_is_sent_for_every_iteration() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", &game);
+        let (sender, receiver) = mpsc::channel();
+        let mut search = Search::new();
+        search.set_info_sender(Some(sender));
+        search.set_max_depth(3);
+        search.set_max_time(30);
+        let best = search.find_best_move(&mut position).unwrap();
+        drop(search);
+
+        let infos: Vec<IterationInfo> = receiver.iter().filter(|info| info.complete).collect();
+        assert_eq!(infos.iter().map(|info| info.depth).collect::<Vec<_>>(), [1, 2, 3]);
+        let last = infos.last().unwrap();
+        assert_eq!(last.pv[0], best);
+        assert!(last.score >= MATE_SCORE);
+        assert!(infos.windows(2).all(|pair| pair[0].nodes <= pair[1].nodes && pair[0].time <= pair[1].time));
+    }
+
+    #[test]
+    fn test_info_throttle() {
+        let start = Instant::now();
+        let mut throttle = InfoThrottle::new(10);
+        assert!(throttle.allow(false, 1, start));
+        assert!(!throttle.allow(false, 1, start + Duration::from_millis(50)));
+        // Completed iterations and new best moves are never held back
+        assert!(throttle.allow(true, 1, start + Duration::from_millis(60)));
+        assert!(throttle.allow(false, 2, start + Duration::from_millis(70)));
+        assert!(!throttle.allow(false, 2, start + Duration::from_millis(100)));
+        assert!(throttle.allow(false, 2, start + Duration::from_millis(170)));
+
+        let mut silent = InfoThrottle::new(0);
+        assert!(silent.allow(false, 1, start));
+        assert!(!silent.allow(false, 1, start));
+        assert!(silent.allow(true, 1, start));
+    }
+
+    #[test]
+    fn test_unfinished_iterations_are_throttled() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &game);
+        let (sender, receiver) = mpsc::channel();
+        let mut search = Search::new();
+        search.set_info_sender(Some(sender));
+        search.set_info_throttle(InfoThrottle::new(0));
+        search.set_max_depth(4);
+        search.set_max_time(30);
+        search.find_best_move(&mut position);
+        drop(search);
+
+        // Without periodic updates only new best moves interrupt the iterations
+        let infos: Vec<IterationInfo> = receiver.iter().collect();
+        assert_eq!(infos.iter().filter(|info| info.complete).count(), 4);
+        let mut best = None;
+        for info in &infos {
+            if !info.complete {
+                assert_ne!(Some(info.pv[0]), best, "depth {}", info.depth);
+            }
+            best = Some(info.pv[0]);
+        }
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let path = std::env::temp_dir().join(format!("rustchess-search-{}.toml", std::process::id()));
+        let mut search = Search::new();
+
+        std::fs::write(&path, "quiescence_depth = 2\n").unwrap();
+        search.load_config(&path).unwrap();
+        assert_eq!(search.params().quiescence_depth, 2);
+
+        std::fs::write(&path, "quiescence_depth = 6\n").unwrap();
+        assert_eq!(search.reload_config().unwrap(), path);
+        assert_eq!(search.params().quiescence_depth, 6);
+
+        // A broken edit keeps the parameters that were loaded before
+        std::fs::write(&path, "quiescence_dpeth = 8\n").unwrap();
+        assert!(search.reload_config().is_err());
+        assert_eq!(search.params().quiescence_depth, 6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_clock_limits_the_search() {
+        let game = Game::new();
+        let mut position = Position::new(&game);
+        position.update_all_legal_moves(&game);
+        let mut search = Search::new();
+        search.set_clock(&Clock { remaining: Duration::from_secs(2), ..Clock::default() }, &position);
+        assert!(search.max_time() < Duration::from_secs(1));
+
+        let start = Instant::now();
+        assert!(search.find_best_move(&mut position).is_some());
+        assert!(start.elapsed() < search.max_time() + WATCHDOG_GRACE * 2, "{:?}", start.elapsed());
+
+        // A fixed time replaces the clock
+        search.set_max_time_ms(300);
+        assert!(search.time_manager.is_none());
+    }
+
+    #[test]
+    fn test_skill_level_caps_the_depth() {
+        let game = Game::new();
+        let mut search = Search::new();
+        search.set_max_time(30);
+        search.set_max_depth(MAX_DEPTH);
+        search.set_skill_level(0);
+        let best_move = search.find_best_move(&mut Position::new(&game)).unwrap();
+        assert_eq!(search.completed_depth(), 1);
+        assert!(Position::new(&game).get_all_legal_moves(&game).contains(&best_move));
+
+        search.set_skill_level(5);
+        search.find_best_move(&mut Position::new(&game));
+        assert_eq!(search.completed_depth(), 3);
+        search.set_skill_level(100);
+        assert_eq!(search.skill_level(), MAX_SKILL_LEVEL);
+        assert_eq!(search.skill_limits(), (MAX_DEPTH, 0));
+    }
+
+    #[test]
+    fn test_node_ceiling_truncates_the_search() {
+        let game = Game::new();
+        let mut position = Position::new(&game);
+        let mut search = Search::new();
+        search.set_max_time(30);
+        search.set_max_depth(2);
+        search.find_best_move(&mut Position::new(&game));
+        assert!(!search.truncated());
+
+        // Nothing else would stop this search for half a minute
+        search.set_max_depth(MAX_DEPTH);
+        search.set_params(SearchParams { max_nodes: 2000, ..SearchParams::default() });
+        let start = Instant::now();
+        let best_move = search.find_best_move(&mut position).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(search.truncated());
+        // Nodes already under way when the ceiling is reached still count
+        assert!(search.nodes_searched() < 2100, "{}", search.nodes_searched());
+        assert!(position.get_all_legal_moves(&game).contains(&best_move));
+    }
+
+    #[test]
+    fn test_pondering_waits_for_the_ponder_hit() {
+        let game = Game::new();
+        let mut position = Position::new(&game);
+        position.update_all_legal_moves(&game);
+        let mut search = Search::new();
+        search.set_max_time_ms(50);
+        let ponder = search.ponder_flag();
+        ponder.store(true, Ordering::Relaxed);
+        let hit = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            ponder.store(false, Ordering::Relaxed);
+        });
+
+        let start = Instant::now();
+        let best = search.find_best_move(&mut position).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300), "{:?}", start.elapsed());
+        // Past its time on the hit, the search ends at once
+        assert!(start.elapsed() < Duration::from_millis(300) + WATCHDOG_GRACE * 2, "{:?}", start.elapsed());
+        hit.join().unwrap();
+        assert!(search.ponder_move(&position, best).is_some());
+    }
+
+    #[test]
+    fn test_search_after_watchdog_timeout() {
+        let game = Game::new();
+        let mut search = Search::new();
+        search.set_max_time_ms(50);
+        search.stall = Duration::from_millis(50) + WATCHDOG_GRACE * 3;
+        assert_eq!(search.find_best_move(&mut Position::new(&game)), None);
+        assert!(search.abandoned());
+        assert!(!search.stop_flag().load(Ordering::Relaxed));
+
+        // The next search runs its course instead of stopping at once
+        search.stall = Duration::ZERO;
+        search.set_max_depth(3);
+        search.set_max_time(30);
+        assert!(search.find_best_move(&mut Position::new(&game)).is_some());
+        assert!(!search.abandoned());
+        assert_eq!(search.completed_depth(), 3);
+    }
+
+    #[test]
+    fn test_low_time_mode_searches_shallow() {
+        let game = Game::new();
+        let mut position = Position::read_FEN("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &game);
+        position.update_all_legal_moves(&game);
+        let mut search = Search::new();
+        search.set_clock(&Clock { remaining: Duration::from_secs(600), ..Clock::default() }, &position);
+        assert!(!search.low_time());
+        search.set_clock(&Clock { remaining: Duration::from_secs(3), ..Clock::default() }, &position);
+        assert!(search.low_time());
+
+        search.max_time = Duration::from_secs(5);
+        assert!(search.find_best_move(&mut position).is_some());
+        assert!(search.completed_depth() >= 1 && search.completed_depth() <= LOW_TIME_DEPTH);
+    }
+
+    #[test]
+    fn test_easy_moves_on_a_clock() {
+        let game = Game::new();
+        let clock = Clock { remaining: Duration::from_secs(600), ..Clock::default() };
+        // Kxg2 is the only legal move; dxe4 wins the knight back
+        for (fen, expected) in [
+            ("k7/8/8/8/8/8/6r1/7K w - - 0 1", Move::encode(7, 14, None)),
+            ("4k3/pppp1ppp/8/8/4n3/3P4/PPP2PPP/4K3 w - - 0 1", Move::encode(19, 28, None)),
+        ] {
+            let mut position = Position::read_FEN(fen, &game);
+            position.update_all_legal_moves(&game);
+            let mut search = Search::new();
+            search.set_clock(&clock, &position);
+
+            let start = Instant::now();
+            assert_eq!(search.find_best_move(&mut position), Some(expected), "{}", fen);
+            assert!(start.elapsed() < search.time_manager.unwrap().optimum() / 2, "{}: {:?}", fen, start.elapsed());
+        }
+    }
+
+    #[test]
+    fn test_root_variety() {
+        let game = Game::new();
+        let choices = |variety: i32, seed: u64| -> Vec<u64> {
+            (0..4).map(|_| {
+                let mut position = Position::new(&game);
+                position.update_all_legal_moves(&game);
+                let mut search = Search::new();
+                search.set_params(SearchParams { root_variety: variety, variety_seed: seed, ..SearchParams::default() });
+                search.set_max_depth(2);
+                search.set_max_time(30);
+                search.find_best_move(&mut position).unwrap()
+            }).collect()
+        };
+        // Without variety, and with a fixed seed, every game opens the same way
+        assert!(choices(0, 0).windows(2).all(|pair| pair[0] == pair[1]));
+        assert!(choices(50, 3).windows(2).all(|pair| pair[0] == pair[1]));
+        // Different seeds spread the choice over near-equal moves
+        let openings: std::collections::HashSet<u64> = (1..=8).map(|seed| choices(50, seed)[0]).collect();
+        assert!(openings.len() > 1);
+    }
+
     #[test]
     fn test_search_depth() {
         let game = Game::new();
@@ -0,0 +1,231 @@
+//! Tunable search parameters.
+//!
+//! Pruning margins, reductions and extensions live in `SearchParams` rather
+//! than in constants, so they can be read from a TOML file and changed
+//! between searches without recompiling:
+//!
+//! ```toml
+//! # search.toml
+//! quiescence_depth = 6
+//! quiescence_checks = true
+//! quiescence_see_pruning = true
+//! quiescence_see_threshold = -50
+//! check_extension = true
+//! null_move_reduction = 2
+//! futility_margin = 150
+//! lmr_reduction = 1
+//! max_nodes = 50000000
+//! ```
+//!
+//! Keys left out keep their defaults. The defaults leave every pruning
+//! technique off, so the search behaves as it does without a file.
+//! `Search::from_config` reads the file named by `RUSTCHESS_SEARCH_CONFIG`,
+//! or the first `search.toml` found among the asset paths (see `assets`).
+
+use std::path::Path;
+
+/// Environment variable naming the parameter file `Search::from_config` loads.
+pub const CONFIG_ENV: &str = "RUSTCHESS_SEARCH_CONFIG";
+/// File loaded when `CONFIG_ENV` is not set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "search.toml";
+/// Default `max_nodes`: hours of search, so it only ends searches that
+/// would otherwise never end.
+pub const DEFAULT_MAX_NODES: u64 = 10_000_000_000;
+
+/// Parameters of the alpha-beta and quiescence search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchParams {
+    /// Plies of captures searched past the nominal depth
+    pub quiescence_depth: i32,
+    /// Also search quiet moves that give check at the first quiescence ply
+    pub quiescence_checks: bool,
+    /// Skip quiescence captures whose static exchange evaluation is below
+    /// `quiescence_see_threshold`
+    pub quiescence_see_pruning: bool,
+    /// Centipawns a capture must win by static exchange to be searched in
+    /// quiescence when SEE pruning is on; negative values keep slightly
+    /// losing captures
+    pub quiescence_see_threshold: i32,
+    /// Search one ply deeper when the side to move is in check
+    pub check_extension: bool,
+    /// Depth reduction of the null-move search; 0 turns null-move pruning off
+    pub null_move_reduction: i32,
+    /// Shallowest depth at which a null move is tried
+    pub null_move_min_depth: i32,
+    /// Quiet moves one ply from the horizon are skipped when the static
+    /// evaluation plus this margin cannot reach alpha; 0 turns it off
+    pub futility_margin: i32,
+    /// Plies late quiet moves are searched shallower; 0 turns it off
+    pub lmr_reduction: i32,
+    /// Shallowest depth at which late moves are reduced
+    pub lmr_min_depth: i32,
+    /// Moves searched at full depth before later ones are reduced
+    pub lmr_min_moves: usize,
+    /// On a clock, answer a lone legal move at once and stop early on a
+    /// clearly winning capture that stays best
+    pub easy_move: bool,
+    /// Largest random bonus, in centipawns, given to each root move so that
+    /// near-equal moves take turns between games; 0 keeps the search
+    /// deterministic
+    pub root_variety: i32,
+    /// Seed of the root bonuses; 0 seeds them from the clock
+    pub variety_seed: u64,
+    /// Nodes one search may visit, whatever its time limit, even in
+    /// infinite analysis; a search that reaches it returns the best line
+    /// found so far and reports itself truncated
+    pub max_nodes: u64,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            quiescence_depth: 4,
+            quiescence_checks: false,
+            quiescence_see_pruning: false,
+            quiescence_see_threshold: 0,
+            check_extension: false,
+            null_move_reduction: 0,
+            null_move_min_depth: 3,
+            futility_margin: 0,
+            lmr_reduction: 0,
+            lmr_min_depth: 3,
+            lmr_min_moves: 4,
+            easy_move: true,
+            root_variety: 0,
+            variety_seed: 0,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+impl SearchParams {
+    /// Parses parameters from TOML text, starting from the defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - `key = value` lines; unknown keys are rejected so that
+    ///   typos do not go unnoticed
+    ///
+    /// # Returns
+    ///
+    /// * The parameters, or an error naming the offending key
+    pub fn from_toml(text: &str) -> Result<SearchParams, String> {
+        let table: toml::Table = text.parse().map_err(|e| format!("Invalid search config: {}", e))?;
+        let mut params = SearchParams::default();
+        for (key, value) in &table {
+            let number = || -> Result<i64, String> {
+                value.as_integer()
+                    .filter(|n| *n >= 0 && *n <= i32::MAX as i64)
+                    .ok_or_else(|| format!("Search config: {} must be a non-negative integer", key))
+            };
+            let signed = || -> Result<i64, String> {
+                value.as_integer()
+                    .filter(|n| *n >= i32::MIN as i64 && *n <= i32::MAX as i64)
+                    .ok_or_else(|| format!("Search config: {} must be an integer", key))
+            };
+            let flag = || -> Result<bool, String> {
+                value.as_bool().ok_or_else(|| format!("Search config: {} must be true or false", key))
+            };
+            match key.as_str() {
+                "quiescence_depth" => params.quiescence_depth = number()? as i32,
+                "quiescence_checks" => params.quiescence_checks = flag()?,
+                "quiescence_see_pruning" => params.quiescence_see_pruning = flag()?,
+                "quiescence_see_threshold" => params.quiescence_see_threshold = signed()? as i32,
+                "check_extension" => params.check_extension = flag()?,
+                "null_move_reduction" => params.null_move_reduction = number()? as i32,
+                "null_move_min_depth" => params.null_move_min_depth = number()? as i32,
+                "futility_margin" => params.futility_margin = number()? as i32,
+                "lmr_reduction" => params.lmr_reduction = number()? as i32,
+                "lmr_min_depth" => params.lmr_min_depth = number()? as i32,
+                "lmr_min_moves" => params.lmr_min_moves = number()? as usize,
+                "easy_move" => params.easy_move = flag()?,
+                "root_variety" => params.root_variety = number()? as i32,
+                // TOML integers stop at i64::MAX, so larger seeds are quoted
+                "variety_seed" => params.variety_seed = value.as_integer()
+                    .and_then(|n| u64::try_from(n).ok())
+                    .or_else(|| value.as_str().and_then(|text| text.parse().ok()))
+                    .ok_or_else(|| format!("Search config: {} must be a non-negative integer", key))?,
+                "max_nodes" => params.max_nodes = value.as_integer()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("Search config: {} must be a positive integer", key))? as u64,
+                other => return Err(format!("Search config: unknown key '{}'", other)),
+            }
+        }
+        Ok(params)
+    }
+
+    /// The parameters as TOML, one `key = value` line each.
+    pub fn to_toml(&self) -> String {
+        format!(
+            "quiescence_depth = {}\nquiescence_checks = {}\nquiescence_see_pruning = {}\n\
+             quiescence_see_threshold = {}\ncheck_extension = {}\nnull_move_reduction = {}\nnull_move_min_depth = {}\n\
+             futility_margin = {}\nlmr_reduction = {}\nlmr_min_depth = {}\nlmr_min_moves = {}\neasy_move = {}\n\
+             root_variety = {}\nvariety_seed = {}\nmax_nodes = {}\n",
+            self.quiescence_depth, self.quiescence_checks, self.quiescence_see_pruning,
+            self.quiescence_see_threshold, self.check_extension, self.null_move_reduction, self.null_move_min_depth,
+            self.futility_margin, self.lmr_reduction, self.lmr_min_depth, self.lmr_min_moves, self.easy_move,
+            self.root_variety, seed_toml(self.variety_seed), self.max_nodes,
+        )
+    }
+
+    /// Reads parameters from a TOML file.
+    pub fn load(path: &Path) -> Result<SearchParams, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        SearchParams::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the parameters to a TOML file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// `seed` as a TOML value: an integer, or a quoted one past `i64::MAX`.
+fn seed_toml(seed: u64) -> String {
+    if i64::try_from(seed).is_ok() { seed.to_string() } else { format!("\"{}\"", seed) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_round_trip_through_toml() {
+        assert_eq!(SearchParams::from_toml("").unwrap(), SearchParams::default());
+
+        let params = SearchParams::from_toml("# tuned\ncheck_extension = true\nfutility_margin = 150\n").unwrap();
+        assert!(params.check_extension);
+        assert_eq!(params.futility_margin, 150);
+        assert_eq!(params.quiescence_depth, SearchParams::default().quiescence_depth);
+        assert_eq!(SearchParams::from_toml(&params.to_toml()).unwrap(), params);
+
+        assert_eq!(SearchParams::from_toml("futility = 1").unwrap_err(), "Search config: unknown key 'futility'");
+        assert!(SearchParams::from_toml("lmr_reduction = -1").is_err());
+        assert!(SearchParams::from_toml("check_extension = 1").is_err());
+        assert!(SearchParams::from_toml("lmr_reduction = ").is_err());
+
+        let quiescence = SearchParams::from_toml("quiescence_see_pruning = true\nquiescence_see_threshold = -50\n").unwrap();
+        assert_eq!(quiescence.quiescence_see_threshold, -50);
+        assert_eq!(SearchParams::from_toml(&quiescence.to_toml()).unwrap(), quiescence);
+        assert!(SearchParams::from_toml("quiescence_checks = 1").is_err());
+
+        let ceiling = SearchParams::from_toml("max_nodes = 50000000000").unwrap();
+        assert_eq!(ceiling.max_nodes, 50_000_000_000);
+        assert_eq!(SearchParams::from_toml(&ceiling.to_toml()).unwrap(), ceiling);
+        assert!(SearchParams::from_toml("max_nodes = 0").is_err());
+    }
+
+    #[test]
+    fn test_variety_seed_takes_any_u64() {
+        let seeded = SearchParams::from_toml("variety_seed = 3000000000").unwrap();
+        assert_eq!(seeded.variety_seed, 3_000_000_000);
+        assert_eq!(SearchParams::from_toml(&seeded.to_toml()).unwrap(), seeded);
+
+        let largest = SearchParams { variety_seed: u64::MAX, ..SearchParams::default() };
+        assert!(largest.to_toml().contains("variety_seed = \"18446744073709551615\""));
+        assert_eq!(SearchParams::from_toml(&largest.to_toml()).unwrap(), largest);
+        assert!(SearchParams::from_toml("variety_seed = -1").is_err());
+        assert!(SearchParams::from_toml("variety_seed = \"seed\"").is_err());
+    }
+}
@@ -0,0 +1,574 @@
+//! Headless HTTP/JSON server.
+//!
+//! Exposes the engine as a small web service so front ends and bots can use
+//! it without linking the crate:
+//!
+//! * `POST /bestmove` with `{"fen": "...", "movetime": 1000}` returns the best
+//!   move; an optional `"moves": ["e2e4", ...]` list is played from the FEN first
+//! * `GET /legal_moves?fen=...` returns the legal moves in long algebraic notation
+//! * `POST /analyze` with the same body as `/bestmove` also reports the search
+//!   score, principal variation and depth, the static evaluation, check status
+//!   and node count
+//! * `GET /image?fen=...` returns an SVG diagram of the position (needs the
+//!   `render` feature)
+//! * `POST /batch` with `{"positions": ["<fen>", {"fen": "...", "moves": [...],
+//!   "movetime": 500}, ...], "movetime": 1000}` searches many positions and
+//!   streams one JSON line per position (`index`, `fen`, `bestmove`, `score`,
+//!   `pv` and `depth`, or `error`) as each completes, then `{"done": true}`
+//!
+//! Requests are handled one at a time on a single engine instance, except
+//! batches: their positions are queued for a pool of engines, one per worker
+//! thread, so at most that many are searched at once however many batches
+//! are waiting. A batch may hold up to `MAX_BATCH_POSITIONS` positions, and
+//! a batch that would take the queue past `MAX_QUEUED_POSITIONS` is turned
+//! away with 503.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::evaluation::Evaluation;
+
+/// Search time used when a request does not specify `movetime`.
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+/// Upper bound on `movetime` so a single request can't monopolize the server.
+const MAX_MOVETIME_MS: u64 = 60_000;
+/// Most positions in one batch.
+pub const MAX_BATCH_POSITIONS: usize = 256;
+/// Most positions waiting or being searched over all batches.
+pub const MAX_QUEUED_POSITIONS: usize = 4 * MAX_BATCH_POSITIONS;
+
+/// Body of `POST /bestmove` and `POST /analyze`.
+#[derive(Debug, Deserialize)]
+struct SearchRequest {
+    fen: String,
+    /// Moves in long algebraic notation played from `fen` before searching
+    #[serde(default)]
+    moves: Vec<String>,
+    /// Search time in milliseconds
+    movetime: Option<u64>,
+}
+
+/// Body of `POST /batch`.
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    positions: Vec<BatchPosition>,
+    /// Search time in milliseconds for positions that do not give their own
+    movetime: Option<u64>,
+}
+
+/// A position of a batch: a bare FEN, or a FEN with moves and a search time.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BatchPosition {
+    Fen(String),
+    Search(SearchRequest),
+}
+
+/// A position of a batch waiting for a worker.
+struct Job {
+    index: usize,
+    fen: String,
+    board: Board,
+    movetime: u64,
+    results: mpsc::Sender<Value>,
+    /// Set once nobody is reading the batch's results any more
+    cancelled: Arc<AtomicBool>,
+}
+
+/// A pool of engines searching the positions of queued batches, one per
+/// worker thread.
+pub struct BatchPool {
+    jobs: Mutex<mpsc::Sender<Job>>,
+    queued: Arc<AtomicUsize>,  // Positions waiting or being searched
+}
+
+/// The results of a batch as they complete, one JSON object per position.
+pub struct BatchResults {
+    pub results: mpsc::Receiver<Value>,
+    /// Set to skip the positions not searched yet
+    pub cancelled: Arc<AtomicBool>,
+}
+
+impl BatchPool {
+    /// Starts the worker threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `workers` - Number of positions searched at once, at least one
+    /// * `engine` - The engine every worker starts as a copy of, each with
+    ///   an empty share of its hash size
+    pub fn new(workers: usize, engine: &Engine) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let workers = workers.max(1);
+        for _ in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let queued = Arc::clone(&queued);
+            let mut engine = engine.with_hash_size(engine.hash_size() / workers);
+            thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting for the next job
+                    let job = match receiver.lock().map(|receiver| receiver.recv()) {
+                        Ok(Ok(job)) => job,
+                        _ => return,
+                    };
+                    if !job.cancelled.load(Ordering::Relaxed) {
+                        engine.set_max_time_ms(job.movetime);
+                        let analysis = engine.analyze(&job.board);
+                        let _ = job.results.send(json!({
+                            "index": job.index,
+                            "fen": job.fen,
+                            "bestmove": analysis.as_ref().map(|a| a.best_move.to_string()),
+                            "score": analysis.as_ref().map(|a| a.score),
+                            "pv": analysis.as_ref().map_or(Vec::new(), |a| a.pv.iter().map(|mv| mv.to_string()).collect()),
+                            "depth": analysis.as_ref().map(|a| a.depth),
+                        }));
+                    }
+                    queued.fetch_sub(1, Ordering::Relaxed);
+                }
+            });
+        }
+        BatchPool { jobs: Mutex::new(sender), queued }
+    }
+
+    /// Queues the positions of a `POST /batch` body. Positions that cannot
+    /// be read are answered with an error at once; the rest are searched in
+    /// order as workers come free.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The request body
+    ///
+    /// # Returns
+    ///
+    /// * The results as they complete, or the HTTP status and message to
+    ///   turn the batch away with
+    pub fn submit(&self, body: &str) -> Result<BatchResults, (u16, String)> {
+        let request: BatchRequest = serde_json::from_str(body)
+            .map_err(|e| (400, format!("Invalid request body: {}", e)))?;
+        if request.positions.is_empty() {
+            return Err((400, "The batch has no positions".to_string()));
+        }
+        if request.positions.len() > MAX_BATCH_POSITIONS {
+            return Err((400, format!("A batch holds at most {} positions", MAX_BATCH_POSITIONS)));
+        }
+        let count = request.positions.len();
+        let queued = self.queued.fetch_add(count, Ordering::Relaxed);
+        if queued + count > MAX_QUEUED_POSITIONS {
+            self.queued.fetch_sub(count, Ordering::Relaxed);
+            return Err((503, format!("{} positions are already queued; try again later", queued)));
+        }
+
+        let (sender, results) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let jobs = self.jobs.lock().map_err(|_| (500, "The worker pool has stopped".to_string()))?;
+        for (index, position) in request.positions.into_iter().enumerate() {
+            let search = match position {
+                BatchPosition::Fen(fen) => SearchRequest { fen, moves: Vec::new(), movetime: None },
+                BatchPosition::Search(search) => search,
+            };
+            let movetime = search.movetime.or(request.movetime).unwrap_or(DEFAULT_MOVETIME_MS).min(MAX_MOVETIME_MS);
+            let job = board_after(&search).map(|board| Job {
+                index,
+                fen: search.fen.clone(),
+                board,
+                movetime,
+                results: sender.clone(),
+                cancelled: Arc::clone(&cancelled),
+            });
+            let queued = job.and_then(|job| jobs.send(job).map_err(|_| "The worker pool has stopped".to_string()));
+            if let Err(msg) = queued {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+                let _ = sender.send(json!({ "index": index, "fen": search.fen, "error": msg }));
+            }
+        }
+        Ok(BatchResults { results, cancelled })
+    }
+}
+
+/// Runs the HTTP server until the process is terminated, with a batch
+/// worker per available CPU.
+///
+/// # Arguments
+///
+/// * `addr` - Address to listen on, e.g. `127.0.0.1:8080`
+///
+/// # Returns
+///
+/// * An error if the address could not be bound
+pub fn serve(addr: &str) -> Result<(), String> {
+    serve_with_workers(addr, thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Runs the HTTP server until the process is terminated.
+///
+/// # Arguments
+///
+/// * `addr` - Address to listen on, e.g. `127.0.0.1:8080`
+/// * `workers` - Number of batch positions searched at once
+///
+/// # Returns
+///
+/// * An error if the address could not be bound
+pub fn serve_with_workers(addr: &str, workers: usize) -> Result<(), String> {
+    let server = Server::http(addr).map_err(|e| format!("Could not listen on {}: {}", addr, e))?;
+    let (mut engine, errors) = Engine::from_config();
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    let pool = BatchPool::new(workers, &engine);
+    println!("Listening on http://{} with {} batch workers", addr, workers.max(1));
+
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            respond(request, 400, json!({ "error": format!("Could not read body: {}", e) }));
+            continue;
+        }
+        let batch = *request.method() == Method::Post && request.url() == "/batch";
+        if !batch {
+            let (status, payload) = handle_request(&mut engine, request.method().as_str(), request.url(), &body);
+            respond(request, status, payload);
+            continue;
+        }
+        // Streaming the results takes as long as the searches, so it must
+        // not hold up the requests behind it
+        match pool.submit(&body) {
+            Ok(results) => {
+                thread::spawn(move || {
+                    if let Err(e) = stream_results(request, &results) {
+                        results.cancelled.store(true, Ordering::Relaxed);
+                        eprintln!("Failed to send batch results: {}", e);
+                    }
+                });
+            }
+            Err((status, msg)) => respond(request, status, json!({ "error": msg })),
+        }
+    }
+    Ok(())
+}
+
+fn respond(request: Request, status: u16, payload: Value) {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(payload.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+/// Sends a batch's results as newline-delimited JSON in a chunked response,
+/// flushing each line as its position completes.
+fn stream_results(request: Request, batch: &BatchResults) -> io::Result<()> {
+    let mut writer = request.into_writer();
+    write!(writer, "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\n\r\n")?;
+    let done = json!({ "done": true });
+    for value in batch.results.iter().chain([done]) {
+        let line = format!("{}\n", value);
+        write!(writer, "{:x}\r\n{}\r\n", line.len(), line)?;
+        writer.flush()?;
+    }
+    write!(writer, "0\r\n\r\n")?;
+    writer.flush()
+}
+
+/// Routes a request to its handler.
+///
+/// # Arguments
+///
+/// * `engine` - The engine used for searches
+/// * `method` - HTTP method, e.g. `GET`
+/// * `url` - Request path including the query string
+/// * `body` - Request body
+///
+/// # Returns
+///
+/// * The HTTP status code and JSON payload to send back
+pub fn handle_request(engine: &mut Engine, method: &str, url: &str, body: &str) -> (u16, Value) {
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => (url, ""),
+    };
+
+    let result = match (method, path) {
+        ("POST", "/bestmove") => best_move(engine, body),
+        ("POST", "/analyze") => analyze(engine, body),
+        ("GET", "/legal_moves") => legal_moves(query),
+        #[cfg(feature = "render")]
+        ("GET", "/image") => image(query),
+        _ => return (404, json!({ "error": format!("No route for {} {}", method, path) })),
+    };
+
+    match result {
+        Ok(payload) => (200, payload),
+        Err(msg) => (400, json!({ "error": msg })),
+    }
+}
+
+fn best_move(engine: &mut Engine, body: &str) -> Result<Value, String> {
+    let (request, board) = parse_search_request(body)?;
+    engine.set_max_time_ms(request.movetime.unwrap_or(DEFAULT_MOVETIME_MS).min(MAX_MOVETIME_MS));
+    let best = engine.best_move(&board);
+
+    Ok(json!({
+        "fen": request.fen,
+        "bestmove": best.map(|mv| mv.to_string()),
+    }))
+}
+
+fn analyze(engine: &mut Engine, body: &str) -> Result<Value, String> {
+    let (request, board) = parse_search_request(body)?;
+    engine.set_max_time_ms(request.movetime.unwrap_or(DEFAULT_MOVETIME_MS).min(MAX_MOVETIME_MS));
+    let analysis = engine.analyze(&board);
+    let eval = Evaluation::new(board.position().clone()).evaluate_position();
+
+    Ok(json!({
+        "fen": request.fen,
+        "bestmove": analysis.as_ref().map(|a| a.best_move.to_string()),
+        "score": analysis.as_ref().map(|a| a.score),
+        "pv": analysis.as_ref().map_or(Vec::new(), |a| a.pv.iter().map(|mv| mv.to_string()).collect()),
+        "depth": analysis.as_ref().map(|a| a.depth),
+        "eval": eval,
+        "check": board.is_check(),
+        "legal_moves": board.legal_moves().len(),
+        "nodes": engine.nodes_searched(),
+        "qnodes": engine.quiescence_nodes(),
+    }))
+}
+
+fn legal_moves(query: &str) -> Result<Value, String> {
+    let fen = fen_param(query)?;
+    let board = Board::from_fen(&fen)?;
+    let moves: Vec<String> = board.legal_moves().iter().map(|mv| mv.to_string()).collect();
+    Ok(json!({ "fen": fen, "moves": moves }))
+}
+
+#[cfg(feature = "render")]
+fn image(query: &str) -> Result<Value, String> {
+    use crate::position::Color;
+    use crate::render::BoardRenderer;
+
+    let fen = fen_param(query)?;
+    let board = Board::from_fen(&fen)?;
+    let svg = BoardRenderer::new(48, Color::White).render_svg(board.position(), None);
+    Ok(json!({ "fen": fen, "svg": svg }))
+}
+
+/// Extracts and decodes the `fen` query parameter.
+fn fen_param(query: &str) -> Result<String, String> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "fen")
+        .map(|(_, value)| percent_decode(value))
+        .ok_or_else(|| "Missing 'fen' query parameter".to_string())?
+}
+
+fn parse_search_request(body: &str) -> Result<(SearchRequest, Board), String> {
+    let request: SearchRequest = serde_json::from_str(body)
+        .map_err(|e| format!("Invalid request body: {}", e))?;
+    let board = board_after(&request)?;
+    Ok((request, board))
+}
+
+/// The board after a request's moves are played from its FEN.
+fn board_after(request: &SearchRequest) -> Result<Board, String> {
+    let mut board = Board::from_fen(&request.fen)?;
+    for text in &request.moves {
+        board.play(board.parse_move(text)?)?;
+    }
+    Ok(board)
+}
+
+/// Decodes `%XX` escapes and `+` (space) in a URL query value.
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = value.get(i + 1..i + 3)
+                    .ok_or_else(|| format!("Truncated escape in '{}'", value))?;
+                // The radix parse alone would take a sign, as in `%+1`
+                if !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid escape '%{}'", hex));
+                }
+                let byte = u8::from_str_radix(hex, 16)
+                    .map_err(|_| format!("Invalid escape '%{}'", hex))?;
+                decoded.push(byte);
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| "Query is not valid UTF-8".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legal_moves_route() {
+        let mut engine = Engine::new();
+        let url = "/legal_moves?fen=rnbqkbnr%2Fpppppppp%2F8%2F8%2F8%2F8%2FPPPPPPPP%2FRNBQKBNR+w+KQkq+-+0+1";
+        let (status, payload) = handle_request(&mut engine, "GET", url, "");
+        assert_eq!(status, 200);
+        assert_eq!(payload["moves"].as_array().unwrap().len(), 20);
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_image_route() {
+        let mut engine = Engine::new();
+        let url = "/image?fen=8%2F8%2F8%2F4k3%2F8%2F8%2F8%2F4K3+w+-+-+0+1";
+        let (status, payload) = handle_request(&mut engine, "GET", url, "");
+        assert_eq!(status, 200);
+        let svg = payload["svg"].as_str().unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<text").count(), 2);
+    }
+
+    #[test]
+    fn test_bestmove_route() {
+        let mut engine = Engine::new();
+        let body = r#"{"fen": "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1", "movetime": 200}"#;
+        let (status, payload) = handle_request(&mut engine, "POST", "/bestmove", body);
+        assert_eq!(status, 200);
+        let best = payload["bestmove"].as_str().unwrap();
+        let board = Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert!(board.parse_move(best).is_ok());
+    }
+
+    #[test]
+    fn test_analyze_reports_eval() {
+        let mut engine = Engine::new();
+        let body = r#"{"fen": "4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "movetime": 100}"#;
+        let (status, payload) = handle_request(&mut engine, "POST", "/analyze", body);
+        assert_eq!(status, 200);
+        assert!(payload["eval"].as_i64().unwrap() > 0);
+        assert!(payload["score"].as_i64().unwrap() > 0);
+        assert!(payload["depth"].as_i64().unwrap() >= 1);
+        assert_eq!(payload["pv"][0], payload["bestmove"]);
+        assert_eq!(payload["check"], false);
+    }
+
+    #[test]
+    fn test_moves_are_played_from_fen() {
+        let mut engine = Engine::new();
+        // After 1.f3 e5 2.g4 only Qh4 mates
+        let body = r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                       "moves": ["f2f3", "e7e5", "g2g4"], "movetime": 300}"#;
+        let (status, payload) = handle_request(&mut engine, "POST", "/analyze", body);
+        assert_eq!(status, 200);
+        assert_eq!(payload["bestmove"], "d8h4");
+
+        let body = r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "moves": ["e2e5"]}"#;
+        let (status, _) = handle_request(&mut engine, "POST", "/bestmove", body);
+        assert_eq!(status, 400);
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        let mut engine = Engine::new();
+        let (status, _) = handle_request(&mut engine, "POST", "/bestmove", "{}");
+        assert_eq!(status, 400);
+        let (status, payload) = handle_request(&mut engine, "POST", "/bestmove", r#"{"fen": "8/8 w"}"#);
+        assert_eq!(status, 400);
+        assert!(payload["error"].as_str().unwrap().contains("FEN"));
+        let (status, _) = handle_request(&mut engine, "GET", "/nope", "");
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_batch_results_arrive_per_position() {
+        let pool = BatchPool::new(2, &Engine::new());
+        let body = r#"{"positions": [
+            {"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "moves": ["f2f3", "e7e5", "g2g4"]},
+            "4k3/8/8/8/8/8/8/3QK3 w - - 0 1",
+            "8/8 w"
+        ], "movetime": 100}"#;
+        let batch = pool.submit(body).unwrap();
+        let mut results: Vec<Value> = batch.results.iter().collect();
+        results.sort_by_key(|result| result["index"].as_u64());
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["bestmove"], "d8h4");
+        assert_eq!(results[0]["pv"][0], "d8h4");
+        assert!(results[1]["score"].as_i64().unwrap() > 0);
+        assert!(results[2]["error"].as_str().unwrap().contains("FEN"));
+        assert_eq!(pool.queued.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_batch_limits() {
+        let pool = BatchPool::new(1, &Engine::new());
+        assert_eq!(pool.submit("{}").err().unwrap().0, 400);
+        assert_eq!(pool.submit(r#"{"positions": []}"#).err().unwrap().0, 400);
+        let fens = vec!["4k3/8/8/8/8/8/8/3QK3 w - - 0 1"; MAX_BATCH_POSITIONS + 1];
+        let body = json!({ "positions": fens }).to_string();
+        assert_eq!(pool.submit(&body).err().unwrap().0, 400);
+
+        // A full queue turns batches away without taking them in
+        pool.queued.store(MAX_QUEUED_POSITIONS, Ordering::Relaxed);
+        let (status, msg) = pool.submit(r#"{"positions": ["4k3/8/8/8/8/8/8/3QK3 w - - 0 1"]}"#).err().unwrap();
+        assert_eq!(status, 503, "{}", msg);
+        assert_eq!(pool.queued.load(Ordering::Relaxed), MAX_QUEUED_POSITIONS);
+    }
+
+    #[test]
+    fn test_batch_is_streamed_over_http() {
+        use std::io::{BufRead, BufReader};
+        use std::net::{TcpListener, TcpStream};
+        use std::time::Duration;
+
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let server_addr = addr.clone();
+        thread::spawn(move || serve_with_workers(&server_addr, 2));
+        let mut stream = None;
+        for _ in 0..50 {
+            match TcpStream::connect(&addr) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+        let mut stream = stream.expect("server never listened");
+        let body = r#"{"positions": ["4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "4k3/8/8/8/8/8/8/3QK3 b - - 0 1"], "movetime": 50}"#;
+        write!(stream, "POST /batch HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            addr, body.len(), body).unwrap();
+
+        // Every other line of the chunked body is a chunk size
+        let lines: Vec<String> = BufReader::new(stream).lines().map(Result::unwrap).collect();
+        assert_eq!(lines[0], "HTTP/1.1 200 OK");
+        assert!(lines.contains(&"Transfer-Encoding: chunked".to_string()));
+        let results: Vec<Value> = lines.iter().filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(Value::is_object)
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[..2].iter().all(|result| result["bestmove"].is_string()));
+        assert_eq!(results[2], json!({ "done": true }));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb+c").unwrap(), "a/b c");
+        assert!(percent_decode("%zz").is_err());
+        assert!(percent_decode("%+1").is_err());
+        assert!(percent_decode("%-1").is_err());
+        assert!(percent_decode("%2").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Strength history: calibration results kept across builds.
+//!
+//! Every `calibrate` run appends one record to a JSON lines log (one JSON
+//! object per line, `strength.jsonl` unless `RUSTCHESS_STRENGTH_LOG` names
+//! another file). A record holds the Elo estimate together with the engine
+//! version, the commit it was built from and the search parameters, so a
+//! drop in strength can be traced back to the change that caused it. The
+//! GUI graphs the log in its "Strength history" window.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::CalibrationReport;
+
+/// Environment variable naming the strength log.
+pub const HISTORY_ENV: &str = "RUSTCHESS_STRENGTH_LOG";
+/// The strength log used when `HISTORY_ENV` is not set.
+pub const DEFAULT_HISTORY_FILE: &str = "strength.jsonl";
+
+/// One calibration run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrengthRecord {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Engine version, from Cargo.toml
+    pub version: String,
+    /// Short hash of the commit built from, when known
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Estimated rating
+    pub elo: f64,
+    /// Half-width of the 95% confidence interval
+    pub margin: f64,
+    pub games: u32,
+    /// Thinking time per engine move
+    pub movetime_ms: u64,
+    /// The search parameters, as TOML
+    #[serde(default)]
+    pub params: String,
+}
+
+impl StrengthRecord {
+    /// Records a calibration run of this build.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The run's outcome
+    /// * `movetime_ms` - Thinking time per engine move
+    /// * `params` - The search parameters the engine played with, as TOML
+    pub fn new(report: &CalibrationReport, movetime_ms: u64, params: &str) -> Self {
+        StrengthRecord {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: build_commit().map(str::to_string),
+            elo: report.elo,
+            margin: report.margin,
+            games: report.records.iter().map(|record| record.wins + record.draws + record.losses).sum(),
+            movetime_ms,
+            params: params.to_string(),
+        }
+    }
+
+    /// The version and, when known, the commit, e.g. `0.1.0 (3f2a9c1)`.
+    pub fn build(&self) -> String {
+        match &self.commit {
+            Some(commit) => format!("{} ({})", self.version, commit),
+            None => self.version.clone(),
+        }
+    }
+
+    /// The UTC date of the run, as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        // Civil date from days since the epoch (Howard Hinnant's algorithm)
+        let days = (self.timestamp / 86_400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The commit this build was made from, set by the build script when built
+/// from a git checkout.
+pub fn build_commit() -> Option<&'static str> {
+    option_env!("RUSTCHESS_COMMIT").filter(|commit| !commit.is_empty())
+}
+
+/// The strength log: `RUSTCHESS_STRENGTH_LOG`, or `strength.jsonl` in the
+/// working directory.
+pub fn history_path() -> PathBuf {
+    std::env::var_os(HISTORY_ENV).map_or_else(|| PathBuf::from(DEFAULT_HISTORY_FILE), PathBuf::from)
+}
+
+/// Appends a record to a log, creating the file if needed.
+pub fn append(path: &Path, record: &StrengthRecord) -> Result<(), String> {
+    let line = serde_json::to_string(record).map_err(|e| format!("Could not encode the record: {}", e))?;
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+/// Reads a log's records, oldest first; blank lines are skipped.
+///
+/// # Returns
+///
+/// * The records, none if the file does not exist yet, or the first
+///   malformed line with its number
+pub fn parse_history(text: &str) -> Result<Vec<StrengthRecord>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("Line {}: {}", index + 1, e)))
+        .collect()
+}
+
+/// Reads the log at `path`; a missing file is an empty history.
+pub fn load(path: &Path) -> Result<Vec<StrengthRecord>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => parse_history(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+    }
+}
+
+/// The parameter lines that differ between two runs, as `name: old -> new`.
+pub fn config_changes(before: &StrengthRecord, after: &StrengthRecord) -> Vec<String> {
+    let settings = |params: &str| -> Vec<(String, String)> {
+        params.lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect()
+    };
+    let (old, new) = (settings(&before.params), settings(&after.params));
+    let value = |settings: &[(String, String)], name: &str| {
+        settings.iter().find(|(key, _)| key == name).map_or_else(|| "unset".to_string(), |(_, value)| value.clone())
+    };
+    let mut names: Vec<&String> = old.iter().chain(&new).map(|(name, _)| name).collect();
+    names.sort();
+    names.dedup();
+    names.into_iter()
+        .filter_map(|name| {
+            let (from, to) = (value(&old, name), value(&new, name));
+            (from != to).then(|| format!("{}: {} -> {}", name, from, to))
+        })
+        .collect()
+}
+
+/// Whether a run rated lower than the one before it by more than the two
+/// runs' combined uncertainty.
+pub fn is_regression(before: &StrengthRecord, after: &StrengthRecord) -> bool {
+    before.elo - after.elo > before.margin.hypot(after.margin)
+}
+
+/// The indices of runs that are regressions on the run before them.
+pub fn regressions(history: &[StrengthRecord]) -> Vec<usize> {
+    history.windows(2)
+        .enumerate()
+        .filter(|(_, pair)| is_regression(&pair[0], &pair[1]))
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+/// Lists every run with its rating, marking regressions with the commits
+/// and parameter changes between the two runs.
+pub fn report(history: &[StrengthRecord]) -> String {
+    let regressed = regressions(history);
+    let mut text = String::new();
+    for (index, record) in history.iter().enumerate() {
+        text.push_str(&format!("{}  {:<20} {:>6.0} +/- {:<4.0} {:>4} games at {} ms\n",
+            record.date(), record.build(), record.elo, record.margin, record.games, record.movetime_ms));
+        if regressed.contains(&index) {
+            let before = &history[index - 1];
+            text.push_str(&format!("    regression of {:.0} Elo since {}\n", before.elo - record.elo, before.build()));
+            for change in config_changes(before, record) {
+                text.push_str(&format!("    {}\n", change));
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64, commit: &str, elo: f64, params: &str) -> StrengthRecord {
+        StrengthRecord {
+            timestamp,
+            version: "0.1.0".to_string(),
+            commit: Some(commit.to_string()),
+            elo,
+            margin: 40.0,
+            games: 80,
+            movetime_ms: 100,
+            params: params.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history_round_trips_and_flags_regressions() {
+        let history = vec![
+            record(0, "aaaaaaa", 1500.0, "null_move_reduction = 2\nlmr_reduction = 1\n"),
+            record(951_782_400, "bbbbbbb", 1480.0, "null_move_reduction = 2\nlmr_reduction = 1\n"),
+            record(1_700_000_000, "ccccccc", 1350.0, "null_move_reduction = 3\nlmr_reduction = 1\n"),
+        ];
+        assert_eq!(history[0].date(), "1970-01-01");
+        assert_eq!(history[1].date(), "2000-02-29");
+        assert_eq!(history[2].build(), "0.1.0 (ccccccc)");
+
+        let path = std::env::temp_dir().join(format!("rustchess_strength_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(load(&path), Ok(Vec::new()));
+        for record in &history {
+            append(&path, record).unwrap();
+        }
+        let loaded = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Ok(history.clone()));
+
+        // A 20 Elo drop is within the margins, a 130 Elo drop is not
+        assert_eq!(regressions(&history), vec![2]);
+        assert_eq!(config_changes(&history[1], &history[2]), vec!["null_move_reduction: 2 -> 3"]);
+        assert!(report(&history).contains("regression of 130 Elo since 0.1.0 (bbbbbbb)\n    null_move_reduction: 2 -> 3\n"));
+
+        // Older records without a commit or parameters still load
+        let old = parse_history(r#"{"timestamp":0,"version":"0.0.9","elo":1400.0,"margin":50.0,"games":40,"movetime_ms":100}"#).unwrap();
+        assert_eq!(old[0].build(), "0.0.9");
+        assert!(parse_history("\n{}\n").unwrap_err().starts_with("Line 2"));
+    }
+}
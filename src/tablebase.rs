@@ -0,0 +1,600 @@
+//! Distance-to-mate tables for the basic endings.
+//!
+//! Every king and queen, king and rook, and king and pawn versus king
+//! position is labelled with the number of plies to mate with best play on
+//! both sides, or as a draw. The tables are built by retrograde analysis one
+//! ply at a time: checkmates first, then the positions from which the
+//! stronger side can reach one, then those in which every defence leads to
+//! one, and so on. Pawn endings promote into the queen and rook tables, so
+//! those are built first.
+//!
+//! Like the KPK bitbase, the tables are built on first use and cached on
+//! disk, unless a saved copy is found among the asset paths. Positions are normalized so the stronger side is White.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::assets::Asset;
+use crate::board_consts::{FILE_A, FILE_H};
+use crate::position::{Color, PieceType, Position};
+use crate::utils::{bit_scan, Bitboard};
+use crate::Game;
+
+/// Side to move x white king x black king x white piece
+const TABLE_SIZE: usize = 2 * 64 * 64 * 64;
+const CACHE_MAGIC: &[u8; 8] = b"RCDTM001";
+pub(crate) const CACHE_FILE: &str = "rustchess_dtm.bin";
+
+/// Entry of a drawn or impossible position
+const DRAW: u8 = u8::MAX;
+/// Entry of a position not resolved yet while building
+const UNKNOWN: u8 = u8::MAX - 1;
+
+static TABLEBASES: OnceLock<Tablebases> = OnceLock::new();
+
+/// The endings the tables cover, each a king and one piece against a king.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Queen,
+    Rook,
+    Pawn,
+}
+
+impl Ending {
+    /// In the order the tables are built and stored.
+    pub const ALL: [Ending; 3] = [Ending::Queen, Ending::Rook, Ending::Pawn];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ending::Queen => "KQ vs K",
+            Ending::Rook => "KR vs K",
+            Ending::Pawn => "KP vs K",
+        }
+    }
+
+    fn piece(self) -> PieceType {
+        match self {
+            Ending::Queen => PieceType::Queen,
+            Ending::Rook => PieceType::Rook,
+            Ending::Pawn => PieceType::Pawn,
+        }
+    }
+
+    fn of(piece: PieceType) -> Option<Ending> {
+        Ending::ALL.into_iter().find(|ending| ending.piece() == piece)
+    }
+}
+
+/// The result of a position with best play, for the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Draw,
+    /// The side to move mates in this many plies
+    Win(u32),
+    /// The side to move is mated in this many plies; 0 if it is mated now
+    Loss(u32),
+}
+
+impl Verdict {
+    /// Full moves the winning side needs to mate, `None` for a draw.
+    pub fn moves_to_mate(self) -> Option<u32> {
+        match self {
+            Verdict::Draw => None,
+            // The winner moves first and last, so the plies are odd
+            Verdict::Win(plies) => Some(plies / 2 + 1),
+            Verdict::Loss(plies) => Some(plies / 2),
+        }
+    }
+
+    /// Orders verdicts from the side to move's point of view: faster wins
+    /// first, slower losses before faster ones.
+    fn rank(self) -> i64 {
+        match self {
+            Verdict::Win(plies) => 1000 - plies as i64,
+            Verdict::Draw => 0,
+            Verdict::Loss(plies) => plies as i64 - 1000,
+        }
+    }
+
+    /// The verdict one ply earlier, for the side that moved into `self`.
+    fn before(self) -> Verdict {
+        match self {
+            Verdict::Draw => Verdict::Draw,
+            Verdict::Win(plies) => Verdict::Loss(plies + 1),
+            Verdict::Loss(plies) => Verdict::Win(plies + 1),
+        }
+    }
+}
+
+/// Distance-to-mate tables for all three endings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tablebases {
+    /// Plies to mate, or `DRAW`, per `Ending::ALL` and `index`
+    tables: [Vec<u8>; 3],
+}
+
+impl Tablebases {
+    /// Builds the tables by retrograde analysis.
+    pub fn generate() -> Self {
+        let queen = generate(Ending::Queen, &[]);
+        let rook = generate(Ending::Rook, &[]);
+        let pawn = generate(Ending::Pawn, &[&queen, &rook]);
+        Tablebases { tables: [queen, rook, pawn] }
+    }
+
+    /// Bytes the tables take.
+    pub fn memory_bytes(&self) -> usize {
+        self.tables.iter().map(Vec::len).sum()
+    }
+
+    /// Reads the tables from `path`, generating and writing them there if
+    /// the file is missing or unreadable.
+    pub fn load_or_generate(path: &Path) -> Self {
+        if let Some(tables) = fs::read(path).ok().and_then(|bytes| Self::from_bytes(&bytes)) {
+            return tables;
+        }
+        let tables = Self::generate();
+        // The cache is only an optimization, so failing to write it is fine
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, tables.to_bytes()).is_ok() {
+            let _ = fs::rename(&tmp, path);
+        }
+        tables
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = CACHE_MAGIC.to_vec();
+        for table in &self.tables {
+            bytes.extend_from_slice(table);
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let data = bytes.strip_prefix(CACHE_MAGIC.as_slice())?;
+        if data.len() != 3 * TABLE_SIZE {
+            return None;
+        }
+        let mut tables = data.chunks_exact(TABLE_SIZE).map(<[u8]>::to_vec);
+        Some(Tablebases { tables: [tables.next()?, tables.next()?, tables.next()?] })
+    }
+
+    fn entry(&self, ending: Ending, idx: usize) -> Verdict {
+        let white_to_move = idx < TABLE_SIZE / 2;
+        match self.tables[ending as usize][idx] {
+            DRAW => Verdict::Draw,
+            plies if white_to_move => Verdict::Win(plies as u32),
+            plies => Verdict::Loss(plies as u32),
+        }
+    }
+
+    /// Looks a position up.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position, with the side to move
+    ///
+    /// # Returns
+    ///
+    /// * Its verdict for the side to move, or `None` if it is not one of the
+    ///   endings covered. Bare kings and a lone minor piece are draws.
+    pub fn probe(&self, position: &Position) -> Option<Verdict> {
+        let mut piece = None;
+        for p in position.pieces.iter().filter(|p| p.piece_type != PieceType::King) {
+            if piece.replace(p).is_some() {
+                return None;
+            }
+        }
+        let Some(piece) = piece else {
+            return Some(Verdict::Draw);
+        };
+        if matches!(piece.piece_type, PieceType::Knight | PieceType::Bishop) {
+            return Some(Verdict::Draw);
+        }
+
+        let ending = Ending::of(piece.piece_type)?;
+        let strong = piece.color;
+        let weak = if strong == Color::White { Color::Black } else { Color::White };
+        // Mirror Black's pieces onto White's side of the board
+        let flip = if strong == Color::White { 0 } else { 56 };
+        let strong_king = position.king_square(strong)? ^ flip;
+        let weak_king = position.king_square(weak)? ^ flip;
+        let piece_square = bit_scan(piece.position) ^ flip;
+        let idx = index(position.active_color == strong, strong_king, weak_king, piece_square);
+        Some(self.entry(ending, idx))
+    }
+
+    /// The move that mates fastest when the side to move wins, keeps the
+    /// draw when it can and otherwise holds out longest.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - A position of one of the endings covered
+    /// * `game` - The game providing the attack tables
+    ///
+    /// # Returns
+    ///
+    /// * The move, or `None` if there is no legal move
+    pub fn best_move(&self, position: &Position, game: &Game) -> Option<u64> {
+        let mut position = position.clone();
+        position.update_all_legal_moves(game);
+        position.get_all_legal_moves(game).into_iter().max_by_key(|&mov| {
+            let mut after = position.clone();
+            after.make_move(mov);
+            self.probe(&after).map_or(0, |verdict| verdict.before().rank())
+        })
+    }
+
+    /// Positions with White to move that White wins in at least `min_moves`
+    /// moves, as FENs, for practising the ending.
+    pub fn won_positions(&self, ending: Ending, min_moves: u32) -> Vec<String> {
+        (0..TABLE_SIZE / 2)
+            .filter(|&idx| self.entry(ending, idx).moves_to_mate().is_some_and(|moves| moves >= min_moves))
+            .map(|idx| {
+                let (_, white_king, black_king, piece) = decode(idx);
+                fen_of(ending, white_king, black_king, piece)
+            })
+            .collect()
+    }
+
+    /// The longest mate with White to move, in plies.
+    pub fn longest_mate(&self, ending: Ending) -> u32 {
+        self.tables[ending as usize][..TABLE_SIZE / 2].iter()
+            .filter(|&&plies| plies != DRAW)
+            .map(|&plies| plies as u32)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The shared tables, loaded or generated on first use.
+pub fn tablebases() -> &'static Tablebases {
+    TABLEBASES.get_or_init(|| Tablebases::load_or_generate(&cache_path()))
+}
+
+/// The shared tables if they have been used yet, without loading them.
+pub fn loaded_tablebases() -> Option<&'static Tablebases> {
+    TABLEBASES.get()
+}
+
+/// A saved copy of the tables found among the asset paths, or where a
+/// generated copy is saved.
+fn cache_path() -> PathBuf {
+    Asset::Tablebases.locate().unwrap_or_else(|| std::env::temp_dir().join(CACHE_FILE))
+}
+
+/// Packs a normalized position into a table index.
+fn index(white_to_move: bool, white_king: usize, black_king: usize, piece: usize) -> usize {
+    let side = if white_to_move { 0 } else { 1 };
+    ((side * 64 + white_king) * 64 + black_king) * 64 + piece
+}
+
+fn decode(idx: usize) -> (bool, usize, usize, usize) {
+    (idx < TABLE_SIZE / 2, (idx >> 12) & 63, (idx >> 6) & 63, idx & 63)
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+    files.max(ranks)
+}
+
+fn king_moves(square: usize) -> impl Iterator<Item = usize> {
+    let king = 1u64 << square;
+    let row = king | ((king << 1) & !FILE_A) | ((king >> 1) & !FILE_H);
+    squares((row | (row << 8) | (row >> 8)) & !king)
+}
+
+/// The squares of a bitboard, lowest first.
+fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard.wrapping_sub(1);
+        (square < 64).then_some(square)
+    })
+}
+
+/// Squares a queen or rook on `from` reaches, stopping at `occupied`
+/// squares, which it attacks.
+fn slides(ending: Ending, from: usize, occupied: Bitboard) -> Bitboard {
+    // Straight lines first, so rooks take the first four
+    const DIRECTIONS: [(i32, i32); 8] = [(0, 1), (1, 0), (0, -1), (-1, 0), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+    let directions = match ending {
+        Ending::Queen => &DIRECTIONS[..],
+        Ending::Rook => &DIRECTIONS[..4],
+        Ending::Pawn => &[],
+    };
+    let mut reach = 0;
+    for &(df, dr) in directions {
+        let (mut file, mut rank) = ((from % 8) as i32, (from / 8) as i32);
+        loop {
+            file += df;
+            rank += dr;
+            if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+                break;
+            }
+            let square = (rank * 8 + file) as usize;
+            reach |= 1 << square;
+            if occupied & (1 << square) != 0 {
+                break;
+            }
+        }
+    }
+    reach
+}
+
+/// Whether White's piece on `piece` attacks `square`.
+fn attacks(ending: Ending, piece: usize, square: usize, occupied: Bitboard) -> bool {
+    match ending {
+        Ending::Pawn => square / 8 == piece / 8 + 1 && (square % 8).abs_diff(piece % 8) == 1,
+        _ => slides(ending, piece, occupied) & (1 << square) != 0,
+    }
+}
+
+fn is_valid(ending: Ending, white_to_move: bool, white_king: usize, black_king: usize, piece: usize) -> bool {
+    white_king != piece && black_king != piece && distance(white_king, black_king) > 1
+        && !(ending == Ending::Pawn && (piece / 8 == 0 || piece / 8 == 7))
+        // The side not to move cannot be in check
+        && !(white_to_move && attacks(ending, piece, black_king, 1 << white_king))
+}
+
+/// Where Black's king moves lead, or `None` if it can take White's piece,
+/// which draws.
+fn black_moves(ending: Ending, white_king: usize, black_king: usize, piece: usize) -> Option<Vec<usize>> {
+    let occupied = (1 << white_king) | (1 << piece);
+    let mut moves = Vec::new();
+    for square in king_moves(black_king).filter(|&square| distance(square, white_king) > 1) {
+        if square == piece {
+            return None;
+        }
+        if !attacks(ending, piece, square, occupied) {
+            moves.push(index(true, white_king, square, piece));
+        }
+    }
+    Some(moves)
+}
+
+/// The positions with White to move from which White reaches the given
+/// position with Black to move. Promotions come from the other tables and
+/// are not included.
+fn white_predecessors(ending: Ending, white_king: usize, black_king: usize, piece: usize) -> Vec<usize> {
+    let kings = (1 << white_king) | (1 << black_king);
+    let mut previous: Vec<usize> = king_moves(white_king)
+        .filter(|&square| square != piece)
+        .map(|square| index(true, square, black_king, piece))
+        .collect();
+    match ending {
+        Ending::Pawn => {
+            let from = piece.wrapping_sub(8);
+            if piece / 8 >= 2 && kings & (1 << from) == 0 {
+                previous.push(index(true, white_king, black_king, from));
+                if piece / 8 == 3 && kings & (1 << (from - 8)) == 0 {
+                    previous.push(index(true, white_king, black_king, from - 8));
+                }
+            }
+        }
+        // Slides are symmetric: the piece came from a square it now reaches
+        _ => previous.extend(squares(slides(ending, piece, kings) & !kings)
+            .map(|square| index(true, white_king, black_king, square))),
+    }
+    previous.retain(|&idx| {
+        let (_, white_king, black_king, piece) = decode(idx);
+        is_valid(ending, true, white_king, black_king, piece)
+    });
+    previous
+}
+
+/// The positions with Black to move from which Black's king reaches the
+/// given position with White to move.
+fn black_predecessors(ending: Ending, white_king: usize, black_king: usize, piece: usize) -> impl Iterator<Item = usize> {
+    king_moves(black_king)
+        .filter(move |&square| square != piece && is_valid(ending, false, white_king, square, piece))
+        .map(move |square| index(false, white_king, square, piece))
+}
+
+/// Builds one table; `promotions` are the finished queen and rook tables
+/// a pawn promotes into.
+fn generate(ending: Ending, promotions: &[&Vec<u8>]) -> Vec<u8> {
+    let mut table = vec![UNKNOWN; TABLE_SIZE];
+    // Black's moves not yet known to lose, per position with Black to move
+    let mut escapes = vec![0u8; TABLE_SIZE];
+    let mut frontier = Vec::new();
+    for idx in 0..TABLE_SIZE {
+        let (white_to_move, white_king, black_king, piece) = decode(idx);
+        if !is_valid(ending, white_to_move, white_king, black_king, piece) {
+            table[idx] = DRAW;
+        } else if !white_to_move {
+            match black_moves(ending, white_king, black_king, piece) {
+                None => table[idx] = DRAW,
+                Some(moves) if moves.is_empty() => {
+                    if attacks(ending, piece, black_king, 1 << white_king) {
+                        table[idx] = 0;
+                        frontier.push(idx);
+                    } else {
+                        table[idx] = DRAW;
+                    }
+                }
+                Some(moves) => escapes[idx] = moves.len() as u8,
+            }
+        }
+    }
+
+    // A pawn on the seventh wins one ply after the best promotion does
+    let mut promotion_wins: Vec<Vec<usize>> = Vec::new();
+    if ending == Ending::Pawn {
+        for idx in (0..TABLE_SIZE / 2).filter(|&idx| table[idx] == UNKNOWN) {
+            let (_, white_king, black_king, pawn) = decode(idx);
+            let push = pawn + 8;
+            if pawn / 8 != 6 || push == white_king || push == black_king {
+                continue;
+            }
+            // Knight and bishop promotions only draw
+            let promoted = index(false, white_king, black_king, push);
+            let best = promotions.iter().map(|table| table[promoted]).min().unwrap_or(DRAW);
+            if best != DRAW {
+                let plies = best as usize + 1;
+                promotion_wins.resize(promotion_wins.len().max(plies + 1), Vec::new());
+                promotion_wins[plies].push(idx);
+            }
+        }
+    }
+
+    // Positions resolved at `plies - 1` resolve their predecessors
+    let mut plies: u8 = 1;
+    while !frontier.is_empty() || (plies as usize) < promotion_wins.len() {
+        let mut found = Vec::new();
+        if plies % 2 == 1 {
+            // White mates in `plies` by moving into a loss in one fewer
+            let promoting = promotion_wins.get(plies as usize).into_iter().flatten().copied();
+            let previous = frontier.iter().flat_map(|&idx| {
+                let (_, white_king, black_king, piece) = decode(idx);
+                white_predecessors(ending, white_king, black_king, piece)
+            });
+            for idx in previous.chain(promoting) {
+                if table[idx] == UNKNOWN {
+                    table[idx] = plies;
+                    found.push(idx);
+                }
+            }
+        } else {
+            // Black is mated in `plies` once its last escape is shut
+            for &idx in &frontier {
+                let (_, white_king, black_king, piece) = decode(idx);
+                for previous in black_predecessors(ending, white_king, black_king, piece) {
+                    if table[previous] == UNKNOWN {
+                        escapes[previous] -= 1;
+                        if escapes[previous] == 0 {
+                            table[previous] = plies;
+                            found.push(previous);
+                        }
+                    }
+                }
+            }
+        }
+        frontier = found;
+        plies += 1;
+    }
+
+    for entry in table.iter_mut().filter(|entry| **entry == UNKNOWN) {
+        *entry = DRAW;
+    }
+    table
+}
+
+/// FEN of a normalized position with White to move.
+fn fen_of(ending: Ending, white_king: usize, black_king: usize, piece: usize) -> String {
+    let symbol = match ending {
+        Ending::Queen => 'Q',
+        Ending::Rook => 'R',
+        Ending::Pawn => 'P',
+    };
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            let square = rank * 8 + file;
+            let occupant = match square {
+                _ if square == white_king => Some('K'),
+                _ if square == black_king => Some('k'),
+                _ if square == piece => Some(symbol),
+                _ => None,
+            };
+            match occupant {
+                Some(symbol) => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(symbol);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+    format!("{} w - - 0 1", placement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbase;
+
+    fn probe(fen: &str) -> Option<Verdict> {
+        let game = Game::new();
+        tablebases().probe(&Position::from_fen(fen, &game).unwrap())
+    }
+
+    #[test]
+    fn test_longest_mates() {
+        let tables = tablebases();
+        // The well-known maxima: mate in 10 with the queen, 16 with the rook
+        assert_eq!(tables.longest_mate(Ending::Queen), 19);
+        assert_eq!(tables.longest_mate(Ending::Rook), 31);
+        assert_eq!(Tablebases::from_bytes(&tables.to_bytes()).as_ref(), Some(tables));
+        assert!(Tablebases::from_bytes(b"RCDTM001 truncated").is_none());
+    }
+
+    #[test]
+    fn test_probe_positions() {
+        assert_eq!(probe("6k1/8/6K1/8/8/8/8/R7 w - - 0 1"), Some(Verdict::Win(1)));
+        assert_eq!(probe("R5k1/8/6K1/8/8/8/8/8 b - - 0 1"), Some(Verdict::Loss(0)));
+        // Colors swapped
+        assert_eq!(probe("r7/8/8/8/8/6k1/8/6K1 b - - 0 1"), Some(Verdict::Win(1)));
+        // The lone king takes the queen, or is stalemated
+        assert_eq!(probe("8/8/8/8/8/8/1q6/K6k w - - 0 1"), Some(Verdict::Draw));
+        assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Verdict::Draw));
+        assert_eq!(probe("k7/8/8/P7/8/8/8/7K w - - 0 1"), Some(Verdict::Draw));
+        assert_eq!(probe("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"), Some(Verdict::Draw));
+        assert_eq!(probe(crate::board::START_FEN), None);
+        assert_eq!(Verdict::Win(7).moves_to_mate(), Some(4));
+        assert_eq!(Verdict::Loss(6).moves_to_mate(), Some(3));
+    }
+
+    #[test]
+    fn test_pawn_table_agrees_with_the_bitbase() {
+        let tables = tablebases();
+        let kpk = bitbase::kpk();
+        for idx in 0..TABLE_SIZE {
+            let (white_to_move, white_king, black_king, pawn) = decode(idx);
+            if !is_valid(Ending::Pawn, white_to_move, white_king, black_king, pawn) {
+                continue;
+            }
+            let to_move = if white_to_move { Color::White } else { Color::Black };
+            let won = kpk.probe(white_king, pawn, black_king, Color::White, to_move);
+            let verdict = tables.entry(Ending::Pawn, idx);
+            assert_eq!(verdict != Verdict::Draw, won, "{} {:?}", fen_of(Ending::Pawn, white_king, black_king, pawn), verdict);
+        }
+    }
+
+    #[test]
+    fn test_best_moves_mate_and_defend() {
+        let game = Game::new();
+        let tables = tablebases();
+        let mut position = Position::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", &game).unwrap();
+        let start = tables.probe(&position).unwrap();
+        let Verdict::Win(mut plies) = start else {
+            panic!("{:?}", start);
+        };
+        // Perfect play on both sides takes exactly the table's distance
+        while plies > 0 {
+            let mov = tables.best_move(&position, &game).unwrap();
+            position.make_move(mov);
+            plies -= 1;
+            let expected = if plies % 2 == 0 { Verdict::Loss(plies) } else { Verdict::Win(plies) };
+            assert_eq!(tables.probe(&position), Some(expected));
+        }
+        position.update_all_legal_moves(&game);
+        assert!(tables.best_move(&position, &game).is_none());
+
+        let starts = tables.won_positions(Ending::Queen, 10);
+        assert!(!starts.is_empty());
+        assert!(starts.iter().all(|fen| probe(fen) == Some(Verdict::Win(19))));
+    }
+}
@@ -0,0 +1,334 @@
+//! Time allocation when playing on a clock.
+//!
+//! A `TimeManager` turns the remaining clock time, the increment and the
+//! moves left to the next time control into two budgets for one move: an
+//! optimum the search normally stops at, and a hard maximum it never
+//! exceeds. Between iterations the search reports its best move and score;
+//! a best move that keeps changing or a falling score stretches the optimum
+//! towards the maximum, a stable one lets the search stop early.
+//!
+//! However long the search thinks, the maximum stays within a share of the
+//! clock minus the move overhead (`MOVE_OVERHEAD` unless the `MoveOverhead`
+//! option says otherwise), and below `EMERGENCY_TIME` the engine moves
+//! on a small fixed budget, so it never loses on time. When even that is
+//! not enough, because the clock left would run out before the game does
+//! at `LOW_TIME_PER_MOVE` a move, the manager asks for low-time mode, in
+//! which the search settles for a shallow fixed depth.
+//!
+//! When a side does run out of time, `adjudicate_flag` scores the game as
+//! FIDE does: a loss, unless the opponent has no way left to mate.
+
+use std::time::Duration;
+
+use crate::position::{Color, PieceType, Position};
+
+/// Time kept back on every move for communication and thread start-up, by
+/// default.
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Below this much usable time, think only briefly and never extend.
+const EMERGENCY_TIME: Duration = Duration::from_secs(1);
+/// Thinking this long per move for the rest of the game is as fast as the
+/// engine can play; with less the manager predicts a loss on time.
+const LOW_TIME_PER_MOVE: Duration = Duration::from_millis(100);
+/// Front ends warn when either side has less than this on its clock.
+pub const LOW_TIME_WARNING: Duration = Duration::from_secs(10);
+/// Moves assumed left in the game without a `movestogo`, in an endgame
+/// with almost nothing left and in the initial position respectively.
+const MIN_MOVES_LEFT: u32 = 15;
+const MAX_MOVES_LEFT: u32 = 40;
+/// The maximum is at most this many times the optimum.
+const MAX_OVERSHOOT: u32 = 5;
+/// Optimum added per best-move change, as a share of the optimum. Changes
+/// count less with every iteration that follows.
+const INSTABILITY_WEIGHT: f64 = 0.5;
+const INSTABILITY_DECAY: f64 = 0.5;
+/// A score falling by more than this many centipawns between iterations
+/// means trouble, and earns `SCORE_DROP_FACTOR` times the optimum.
+const SCORE_DROP_MARGIN: i32 = 30;
+const SCORE_DROP_FACTOR: f64 = 1.5;
+/// Another iteration is started only before this share of the soft limit
+/// has passed, since it usually takes longer than all earlier ones together.
+const NEXT_ITERATION_SHARE: f64 = 0.5;
+
+/// The side to move's clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    /// Moves to play before the next time control; `None` for sudden death
+    pub moves_to_go: Option<u32>,
+}
+
+/// How a game ends when one side's clock runs out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagResult {
+    /// The side with time left wins
+    Win(Color),
+    /// The side with time left cannot mate, so the flag only draws
+    Draw,
+}
+
+/// Scores a game lost on time by `flagged` in `position` (FIDE Laws 6.9).
+///
+/// # Arguments
+///
+/// * `position` - The position on the board when the flag fell
+/// * `flagged` - The side whose time ran out
+///
+/// # Returns
+///
+/// * A win for the opponent, or a draw if the opponent could not mate by
+///   any series of legal moves
+pub fn adjudicate_flag(position: &Position, flagged: Color) -> FlagResult {
+    let opponent = if flagged == Color::White { Color::Black } else { Color::White };
+    if position.can_mate(opponent) {
+        FlagResult::Win(opponent)
+    } else {
+        FlagResult::Draw
+    }
+}
+
+/// Time budgets for one move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeManager {
+    optimum: Duration,
+    maximum: Duration,
+    /// Decaying count of best-move changes between iterations
+    instability: f64,
+    /// Extra factor on the optimum while the score is falling
+    score_factor: f64,
+    last_best: Option<u64>,
+    last_score: Option<i32>,
+    /// The clock is about to run out; see `low_time`
+    low_time: bool,
+}
+
+impl TimeManager {
+    /// Allocates time for the next move.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The side to move's clock
+    /// * `position` - The position to move in; without `moves_to_go` its
+    ///   material estimates how many moves the game has left
+    pub fn new(clock: &Clock, position: &Position) -> Self {
+        TimeManager::with_overhead(clock, position, MOVE_OVERHEAD)
+    }
+
+    /// Allocates time for the next move, keeping `overhead` back rather
+    /// than `MOVE_OVERHEAD` for the move to reach the opponent.
+    pub fn with_overhead(clock: &Clock, position: &Position, overhead: Duration) -> Self {
+        let usable = clock.remaining.saturating_sub(overhead);
+        let moves_left = clock.moves_to_go.map_or_else(|| estimate_moves_left(position), |moves| moves.max(1));
+
+        let (optimum, maximum) = if usable < EMERGENCY_TIME {
+            let budget = (usable / 10 + clock.increment / 2).min(usable / 3);
+            (budget, budget)
+        } else {
+            // Never more than a third of the clock, unless the next time
+            // control starts after this move
+            let cap = if moves_left == 1 { usable * 9 / 10 } else { usable / 3 };
+            let optimum = (usable / moves_left + clock.increment * 3 / 4).min(cap);
+            (optimum, (optimum * MAX_OVERSHOOT).min(cap))
+        };
+
+        let floor = Duration::from_millis(1);
+        TimeManager {
+            optimum: optimum.max(floor),
+            maximum: maximum.max(floor),
+            instability: 0.0,
+            score_factor: 1.0,
+            last_best: None,
+            last_score: None,
+            low_time: usable < EMERGENCY_TIME || flag_predicted(usable, clock.increment, moves_left),
+        }
+    }
+
+    /// Whether the clock is so low that the search should play in low-time
+    /// mode: shallow fixed-depth searches that trust the transposition table
+    /// and skip the refinements of the quiescence search.
+    pub fn low_time(&self) -> bool {
+        self.low_time
+    }
+
+    /// Time the search aims for when its best move is stable.
+    pub fn optimum(&self) -> Duration {
+        self.optimum
+    }
+
+    /// Time the search may never exceed.
+    pub fn maximum(&self) -> Duration {
+        self.maximum
+    }
+
+    /// Forgets the iterations of an earlier search.
+    pub fn start(&mut self) {
+        self.instability = 0.0;
+        self.score_factor = 1.0;
+        self.last_best = None;
+        self.last_score = None;
+    }
+
+    /// Records the result of a completed iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * `best_move` - The iteration's best move
+    /// * `score` - Its score from the point of view of the side to move
+    pub fn record_iteration(&mut self, best_move: u64, score: i32) {
+        self.instability *= INSTABILITY_DECAY;
+        if self.last_best.is_some_and(|last| last != best_move) {
+            self.instability += 1.0;
+        }
+        self.score_factor = match self.last_score {
+            Some(last) if score < last - SCORE_DROP_MARGIN => SCORE_DROP_FACTOR,
+            _ => 1.0,
+        };
+        self.last_best = Some(best_move);
+        self.last_score = Some(score);
+    }
+
+    /// The optimum stretched by instability and a falling score, up to the
+    /// maximum.
+    pub fn soft_limit(&self) -> Duration {
+        let factor = (1.0 + self.instability * INSTABILITY_WEIGHT) * self.score_factor;
+        self.optimum.mul_f64(factor).min(self.maximum)
+    }
+
+    /// Whether there is time to start another iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - Time searched so far
+    pub fn should_start_iteration(&self, elapsed: Duration) -> bool {
+        elapsed < self.soft_limit().mul_f64(NEXT_ITERATION_SHARE)
+    }
+}
+
+/// Whether `clock` runs out before `moves_left` more moves are played at
+/// `LOW_TIME_PER_MOVE` each, counting the increments earned on the way.
+///
+/// # Arguments
+///
+/// * `clock` - The side to move's clock
+/// * `moves_left` - Moves to play before the clock is topped up, or in the
+///   rest of the game
+pub fn predicts_flag(clock: &Clock, moves_left: u32) -> bool {
+    flag_predicted(clock.remaining.saturating_sub(MOVE_OVERHEAD), clock.increment, moves_left)
+}
+
+/// `predicts_flag` for `usable` time left after the move overhead.
+fn flag_predicted(usable: Duration, increment: Duration, moves_left: u32) -> bool {
+    let moves_left = moves_left.max(1);
+    usable + increment * (moves_left - 1) < LOW_TIME_PER_MOVE * moves_left
+}
+
+/// Moves the game probably has left, from the material on the board: from
+/// `MAX_MOVES_LEFT` with all pieces on the board down to `MIN_MOVES_LEFT`
+/// with bare kings.
+pub fn estimate_moves_left(position: &Position) -> u32 {
+    let pieces = position.pieces.iter()
+        .filter(|piece| piece.piece_type != PieceType::King)
+        .count()
+        .min(30) as u32;
+    MIN_MOVES_LEFT + pieces * (MAX_MOVES_LEFT - MIN_MOVES_LEFT) / 30
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    fn clock(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> Clock {
+        Clock {
+            remaining: Duration::from_millis(remaining_ms),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+        }
+    }
+
+    #[test]
+    fn test_allocation_follows_the_clock() {
+        let game = Game::new();
+        let start = &game.position;
+        let endgame = Position::read_FEN("8/5k2/8/8/8/8/3K1R2/8 w - - 0 1", &game);
+        assert_eq!(estimate_moves_left(start), MAX_MOVES_LEFT);
+        assert_eq!(estimate_moves_left(&endgame), MIN_MOVES_LEFT);
+
+        let opening = TimeManager::new(&clock(60_000, 0, None), start);
+        assert_eq!(opening.optimum(), Duration::from_millis(59_950) / 40);
+        assert_eq!(opening.maximum(), opening.optimum() * MAX_OVERSHOOT);
+        // Fewer moves left and an increment both mean more time per move
+        assert!(TimeManager::new(&clock(60_000, 0, None), &endgame).optimum() > opening.optimum());
+        assert!(TimeManager::new(&clock(60_000, 1000, None), start).optimum() > opening.optimum());
+        // The last move before the control may use most of the clock
+        let last = TimeManager::new(&clock(10_000, 0, Some(1)), start);
+        assert!(last.maximum() > Duration::from_secs(8) && last.maximum() < Duration::from_secs(10));
+        // A larger overhead leaves less to share out
+        let slow_link = TimeManager::with_overhead(&clock(60_000, 0, None), start, Duration::from_millis(2_050));
+        assert_eq!(slow_link.optimum(), Duration::from_millis(57_950) / 40);
+    }
+
+    #[test]
+    fn test_low_clock_never_flags() {
+        let game = Game::new();
+        for remaining_ms in [0, 1, 40, 60, 500, 1_100, 3_000] {
+            for increment_ms in [0, 100, 2_000] {
+                let manager = TimeManager::new(&clock(remaining_ms, increment_ms, Some(1)), &game.position);
+                let usable = Duration::from_millis(remaining_ms).saturating_sub(MOVE_OVERHEAD);
+                assert!(manager.optimum() <= manager.maximum());
+                assert!(manager.maximum() <= usable.max(Duration::from_millis(1)), "{} ms left", remaining_ms);
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_time_mode_predicts_the_flag() {
+        let game = Game::new();
+        assert!(!predicts_flag(&clock(60_000, 0, None), 40));
+        assert!(predicts_flag(&clock(3_000, 0, None), 40));
+        // An increment pays for the moves to come
+        assert!(!predicts_flag(&clock(3_000, 1_000, None), 40));
+        assert!(!predicts_flag(&clock(3_000, 0, Some(5)), 5));
+
+        assert!(!TimeManager::new(&clock(60_000, 0, None), &game.position).low_time());
+        assert!(TimeManager::new(&clock(3_000, 0, None), &game.position).low_time());
+        assert!(TimeManager::new(&clock(900, 2_000, None), &game.position).low_time());
+    }
+
+    #[test]
+    fn test_instability_extends_the_soft_limit() {
+        let game = Game::new();
+        let mut manager = TimeManager::new(&clock(60_000, 0, None), &game.position);
+        manager.record_iteration(1, 20);
+        manager.record_iteration(1, 25);
+        assert_eq!(manager.soft_limit(), manager.optimum());
+        assert!(manager.should_start_iteration(manager.optimum() / 4));
+        assert!(!manager.should_start_iteration(manager.optimum() * 3 / 4));
+
+        // A new best move, then a falling score
+        manager.record_iteration(2, 25);
+        assert!(manager.soft_limit() > manager.optimum());
+        manager.record_iteration(2, -40);
+        let troubled = manager.soft_limit();
+        assert!(troubled > manager.optimum().mul_f64(SCORE_DROP_FACTOR));
+        assert!(troubled <= manager.maximum());
+
+        manager.start();
+        assert_eq!(manager.soft_limit(), manager.optimum());
+    }
+
+    #[test]
+    fn test_flag_against_insufficient_material_draws() {
+        let game = Game::new();
+        let flag = |fen: &str, flagged: Color| adjudicate_flag(&Position::from_fen(fen, &game).unwrap(), flagged);
+        assert_eq!(flag(crate::board::START_FEN, Color::White), FlagResult::Win(Color::Black));
+        // White flags with a rook against a bare king and a knight
+        assert_eq!(flag("4k3/8/8/8/8/8/8/4KRn1 w - - 0 1", Color::White), FlagResult::Win(Color::Black));
+        assert_eq!(flag("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", Color::White), FlagResult::Draw);
+        assert_eq!(flag("4k3/8/8/8/8/8/8/4KR2 b - - 0 1", Color::Black), FlagResult::Win(Color::White));
+        // Two bishops on one color cannot force, or even help, a mate
+        assert_eq!(flag("4k3/8/8/8/8/8/3p4/2b1K1b1 w - - 0 1", Color::White), FlagResult::Win(Color::Black));
+        assert_eq!(flag("4k3/8/8/8/8/8/3Q4/2b1K1b1 w - - 0 1", Color::White), FlagResult::Draw);
+    }
+}
@@ -1,5 +1,13 @@
+use alloc::{format, string::{String, ToString}, vec, vec::Vec};
 use crate::chess_move::Move;
 
+/// Starts every saved table. The digits are the revision of what scores
+/// mean: bump them whenever a change to evaluation or search makes saved
+/// scores stale, and older files are ignored.
+const HASH_FILE_MAGIC: &[u8; 8] = b"RCTT0002";
+/// Bytes per saved entry: hash, depth, flag, value and best move.
+const SAVED_ENTRY_SIZE: usize = 8 + 4 + 1 + 4 + 8;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum NodeType {
     Exact,    // Exact score
@@ -27,7 +35,7 @@ pub struct TranspositionTable {
 impl TranspositionTable {
     pub fn new(size_mb: usize) -> Self {
         // Calculate number of entries that fit in size_mb megabytes
-        let entry_size = std::mem::size_of::<TranspositionEntry>();
+        let entry_size = core::mem::size_of::<Option<TranspositionEntry>>();
         let num_entries = (size_mb * 1024 * 1024) / entry_size;
         
         TranspositionTable {
@@ -77,6 +85,75 @@ impl TranspositionTable {
         self.age = 0;
     }
 
+    /// Bytes the entries take.
+    pub fn memory_bytes(&self) -> usize {
+        self.table.len() * core::mem::size_of::<Option<TranspositionEntry>>()
+    }
+
+    /// Replaces the table with an empty one of `size_mb` megabytes.
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    /// Serializes the entries searched at least `min_depth` plies deep,
+    /// for a hash file that outlives the session.
+    ///
+    /// The bytes start with `HASH_FILE_MAGIC` and the crate version, so a
+    /// file written by another version of the engine is not loaded.
+    pub fn to_bytes(&self, min_depth: i32) -> Vec<u8> {
+        let version = env!("CARGO_PKG_VERSION").as_bytes();
+        let mut bytes = HASH_FILE_MAGIC.to_vec();
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version);
+        for entry in self.table.iter().flatten().filter(|entry| entry.depth >= min_depth) {
+            bytes.extend_from_slice(&entry.hash.to_le_bytes());
+            bytes.extend_from_slice(&entry.depth.to_le_bytes());
+            bytes.push(entry.flag as u8);
+            bytes.extend_from_slice(&entry.value.to_le_bytes());
+            bytes.extend_from_slice(&entry.best_move.unwrap_or(0).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Stores the entries serialized by `to_bytes`, as if they had just
+    /// been searched; entries already in the table are replaced as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The contents of a hash file
+    ///
+    /// # Returns
+    ///
+    /// * The number of entries read, or an error if the bytes are not a
+    ///   hash file or were written by another version of the engine
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        let data = bytes.strip_prefix(HASH_FILE_MAGIC.as_slice())
+            .ok_or_else(|| "Not a hash file, or one from another engine revision".to_string())?;
+        let (&length, data) = data.split_first().ok_or_else(|| "Truncated hash file".to_string())?;
+        let version = data.get(..length as usize).ok_or_else(|| "Truncated hash file".to_string())?;
+        if version != env!("CARGO_PKG_VERSION").as_bytes() {
+            return Err(format!("Hash file written by engine version {}", String::from_utf8_lossy(version)));
+        }
+        let data = &data[length as usize..];
+        if data.len() % SAVED_ENTRY_SIZE != 0 {
+            return Err("Truncated hash file".to_string());
+        }
+
+        let word = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+        let int = |bytes: &[u8]| i32::from_le_bytes(bytes.try_into().unwrap());
+        for chunk in data.chunks_exact(SAVED_ENTRY_SIZE) {
+            let flag = match chunk[12] {
+                0 => NodeType::Exact,
+                1 => NodeType::Alpha,
+                2 => NodeType::Beta,
+                other => return Err(format!("Invalid node type {} in hash file", other)),
+            };
+            let best_move = Some(word(&chunk[17..25])).filter(|&mov| mov != 0);
+            self.store(word(&chunk[0..8]), int(&chunk[8..12]), flag, int(&chunk[13..17]), best_move);
+        }
+        Ok(data.len() / SAVED_ENTRY_SIZE)
+    }
+
     fn get_index(&self, hash: u64) -> usize {
         (hash as usize) % self.size
     }
@@ -105,6 +182,27 @@ mod tests {
         assert_eq!(entry.best_move, best_move);
     }
 
+    #[test]
+    fn test_memory_bytes_fit_the_requested_size() {
+        let slot = core::mem::size_of::<Option<TranspositionEntry>>();
+        for size_mb in [1, 3, 16] {
+            let tt = TranspositionTable::new(size_mb);
+            let requested = size_mb * 1024 * 1024;
+            assert!(tt.memory_bytes() <= requested, "{} MB", size_mb);
+            assert!(tt.memory_bytes() + slot > requested, "{} MB", size_mb);
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(123456789, 4, NodeType::Exact, 100, None);
+        assert!(tt.memory_bytes() <= 1024 * 1024);
+        tt.resize(2);
+        assert!(tt.memory_bytes() > 1024 * 1024 && tt.memory_bytes() <= 2 * 1024 * 1024);
+        assert!(tt.probe(123456789).is_none());
+    }
+
     #[test]
     fn test_replacement_strategy() {
         let mut tt = TranspositionTable::new(1);
@@ -140,4 +238,30 @@ mod tests {
 
         assert_ne!(initial_age, new_age);
     }
+
+    #[test]
+    fn test_hash_file_round_trip() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(1, 6, NodeType::Exact, 35, Some(Move::encode(12, 28, None)));
+        tt.store(2, 1, NodeType::Beta, -20, None);
+        tt.store(3, 4, NodeType::Alpha, -20, None);
+        let bytes = tt.to_bytes(3);
+
+        let mut loaded = TranspositionTable::new(1);
+        assert_eq!(loaded.load_bytes(&bytes), Ok(2));
+        let entry = loaded.probe(1).unwrap();
+        assert_eq!((entry.depth, entry.flag, entry.value), (6, NodeType::Exact, 35));
+        assert_eq!(entry.best_move, Some(Move::encode(12, 28, None)));
+        assert_eq!(loaded.probe(3).unwrap().flag, NodeType::Alpha);
+        assert!(loaded.probe(2).is_none());
+
+        // Another revision, another version or a cut-off file is rejected
+        let mut other_revision = bytes.clone();
+        other_revision[7] = b'0';
+        assert!(loaded.load_bytes(&other_revision).is_err());
+        let mut other_version = bytes.clone();
+        other_version[9] = b'9';
+        assert!(loaded.load_bytes(&other_version).is_err());
+        assert!(loaded.load_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
 }
@@ -0,0 +1,330 @@
+//! Search parameter tuning by SPSA.
+//!
+//! Simultaneous perturbation stochastic approximation moves every tuned
+//! parameter at once: each iteration picks a random direction, plays a
+//! short self-play match between the parameters nudged one way and the
+//! parameters nudged the other way, and steps towards the side that scored
+//! better. Steps shrink as the iterations go on, so the parameters settle.
+//!
+//! Matches are played with `calibration::play_game_from` from short random
+//! openings, each opening twice with colors swapped. The result is a
+//! `SearchParams` that `Search` reads back from its TOML file.
+
+use rand::prelude::*;
+
+use crate::board::Board;
+use crate::calibration::{play_game_from, MatchResult};
+use crate::player::SearchLimits;
+use crate::position::Color;
+use crate::search::Search;
+use crate::search_params::SearchParams;
+
+/// Decay exponent of the step size.
+const ALPHA: f64 = 0.602;
+/// Decay exponent of the perturbation size.
+const GAMMA: f64 = 0.101;
+/// Iterations added to the step size's denominator, damping the first steps.
+const STABILITY: f64 = 10.0;
+
+/// A search parameter the tuner can move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tunable {
+    QuiescenceDepth,
+    /// 1 searches quiet checks at the first quiescence ply, 0 does not
+    QuiescenceChecks,
+    /// 1 prunes quiescence captures by SEE, 0 does not
+    QuiescenceSeePruning,
+    QuiescenceSeeThreshold,
+    NullMoveReduction,
+    FutilityMargin,
+    LmrReduction,
+    LmrMinMoves,
+}
+
+impl Tunable {
+    /// Every tunable parameter.
+    pub const ALL: [Tunable; 8] = [
+        Tunable::QuiescenceDepth,
+        Tunable::QuiescenceChecks,
+        Tunable::QuiescenceSeePruning,
+        Tunable::QuiescenceSeeThreshold,
+        Tunable::NullMoveReduction,
+        Tunable::FutilityMargin,
+        Tunable::LmrReduction,
+        Tunable::LmrMinMoves,
+    ];
+
+    /// The parameter's key in the TOML file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tunable::QuiescenceDepth => "quiescence_depth",
+            Tunable::QuiescenceChecks => "quiescence_checks",
+            Tunable::QuiescenceSeePruning => "quiescence_see_pruning",
+            Tunable::QuiescenceSeeThreshold => "quiescence_see_threshold",
+            Tunable::NullMoveReduction => "null_move_reduction",
+            Tunable::FutilityMargin => "futility_margin",
+            Tunable::LmrReduction => "lmr_reduction",
+            Tunable::LmrMinMoves => "lmr_min_moves",
+        }
+    }
+
+    /// Smallest and largest value the tuner may try.
+    pub fn range(self) -> (i32, i32) {
+        match self {
+            Tunable::QuiescenceDepth => (1, 8),
+            Tunable::QuiescenceChecks | Tunable::QuiescenceSeePruning => (0, 1),
+            Tunable::QuiescenceSeeThreshold => (-300, 300),
+            Tunable::NullMoveReduction => (0, 4),
+            Tunable::FutilityMargin => (0, 400),
+            Tunable::LmrReduction => (0, 3),
+            Tunable::LmrMinMoves => (1, 12),
+        }
+    }
+
+    /// Size of the first perturbation, large enough that the two sides of a
+    /// match actually play differently.
+    pub fn perturbation(self) -> f64 {
+        match self {
+            Tunable::FutilityMargin | Tunable::QuiescenceSeeThreshold => 40.0,
+            Tunable::LmrMinMoves => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    pub fn get(self, params: &SearchParams) -> i32 {
+        match self {
+            Tunable::QuiescenceDepth => params.quiescence_depth,
+            Tunable::QuiescenceChecks => params.quiescence_checks as i32,
+            Tunable::QuiescenceSeePruning => params.quiescence_see_pruning as i32,
+            Tunable::QuiescenceSeeThreshold => params.quiescence_see_threshold,
+            Tunable::NullMoveReduction => params.null_move_reduction,
+            Tunable::FutilityMargin => params.futility_margin,
+            Tunable::LmrReduction => params.lmr_reduction,
+            Tunable::LmrMinMoves => params.lmr_min_moves as i32,
+        }
+    }
+
+    /// Sets the parameter, clamped to its range.
+    pub fn set(self, params: &mut SearchParams, value: i32) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        match self {
+            Tunable::QuiescenceDepth => params.quiescence_depth = value,
+            Tunable::QuiescenceChecks => params.quiescence_checks = value != 0,
+            Tunable::QuiescenceSeePruning => params.quiescence_see_pruning = value != 0,
+            Tunable::QuiescenceSeeThreshold => params.quiescence_see_threshold = value,
+            Tunable::NullMoveReduction => params.null_move_reduction = value,
+            Tunable::FutilityMargin => params.futility_margin = value,
+            Tunable::LmrReduction => params.lmr_reduction = value,
+            Tunable::LmrMinMoves => params.lmr_min_moves = value as usize,
+        }
+    }
+}
+
+/// How the tuner plays its matches and how far it steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpsaSettings {
+    /// Openings per match, each played with both colors
+    pub game_pairs: u32,
+    /// Thinking time per move
+    pub limits: SearchLimits,
+    /// Random moves played from the initial position before each game pair
+    pub opening_plies: u32,
+    /// Scales every step, in units of the parameters' perturbation
+    pub learning_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for SpsaSettings {
+    fn default() -> Self {
+        SpsaSettings {
+            game_pairs: 4,
+            limits: SearchLimits::movetime_ms(50),
+            opening_plies: 4,
+            learning_rate: 2.0,
+            seed: 0,
+        }
+    }
+}
+
+/// What one iteration tried and found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationReport {
+    /// Counted from 1
+    pub iteration: u32,
+    pub plus: SearchParams,
+    pub minus: SearchParams,
+    /// Score of `plus` against `minus`, from -1 (lost every game) to 1
+    pub score: f64,
+    /// The parameters after the step
+    pub params: SearchParams,
+}
+
+/// An SPSA run over a set of tunable parameters.
+pub struct Spsa {
+    tunables: Vec<Tunable>,
+    /// Current values of the tuned parameters, unrounded
+    theta: Vec<f64>,
+    /// Parameters that are not tuned keep these values
+    base: SearchParams,
+    settings: SpsaSettings,
+    rng: StdRng,
+    iteration: u32,
+}
+
+impl Spsa {
+    /// Creates a tuner.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The parameters to start from, usually the current config
+    /// * `tunables` - The parameters to tune; the others keep their values
+    /// * `settings` - Match length and step size
+    pub fn new(start: SearchParams, tunables: &[Tunable], settings: SpsaSettings) -> Self {
+        Spsa {
+            tunables: tunables.to_vec(),
+            theta: tunables.iter().map(|tunable| tunable.get(&start) as f64).collect(),
+            base: start,
+            rng: StdRng::seed_from_u64(settings.seed),
+            settings,
+            iteration: 0,
+        }
+    }
+
+    /// The current parameters, rounded to whole values.
+    pub fn params(&self) -> SearchParams {
+        self.params_at(&self.theta)
+    }
+
+    fn params_at(&self, theta: &[f64]) -> SearchParams {
+        let mut params = self.base;
+        for (tunable, value) in self.tunables.iter().zip(theta) {
+            tunable.set(&mut params, value.round() as i32);
+        }
+        params
+    }
+
+    /// Runs `iterations` iterations.
+    ///
+    /// # Arguments
+    ///
+    /// * `iterations` - Number of matches to play
+    /// * `on_iteration` - Called after every iteration, e.g. to save progress
+    ///
+    /// # Returns
+    ///
+    /// * The tuned parameters
+    pub fn run<F: FnMut(&IterationReport)>(&mut self, iterations: u32, mut on_iteration: F) -> SearchParams {
+        for _ in 0..iterations {
+            let report = self.step();
+            on_iteration(&report);
+        }
+        self.params()
+    }
+
+    /// Plays one self-play match and steps the parameters.
+    pub fn step(&mut self) -> IterationReport {
+        let limits = self.settings.limits.clone();
+        let openings: Vec<Board> = (0..self.settings.game_pairs).map(|_| self.random_opening()).collect();
+        self.step_with(|plus, minus| play_match(plus, minus, &openings, &limits))
+    }
+
+    /// Like `step`, with the match result supplied by `play` instead of
+    /// self-play.
+    ///
+    /// # Arguments
+    ///
+    /// * `play` - Scores the first parameters against the second, from -1 to 1
+    pub fn step_with<F: FnOnce(&SearchParams, &SearchParams) -> f64>(&mut self, play: F) -> IterationReport {
+        let k = self.iteration as f64;
+        let step_size = self.settings.learning_rate / (k + 1.0 + STABILITY).powf(ALPHA);
+        let spread = 1.0 / (k + 1.0).powf(GAMMA);
+
+        let deltas: Vec<f64> = self.tunables.iter()
+            .map(|tunable| if self.rng.gen::<bool>() { 1.0 } else { -1.0 } * tunable.perturbation() * spread)
+            .collect();
+        let shifted = |sign: f64| -> Vec<f64> {
+            self.theta.iter().zip(&deltas).map(|(value, delta)| value + sign * delta).collect()
+        };
+        let plus = self.params_at(&shifted(1.0));
+        let minus = self.params_at(&shifted(-1.0));
+
+        let score = play(&plus, &minus);
+        for ((value, delta), tunable) in self.theta.iter_mut().zip(&deltas).zip(&self.tunables) {
+            let (min, max) = tunable.range();
+            *value = (*value + step_size * score * delta).clamp(min as f64, max as f64);
+        }
+
+        self.iteration += 1;
+        IterationReport { iteration: self.iteration, plus, minus, score, params: self.params() }
+    }
+
+    /// A position after a few random legal moves from the initial position.
+    fn random_opening(&mut self) -> Board {
+        let mut board = Board::new();
+        for _ in 0..self.settings.opening_plies {
+            let moves = board.legal_moves();
+            let Some(&mv) = moves.choose(&mut self.rng) else {
+                break;
+            };
+            if board.play(mv).is_err() {
+                break;
+            }
+        }
+        board
+    }
+}
+
+/// Plays every opening twice, once with each color, and scores `plus`
+/// against `minus` from -1 to 1.
+fn play_match(plus: &SearchParams, minus: &SearchParams, openings: &[Board], limits: &SearchLimits) -> f64 {
+    let (mut plus_player, mut minus_player) = (Search::new(), Search::new());
+    plus_player.set_params(*plus);
+    minus_player.set_params(*minus);
+
+    let mut score = 0.0;
+    for opening in openings {
+        for color in [Color::White, Color::Black] {
+            score += match play_game_from(opening, &mut plus_player, &mut minus_player, color, limits) {
+                MatchResult::Win => 1.0,
+                MatchResult::Draw => 0.0,
+                MatchResult::Loss => -1.0,
+            };
+        }
+    }
+    score / (2 * openings.len()).max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spsa_walks_towards_the_stronger_side() {
+        let start = SearchParams { futility_margin: 100, ..SearchParams::default() };
+        let mut spsa = Spsa::new(start, &[Tunable::FutilityMargin, Tunable::LmrReduction], SpsaSettings::default());
+        // A match oracle in which a larger futility margin always wins
+        for _ in 0..30 {
+            let report = spsa.step_with(|plus, minus| (plus.futility_margin - minus.futility_margin).signum() as f64);
+            assert_ne!(report.plus.futility_margin, report.minus.futility_margin);
+        }
+        let params = spsa.params();
+        assert!(params.futility_margin > 200, "{}", params.futility_margin);
+        assert!(params.futility_margin <= Tunable::FutilityMargin.range().1);
+        assert_eq!(params.quiescence_depth, start.quiescence_depth);
+
+        assert_eq!(SearchParams::from_toml(&params.to_toml()).unwrap(), params);
+    }
+
+    #[test]
+    fn test_tunables_clamp_to_their_range() {
+        let mut params = SearchParams::default();
+        for tunable in Tunable::ALL {
+            let (min, max) = tunable.range();
+            tunable.set(&mut params, max + 100);
+            assert_eq!(tunable.get(&params), max, "{}", tunable.name());
+            tunable.set(&mut params, min - 100);
+            assert_eq!(tunable.get(&params), min, "{}", tunable.name());
+        }
+    }
+}
@@ -1,10 +1,885 @@
+//! Universal Chess Interface (UCI) front end.
+//!
+//! Translates UCI commands into `Board` and `Engine` calls so the engine can
+//! be driven by GUIs such as Arena or Cute Chess. Searches run on a
+//! background thread, so `isready` and `stop` are answered while the engine
+//! is thinking.
+//!
+//! `bestmove` names the reply the engine expects as its `ponder` move. A GUI
+//! that enables the `Ponder` option then sends `go ponder` with the position
+//! after that reply, and `ponderhit` if the opponent plays it, which turns
+//! the ponder search into the real one, or `stop` if not.
+//!
+//! The engine options of `options` are announced and set like any other;
+//! they start from the options file. With `OwnBook` on, `go` answers from
+//! the opening book at once while the position is in it.
+//!
+//! `go depth N`, `go nodes N` and `go mate N` (searched to 2N-1 plies) limit
+//! that search only, and without a clock or `movetime` they alone end it.
+//!
+//! With `UCI_Chess960` on, castling is read and written as the king taking
+//! its own rook (`e1h1`), as Chess960 GUIs expect. Positions whose kings or
+//! rooks start on other files are Chess960 whatever the option says.
+
+use std::io::{self, BufRead, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::assets::Asset;
+use crate::board::{Board, PositionBuilder};
+use crate::book::{self, OpeningBook};
+use crate::chess_move::Move;
+use crate::engine::Engine;
+use crate::options::{self, EngineOptions};
+use crate::perft::{self, Perft};
+use crate::player::Player;
+use crate::position::Color;
+use crate::search::{InfoThrottle, IterationInfo, DEFAULT_INFO_RATE};
+use crate::time_manager::Clock;
+
+/// Search time for a bare `go` without any limits.
+const DEFAULT_MOVETIME_MS: u64 = 5000;
+/// Search time for `go infinite`; the search normally ends with `stop`.
+const INFINITE_MOVETIME_MS: u64 = 24 * 60 * 60 * 1000;
+/// Size of the subtree count cache for `go perft`.
+const PERFT_HASH_MB: usize = 16;
+/// Highest `InfoRate` option value, in info lines per second.
+const MAX_INFO_RATE: u32 = 1000;
+/// How often the info thread checks whether the search has finished.
+const INFO_POLL: Duration = Duration::from_millis(5);
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// UCI protocol handler.
 pub struct UCI {
-    engine: Engine,
+    board: Board,
+    /// `None` while a search thread owns the engine
+    engine: Option<Engine>,
+    search_thread: Option<JoinHandle<Engine>>,
+    stop: Arc<AtomicBool>,
+    /// The engine's ponder flag, cleared by `ponderhit`
+    ponder: Arc<AtomicBool>,
+    options: EngineOptions,
+    /// Loaded while `OwnBook` is on
+    book: Option<Box<dyn OpeningBook>>,
+    /// The `UCI_Chess960` option
+    chess960: bool,
+    output: Output,
 }
 
 impl UCI {
-    pub fn handle_command(&mut self, command: &str) {
-        // Parse and handle UCI commands
-        // position, go, stop, etc.
+    /// Creates a handler that writes its responses to stdout.
+    pub fn new() -> UCI {
+        UCI::with_output(io::stdout())
+    }
+
+    /// Creates a handler that writes its responses to `output`. The engine
+    /// starts from the search parameters and hash file the environment
+    /// names; files that cannot be read are reported as `info string`.
+    pub fn with_output<W: Write + Send + 'static>(output: W) -> UCI {
+        let (mut engine, errors) = Engine::from_config();
+        let options = EngineOptions::load_config();
+        engine.set_options(&options);
+        let mut uci = UCI {
+            board: Board::new(),
+            stop: engine.stop_flag(),
+            ponder: engine.ponder_flag(),
+            engine: Some(engine),
+            search_thread: None,
+            options,
+            book: None,
+            chess960: false,
+            output: Arc::new(Mutex::new(Box::new(output))),
+        };
+        for error in errors {
+            uci.send(&format!("info string {}", error));
+        }
+        if options.own_book {
+            uci.load_book();
+        }
+        uci
+    }
+
+    /// Reads commands from stdin until `quit` or end of input.
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => {
+                    if !self.handle_command(&line) {
+                        return;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        self.finish_search(true);
+        self.save_hash();
+    }
+
+    /// Handles a single line of input.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - One UCI command, e.g. `position startpos moves e2e4`
+    ///
+    /// # Returns
+    ///
+    /// * `false` once `quit` has been received, `true` otherwise
+    pub fn handle_command(&mut self, command: &str) -> bool {
+        let tokens: Vec<&str> = command.split_whitespace().collect();
+        let Some((&name, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        match name {
+            "uci" => {
+                self.send("id name RustChess");
+                self.send("id author Cr-Browning");
+                for option in options::OPTIONS {
+                    self.send(&option.uci_line());
+                }
+                self.send(&format!("option name InfoRate type spin default {} min 0 max {}", DEFAULT_INFO_RATE, MAX_INFO_RATE));
+                self.send("option name Ponder type check default false");
+                self.send("option name UCI_Chess960 type check default false");
+                self.send("uciok");
+            }
+            "isready" => self.send("readyok"),
+            "ucinewgame" => {
+                self.finish_search(true);
+                self.board = Board::new();
+            }
+            "position" => {
+                self.finish_search(true);
+                match parse_position(args, self.chess960) {
+                    Ok(board) => self.board = board,
+                    Err(e) => self.send(&format!("info string {}", e)),
+                }
+            }
+            "setoption" => self.set_option(args),
+            "go" => self.go(args),
+            "ponderhit" => self.ponder.store(false, Ordering::Relaxed),
+            "stop" => self.finish_search(true),
+            "quit" => {
+                self.finish_search(true);
+                self.save_hash();
+                return false;
+            }
+            // Unknown commands are ignored, as the protocol requires
+            _ => {}
+        }
+        true
+    }
+
+    /// Saves the engine's hash file, if it has one, as the session ends.
+    fn save_hash(&self) {
+        if let Some(Err(e)) = self.engine.as_ref().map(Player::save_hash) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Handles `setoption name <name> value <value>`. The engine options
+    /// of `options` apply from the next search; `InfoRate` caps the `info`
+    /// lines sent between completed iterations per second, and
+    /// `UCI_Chess960` how castling is written from the next `position`.
+    /// Other options are ignored.
+    fn set_option(&mut self, args: &[&str]) {
+        let value_index = args.iter().position(|&token| token == "value").unwrap_or(args.len());
+        let name = args.get(1..value_index).unwrap_or_default().join(" ");
+        let value = args.get(value_index + 1..).map(|tokens| tokens.join(" "));
+        if name.eq_ignore_ascii_case("InfoRate") {
+            match value.and_then(|value| value.parse::<u32>().ok()).filter(|&rate| rate <= MAX_INFO_RATE) {
+                Some(rate) => {
+                    self.finish_search(false);
+                    if let Some(engine) = &mut self.engine {
+                        engine.set_info_throttle(InfoThrottle::new(rate));
+                    }
+                }
+                None => self.send(&format!("info string InfoRate expects 0 to {}", MAX_INFO_RATE)),
+            }
+        } else if name.eq_ignore_ascii_case("UCI_Chess960") {
+            match value.as_deref().map(str::to_ascii_lowercase).as_deref() {
+                Some("true") => self.chess960 = true,
+                Some("false") => self.chess960 = false,
+                _ => self.send("info string UCI_Chess960 expects true or false"),
+            }
+        } else if options::EngineOption::find(&name).is_some() {
+            if let Err(e) = self.options.set(&name, value.as_deref().unwrap_or_default()) {
+                self.send(&format!("info string {}", e));
+                return;
+            }
+            self.finish_search(false);
+            if let Some(engine) = &mut self.engine {
+                engine.set_options(&self.options);
+            }
+            if !self.options.own_book {
+                self.book = None;
+            } else if self.book.is_none() {
+                self.load_book();
+            }
+        }
+    }
+
+    /// Loads the opening book for `OwnBook`, or says why there is none.
+    fn load_book(&mut self) {
+        match Asset::OpeningBook.locate().map(book::load_book) {
+            Some(Ok(book)) => self.book = Some(book),
+            Some(Err(e)) => self.send(&format!("info string {}", e)),
+            None => self.send(&format!("info string OwnBook is on but no opening book was found; {}", Asset::OpeningBook.hint())),
+        }
+    }
+
+    /// Starts a search on a background thread; it prints `bestmove` when done.
+    /// With `ponder` the search keeps going, and `bestmove` waits, until
+    /// `ponderhit` or `stop`.
+    fn go(&mut self, args: &[&str]) {
+        self.finish_search(true);
+        if args.first() == Some(&"perft") {
+            self.perft(args.get(1).copied());
+            return;
+        }
+        let Some(mut engine) = self.engine.take() else {
+            return;
+        };
+
+        let infinite = args.contains(&"infinite");
+        let pondering = args.contains(&"ponder");
+        if let Some(mv) = self.book.as_deref().filter(|_| !infinite && !pondering).and_then(|book| book::book_move(book, &self.board)) {
+            self.send("info string book move");
+            self.send(&format!("bestmove {}", mv));
+            self.engine = Some(engine);
+            return;
+        }
+        let depth = arg_value(args, "depth")
+            .or_else(|| arg_value(args, "mate").map(|moves| (2 * moves).saturating_sub(1)))
+            .map(|depth| i32::try_from(depth).unwrap_or(i32::MAX));
+        let nodes = arg_value(args, "nodes");
+        let movetime = arg_value(args, "movetime");
+        match clock_from_args(args, self.board.side_to_move()) {
+            _ if infinite => engine.set_max_time_ms(INFINITE_MOVETIME_MS),
+            Some(clock) if movetime.is_none() => engine.set_clock(&clock, &self.board),
+            None if movetime.is_none() && (depth.is_some() || nodes.is_some()) => engine.set_max_time_ms(INFINITE_MOVETIME_MS),
+            _ => engine.set_max_time_ms(movetime.unwrap_or(DEFAULT_MOVETIME_MS)),
+        }
+        // The limits of this `go` replace the engine's own until it is done
+        let (max_depth, max_nodes) = (engine.max_depth(), engine.params().max_nodes);
+        if let Some(depth) = depth {
+            engine.set_max_depth(depth);
+        }
+        if let Some(nodes) = nodes {
+            engine.set_max_nodes(nodes);
+        }
+        engine.set_root_moves(&search_moves(args, &self.board), &[]);
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(pondering, Ordering::Relaxed);
+
+        let board = self.board.clone();
+        let stop = Arc::clone(&self.stop);
+        let ponder = Arc::clone(&self.ponder);
+        let output = Arc::clone(&self.output);
+        self.search_thread = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let (sender, receiver) = mpsc::channel();
+            engine.set_info_sender(Some(sender));
+            let done = Arc::new(AtomicBool::new(false));
+            let info_thread = {
+                let (board, output, done) = (board.clone(), Arc::clone(&output), Arc::clone(&done));
+                thread::spawn(move || loop {
+                    match receiver.recv_timeout(INFO_POLL) {
+                        Ok(info) => write_line(&output, &info_line(&board, &info)),
+                        // A search abandoned by the watchdog may keep its sender
+                        Err(mpsc::RecvTimeoutError::Timeout) if !done.load(Ordering::Relaxed) => {}
+                        Err(_) => break,
+                    }
+                })
+            };
+            let best = engine.best_move(&board);
+            engine.set_info_sender(None);
+            done.store(true, Ordering::Relaxed);
+            let _ = info_thread.join();
+
+            // In infinite mode `bestmove` must wait for `stop`, and while
+            // pondering for `ponderhit` or `stop`
+            while (infinite || ponder.load(Ordering::Relaxed)) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            if engine.abandoned() {
+                write_line(&output, "info string search overran its time and was abandoned; hash table cleared");
+            }
+            if engine.truncated() && nodes.is_none() {
+                write_line(&output, &format!("info string search stopped at the node limit of {}", engine.params().max_nodes));
+            }
+            engine.set_max_depth(max_depth);
+            engine.set_max_nodes(max_nodes);
+            write_line(&output, &format!("info nodes {} time {}",
+                engine.nodes_searched(), start.elapsed().as_millis()));
+            let line = match best {
+                Some(mv) => match engine.ponder_move(&board, mv) {
+                    Some(reply) => format!("bestmove {} ponder {}", mv, reply),
+                    None => format!("bestmove {}", mv),
+                },
+                None => "bestmove 0000".to_string(),
+            };
+            write_line(&output, &line);
+            engine
+        }));
+    }
+
+    /// Answers `go perft <depth>` with the leaf count below every move, in
+    /// the format other engines use, so the counts can be compared.
+    fn perft(&self, depth: Option<&str>) {
+        let Some(depth) = depth.and_then(|depth| depth.parse::<i32>().ok()).filter(|&depth| depth > 0) else {
+            self.send("info string go perft expects a positive depth");
+            return;
+        };
+        let divide = Perft::with_hash(PERFT_HASH_MB).divide(self.board.position(), depth);
+        for line in perft::divide_lines(&divide) {
+            self.send(&line);
+        }
+    }
+
+    /// Waits for the running search, if any, and takes the engine back.
+    ///
+    /// # Arguments
+    ///
+    /// * `stop` - Abort the search instead of letting it use its full time
+    fn finish_search(&mut self, stop: bool) {
+        let Some(handle) = self.search_thread.take() else {
+            return;
+        };
+        if stop {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        let engine = handle.join().unwrap_or_else(|_| {
+            write_line(&self.output, "info string search thread panicked");
+            Engine::new()
+        });
+        self.stop = engine.stop_flag();
+        self.ponder = engine.ponder_flag();
+        self.engine = Some(engine);
+    }
+
+    fn send(&self, line: &str) {
+        write_line(&self.output, line);
+    }
+}
+
+impl Default for UCI {
+    fn default() -> Self {
+        UCI::new()
+    }
+}
+
+impl Drop for UCI {
+    fn drop(&mut self) {
+        self.finish_search(true);
+    }
+}
+
+fn write_line(output: &Output, line: &str) {
+    if let Ok(mut out) = output.lock() {
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    }
+}
+
+/// Formats a search update as a UCI `info` line, with the moves of the
+/// principal variation in long algebraic notation.
+fn info_line(board: &Board, info: &IterationInfo) -> String {
+    let mut position = board.position().clone();
+    let pv: Vec<String> = info.pv.iter().map(|&mov| {
+        let text = Move::from_u64(mov, &position).to_string();
+        position.make_move(mov);
+        text
+    }).collect();
+    let score = match info.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    format!("info depth {} seldepth {} score {} nodes {} time {} pv {}",
+        info.depth, info.seldepth, score, info.nodes, info.time.as_millis(), pv.join(" "))
+}
+
+/// Builds the board described by the arguments of a `position` command.
+///
+/// # Arguments
+///
+/// * `args` - Tokens after `position`: `startpos` or `fen <fields>`,
+///   optionally followed by `moves <move>...`
+/// * `chess960` - Whether castling moves are written as the king taking
+///   its own rook
+///
+/// # Returns
+///
+/// * The resulting board, or an error if the FEN or any move is invalid
+pub(crate) fn parse_position(args: &[&str], chess960: bool) -> Result<Board, String> {
+    PositionBuilder::from_tokens(args)
+        .map_err(|_| format!("Invalid position command: position {}", args.join(" ")))?
+        .chess960(chess960)
+        .build()
+}
+
+/// The number following `name` in the arguments of `go`, if any.
+fn arg_value(args: &[&str], name: &str) -> Option<u64> {
+    let index = args.iter().position(|&token| token == name)?;
+    args.get(index + 1)?.parse().ok()
+}
+
+/// The moves listed after `searchmoves` in the arguments of `go`, up to
+/// the first token that is not a legal move; empty without `searchmoves`.
+fn search_moves(args: &[&str], board: &Board) -> Vec<Move> {
+    let Some(index) = args.iter().position(|&token| token == "searchmoves") else {
+        return Vec::new();
+    };
+    args[index + 1..].iter()
+        .map_while(|token| board.parse_move(token).ok())
+        .collect()
+}
+
+/// The side to move's clock from the arguments of `go`, if it was given.
+fn clock_from_args(args: &[&str], side: Color) -> Option<Clock> {
+    let (time, increment) = match side {
+        Color::White => ("wtime", "winc"),
+        Color::Black => ("btime", "binc"),
+    };
+    Some(Clock {
+        remaining: Duration::from_millis(arg_value(args, time)?),
+        increment: Duration::from_millis(arg_value(args, increment).unwrap_or(0)),
+        moves_to_go: arg_value(args, "movestogo").map(|moves| moves.min(u32::MAX as u64) as u32),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::PieceType;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use std::sync::mpsc;
+
+    /// Writer that lets the test read what the handler has printed so far.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap())
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    /// Longest any session may take before it counts as a hang.
+    const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+    /// Longest `isready` may take to be answered, even mid-search.
+    const READY_TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Plays a recorded session and checks the protocol invariants: the
+    /// session finishes, every `isready` is answered in time, and every
+    /// `bestmove` is legal in the position its `go` was sent from.
+    fn run_session(commands: &[&str]) -> Vec<String> {
+        let commands: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let buffer = SharedBuffer::default();
+            let mut uci = UCI::with_output(buffer.clone());
+            let mut searched_from = Vec::new();
+            let mut board = Board::new();
+
+            for command in &commands {
+                if command.trim() == "isready" {
+                    let seen = buffer.lines().len();
+                    let start = Instant::now();
+                    uci.handle_command(command);
+                    while !buffer.lines()[seen..].iter().any(|line| line == "readyok") {
+                        assert!(start.elapsed() < READY_TIMEOUT, "isready not answered in time");
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    continue;
+                }
+
+                let tokens: Vec<&str> = command.split_whitespace().collect();
+                match tokens.first() {
+                    Some(&"position") => {
+                        if let Ok(new_board) = parse_position(&tokens[1..], false) {
+                            board = new_board;
+                        }
+                    }
+                    Some(&"ucinewgame") => board = Board::new(),
+                    Some(&"go") => searched_from.push(board.clone()),
+                    _ => {}
+                }
+                if !uci.handle_command(command) {
+                    break;
+                }
+            }
+            drop(uci);
+            sender.send((buffer.lines(), searched_from)).unwrap();
+        });
+
+        let (output, searched_from) = receiver.recv_timeout(SESSION_TIMEOUT)
+            .expect("UCI session hung");
+
+        let best_moves: Vec<&str> = output.iter()
+            .filter_map(|line| line.strip_prefix("bestmove "))
+            .filter_map(|text| text.split_whitespace().next())
+            .collect();
+        assert_eq!(best_moves.len(), searched_from.len(), "one bestmove per go: {:?}", output);
+        for (text, board) in best_moves.iter().zip(&searched_from) {
+            if board.legal_moves().is_empty() {
+                assert_eq!(*text, "0000");
+            } else {
+                assert!(board.parse_move(text).is_ok(), "illegal bestmove {}", text);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_handshake() {
+        let output = run_session(&["uci", "isready", "quit"]);
+        assert_eq!(output, vec![
+            "id name RustChess",
+            "id author Cr-Browning",
+            "option name Hash type spin default 32 min 1 max 4096",
+            "option name Threads type spin default 1 min 1 max 1",
+            "option name MoveOverhead type spin default 50 min 0 max 5000",
+            "option name SkillLevel type spin default 20 min 0 max 20",
+            "option name OwnBook type check default false",
+            "option name InfoRate type spin default 10 min 0 max 1000",
+            "option name Ponder type check default false",
+            "option name UCI_Chess960 type check default false",
+            "uciok",
+            "readyok",
+        ]);
+    }
+
+    #[test]
+    fn test_engine_options_are_set_and_checked() {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        for command in ["setoption name SkillLevel value 3", "setoption name moveoverhead value 400",
+            "setoption name Threads value 4", "setoption name Hash value banana", "setoption name Hash"] {
+            uci.handle_command(command);
+        }
+        assert_eq!(uci.options.skill_level, 3);
+        assert_eq!(uci.options.move_overhead, Duration::from_millis(400));
+        assert_eq!(uci.options.threads, 1);
+        assert_eq!(buffer.lines(), vec![
+            "info string Threads expects 1 to 1",
+            "info string Hash expects 1 to 4096",
+            "info string Hash expects 1 to 4096",
+        ]);
+
+        // With a book, `go` answers from it without searching
+        let mut learned = book::LearningBook::new();
+        let e4 = Board::new().parse_move("e2e4").unwrap();
+        learned.learn(&[(Board::new().hash(), e4.to_u64())], book::GameOutcome::Win);
+        uci.book = Some(Box::new(learned));
+        uci.handle_command("go movetime 60000");
+        uci.finish_search(false);
+        assert_eq!(buffer.lines().last().map(String::as_str), Some("bestmove e2e4"));
+        assert_eq!(uci.engine.as_ref().map(Engine::nodes_searched), Some(0));
+    }
+
+    #[test]
+    fn test_chess960_castling_takes_the_rook() {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        let castle = "position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1h1";
+        uci.handle_command(castle);
+        assert!(buffer.lines()[0].starts_with("info string Move 7 (e1h1)"));
+
+        uci.handle_command("setoption name UCI_Chess960 value true");
+        uci.handle_command(castle);
+        assert_eq!(buffer.lines().len(), 1);
+        assert_eq!(uci.board.piece_at(6), Some((Color::White, PieceType::King)));
+        assert_eq!(uci.board.piece_at(5), Some((Color::White, PieceType::Rook)));
+
+        // Chess960 starting files need no option
+        uci.handle_command("setoption name UCI_Chess960 value false");
+        uci.handle_command("position fen 4k3/8/8/8/8/8/8/1R3KR1 w GB - 0 1 moves f1b1");
+        assert_eq!(uci.board.piece_at(2), Some((Color::White, PieceType::King)));
+        assert_eq!(uci.board.piece_at(3), Some((Color::White, PieceType::Rook)));
+        assert_eq!(buffer.lines().len(), 1);
+    }
+
+    #[test]
+    fn test_go_from_fen_and_moves() {
+        run_session(&[
+            "uci",
+            "position fen rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+            "go movetime 200",
+            "isready",
+            "position startpos moves e2e4 e7e5 g1f3",
+            "go wtime 10000 btime 10000 winc 100 binc 100",
+            "isready",
+        ]);
+    }
+
+    /// Sends `position` and `go` and waits for the search to end on its own.
+    fn search_to_end(position: &str, go: &str) -> Vec<String> {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        uci.handle_command(position);
+        uci.handle_command(go);
+        uci.finish_search(false);
+        buffer.lines()
+    }
+
+    /// The deepest `info depth` line of the output.
+    fn deepest_info(output: &[String]) -> Option<i32> {
+        output.iter()
+            .filter_map(|line| line.strip_prefix("info depth "))
+            .filter_map(|text| text.split_whitespace().next()?.parse().ok())
+            .max()
+    }
+
+    #[test]
+    fn test_go_depth_nodes_and_mate() {
+        let start = Instant::now();
+        let output = search_to_end("position startpos", "go depth 1");
+        assert!(start.elapsed() < Duration::from_secs(1), "go depth 1 took {:?}", start.elapsed());
+        assert_eq!(deepest_info(&output), Some(1), "{:?}", output);
+        assert!(output.last().is_some_and(|line| line.starts_with("bestmove ")), "{:?}", output);
+
+        let output = search_to_end("position startpos", "go nodes 100");
+        let nodes: u64 = output.iter()
+            .find_map(|line| line.strip_prefix("info nodes "))
+            .and_then(|text| text.split_whitespace().next()?.parse().ok())
+            .unwrap();
+        assert!(nodes < 1000, "go nodes 100 searched {} nodes", nodes);
+        assert!(!output.iter().any(|line| line.starts_with("info string")), "{:?}", output);
+
+        // Mate in N moves is searched to 2N-1 plies
+        let back_rank = "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(deepest_info(&search_to_end(back_rank, "go mate 1")), Some(1));
+        let output = search_to_end(back_rank, "go mate 2");
+        assert_eq!(deepest_info(&output), Some(3), "{:?}", output);
+        assert_eq!(output.last().map(String::as_str), Some("bestmove a1a8"), "{:?}", output);
+
+        // Mates are scored in moves, not centipawns
+        let output = search_to_end(back_rank, "go depth 2");
+        assert!(output.iter().any(|line| line.contains(" score mate 1 ")), "{:?}", output);
+        assert!(!output.iter().any(|line| line.contains(" score cp 9")), "{:?}", output);
+        let mated = IterationInfo { complete: true, depth: 2, seldepth: 2, score: -99998, pv: Vec::new(), nodes: 0, time: Duration::ZERO };
+        assert!(info_line(&Board::new(), &mated).contains(" score mate -1 "));
+
+        // The limits end with their search
+        let mut uci = UCI::with_output(SharedBuffer::default());
+        let engine = uci.engine.as_ref().unwrap();
+        let (max_depth, max_nodes) = (engine.max_depth(), engine.params().max_nodes);
+        uci.handle_command("go depth 2 nodes 5000");
+        uci.finish_search(false);
+        let engine = uci.engine.as_ref().unwrap();
+        assert_eq!((engine.max_depth(), engine.params().max_nodes), (max_depth, max_nodes));
+    }
+
+    #[test]
+    fn test_go_searchmoves() {
+        let output = run_session(&["position startpos", "go searchmoves a2a3 h2h4 movetime 100", "isready"]);
+        let best = output.iter().find(|line| line.starts_with("bestmove")).unwrap();
+        assert!(best == "bestmove a2a3" || best == "bestmove h2h4", "{}", best);
+
+        let board = Board::new();
+        let args = ["searchmoves", "e2e4", "wtime", "1000"];
+        assert_eq!(search_moves(&args, &board), vec![board.parse_move("e2e4").unwrap()]);
+        assert!(search_moves(&["movetime", "10"], &board).is_empty());
+    }
+
+    #[test]
+    fn test_stop_during_infinite_search() {
+        let output = run_session(&[
+            "position startpos",
+            "go infinite",
+            "isready",
+            "stop",
+            "isready",
+        ]);
+        assert!(output.iter().any(|line| line.starts_with("bestmove")));
+    }
+
+    #[test]
+    fn test_ponderhit_and_ponder_miss() {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        let wait_for_bestmove = |count: usize| {
+            let start = Instant::now();
+            while buffer.lines().iter().filter(|line| line.starts_with("bestmove")).count() < count {
+                assert!(start.elapsed() < SESSION_TIMEOUT, "no bestmove");
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        uci.handle_command("position startpos moves e2e4");
+        uci.handle_command("go movetime 300");
+        wait_for_bestmove(1);
+        let last = buffer.lines().last().unwrap().clone();
+        let (best, reply) = last.strip_prefix("bestmove ").unwrap().split_once(" ponder ").unwrap();
+
+        // Pondering holds `bestmove` back well past the movetime, until the hit
+        uci.handle_command(&format!("position startpos moves e2e4 {} {}", best, reply));
+        uci.handle_command("go ponder movetime 100");
+        thread::sleep(Duration::from_millis(400));
+        assert!(!buffer.lines().iter().any(|line| line.starts_with("bestmove") && line != &last));
+        uci.handle_command("ponderhit");
+        wait_for_bestmove(2);
+
+        // On a miss the GUI stops the ponder search and searches the real move
+        uci.handle_command("go ponder movetime 100");
+        thread::sleep(Duration::from_millis(200));
+        uci.handle_command("stop");
+        wait_for_bestmove(3);
+    }
+
+    /// Searches `fen` for `movetime` and returns the output once `bestmove`
+    /// has been printed.
+    fn search_output(options: &[&str], fen: &str, movetime: u64) -> Vec<String> {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        for option in options {
+            uci.handle_command(option);
+        }
+        uci.handle_command(&format!("position fen {}", fen));
+        uci.handle_command(&format!("go movetime {}", movetime));
+        let start = Instant::now();
+        while !buffer.lines().iter().any(|line| line.starts_with("bestmove")) {
+            assert!(start.elapsed() < SESSION_TIMEOUT, "no bestmove");
+            thread::sleep(Duration::from_millis(5));
+        }
+        buffer.lines()
+    }
+
+    #[test]
+    fn test_info_lines_are_throttled() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let output = search_output(&[], fen, 300);
+        let infos: Vec<&String> = output.iter().filter(|line| line.starts_with("info depth")).collect();
+        assert!(!infos.is_empty(), "{:?}", output);
+        assert!(infos.iter().all(|line| line.contains(" score cp ") && line.contains(" pv ")));
+        let last_info = output.iter().rposition(|line| line.starts_with("info depth")).unwrap();
+        assert!(last_info < output.iter().position(|line| line.starts_with("bestmove")).unwrap());
+
+        // Without periodic updates only finished iterations and new best moves are sent
+        let quiet = search_output(&["setoption name InfoRate value 0"], fen, 300);
+        let changes = quiet.iter().filter(|line| line.starts_with("info depth")).count();
+        assert!(changes > 0 && changes < 40, "{:?}", quiet);
+
+        let output = run_session(&["setoption name InfoRate value banana"]);
+        assert!(output[0].starts_with("info string InfoRate"));
+    }
+
+    #[test]
+    fn test_go_perft_prints_divide() {
+        let buffer = SharedBuffer::default();
+        let mut uci = UCI::with_output(buffer.clone());
+        uci.handle_command("position startpos moves e2e4");
+        uci.handle_command("go perft 2");
+        uci.handle_command("go perft");
+        let output = buffer.lines();
+        assert_eq!(output.len(), 23);
+        assert!(output.contains(&"d7d5: 31".to_string()));
+        assert_eq!(output[21], "Nodes searched: 600");
+        assert!(output[22].starts_with("info string"));
+    }
+
+    #[test]
+    fn test_quit_during_search() {
+        run_session(&["position startpos", "go infinite", "quit", "go movetime 10"]);
+    }
+
+    #[test]
+    fn test_long_move_list() {
+        // Knights shuffling out and back, twenty times over
+        let mut command = String::from("position startpos moves");
+        for _ in 0..20 {
+            command.push_str(" g1f3 g8f6 f3g1 f6g8");
+        }
+        let output = run_session(&[&command, "isready", "go movetime 100"]);
+        assert!(!output.iter().any(|line| line.starts_with("info string")), "{:?}", output);
+    }
+
+    #[test]
+    fn test_checkmated_position_reports_null_move() {
+        let output = run_session(&[
+            "position fen rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            "go movetime 100",
+        ]);
+        assert!(output.contains(&"bestmove 0000".to_string()));
+    }
+
+    #[test]
+    fn test_malformed_commands_are_survived() {
+        let output = run_session(&[
+            "",
+            "   ",
+            "position",
+            "position fen",
+            "position fen not a fen at all",
+            "position startpos moves e2e5",
+            "position startpos moves e2e4 zz",
+            "position banana",
+            "go movetime",
+            "go movetime -5",
+            "go wtime abc",
+            "stop",
+            "stop",
+            "xyzzy",
+            "setoption name Hash value 64",
+            "isready",
+        ]);
+        assert!(output.iter().filter(|line| line.starts_with("info string")).count() >= 5);
+    }
+
+    #[test]
+    fn test_rejected_position_keeps_previous_board() {
+        let output = run_session(&[
+            "position fen 4k3/8/8/8/8/8/8/3QK3 w - - 0 1",
+            "position startpos moves e2e4 e2e4",
+            "go movetime 100",
+        ]);
+        let best = output.iter().find_map(|line| line.strip_prefix("bestmove ")).unwrap();
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert!(board.parse_move(best).is_ok());
+    }
+
+    #[test]
+    fn test_random_command_fuzz() {
+        let vocabulary = [
+            "uci", "isready", "ucinewgame", "position", "startpos", "fen", "moves", "go",
+            "stop", "movetime", "infinite", "wtime", "btime", "10", "0", "-1", "e2e4",
+            "e7e5", "g1f3", "8/8/8/8/8/8/8/K6k", "w", "b", "-", "KQkq", "1", "\u{e9}",
+        ];
+        let mut rng = StdRng::seed_from_u64(3683);
+        let mut session: Vec<String> = Vec::new();
+        for _ in 0..200 {
+            let length = rng.gen_range(0..8);
+            let words: Vec<&str> = (0..length)
+                .map(|_| vocabulary[rng.gen_range(0..vocabulary.len())])
+                .collect();
+            session.push(words.join(" "));
+        }
+        // Keep random `go` commands short so the session stays quick
+        let session: Vec<String> = session.into_iter()
+            .map(|command| if command.starts_with("go") { "go movetime 5".to_string() } else { command })
+            .collect();
+        let commands: Vec<&str> = session.iter().map(String::as_str).collect();
+        run_session(&commands);
     }
 }
@@ -1,25 +1,418 @@
 use crate::position::{Position, Color, PieceType};
 use crate::search::Search;
+use crate::player::{Player, SearchLimits};
 use crate::Game;
 use crate::evaluation::Evaluation;
 use crate::utils::bit_scan_safe;
+use crate::book::{self, BookCheck, OpeningBook, GameOutcome, DEFAULT_EXIT_MARGIN};
+use crate::opponent::OpponentModel;
+use crate::memory::{self, MemoryUse};
+use crate::chess_move::Move;
+use crate::board::Board;
+use crate::notation;
+use crate::diagram;
+use std::fs;
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+/// Commands understood at the move prompt, completed with Tab
+const COMMANDS: [&str; 13] = ["move", "moves", "fen", "diagram", "undo", "analyze", "level", "reload", "memory", "board", "set", "help", "quit"];
+
+/// Environment variable naming the file the command-line game keeps its
+/// settings in; without it they are kept in `SETTINGS_FILE` in the home
+/// directory.
+pub const SETTINGS_ENV: &str = "RUSTCHESS_CLI_SETTINGS";
+const SETTINGS_FILE: &str = ".rustchess_cli";
+
+// ANSI escapes for `DisplayOptions::color`
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+const RESET: &str = "\x1b[0m";
+
+/// Completes commands and the legal moves of the current position.
+#[derive(Default)]
+struct InputHelper {
+    moves: Vec<String>,  // Legal moves in SAN, updated before every prompt
+}
+
+impl Completer for InputHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(completions(&line[..pos], &self.moves))
+    }
+}
+
+impl Hinter for InputHelper {
+    type Hint = String;
+}
+
+impl Highlighter for InputHelper {}
+
+impl Validator for InputHelper {}
+
+impl Helper for InputHelper {}
+
+/// Completions of the last word of `line`: commands or moves as the first
+/// word, and moves after `move`.
+///
+/// # Returns
+///
+/// * Where the completed word starts, and the candidates
+fn completions(line: &str, moves: &[String]) -> (usize, Vec<String>) {
+    let start = line.rfind(' ').map_or(0, |index| index + 1);
+    let word = &line[start..];
+    let candidates: Vec<&str> = match line[..start].trim() {
+        "" => COMMANDS.iter().copied().chain(moves.iter().map(String::as_str)).collect(),
+        "move" => moves.iter().map(String::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let matching = candidates.into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(str::to_string)
+        .collect();
+    (start, matching)
+}
+
+/// Groups moves in SAN by the piece that moves, king first and pawns last,
+/// leaving out pieces without moves. Castling counts as a king move.
+fn group_by_piece(moves: &[String]) -> Vec<(&'static str, Vec<&str>)> {
+    let mut groups: Vec<(&'static str, Vec<&str>)> = ["King", "Queen", "Rook", "Bishop", "Knight", "Pawn"]
+        .iter()
+        .map(|&name| (name, Vec::new()))
+        .collect();
+    for mv in moves {
+        let group = match mv.chars().next() {
+            Some('K') | Some('O') => 0,
+            Some('Q') => 1,
+            Some('R') => 2,
+            Some('B') => 3,
+            Some('N') => 4,
+            _ => 5,
+        };
+        groups[group].1.push(mv);
+    }
+    groups.retain(|(_, moves)| !moves.is_empty());
+    groups
+}
+
+/// How the command-line board is drawn, chosen with `set display` and
+/// remembered between runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Chess symbols (♔ ♚) instead of letters
+    pub unicode: bool,
+    /// Light and dark squares drawn with ANSI colors
+    pub color: bool,
+    /// Rank and file labels on all four sides instead of left and bottom
+    pub coordinates: bool,
+    /// One character per square and no frame
+    pub compact: bool,
+}
+
+impl DisplayOptions {
+    /// The option names `set display` takes, in the order they are shown.
+    const NAMES: [&'static str; 4] = ["unicode", "color", "coordinates", "compact"];
+
+    fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "unicode" => Some(&mut self.unicode),
+            "color" => Some(&mut self.color),
+            "coordinates" => Some(&mut self.coordinates),
+            "compact" => Some(&mut self.compact),
+            _ => None,
+        }
+    }
+
+    /// Reads stored options; unknown keys and unreadable values keep their
+    /// defaults, so settings from other versions still load.
+    pub fn parse(text: &str) -> Self {
+        let mut options = DisplayOptions::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let Some(name) = key.trim().strip_prefix("display_") else {
+                continue;
+            };
+            if let (Some(option), Ok(on)) = (options.option_mut(name), value.trim().parse()) {
+                *option = on;
+            }
+        }
+        options
+    }
+
+    pub fn to_text(mut self) -> String {
+        Self::NAMES.iter().map(|name| format!("display_{}={}\n", name, self.option_mut(name).unwrap())).collect()
+    }
+
+    /// Turns an option on or off, as `set display <option> <on|off>` asks.
+    ///
+    /// # Arguments
+    ///
+    /// * `argument` - The words after `set display`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())`, or the usage if the option or the value is unknown
+    pub fn set(&mut self, argument: &str) -> Result<(), String> {
+        let usage = || format!("Usage: set display <{}> <on|off>", Self::NAMES.join("|"));
+        let mut words = argument.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return Err(usage());
+        };
+        let on = match value {
+            "on" => true,
+            "off" => false,
+            _ => return Err(usage()),
+        };
+        *self.option_mut(name).ok_or_else(usage)? = on;
+        Ok(())
+    }
+
+    /// The options as `set display` lists them, e.g. `unicode on, color off`.
+    pub fn describe(mut self) -> String {
+        Self::NAMES.iter()
+            .map(|name| format!("{} {}", name, if *self.option_mut(name).unwrap() { "on" } else { "off" }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The letter, or with `unicode` the chess symbol, of a piece.
+fn piece_symbol(piece_type: PieceType, color: Color, unicode: bool) -> char {
+    let index = match piece_type {
+        PieceType::King => 0,
+        PieceType::Queen => 1,
+        PieceType::Rook => 2,
+        PieceType::Bishop => 3,
+        PieceType::Knight => 4,
+        PieceType::Pawn => 5,
+    };
+    let symbols = match (color, unicode) {
+        (Color::White, false) => ['K', 'Q', 'R', 'B', 'N', 'P'],
+        (Color::Black, false) => ['k', 'q', 'r', 'b', 'n', 'p'],
+        (Color::White, true) => ['♔', '♕', '♖', '♗', '♘', '♙'],
+        (Color::Black, true) => ['♚', '♛', '♜', '♝', '♞', '♟'],
+    };
+    symbols[index]
+}
+
+/// Draws the board as text, White at the bottom.
+///
+/// # Arguments
+///
+/// * `position` - The position to draw
+/// * `options` - Symbols, colors, labels and size
+///
+/// # Returns
+///
+/// * The lines of the board, each ended by a newline
+pub fn render_board(position: &Position, options: DisplayOptions) -> String {
+    let (cell, frame) = if options.compact { ("", "") } else { (" ", "|") };
+    let files: String = "abcdefgh".chars().map(|file| format!("{}{}", cell, file)).collect();
+    let files = format!("  {}{}", if options.compact { "" } else { " " }, files);
+    let border = format!("  +{}+", "-".repeat(17));
+
+    let mut lines = Vec::new();
+    if options.coordinates {
+        lines.push(files.clone());
+    }
+    if !options.compact {
+        lines.push(border.clone());
+    }
+    for rank in (0..8).rev() {
+        let mut line = format!("{} {}", rank + 1, frame);
+        for file in 0..8 {
+            let square = rank * 8 + file;
+            let piece = position.pieces.iter()
+                .find(|p| bit_scan_safe(p.position).is_some_and(|pos| pos == square));
+            let symbol = piece.map_or(if options.color { ' ' } else { '.' }, |piece| piece_symbol(piece.piece_type, piece.color, options.unicode));
+            if options.color {
+                let background = if (rank + file) % 2 == 0 { DARK_SQUARE } else { LIGHT_SQUARE };
+                let foreground = if piece.is_some_and(|piece| piece.color == Color::White) { WHITE_PIECE } else { BLACK_PIECE };
+                line.push_str(&format!("{}{}{}{}{}", background, foreground, cell, symbol, RESET));
+            } else {
+                line.push_str(&format!("{}{}", cell, symbol));
+            }
+        }
+        if !options.compact {
+            line.push_str(" |");
+        }
+        if options.coordinates {
+            line.push_str(&format!(" {}", rank + 1));
+        }
+        lines.push(line);
+    }
+    if !options.compact {
+        lines.push(border);
+    }
+    lines.push(files);
+
+    let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    if options.compact { text } else { format!("\n{}\n", text) }
+}
+
+/// The file `SETTINGS_ENV` names, or `SETTINGS_FILE` in the home directory.
+fn settings_path() -> Option<PathBuf> {
+    std::env::var_os(SETTINGS_ENV).map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(SETTINGS_FILE))
+    })
+}
 
 pub struct ChessUI {
     game: Game,
-    search: Search,
+    engine: Box<dyn Player>,  // Plays the side the user does not
+    limits: SearchLimits,
     player_color: Color,
     invalid_moves: Vec<u64>,  // Track invalid moves for current turn
+    book: Option<Box<dyn OpeningBook>>,
+    book_margin: Option<i32>,  // Centipawns a book move may trail the engine's choice by; `None` plays it unchecked
+    engine_moves: Vec<(u64, u64)>,  // (position hash, move) for book learning
+    opponent: OpponentModel,  // Openings played against the user this session
+    history: Vec<Position>,  // Positions before each move of the game, for `undo`
+    editor: Option<Editor<InputHelper, DefaultHistory>>,  // `None` if line editing is unavailable
+    display: DisplayOptions,  // How `display_board` draws the board
 }
 
 impl ChessUI {
     pub fn new() -> Self {
         ChessUI {
             game: Game::new(),
-            search: Search::new(),
+            engine: Box::new(Search::new()),
+            limits: SearchLimits::default(),
             player_color: Color::White,
             invalid_moves: Vec::new(),
+            book: None,
+            book_margin: Some(DEFAULT_EXIT_MARGIN),
+            engine_moves: Vec::new(),
+            opponent: OpponentModel::new("you"),
+            history: Vec::new(),
+            editor: Editor::new().ok().map(|mut editor| {
+                editor.set_helper(Some(InputHelper::default()));
+                editor
+            }),
+            display: settings_path()
+                .and_then(|path| fs::read_to_string(path).ok())
+                .map_or_else(DisplayOptions::default, |text| DisplayOptions::parse(&text)),
+        }
+    }
+
+    /// Saves the engine's hash file, if it has one, and exits.
+    fn quit(&self) -> ! {
+        if let Err(e) = self.engine.save_hash() {
+            println!("{}", e);
+        }
+        std::process::exit(0)
+    }
+
+    /// Reads a line with editing, history and completion when available.
+    ///
+    /// # Returns
+    ///
+    /// * The line, or `None` at end of input or on Ctrl+C
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        let Some(editor) = &mut self.editor else {
+            print!("{}", prompt);
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            return match io::stdin().read_line(&mut input) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(input),
+            };
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = editor.add_history_entry(line.as_str());
+                }
+                Some(line)
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => None,
+            Err(e) => {
+                println!("Could not read input: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Plays from `book` when it knows the position and updates it with the
+    /// result of every finished game.
+    pub fn set_book(&mut self, book: Box<dyn OpeningBook>) {
+        self.book = Some(book);
+    }
+
+    /// Sets how many centipawns worse than the engine's own choice a book
+    /// move may score before the engine leaves the book; `None` plays book
+    /// moves without checking them.
+    pub fn set_book_margin(&mut self, margin: Option<i32>) {
+        self.book_margin = margin;
+    }
+
+    /// Lets `engine` play against the user instead of the built-in search,
+    /// e.g. an external UCI engine.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The opponent
+    /// * `limits` - How long it may think per move
+    pub fn set_engine(&mut self, engine: Box<dyn Player>, limits: SearchLimits) {
+        self.engine = engine;
+        self.limits = limits;
+    }
+
+    /// Picks the engine's move, preferring a legal book move over searching.
+    /// Book moves that lost to the user this session are avoided, and the
+    /// others take turns from game to game. A book move that searches
+    /// clearly worse than the engine's own choice is dropped for it.
+    fn choose_engine_move(&mut self, position: &Position) -> Option<u64> {
+        if let Some(book) = &self.book {
+            let hash = position.get_hash(&self.game);
+            if let Some(mov) = self.opponent.choose_book_move(book.as_ref(), position, hash) {
+                let mut position_copy = position.clone();
+                position_copy.update_all_legal_moves(&self.game);
+                if position_copy.get_all_legal_moves(&self.game).contains(&mov)
+                    && !self.invalid_moves.contains(&mov) {
+                    let check = match self.book_margin {
+                        Some(margin) => book::verify_book_move(self.engine.as_mut(), position, mov, &self.limits, margin),
+                        None => BookCheck::Unverified,
+                    };
+                    match check {
+                        BookCheck::Refuted { best, book_score, best_score } if !self.invalid_moves.contains(&best.to_u64()) => {
+                            println!("Engine leaves the book: {} scores {:+} against {:+} for {}",
+                                Move::from_u64(mov, position), book_score, best_score, best);
+                            return Some(best.to_u64());
+                        }
+                        _ => {
+                            println!("Engine plays from book");
+                            return Some(mov);
+                        }
+                    }
+                }
+            }
+        }
+        self.engine.choose_move_paced(position, &self.limits).map(|mov| mov.to_u64())
+    }
+
+    /// Credits the engine's opening moves with the game result, remembers
+    /// them for the rest of the session and saves the book
+    fn learn_from_game(&mut self, outcome: GameOutcome) {
+        self.opponent.record_game(&self.engine_moves, outcome);
+        if let Some(book) = &mut self.book {
+            book.learn(&self.engine_moves, outcome);
+            if let Err(e) = book.save() {
+                println!("Could not save opening book: {}", e);
+            }
         }
+        self.engine_moves.clear();
     }
 
     fn validate_engine_move(&self, position: &Position, engine_move: u64) -> Result<(), String> {
@@ -40,7 +433,7 @@ impl ChessUI {
         if let Some(piece) = piece {
             // Verify the piece belongs to the engine
             if piece.color != engine_color {
-                let move_str = self.format_move(from_square, to_square, piece.piece_type);
+                let move_str = Move::from_u64(engine_move, position).to_string();
                 let piece_name = match piece.piece_type {
                     PieceType::King => "King",
                     PieceType::Queen => "Queen",
@@ -68,7 +461,7 @@ impl ChessUI {
                         Color::Black => position.white_occupancy & to_bitboard != 0,
                     };
                     if !has_enemy_piece {
-                        let move_str = self.format_move(from_square, to_square, piece.piece_type);
+                        let move_str = Move::from_u64(engine_move, position).to_string();
                         return Err(format!("Engine tried illegal pawn capture {} (no piece to capture)", move_str));
                     }
                 }
@@ -89,8 +482,7 @@ impl ChessUI {
         const MAX_ATTEMPTS: i32 = 10;  // Limit retries
 
         while attempts < MAX_ATTEMPTS {
-            let mut position_copy = position.clone();
-            if let Some(engine_move) = self.search.find_best_move(&mut position_copy) {
+            if let Some(engine_move) = self.choose_engine_move(position) {
                 // Skip if this move was already found to be invalid
                 if self.invalid_moves.contains(&engine_move) {
                     attempts += 1;
@@ -99,13 +491,15 @@ impl ChessUI {
 
                 match self.validate_engine_move(position, engine_move) {
                     Ok(()) => {
-                        let (from_square, to_square) = self.decode_move(engine_move);
-                        if let Some(piece_type) = position.get_piece_type_at(1u64 << from_square) {
+                        let (from_square, _) = self.decode_move(engine_move);
+                        if position.get_piece_type_at(1u64 << from_square).is_some() {
                             let eval = self.get_evaluation(position);
                             println!("Engine plays: {} ({:+.2})", 
-                                self.format_move(from_square, to_square, piece_type),
+                                notation::san_of(position, &self.game, engine_move),
                                 eval as f32 / 100.0
                             );
+                            self.engine_moves.push((position.get_hash(&self.game), engine_move));
+                            self.history.push(position.clone());
                             position.make_move(engine_move);
                             self.display_board(position);
                             self.invalid_moves.clear();  // Clear invalid moves after successful move
@@ -136,16 +530,25 @@ impl ChessUI {
         false
     }
 
+    /// Plays games against the user until they decline a rematch.
+    pub fn play_session(&mut self) {
+        loop {
+            self.play_game();
+            let again = self.read_line("\nPlay again? (y/n): ");
+            if !again.is_some_and(|answer| answer.trim().to_lowercase().starts_with('y')) {
+                break;
+            }
+        }
+    }
+
     pub fn play_game(&mut self) {
         println!("Welcome to RustChess!");
         
         // Get player color preference
-        print!("Would you like to play as White or Black? (w/b): ");
-        io::stdout().flush().unwrap();
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        
+        let Some(input) = self.read_line("Would you like to play as White or Black? (w/b): ") else {
+            return;
+        };
+
         self.player_color = match input.trim().to_lowercase().as_str() {
             "b" | "black" => {
                 println!("You are playing as Black. Engine will play as White.");
@@ -157,24 +560,25 @@ impl ChessUI {
             }
         };
 
-        println!("\nEnter moves in algebraic notation (e.g., 'e2e4', 'g1f3')");
-        println!("Type 'quit' to exit, 'board' to display the current position\n");
+        println!("\nEnter moves in algebraic notation (e.g., 'e4', 'Nf3' or 'e2e4')");
+        println!("Type 'help' for commands; Tab completes commands and moves\n");
 
         let mut position = Position::new(&self.game);
+        self.history.clear();
         self.display_board(&position);
         
         // If engine plays White, make first move
         if self.player_color == Color::Black {
-            let mut position_copy = position.clone();
-            if let Some(engine_move) = self.search.find_best_move(&mut position_copy) {
+            if let Some(engine_move) = self.choose_engine_move(&position) {
                 match self.validate_engine_move(&position, engine_move) {
                     Ok(()) => {
-                        let (from_square, to_square) = self.decode_move(engine_move);
                         let eval = self.get_evaluation(&position);
                         println!("Engine plays: {} ({:+.2})", 
-                            self.format_move(from_square, to_square, position.get_piece_type_at(1u64 << from_square).unwrap_or(PieceType::Pawn)),
+                            notation::san_of(&position, &self.game, engine_move),
                             eval as f32 / 100.0
                         );
+                        self.engine_moves.push((position.get_hash(&self.game), engine_move));
+                        self.history.push(position.clone());
                         position.make_move(engine_move);
                         self.display_board(&position);
                     },
@@ -193,22 +597,25 @@ impl ChessUI {
             if position.get_all_legal_moves(&self.game).is_empty() {
                 if position.is_in_check(&self.game) {
                     println!("\nCheckmate! {} wins!", if position.active_color == Color::White { "Black" } else { "White" });
+                    let engine_mated = position.active_color != self.player_color;
+                    self.learn_from_game(if engine_mated { GameOutcome::Loss } else { GameOutcome::Win });
                 } else {
                     println!("\nStalemate! Game is drawn.");
+                    self.learn_from_game(GameOutcome::Draw);
                 }
                 break;
             }
 
             if position.active_color == self.player_color {
                 // Player's turn
-                match self.get_player_move(&position) {
+                match self.get_player_move(&mut position) {
                     Ok(mov) => {
-                        let (from_square, to_square) = self.decode_move(mov);
                         let eval = self.get_evaluation(&position);
                         println!("Player plays: {} ({:+.2})", 
-                            self.format_move(from_square, to_square, position.get_piece_type_at(1u64 << from_square).unwrap_or(PieceType::Pawn)),
+                            notation::san_of(&position, &self.game, mov),
                             eval as f32 / 100.0
                         );
+                        self.history.push(position.clone());
                         position.make_move(mov);
                         self.display_board(&position);
                     }
@@ -224,40 +631,210 @@ impl ChessUI {
                 self.invalid_moves.clear();  // Clear invalid moves at start of turn
                 if !self.make_engine_move(&mut position) {
                     println!("Engine resigned!");
+                    self.learn_from_game(GameOutcome::Loss);
                     break;
                 }
             }
         }
     }
 
-    fn get_player_move(&self, position: &Position) -> Result<u64, String> {
+    /// Reads the player's move, carrying out any commands typed instead.
+    ///
+    /// # Returns
+    ///
+    /// * The move, or an error to show; empty after a command
+    fn get_player_move(&mut self, position: &mut Position) -> Result<u64, String> {
         // Verify correct turn order
         if position.active_color != self.player_color {
             return Err(format!("It's {}'s turn to move", 
                 if position.active_color == Color::White { "White" } else { "Black" }));
         }
 
-        print!("Your move: ");
-        io::stdout().flush().unwrap();
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).map_err(|e| e.to_string())?;
-        
-        let input = input.trim().to_lowercase();
-        match input.as_str() {
-            "quit" => std::process::exit(0),
-            "board" => {
+        let board = Board::from_position(position.clone());
+        if let Some(helper) = self.editor.as_mut().and_then(|editor| editor.helper_mut()) {
+            helper.moves = board.legal_moves().into_iter().map(|mv| notation::to_san(&board, mv)).collect();
+        }
+        let Some(input) = self.read_line("Your move: ") else {
+            self.quit();
+        };
+
+        let input = input.trim();
+        let (command, argument) = input.split_once(' ').map_or((input, ""), |(command, rest)| (command, rest.trim()));
+        match command.to_lowercase().as_str() {
+            "quit" => self.quit(),
+            "board" => self.display_board(position),
+            "help" => {
+                println!("move <move>   play a move (the word 'move' is optional)");
+                println!("moves         list the legal moves by piece");
+                println!("fen [<fen>]   show the position as FEN, or set up a new one");
+                println!("diagram       set up a position pasted as a board diagram");
+                println!("undo          take back your last move and the engine's reply");
+                println!("analyze       show the engine's evaluation and best line");
+                println!("level [ms]    show or set the engine's time per move");
+                println!("reload        read the engine's search parameter file again");
+                println!("memory [MB]   show the engine's memory use, or resize its hash table");
+                println!("set display [<option> <on|off>]");
+                println!("              show or change how the board is drawn: unicode, color,");
+                println!("              coordinates (on all four sides) or compact");
+                println!("board, quit");
+            }
+            "moves" => self.list_moves(position),
+            "fen" if argument.is_empty() => println!("{}", position.to_fen()),
+            "fen" => self.set_fen(position, argument),
+            "diagram" => self.read_diagram(position)?,
+            "undo" => self.undo(position)?,
+            "analyze" => self.analyze(&board),
+            "level" => self.level(argument)?,
+            "reload" => println!("{}", self.engine.reload_config()?),
+            "memory" => self.memory(argument)?,
+            "set" => self.set(argument, position)?,
+            "move" => return self.parse_move(argument, position),
+            _ => return self.parse_move(input, position),
+        }
+        Err("".to_string())
+    }
+
+    /// Prints the legal moves grouped by piece, with their counts.
+    fn list_moves(&self, position: &Position) {
+        let moves = position.legal_moves_san(&self.game);
+        println!("{} legal moves", moves.len());
+        for (piece, moves) in group_by_piece(&moves) {
+            println!("{:<7} ({:>2}): {}", piece, moves.len(), moves.join(" "));
+        }
+    }
+
+    /// Replaces the position with one given as FEN, explaining why it is
+    /// rejected if it is malformed or illegal.
+    fn set_fen(&mut self, position: &mut Position, fen: &str) {
+        match Position::from_fen(fen, &self.game) {
+            Ok(new_position) => {
+                *position = new_position;
+                self.history.clear();
                 self.display_board(position);
-                return Err("".to_string());
             }
-            _ => self.parse_move(&input, position)
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Reads a board diagram, pasted or typed a line at a time and ended by
+    /// an empty line, and sets it up like `fen` would.
+    fn read_diagram(&mut self, position: &mut Position) -> Result<(), String> {
+        println!("Paste the diagram, then an empty line");
+        let mut text = String::new();
+        while let Some(line) = self.read_line("") {
+            if line.trim().is_empty() && !text.trim().is_empty() {
+                break;
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+        let fen = diagram::diagram_to_fen(&text)?;
+        self.set_fen(position, &fen);
+        Ok(())
+    }
+
+    /// Takes back the player's last move and the engine's reply.
+    fn undo(&mut self, position: &mut Position) -> Result<(), String> {
+        // The player is to move, so the last two moves are the engine's reply and the player's move
+        if self.history.len() < 2 {
+            return Err("There is no move of yours to take back".to_string());
+        }
+        for _ in 0..2 {
+            let previous = self.history.pop().unwrap();
+            if self.engine_moves.last().map(|&(hash, _)| hash) == Some(previous.get_hash(&self.game)) {
+                self.engine_moves.pop();
+            }
+            *position = previous;
+        }
+        self.display_board(position);
+        Ok(())
+    }
+
+    /// Prints the engine's evaluation and principal variation of the board.
+    fn analyze(&mut self, board: &Board) {
+        let (best, analysis) = self.engine.choose_move_analyzed(board.position(), &self.limits);
+        match (best, analysis) {
+            (_, Some(analysis)) => {
+                let mut line = board.clone();
+                let mut pv = Vec::new();
+                for mv in analysis.pv {
+                    pv.push(notation::to_san(&line, mv));
+                    if line.play(mv).is_err() {
+                        break;
+                    }
+                }
+                println!("Evaluation {:+.2}: {}", analysis.score as f32 / 100.0, pv.join(" "));
+            }
+            (Some(best), None) => println!("{} suggests {}", self.engine.name(), notation::to_san(board, best)),
+            (None, None) => println!("No move to suggest"),
+        }
+    }
+
+    /// Shows or sets the engine's time per move in milliseconds.
+    fn level(&mut self, argument: &str) -> Result<(), String> {
+        if argument.is_empty() {
+            match self.limits.movetime {
+                Some(movetime) => println!("Engine thinks {} ms per move", movetime.as_millis()),
+                None => println!("Engine uses its default time per move"),
+            }
+            return Ok(());
+        }
+        let millis: u64 = argument.parse().map_err(|_| "Usage: level <milliseconds per move>".to_string())?;
+        self.limits.movetime = Some(Duration::from_millis(millis));
+        println!("Engine now thinks {} ms per move", millis);
+        Ok(())
+    }
+
+    /// Carries out `set display`: lists the display options, or changes one,
+    /// saves it and draws the board again.
+    fn set(&mut self, argument: &str, position: &Position) -> Result<(), String> {
+        let Some(options) = argument.strip_prefix("display").filter(|rest| rest.is_empty() || rest.starts_with(' ')) else {
+            return Err("Usage: set display [<option> <on|off>]".to_string());
+        };
+        if options.trim().is_empty() {
+            println!("Display: {}", self.display.describe());
+            return Ok(());
+        }
+        self.display.set(options)?;
+        if let Some(path) = settings_path() {
+            fs::write(&path, self.display.to_text())
+                .map_err(|e| format!("Could not save the settings to {}: {}", path.display(), e))?;
+        }
+        self.display_board(position);
+        Ok(())
+    }
+
+    /// Shows the memory the engine's tables take, or resizes its hash table.
+    fn memory(&mut self, argument: &str) -> Result<(), String> {
+        if !argument.is_empty() {
+            let size_mb: usize = argument.parse()
+                .ok().filter(|&size_mb| size_mb > 0)
+                .ok_or("Usage: memory [megabytes of hash table]")?;
+            self.engine.set_hash_size(size_mb)?;
+            println!("Engine hash table is now {} MB", size_mb);
+            return Ok(());
         }
+        let mut uses = vec![MemoryUse::new("Engine hash table", self.engine.memory_bytes())];
+        if let Some(book) = &self.book {
+            uses.push(MemoryUse::new("Opening book", book.memory_bytes()));
+        }
+        uses.extend(memory::endgame_tables());
+        print!("{}", memory::report(&uses));
+        Ok(())
     }
 
     fn parse_move(&self, input: &str, position: &Position) -> Result<u64, String> {
-        if input.len() != 4 {
-            return Err("Move must be in format 'e2e4'".to_string());
+        let bytes = input.as_bytes();
+        let long_algebraic = input.is_ascii() && (input.len() == 4 || input.len() == 5)
+            && bytes[0].is_ascii_lowercase() && bytes[1].is_ascii_digit();
+        if !long_algebraic {
+            // Standard algebraic notation, e.g. 'Nf3' or 'exd5'
+            return notation::parse_san(&Board::from_position(position.clone()), input)
+                .map(|mv| mv.to_u64())
+                .map_err(|e| format!("{} (use e.g. 'e4', 'Nf3' or 'e2e4')", e));
         }
+        let input = input.to_lowercase();
+        let input = input.as_str();
 
         let chars: Vec<char> = input.chars().collect();
         
@@ -283,34 +860,27 @@ impl ChessUI {
             return Err("No piece at source square".to_string());
         }
 
+        // Pawns reaching the last rank promote to a queen unless told otherwise
+        let promotion = match chars.get(4) {
+            None | Some('q') => PieceType::Queen,
+            Some('r') => PieceType::Rook,
+            Some('b') => PieceType::Bishop,
+            Some('n') => PieceType::Knight,
+            Some(_) => return Err("Promotion piece must be one of q, r, b, n".to_string()),
+        };
+
         // Verify the move is legal
         let legal_moves = position.get_all_legal_moves(&self.game);
-        let mov = from_square | (to_square << 6);
-        
-        if !legal_moves.contains(&mov) {
-            return Err("Illegal move".to_string());
-        }
+        let mov = Move::encode(from_square as usize, to_square as usize, None);
+        let promotion_mov = Move::encode(from_square as usize, to_square as usize, Some(promotion));
 
-        Ok(mov)
-    }
-
-    fn format_move(&self, from: u64, to: u64, piece_type: PieceType) -> String {
-        let files = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
-        let piece_symbol = match piece_type {
-            PieceType::King => "K",
-            PieceType::Queen => "Q",
-            PieceType::Rook => "R",
-            PieceType::Bishop => "B",
-            PieceType::Knight => "N",
-            PieceType::Pawn => "",
-        };
-        
-        let from_file = files[(from % 8) as usize];
-        let from_rank = (from / 8) + 1;
-        let to_file = files[(to % 8) as usize];
-        let to_rank = (to / 8) + 1;
-        
-        format!("{}{}{}{}{}", piece_symbol, from_file, from_rank, to_file, to_rank)
+        if legal_moves.contains(&mov) {
+            Ok(mov)
+        } else if legal_moves.contains(&promotion_mov) {
+            Ok(promotion_mov)
+        } else {
+            Err(position.explain_illegal_move(&self.game, from_square as usize, to_square as usize))
+        }
     }
 
     fn decode_move(&self, mov: u64) -> (u64, u64) {
@@ -325,37 +895,82 @@ impl ChessUI {
     }
 
     fn display_board(&self, position: &Position) {
-        println!("\n  +-----------------+");
-        for rank in (0..8).rev() {
-            print!("{} |", rank + 1);
-            for file in 0..8 {
-                let square = rank * 8 + file;
-                let piece = position.pieces.iter()
-                    .find(|p| bit_scan_safe(p.position).map_or(false, |pos| pos == square));
-                
-                let symbol = if let Some(piece) = piece {
-                    match (piece.piece_type, piece.color) {
-                        (PieceType::Pawn, Color::White) => "P",
-                        (PieceType::Knight, Color::White) => "N",
-                        (PieceType::Bishop, Color::White) => "B",
-                        (PieceType::Rook, Color::White) => "R",
-                        (PieceType::Queen, Color::White) => "Q",
-                        (PieceType::King, Color::White) => "K",
-                        (PieceType::Pawn, Color::Black) => "p",
-                        (PieceType::Knight, Color::Black) => "n",
-                        (PieceType::Bishop, Color::Black) => "b",
-                        (PieceType::Rook, Color::Black) => "r",
-                        (PieceType::Queen, Color::Black) => "q",
-                        (PieceType::King, Color::Black) => "k",
-                    }
-                } else {
-                    "."
-                };
-                print!(" {}", symbol);
-            }
-            println!(" |");
-        }
-        println!("  +-----------------+");
-        println!("    a b c d e f g h\n");
+        print!("{}", render_board(position, self.display));
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions() {
+        let moves: Vec<String> = ["e4", "Nf3", "Nc3", "f4"].iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(completions("N", &moves), (0, vec!["Nf3".to_string(), "Nc3".to_string()]));
+        assert_eq!(completions("u", &moves), (0, vec!["undo".to_string()]));
+        assert_eq!(completions("f", &moves), (0, vec!["fen".to_string(), "f4".to_string()]));
+        assert_eq!(completions("move e", &moves), (5, vec!["e4".to_string()]));
+        assert_eq!(completions("level 1", &moves), (6, Vec::new()));
+    }
+
+    #[test]
+    fn test_render_board() {
+        let game = Game::new();
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1", &game).unwrap();
+        let plain = render_board(&position, DisplayOptions::default());
+        assert!(plain.starts_with("\n  +-----------------+\n8 | . . . . k . . . |\n"));
+        assert!(plain.ends_with("1 | . . . . K . . R |\n  +-----------------+\n    a b c d e f g h\n\n"));
+        // The default board can be pasted back as a diagram
+        assert_eq!(diagram::diagram_to_fen(&plain).unwrap(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+
+        let compact = DisplayOptions { compact: true, coordinates: true, unicode: true, ..DisplayOptions::default() };
+        let compact = render_board(&position, compact);
+        assert_eq!(compact.lines().collect::<Vec<_>>(), [
+            "  abcdefgh",
+            "8 ....♚... 8",
+            "7 ........ 7",
+            "6 ........ 6",
+            "5 ........ 5",
+            "4 ........ 4",
+            "3 ........ 3",
+            "2 ........ 2",
+            "1 ....♔..♖ 1",
+            "  abcdefgh",
+        ]);
+
+        let framed = render_board(&position, DisplayOptions { coordinates: true, ..DisplayOptions::default() });
+        assert!(framed.contains("    a b c d e f g h\n  +-----------------+\n8 | . . . . k . . . | 8\n"));
+
+        let colored = render_board(&position, DisplayOptions { color: true, ..DisplayOptions::default() });
+        // h1 is a light square, a1 a dark one
+        assert!(colored.contains(&format!("{}{} R{}", LIGHT_SQUARE, WHITE_PIECE, RESET)));
+        assert!(colored.contains(&format!("1 |{}{}  {}", DARK_SQUARE, BLACK_PIECE, RESET)));
+    }
+
+    #[test]
+    fn test_display_options() {
+        let mut options = DisplayOptions::default();
+        options.set("unicode on").unwrap();
+        options.set("compact on").unwrap();
+        options.set("compact off").unwrap();
+        assert_eq!(options, DisplayOptions { unicode: true, ..DisplayOptions::default() });
+        assert_eq!(options.describe(), "unicode on, color off, coordinates off, compact off");
+        assert!(options.set("sparkles on").is_err());
+        assert!(options.set("unicode yes").is_err());
+        assert!(options.set("unicode").is_err());
+
+        assert_eq!(DisplayOptions::parse(&options.to_text()), options);
+        assert_eq!(DisplayOptions::parse("display_color=true\ndisplay_color=maybe\nhash_mb=64\n"),
+            DisplayOptions { color: true, ..DisplayOptions::default() });
+    }
+
+    #[test]
+    fn test_group_by_piece() {
+        let moves: Vec<String> = ["e4", "Nf3", "O-O", "Bxc4", "exd5", "Nc3", "Kh1"].iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(group_by_piece(&moves), vec![
+            ("King", vec!["O-O", "Kh1"]),
+            ("Bishop", vec!["Bxc4"]),
+            ("Knight", vec!["Nf3", "Nc3"]),
+            ("Pawn", vec!["e4", "exd5"]),
+        ]);
+    }
+}
@@ -3,6 +3,8 @@
 //! This module provides various utility functions for chess operations,
 //! including bitboard manipulation, string parsing, and debugging tools.
 
+use alloc::vec::Vec;
+
 /// Type alias for a 64-bit integer representing a chess board
 pub type Bitboard = u64;
 
@@ -125,6 +127,7 @@ pub fn extract_bits(mut bitboard: Bitboard) -> Vec<usize> {
 /// 
 /// * `bitboard` - The bitboard to print
 /// * `highlight` - Optional square index to highlight in the output
+#[cfg(feature = "std")]
 pub fn print_bitboard(bitboard: Bitboard, highlight: Option<usize>) {
     println!("Bitboard: {}", bitboard);
     for rank in (0..8).rev() {
@@ -44,10 +44,6 @@ impl Zobrist {
 
         // Hash pieces
         for piece in &pos.pieces {
-            if piece.position == 0 {
-                continue;
-            }
-
             let square = piece.position.trailing_zeros() as usize;
             let piece_index = self.get_piece_index(piece.piece_type, piece.color);
             hash ^= self.piece_square[piece_index][square];